@@ -0,0 +1,87 @@
+// Order entry messages through the serde framing against the fixed layout
+// of codec.rs. Run with `cargo bench --bench codec`.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use order_book::{
+    book::{ExecutionReport, OrderStatus, OrderTimestamps},
+    codec::FixedCodec,
+    req::{PlaceOrderArgs, Request},
+    resp::Response,
+    OrderType,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+fn place_order() -> Request {
+    Request::PlaceOrder(PlaceOrderArgs {
+        order_type: OrderType::Bid,
+        price: 100_250,
+        quantity: 40,
+        owner: "market-maker-7".to_string(),
+        expires_at: None,
+        client_order_id: Some("mm7-000123".to_string()),
+    })
+}
+
+fn place_ok() -> Response {
+    Response::PlaceOk(ExecutionReport {
+        order_id: Uuid::new_v4(),
+        filled_quantity: 15,
+        status: OrderStatus::Resting {
+            remaining_qty: 25,
+            price: 100_250,
+            side: OrderType::Bid,
+            timestamp: 1_700_000_000_000,
+            arrival_seq: 81_234,
+        },
+        timestamps: Some(OrderTimestamps {
+            received_nanos: Some(1_000),
+            matched_nanos: 1_800,
+            acked_nanos: Some(2_100),
+        }),
+        self_match_prevention: None,
+    })
+}
+
+fn bench_message<T: Serialize + DeserializeOwned + FixedCodec>(
+    c: &mut Criterion,
+    name: &str,
+    msg: T,
+) {
+    let mut group = c.benchmark_group(name);
+    let mut buf = Vec::with_capacity(256);
+
+    group.bench_function("serde_encode", |b| {
+        b.iter(|| {
+            buf.clear();
+            rmp_serde::encode::write_named(&mut buf, black_box(&msg)).unwrap();
+        })
+    });
+    buf.clear();
+    rmp_serde::encode::write_named(&mut buf, &msg).unwrap();
+    let serde_body = buf.clone();
+    group.bench_function("serde_decode", |b| {
+        b.iter(|| rmp_serde::from_slice::<T>(black_box(&serde_body)).unwrap())
+    });
+
+    group.bench_function("fixed_encode", |b| {
+        b.iter(|| {
+            buf.clear();
+            assert!(black_box(&msg).encode_fixed(&mut buf));
+        })
+    });
+    buf.clear();
+    msg.encode_fixed(&mut buf);
+    let fixed_body = buf.clone();
+    group.bench_function("fixed_decode", |b| {
+        b.iter(|| T::decode_fixed(black_box(&fixed_body)).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_codec(c: &mut Criterion) {
+    bench_message(c, "place_order", place_order());
+    bench_message(c, "place_ok", place_ok());
+}
+
+criterion_group!(benches, bench_codec);
+criterion_main!(benches);
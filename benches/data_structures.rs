@@ -0,0 +1,142 @@
+// Micro-benchmarks of the price level storage behind OrderBook. Run with
+// `cargo bench --features internals`.
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use order_book::{
+    book::PriceLevelIndex,
+    linked_list::{NodeId, SlabLinkedList},
+    price_tree::PriceTree,
+    Order, OrderType,
+};
+
+const SIZES: [usize; 3] = [100, 1_000, 10_000];
+
+fn filled_list(len: usize) -> (SlabLinkedList<u64>, Vec<NodeId>) {
+    let mut list = SlabLinkedList::new();
+    let node_ids = (0..len as u64).map(|value| list.push_back(value)).collect();
+    (list, node_ids)
+}
+
+fn bench_linked_list(c: &mut Criterion) {
+    let mut group = c.benchmark_group("slab_linked_list");
+    for len in SIZES {
+        // The queue at one price level: new orders join the back and fills
+        // take from the front
+        group.bench_with_input(BenchmarkId::new("push_pop", len), &len, |b, &len| {
+            let (mut list, _) = filled_list(len);
+            b.iter(|| {
+                list.push_back(black_box(1));
+                list.pop_front()
+            })
+        });
+
+        // Cancels from anywhere in the queue
+        group.bench_with_input(BenchmarkId::new("remove", len), &len, |b, &len| {
+            b.iter_batched(
+                || {
+                    let (list, mut node_ids) = filled_list(len);
+                    node_ids.shuffle(&mut StdRng::seed_from_u64(0));
+                    (list, node_ids)
+                },
+                |(mut list, node_ids)| {
+                    for node_id in node_ids {
+                        list.remove(node_id);
+                    }
+                    list
+                },
+                BatchSize::LargeInput,
+            )
+        });
+
+        group.bench_with_input(BenchmarkId::new("iter", len), &len, |b, &len| {
+            let (list, _) = filled_list(len);
+            b.iter(|| list.iter().map(|(_, value)| value).sum::<u64>())
+        });
+    }
+    group.finish();
+}
+
+// Tree with `levels` price levels of one order each, starting at 1
+fn filled_tree(levels: usize, index: PriceLevelIndex) -> PriceTree {
+    let mut tree = PriceTree::new(OrderType::Ask);
+    tree.set_index(index).unwrap();
+    for price in 1..=levels as u32 {
+        tree.insert_order(Order::new(price, 10)).unwrap();
+    }
+    tree
+}
+
+fn bench_price_tree(c: &mut Criterion) {
+    let mut group = c.benchmark_group("price_tree");
+    for (levels, (index_name, index)) in SIZES.into_iter().flat_map(|levels| {
+        // A ladder covering every price the benchmarks use
+        let ladder = PriceLevelIndex::Ladder {
+            min_price: 0,
+            max_price: levels as u32 + 1,
+            tick_size: 1,
+        };
+        [("tree", PriceLevelIndex::Tree), ("ladder", ladder)].map(|index| (levels, index))
+    }) {
+        // Adds to and then empties a random existing level, so the tree
+        // keeps its shape
+        group.bench_with_input(
+            BenchmarkId::new(format!("insert_remove_existing_level/{index_name}"), levels),
+            &levels,
+            |b, &levels| {
+                let mut tree = filled_tree(levels, index);
+                let mut rng = StdRng::seed_from_u64(0);
+                b.iter(|| {
+                    let price = rng.gen_range(1..=levels as u32);
+                    let key = tree.insert_order(Order::new(price, 10)).unwrap();
+                    tree.remove_order(&key).unwrap();
+                })
+            },
+        );
+
+        // Opens and closes a level behind the best
+        group.bench_with_input(
+            BenchmarkId::new(format!("insert_remove_new_level/{index_name}"), levels),
+            &levels,
+            |b, &levels| {
+                let mut tree = filled_tree(levels, index);
+                let price = levels as u32 + 1;
+                b.iter(|| {
+                    let key = tree.insert_order(Order::new(black_box(price), 10)).unwrap();
+                    tree.remove_order(&key).unwrap();
+                })
+            },
+        );
+
+        // Empties the best level, moving best to the next one
+        group.bench_with_input(
+            BenchmarkId::new(format!("remove_best/{index_name}"), levels),
+            &levels,
+            |b, &levels| {
+                let mut tree = filled_tree(levels, index);
+                b.iter(|| {
+                    let key = tree.insert_order(Order::new(0, 10)).unwrap();
+                    tree.remove_order(&key).unwrap();
+                    tree.best().map(|level| level.price())
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new(format!("top_10/{index_name}"), levels),
+            &levels,
+            |b, &levels| {
+                let tree = filled_tree(levels, index);
+                b.iter(|| {
+                    tree.top_n(black_box(10))
+                        .map(|(_, level)| level.total_quantity())
+                        .sum::<u64>()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_linked_list, bench_price_tree);
+criterion_main!(benches);
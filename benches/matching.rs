@@ -0,0 +1,119 @@
+// Order book operations on books of a given depth. Run with `cargo bench`.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::time::{Duration, Instant};
+
+use order_book::{OrderBook, OrderType};
+
+// Price levels per side of the benchmark books
+const DEPTHS: [u32; 3] = [10, 100, 1_000];
+const ORDERS_PER_LEVEL: usize = 10;
+// Best bid is MID - 1 and best ask MID + 1
+const MID: u32 = 100_000;
+
+// Book with `depth` levels of resting orders on either side of MID
+fn deep_book(depth: u32) -> OrderBook {
+    let mut book = OrderBook::new();
+    for level in 1..=depth {
+        for _ in 0..ORDERS_PER_LEVEL {
+            book.place_order("maker", MID - level, 10, OrderType::Bid)
+                .unwrap();
+            book.place_order("maker", MID + level, 10, OrderType::Ask)
+                .unwrap();
+        }
+    }
+    book
+}
+
+fn bench_place_order(c: &mut Criterion) {
+    let mut group = c.benchmark_group("place_order");
+    for depth in DEPTHS {
+        // Joins a random level without crossing, then is canceled untimed so
+        // the book keeps its depth
+        group.bench_with_input(BenchmarkId::new("resting", depth), &depth, |b, &depth| {
+            let mut book = deep_book(depth);
+            let mut rng = StdRng::seed_from_u64(0);
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let price = MID - rng.gen_range(1..=depth);
+                    let start = Instant::now();
+                    let order_id = book
+                        .place_order(black_box("taker"), price, 10, OrderType::Bid)
+                        .unwrap();
+                    elapsed += start.elapsed();
+                    book.cancel_order(order_id).unwrap();
+                }
+                elapsed
+            });
+        });
+
+        // Fills the whole best ask level, which is put back untimed
+        group.bench_with_input(BenchmarkId::new("crossing", depth), &depth, |b, &depth| {
+            let mut book = deep_book(depth);
+            let level_quantity = 10 * ORDERS_PER_LEVEL as u64;
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let start = Instant::now();
+                    book.place_order(black_box("taker"), MID + 1, level_quantity, OrderType::Bid)
+                        .unwrap();
+                    elapsed += start.elapsed();
+                    for _ in 0..ORDERS_PER_LEVEL {
+                        book.place_order("maker", MID + 1, 10, OrderType::Ask)
+                            .unwrap();
+                    }
+                }
+                elapsed
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_cancel_order(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cancel_order");
+    for depth in DEPTHS {
+        // Cancels an order placed untimed at a random level
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, &depth| {
+            let mut book = deep_book(depth);
+            let mut rng = StdRng::seed_from_u64(0);
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let price = MID + rng.gen_range(1..=depth);
+                    let order_id = book
+                        .place_order("taker", price, 10, OrderType::Ask)
+                        .unwrap();
+                    let start = Instant::now();
+                    book.cancel_order(black_box(order_id)).unwrap();
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_l2_snapshot(c: &mut Criterion) {
+    let mut group = c.benchmark_group("l2_snapshot");
+    for depth in DEPTHS {
+        let book = deep_book(depth);
+        group.bench_with_input(BenchmarkId::new("full", depth), &book, |b, book| {
+            b.iter(|| book.view_book_l2())
+        });
+        group.bench_with_input(BenchmarkId::new("top_10", depth), &book, |b, book| {
+            b.iter(|| book.view_book_l2_depth(black_box(10)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_place_order,
+    bench_cancel_order,
+    bench_l2_snapshot
+);
+criterion_main!(benches);
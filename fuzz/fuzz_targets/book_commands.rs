@@ -0,0 +1,173 @@
+// Applies arbitrary sequences of commands to an OrderBook and checks its
+// invariants after each one. Run with `cargo +nightly fuzz run book_commands`
+// from the repository root.
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use uuid::Uuid;
+
+use order_book::{book::CancelFilter, OrderBook, OrderStatus, OrderType};
+
+// Prices and owners are kept to a handful of values so orders cross, queue
+// behind each other and partially fill often
+const OWNERS: [&str; 3] = ["alice", "bob", "carol"];
+const BASE_PRICE: u32 = 100;
+
+#[derive(Arbitrary, Debug)]
+enum Command {
+    Place {
+        owner: u8,
+        is_bid: bool,
+        price: u8,
+        quantity: u16,
+    },
+    // Picks one of the orders placed so far, which may already be closed
+    Cancel {
+        order: u16,
+    },
+    // Cancel and replace at a new price and quantity, the way clients amend
+    Amend {
+        order: u16,
+        price: u8,
+        quantity: u16,
+    },
+    CancelOwner {
+        owner: u8,
+    },
+    StartAuction,
+    Uncross,
+    Halt,
+    Resume,
+}
+
+fn owner(owner: u8) -> &'static str {
+    OWNERS[owner as usize % OWNERS.len()]
+}
+
+fn price(price: u8) -> u32 {
+    BASE_PRICE + (price % 16) as u32
+}
+
+fn pick(placed: &[(Uuid, &'static str)], order: u16) -> Option<(Uuid, &'static str)> {
+    if placed.is_empty() {
+        return None;
+    }
+    Some(placed[order as usize % placed.len()])
+}
+
+fn check_invariants(book: &OrderBook, placed: &[(Uuid, &'static str)]) {
+    // Orders cross only while they are held for the auction
+    if book.phase() == order_book::TradingPhase::Continuous {
+        if let (Some(bid), Some(ask)) = (book.best_bid(), book.best_ask()) {
+            assert!(
+                bid.price() < ask.price(),
+                "Crossed book: bid {} ask {}",
+                bid.price(),
+                ask.price()
+            );
+        }
+    }
+
+    // Every level adds up to its orders and none is left empty
+    let mut resting = 0;
+    for side in [OrderType::Bid, OrderType::Ask] {
+        for (_, level) in book.top_levels(side, usize::MAX) {
+            assert!(level.num_orders() > 0, "Empty level at {}", level.price());
+            let quantity: u64 = level.iter().map(|(_, order)| order.quantity()).sum();
+            assert_eq!(level.total_quantity(), quantity);
+            for (_, order) in level.iter() {
+                assert_eq!(order.price(), level.price());
+                assert!(order.quantity() > 0);
+            }
+            resting += quantity;
+        }
+    }
+
+    // Every order placed is either resting or closed, and each owner's open
+    // orders account for everything resting
+    let mut open = 0;
+    for owner in OWNERS {
+        open += book
+            .open_orders(owner)
+            .iter()
+            .map(|order| order.quantity)
+            .sum::<u64>();
+    }
+    assert_eq!(open, resting);
+    for (order_id, _) in placed {
+        let status = book.order_status(*order_id);
+        assert!(status.is_some(), "Lost order {order_id}");
+        if let Some(OrderStatus::Resting { remaining_qty, .. }) = status {
+            assert!(remaining_qty > 0);
+        }
+    }
+    book.check_conservation().unwrap();
+}
+
+fuzz_target!(|commands: Vec<Command>| {
+    let mut book = OrderBook::new();
+    let mut placed = Vec::new();
+    for command in commands {
+        match command {
+            Command::Place {
+                owner: owner_idx,
+                is_bid,
+                price: price_idx,
+                quantity,
+            } => {
+                let order_type = if is_bid {
+                    OrderType::Bid
+                } else {
+                    OrderType::Ask
+                };
+                let owner = owner(owner_idx);
+                // Rejections, e.g. of a zero quantity, are fine
+                if let Ok(order_id) =
+                    book.place_order(owner, price(price_idx), quantity as u64, order_type)
+                {
+                    placed.push((order_id, owner));
+                }
+            }
+            Command::Cancel { order } => {
+                if let Some((order_id, _)) = pick(&placed, order) {
+                    let was_resting = matches!(
+                        book.order_status(order_id),
+                        Some(OrderStatus::Resting { .. })
+                    );
+                    assert_eq!(book.cancel_order(order_id).is_ok(), was_resting);
+                }
+            }
+            Command::Amend {
+                order,
+                price: price_idx,
+                quantity,
+            } => {
+                if let Some((order_id, owner)) = pick(&placed, order) {
+                    if let Some(OrderStatus::Resting { side, .. }) = book.order_status(order_id) {
+                        book.cancel_order(order_id).unwrap();
+                        if let Ok(order_id) =
+                            book.place_order(owner, price(price_idx), quantity as u64, side)
+                        {
+                            placed.push((order_id, owner));
+                        }
+                    }
+                }
+            }
+            Command::CancelOwner { owner: owner_idx } => {
+                let owner = owner(owner_idx);
+                book.cancel_all(&CancelFilter::Owner(owner.to_string()));
+                assert!(book.open_orders(owner).is_empty());
+            }
+            Command::StartAuction => {
+                let _ = book.start_auction();
+            }
+            Command::Uncross => {
+                let _ = book.uncross();
+            }
+            Command::Halt => book.halt(),
+            Command::Resume => book.resume(),
+        }
+        check_invariants(&book, &placed);
+    }
+});
@@ -0,0 +1,78 @@
+#ifndef ORDER_BOOK_H
+#define ORDER_BOOK_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define OB_OK 0
+
+#define OB_ERR_NULL -1
+
+#define OB_ERR_ARGUMENT -2
+
+#define OB_ERR_INVALID_PRICE -3
+
+#define OB_ERR_INVALID_QUANTITY -4
+
+#define OB_ERR_EXPIRY_IN_PAST -5
+
+#define OB_ERR_TRADING_HALTED -6
+
+#define OB_ERR_PRICE_OFF_LADDER -7
+
+#define OB_ERR_QUANTITY_OVERFLOW -8
+
+#define OB_ERR_RISK_REJECTED -9
+
+#define OB_ERR_UNKNOWN_ORDER -10
+
+#define OB_ERR_ALREADY_FILLED -11
+
+#define OB_ERR_ALREADY_CANCELED -12
+
+#define OB_ERR_REJECTED -13
+
+#define OB_SIDE_BID 0
+
+#define OB_SIDE_ASK 1
+
+typedef struct ObBook ObBook;
+
+typedef struct ObOrderId {
+  uint8_t bytes[16];
+} ObOrderId;
+
+typedef struct ObLevel {
+  uint32_t price;
+  uint64_t total_quantity;
+  uint64_t num_orders;
+} ObLevel;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+ObBook *ob_book_new(void);
+
+void ob_book_free(ObBook *book);
+
+int32_t ob_place(ObBook *book,
+                 const char *owner,
+                 uint8_t side,
+                 uint32_t price,
+                 uint64_t quantity,
+                 ObOrderId *order_id);
+
+int32_t ob_cancel(ObBook *book, const ObOrderId *order_id, uint64_t *canceled_quantity);
+
+int64_t ob_snapshot(const ObBook *book, uint8_t side, ObLevel *levels, uintptr_t capacity);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ORDER_BOOK_H */
@@ -0,0 +1,114 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Where the quantity of one order went. At all times
+// placed = filled + cancelled + expired + resting.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OrderAccount {
+    pub placed: u64,
+    pub filled: u64,
+    pub cancelled: u64,
+    pub expired: u64,
+    // Taken from the book when the account is queried
+    pub resting: u64,
+}
+
+impl OrderAccount {
+    pub fn is_balanced(&self) -> bool {
+        [self.filled, self.cancelled, self.expired, self.resting]
+            .into_iter()
+            .try_fold(0u64, |total, quantity| total.checked_add(quantity))
+            == Some(self.placed)
+    }
+}
+
+// Running totals kept independently of the order book's own bookkeeping, so
+// a matcher bug shows up as an account that doesn't add up. Only orders
+// placed on this book are tracked, not those applied to a replica.
+#[derive(Default)]
+pub struct OrderAccounting {
+    accounts: HashMap<Uuid, OrderAccount>,
+}
+
+impl OrderAccounting {
+    pub fn record_placed(&mut self, order_id: Uuid, quantity: u64) {
+        self.accounts.entry(order_id).or_default().placed += quantity;
+    }
+
+    pub fn record_fill(&mut self, order_id: Uuid, quantity: u64) {
+        self.accounts.entry(order_id).or_default().filled += quantity;
+    }
+
+    pub fn record_cancelled(&mut self, order_id: Uuid, quantity: u64) {
+        self.accounts.entry(order_id).or_default().cancelled += quantity;
+    }
+
+    pub fn record_expired(&mut self, order_id: Uuid, quantity: u64) {
+        self.accounts.entry(order_id).or_default().expired += quantity;
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    // `resting` gives the quantity an order still has on the book
+    pub fn account(&self, order_id: Uuid, resting: u64) -> Option<OrderAccount> {
+        let mut account = *self.accounts.get(&order_id)?;
+        account.resting = resting;
+        Some(account)
+    }
+
+    // Checks every account and returns how many were checked
+    pub fn check(&self, resting: impl Fn(Uuid) -> u64) -> Result<usize> {
+        for (&order_id, account) in &self.accounts {
+            let account = OrderAccount {
+                resting: resting(order_id),
+                ..*account
+            };
+            if !account.is_balanced() {
+                return Err(anyhow!(
+                    "Quantity of order {order_id} is not conserved: {account:?}"
+                ));
+            }
+        }
+        Ok(self.accounts.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_balanced_accounts() {
+        let mut accounting = OrderAccounting::default();
+        let order_id = Uuid::new_v4();
+        accounting.record_placed(order_id, 10);
+        accounting.record_fill(order_id, 4);
+        assert_eq!(accounting.check(|_| 6).unwrap(), 1);
+        assert!(accounting.check(|_| 5).is_err());
+
+        accounting.record_expired(order_id, 6);
+        let account = accounting.account(order_id, 0).unwrap();
+        assert!(account.is_balanced());
+        assert_eq!(account.expired, 6);
+        assert!(accounting.account(Uuid::new_v4(), 0).is_none());
+    }
+
+    #[test]
+    fn test_overflowing_account_is_unbalanced() {
+        let account = OrderAccount {
+            placed: 1,
+            filled: u64::MAX,
+            cancelled: 2,
+            ..Default::default()
+        };
+        assert!(!account.is_balanced());
+    }
+}
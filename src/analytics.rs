@@ -0,0 +1,306 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::{
+    book::{OrderBook, OrderType},
+    stats::SessionStats,
+    tape::Trade,
+};
+
+// Number of levels per side used for the imbalance when none is requested
+pub const DEFAULT_ANALYTICS_DEPTH: usize = 5;
+// Period the market statistics roll over
+pub const MARKET_STATS_WINDOW_MS: u64 = 24 * 60 * 60 * 1000;
+// Granularity trades age out of the window at
+const MARKET_STATS_BUCKET_MS: u64 = 60 * 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BookAnalytics {
+    pub mid_price: Option<f64>,
+    // Best ask minus best bid. Negative while an auction book is crossed.
+    pub spread: Option<i64>,
+    // Levels per side the imbalance was computed over
+    pub depth: usize,
+    pub imbalance: Option<f64>,
+    pub microprice: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BookStats {
+    // Sequence number of the latest event reflected in the stats
+    pub as_of_seq: u64,
+    pub session: SessionStats,
+    pub analytics: BookAnalytics,
+}
+
+// Trading over the last window, rolling with the clock rather than the
+// session. Trades age out a bucket at a time, so the window covers up to
+// one bucket more than its length.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MarketStats {
+    // Unix timestamp in milliseconds the window ends at
+    pub as_of: u64,
+    pub window_ms: u64,
+    // Price of the latest trade, however long ago it was
+    pub last: Option<u32>,
+    // Price of the first trade in the window
+    pub open: Option<u32>,
+    pub high: Option<u32>,
+    pub low: Option<u32>,
+    pub volume: u64,
+    pub trade_count: u64,
+    // Last price against the window's open, None without trades in it
+    pub price_change: Option<i64>,
+    pub price_change_pct: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StatsBucket {
+    start: u64,
+    open: u32,
+    high: u32,
+    low: u32,
+    volume: u64,
+    trade_count: u64,
+}
+
+// Time windowed accumulator behind MarketStats. Trades are summed into
+// fixed buckets, so recording one and reading the stats cost at most a pass
+// over the window's buckets, however many trades it holds.
+#[derive(Debug, Clone)]
+pub struct RollingStats {
+    window_ms: u64,
+    bucket_ms: u64,
+    // Oldest first
+    buckets: VecDeque<StatsBucket>,
+    last: Option<u32>,
+}
+
+impl Default for RollingStats {
+    fn default() -> Self {
+        RollingStats::new(MARKET_STATS_WINDOW_MS, MARKET_STATS_BUCKET_MS)
+    }
+}
+
+impl RollingStats {
+    pub fn new(window_ms: u64, bucket_ms: u64) -> RollingStats {
+        RollingStats {
+            window_ms,
+            bucket_ms: bucket_ms.max(1),
+            buckets: VecDeque::new(),
+            last: None,
+        }
+    }
+
+    // Trades are expected in time order, as the tape records them. One
+    // stamped earlier than the latest bucket is counted in it.
+    pub fn record_trade(&mut self, trade: &Trade) {
+        let start = trade.timestamp - trade.timestamp % self.bucket_ms;
+        self.last = Some(trade.price);
+        match self.buckets.back_mut() {
+            Some(bucket) if bucket.start >= start => {
+                bucket.high = bucket.high.max(trade.price);
+                bucket.low = bucket.low.min(trade.price);
+                bucket.volume = bucket.volume.saturating_add(trade.quantity);
+                bucket.trade_count += 1;
+            }
+            _ => self.buckets.push_back(StatsBucket {
+                start,
+                open: trade.price,
+                high: trade.price,
+                low: trade.price,
+                volume: trade.quantity,
+                trade_count: 1,
+            }),
+        }
+        self.expire(trade.timestamp);
+    }
+
+    pub fn stats(&mut self, now: u64) -> MarketStats {
+        self.expire(now);
+        let open = self.buckets.front().map(|bucket| bucket.open);
+        let price_change = open
+            .zip(self.last)
+            .map(|(open, last)| last as i64 - open as i64);
+        MarketStats {
+            as_of: now,
+            window_ms: self.window_ms,
+            last: self.last,
+            open,
+            high: self.buckets.iter().map(|bucket| bucket.high).max(),
+            low: self.buckets.iter().map(|bucket| bucket.low).min(),
+            volume: self
+                .buckets
+                .iter()
+                .fold(0u64, |volume, bucket| volume.saturating_add(bucket.volume)),
+            trade_count: self.buckets.iter().map(|bucket| bucket.trade_count).sum(),
+            price_change,
+            price_change_pct: open
+                .zip(price_change)
+                .map(|(open, change)| change as f64 / open as f64 * 100.0),
+        }
+    }
+
+    // Drops the buckets that ended before the window ending at `now`
+    fn expire(&mut self, now: u64) {
+        let window_start = now.saturating_sub(self.window_ms);
+        while self
+            .buckets
+            .front()
+            .is_some_and(|bucket| bucket.start + self.bucket_ms <= window_start)
+        {
+            self.buckets.pop_front();
+        }
+    }
+}
+
+impl OrderBook {
+    pub fn mid_price(&self) -> Option<f64> {
+        let (bid, ask) = self.best_prices()?;
+        Some((bid as f64 + ask as f64) / 2.0)
+    }
+
+    pub fn spread(&self) -> Option<i64> {
+        let (bid, ask) = self.best_prices()?;
+        Some(ask as i64 - bid as i64)
+    }
+
+    // (bid quantity - ask quantity) / (bid quantity + ask quantity) over the
+    // best `depth` levels of each side, between -1 (all asks) and 1 (all bids)
+    pub fn imbalance(&self, depth: usize) -> Option<f64> {
+        let side_quantity = |side: OrderType| -> f64 {
+            self.top_levels(side, depth)
+                .map(|(_, price_node)| price_node.total_quantity() as f64)
+                .sum()
+        };
+        let bid_quantity = side_quantity(OrderType::Bid);
+        let ask_quantity = side_quantity(OrderType::Ask);
+        let total_quantity = bid_quantity + ask_quantity;
+        if total_quantity == 0.0 {
+            return None;
+        }
+        Some((bid_quantity - ask_quantity) / total_quantity)
+    }
+
+    // Mid price weighted by the opposite side's top-of-book quantity, so it
+    // leans towards the side more likely to be traded through
+    pub fn microprice(&self) -> Option<f64> {
+        let best_bid = self.best_bid()?;
+        let best_ask = self.best_ask()?;
+        let bid_quantity = best_bid.total_quantity() as f64;
+        let ask_quantity = best_ask.total_quantity() as f64;
+        Some(
+            (best_bid.price() as f64 * ask_quantity + best_ask.price() as f64 * bid_quantity)
+                / (bid_quantity + ask_quantity),
+        )
+    }
+
+    pub fn analytics(&self, depth: usize) -> BookAnalytics {
+        BookAnalytics {
+            mid_price: self.mid_price(),
+            spread: self.spread(),
+            depth,
+            imbalance: self.imbalance(depth),
+            microprice: self.microprice(),
+        }
+    }
+
+    pub fn book_stats(&self, depth: usize) -> BookStats {
+        BookStats {
+            as_of_seq: self.last_event_seq(),
+            session: self.session_stats().clone(),
+            analytics: self.analytics(depth),
+        }
+    }
+
+    fn best_prices(&self) -> Option<(u32, u32)> {
+        Some((self.best_bid()?.price(), self.best_ask()?.price()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book() -> OrderBook {
+        let mut book = OrderBook::new();
+        book.place_order("alice", 99, 30, OrderType::Bid).unwrap();
+        book.place_order("alice", 98, 10, OrderType::Bid).unwrap();
+        book.place_order("alice", 101, 10, OrderType::Ask).unwrap();
+        book.place_order("alice", 102, 30, OrderType::Ask).unwrap();
+        book
+    }
+
+    #[test]
+    fn test_empty_book() {
+        let book = OrderBook::new();
+        let analytics = book.analytics(DEFAULT_ANALYTICS_DEPTH);
+        assert_eq!(analytics.mid_price, None);
+        assert_eq!(analytics.spread, None);
+        assert_eq!(analytics.imbalance, None);
+        assert_eq!(analytics.microprice, None);
+    }
+
+    #[test]
+    fn test_mid_and_spread() {
+        let book = book();
+        assert_eq!(book.mid_price(), Some(100.0));
+        assert_eq!(book.spread(), Some(2));
+    }
+
+    #[test]
+    fn test_imbalance() {
+        let book = book();
+        // Top of book only: 30 bid against 10 ask
+        assert_eq!(book.imbalance(1), Some(0.5));
+        // Both levels: 40 against 40
+        assert_eq!(book.imbalance(2), Some(0.0));
+    }
+
+    #[test]
+    fn test_market_stats_roll_over_the_window() {
+        let trade = |timestamp: u64, price: u32, quantity: u64| Trade {
+            seq: 0,
+            timestamp,
+            price,
+            quantity,
+            aggressor: None,
+            maker_order_id: Default::default(),
+            maker_owner: "alice".to_string(),
+            taker_order_id: Default::default(),
+            taker_owner: "bob".to_string(),
+            fees: Default::default(),
+        };
+        let mut stats = RollingStats::new(1_000, 100);
+        assert_eq!(stats.stats(0).last, None);
+        stats.record_trade(&trade(50, 100, 5));
+        stats.record_trade(&trade(80, 110, 1));
+        stats.record_trade(&trade(500, 90, 2));
+        stats.record_trade(&trade(1_020, 95, 4));
+
+        let market_stats = stats.stats(1_050);
+        assert_eq!(market_stats.open, Some(100));
+        assert_eq!((market_stats.high, market_stats.low), (Some(110), Some(90)));
+        assert_eq!((market_stats.volume, market_stats.trade_count), (12, 4));
+        assert_eq!(market_stats.price_change, Some(-5));
+        assert_eq!(market_stats.price_change_pct, Some(-5.0));
+
+        // First bucket ages out
+        let market_stats = stats.stats(1_100);
+        assert_eq!(market_stats.open, Some(90));
+        assert_eq!(market_stats.high, Some(95));
+        assert_eq!(market_stats.volume, 6);
+        // Quiet for a whole window, only the last price is left
+        let market_stats = stats.stats(3_000);
+        assert_eq!(market_stats.last, Some(95));
+        assert_eq!((market_stats.open, market_stats.trade_count), (None, 0));
+        assert_eq!(market_stats.price_change, None);
+    }
+
+    #[test]
+    fn test_microprice() {
+        let book = book();
+        // Heavier bid pulls the microprice towards the ask
+        assert_eq!(book.microprice(), Some(100.5));
+    }
+}
@@ -0,0 +1,587 @@
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::{book::CancelFilter, clearing::AccountAction, clock::unix_millis, req::Request};
+
+// How far a request's timestamp may drift from the server clock
+pub const DEFAULT_FRESHNESS_WINDOW_MS: u64 = 30_000;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Request signed with a secret shared between the client and the server.
+// The nonce and timestamp are covered by the signature, so a captured frame
+// can only be replayed within the freshness window, where its nonce is
+// remembered and rejected.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedRequest {
+    pub client_id: String,
+    pub nonce: u64,
+    // Unix timestamp in milliseconds the request was signed at
+    pub timestamp: u64,
+    // Encoded request, signed as sent so re-encoding can't change the bytes
+    pub payload: Vec<u8>,
+    // HMAC-SHA256 over the client id, nonce, timestamp and payload
+    pub signature: Vec<u8>,
+}
+
+impl SignedRequest {
+    pub fn sign(
+        client_id: &str,
+        secret: &[u8],
+        nonce: u64,
+        timestamp: u64,
+        request: &Request,
+    ) -> Result<SignedRequest> {
+        let payload = rmp_serde::to_vec_named(request)?;
+        let signature = mac(secret, client_id, nonce, timestamp, &payload)?
+            .finalize()
+            .into_bytes()
+            .to_vec();
+        Ok(SignedRequest {
+            client_id: client_id.to_string(),
+            nonce,
+            timestamp,
+            payload,
+            signature,
+        })
+    }
+
+    fn verify_signature(&self, secret: &[u8]) -> Result<()> {
+        mac(
+            secret,
+            &self.client_id,
+            self.nonce,
+            self.timestamp,
+            &self.payload,
+        )?
+        .verify_slice(&self.signature)
+        .map_err(|_| anyhow!("Invalid signature for client {}", self.client_id))
+    }
+
+    pub fn request(&self) -> Result<Request> {
+        Ok(rmp_serde::from_slice(&self.payload)?)
+    }
+}
+
+fn mac(
+    secret: &[u8],
+    client_id: &str,
+    nonce: u64,
+    timestamp: u64,
+    payload: &[u8],
+) -> Result<HmacSha256> {
+    let mut mac = HmacSha256::new_from_slice(secret)?;
+    // Length prefix keeps the client id from running into the nonce
+    mac.update(&(client_id.len() as u64).to_be_bytes());
+    mac.update(client_id.as_bytes());
+    mac.update(&nonce.to_be_bytes());
+    mac.update(&timestamp.to_be_bytes());
+    mac.update(payload);
+    Ok(mac)
+}
+
+// Remembers the nonces seen within the freshness window. Anything older is
+// rejected on its timestamp alone, so it can be forgotten.
+pub struct ReplayGuard {
+    window_ms: u64,
+    seen: HashSet<(String, u64)>,
+    // (timestamp, client id, nonce), oldest first
+    by_time: BTreeSet<(u64, String, u64)>,
+}
+
+impl ReplayGuard {
+    pub fn new(window_ms: u64) -> ReplayGuard {
+        ReplayGuard {
+            window_ms,
+            seen: HashSet::new(),
+            by_time: BTreeSet::new(),
+        }
+    }
+
+    pub fn check(&mut self, client_id: &str, nonce: u64, timestamp: u64, now: u64) -> Result<()> {
+        if timestamp.abs_diff(now) > self.window_ms {
+            return Err(anyhow!(
+                "Request timestamp {timestamp} is outside the {}ms freshness window",
+                self.window_ms
+            ));
+        }
+        self.prune(now);
+        if !self.seen.insert((client_id.to_string(), nonce)) {
+            return Err(anyhow!("Nonce {nonce} was already used by {client_id}"));
+        }
+        self.by_time
+            .insert((timestamp, client_id.to_string(), nonce));
+        Ok(())
+    }
+
+    fn prune(&mut self, now: u64) {
+        let oldest = now.saturating_sub(self.window_ms);
+        while let Some(first) = self.by_time.first() {
+            if first.0 >= oldest {
+                break;
+            }
+            let (_, client_id, nonce) = self.by_time.pop_first().unwrap();
+            self.seen.remove(&(client_id, nonce));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+}
+
+// Session layer check run on every incoming request. Once any client secret
+// is configured, order entry and views of an owner's account have to be
+// signed, and admin requests signed by an admin client.
+pub struct Authenticator {
+    secrets: HashMap<String, Vec<u8>>,
+    // Clients allowed to send admin requests
+    admins: HashSet<String>,
+    guard: ReplayGuard,
+}
+
+impl Authenticator {
+    pub fn new(secrets: HashMap<String, Vec<u8>>, window_ms: u64) -> Authenticator {
+        Authenticator {
+            secrets,
+            admins: HashSet::new(),
+            guard: ReplayGuard::new(window_ms),
+        }
+    }
+
+    // Every admin needs a secret to sign with
+    pub fn set_admins(&mut self, admins: HashSet<String>) -> Result<()> {
+        if let Some(client_id) = admins.iter().find(|client_id| !self.has_client(client_id)) {
+            return Err(anyhow!("Admin client {client_id} has no secret"));
+        }
+        self.admins = admins;
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.secrets.is_empty()
+    }
+
+    // Unwraps a signed request after checking its signature, freshness and
+    // nonce. Unsigned requests pass through unless they need a signature.
+    pub fn open(&mut self, request: Request, now: u64) -> Result<Request> {
+        Ok(self.authenticate(request, now)?.1)
+    }
+
+    // Same as open, along with the id of the client that signed the request,
+    // or None for an unsigned one
+    pub fn authenticate(
+        &mut self,
+        request: Request,
+        now: u64,
+    ) -> Result<(Option<String>, Request)> {
+        match request {
+            Request::Signed(signed) => {
+                let secret = self
+                    .secrets
+                    .get(&signed.client_id)
+                    .ok_or_else(|| anyhow!("Unknown client {}", signed.client_id))?;
+                // Checked before the nonce is recorded so forged frames can't
+                // use up a client's nonces
+                signed.verify_signature(secret)?;
+                self.guard
+                    .check(&signed.client_id, signed.nonce, signed.timestamp, now)?;
+                match signed.request()? {
+                    Request::Signed(_) => Err(anyhow!("Signed requests can't be nested")),
+                    request
+                        if requires_admin(&request) && !self.admins.contains(&signed.client_id) =>
+                    {
+                        Err(anyhow!("Client {} isn't an admin", signed.client_id))
+                    }
+                    request => Ok((Some(signed.client_id), request)),
+                }
+            }
+            request if self.is_enabled() && requires_signature(&request) => Err(anyhow!(
+                "Requests for an owner's orders or account have to be signed"
+            )),
+            request if self.is_enabled() && requires_admin(&request) => {
+                Err(anyhow!("Admin requests have to be signed"))
+            }
+            request => Ok((None, request)),
+        }
+    }
+
+    pub fn has_client(&self, client_id: &str) -> bool {
+        self.secrets.contains_key(client_id)
+    }
+
+    // Admins may act on anyone's orders, see acts_for
+    pub fn is_admin(&self, client_id: &str) -> bool {
+        self.admins.contains(client_id)
+    }
+}
+
+// Whether a request signed by `client_id` only acts on the client's own
+// orders, owners being the client ids that sign for them. `owns_order` says
+// whether an order named by id is theirs, as only the book knows.
+pub fn acts_for(client_id: &str, request: &Request, owns_order: impl Fn(Uuid) -> bool) -> bool {
+    let owns = |owner: &str| owner == client_id;
+    match request {
+        Request::PlaceOrder(place_order_args) => owns(&place_order_args.owner),
+        Request::PlaceMarketOrder(place_market_order_args) => owns(&place_market_order_args.owner),
+        Request::CancelOrder(cancel_order_args) => owns_order(cancel_order_args.order_id),
+        Request::CancelClientOrder(cancel_client_order_args) => {
+            owns(&cancel_client_order_args.owner)
+        }
+        // Sweeping the whole book or a price range is left to admins
+        Request::CancelAll(cancel_all_args) => {
+            matches!(&cancel_all_args.filter, CancelFilter::Owner(owner) if owns(owner))
+        }
+        Request::PlaceOrders(orders) => orders.iter().all(|order| owns(&order.owner)),
+        Request::CancelOrders(cancels) => cancels.iter().all(|cancel| owns_order(cancel.order_id)),
+        Request::LoadOrders(orders) => orders.iter().all(|order| owns(&order.owner)),
+        Request::SubscribeFills(subscribe_fills_args) => owns(&subscribe_fills_args.owner),
+        Request::Deposit(funds_args) | Request::Withdraw(funds_args) => owns(&funds_args.owner),
+        // Moves cash of the owner it debits, or for a deposit the owner
+        // credited
+        Request::AccountAction(account_action_args) => match &account_action_args.action {
+            AccountAction::Deposit { owner, .. } | AccountAction::Withdraw { owner, .. } => {
+                owns(owner)
+            }
+            AccountAction::Transfer { from, .. } => owns(from),
+        },
+        // An owner's account, orders and standing are only theirs to see
+        Request::ViewAccount(view_account_args)
+        | Request::ViewBalance(view_account_args)
+        | Request::ViewMargin(view_account_args)
+        | Request::ViewFeeTier(view_account_args) => owns(&view_account_args.owner),
+        Request::ViewOpenOrders(view_open_orders_args) => owns(&view_open_orders_args.owner),
+        Request::QueryOrderAccount(query_order_args) => owns_order(query_order_args.order_id),
+        _ => true,
+    }
+}
+
+// Requests for a symbol's book need what the request would on its own
+fn requires_signature(request: &Request) -> bool {
+    if let Request::OnSymbol(symbol_request) = request {
+        return requires_signature(&symbol_request.request);
+    }
+    matches!(
+        request,
+        Request::PlaceOrder(_)
+            | Request::PlaceMarketOrder(_)
+            | Request::CancelOrder(_)
+            | Request::CancelClientOrder(_)
+            | Request::CancelAll(_)
+            | Request::PlaceOrders(_)
+            | Request::CancelOrders(_)
+            | Request::LoadOrders(_)
+            | Request::SubscribeFills(_)
+            | Request::Deposit(_)
+            | Request::Withdraw(_)
+            | Request::AccountAction(_)
+            | Request::ViewAccount(_)
+            | Request::ViewBalance(_)
+            | Request::ViewMargin(_)
+            | Request::ViewFeeTier(_)
+            | Request::ViewOpenOrders(_)
+            | Request::QueryOrderAccount(_)
+    )
+}
+
+// Venue-level operations, which change the venue's rules, history or the
+// book as a whole rather than the sender's own orders
+fn requires_admin(request: &Request) -> bool {
+    if let Request::OnSymbol(symbol_request) = request {
+        return requires_admin(&symbol_request.request);
+    }
+    matches!(
+        request,
+        Request::SetParticipantRisk(_)
+            | Request::BustTrade(_)
+            | Request::EndOfDay(_)
+            | Request::NetTrades
+            | Request::ScheduleFees(_)
+            | Request::SetFeeTiers(_)
+            | Request::ResumeTrading
+            | Request::StartAuction
+            | Request::Uncross
+            // Seeds the book, whoever the orders are for, see seed.rs
+            | Request::LoadOrders(_)
+            // Moves a book, halting it on the way
+            | Request::MigrateSymbol(_)
+            | Request::SetInstrumentStatus(_)
+            // Every owner's account actions
+            | Request::ViewAuditLog
+    )
+}
+
+// Client credentials the bundled binaries sign requests with when both are
+// set, as the server requires for order entry once it has client secrets
+pub const CLIENT_ID_ENV: &str = "ORDER_BOOK_CLIENT_ID";
+pub const CLIENT_SECRET_ENV: &str = "ORDER_BOOK_CLIENT_SECRET";
+
+// Signs the request with the credentials in the environment, or leaves it
+// unsigned without them
+pub fn sign_from_env(request: Request) -> Result<Request> {
+    match (
+        std::env::var(CLIENT_ID_ENV),
+        std::env::var(CLIENT_SECRET_ENV),
+    ) {
+        (Ok(client_id), Ok(secret)) => Ok(Request::Signed(Box::new(SignedRequest::sign(
+            &client_id,
+            secret.as_bytes(),
+            Uuid::new_v4().as_u64_pair().0,
+            unix_millis(),
+            &request,
+        )?))),
+        _ => Ok(request),
+    }
+}
+
+// Parses "client:secret" pairs separated by commas
+pub fn parse_client_secrets(secrets: &str) -> Result<HashMap<String, Vec<u8>>> {
+    secrets
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (client_id, secret) = pair
+                .trim()
+                .split_once(':')
+                .filter(|(client_id, secret)| !client_id.is_empty() && !secret.is_empty())
+                .ok_or_else(|| anyhow!("Expected client:secret but got {pair:?}"))?;
+            Ok((client_id.to_string(), secret.as_bytes().to_vec()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instrument::InstrumentStatus,
+        query::PageRequest,
+        req::{
+            AccountActionArgs, CancelAllArgs, CancelOrderArgs, FundsArgs, MigrateSymbolArgs,
+            QueryOrderArgs, SetInstrumentStatusArgs, SymbolRequest, ViewAccountArgs,
+            ViewOpenOrdersArgs,
+        },
+    };
+    use uuid::Uuid;
+
+    const NOW: u64 = 1_000_000;
+
+    fn authenticator() -> Authenticator {
+        Authenticator::new(parse_client_secrets("alice:s3cret").unwrap(), 1_000)
+    }
+
+    fn cancel() -> Request {
+        Request::CancelOrder(CancelOrderArgs {
+            order_id: Uuid::new_v4(),
+        })
+    }
+
+    fn signed(nonce: u64, timestamp: u64) -> Request {
+        Request::Signed(Box::new(
+            SignedRequest::sign("alice", b"s3cret", nonce, timestamp, &cancel()).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_signed_request_is_opened_once() {
+        let mut auth = authenticator();
+        let request = auth.open(signed(1, NOW), NOW).unwrap();
+        assert!(matches!(request, Request::CancelOrder(_)));
+
+        // Replaying the same frame is rejected
+        assert!(auth.open(signed(1, NOW), NOW + 10).is_err());
+        auth.open(signed(2, NOW), NOW + 10).unwrap();
+    }
+
+    #[test]
+    fn test_stale_and_tampered_requests() {
+        let mut auth = authenticator();
+        assert!(auth.open(signed(1, NOW - 1_001), NOW).is_err());
+        assert!(auth.open(signed(2, NOW + 1_001), NOW).is_err());
+
+        let mut tampered = SignedRequest::sign("alice", b"s3cret", 3, NOW, &cancel()).unwrap();
+        tampered.nonce = 4;
+        assert!(auth.open(Request::Signed(Box::new(tampered)), NOW).is_err());
+
+        let wrong_secret = SignedRequest::sign("alice", b"guess", 5, NOW, &cancel()).unwrap();
+        assert!(auth
+            .open(Request::Signed(Box::new(wrong_secret)), NOW)
+            .is_err());
+        let unknown = SignedRequest::sign("mallory", b"s3cret", 6, NOW, &cancel()).unwrap();
+        assert!(auth.open(Request::Signed(Box::new(unknown)), NOW).is_err());
+
+        // Rejected frames don't use up their nonce
+        auth.open(signed(4, NOW), NOW).unwrap();
+    }
+
+    #[test]
+    fn test_unsigned_order_entry() {
+        let mut auth = authenticator();
+        assert!(auth.open(cancel(), NOW).is_err());
+        assert!(auth.open(Request::CancelOrders(Vec::new()), NOW).is_err());
+        assert!(auth.open(Request::LoadOrders(Vec::new()), NOW).is_err());
+        let deposit = Request::Deposit(FundsArgs {
+            owner: "alice".to_string(),
+            amount: 100,
+            memo: String::new(),
+        });
+        assert!(auth.open(deposit, NOW).is_err());
+        let balance = Request::ViewBalance(ViewAccountArgs {
+            owner: "alice".to_string(),
+        });
+        assert!(auth.open(balance, NOW).is_err());
+        assert!(auth.open(Request::ViewL2Book, NOW).is_ok());
+        // Nor does naming the symbol of an exchange's book get around it
+        let on_symbol = |request| {
+            Request::OnSymbol(Box::new(SymbolRequest {
+                symbol: "ACME".to_string(),
+                request,
+            }))
+        };
+        assert!(auth.open(on_symbol(cancel()), NOW).is_err());
+        assert!(auth.open(on_symbol(Request::ResumeTrading), NOW).is_err());
+        assert!(auth.open(on_symbol(Request::ViewL2Book), NOW).is_ok());
+
+        // Without configured secrets the server stays open
+        let mut open = Authenticator::new(HashMap::new(), 1_000);
+        assert!(open.open(cancel(), NOW).is_ok());
+    }
+
+    #[test]
+    fn test_admin_requests() {
+        let mut auth =
+            Authenticator::new(parse_client_secrets("alice:s3cret,ops:0ps").unwrap(), 1_000);
+        assert!(auth
+            .set_admins(HashSet::from(["mallory".to_string()]))
+            .is_err());
+        auth.set_admins(HashSet::from(["ops".to_string()])).unwrap();
+
+        let sign = |client_id, secret: &[u8], nonce, request: &Request| {
+            Request::Signed(Box::new(
+                SignedRequest::sign(client_id, secret, nonce, NOW, request).unwrap(),
+            ))
+        };
+        assert!(auth.open(Request::ResumeTrading, NOW).is_err());
+        assert!(auth
+            .open(sign("alice", b"s3cret", 1, &Request::ResumeTrading), NOW)
+            .is_err());
+        let (client_id, _) = auth
+            .authenticate(sign("ops", b"0ps", 2, &Request::ResumeTrading), NOW)
+            .unwrap();
+        assert_eq!(client_id.as_deref(), Some("ops"));
+
+        // Auctions, netting, seeding the book, moving it between shards and
+        // opening or halting it are run by the venue, not by participants,
+        // even for orders of their own
+        let venue_requests = [
+            Request::StartAuction,
+            Request::Uncross,
+            Request::NetTrades,
+            Request::LoadOrders(Vec::new()),
+            Request::ViewAuditLog,
+            Request::MigrateSymbol(MigrateSymbolArgs {
+                symbol: "ACME".to_string(),
+                shard: 1,
+            }),
+            Request::SetInstrumentStatus(SetInstrumentStatusArgs {
+                symbol: "ACME".to_string(),
+                status: InstrumentStatus::Halted,
+            }),
+        ];
+        for (nonce, request) in (3..).zip(venue_requests) {
+            assert!(auth
+                .open(sign("alice", b"s3cret", nonce, &request), NOW)
+                .is_err());
+            assert!(auth.open(sign("ops", b"0ps", nonce, &request), NOW).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_acts_for_own_orders_only() {
+        let order_id = Uuid::new_v4();
+        let cancel = Request::CancelOrder(CancelOrderArgs { order_id });
+        assert!(acts_for("alice", &cancel, |id| id == order_id));
+        assert!(!acts_for("alice", &cancel, |_| false));
+
+        let cancel_all = |filter| Request::CancelAll(CancelAllArgs { filter });
+        assert!(acts_for(
+            "alice",
+            &cancel_all(CancelFilter::Owner("alice".to_string())),
+            |_| false
+        ));
+        assert!(!acts_for(
+            "alice",
+            &cancel_all(CancelFilter::Owner("bob".to_string())),
+            |_| false
+        ));
+        assert!(!acts_for("alice", &cancel_all(CancelFilter::All), |_| {
+            false
+        }));
+        assert!(acts_for("alice", &Request::ViewL2Book, |_| false));
+
+        let transfer = |from: &str, to: &str| {
+            Request::AccountAction(AccountActionArgs {
+                action: AccountAction::Transfer {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    amount: 100,
+                },
+                memo: String::new(),
+            })
+        };
+        assert!(acts_for("alice", &transfer("alice", "bob"), |_| false));
+        assert!(!acts_for("alice", &transfer("bob", "alice"), |_| false));
+
+        let account = |owner: &str| ViewAccountArgs {
+            owner: owner.to_string(),
+        };
+        for view in [
+            Request::ViewAccount,
+            Request::ViewBalance,
+            Request::ViewMargin,
+            Request::ViewFeeTier,
+        ] {
+            assert!(acts_for("alice", &view(account("alice")), |_| false));
+            assert!(!acts_for("alice", &view(account("bob")), |_| false));
+        }
+        let open_orders = |owner: &str| {
+            Request::ViewOpenOrders(ViewOpenOrdersArgs {
+                owner: owner.to_string(),
+                page: PageRequest::default(),
+            })
+        };
+        assert!(acts_for("alice", &open_orders("alice"), |_| false));
+        assert!(!acts_for("alice", &open_orders("bob"), |_| false));
+        let order_account = Request::QueryOrderAccount(QueryOrderArgs { order_id });
+        assert!(acts_for("alice", &order_account, |id| id == order_id));
+        assert!(!acts_for("alice", &order_account, |_| false));
+    }
+
+    #[test]
+    fn test_guard_forgets_expired_nonces() {
+        let mut guard = ReplayGuard::new(100);
+        guard.check("alice", 1, NOW, NOW).unwrap();
+        guard.check("bob", 1, NOW, NOW).unwrap();
+        assert_eq!(guard.len(), 2);
+
+        guard.check("alice", 2, NOW + 150, NOW + 150).unwrap();
+        assert_eq!(guard.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_client_secrets() {
+        let secrets = parse_client_secrets("alice:a, bob:b").unwrap();
+        assert_eq!(secrets["bob"], b"b");
+        assert!(parse_client_secrets("").unwrap().is_empty());
+        assert!(parse_client_secrets("alice").is_err());
+    }
+}
@@ -0,0 +1,547 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::{
+    book::{OrderBook, OrderStatus, OrderType},
+    clock::ManualClock,
+    feed::{BookEvent, SequencedEvent},
+    quoting::{QuoteConfig, Quoter},
+    seed::{self, RestingOrder},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistoricalAction {
+    // `order_ref` is whatever id the data refers to the order by
+    Place {
+        order_ref: String,
+        owner: String,
+        order_type: OrderType,
+        price: u32,
+        quantity: u64,
+    },
+    Cancel {
+        order_ref: String,
+    },
+}
+
+// One order or cancel from the historical data
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoricalOrder {
+    // Unix timestamp in milliseconds the book's clock is set to
+    pub timestamp: u64,
+    pub action: HistoricalAction,
+}
+
+// Parses CSV with a header line and one order or cancel per line:
+//
+//   timestamp,action,order_ref,owner,side,price,quantity
+//   1700000000000,place,o1,alice,bid,100,5
+//   1700000000250,cancel,o1,,,,
+pub fn parse_csv(text: &str) -> Result<Vec<HistoricalOrder>> {
+    let mut orders = Vec::new();
+    for (idx, line) in text.lines().enumerate().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let order = parse_csv_line(line).with_context(|| format!("Line {}", idx + 1))?;
+        orders.push(order);
+    }
+    Ok(orders)
+}
+
+fn parse_csv_line(line: &str) -> Result<HistoricalOrder> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let [timestamp, action, order_ref, owner, side, price, quantity] = fields[..] else {
+        return Err(anyhow!("Expected 7 fields but got {}", fields.len()));
+    };
+    let action = match action {
+        "place" => HistoricalAction::Place {
+            order_ref: order_ref.to_string(),
+            owner: owner.to_string(),
+            order_type: match side {
+                "bid" => OrderType::Bid,
+                "ask" => OrderType::Ask,
+                _ => return Err(anyhow!("Expected bid or ask but got {side:?}")),
+            },
+            price: price.parse()?,
+            quantity: quantity.parse()?,
+        },
+        "cancel" => HistoricalAction::Cancel {
+            order_ref: order_ref.to_string(),
+        },
+        _ => return Err(anyhow!("Expected place or cancel but got {action:?}")),
+    };
+    Ok(HistoricalOrder {
+        timestamp: timestamp.parse()?,
+        action,
+    })
+}
+
+// Parses an event log of one JSON encoded SequencedEvent per line, as
+// returned by GetEvents, and rebuilds the orders behind it
+pub fn parse_event_log(text: &str) -> Result<Vec<HistoricalOrder>> {
+    let events = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(idx, line)| serde_json::from_str(line).with_context(|| format!("Event {}", idx + 1)))
+        .collect::<Result<Vec<SequencedEvent>>>()?;
+    Ok(from_events(&events))
+}
+
+// Order whose trades have been seen but not whether any of it rested
+struct Aggressor {
+    order_id: Uuid,
+    owner: String,
+    order_type: OrderType,
+    // Price of the last level it traded at, which its limit was at least
+    price: u32,
+    traded: u64,
+}
+
+impl Aggressor {
+    fn into_order(self, timestamp: u64, rested: u64) -> HistoricalOrder {
+        HistoricalOrder {
+            timestamp,
+            action: HistoricalAction::Place {
+                order_ref: self.order_id.to_string(),
+                owner: self.owner,
+                order_type: self.order_type,
+                price: self.price,
+                quantity: self.traded + rested,
+            },
+        }
+    }
+}
+
+// Rebuilds the orders and cancels behind a book's events. An order that
+// traded on arrival comes back at the price of the last level it reached,
+// since the feed doesn't carry its limit unless some of it rested. Events
+// other than trades carry no time, so they take the time of the trade
+// before them. Auction trades are skipped.
+pub fn from_events(events: &[SequencedEvent]) -> Vec<HistoricalOrder> {
+    let mut orders = Vec::new();
+    let mut timestamp = 0;
+    let mut aggressor: Option<Aggressor> = None;
+    for event in events {
+        match &event.event {
+            BookEvent::Traded(trade) => {
+                timestamp = trade.timestamp;
+                let Some(order_type) = trade.aggressor else {
+                    continue;
+                };
+                match &mut aggressor {
+                    Some(aggressor) if aggressor.order_id == trade.taker_order_id => {
+                        aggressor.price = trade.price;
+                        aggressor.traded += trade.quantity;
+                    }
+                    _ => {
+                        if let Some(done) = aggressor.take() {
+                            orders.push(done.into_order(timestamp, 0));
+                        }
+                        aggressor = Some(Aggressor {
+                            order_id: trade.taker_order_id,
+                            owner: trade.taker_owner.clone(),
+                            order_type,
+                            price: trade.price,
+                            traded: trade.quantity,
+                        });
+                    }
+                }
+            }
+            // The makers an aggressor traded against come off the book
+            // before it rests
+            BookEvent::OrderReduced { .. }
+            | BookEvent::OrderRemoved {
+                status: OrderStatus::Filled,
+                ..
+            } => {}
+            BookEvent::OrderRested {
+                order_id,
+                owner,
+                order_type,
+                price,
+                quantity,
+                ..
+            } => {
+                match aggressor.take() {
+                    Some(done) if done.order_id == *order_id => {
+                        let mut order = done.into_order(timestamp, *quantity);
+                        if let HistoricalAction::Place { price: limit, .. } = &mut order.action {
+                            *limit = *price;
+                        }
+                        orders.push(order);
+                        continue;
+                    }
+                    Some(done) => orders.push(done.into_order(timestamp, 0)),
+                    None => {}
+                }
+                orders.push(HistoricalOrder {
+                    timestamp,
+                    action: HistoricalAction::Place {
+                        order_ref: order_id.to_string(),
+                        owner: owner.clone(),
+                        order_type: *order_type,
+                        price: *price,
+                        quantity: *quantity,
+                    },
+                });
+            }
+            other => {
+                if let Some(done) = aggressor.take() {
+                    orders.push(done.into_order(timestamp, 0));
+                }
+                if let BookEvent::OrderRemoved { order_id, .. } = other {
+                    orders.push(HistoricalOrder {
+                        timestamp,
+                        action: HistoricalAction::Cancel {
+                            order_ref: order_id.to_string(),
+                        },
+                    });
+                }
+            }
+        }
+    }
+    if let Some(done) = aggressor {
+        orders.push(done.into_order(timestamp, 0));
+    }
+    orders
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrategyAction {
+    Place {
+        order_type: OrderType,
+        price: u32,
+        quantity: u64,
+    },
+    Cancel(Uuid),
+}
+
+// Trading logic injected into a backtest. Its orders are placed under the
+// backtest's strategy owner and match against the historical flow.
+pub trait Strategy {
+    // Sees the book after each historical order and returns what to send
+    fn on_book(&mut self, now: u64, book: &OrderBook) -> Vec<StrategyAction>;
+
+    // Id of an order it placed, in the order the places were returned
+    fn on_placed(&mut self, _order_id: Uuid, _order_type: OrderType) {}
+}
+
+// Quotes around the mid the way the market maker bot does
+pub struct QuotingStrategy {
+    quoter: Quoter,
+    from_seq: u64,
+}
+
+impl QuotingStrategy {
+    pub fn new(config: QuoteConfig) -> QuotingStrategy {
+        QuotingStrategy {
+            quoter: Quoter::new(config),
+            from_seq: 1,
+        }
+    }
+}
+
+impl Strategy for QuotingStrategy {
+    fn on_book(&mut self, _now: u64, book: &OrderBook) -> Vec<StrategyAction> {
+        // The feed always holds the events of the last step
+        for event in book
+            .events_since(self.from_seq, usize::MAX)
+            .unwrap_or_default()
+        {
+            self.from_seq = event.seq + 1;
+            self.quoter.on_event(&event.event);
+        }
+        let Some(quotes) = self.quoter.requote(book) else {
+            return Vec::new();
+        };
+        let mut actions: Vec<StrategyAction> = self
+            .quoter
+            .live_orders()
+            .into_iter()
+            .map(StrategyAction::Cancel)
+            .collect();
+        self.quoter.clear_live();
+        actions.extend(quotes.into_iter().map(|quote| StrategyAction::Place {
+            order_type: quote.order_type,
+            price: quote.price,
+            quantity: quote.quantity,
+        }));
+        actions
+    }
+
+    fn on_placed(&mut self, order_id: Uuid, order_type: OrderType) {
+        self.quoter.record_placed(order_id, order_type);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BacktestReport {
+    pub historical_orders: u64,
+    // Historical orders the replay rejected, e.g. cancels of orders the
+    // strategy had already traded against
+    pub historical_rejected: u64,
+    pub strategy_orders: u64,
+    pub strategy_rejected: u64,
+    pub quantity_placed: u64,
+    // Strategy fills where it was the resting and the incoming side
+    pub maker_quantity: u64,
+    pub taker_quantity: u64,
+    pub trades: u64,
+    // Fees are taken out of the cash
+    pub position: i64,
+    pub cash: i64,
+    // Last trade price, at which the position is marked
+    pub mark_price: Option<u32>,
+    // Cash plus the marked position. None without any trade to mark at.
+    pub pnl: Option<i64>,
+}
+
+impl BacktestReport {
+    // Share of the strategy's placed quantity that traded
+    pub fn fill_rate(&self) -> f64 {
+        if self.quantity_placed == 0 {
+            return 0.0;
+        }
+        (self.maker_quantity + self.taker_quantity) as f64 / self.quantity_placed as f64
+    }
+}
+
+// Replays the historical orders through a book seeded with `seed`, on a
+// clock following their timestamps, letting the strategy act after each one
+pub fn run_backtest(
+    seed: &[RestingOrder],
+    orders: &[HistoricalOrder],
+    strategy: &mut dyn Strategy,
+    strategy_owner: &str,
+) -> Result<BacktestReport> {
+    let clock = ManualClock::new(orders.first().map_or(0, |order| order.timestamp));
+    let mut book = OrderBook::new();
+    book.set_clock(Box::new(clock.clone()));
+    seed::load(&mut book, seed).context("Failed to seed the book")?;
+    let mut report = BacktestReport::default();
+    let mut order_ids: HashMap<&str, Uuid> = HashMap::new();
+    let mut next_trade_seq = 1;
+
+    for order in orders {
+        // Out of order timestamps don't move the clock back
+        clock.set(order.timestamp.max(book.now()));
+        report.historical_orders += 1;
+        let accepted = match &order.action {
+            HistoricalAction::Place {
+                order_ref,
+                owner,
+                order_type,
+                price,
+                quantity,
+            } => {
+                if owner == strategy_owner {
+                    return Err(anyhow!(
+                        "Historical order {order_ref} uses the strategy owner {owner}"
+                    ));
+                }
+                book.place_order(owner, *price, *quantity, *order_type)
+                    .map(|order_id| order_ids.insert(order_ref, order_id))
+                    .is_ok()
+            }
+            HistoricalAction::Cancel { order_ref } => order_ids
+                .get(order_ref.as_str())
+                .is_some_and(|&order_id| book.cancel_order(order_id).is_ok()),
+        };
+        if !accepted {
+            report.historical_rejected += 1;
+        }
+
+        for action in strategy.on_book(book.now(), &book) {
+            match action {
+                StrategyAction::Place {
+                    order_type,
+                    price,
+                    quantity,
+                } => match book.place_order(strategy_owner, price, quantity, order_type) {
+                    Ok(order_id) => {
+                        report.strategy_orders += 1;
+                        report.quantity_placed += quantity;
+                        strategy.on_placed(order_id, order_type);
+                    }
+                    Err(_) => report.strategy_rejected += 1,
+                },
+                // Orders that already traded fail to cancel, which is fine
+                StrategyAction::Cancel(order_id) => {
+                    let _ = book.cancel_order(order_id);
+                }
+            }
+        }
+
+        // Read each step so trades never age out of the tape's memory
+        for trade in book.get_trades(next_trade_seq, usize::MAX)? {
+            next_trade_seq = trade.seq + 1;
+            if trade.maker_owner == strategy_owner {
+                report.maker_quantity += trade.quantity;
+            } else if trade.taker_owner == strategy_owner {
+                report.taker_quantity += trade.quantity;
+            } else {
+                continue;
+            }
+            report.trades += 1;
+        }
+    }
+
+    let statement = book.clearing_house().account_statement(strategy_owner);
+    report.position = statement.position;
+    report.cash = statement.cash;
+    report.mark_price = book.session_stats().last();
+    report.pnl = report
+        .mark_price
+        .map(|price| report.cash + report.position * price as i64);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HISTORY: &str = "\
+timestamp,action,order_ref,owner,side,price,quantity
+1000,place,a1,alice,ask,105,10
+1000,place,b1,bob,bid,95,10
+2000,place,c1,carol,bid,105,4
+3000,cancel,a1,,,,
+3000,place,d1,dave,ask,99,3
+";
+
+    // Joins the bid once at 100 and does nothing else
+    struct JoinBid {
+        placed: Option<Uuid>,
+    }
+
+    impl Strategy for JoinBid {
+        fn on_book(&mut self, _now: u64, _book: &OrderBook) -> Vec<StrategyAction> {
+            if self.placed.is_some() {
+                return Vec::new();
+            }
+            vec![StrategyAction::Place {
+                order_type: OrderType::Bid,
+                price: 100,
+                quantity: 5,
+            }]
+        }
+
+        fn on_placed(&mut self, order_id: Uuid, _order_type: OrderType) {
+            self.placed = Some(order_id);
+        }
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let orders = parse_csv(HISTORY).unwrap();
+        assert_eq!(orders.len(), 5);
+        assert_eq!(
+            orders[3],
+            HistoricalOrder {
+                timestamp: 3000,
+                action: HistoricalAction::Cancel {
+                    order_ref: "a1".to_string()
+                }
+            }
+        );
+        let err = parse_csv("header\n1000,place,a1,alice,buy,105,10").unwrap_err();
+        assert!(format!("{err:#}").contains("Line 2"));
+    }
+
+    #[test]
+    fn test_strategy_trades_against_history() {
+        let orders = parse_csv(HISTORY).unwrap();
+        let mut strategy = JoinBid { placed: None };
+        let report = run_backtest(&[], &orders, &mut strategy, "strategy").unwrap();
+
+        assert_eq!(report.historical_orders, 5);
+        assert_eq!(report.historical_rejected, 0);
+        assert_eq!(report.strategy_orders, 1);
+        // Dave's ask at 99 hits the strategy's bid ahead of bob's at 95
+        assert_eq!(report.maker_quantity, 3);
+        assert_eq!(report.taker_quantity, 0);
+        assert_eq!(report.position, 3);
+        assert_eq!(report.cash, -300);
+        assert_eq!(report.mark_price, Some(100));
+        assert_eq!(report.pnl, Some(0));
+        assert_eq!(report.fill_rate(), 0.6);
+
+        let mut clash = JoinBid { placed: None };
+        assert!(run_backtest(&[], &orders, &mut clash, "alice").is_err());
+
+        // Starting from a book with an ask at 100, the strategy's bid takes
+        // it before resting the rest for dave to hit
+        let seed = vec![RestingOrder {
+            price: 100,
+            quantity: 2,
+            side: OrderType::Ask,
+            owner: "erin".to_string(),
+        }];
+        let mut seeded = JoinBid { placed: None };
+        let report = run_backtest(&seed, &orders, &mut seeded, "strategy").unwrap();
+        assert_eq!(report.taker_quantity, 2);
+        assert_eq!(report.maker_quantity, 3);
+        assert_eq!(report.position, 5);
+        assert_eq!(report.cash, -500);
+    }
+
+    #[test]
+    fn test_event_log_rebuilds_orders() {
+        // Record a session, then replay its feed into a new book
+        let mut book = OrderBook::new();
+        book.place_order("alice", 105, 10, OrderType::Ask).unwrap();
+        book.place_order("alice", 106, 10, OrderType::Ask).unwrap();
+        let doomed = book.place_order("bob", 90, 10, OrderType::Bid).unwrap();
+        // Sweeps 105 and rests 2 at 106
+        book.place_order("carol", 106, 22, OrderType::Bid).unwrap();
+        book.cancel_order(doomed).unwrap();
+        book.place_order("dave", 106, 2, OrderType::Ask).unwrap();
+        let log: String = book
+            .events_since(1, usize::MAX)
+            .unwrap()
+            .iter()
+            .map(|event| serde_json::to_string(event).unwrap() + "\n")
+            .collect();
+
+        let orders = parse_event_log(&log).unwrap();
+        let places: Vec<(&str, u32, u64)> = orders
+            .iter()
+            .filter_map(|order| match &order.action {
+                HistoricalAction::Place {
+                    owner,
+                    price,
+                    quantity,
+                    ..
+                } => Some((owner.as_str(), *price, *quantity)),
+                HistoricalAction::Cancel { .. } => None,
+            })
+            .collect();
+        assert_eq!(
+            places,
+            vec![
+                ("alice", 105, 10),
+                ("alice", 106, 10),
+                ("bob", 90, 10),
+                ("carol", 106, 22),
+                ("dave", 106, 2),
+            ]
+        );
+
+        let mut idle = QuotingStrategy::new(QuoteConfig {
+            owner: "strategy".to_string(),
+            half_spread: 50,
+            size: 1,
+            requote_threshold: u32::MAX,
+            initial_mid: 100,
+            max_position: 0,
+        });
+        let report = run_backtest(&[], &orders, &mut idle, "strategy").unwrap();
+        assert_eq!(report.historical_rejected, 0);
+        assert_eq!(report.strategy_orders, 0);
+        assert_eq!(report.mark_price, book.session_stats().last());
+    }
+}
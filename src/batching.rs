@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct BatchingConfig {
+    // Length of the window command rates are measured over, in milliseconds
+    pub window_ms: u64,
+    // Commands within a window at which a symbol starts batching
+    pub busy_threshold: u32,
+    // Batching stops after a full window with fewer commands than this
+    pub calm_threshold: u32,
+    // Longest a command may wait in a batch, in milliseconds
+    pub max_batch_delay_ms: u64,
+}
+
+impl Default for BatchingConfig {
+    fn default() -> Self {
+        BatchingConfig {
+            window_ms: 1_000,
+            busy_threshold: 1_000,
+            calm_threshold: 500,
+            max_batch_delay_ms: 5,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BatchingMetrics {
+    pub commands: u64,
+    pub batched_commands: u64,
+    pub batches_flushed: u64,
+    pub batching: bool,
+    pub times_enabled: u64,
+    pub times_disabled: u64,
+    // Unix timestamps in milliseconds of the latest adaptations
+    pub last_enabled_at: Option<u64>,
+    pub last_disabled_at: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct SymbolActivity {
+    window_start: u64,
+    window_commands: u32,
+    metrics: BatchingMetrics,
+}
+
+// Tracks per-symbol command rates and decides which symbols are busy enough
+// to be matched in micro-batches
+#[derive(Debug, Default)]
+pub struct AdaptiveBatcher {
+    config: BatchingConfig,
+    symbols: HashMap<String, SymbolActivity>,
+}
+
+impl AdaptiveBatcher {
+    pub fn new(config: BatchingConfig) -> AdaptiveBatcher {
+        AdaptiveBatcher {
+            config,
+            symbols: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &BatchingConfig {
+        &self.config
+    }
+
+    // Counts a command for the symbol and returns whether it should be batched
+    pub fn record(&mut self, symbol: &str, now: u64) -> bool {
+        let config = self.config;
+        let activity = self.symbols.entry(symbol.to_string()).or_default();
+
+        if now >= activity.window_start.saturating_add(config.window_ms) {
+            if activity.metrics.batching && activity.window_commands < config.calm_threshold {
+                activity.metrics.batching = false;
+                activity.metrics.times_disabled += 1;
+                activity.metrics.last_disabled_at = Some(now);
+            }
+            activity.window_start = now;
+            activity.window_commands = 0;
+        }
+
+        activity.window_commands += 1;
+        if !activity.metrics.batching && activity.window_commands >= config.busy_threshold {
+            activity.metrics.batching = true;
+            activity.metrics.times_enabled += 1;
+            activity.metrics.last_enabled_at = Some(now);
+        }
+
+        activity.metrics.commands += 1;
+        if activity.metrics.batching {
+            activity.metrics.batched_commands += 1;
+        }
+        activity.metrics.batching
+    }
+
+    pub fn record_flush(&mut self, symbol: &str) {
+        if let Some(activity) = self.symbols.get_mut(symbol) {
+            activity.metrics.batches_flushed += 1;
+        }
+    }
+
+    pub fn metrics(&self, symbol: &str) -> Option<&BatchingMetrics> {
+        self.symbols.get(symbol).map(|activity| &activity.metrics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BatchingConfig {
+        BatchingConfig {
+            window_ms: 100,
+            busy_threshold: 5,
+            calm_threshold: 2,
+            max_batch_delay_ms: 10,
+        }
+    }
+
+    #[test]
+    fn test_busy_symbol_starts_batching() {
+        let mut batcher = AdaptiveBatcher::new(config());
+        for _ in 0..4 {
+            assert!(!batcher.record("AAPL", 10));
+        }
+        assert!(batcher.record("AAPL", 20));
+        assert!(!batcher.record("MSFT", 20));
+
+        let metrics = batcher.metrics("AAPL").unwrap();
+        assert_eq!(metrics.commands, 5);
+        assert_eq!(metrics.batched_commands, 1);
+        assert_eq!(metrics.times_enabled, 1);
+        assert_eq!(metrics.last_enabled_at, Some(20));
+    }
+
+    #[test]
+    fn test_calm_symbol_stops_batching() {
+        let mut batcher = AdaptiveBatcher::new(config());
+        for _ in 0..5 {
+            batcher.record("AAPL", 0);
+        }
+        // Still busy in the next window, so batching continues
+        for _ in 0..3 {
+            assert!(batcher.record("AAPL", 100));
+        }
+        // One quiet window turns it off again
+        assert!(batcher.record("AAPL", 200));
+        assert!(!batcher.record("AAPL", 300));
+
+        let metrics = batcher.metrics("AAPL").unwrap();
+        assert_eq!(metrics.times_disabled, 1);
+        assert_eq!(metrics.last_disabled_at, Some(300));
+    }
+}
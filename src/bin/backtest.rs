@@ -0,0 +1,91 @@
+use anyhow::Result;
+use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
+
+use order_book::{
+    backtest::{parse_csv, parse_event_log, run_backtest, QuotingStrategy},
+    quoting::QuoteConfig,
+    seed::read_file,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// timestamp,action,order_ref,owner,side,price,quantity lines
+    Csv,
+    /// JSON lines of events as returned by GetEvents
+    Events,
+}
+
+/// Replays historical orders through the matching engine with the market
+/// maker's quoting injected, and reports its fills and P&L
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// File of historical orders or events
+    path: PathBuf,
+    #[clap(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+    /// CSV or .json file of resting orders the book starts with
+    #[clap(long)]
+    seed: Option<PathBuf>,
+    #[clap(long, default_value = "backtest")]
+    owner: String,
+    /// Ticks between the mid and each quote
+    #[clap(long, default_value_t = 5)]
+    half_spread: u32,
+    #[clap(long, default_value_t = 10)]
+    size: u64,
+    /// Ticks the mid has to move before resting quotes are replaced
+    #[clap(long, default_value_t = 2)]
+    requote_threshold: u32,
+    /// Mid to quote around until anyone else quotes or trades
+    #[clap(long, default_value_t = 10_000)]
+    initial_mid: u32,
+    /// Largest position either way before a side stops being quoted
+    #[clap(long, default_value_t = 100)]
+    max_position: i64,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let text = std::fs::read_to_string(&cli.path)?;
+    let orders = match cli.format {
+        Format::Csv => parse_csv(&text)?,
+        Format::Events => parse_event_log(&text)?,
+    };
+    let seed = match &cli.seed {
+        Some(path) => read_file(path)?,
+        None => Vec::new(),
+    };
+    let mut strategy = QuotingStrategy::new(QuoteConfig {
+        owner: cli.owner.clone(),
+        half_spread: cli.half_spread,
+        size: cli.size,
+        requote_threshold: cli.requote_threshold,
+        initial_mid: cli.initial_mid,
+        max_position: cli.max_position,
+    });
+    let report = run_backtest(&seed, &orders, &mut strategy, &cli.owner)?;
+
+    println!(
+        "Replayed {} historical orders ({} rejected)",
+        report.historical_orders, report.historical_rejected
+    );
+    println!(
+        "Strategy placed {} orders ({} rejected) for {} quantity",
+        report.strategy_orders, report.strategy_rejected, report.quantity_placed
+    );
+    println!(
+        "Filled {} as maker and {} as taker over {} trades ({:.1}% fill rate)",
+        report.maker_quantity,
+        report.taker_quantity,
+        report.trades,
+        report.fill_rate() * 100.0
+    );
+    println!("Position {} and cash {}", report.position, report.cash);
+    match (report.mark_price, report.pnl) {
+        (Some(mark_price), Some(pnl)) => println!("P&L {pnl} marked at {mark_price}"),
+        _ => println!("No trades to mark the position at"),
+    }
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use anyhow::Result;
+use clap::Parser;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+use std::time::{Duration, Instant};
+use tokio_stream::StreamExt;
+
+use order_book::{
+    book::{L2Book, L2Entry, OrderType},
+    client::{OrderBookClient, Subscription},
+    config::parse_server_url,
+    req::{Channel, SubscribeArgs},
+    resp::{MarketData, MarketDataUpdate},
+    tape::Trade,
+};
+
+// How long the price of the last trade stays highlighted
+const TRADE_FLASH: Duration = Duration::from_millis(500);
+const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Live depth-of-market ladder of a server's book, following its L2 and
+/// trade feeds. Press q or Esc to quit.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Server to connect to as host:port or tcp://host:port
+    #[clap(long, default_value = "127.0.0.1:8080", env = "ORDER_BOOK_SERVER")]
+    server: String,
+    /// Symbol shown in the header, as configured on the server
+    #[clap(long)]
+    symbol: Option<String>,
+    /// Levels shown each side of the spread
+    #[clap(long, default_value_t = 10)]
+    depth: usize,
+}
+
+// What the ladder shows, as of the latest updates
+struct Ladder {
+    symbol: Option<String>,
+    l2_book: L2Book,
+    last_trade: Option<Trade>,
+    traded_at: Option<Instant>,
+    // Updates the server dropped as the viewer fell behind
+    gaps: u64,
+}
+
+impl Ladder {
+    fn new(symbol: Option<String>) -> Ladder {
+        Ladder {
+            symbol,
+            l2_book: L2Book {
+                as_of_seq: 0,
+                bid: Vec::new(),
+                ask: Vec::new(),
+                implied_bid: None,
+                implied_ask: None,
+            },
+            last_trade: None,
+            traded_at: None,
+            gaps: 0,
+        }
+    }
+
+    fn apply(&mut self, update: MarketDataUpdate) {
+        if update.gap {
+            self.gaps += 1;
+        }
+        match update.data {
+            MarketData::L2(l2_book) => self.l2_book = l2_book,
+            MarketData::Trades(trades) => {
+                if let Some(trade) = trades.into_iter().last() {
+                    self.last_trade = Some(trade);
+                    self.traded_at = Some(Instant::now());
+                }
+            }
+            MarketData::L1(_) => {}
+        }
+    }
+
+    // Best bid is last, as sides are listed in ascending price order
+    fn best_bid(&self) -> Option<&L2Entry> {
+        self.l2_book.bid.last()
+    }
+
+    fn best_ask(&self) -> Option<&L2Entry> {
+        self.l2_book.ask.first()
+    }
+
+    fn flashing_price(&self) -> Option<u32> {
+        if self.traded_at?.elapsed() >= TRADE_FLASH {
+            return None;
+        }
+        self.last_trade.as_ref().map(|trade| trade.price)
+    }
+
+    fn header(&self) -> Line<'static> {
+        let level = |entry: Option<&L2Entry>| match entry {
+            Some(entry) => format!("{} x {}", entry.price, entry.total_quantity),
+            None => "-".to_string(),
+        };
+        let spread = match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => (ask.price as i64 - bid.price as i64).to_string(),
+            _ => "-".to_string(),
+        };
+        let last = match &self.last_trade {
+            Some(trade) => {
+                let side = match trade.aggressor {
+                    Some(OrderType::Bid) => " buy",
+                    Some(OrderType::Ask) => " sell",
+                    None => "",
+                };
+                format!("{} x {}{side}", trade.price, trade.quantity)
+            }
+            None => "-".to_string(),
+        };
+        Line::from(format!(
+            "{}  bid {}  ask {}  spread {spread}  last {last}  gaps {}  seq {}",
+            self.symbol.as_deref().unwrap_or("book"),
+            level(self.best_bid()),
+            level(self.best_ask()),
+            self.gaps,
+            self.l2_book.as_of_seq,
+        ))
+    }
+
+    // Asks from the highest down to the best, then bids from the best down
+    fn rows(&self) -> Vec<Row<'static>> {
+        let flashing = self.flashing_price();
+        let price_cell = |price: u32| {
+            let style = match flashing {
+                Some(flash) if flash == price => Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+                _ => Style::default().add_modifier(Modifier::BOLD),
+            };
+            Cell::from(price.to_string()).style(style)
+        };
+        let asks = self.l2_book.ask.iter().rev().map(|entry| {
+            Row::new(vec![
+                Cell::from(""),
+                price_cell(entry.price),
+                Cell::from(entry.total_quantity.to_string()).style(Style::default().fg(Color::Red)),
+            ])
+        });
+        let bids = self.l2_book.bid.iter().rev().map(|entry| {
+            Row::new(vec![
+                Cell::from(entry.total_quantity.to_string())
+                    .style(Style::default().fg(Color::Green)),
+                price_cell(entry.price),
+                Cell::from(""),
+            ])
+        });
+        asks.chain(bids).collect()
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, ladder] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(frame.area());
+        frame.render_widget(
+            Paragraph::new(self.header()).block(Block::default().borders(Borders::ALL)),
+            header,
+        );
+        let widths = [Constraint::Length(12); 3];
+        let table = Table::new(self.rows(), widths)
+            .header(
+                Row::new(vec!["Bid", "Price", "Ask"])
+                    .style(Style::default().add_modifier(Modifier::UNDERLINED)),
+            )
+            .block(Block::default().borders(Borders::ALL).title(" q to quit "));
+        frame.render_widget(table, ladder);
+    }
+}
+
+// Whether a key to quit was pressed since the last check
+fn quit_pressed() -> Result<bool> {
+    while event::poll(Duration::ZERO)? {
+        if let Event::Key(key) = event::read()? {
+            let ctrl_c =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if key.kind == KeyEventKind::Press
+                && (ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc))
+            {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+// Redraws on a timer rather than per update, so a busy feed doesn't cost a
+// frame per update and a trade's flash still fades on a quiet one
+async fn run(
+    terminal: &mut DefaultTerminal,
+    updates: &mut Subscription<MarketDataUpdate>,
+    mut ladder: Ladder,
+) -> Result<()> {
+    let mut redraw = tokio::time::interval(REDRAW_INTERVAL);
+    loop {
+        tokio::select! {
+            update = updates.next() => match update {
+                Some(update) => ladder.apply(update?),
+                None => return Ok(()),
+            },
+            _ = redraw.tick() => {
+                terminal.draw(|frame| ladder.draw(frame))?;
+                if quit_pressed()? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let client = OrderBookClient::connect(&parse_server_url(&cli.server)?).await?;
+    let subscription = |channel, depth| SubscribeArgs {
+        symbol: cli.symbol.clone(),
+        channel,
+        depth,
+    };
+    let mut updates = client
+        .subscribe_market_data(vec![
+            subscription(Channel::L2, Some(cli.depth)),
+            subscription(Channel::Trades, None),
+        ])
+        .await?;
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut updates, Ladder::new(cli.symbol.clone())).await;
+    // Puts the terminal back however the viewer stopped
+    ratatui::restore();
+    result
+}
@@ -1,73 +1,1169 @@
-use anyhow::Result;
-use tokio::net::TcpStream;
+use anyhow::{anyhow, Result};
 
 use order_book::{
-    book::OrderType,
-    req::{CancelOrderArgs, PlaceOrderArgs, Request},
+    analytics::DEFAULT_ANALYTICS_DEPTH,
+    book::{CancelFilter, ExecutionReport, MarketProtection, OpenOrder, OrderStatus, OrderType},
+    clearing::AccountAction,
+    client::{OrderBookClient, ReconnectPolicy},
+    config::{parse_server_url, ServerConfig},
+    export::{write_orders, ExportFormat},
+    fees::FeeTier,
+    instrument::InstrumentStatus,
+    order::ANONYMOUS_OWNER,
+    order_cache::OrderCache,
+    query::{PageRequest, DEFAULT_PAGE_LIMIT},
+    req::{
+        AccountActionArgs, BustTradeArgs, CancelAllArgs, CancelClientOrderArgs, CancelOrderArgs,
+        EndOfDayArgs, FundsArgs, MigrateSymbolArgs, OptionChainArgs, PlaceMarketOrderArgs,
+        PlaceOrderArgs, QueryCandlesArgs, QueryOrderArgs, Request, ScheduleFeesArgs,
+        SetFeeTiersArgs, SetInstrumentStatusArgs, SetParticipantRiskArgs, ViewAccountArgs,
+        ViewOpenOrdersArgs, ViewStatsArgs,
+    },
     resp::Response,
-    wire::{read_msg, write_msg},
+    risk::{ParticipantRiskConfig, RiskCheckKind, SelfMatchPrevention},
+    seed::read_file,
+    settlement::{EndOfDayOptions, DEFAULT_SETTLEMENT_WINDOW_MS},
 };
 
-use clap::{Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Editor, Helper,
+};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
 use uuid::Uuid;
 
+const PROMPT: &str = "order-book> ";
+
+// File the client keeps its acked orders in between runs
+const ORDER_CACHE_ENV: &str = "ORDER_BOOK_ORDER_CACHE";
+const DEFAULT_ORDER_CACHE: &str = ".order_book_orders.json";
+
+/// Sends a single command, or starts an interactive session over one
+/// connection when run without one
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
 struct Cli {
+    /// Server to connect to as host:port or tcp://host:port, by default the
+    /// one --config binds
+    #[clap(long, global = true, env = "ORDER_BOOK_SERVER")]
+    server: Option<String>,
+    /// Server's TOML config, to connect to the address it binds
+    #[clap(long, global = true, env = "ORDER_BOOK_CONFIG")]
+    config: Option<PathBuf>,
+    /// Has the server compress large responses, such as deep books
+    #[clap(long, global = true)]
+    compress: bool,
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+// One line of an interactive session
+#[derive(Parser)]
+#[command(no_binary_name = true, disable_version_flag = true)]
+#[command(override_usage = "<COMMAND> [ARGS]")]
+struct ReplLine {
+    #[command(subcommand)]
+    command: Commands,
+}
+
 #[derive(Subcommand)]
 enum Commands {
+    #[command(visible_alias = "place")]
     PlaceOrder {
         #[clap(long, short, action)]
         is_bid: bool,
+        #[clap(long, default_value = ANONYMOUS_OWNER)]
+        owner: String,
+        /// Unix timestamp in milliseconds after which the order expires
+        #[clap(long)]
+        expires_at: Option<u64>,
+        /// Id of your choosing. Resending it places nothing new and returns
+        /// the first order's id.
+        #[clap(long)]
+        client_order_id: Option<String>,
         price: u32,
-        quantity: u32,
+        quantity: u64,
+    },
+    /// Trade at once at the best prices available, within a protection
+    /// limit, canceling what can't be filled
+    PlaceMarketOrder {
+        #[clap(long, short, action)]
+        is_bid: bool,
+        #[clap(long, default_value = ANONYMOUS_OWNER)]
+        owner: String,
+        /// Worst price to trade at
+        #[clap(long, required_unless_present = "protection_ticks")]
+        protection_price: Option<u32>,
+        /// Ticks past the best price on the other side to trade at most
+        #[clap(long, conflicts_with = "protection_price")]
+        protection_ticks: Option<u32>,
+        #[clap(long)]
+        client_order_id: Option<String>,
+        quantity: u64,
     },
+    #[command(visible_alias = "cancel")]
     CancelOrder {
         order_id: Uuid,
     },
+    /// Cancel an order by the client order id it was placed with
+    CancelClientOrder {
+        #[clap(long, default_value = ANONYMOUS_OWNER)]
+        owner: String,
+        client_order_id: String,
+    },
+    /// Place several orders in one request, applied together in order
+    PlaceOrders {
+        #[clap(long, default_value = ANONYMOUS_OWNER)]
+        owner: String,
+        /// Unix timestamp in milliseconds after which the orders expire
+        #[clap(long)]
+        expires_at: Option<u64>,
+        /// <bid|ask>:<price>:<quantity>
+        #[clap(value_parser = parse_order_spec, required = true)]
+        orders: Vec<OrderSpec>,
+    },
+    /// Cancel several orders in one request
+    CancelOrders {
+        #[clap(required = true)]
+        order_ids: Vec<Uuid>,
+    },
+    /// Seed the book with the resting orders in a CSV or JSON file, loading
+    /// all of them or none if any would trade or be rejected
+    LoadOrders {
+        /// CSV of price,quantity,side,owner lines, or a .json array
+        path: PathBuf,
+    },
+    /// Cancel every resting order, those of one owner, or those of one side
+    /// within a price range
+    CancelAll {
+        #[clap(long, conflicts_with_all = ["min_price", "max_price"])]
+        owner: Option<String>,
+        #[clap(long, short, action, requires = "min_price")]
+        is_bid: bool,
+        #[clap(long, requires = "max_price")]
+        min_price: Option<u32>,
+        #[clap(long, requires = "min_price")]
+        max_price: Option<u32>,
+    },
+    #[command(visible_alias = "book")]
     ViewL2Book,
+    ViewL1Book,
+    /// Write every resting order to a file for offline analysis
+    ExportBook {
+        path: PathBuf,
+        #[clap(long, value_enum, default_value_t = ExportFormatArg::Json)]
+        format: ExportFormatArg,
+    },
+    ViewStats {
+        /// Levels per side included in the order book imbalance
+        #[clap(long, default_value_t = DEFAULT_ANALYTICS_DEPTH)]
+        depth: usize,
+    },
+    ResumeTrading,
+    #[command(visible_alias = "trades")]
+    GetTrades {
+        #[clap(flatten)]
+        page: PageArgs,
+    },
+    ScheduleFees {
+        #[clap(allow_negative_numbers = true)]
+        maker_fee_bps: i32,
+        #[clap(allow_negative_numbers = true)]
+        taker_fee_bps: i32,
+        /// Unix timestamp in milliseconds
+        effective_from: u64,
+    },
+    ViewFeeSchedules,
+    /// Replace the volume fee tiers. Omit to charge everyone by the schedule.
+    SetFeeTiers {
+        /// <min_volume>:<maker_fee_bps>:<taker_fee_bps>
+        #[clap(value_parser = parse_fee_tier)]
+        tiers: Vec<FeeTier>,
+    },
+    /// Show an owner's 30 day volume, fee tier and accrued fees and rebates
+    ViewFeeTier {
+        owner: String,
+    },
+    ViewAccount {
+        owner: String,
+    },
+    /// Show an owner's cash and how much of it their resting bids reserve
+    ViewBalance {
+        owner: String,
+    },
+    /// Show an owner's equity at the mark price and the margin their
+    /// position and open orders need
+    ViewMargin {
+        owner: String,
+    },
+    /// Close the session: settle, expire day orders and report positions
+    EndOfDay {
+        /// Trades this long before the close make up the settlement price
+        #[clap(long, default_value_t = DEFAULT_SETTLEMENT_WINDOW_MS)]
+        settlement_window_ms: u64,
+        /// Leave orders without an expiry resting into the next session
+        #[clap(long)]
+        keep_day_orders: bool,
+        /// Stay halted instead of starting the next session
+        #[clap(long)]
+        no_roll: bool,
+    },
+    ViewSettlements,
+    /// Net every participant's trades since the last netting run
+    NetTrades,
+    ViewNettingRuns,
+    /// Latency percentiles of the book's operations
+    ViewLatency,
+    /// Checksum of the resting orders, to compare against a replica's
+    ViewChecksum,
+    /// Last price and the high, low, volume and price change over 24 hours
+    ViewMarketStats,
+    BustTrade {
+        trade_seq: u64,
+    },
+    Deposit {
+        owner: String,
+        amount: u64,
+        #[clap(long, default_value = "")]
+        memo: String,
+    },
+    Withdraw {
+        owner: String,
+        amount: u64,
+        #[clap(long, default_value = "")]
+        memo: String,
+    },
+    Transfer {
+        from: String,
+        to: String,
+        amount: u64,
+        #[clap(long, default_value = "")]
+        memo: String,
+    },
+    ViewAuditLog,
+    StartAuction,
+    Uncross,
+    /// Moves a symbol's book to another shard of an exchange server
+    MigrateSymbol {
+        symbol: String,
+        shard: usize,
+    },
+    /// Opens, halts or closes trading in a symbol of an exchange server
+    SetInstrumentStatus {
+        symbol: String,
+        #[clap(value_enum)]
+        status: InstrumentStatusArg,
+    },
+    /// Calls and puts an exchange server lists on the underlying
+    ViewOptionChain {
+        underlying: String,
+        /// Only the series expiring at this Unix timestamp in milliseconds
+        #[clap(long)]
+        expires_at: Option<u64>,
+    },
+    QueryCandles {
+        /// Bar length in milliseconds
+        #[clap(long, default_value_t = 60_000)]
+        interval_ms: u64,
+        #[clap(flatten)]
+        page: PageArgs,
+    },
+    SetParticipantRisk {
+        owner: String,
+        /// Pre-trade checks the participant is exempt from. Omit to clear.
+        #[clap(long, value_enum)]
+        bypass: Vec<RiskCheckArg>,
+        /// What happens when the participant's orders would trade with each
+        /// other. Omit to follow the book's setting.
+        #[clap(long, value_enum)]
+        self_match_prevention: Option<SelfMatchArg>,
+    },
+    ViewOpenOrders {
+        owner: String,
+        #[clap(flatten)]
+        page: PageArgs,
+    },
+    QueryOrder {
+        order_id: Uuid,
+    },
+    QueryOrderAccount {
+        order_id: Uuid,
+    },
+    /// Check that no order quantity was lost or created by the matcher
+    CheckConservation,
+    /// Compare the locally cached orders of an owner with the server's open
+    /// orders and update the cache to match
+    Reconcile {
+        owner: String,
+    },
+}
+
+// Paging flags shared by every list query
+#[derive(Args)]
+struct PageArgs {
+    /// next_cursor of the previous page
+    #[clap(long)]
+    cursor: Option<u64>,
+    #[clap(long, default_value_t = DEFAULT_PAGE_LIMIT)]
+    limit: usize,
+    /// Unix timestamp in milliseconds of the earliest item
+    #[clap(long)]
+    from_time: Option<u64>,
+    /// Unix timestamp in milliseconds of the latest item
+    #[clap(long)]
+    to_time: Option<u64>,
+}
+
+impl From<&PageArgs> for PageRequest {
+    fn from(args: &PageArgs) -> PageRequest {
+        PageRequest {
+            cursor: args.cursor,
+            limit: args.limit,
+            from_time: args.from_time,
+            to_time: args.to_time,
+        }
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum RiskCheckArg {
+    PriceBand,
+    MaxNotional,
+    MaxOrderSize,
+    PriceCollar,
+    PositionLimit,
+    DuplicateOrder,
+    Funds,
+    Margin,
+    Custom,
+}
+
+impl From<RiskCheckArg> for RiskCheckKind {
+    fn from(arg: RiskCheckArg) -> RiskCheckKind {
+        match arg {
+            RiskCheckArg::PriceBand => RiskCheckKind::PriceBand,
+            RiskCheckArg::MaxNotional => RiskCheckKind::MaxNotional,
+            RiskCheckArg::MaxOrderSize => RiskCheckKind::MaxOrderSize,
+            RiskCheckArg::PriceCollar => RiskCheckKind::PriceCollar,
+            RiskCheckArg::PositionLimit => RiskCheckKind::PositionLimit,
+            RiskCheckArg::DuplicateOrder => RiskCheckKind::DuplicateOrder,
+            RiskCheckArg::Funds => RiskCheckKind::Funds,
+            RiskCheckArg::Margin => RiskCheckKind::Margin,
+            RiskCheckArg::Custom => RiskCheckKind::Custom,
+        }
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum SelfMatchArg {
+    CancelResting,
+    CancelAggressing,
+    CancelBoth,
+    DecrementAndCancel,
+}
+
+impl From<SelfMatchArg> for SelfMatchPrevention {
+    fn from(arg: SelfMatchArg) -> SelfMatchPrevention {
+        match arg {
+            SelfMatchArg::CancelResting => SelfMatchPrevention::CancelResting,
+            SelfMatchArg::CancelAggressing => SelfMatchPrevention::CancelAggressing,
+            SelfMatchArg::CancelBoth => SelfMatchPrevention::CancelBoth,
+            SelfMatchArg::DecrementAndCancel => SelfMatchPrevention::DecrementAndCancel,
+        }
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum InstrumentStatusArg {
+    PreOpen,
+    Open,
+    Halted,
+    Closed,
+}
+
+impl From<InstrumentStatusArg> for InstrumentStatus {
+    fn from(arg: InstrumentStatusArg) -> InstrumentStatus {
+        match arg {
+            InstrumentStatusArg::PreOpen => InstrumentStatus::PreOpen,
+            InstrumentStatusArg::Open => InstrumentStatus::Open,
+            InstrumentStatusArg::Halted => InstrumentStatus::Halted,
+            InstrumentStatusArg::Closed => InstrumentStatus::Closed,
+        }
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ExportFormatArg {
+    Json,
+    Csv,
+}
+
+impl From<ExportFormatArg> for ExportFormat {
+    fn from(arg: ExportFormatArg) -> ExportFormat {
+        match arg {
+            ExportFormatArg::Json => ExportFormat::Json,
+            ExportFormatArg::Csv => ExportFormat::Csv,
+        }
+    }
+}
+
+fn parse_fee_tier(tier: &str) -> Result<FeeTier> {
+    match tier.split(':').collect::<Vec<_>>().as_slice() {
+        [min_volume, maker_fee_bps, taker_fee_bps] => Ok(FeeTier {
+            min_volume: min_volume.parse()?,
+            maker_fee_bps: maker_fee_bps.parse()?,
+            taker_fee_bps: taker_fee_bps.parse()?,
+        }),
+        _ => Err(anyhow!(
+            "Expected <min_volume>:<maker_fee_bps>:<taker_fee_bps> but got {tier}"
+        )),
+    }
+}
+
+#[derive(Clone)]
+struct OrderSpec {
+    order_type: OrderType,
+    price: u32,
+    quantity: u64,
+}
+
+fn parse_order_spec(order: &str) -> Result<OrderSpec> {
+    match order.split(':').collect::<Vec<_>>().as_slice() {
+        [side, price, quantity] => Ok(OrderSpec {
+            order_type: match *side {
+                "bid" => OrderType::Bid,
+                "ask" => OrderType::Ask,
+                _ => return Err(anyhow!("Expected bid or ask but got {side}")),
+            },
+            price: price.parse()?,
+            quantity: quantity.parse()?,
+        }),
+        _ => Err(anyhow!(
+            "Expected <bid|ask>:<price>:<quantity> but got {order}"
+        )),
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
-
+    let addr = match (&cli.server, &cli.config) {
+        (Some(url), _) => parse_server_url(url)?,
+        (None, Some(path)) => ServerConfig::load(path)?.addr(),
+        (None, None) => ServerConfig::default().addr(),
+    };
+    // Opened on the first request. A failed request drops it, so the next
+    // one reconnects rather than the session ending.
+    let mut client = OrderBookClient::new(
+        &addr,
+        ReconnectPolicy {
+            max_attempts: 1,
+            ..ReconnectPolicy::default()
+        },
+    );
+    client.set_compression(cli.compress).await?;
     match &cli.command {
-        Some(Commands::PlaceOrder {
+        Some(command) => run_command(&mut client, command).await,
+        None => run_interactive(&mut client).await,
+    }
+}
+
+async fn run_command(client: &mut OrderBookClient, command: &Commands) -> Result<()> {
+    match command {
+        Commands::PlaceOrder {
             is_bid,
+            owner,
+            expires_at,
+            client_order_id,
             price,
             quantity,
-        }) => {
+        } => {
             let order_type = if *is_bid {
                 OrderType::Bid
             } else {
                 OrderType::Ask
             };
-            process_request(Request::PlaceOrder(PlaceOrderArgs { order_type, quantity: *quantity, price: *price })).await.unwrap();
+            let response = process_request(
+                client,
+                Request::PlaceOrder(PlaceOrderArgs {
+                    order_type,
+                    quantity: *quantity,
+                    price: *price,
+                    owner: owner.clone(),
+                    expires_at: *expires_at,
+                    client_order_id: client_order_id.clone(),
+                }),
+            )
+            .await?;
+            // Orders filled in full never rest, reconcile catches later fills
+            if let Response::PlaceOk(report) = response {
+                if let Some(order) = resting_order(&report, owner, order_type, *price, *expires_at)
+                {
+                    update_order_cache(|cache| cache.record_placed(order))?;
+                }
+            }
         }
-        Some(Commands::CancelOrder { order_id }) => {
-            process_request(Request::CancelOrder(CancelOrderArgs { order_id: *order_id })).await.unwrap();
+        Commands::PlaceMarketOrder {
+            is_bid,
+            owner,
+            protection_price,
+            protection_ticks,
+            client_order_id,
+            quantity,
+        } => {
+            let order_type = if *is_bid {
+                OrderType::Bid
+            } else {
+                OrderType::Ask
+            };
+            let protection = match (protection_price, protection_ticks) {
+                (Some(price), _) => MarketProtection::Price(*price),
+                (None, Some(ticks)) => MarketProtection::Ticks(*ticks),
+                (None, None) => unreachable!("clap requires a protection"),
+            };
+            // Market orders never rest, so there is nothing to cache
+            process_request(
+                client,
+                Request::PlaceMarketOrder(PlaceMarketOrderArgs {
+                    order_type,
+                    quantity: *quantity,
+                    owner: owner.clone(),
+                    protection,
+                    client_order_id: client_order_id.clone(),
+                }),
+            )
+            .await?;
+        }
+        Commands::CancelOrder { order_id } => {
+            let response = process_request(
+                client,
+                Request::CancelOrder(CancelOrderArgs {
+                    order_id: *order_id,
+                }),
+            )
+            .await?;
+            if let Response::CancelOk(_) = response {
+                update_order_cache(|cache| cache.record_canceled(*order_id))?;
+            }
+        }
+        Commands::CancelClientOrder {
+            owner,
+            client_order_id,
+        } => {
+            // The cache only knows order ids, so reconcile drops the order
+            process_request(
+                client,
+                Request::CancelClientOrder(CancelClientOrderArgs {
+                    owner: owner.clone(),
+                    client_order_id: client_order_id.clone(),
+                }),
+            )
+            .await?;
+        }
+        Commands::PlaceOrders {
+            owner,
+            expires_at,
+            orders,
+        } => {
+            let response = process_request(
+                client,
+                Request::PlaceOrders(
+                    orders
+                        .iter()
+                        .map(|order| PlaceOrderArgs {
+                            order_type: order.order_type,
+                            price: order.price,
+                            quantity: order.quantity,
+                            owner: owner.clone(),
+                            expires_at: *expires_at,
+                            client_order_id: None,
+                        })
+                        .collect(),
+                ),
+            )
+            .await?;
+            if let Response::PlaceOrdersOk(results) = response {
+                update_order_cache(|cache| {
+                    for (order, result) in orders.iter().zip(results) {
+                        let Response::PlaceOk(report) = result else {
+                            continue;
+                        };
+                        if let Some(open_order) = resting_order(
+                            &report,
+                            owner,
+                            order.order_type,
+                            order.price,
+                            *expires_at,
+                        ) {
+                            cache.record_placed(open_order);
+                        }
+                    }
+                })?;
+            }
+        }
+        Commands::LoadOrders { path } => {
+            let orders = read_file(path)?;
+            let response = process_request(client, Request::LoadOrders(orders.clone())).await?;
+            if let Response::LoadOrdersOk(order_ids) = response {
+                update_order_cache(|cache| {
+                    for (order, order_id) in orders.into_iter().zip(order_ids) {
+                        cache.record_placed(OpenOrder {
+                            order_id,
+                            owner: order.owner,
+                            order_type: order.side,
+                            price: order.price,
+                            quantity: order.quantity,
+                            expires_at: None,
+                            timestamp: 0,
+                            arrival_seq: 0,
+                        });
+                    }
+                })?;
+            }
+        }
+        Commands::CancelOrders { order_ids } => {
+            let response = process_request(
+                client,
+                Request::CancelOrders(
+                    order_ids
+                        .iter()
+                        .map(|&order_id| CancelOrderArgs { order_id })
+                        .collect(),
+                ),
+            )
+            .await?;
+            if let Response::CancelOrdersOk(results) = response {
+                update_order_cache(|cache| {
+                    for (&order_id, result) in order_ids.iter().zip(results) {
+                        if let Response::CancelOk(_) = result {
+                            cache.record_canceled(order_id);
+                        }
+                    }
+                })?;
+            }
+        }
+        Commands::CancelAll {
+            owner,
+            is_bid,
+            min_price,
+            max_price,
+        } => {
+            let filter = match (owner, min_price, max_price) {
+                (Some(owner), _, _) => CancelFilter::Owner(owner.clone()),
+                (None, Some(min_price), Some(max_price)) => CancelFilter::PriceRange {
+                    side: if *is_bid {
+                        OrderType::Bid
+                    } else {
+                        OrderType::Ask
+                    },
+                    min_price: *min_price,
+                    max_price: *max_price,
+                },
+                _ => CancelFilter::All,
+            };
+            let response =
+                process_request(client, Request::CancelAll(CancelAllArgs { filter })).await?;
+            if let Response::CancelAllOk(order_ids) = response {
+                update_order_cache(|cache| {
+                    for order_id in order_ids {
+                        cache.record_canceled(order_id);
+                    }
+                })?;
+            }
+        }
+        Commands::ViewL2Book => {
+            process_request(client, Request::ViewL2Book).await?;
+        }
+        Commands::ExportBook { path, format } => {
+            let response = client.request(Request::ViewL3Book).await?;
+            let Response::L3BookOk(l3_book) = response else {
+                println!("Response: {:#?}", response);
+                return Ok(());
+            };
+            let count = l3_book.orders.len();
+            let file = BufWriter::new(File::create(path)?);
+            write_orders(l3_book.orders, (*format).into(), file)?;
+            println!(
+                "Exported {count} orders as of event {} to {}",
+                l3_book.as_of_seq,
+                path.display()
+            );
+        }
+        Commands::ViewL1Book => {
+            process_request(client, Request::ViewL1Book).await?;
+        }
+        Commands::ViewStats { depth } => {
+            process_request(client, Request::ViewStats(ViewStatsArgs { depth: *depth })).await?;
+        }
+        Commands::ResumeTrading => {
+            process_request(client, Request::ResumeTrading).await?;
+        }
+        Commands::GetTrades { page } => {
+            process_request(client, Request::GetTrades(page.into())).await?;
+        }
+        Commands::ScheduleFees {
+            maker_fee_bps,
+            taker_fee_bps,
+            effective_from,
+        } => {
+            process_request(
+                client,
+                Request::ScheduleFees(ScheduleFeesArgs {
+                    maker_fee_bps: *maker_fee_bps,
+                    taker_fee_bps: *taker_fee_bps,
+                    effective_from: *effective_from,
+                }),
+            )
+            .await?;
+        }
+        Commands::ViewFeeSchedules => {
+            process_request(client, Request::ViewFeeSchedules).await?;
+        }
+        Commands::SetFeeTiers { tiers } => {
+            process_request(
+                client,
+                Request::SetFeeTiers(SetFeeTiersArgs {
+                    tiers: tiers.clone(),
+                }),
+            )
+            .await?;
+        }
+        Commands::ViewFeeTier { owner } => {
+            process_request(
+                client,
+                Request::ViewFeeTier(ViewAccountArgs {
+                    owner: owner.clone(),
+                }),
+            )
+            .await?;
+        }
+        Commands::EndOfDay {
+            settlement_window_ms,
+            keep_day_orders,
+            no_roll,
+        } => {
+            process_request(
+                client,
+                Request::EndOfDay(EndOfDayArgs {
+                    options: EndOfDayOptions {
+                        settlement_window_ms: *settlement_window_ms,
+                        expire_day_orders: !keep_day_orders,
+                        roll: !no_roll,
+                    },
+                }),
+            )
+            .await?;
+        }
+        Commands::ViewSettlements => {
+            process_request(client, Request::ViewSettlements).await?;
+        }
+        Commands::NetTrades => {
+            process_request(client, Request::NetTrades).await?;
+        }
+        Commands::ViewNettingRuns => {
+            process_request(client, Request::ViewNettingRuns).await?;
+        }
+        Commands::ViewLatency => {
+            process_request(client, Request::ViewLatency).await?;
+        }
+        Commands::ViewChecksum => {
+            process_request(client, Request::ViewChecksum).await?;
+        }
+        Commands::ViewMarketStats => {
+            process_request(client, Request::ViewMarketStats).await?;
+        }
+        Commands::ViewAccount { owner } => {
+            process_request(
+                client,
+                Request::ViewAccount(ViewAccountArgs {
+                    owner: owner.clone(),
+                }),
+            )
+            .await?;
+        }
+        Commands::ViewBalance { owner } => {
+            process_request(
+                client,
+                Request::ViewBalance(ViewAccountArgs {
+                    owner: owner.clone(),
+                }),
+            )
+            .await?;
+        }
+        Commands::ViewMargin { owner } => {
+            process_request(
+                client,
+                Request::ViewMargin(ViewAccountArgs {
+                    owner: owner.clone(),
+                }),
+            )
+            .await?;
+        }
+        Commands::BustTrade { trade_seq } => {
+            process_request(
+                client,
+                Request::BustTrade(BustTradeArgs {
+                    trade_seq: *trade_seq,
+                }),
+            )
+            .await?;
+        }
+        Commands::Deposit {
+            owner,
+            amount,
+            memo,
+        } => {
+            process_request(
+                client,
+                Request::Deposit(FundsArgs {
+                    owner: owner.clone(),
+                    amount: *amount,
+                    memo: memo.clone(),
+                }),
+            )
+            .await?;
+        }
+        Commands::Withdraw {
+            owner,
+            amount,
+            memo,
+        } => {
+            process_request(
+                client,
+                Request::Withdraw(FundsArgs {
+                    owner: owner.clone(),
+                    amount: *amount,
+                    memo: memo.clone(),
+                }),
+            )
+            .await?;
+        }
+        Commands::Transfer {
+            from,
+            to,
+            amount,
+            memo,
+        } => {
+            let action = AccountAction::Transfer {
+                from: from.clone(),
+                to: to.clone(),
+                amount: *amount,
+            };
+            process_account_action(client, action, memo).await?;
+        }
+        Commands::ViewAuditLog => {
+            process_request(client, Request::ViewAuditLog).await?;
+        }
+        Commands::StartAuction => {
+            process_request(client, Request::StartAuction).await?;
+        }
+        Commands::Uncross => {
+            process_request(client, Request::Uncross).await?;
+        }
+        Commands::MigrateSymbol { symbol, shard } => {
+            process_request(
+                client,
+                Request::MigrateSymbol(MigrateSymbolArgs {
+                    symbol: symbol.clone(),
+                    shard: *shard,
+                }),
+            )
+            .await?;
+        }
+        Commands::SetInstrumentStatus { symbol, status } => {
+            process_request(
+                client,
+                Request::SetInstrumentStatus(SetInstrumentStatusArgs {
+                    symbol: symbol.clone(),
+                    status: (*status).into(),
+                }),
+            )
+            .await?;
+        }
+        Commands::ViewOptionChain {
+            underlying,
+            expires_at,
+        } => {
+            process_request(
+                client,
+                Request::ViewOptionChain(OptionChainArgs {
+                    underlying: underlying.clone(),
+                    expires_at: *expires_at,
+                }),
+            )
+            .await?;
+        }
+        Commands::QueryCandles { interval_ms, page } => {
+            process_request(
+                client,
+                Request::QueryCandles(QueryCandlesArgs {
+                    interval_ms: *interval_ms,
+                    page: page.into(),
+                }),
+            )
+            .await?;
         }
-        Some(Commands::ViewL2Book) => {
-            process_request(Request::ViewL2Book).await.unwrap();
+        Commands::SetParticipantRisk {
+            owner,
+            bypass,
+            self_match_prevention,
+        } => {
+            process_request(
+                client,
+                Request::SetParticipantRisk(SetParticipantRiskArgs {
+                    owner: owner.clone(),
+                    config: ParticipantRiskConfig {
+                        bypass: bypass.iter().map(|&check| check.into()).collect(),
+                        self_match_prevention: self_match_prevention.map(Into::into),
+                    },
+                }),
+            )
+            .await?;
         }
-        None => {
-            println!("No command issued");
+        Commands::ViewOpenOrders { owner, page } => {
+            process_request(
+                client,
+                Request::ViewOpenOrders(ViewOpenOrdersArgs {
+                    owner: owner.clone(),
+                    page: page.into(),
+                }),
+            )
+            .await?;
+        }
+        Commands::QueryOrder { order_id } => {
+            process_request(
+                client,
+                Request::QueryOrder(QueryOrderArgs {
+                    order_id: *order_id,
+                }),
+            )
+            .await?;
+        }
+        Commands::QueryOrderAccount { order_id } => {
+            process_request(
+                client,
+                Request::QueryOrderAccount(QueryOrderArgs {
+                    order_id: *order_id,
+                }),
+            )
+            .await?;
+        }
+        Commands::CheckConservation => {
+            process_request(client, Request::CheckConservation).await?;
+        }
+        Commands::Reconcile { owner } => {
+            reconcile(client, owner).await?;
         }
     }
-
     Ok(())
 }
 
-async fn process_request(request: Request) -> Result<()> {
-    let mut socket = TcpStream::connect("127.0.0.1:8080").await?;
-    write_msg(&mut socket, &request).await.unwrap();
-    let response: Response = read_msg(&mut socket).await.unwrap();
+async fn process_account_action(
+    client: &mut OrderBookClient,
+    action: AccountAction,
+    memo: &str,
+) -> Result<Response> {
+    process_request(
+        client,
+        Request::AccountAction(AccountActionArgs {
+            action,
+            memo: memo.to_string(),
+        }),
+    )
+    .await
+}
+
+async fn process_request(client: &mut OrderBookClient, request: Request) -> Result<Response> {
+    let response = client.request(request).await?;
     println!("Response: {:#?}", response);
+    Ok(response)
+}
+
+fn order_cache_path() -> PathBuf {
+    std::env::var(ORDER_CACHE_ENV)
+        .unwrap_or_else(|_| DEFAULT_ORDER_CACHE.to_string())
+        .into()
+}
+
+// Order as it rests on the book after being placed, if any of it does
+fn resting_order(
+    report: &ExecutionReport,
+    owner: &str,
+    order_type: OrderType,
+    price: u32,
+    expires_at: Option<u64>,
+) -> Option<OpenOrder> {
+    let OrderStatus::Resting {
+        remaining_qty,
+        timestamp,
+        arrival_seq,
+        ..
+    } = report.status
+    else {
+        return None;
+    };
+    Some(OpenOrder {
+        order_id: report.order_id,
+        owner: owner.to_string(),
+        order_type,
+        price,
+        quantity: remaining_qty,
+        expires_at,
+        timestamp,
+        arrival_seq,
+    })
+}
+
+fn update_order_cache(update: impl FnOnce(&mut OrderCache)) -> Result<()> {
+    let path = order_cache_path();
+    let mut cache = OrderCache::load(&path)?;
+    update(&mut cache);
+    cache.save(&path)
+}
+
+async fn reconcile(client: &mut OrderBookClient, owner: &str) -> Result<()> {
+    let open_orders = client.open_orders(owner).await?;
+
+    let path = order_cache_path();
+    let mut cache = OrderCache::load(&path)?;
+    let mismatches = cache.reconcile(owner, &open_orders);
+    cache.save(&path)?;
+    if mismatches.is_empty() {
+        println!("{} open orders match the cache", open_orders.len());
+    } else {
+        println!("Mismatches: {:#?}", mismatches);
+    }
     Ok(())
 }
+
+async fn run_interactive(client: &mut OrderBookClient) -> Result<()> {
+    let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ReplHelper::new()));
+    println!("Type help for commands, Tab to complete and exit to leave");
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            // Ctrl-C drops the line being typed, Ctrl-D ends the session
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        let words = match split_words(&line) {
+            Ok(words) => words,
+            Err(err) => {
+                eprintln!("{err}");
+                continue;
+            }
+        };
+        match words.first().map(String::as_str) {
+            None => continue,
+            Some("exit" | "quit") => return Ok(()),
+            Some(_) => {}
+        }
+        editor.add_history_entry(line.as_str())?;
+        match ReplLine::try_parse_from(words) {
+            Ok(repl_line) => {
+                if let Err(err) = run_command(client, &repl_line.command).await {
+                    eprintln!("Error: {err:#}");
+                }
+            }
+            // Covers help as well as mistakes
+            Err(err) => err.print()?,
+        }
+    }
+}
+
+// Splits a line into words on whitespace, keeping double quoted text such as
+// a memo with spaces together
+fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quoted {
+        return Err(anyhow!("Unterminated quote"));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+// Completes command names as the first word and their long flags after it
+struct ReplHelper {
+    command: clap::Command,
+}
+
+impl ReplHelper {
+    fn new() -> ReplHelper {
+        ReplHelper {
+            command: ReplLine::command(),
+        }
+    }
+
+    fn candidates(&self, words: &[&str]) -> Vec<String> {
+        let Some((name, _)) = words.split_first() else {
+            let mut names: Vec<String> = self
+                .command
+                .get_subcommands()
+                .flat_map(|subcommand| {
+                    std::iter::once(subcommand.get_name()).chain(subcommand.get_visible_aliases())
+                })
+                .map(str::to_string)
+                .collect();
+            names.extend(["help", "exit", "quit"].map(str::to_string));
+            return names;
+        };
+        self.command
+            .find_subcommand(name)
+            .into_iter()
+            .flat_map(|subcommand| subcommand.get_arguments())
+            .filter_map(|arg| arg.get_long())
+            .map(|long| format!("--{long}"))
+            .collect()
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |idx| idx + 1);
+        let (before, partial) = line.split_at(start);
+        let words: Vec<&str> = before.split_whitespace().collect();
+        let mut candidates: Vec<String> = self
+            .candidates(&words)
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(partial))
+            .collect();
+        candidates.sort();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
@@ -0,0 +1,227 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+use tokio::{net::TcpStream, task::JoinSet};
+use uuid::Uuid;
+
+use order_book::{
+    auth::sign_from_env,
+    req::{CancelOrderArgs, Request},
+    resp::Response,
+    simulation::{FlowAction, FlowConfig, OrderFlow},
+    wire::Framed,
+};
+
+/// Fires a mix of requests at a server over concurrent connections and
+/// reports throughput and latency percentiles
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    addr: String,
+    #[clap(long, default_value_t = 16)]
+    connections: usize,
+    /// Requests sent on each connection, one at a time
+    #[clap(long, default_value_t = 1_000)]
+    requests: usize,
+    /// Relative weight of order placements in the mix
+    #[clap(long, default_value_t = 60)]
+    place: u32,
+    /// Relative weight of cancels of this connection's earlier orders
+    #[clap(long, default_value_t = 20)]
+    cancel: u32,
+    /// Relative weight of top of book queries
+    #[clap(long, default_value_t = 15)]
+    l1: u32,
+    /// Relative weight of full depth queries
+    #[clap(long, default_value_t = 5)]
+    l2: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum RequestKind {
+    Place,
+    Cancel,
+    ViewL1,
+    ViewL2,
+}
+
+// What one connection saw
+#[derive(Default)]
+struct ConnectionReport {
+    latencies: BTreeMap<RequestKind, Vec<Duration>>,
+    // Requests the server answered with an error of any kind
+    errors: u64,
+    overloaded: u64,
+}
+
+impl ConnectionReport {
+    fn merge(&mut self, other: ConnectionReport) {
+        for (kind, latencies) in other.latencies {
+            self.latencies.entry(kind).or_default().extend(latencies);
+        }
+        self.errors += other.errors;
+        self.overloaded += other.overloaded;
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Mix {
+    weights: [(RequestKind, u32); 4],
+    total: u32,
+}
+
+impl Mix {
+    fn new(cli: &Cli) -> Result<Mix> {
+        let weights = [
+            (RequestKind::Place, cli.place),
+            (RequestKind::Cancel, cli.cancel),
+            (RequestKind::ViewL1, cli.l1),
+            (RequestKind::ViewL2, cli.l2),
+        ];
+        let total = weights.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return Err(anyhow!("At least one request weight has to be above 0"));
+        }
+        Ok(Mix { weights, total })
+    }
+
+    fn pick(&self, rng: &mut StdRng) -> RequestKind {
+        let mut roll = rng.gen_range(0..self.total);
+        for (kind, weight) in self.weights {
+            if roll < weight {
+                return kind;
+            }
+            roll -= weight;
+        }
+        unreachable!()
+    }
+}
+
+async fn run_connection(
+    addr: String,
+    connection_idx: usize,
+    requests: usize,
+    mix: Mix,
+) -> Result<ConnectionReport> {
+    let mut socket = Framed::new(TcpStream::connect(&addr).await?);
+    let mut rng = StdRng::seed_from_u64(connection_idx as u64);
+    // Cancels are picked by the mix rather than the flow
+    let mut flow = OrderFlow::new(
+        FlowConfig {
+            cancel_ratio: 0.0,
+            ..Default::default()
+        },
+        connection_idx as u64,
+    );
+    let mut placed: Vec<Uuid> = Vec::new();
+    let mut report = ConnectionReport::default();
+
+    for _ in 0..requests {
+        let kind = match mix.pick(&mut rng) {
+            RequestKind::Cancel if placed.is_empty() => RequestKind::Place,
+            kind => kind,
+        };
+        let request = match kind {
+            RequestKind::Place => match flow.next_action() {
+                FlowAction::Place(args) => Request::PlaceOrder(args),
+                FlowAction::Cancel(_) => unreachable!("Flow places only"),
+            },
+            RequestKind::Cancel => {
+                let idx = rng.gen_range(0..placed.len());
+                Request::CancelOrder(CancelOrderArgs {
+                    order_id: placed.swap_remove(idx),
+                })
+            }
+            RequestKind::ViewL1 => Request::ViewL1Book,
+            RequestKind::ViewL2 => Request::ViewL2Book,
+        };
+        // Signing is part of what a client pays per request, so it's timed
+        let started = Instant::now();
+        socket.write_msg(&sign_from_env(request)?).await?;
+        let response: Response = socket.read_msg().await?;
+        report
+            .latencies
+            .entry(kind)
+            .or_default()
+            .push(started.elapsed());
+
+        match response {
+            Response::PlaceOk(report) => placed.push(report.order_id),
+            Response::Overloaded => report.overloaded += 1,
+            // Orders filled since they were placed can no longer be canceled
+            Response::CancelErr(_) => {}
+            Response::CancelOk(_) | Response::L1BookOk(_) | Response::L2BookOk(_) => {}
+            _ => report.errors += 1,
+        }
+    }
+    Ok(report)
+}
+
+// Nearest rank percentile of sorted latencies
+fn percentile(sorted: &[Duration], pct: f64) -> Duration {
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn print_latencies(label: &str, latencies: &mut [Duration]) {
+    latencies.sort();
+    println!(
+        "{label:<8} {:>8} {:>10.2?} {:>10.2?} {:>10.2?} {:>10.2?}",
+        latencies.len(),
+        percentile(latencies, 50.0),
+        percentile(latencies, 90.0),
+        percentile(latencies, 99.0),
+        latencies[latencies.len() - 1],
+    );
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let mix = Mix::new(&cli)?;
+
+    let started = Instant::now();
+    let mut connections = JoinSet::new();
+    for connection_idx in 0..cli.connections {
+        connections.spawn(run_connection(
+            cli.addr.clone(),
+            connection_idx,
+            cli.requests,
+            mix,
+        ));
+    }
+    let mut report = ConnectionReport::default();
+    while let Some(result) = connections.join_next().await {
+        report.merge(result??);
+    }
+    let elapsed = started.elapsed();
+
+    let total: usize = report.latencies.values().map(Vec::len).sum();
+    println!(
+        "{total} requests over {} connections in {elapsed:.2?} ({:.0} per second)",
+        cli.connections,
+        total as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "{} errors, {} turned away by a full matching queue",
+        report.errors, report.overloaded
+    );
+    println!(
+        "{:<8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "", "count", "p50", "p90", "p99", "max"
+    );
+    let mut all: Vec<Duration> = Vec::with_capacity(total);
+    for (kind, latencies) in &mut report.latencies {
+        print_latencies(&format!("{kind:?}"), latencies);
+        all.extend(latencies.iter());
+    }
+    if !all.is_empty() {
+        print_latencies("All", &mut all);
+    }
+    Ok(())
+}
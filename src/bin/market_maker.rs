@@ -0,0 +1,172 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+use order_book::{
+    auth::sign_from_env,
+    book::OrderBook,
+    quoting::{QuoteConfig, Quoter},
+    req::{CancelOrderArgs, GetEventsArgs, PlaceOrderArgs, Request},
+    resp::Response,
+    wire::{read_msg, write_msg},
+};
+
+const EVENTS_PER_POLL: usize = 1_000;
+
+/// Keeps two-sided quotes around the mid of a server's book, replacing them
+/// when they trade or the mid moves
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    addr: String,
+    #[clap(long, default_value = "market-maker")]
+    owner: String,
+    /// Ticks between the mid and each quote
+    #[clap(long, default_value_t = 5)]
+    half_spread: u32,
+    #[clap(long, default_value_t = 10)]
+    size: u64,
+    /// Ticks the mid has to move before resting quotes are replaced
+    #[clap(long, default_value_t = 2)]
+    requote_threshold: u32,
+    /// Mid to quote around until anyone else quotes or trades
+    #[clap(long, default_value_t = 10_000)]
+    initial_mid: u32,
+    /// Largest position either way before a side stops being quoted
+    #[clap(long, default_value_t = 100)]
+    max_position: i64,
+    /// How often the server's event feed is polled
+    #[clap(long, default_value_t = 50)]
+    poll_ms: u64,
+}
+
+async fn send(socket: &mut TcpStream, request: Request) -> Result<Response> {
+    write_msg(socket, &sign_from_env(request)?).await?;
+    read_msg(socket).await
+}
+
+// Applies every event published since the last poll to the mirror and the
+// quoter, the same way the replica follows the primary
+async fn catch_up(
+    socket: &mut TcpStream,
+    mirror: &mut OrderBook,
+    quoter: &mut Quoter,
+) -> Result<()> {
+    loop {
+        let from_seq = mirror.last_event_seq() + 1;
+        let request = Request::GetEvents(GetEventsArgs {
+            from_seq,
+            limit: EVENTS_PER_POLL,
+        });
+        let events = match send(socket, request).await? {
+            Response::EventsOk(events) => events,
+            Response::EventsErr => {
+                return Err(anyhow!(
+                    "Server no longer retains event {from_seq}, so the book can't be mirrored"
+                ))
+            }
+            response => return Err(anyhow!("Unexpected response {response:?}")),
+        };
+        let caught_up = events.len() < EVENTS_PER_POLL;
+        for event in events {
+            quoter.on_event(&event.event);
+            mirror.apply_event(event)?;
+        }
+        if caught_up {
+            return Ok(());
+        }
+    }
+}
+
+async fn cancel_quotes(socket: &mut TcpStream, quoter: &mut Quoter) -> Result<()> {
+    let live = quoter.live_orders();
+    if !live.is_empty() {
+        // Quotes that already traded fail to cancel, which is fine
+        send(
+            socket,
+            Request::CancelOrders(
+                live.into_iter()
+                    .map(|order_id| CancelOrderArgs { order_id })
+                    .collect(),
+            ),
+        )
+        .await?;
+    }
+    quoter.clear_live();
+    Ok(())
+}
+
+async fn run(cli: &Cli, socket: &mut TcpStream, quoter: &mut Quoter) -> Result<()> {
+    let mut mirror = OrderBook::new();
+    let mut interval = tokio::time::interval(Duration::from_millis(cli.poll_ms));
+    loop {
+        interval.tick().await;
+        catch_up(socket, &mut mirror, quoter).await?;
+        let Some(quotes) = quoter.requote(&mirror) else {
+            continue;
+        };
+
+        cancel_quotes(socket, quoter).await?;
+        let request = Request::PlaceOrders(
+            quotes
+                .iter()
+                .map(|quote| PlaceOrderArgs {
+                    order_type: quote.order_type,
+                    price: quote.price,
+                    quantity: quote.quantity,
+                    owner: cli.owner.clone(),
+                    expires_at: None,
+                    client_order_id: None,
+                })
+                .collect(),
+        );
+        let Response::PlaceOrdersOk(results) = send(socket, request).await? else {
+            eprintln!("Quotes were not accepted");
+            continue;
+        };
+        for (quote, result) in quotes.iter().zip(results) {
+            match result {
+                Response::PlaceOk(report) => {
+                    quoter.record_placed(report.order_id, quote.order_type)
+                }
+                response => eprintln!("Quote {quote:?} rejected: {response:?}"),
+            }
+        }
+        println!(
+            "Quoting {:?} with position {}",
+            quotes
+                .iter()
+                .map(|quote| (quote.order_type, quote.price))
+                .collect::<Vec<_>>(),
+            quoter.position()
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let mut quoter = Quoter::new(QuoteConfig {
+        owner: cli.owner.clone(),
+        half_spread: cli.half_spread,
+        size: cli.size,
+        requote_threshold: cli.requote_threshold,
+        initial_mid: cli.initial_mid,
+        max_position: cli.max_position,
+    });
+    let mut socket = TcpStream::connect(&cli.addr).await?;
+
+    let result = tokio::select! {
+        result = run(&cli, &mut socket, &mut quoter) => result,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    };
+    // Quotes are pulled on the way out so none are left resting unattended.
+    // Interrupting may have left a response unread, so this uses a new
+    // connection.
+    let mut socket = TcpStream::connect(&cli.addr).await?;
+    cancel_quotes(&mut socket, &mut quoter).await?;
+    println!("Stopped with position {}", quoter.position());
+    result
+}
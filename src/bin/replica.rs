@@ -0,0 +1,195 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::RwLock,
+};
+
+use order_book::{
+    book::OrderBook,
+    config::ServerConfig,
+    replication::CHECKSUM_INTERVAL,
+    req::{GetEventsArgs, HandshakeArgs, Request},
+    resp::Response,
+    wire::Framed,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const EVENTS_PER_POLL: usize = 1_000;
+
+// Serves market data queries from the replicated book. Anything that would
+// change the book has to go to the primary.
+async fn process_socket(socket: TcpStream, book: Arc<RwLock<OrderBook>>) {
+    let mut socket = Framed::new(socket);
+    loop {
+        let Ok(msg) = socket.read_msg().await else {
+            // Failed to parse request
+            return;
+        };
+        let response = match msg {
+            Request::ViewL2Book => Response::L2BookOk(book.read().await.view_book_l2()),
+            Request::ViewL1Book => Response::L1BookOk(book.read().await.view_book_l1()),
+            Request::ViewL3Book => Response::L3BookOk(book.read().await.view_book_l3()),
+            Request::ViewStats(view_stats_args) => {
+                Response::StatsOk(book.read().await.book_stats(view_stats_args.depth))
+            }
+            Request::GetTrades(page) => match book.read().await.query_trades(&page) {
+                Ok(trades) => Response::TradesOk(trades),
+                Err(_) => Response::TradesErr,
+            },
+            Request::QueryCandles(query_candles_args) => match book
+                .read()
+                .await
+                .query_candles(query_candles_args.interval_ms, &query_candles_args.page)
+            {
+                Ok(candles) => Response::CandlesOk(candles),
+                Err(_) => Response::CandlesErr,
+            },
+            Request::ViewOpenOrders(view_open_orders_args) => match book
+                .read()
+                .await
+                .query_open_orders(&view_open_orders_args.owner, &view_open_orders_args.page)
+            {
+                Ok(open_orders) => Response::OpenOrdersOk(open_orders),
+                Err(_) => Response::OpenOrdersErr,
+            },
+            Request::QueryOrder(query_order_args) => {
+                match book.read().await.order_status(query_order_args.order_id) {
+                    Some(status) => Response::OrderStatusOk(status),
+                    None => Response::OrderStatusErr,
+                }
+            }
+            Request::ViewChecksum => Response::ChecksumOk(book.read().await.checksum()),
+            // Ages trades out of the window, so needs the write lock
+            Request::ViewMarketStats => Response::MarketStatsOk(book.write().await.market_stats()),
+            // Replicas can feed further replicas
+            Request::GetEvents(get_events_args) => match book
+                .read()
+                .await
+                .events_since(get_events_args.from_seq, get_events_args.limit)
+            {
+                Ok(events) => Response::EventsOk(events),
+                Err(_) => Response::EventsErr,
+            },
+            // Answers every request in turn, so acks are never async
+            Request::Handshake(handshake_args) => {
+                let handshake_args = HandshakeArgs {
+                    async_acks: false,
+                    ..handshake_args
+                };
+                if socket
+                    .write_msg(&Response::HandshakeOk(handshake_args))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+                socket.set_compression(handshake_args.compression);
+                socket.set_fixed_layout(handshake_args.fixed_layout);
+                continue;
+            }
+            _ => Response::ReadOnlyErr,
+        };
+        // The client is gone
+        if socket.write_msg(&response).await.is_err() {
+            return;
+        }
+    }
+}
+
+// Polls the primary's event feed and applies every new event in order.
+// Once caught up it compares checksums with the primary now and then.
+async fn follow_primary(primary_addr: String, book: Arc<RwLock<OrderBook>>) -> Result<()> {
+    let mut socket = Framed::new(TcpStream::connect(primary_addr).await?);
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut checksum_due = Instant::now();
+    loop {
+        interval.tick().await;
+        let from_seq = book.read().await.last_event_seq() + 1;
+        let request = Request::GetEvents(GetEventsArgs {
+            from_seq,
+            limit: EVENTS_PER_POLL,
+        });
+        socket.write_msg(&request).await?;
+        match socket.read_msg().await? {
+            Response::EventsOk(events) => {
+                if events.is_empty() {
+                    if Instant::now() >= checksum_due {
+                        checksum_due = Instant::now() + CHECKSUM_INTERVAL;
+                        check_against_primary(&mut socket, &book).await?;
+                    }
+                    continue;
+                }
+                let mut book = book.write().await;
+                for event in events {
+                    book.apply_event(event)?;
+                }
+            }
+            Response::EventsErr => {
+                return Err(anyhow!(
+                    "Primary no longer retains event {from_seq}, the replica must be rebuilt"
+                ));
+            }
+            response => return Err(anyhow!("Unexpected response {response:?}")),
+        }
+    }
+}
+
+// Fails if the primary's checksum differs from the replica's as of the same
+// event. The primary may have moved on since the last poll, in which case
+// there's nothing to compare until the next check.
+async fn check_against_primary(socket: &mut Framed, book: &RwLock<OrderBook>) -> Result<()> {
+    socket.write_msg(&Request::ViewChecksum).await?;
+    let primary_checksum = match socket.read_msg().await? {
+        Response::ChecksumOk(checksum) => checksum,
+        response => return Err(anyhow!("Unexpected response {response:?}")),
+    };
+    let checksum = book.read().await.checksum();
+    if checksum.as_of_seq == primary_checksum.as_of_seq && checksum != primary_checksum {
+        return Err(anyhow!(
+            "Diverged from the primary: {checksum:?} against its {primary_checksum:?}"
+        ));
+    }
+    Ok(())
+}
+
+/// Serves market data from a copy of the primary's book
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Primary's TOML config, giving its address and the replica's port
+    #[clap(long)]
+    config: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = match Cli::parse().config {
+        Some(path) => ServerConfig::load(&path)?,
+        None => ServerConfig::default(),
+    };
+    let book = Arc::new(RwLock::new(OrderBook::new()));
+    let listener = TcpListener::bind(config.replica_addr()).await?;
+
+    let follower_book = book.clone();
+    let primary_addr = config.addr();
+    tokio::spawn(async move {
+        if let Err(err) = follow_primary(primary_addr, follower_book).await {
+            eprintln!("Stopped following the primary: {err}");
+        }
+    });
+
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let book = book.clone();
+
+        tokio::spawn(async move {
+            process_socket(socket, book).await;
+        });
+    }
+}
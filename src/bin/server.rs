@@ -1,67 +1,291 @@
-use anyhow::Result;
-use std::sync::Arc;
-use tokio::{
-    net::{TcpListener, TcpStream},
-    sync::RwLock,
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
+use tokio::net::TcpListener;
 
-use order_book::{resp::Response, req::Request, book::OrderBook, wire::{read_msg, write_msg}};
+use order_book::{
+    auth::{parse_client_secrets, Authenticator, DEFAULT_FRESHNESS_WINDOW_MS},
+    clock::parse_time_of_day,
+    config::ServerConfig,
+    engine::{BookHandle, ExchangeHandle, DEFAULT_QUEUE_CAPACITY},
+    listener::ClearingLogListener,
+    price_feed::JsonPriceFeed,
+    replication::{follow_primary, DEFAULT_FAILOVER_TIMEOUT},
+    schedule::TradingHours,
+    server::{
+        run_end_of_day, run_price_feed, run_trading_hours, serve_exchange, serve_metrics,
+        serve_tenants, serve_with_options, sweep_exchange, sweep_expired_orders, ServeOptions,
+    },
+    settlement::EndOfDayOptions,
+    tenant::Tenants,
+};
 
-async fn process_socket(mut socket: TcpStream, book: Arc<RwLock<OrderBook>>) {
-    loop {
-        // Deserialize incoming request
-        match read_msg(&mut socket).await {
-            Ok(msg) => {
-                match msg {
-                    Request::ViewL2Book => {
-                        let book = book.read().await;
-                        let l2_book = book.view_book_l2();
-                        write_msg(&mut socket, &Response::L2BookOk(l2_book))
-                            .await
-                            .unwrap();
-                    }
-                    Request::CancelOrder(orders_args) => {
-                        let mut book = book.write().await;
-                        match book.cancel_order(orders_args.order_id) {
-                            Ok(()) => write_msg(&mut socket, &Response::CancelOk).await.unwrap(),
-                            Err(_) => write_msg(&mut socket, &Response::CancelErr).await.unwrap(),
-                        }
-                    }
-                    Request::PlaceOrder(place_order_args) => {
-                        let mut book = book.write().await;
-                        match book.place_order(
-                            place_order_args.price,
-                            place_order_args.quantity,
-                            place_order_args.order_type,
-                        ) {
-                            Ok(order_id) => write_msg(&mut socket, &Response::PlaceOk(order_id))
-                                .await
-                                .unwrap(),
-                            Err(_) => write_msg(&mut socket, &Response::PlacErr).await.unwrap(),
-                        }
-                    }
-                }
-                // Write response
-            }
-            Err(_) => {
-                // Failed to parse request
-                return;
-            }
+// Comma separated client:secret pairs. Order entry has to be signed once set.
+const CLIENT_SECRETS_ENV: &str = "ORDER_BOOK_CLIENT_SECRETS";
+// Comma separated ids of the clients allowed to send admin requests, such as
+// busting trades or resuming trading
+const ADMIN_CLIENTS_ENV: &str = "ORDER_BOOK_ADMIN_CLIENTS";
+// HH:MM in UTC at which end of day runs with the default options
+const END_OF_DAY_ENV: &str = "ORDER_BOOK_END_OF_DAY";
+// HH:MM-HH:MM-HH:MM in UTC giving the pre-open, open and close
+const TRADING_HOURS_ENV: &str = "ORDER_BOOK_TRADING_HOURS";
+
+/// Serves the order book, configured by a TOML file and the options below,
+/// which take precedence over it
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// TOML config, see config.rs. Defaults apply without one.
+    #[clap(long, env = "ORDER_BOOK_CONFIG")]
+    config: Option<PathBuf>,
+    /// Address to bind the listeners to
+    #[clap(long, env = "ORDER_BOOK_HOST")]
+    host: Option<String>,
+    /// Port for order entry
+    #[clap(long, env = "ORDER_BOOK_PORT")]
+    port: Option<u16>,
+    /// Port that only answers queries, for market data clients
+    #[clap(long, env = "ORDER_BOOK_MARKET_DATA_PORT")]
+    market_data_port: Option<u16>,
+    /// Port serving latency metrics to Prometheus
+    #[clap(long, env = "ORDER_BOOK_METRICS_PORT")]
+    metrics_port: Option<u16>,
+    /// Directory to keep trades the tape no longer holds in memory
+    #[clap(long, env = "ORDER_BOOK_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+    /// Address of a primary to follow as its warm standby. Only queries
+    /// are answered until the primary is lost and this server takes over.
+    #[clap(long, env = "ORDER_BOOK_STANDBY_OF")]
+    standby_of: Option<String>,
+}
+
+impl Cli {
+    fn server_config(&self) -> Result<ServerConfig> {
+        let mut config = match &self.config {
+            Some(path) => ServerConfig::load(path)?,
+            None => ServerConfig::default(),
         };
+        if let Some(host) = &self.host {
+            config.bind_address = host.clone();
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(market_data_port) = self.market_data_port {
+            config.market_data_port = Some(market_data_port);
+        }
+        if let Some(metrics_port) = self.metrics_port {
+            config.metrics_port = Some(metrics_port);
+        }
+        if let Some(data_dir) = &self.data_dir {
+            config.data_dir = Some(data_dir.clone());
+        }
+        Ok(config)
+    }
+}
+
+// Feed marking the book, the symbol it's marked under and how often it's
+// polled
+type MarkPriceFeed = (JsonPriceFeed, String, Duration);
+
+// Spawns the tasks that change the book on a timer
+fn run_housekeeping(
+    book: BookHandle,
+    hours: Option<TradingHours>,
+    end_of_day: Option<u64>,
+    mark_prices: Option<MarkPriceFeed>,
+) {
+    tokio::spawn(sweep_expired_orders(book.clone()));
+    if let Some((feed, symbol, interval)) = mark_prices {
+        tokio::spawn(run_price_feed(
+            book.clone(),
+            Box::new(feed),
+            symbol,
+            interval,
+        ));
+    }
+    if let Some(hours) = hours {
+        tokio::spawn(run_trading_hours(book.clone(), hours));
+    }
+    if let Some(time_of_day) = end_of_day {
+        tokio::spawn(run_end_of_day(
+            book,
+            time_of_day,
+            EndOfDayOptions::default(),
+        ));
+    }
+}
+
+// A book per configured tenant. Every client of a tenant must have a secret,
+// since only signed requests can be told apart by tenant.
+fn build_tenants(config: &ServerConfig, auth: &Authenticator) -> Result<Tenants> {
+    let mut tenants = Tenants::new();
+    for tenant in &config.tenants {
+        let mut book = config.build_tenant_book(tenant)?;
+        book.add_listener(Box::new(ClearingLogListener));
+        tenants.add_tenant(&tenant.id, BookHandle::spawn(book, DEFAULT_QUEUE_CAPACITY))?;
+        for client_id in &tenant.clients {
+            if !auth.has_client(client_id) {
+                return Err(anyhow!(
+                    "Client {client_id} of tenant {} has no secret in {CLIENT_SECRETS_ENV}",
+                    tenant.id
+                ));
+            }
+            tenants.add_client(client_id, &tenant.id)?;
+        }
     }
+    Ok(tenants)
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let book = Arc::new(RwLock::new(OrderBook::new()));
-    let listener = TcpListener::bind("127.0.0.1:8080").await?;
+    let cli = Cli::parse();
+    let config = cli.server_config()?;
+    let secrets = match std::env::var(CLIENT_SECRETS_ENV) {
+        Ok(secrets) => parse_client_secrets(&secrets)?,
+        Err(_) => HashMap::new(),
+    };
+    let mut auth = Authenticator::new(secrets, DEFAULT_FRESHNESS_WINDOW_MS);
+    if let Ok(admins) = std::env::var(ADMIN_CLIENTS_ENV) {
+        auth.set_admins(
+            admins
+                .split(',')
+                .map(str::trim)
+                .filter(|client_id| !client_id.is_empty())
+                .map(str::to_string)
+                .collect(),
+        )?;
+    }
+    let listener = TcpListener::bind(config.addr()).await?;
+    let hours = match std::env::var(TRADING_HOURS_ENV) {
+        Ok(hours) => Some(TradingHours::parse(&hours)?),
+        Err(_) => None,
+    };
+    let end_of_day = match std::env::var(END_OF_DAY_ENV) {
+        Ok(time) => Some(parse_time_of_day(&time)?),
+        Err(_) => None,
+    };
+    let mark_prices = config.mark_price_feed()?;
+    let options = ServeOptions {
+        rate_limit: config.rate_limit,
+        timeouts: config.timeouts,
+        max_frame_len: config.max_frame_len,
+        json_fallback: config.json_fallback,
+        outbox_limit: config.outbox_limit,
+        backpressure: config.backpressure,
+        ..Default::default()
+    };
+
+    if !config.tenants.is_empty() {
+        if cli.standby_of.is_some() || config.metrics_port.is_some() {
+            return Err(anyhow!(
+                "Standbys and latency metrics only cover servers without tenants"
+            ));
+        }
+        let tenants = Arc::new(build_tenants(&config, &auth)?);
+        for (_, book) in tenants.iter() {
+            run_housekeeping(book.clone(), hours, end_of_day, mark_prices.clone());
+        }
+        let auth = Arc::new(Mutex::new(auth));
+        if let Some(addr) = config.market_data_addr() {
+            let listener = TcpListener::bind(addr).await?;
+            let options = ServeOptions {
+                market_data_only: true,
+                ..options
+            };
+            let (tenants, auth) = (tenants.clone(), auth.clone());
+            tokio::spawn(async move {
+                if let Err(err) = serve_tenants(listener, tenants, auth, options).await {
+                    eprintln!("Stopped serving market data: {err}");
+                }
+            });
+        }
+        return serve_tenants(listener, tenants, auth, options).await;
+    }
 
-    loop {
-        let (socket, _) = listener.accept().await?;
-        let book = book.clone();
+    if let Some(mut exchange) = config.build_exchange()? {
+        if cli.standby_of.is_some()
+            || config.metrics_port.is_some()
+            || hours.is_some()
+            || end_of_day.is_some()
+        {
+            return Err(anyhow!(
+                "Standbys, latency metrics, trading hours and end of day only cover servers without an exchange"
+            ));
+        }
+        for book in exchange.books_mut() {
+            book.add_listener(Box::new(ClearingLogListener));
+        }
+        let exchange = ExchangeHandle::spawn(exchange, DEFAULT_QUEUE_CAPACITY);
+        tokio::spawn(sweep_exchange(exchange.clone()));
+        let auth = Arc::new(Mutex::new(auth));
+        if let Some(addr) = config.market_data_addr() {
+            let listener = TcpListener::bind(addr).await?;
+            let options = ServeOptions {
+                market_data_only: true,
+                ..options
+            };
+            let (exchange, auth) = (exchange.clone(), auth.clone());
+            tokio::spawn(async move {
+                if let Err(err) = serve_exchange(listener, exchange, auth, options).await {
+                    eprintln!("Stopped serving market data: {err}");
+                }
+            });
+        }
+        return serve_exchange(listener, exchange, auth, options).await;
+    }
+
+    let mut book = config.build_book()?;
+    book.add_listener(Box::new(ClearingLogListener));
+    let latency = book.latency().clone();
+    let book = BookHandle::spawn(book, DEFAULT_QUEUE_CAPACITY);
+    let auth = Arc::new(Mutex::new(auth));
 
+    match cli.standby_of {
+        // Expiry, trading hours and end of day reach a standby through the
+        // primary's events, so it only runs them itself once promoted
+        Some(primary_addr) => {
+            book.set_standby(true);
+            let book = book.clone();
+            tokio::spawn(async move {
+                match follow_primary(&primary_addr, &book, DEFAULT_FAILOVER_TIMEOUT).await {
+                    Ok(()) => {
+                        eprintln!("Lost the primary at {primary_addr}, taking over");
+                        run_housekeeping(book, hours, end_of_day, mark_prices);
+                    }
+                    Err(err) => eprintln!("Stopped following the primary: {err:#}"),
+                }
+            });
+        }
+        None => run_housekeeping(book.clone(), hours, end_of_day, mark_prices),
+    }
+
+    if let Some(addr) = config.metrics_addr() {
+        let listener = TcpListener::bind(addr).await?;
         tokio::spawn(async move {
-            process_socket(socket, book).await;
+            if let Err(err) = serve_metrics(listener, latency).await {
+                eprintln!("Stopped serving metrics: {err}");
+            }
+        });
+    }
+
+    if let Some(addr) = config.market_data_addr() {
+        let listener = TcpListener::bind(addr).await?;
+        let options = ServeOptions {
+            market_data_only: true,
+            ..options
+        };
+        let (book, auth) = (book.clone(), auth.clone());
+        tokio::spawn(async move {
+            if let Err(err) = serve_with_options(listener, book, auth, options).await {
+                eprintln!("Stopped serving market data: {err}");
+            }
         });
     }
+    serve_with_options(listener, book, auth, options).await
 }
@@ -0,0 +1,155 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use std::time::Instant;
+use tokio::net::TcpStream;
+
+use order_book::{
+    analytics::DEFAULT_ANALYTICS_DEPTH,
+    auth::sign_from_env,
+    book::OrderBook,
+    query::{PageRequest, MAX_PAGE_LIMIT},
+    req::{Request, ViewStatsArgs},
+    resp::Response,
+    server::handle_request,
+    simulation::{FlowConfig, OrderFlow, SimulationReport},
+    wire::{read_msg, write_msg},
+};
+
+/// Sends synthetic order flow to a book and reports how it filled
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Server to send the flow to, e.g. 127.0.0.1:8080. Without it the flow
+    /// runs against a book in this process.
+    #[clap(long)]
+    addr: Option<String>,
+    /// Actions to send, places and cancels together
+    #[clap(long, default_value_t = 10_000)]
+    actions: u64,
+    /// Mean actions per second, arriving as a Poisson process. 0 sends them
+    /// as fast as the book answers.
+    #[clap(long, default_value_t = 0.0)]
+    rate: f64,
+    #[clap(long, default_value_t = FlowConfig::default().traders)]
+    traders: usize,
+    #[clap(long, default_value_t = FlowConfig::default().mid_price)]
+    mid_price: u32,
+    /// Mean distance of passive orders from the mid, in ticks
+    #[clap(long, default_value_t = FlowConfig::default().mean_offset)]
+    mean_offset: f64,
+    /// Share of orders priced to trade on arrival
+    #[clap(long, default_value_t = FlowConfig::default().aggressive_ratio)]
+    aggressive_ratio: f64,
+    /// Share of actions that cancel an earlier order
+    #[clap(long, default_value_t = FlowConfig::default().cancel_ratio)]
+    cancel_ratio: f64,
+    #[clap(long, default_value_t = FlowConfig::default().max_quantity)]
+    max_quantity: u64,
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+}
+
+enum Target {
+    Local(Box<OrderBook>),
+    Remote(TcpStream),
+}
+
+impl Target {
+    async fn send(&mut self, request: Request) -> Result<Response> {
+        match self {
+            Target::Local(book) => Ok(handle_request(book, request)),
+            Target::Remote(socket) => {
+                write_msg(socket, &sign_from_env(request)?).await?;
+                read_msg(socket).await
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    if !(0.0..=1.0).contains(&cli.aggressive_ratio) || !(0.0..=1.0).contains(&cli.cancel_ratio) {
+        return Err(anyhow!("Ratios have to be between 0 and 1"));
+    }
+    let config = FlowConfig {
+        traders: cli.traders,
+        mid_price: cli.mid_price,
+        mean_offset: cli.mean_offset,
+        aggressive_ratio: cli.aggressive_ratio,
+        cancel_ratio: cli.cancel_ratio,
+        max_quantity: cli.max_quantity,
+    };
+    let mut target = match &cli.addr {
+        Some(addr) => Target::Remote(TcpStream::connect(addr).await?),
+        None => Target::Local(Box::new(OrderBook::new())),
+    };
+
+    let mut flow = OrderFlow::new(config, cli.seed);
+    let mut report = SimulationReport::default();
+    let started = Instant::now();
+    // Arrivals are scheduled from the start rather than from the previous
+    // send, so timer granularity doesn't lower the rate
+    let mut next_arrival = tokio::time::Instant::now();
+    for _ in 0..cli.actions {
+        if cli.rate > 0.0 {
+            next_arrival += flow.next_delay(cli.rate);
+            tokio::time::sleep_until(next_arrival).await;
+        }
+        let action = flow.next_action();
+        let response = target.send(action.request()).await?;
+        if let Response::PlaceOk(report) = response {
+            flow.record_placed(report.order_id);
+        }
+        report.record(&action, &response);
+    }
+    let elapsed = started.elapsed();
+
+    // Trades are matched back to the simulated orders, which on a shared
+    // server may be only some of them
+    let mut page = PageRequest {
+        limit: MAX_PAGE_LIMIT,
+        ..Default::default()
+    };
+    loop {
+        let Response::TradesOk(trades) = target.send(Request::GetTrades(page)).await? else {
+            return Err(anyhow!("Failed to get trades"));
+        };
+        for trade in &trades.items {
+            report.record_trade(trade);
+        }
+        match trades.next_cursor {
+            Some(cursor) => page.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    let stats = target
+        .send(Request::ViewStats(ViewStatsArgs {
+            depth: DEFAULT_ANALYTICS_DEPTH,
+        }))
+        .await?;
+
+    println!(
+        "Sent {} actions in {:.2?} ({:.0} per second)",
+        cli.actions,
+        elapsed,
+        cli.actions as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "Orders: {} accepted, {} rejected, {} overloaded",
+        report.orders_accepted, report.orders_rejected, report.overloaded
+    );
+    println!(
+        "Cancels: {} accepted, {} rejected",
+        report.cancels_accepted, report.cancels_rejected
+    );
+    println!(
+        "Fills: {} of {} placed in {} trades ({:.1}%)",
+        report.quantity_filled,
+        report.quantity_placed,
+        report.trades,
+        report.fill_rate() * 100.0
+    );
+    println!("Book: {stats:#?}");
+    Ok(())
+}
@@ -0,0 +1,274 @@
+use std::{net::TcpStream, thread, time::Duration};
+use uuid::Uuid;
+
+use crate::{
+    auth::sign_from_env,
+    book::{
+        CancelFilter, ExecutionReport, L1Book, L2Book, L3Book, OpenOrder, OrderStatus, OrderType,
+    },
+    client::{can_resend, ClientError, ClientResult, ReconnectPolicy},
+    query::{Page, PageRequest, MAX_PAGE_LIMIT},
+    req::{
+        CancelAllArgs, CancelClientOrderArgs, CancelOrderArgs, PlaceOrderArgs, QueryOrderArgs,
+        Request, ViewOpenOrdersArgs,
+    },
+    resp::Response,
+    tape::Trade,
+    wire::{read_msg_blocking, write_msg_blocking},
+};
+
+// Counterpart of OrderBookClient for scripts and tests that run without an
+// async runtime. Every call blocks the thread until the response arrives.
+// Requests are signed with the credentials in the environment when set, and
+// the connection opens on the first request and reopens after a drop, as
+// the policy allows. Neither compression nor the fixed layout is negotiated.
+pub struct BlockingClient {
+    addr: String,
+    policy: ReconnectPolicy,
+    // Read and write timeout of every socket opened
+    timeout: Option<Duration>,
+    socket: Option<TcpStream>,
+}
+
+impl BlockingClient {
+    // Client that opens its connection on the first request rather than
+    // straight away
+    pub fn new(addr: &str, policy: ReconnectPolicy) -> BlockingClient {
+        BlockingClient {
+            addr: addr.to_string(),
+            policy,
+            timeout: None,
+            socket: None,
+        }
+    }
+
+    pub fn connect(addr: &str) -> ClientResult<BlockingClient> {
+        BlockingClient::connect_with_policy(addr, ReconnectPolicy::default())
+    }
+
+    pub fn connect_with_policy(
+        addr: &str,
+        policy: ReconnectPolicy,
+    ) -> ClientResult<BlockingClient> {
+        let mut client = BlockingClient::new(addr, policy);
+        client.open()?;
+        Ok(client)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    // Closes the connection. The next request opens a new one.
+    pub fn disconnect(&mut self) {
+        self.socket = None;
+    }
+
+    // Fails a request whose response doesn't arrive within `timeout`, rather
+    // than waiting on it for good. Applies to the open connection and the
+    // ones opened afterwards.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> ClientResult<()> {
+        self.timeout = timeout;
+        if let Some(socket) = &self.socket {
+            socket
+                .set_read_timeout(timeout)
+                .map_err(anyhow::Error::from)?;
+            socket
+                .set_write_timeout(timeout)
+                .map_err(anyhow::Error::from)?;
+        }
+        Ok(())
+    }
+
+    // Sends any request and returns the server's response as is, error
+    // responses included
+    pub fn request(&mut self, request: Request) -> ClientResult<Response> {
+        let resend = self.policy.resend_idempotent && can_resend(&request);
+        let mut backoff = Backoff::new(&self.policy);
+        loop {
+            let socket = match &mut self.socket {
+                Some(socket) => socket,
+                None => self.open()?,
+            };
+            match send(socket, request.clone()) {
+                Ok(response) => return Ok(response),
+                Err(err) => {
+                    self.socket = None;
+                    if !resend || !backoff.wait() {
+                        return Err(err.into());
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn place(&mut self, place_order_args: PlaceOrderArgs) -> ClientResult<ExecutionReport> {
+        match self.request(Request::PlaceOrder(place_order_args))? {
+            Response::PlaceOk(report) => Ok(report),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    // Good-till-canceled limit order without a client order id
+    pub fn place_limit_order(
+        &mut self,
+        owner: &str,
+        order_type: OrderType,
+        price: u32,
+        quantity: u64,
+    ) -> ClientResult<ExecutionReport> {
+        self.place(PlaceOrderArgs {
+            order_type,
+            price,
+            quantity,
+            owner: owner.to_string(),
+            expires_at: None,
+            client_order_id: None,
+        })
+    }
+
+    pub fn place_order(&mut self, place_order_args: PlaceOrderArgs) -> ClientResult<Uuid> {
+        Ok(self.place(place_order_args)?.order_id)
+    }
+
+    // Cancels the order and returns the quantity that was still resting
+    pub fn cancel(&mut self, order_id: Uuid) -> ClientResult<u64> {
+        self.cancel_request(Request::CancelOrder(CancelOrderArgs { order_id }))
+    }
+
+    pub fn cancel_client_order(&mut self, owner: &str, client_order_id: &str) -> ClientResult<u64> {
+        self.cancel_request(Request::CancelClientOrder(CancelClientOrderArgs {
+            owner: owner.to_string(),
+            client_order_id: client_order_id.to_string(),
+        }))
+    }
+
+    fn cancel_request(&mut self, request: Request) -> ClientResult<u64> {
+        match self.request(request)? {
+            Response::CancelOk(canceled_quantity) => Ok(canceled_quantity),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    // Ids of the canceled orders
+    pub fn cancel_all(&mut self, filter: CancelFilter) -> ClientResult<Vec<Uuid>> {
+        match self.request(Request::CancelAll(CancelAllArgs { filter }))? {
+            Response::CancelAllOk(order_ids) => Ok(order_ids),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    pub fn order_status(&mut self, order_id: Uuid) -> ClientResult<OrderStatus> {
+        match self.request(Request::QueryOrder(QueryOrderArgs { order_id }))? {
+            Response::OrderStatusOk(status) => Ok(status),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    // Every open order of `owner`, following pages until the last one
+    pub fn open_orders(&mut self, owner: &str) -> ClientResult<Vec<OpenOrder>> {
+        let mut open_orders = Vec::new();
+        let mut cursor = None;
+        loop {
+            let request = Request::ViewOpenOrders(ViewOpenOrdersArgs {
+                owner: owner.to_string(),
+                page: PageRequest {
+                    cursor,
+                    limit: MAX_PAGE_LIMIT,
+                    ..PageRequest::default()
+                },
+            });
+            match self.request(request)? {
+                Response::OpenOrdersOk(page) => {
+                    open_orders.extend(page.items);
+                    cursor = page.next_cursor;
+                    if cursor.is_none() {
+                        return Ok(open_orders);
+                    }
+                }
+                response => return Err(ClientError::from_response(response)),
+            }
+        }
+    }
+
+    pub fn l1_snapshot(&mut self) -> ClientResult<L1Book> {
+        match self.request(Request::ViewL1Book)? {
+            Response::L1BookOk(l1_book) => Ok(l1_book),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    pub fn l2_snapshot(&mut self) -> ClientResult<L2Book> {
+        match self.request(Request::ViewL2Book)? {
+            Response::L2BookOk(l2_book) => Ok(l2_book),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    pub fn l3_snapshot(&mut self) -> ClientResult<L3Book> {
+        match self.request(Request::ViewL3Book)? {
+            Response::L3BookOk(l3_book) => Ok(l3_book),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    pub fn trades(&mut self, page: PageRequest) -> ClientResult<Page<Trade>> {
+        match self.request(Request::GetTrades(page))? {
+            Response::TradesOk(trades) => Ok(trades),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    fn open(&mut self) -> ClientResult<&mut TcpStream> {
+        let mut backoff = Backoff::new(&self.policy);
+        loop {
+            match self.connect_socket() {
+                Ok(socket) => return Ok(self.socket.insert(socket)),
+                Err(err) if !backoff.wait() => return Err(ClientError::Connection(err.into())),
+                Err(_) => {}
+            }
+        }
+    }
+
+    fn connect_socket(&self) -> std::io::Result<TcpStream> {
+        let socket = TcpStream::connect(&self.addr)?;
+        socket.set_nodelay(true)?;
+        socket.set_read_timeout(self.timeout)?;
+        socket.set_write_timeout(self.timeout)?;
+        Ok(socket)
+    }
+}
+
+fn send(socket: &mut TcpStream, request: Request) -> anyhow::Result<Response> {
+    // Signed for every attempt, since the server turns away repeated nonces
+    write_msg_blocking(socket, &sign_from_env(request)?)?;
+    read_msg_blocking(socket)
+}
+
+// Waits between attempts, doubling the wait up to the policy's maximum
+struct Backoff {
+    wait: Duration,
+    max_wait: Duration,
+    attempts_left: u32,
+}
+
+impl Backoff {
+    fn new(policy: &ReconnectPolicy) -> Backoff {
+        Backoff {
+            wait: policy.initial_backoff,
+            max_wait: policy.max_backoff,
+            attempts_left: policy.max_attempts.saturating_sub(1),
+        }
+    }
+
+    // False once every attempt was used
+    fn wait(&mut self) -> bool {
+        if self.attempts_left == 0 {
+            return false;
+        }
+        self.attempts_left -= 1;
+        thread::sleep(self.wait);
+        self.wait = (self.wait * 2).min(self.max_wait);
+        true
+    }
+}
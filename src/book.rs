@@ -1,168 +1,822 @@
-use anyhow::{anyhow, Result};
+use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
 use uuid::Uuid;
 
 use crate::{
+    accounting::{OrderAccount, OrderAccounting},
+    analytics::{MarketStats, RollingStats},
+    candles::{Candle, CandleAggregator},
+    clearing::{AccountAction, AuditEvent, Balance, ClearingHouse, TradeFees},
+    clock::{self, Clock, Instant, SystemClock},
+    error::OrderBookError,
+    export::{self, ExportFormat},
+    feed::{BookEvent, EventFeed, SequencedEvent},
+    instrument::InstrumentStatus,
+    listener::BookListener,
+    matching::{MatchingEngine, PriceTimeFifo},
+    metrics::{LatencyMetrics, Operation},
     order::Order,
-    price_tree::{OrderKey, PriceTree},
+    price_tree::{OrderKey, PriceNode, PriceTree, TopLevels},
+    query::{self, Page, PageRequest, Paginated},
+    risk::{
+        self, BandReference, ExposureTracker, MarginStatus, OpenExposure, ParticipantRiskConfig,
+        RiskCheck, RiskCheckKind, RiskConfig, RiskOrder, RiskRejection, SelfMatchPrevention,
+    },
+    settlement::{self, EndOfDayOptions, SettlementPriceSource, SettlementReport},
+    stats::SessionStats,
+    tape::{Trade, TradeTape},
 };
 
 pub struct OrderBook {
     bid_tree: PriceTree,
     ask_tree: PriceTree,
     order_id_map: HashMap<Uuid, (OrderType, OrderKey)>,
-    order_removed_set: HashSet<Uuid>,
+    // Resting order ids of every owner
+    owner_index: HashMap<String, HashSet<Uuid>>,
+    // Final status of every order that left the book
+    closed_orders: HashMap<Uuid, OrderStatus>,
+    // When each open order placed on this book was taken up for matching,
+    // in monotonic nanoseconds
+    matched_at: HashMap<Uuid, u64>,
+    // Self-match prevention applied to each open order that met one of its
+    // owner's, as aggressor or as the resting order reduced
+    self_matches: HashMap<Uuid, SelfMatchPrevention>,
+    // Order placed last. Its entries above outlive its close until the next
+    // order, so the ack built right after placing it can report them.
+    last_placed: Option<Uuid>,
+    // Resting good-till-date orders ordered by expiry time
+    expiry_index: BTreeSet<(u64, Uuid)>,
+    session_stats: SessionStats,
+    // Trading over the last 24 hours, carried across sessions
+    market_stats: RollingStats,
+    // Sequence number of the first trade of the current session
+    session_start_seq: u64,
+    risk_config: RiskConfig,
+    // Run in order on every order before it is matched
+    risk_checks: Vec<Box<dyn RiskCheck>>,
+    open_exposure: ExposureTracker,
+    // Price the circuit breaker's band is around: the last trade, or the
+    // previous close until the session trades
+    reference_price: Option<u32>,
+    // Latest external mark price, see price_feed.rs
+    mark_price: Option<u32>,
+    // Uncross the auction would make now, kept up to date during one
+    indicative: Option<AuctionUncross>,
+    // When the auction a volatility interruption started uncrosses
+    volatility_auction_ends_at: Option<u64>,
+    halted: bool,
+    phase: TradingPhase,
+    // Status of the instrument the book trades, kept in step by
+    // Instrument::transition. None for a book that trades none.
+    instrument_status: Option<InstrumentStatus>,
+    trade_tape: TradeTape,
+    candles: CandleAggregator,
+    clearing_house: ClearingHouse,
+    event_feed: EventFeed,
+    listeners: Vec<Box<dyn BookListener>>,
+    accounting: OrderAccounting,
+    clock: Box<dyn Clock>,
+    // Order placed under each (owner, client order id) this session
+    client_order_ids: HashMap<(String, String), Uuid>,
+    // Arrival sequence number the next accepted order gets
+    next_arrival_seq: u64,
+    // Splits incoming quantity among the orders of each level
+    matching_engine: Box<dyn MatchingEngine>,
+    // Shared so the metrics can be read without going through the book
+    latency: Arc<LatencyMetrics>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum OrderType {
     Bid,
     Ask,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TradingPhase {
+    Continuous,
+    Auction,
+}
+
+// Expected size of a book, allocated up front so bursts of orders don't have
+// to wait on the book's storage growing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookCapacity {
+    // Price levels on each side
+    pub levels: usize,
+    // Resting orders across both sides
+    pub orders: usize,
+    // Orders each new price level starts with room for
+    pub orders_per_level: usize,
+}
+
+// How each side of a book finds its price levels by price
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PriceLevelIndex {
+    // Ordered map, holding levels at any price
+    #[default]
+    Tree,
+    // Array with a slot for every tick from min_price to max_price, both
+    // inclusive. Finds levels faster than the tree for a known price band,
+    // but orders priced off its ticks are rejected.
+    Ladder {
+        min_price: u32,
+        max_price: u32,
+        #[serde(default = "default_tick_size")]
+        tick_size: u32,
+    },
+}
+
+fn default_tick_size() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuctionUncross {
+    pub price: u32,
+    pub volume: u64,
+}
+
+#[derive(Debug)]
+struct AuctionFill {
+    order_id: Uuid,
+    owner: String,
+    order_quantity: u64,
+    fill_quantity: u64,
+}
+
 #[derive(Debug)]
-struct PartialOrderMatch {
+struct OrderMatch {
+    order_id: Uuid,
     order_key: OrderKey,
-    remaining_quantity: u32,
+    fill_quantity: u64,
+    // Left resting once filled, zero when filled in full
+    remaining_quantity: u64,
 }
 
 #[derive(Debug)]
 struct MatchOutcome {
-    remaining_quantity: u32,
-    full_order: Vec<(Uuid, OrderKey)>,
-    partial_order: Option<PartialOrderMatch>,
+    remaining_quantity: u64,
+    // Resting orders that trade, in the order their trades are recorded
+    matches: Vec<OrderMatch>,
+    // Price of the furthest level the incoming order trades against
+    worst_price: Option<u32>,
+    // Levels every order of which is filled in full, best first. Their
+    // orders come first in `matches`.
+    swept_levels: Vec<u32>,
+    // Resting order of the same owner matching stopped at
+    self_matched: Option<Uuid>,
+}
+
+// How far a market order may trade through the book. Whatever it can't fill
+// within the limit is canceled rather than left resting.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketProtection {
+    // Worst price the order accepts
+    Price(u32),
+    // Ticks past the best price on the other side when the order arrives
+    Ticks(u32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct L2Entry {
+    pub price: u32,
+    pub total_quantity: u64,
+    pub num_orders: usize,
+    // Smallest and largest resting order at the price
+    pub min_order_quantity: u64,
+    pub max_order_quantity: u64,
+    // Timestamp of the order first in time priority
+    pub oldest_order_timestamp: u64,
+}
+
+impl L2Entry {
+    fn from_level(price_node: &PriceNode) -> L2Entry {
+        // Levels in the book always hold an order
+        L2Entry {
+            price: price_node.price(),
+            total_quantity: price_node.total_quantity(),
+            num_orders: price_node.num_orders(),
+            min_order_quantity: price_node.min_order_quantity().unwrap_or(0),
+            max_order_quantity: price_node.max_order_quantity().unwrap_or(0),
+            oldest_order_timestamp: price_node.oldest_order_timestamp().unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct L2Book {
+    // Sequence number of the latest event reflected in the snapshot
+    pub as_of_seq: u64,
+    // Both sides in ascending price order
+    pub bid: Vec<L2Entry>,
+    pub ask: Vec<L2Entry>,
+    // Best prices spread orders imply into the book, see spread.rs. Only
+    // filled in by SpreadBook::view_leg_l2.
+    #[serde(default)]
+    pub implied_bid: Option<L2Entry>,
+    #[serde(default)]
+    pub implied_ask: Option<L2Entry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    Resting {
+        remaining_qty: u64,
+        price: u32,
+        side: OrderType,
+        // When the book accepted the order, in unix milliseconds, and its
+        // place in the book's arrival order
+        timestamp: u64,
+        arrival_seq: u64,
+    },
+    Filled,
+    PartiallyFilledThenCanceled,
+    // Canceled or expired without any fill
+    Canceled,
+}
+
+// Where an order stands as of the response to it: acknowledged and resting,
+// partially filled with the rest resting, or filled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionReport {
+    pub order_id: Uuid,
+    pub filled_quantity: u64,
+    pub status: OrderStatus,
+    // None for orders the book mirrored rather than matched itself
+    #[serde(default)]
+    pub timestamps: Option<OrderTimestamps>,
+    // Policy applied when the order met another order of its owner
+    #[serde(default)]
+    pub self_match_prevention: Option<SelfMatchPrevention>,
+}
+
+// When the exchange handled an order, in nanoseconds on the server's
+// monotonic clock, see clock::monotonic_nanos. Differences between them are
+// time spent inside the exchange, apart from time on the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderTimestamps {
+    // Server read the request off the connection
+    pub received_nanos: Option<u64>,
+    // Book took the order up for matching
+    pub matched_nanos: u64,
+    // Server sent the response
+    pub acked_nanos: Option<u64>,
+}
+
+// Resting orders a mass cancel applies to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CancelFilter {
+    All,
+    Owner(String),
+    // Both prices inclusive
+    PriceRange {
+        side: OrderType,
+        min_price: u32,
+        max_price: u32,
+    },
+}
+
+// Resting order as reported to its owner
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OpenOrder {
+    pub order_id: Uuid,
+    pub owner: String,
+    pub order_type: OrderType,
+    pub price: u32,
+    // Quantity left to fill
+    pub quantity: u64,
+    pub expires_at: Option<u64>,
+    // When the book accepted the order, in unix milliseconds, and its place
+    // in the book's arrival order. Zero until the server reports them, e.g.
+    // in a client's own record of an order it placed.
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(default)]
+    pub arrival_seq: u64,
+}
+
+impl OpenOrder {
+    fn from_order(order: &Order, order_type: OrderType) -> OpenOrder {
+        OpenOrder {
+            order_id: order.id(),
+            owner: order.owner().to_string(),
+            order_type,
+            price: order.price(),
+            quantity: order.quantity(),
+            expires_at: order.expires_at(),
+            timestamp: order.timestamp(),
+            arrival_seq: order.arrival_seq(),
+        }
+    }
+}
+
+impl Paginated for OpenOrder {
+    fn cursor(&self) -> u64 {
+        self.arrival_seq
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
 }
 
+// Every resting order, as of the event with sequence number `as_of_seq`
 #[derive(Serialize, Deserialize, Debug)]
-struct L2Entry {
-    price: u32,
-    total_quantity: u32,
-    num_orders: usize,
+pub struct L3Book {
+    pub as_of_seq: u64,
+    // In the order l3_orders gives them
+    pub orders: Vec<OpenOrder>,
+}
+
+// Fingerprint of the resting orders as of the event with sequence number
+// `as_of_seq`. Books that applied the same events have the same checksum,
+// so comparing a replica's with its primary's catches a replica that
+// diverged.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookChecksum {
+    pub as_of_seq: u64,
+    pub checksum: u32,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct L2Book {
-    bid: Vec<L2Entry>,
-    ask: Vec<L2Entry>,
+pub struct L1Book {
+    pub as_of_seq: u64,
+    pub bid: Option<L2Entry>,
+    pub ask: Option<L2Entry>,
+    pub stats: SessionStats,
+    pub halted: bool,
+    pub phase: TradingPhase,
+    pub indicative_uncross: Option<AuctionUncross>,
+    // Price the circuit breaker's band is around
+    #[serde(default)]
+    pub reference_price: Option<u32>,
+    // When a volatility auction uncrosses, while one is running
+    #[serde(default)]
+    pub auction_ends_at: Option<u64>,
+    #[serde(default)]
+    pub mark_price: Option<u32>,
+    // Status of the instrument the book trades, if it trades one
+    #[serde(default)]
+    pub instrument_status: Option<InstrumentStatus>,
 }
 
 impl OrderBook {
     pub fn new() -> OrderBook {
+        OrderBook::with_risk_config(RiskConfig::default())
+    }
+
+    pub fn with_risk_config(risk_config: RiskConfig) -> OrderBook {
         OrderBook {
-            bid_tree: PriceTree::new(),
-            ask_tree: PriceTree::new(),
+            bid_tree: PriceTree::new(OrderType::Bid),
+            ask_tree: PriceTree::new(OrderType::Ask),
             order_id_map: HashMap::new(),
-            order_removed_set: HashSet::new(),
+            owner_index: HashMap::new(),
+            closed_orders: HashMap::new(),
+            matched_at: HashMap::new(),
+            self_matches: HashMap::new(),
+            last_placed: None,
+            expiry_index: BTreeSet::new(),
+            session_stats: SessionStats::new(),
+            market_stats: RollingStats::default(),
+            session_start_seq: 1,
+            risk_checks: risk::build_checks(&risk_config),
+            open_exposure: ExposureTracker::default(),
+            risk_config,
+            reference_price: None,
+            mark_price: None,
+            indicative: None,
+            volatility_auction_ends_at: None,
+            halted: false,
+            phase: TradingPhase::Continuous,
+            instrument_status: None,
+            trade_tape: TradeTape::default(),
+            candles: CandleAggregator::default(),
+            clearing_house: ClearingHouse::default(),
+            event_feed: EventFeed::default(),
+            listeners: Vec::new(),
+            accounting: OrderAccounting::default(),
+            clock: Box::new(SystemClock),
+            client_order_ids: HashMap::new(),
+            next_arrival_seq: 1,
+            matching_engine: Box::new(PriceTimeFifo),
+            latency: Arc::new(LatencyMetrics::default()),
         }
     }
 
     pub fn place_order(
         &mut self,
+        owner: &str,
         price: u32,
-        quantity: u32,
+        quantity: u64,
         order_type: OrderType,
-    ) -> Result<Uuid> {
-        if quantity == 0 || price == 0 {
-            return Err(anyhow!("Price or quantity should be bigger than 0"));
-        }
+    ) -> Result<Uuid, OrderBookError> {
+        self.place_order_with_expiry(owner, price, quantity, order_type, None)
+    }
 
-        let mut order = Order::new(price, quantity);
-        let match_outcome = Self::find_matching_orders(&self, &order, &order_type);
+    // Places a good-till-date order when `expires_at` (unix millis) is set
+    pub fn place_order_with_expiry(
+        &mut self,
+        owner: &str,
+        price: u32,
+        quantity: u64,
+        order_type: OrderType,
+        expires_at: Option<u64>,
+    ) -> Result<Uuid, OrderBookError> {
+        let started = Instant::now();
+        let result = self.execute_order(owner, price, quantity, order_type, expires_at, true);
+        self.latency.record(Operation::Place, started.elapsed());
+        result
+    }
 
-        let tree_to_remove = match order_type {
-            OrderType::Ask => &mut self.bid_tree,
-            OrderType::Bid => &mut self.ask_tree,
+    // Trades at the best prices on the other side, no further than the
+    // protection allows, and cancels what is left
+    pub fn place_market_order(
+        &mut self,
+        owner: &str,
+        quantity: u64,
+        order_type: OrderType,
+        protection: MarketProtection,
+    ) -> Result<Uuid, OrderBookError> {
+        let price = match protection {
+            MarketProtection::Price(price) => price,
+            MarketProtection::Ticks(ticks) => {
+                let opposite_tree = match order_type {
+                    OrderType::Ask => &self.bid_tree,
+                    OrderType::Bid => &self.ask_tree,
+                };
+                let best = opposite_tree
+                    .best()
+                    .ok_or(OrderBookError::NoLiquidity)?
+                    .price();
+                let through = ticks.saturating_mul(opposite_tree.tick_size());
+                match order_type {
+                    OrderType::Ask => best.saturating_sub(through).max(1),
+                    OrderType::Bid => best.saturating_add(through),
+                }
+            }
         };
-        // Send orders to clearing house and remove from book
-        // These prints imitates order sent to clearing house
-        if match_outcome.remaining_quantity != order.quantity() {
-            println!("== Clearing House Orders ==");
-            let filled_quantity = order.quantity() - match_outcome.remaining_quantity;
-            println!(
-                "{order_type:?} -> ID: {} Qty: {}, Price: {}",
-                order.id(),
-                filled_quantity,
-                order.price()
-            );
+        let started = Instant::now();
+        let result = self.execute_order(owner, price, quantity, order_type, None, false);
+        self.latency.record(Operation::Place, started.elapsed());
+        result
+    }
 
-            let resting_order_type = match order_type {
-                OrderType::Ask => OrderType::Bid,
-                OrderType::Bid => OrderType::Ask,
-            };
+    fn execute_order(
+        &mut self,
+        owner: &str,
+        price: u32,
+        quantity: u64,
+        order_type: OrderType,
+        expires_at: Option<u64>,
+        // Whether what the order doesn't fill rests, rather than being canceled
+        rests: bool,
+    ) -> Result<Uuid, OrderBookError> {
+        if price == 0 {
+            return Err(OrderBookError::InvalidPrice);
+        }
+        if quantity == 0 {
+            return Err(OrderBookError::InvalidQuantity);
+        }
 
-            for (_, order_key) in &match_outcome.full_order {
-                let filled_order = tree_to_remove.get_order(order_key).unwrap();
-                println!(
-                    "{resting_order_type:?} -> ID: {} Qty: {}, Price: {}",
-                    filled_order.id(),
-                    filled_order.quantity(),
-                    filled_order.price()
-                );
+        if expires_at.is_some_and(|expires_at| expires_at <= self.now()) {
+            return Err(OrderBookError::ExpiryInPast);
+        }
+
+        if self.halted {
+            return Err(OrderBookError::TradingHalted);
+        }
+
+        // Resting the order must not overflow its price level. Checked before
+        // matching so the order never fails after trades have executed.
+        let tree_to_add = match order_type {
+            OrderType::Ask => &self.ask_tree,
+            OrderType::Bid => &self.bid_tree,
+        };
+        if rests && !tree_to_add.holds(price) {
+            return Err(OrderBookError::PriceOffLadder(price));
+        }
+        if rests
+            && tree_to_add
+                .level_quantity(price)
+                .checked_add(quantity)
+                .is_none()
+        {
+            return Err(OrderBookError::QuantityOverflow);
+        }
+
+        let mut order = Order::with_owner(owner.to_string(), price, quantity);
+        order.set_expires_at(expires_at);
+        // Orders accumulate without matching during an auction
+        let match_outcome = match self.phase {
+            TradingPhase::Continuous => {
+                let started = Instant::now();
+                let match_outcome = self.find_matching_orders(&order, &order_type);
+                self.latency.record(Operation::Match, started.elapsed());
+                match_outcome
             }
+            TradingPhase::Auction => MatchOutcome {
+                remaining_quantity: order.quantity(),
+                matches: Vec::new(),
+                worst_price: None,
+                swept_levels: Vec::new(),
+                self_matched: None,
+            },
+        };
 
-            match &match_outcome.partial_order {
-                Some(partial_order) => {
-                    let partial_filled_order =
-                        tree_to_remove.get_order(&partial_order.order_key).unwrap();
-                    let partial_quantity =
-                        partial_filled_order.quantity() - partial_order.remaining_quantity;
-                    println!(
-                        "{resting_order_type:?} -> ID: {} Qty: {}, Price: {}",
-                        partial_filled_order.id(),
-                        partial_quantity,
-                        partial_filled_order.price()
-                    );
+        // Circuit breaker: reject orders that would trade outside the price
+        // band. Checked ahead of the risk chain, whose checks keep state such
+        // as recent orders, so a breaching order leaves nothing behind.
+        let mut band_bypass = None;
+        if let (Some(band), Some(reference_price), Some(worst_price)) = (
+            self.risk_config.price_band,
+            self.band_reference_price(),
+            match_outcome.worst_price,
+        ) {
+            if !band.contains(reference_price, worst_price) {
+                let rejection = RiskRejection::PriceBand {
+                    price: worst_price,
+                    reference_price,
+                };
+                if self.risk_config.bypasses(owner, RiskCheckKind::PriceBand) {
+                    band_bypass = Some((RiskCheckKind::PriceBand, rejection));
+                } else {
+                    if band.halt_on_breach {
+                        self.halt();
+                    }
+                    return Err(rejection.into());
                 }
-                None => {}
             }
-            println!("== End of Orders ==");
         }
-        // End of clearing house log
 
-        // Removing orders from tree
-        for (filled_order_id, key) in &match_outcome.full_order {
-            tree_to_remove.remove_order(&key).unwrap();
-            self.order_id_map.remove(&filled_order_id);
-            self.order_removed_set.insert(*filled_order_id);
+        // Checks relaxed for this participant, with the reason they would have failed.
+        // The checks are moved out while they run so they can look at the book.
+        let mut risk_checks = std::mem::take(&mut self.risk_checks);
+        let risk_order = RiskOrder {
+            owner,
+            order_type,
+            price,
+            quantity,
+            timestamp: self.now(),
+        };
+        let risk_outcome = risk::run_checks(&mut risk_checks, &risk_order, self);
+        self.risk_checks = risk_checks;
+        let mut bypassed_checks = risk_outcome?;
+        bypassed_checks.extend(band_bypass);
+
+        order.set_arrival(self.now(), self.next_arrival_seq);
+        self.next_arrival_seq += 1;
+        if let Some(previous) = self.last_placed.replace(order.id()) {
+            if !self.order_id_map.contains_key(&previous) {
+                self.forget_order_details(previous);
+            }
         }
+        self.matched_at.insert(order.id(), clock::monotonic_nanos());
 
-        // Updating orders to tree
-        match &match_outcome.partial_order {
-            Some(partial_order) => {
-                tree_to_remove
-                    .update_order_quantity(
-                        &partial_order.order_key,
-                        partial_order.remaining_quantity,
-                    )
-                    .unwrap();
+        // Volatility interruption: rather than trade outside its band, the
+        // book pauses for a short auction that the order joins
+        let match_outcome = match (
+            self.risk_config.volatility_interruption,
+            self.band_reference_price(),
+            match_outcome.worst_price,
+        ) {
+            (Some(interruption), Some(reference_price), Some(worst_price))
+                if !interruption.contains(reference_price, worst_price) =>
+            {
+                self.start_auction()?;
+                self.volatility_auction_ends_at = Some(self.now() + interruption.auction_ms);
+                MatchOutcome {
+                    remaining_quantity: order.quantity(),
+                    matches: Vec::new(),
+                    worst_price: None,
+                    swept_levels: Vec::new(),
+                    self_matched: None,
+                }
             }
-            None => {}
+            _ => match_outcome,
         };
 
-        order.update_quantity(match_outcome.remaining_quantity);
+        // Every relaxed check is recorded once the order is accepted
+        if !bypassed_checks.is_empty() {
+            let timestamp = self.now();
+            for (check, rejection) in bypassed_checks {
+                self.clearing_house.audit(
+                    timestamp,
+                    AuditEvent::RiskBypass {
+                        owner: owner.to_string(),
+                        order_id: order.id(),
+                        check,
+                    },
+                    &rejection.to_string(),
+                );
+            }
+        }
+
+        for listener in &mut self.listeners {
+            listener.on_order_accepted(order.id(), owner, order_type, price, quantity);
+        }
+        self.accounting.record_placed(order.id(), quantity);
+
+        // Matching stops at an order of the same owner, if they're kept
+        // from trading with themselves, and carries on after the policy
+        // canceled or reduced one of the two
+        let self_match_prevention = self.risk_config.self_match_prevention(owner);
+        let mut match_outcome = match_outcome;
+        let mut canceled_quantity = 0;
+        loop {
+            self.fill_matches(&order, order_type, &match_outcome);
+            order.update_quantity(match_outcome.remaining_quantity);
+            let (Some(resting_id), Some(policy)) =
+                (match_outcome.self_matched, self_match_prevention)
+            else {
+                break;
+            };
+            let resting_quantity = self.resting_quantity(resting_id);
+            let (resting_canceled, incoming_canceled) = match policy {
+                SelfMatchPrevention::CancelResting => (resting_quantity, 0),
+                SelfMatchPrevention::CancelAggressing => (0, order.quantity()),
+                SelfMatchPrevention::CancelBoth => (resting_quantity, order.quantity()),
+                SelfMatchPrevention::DecrementAndCancel => {
+                    let decrement = resting_quantity.min(order.quantity());
+                    (decrement, decrement)
+                }
+            };
+            if resting_canceled > 0 {
+                self.self_matches.insert(resting_id, policy);
+                if resting_canceled == resting_quantity {
+                    self.cancel_resting_order(resting_id, false).unwrap();
+                } else {
+                    self.reduce_resting_order(resting_id, resting_quantity - resting_canceled)
+                        .unwrap();
+                    self.accounting
+                        .record_cancelled(resting_id, resting_canceled);
+                }
+            }
+            self.self_matches.insert(order.id(), policy);
+            if incoming_canceled > 0 {
+                self.accounting
+                    .record_cancelled(order.id(), incoming_canceled);
+                canceled_quantity += incoming_canceled;
+                order.update_quantity(order.quantity() - incoming_canceled);
+            }
+            if order.quantity() == 0 {
+                break;
+            }
+            match_outcome = self.find_matching_orders(&order, &order_type);
+        }
         let order_id = order.id();
+        if !rests && order.quantity() > 0 {
+            self.accounting.record_cancelled(order_id, order.quantity());
+            canceled_quantity += order.quantity();
+            order.update_quantity(0);
+        }
 
         // If incoming order is unfulfilled, it will be added to the book as a resting order
         if order.quantity() > 0 {
-            let tree_to_add = match order_type {
-                OrderType::Ask => &mut self.ask_tree,
-                OrderType::Bid => &mut self.bid_tree,
-            };
-            let order_key = tree_to_add.insert_order(order);
-            self.order_id_map.insert(order_id, (order_type, order_key));
+            self.event_feed.publish(BookEvent::OrderRested {
+                order_id,
+                owner: order.owner().to_string(),
+                order_type,
+                price: order.price(),
+                quantity: order.quantity(),
+                expires_at: order.expires_at(),
+                timestamp: order.timestamp(),
+                arrival_seq: order.arrival_seq(),
+            });
+            self.rest_order(order, order_type).unwrap();
+        } else if canceled_quantity == 0 {
+            self.closed_orders.insert(order_id, OrderStatus::Filled);
         } else {
-            self.order_removed_set.insert(order_id);
+            let status = if canceled_quantity == quantity {
+                OrderStatus::Canceled
+            } else {
+                OrderStatus::PartiallyFilledThenCanceled
+            };
+            self.closed_orders.insert(order_id, status);
+            for listener in &mut self.listeners {
+                listener.on_order_canceled(order_id);
+            }
         }
 
+        self.enforce_conservation();
+
+        self.enforce_invariants();
         Ok(order_id)
     }
 
+    // Records the trades of one matching pass and takes the resting orders
+    // they filled out of the book, or reduces them
+    fn fill_matches(&mut self, order: &Order, order_type: OrderType, match_outcome: &MatchOutcome) {
+        let tree_to_remove = match order_type {
+            OrderType::Ask => &mut self.bid_tree,
+            OrderType::Bid => &mut self.ask_tree,
+        };
+        // Send trades to clearing house and remove filled orders from book
+        if match_outcome.remaining_quantity != order.quantity() {
+            // (maker order id, maker owner, price, quantity) of every resting order that traded
+            let mut fills = Vec::new();
+            for order_match in &match_outcome.matches {
+                let filled_order = tree_to_remove.get_order(&order_match.order_key).unwrap();
+                fills.push((
+                    filled_order.id(),
+                    filled_order.owner().to_string(),
+                    filled_order.price(),
+                    order_match.fill_quantity,
+                ));
+            }
+
+            let timestamp = self.now();
+            for (maker_order_id, maker_owner, fill_price, fill_quantity) in fills {
+                self.record_trade(Trade {
+                    seq: 0,
+                    timestamp,
+                    price: fill_price,
+                    quantity: fill_quantity,
+                    aggressor: Some(order_type),
+                    maker_order_id,
+                    maker_owner,
+                    taker_order_id: order.id(),
+                    taker_owner: order.owner().to_string(),
+                    fees: TradeFees::default(),
+                });
+            }
+            self.reference_price = self.session_stats.last();
+        }
+
+        // Removing orders from tree, a whole level at a time where every
+        // order on it was filled
+        let resting_type = match order_type {
+            OrderType::Ask => OrderType::Bid,
+            OrderType::Bid => OrderType::Ask,
+        };
+        let mut swept_orders = 0;
+        for &price in &match_outcome.swept_levels {
+            let tree_to_remove = match resting_type {
+                OrderType::Ask => &mut self.ask_tree,
+                OrderType::Bid => &mut self.bid_tree,
+            };
+            for filled_order in tree_to_remove.remove_level(price).unwrap() {
+                let order_id = filled_order.id();
+                let status = self.unindex_order(&filled_order, resting_type, true);
+                self.event_feed
+                    .publish(BookEvent::OrderRemoved { order_id, status });
+                swept_orders += 1;
+            }
+            self.notify_level_change(resting_type, price);
+        }
+        // Removing the other filled orders and reducing those partly filled
+        for order_match in match_outcome.matches.iter().skip(swept_orders) {
+            if order_match.remaining_quantity == 0 {
+                self.remove_resting_order(order_match.order_id, true)
+                    .unwrap();
+            } else {
+                self.reduce_resting_order(order_match.order_id, order_match.remaining_quantity)
+                    .unwrap();
+            }
+        }
+    }
+
     fn find_matching_orders(&self, incoming_order: &Order, order_type: &OrderType) -> MatchOutcome {
         let mut remaining_quantity = incoming_order.quantity();
         // Find as many existing orders that can match the incoming order
-        let mut full_matching_order: Vec<(Uuid, OrderKey)> = Vec::new();
-        let mut partial_matching_order: Option<PartialOrderMatch> = None;
+        let mut matches: Vec<OrderMatch> = Vec::new();
+        let mut worst_price: Option<u32> = None;
+        let mut swept_levels: Vec<u32> = Vec::new();
+        let mut self_matched: Option<Uuid> = None;
+        let prevent_self_match = self
+            .risk_config
+            .self_match_prevention(incoming_order.owner())
+            .is_some();
+
+        let price_valid = |existing_order_price: u32| match order_type {
+            OrderType::Ask => existing_order_price >= incoming_order.price(),
+            OrderType::Bid => existing_order_price <= incoming_order.price(),
+        };
+
+        let opposite_tree = match order_type {
+            OrderType::Ask => &self.bid_tree,
+            OrderType::Bid => &self.ask_tree,
+        };
+        // Common case: the order does not cross the best opposite level
+        if !opposite_tree
+            .best()
+            .is_some_and(|best| price_valid(best.price()))
+        {
+            return MatchOutcome {
+                remaining_quantity,
+                matches,
+                worst_price,
+                swept_levels,
+                self_matched,
+            };
+        }
 
         let mut tree_iter = match order_type {
             OrderType::Ask => self.bid_tree.iter(),
@@ -174,91 +828,2901 @@ impl OrderBook {
             OrderType::Bid => tree_iter.next(),
         };
 
-        let price_valid = |existing_order_price: u32| match order_type {
-            OrderType::Ask => existing_order_price >= incoming_order.price(),
-            OrderType::Bid => existing_order_price <= incoming_order.price(),
-        };
-
-        while let Some((price_node_id, price_node)) = tree_next() {
-            if price_valid(price_node.price()) {
-                // Iterate through orders from oldest to newest
-                for (linked_list_node_id, existing_order) in price_node.iter() {
-                    let order_key = OrderKey::new(price_node_id, linked_list_node_id);
-                    if existing_order.quantity() <= remaining_quantity {
-                        full_matching_order.push((existing_order.id(), order_key));
-                        remaining_quantity -= existing_order.quantity();
-                    } else {
-                        partial_matching_order = Some(PartialOrderMatch {
-                            order_key,
-                            remaining_quantity: existing_order.quantity() - remaining_quantity,
-                        });
-                        remaining_quantity = 0;
-                    }
-
-                    // Incoming order is completely filled
-                    if remaining_quantity == 0 {
-                        return MatchOutcome {
-                            remaining_quantity,
-                            full_order: full_matching_order,
-                            partial_order: partial_matching_order,
-                        };
-                    }
+        'levels: while let Some((price_node_id, price_node)) = tree_next() {
+            if !price_valid(price_node.price()) {
+                break;
+            }
+            worst_price = Some(price_node.price());
+            let allocations = self.matching_engine.allocate(
+                remaining_quantity,
+                price_node.total_quantity(),
+                &mut price_node.iter().map(|(_, order)| order.quantity()),
+            );
+            let mut level_full_orders = 0;
+            // Allocations follow the orders from oldest to newest
+            for ((linked_list_node_id, existing_order), fill_quantity) in
+                price_node.iter().zip(allocations)
+            {
+                if fill_quantity == 0 {
+                    continue;
                 }
+                if prevent_self_match && existing_order.owner() == incoming_order.owner() {
+                    self_matched = Some(existing_order.id());
+                    break 'levels;
+                }
+                let order_key =
+                    OrderKey::new(price_node_id, price_node.generation(), linked_list_node_id);
+                let left_resting = existing_order.quantity() - fill_quantity;
+                if left_resting == 0 {
+                    level_full_orders += 1;
+                }
+                matches.push(OrderMatch {
+                    order_id: existing_order.id(),
+                    order_key,
+                    fill_quantity,
+                    remaining_quantity: left_resting,
+                });
+                remaining_quantity -= fill_quantity;
+            }
+            // Only a level left empty lets the order trade at the next price
+            if level_full_orders < price_node.num_orders() {
+                break;
+            }
+            swept_levels.push(price_node.price());
+            // Incoming order is completely filled
+            if remaining_quantity == 0 {
+                break;
             }
         }
 
         MatchOutcome {
             remaining_quantity,
-            full_order: full_matching_order,
-            partial_order: partial_matching_order,
+            matches,
+            worst_price,
+            swept_levels,
+            self_matched,
+        }
+    }
+
+    // Returns the quantity that was still resting
+    pub fn cancel_order(&mut self, order_id: Uuid) -> Result<u64, OrderBookError> {
+        let started = Instant::now();
+        let canceled = self.cancel_resting_order(order_id, false);
+        self.latency.record(Operation::Cancel, started.elapsed());
+        let canceled_quantity = canceled?;
+        self.enforce_conservation();
+        self.enforce_invariants();
+        Ok(canceled_quantity)
+    }
+
+    fn cancel_resting_order(
+        &mut self,
+        order_id: Uuid,
+        expired: bool,
+    ) -> Result<u64, OrderBookError> {
+        if let Some(&status) = self.closed_orders.get(&order_id) {
+            return Err(OrderBookError::closed(status));
+        }
+
+        let remaining_quantity = self.resting_quantity(order_id);
+        self.remove_resting_order(order_id, false)?;
+        if expired {
+            self.accounting.record_expired(order_id, remaining_quantity);
+        } else {
+            self.accounting
+                .record_cancelled(order_id, remaining_quantity);
+        }
+        for listener in &mut self.listeners {
+            listener.on_order_canceled(order_id);
+        }
+        Ok(remaining_quantity)
+    }
+
+    fn resting_quantity(&self, order_id: Uuid) -> u64 {
+        match self.order_status(order_id) {
+            Some(OrderStatus::Resting { remaining_qty, .. }) => remaining_qty,
+            _ => 0,
+        }
+    }
+
+    // Where the quantity of an order placed on this book went
+    pub fn order_account(&self, order_id: Uuid) -> Option<OrderAccount> {
+        self.accounting
+            .account(order_id, self.resting_quantity(order_id))
+    }
+
+    // Checks placed = filled + cancelled + expired + resting for every order
+    // and that the ledger's balances match its journal, and returns how many
+    // orders were checked
+    pub fn check_conservation(&self) -> anyhow::Result<usize> {
+        self.clearing_house.ledger().check()?;
+        self.accounting
+            .check(|order_id| self.resting_quantity(order_id))
+    }
+
+    // Panics on the first violation when built with `conservation-checks`.
+    // Checks every order, so it is meant for tests and debugging.
+    fn enforce_conservation(&self) {
+        #[cfg(feature = "conservation-checks")]
+        if let Err(err) = self.check_conservation() {
+            panic!("{err}");
+        }
+    }
+
+    // Checks the book agrees with itself: not crossed outside an auction,
+    // every level adding up to its orders, and the order, owner and expiry
+    // indexes matching the resting orders. Returns how many resting orders
+    // were checked. Walks the whole book, so it is meant for tests and
+    // debugging.
+    pub fn check_invariants(&self) -> anyhow::Result<usize> {
+        if self.phase == TradingPhase::Continuous {
+            if let (Some(best_bid), Some(best_ask)) = (self.best_bid(), self.best_ask()) {
+                if best_bid.price() >= best_ask.price() {
+                    return Err(anyhow!(
+                        "Book is crossed with bid {} and ask {}",
+                        best_bid.price(),
+                        best_ask.price()
+                    ));
+                }
+            }
+        }
+        let resting_orders = self.bid_tree.check()? + self.ask_tree.check()?;
+        if self.order_id_map.len() != resting_orders {
+            return Err(anyhow!(
+                "{} orders are indexed but {resting_orders} rest on the book",
+                self.order_id_map.len()
+            ));
+        }
+        for (&order_id, (order_type, order_key)) in &self.order_id_map {
+            let tree = match order_type {
+                OrderType::Ask => &self.ask_tree,
+                OrderType::Bid => &self.bid_tree,
+            };
+            let order = tree
+                .get_order(order_key)
+                .filter(|order| order.id() == order_id)
+                .ok_or_else(|| anyhow!("Order {order_id} is indexed where it doesn't rest"))?;
+            if !self
+                .owner_index
+                .get(order.owner())
+                .is_some_and(|order_ids| order_ids.contains(&order_id))
+            {
+                return Err(anyhow!(
+                    "Order {order_id} is missing from the orders of {}",
+                    order.owner()
+                ));
+            }
+            if let Some(expires_at) = order.expires_at() {
+                if !self.expiry_index.contains(&(expires_at, order_id)) {
+                    return Err(anyhow!("Order {order_id} is missing from the expiry index"));
+                }
+            }
+        }
+        let owner_indexed: usize = self.owner_index.values().map(HashSet::len).sum();
+        if owner_indexed != resting_orders {
+            return Err(anyhow!(
+                "{owner_indexed} orders are indexed by owner but {resting_orders} rest on the book"
+            ));
+        }
+        for &(expires_at, order_id) in &self.expiry_index {
+            let resting_expiry = self
+                .order_id_map
+                .get(&order_id)
+                .and_then(|(order_type, order_key)| match order_type {
+                    OrderType::Ask => self.ask_tree.get_order(order_key),
+                    OrderType::Bid => self.bid_tree.get_order(order_key),
+                })
+                .and_then(|order| order.expires_at());
+            if resting_expiry != Some(expires_at) {
+                return Err(anyhow!(
+                    "Order {order_id} expires at {expires_at} in the index but not on the book"
+                ));
+            }
+        }
+        Ok(resting_orders)
+    }
+
+    // Panics on the first broken invariant in unit tests or when built with
+    // `conservation-checks`, never in a plain debug build as it walks the
+    // whole book
+    fn enforce_invariants(&self) {
+        #[cfg(any(test, feature = "conservation-checks"))]
+        if let Err(err) = self.check_invariants() {
+            panic!("{err}");
+        }
+    }
+
+    // Cancels every resting order matching the filter in one step and returns
+    // their ids, bids first in price order
+    pub fn cancel_all(&mut self, filter: &CancelFilter) -> Vec<Uuid> {
+        let level_order_ids = |tree: &PriceTree, range: RangeInclusive<u32>| -> Vec<Uuid> {
+            tree.range(range)
+                .flat_map(|(_, price_node)| price_node.iter().map(|(_, order)| order.id()))
+                .collect()
+        };
+        let order_ids = match filter {
+            CancelFilter::All => {
+                let mut order_ids = level_order_ids(&self.bid_tree, 0..=u32::MAX);
+                order_ids.extend(level_order_ids(&self.ask_tree, 0..=u32::MAX));
+                order_ids
+            }
+            CancelFilter::Owner(owner) => self
+                .open_orders(owner)
+                .into_iter()
+                .map(|order| order.order_id)
+                .collect(),
+            CancelFilter::PriceRange {
+                side,
+                min_price,
+                max_price,
+            } => {
+                let tree = match side {
+                    OrderType::Ask => &self.ask_tree,
+                    OrderType::Bid => &self.bid_tree,
+                };
+                if min_price > max_price {
+                    return Vec::new();
+                }
+                level_order_ids(tree, *min_price..=*max_price)
+            }
+        };
+        for order_id in &order_ids {
+            self.cancel_order(*order_id).unwrap();
+        }
+        order_ids
+    }
+
+    // Makes room for the book to reach `capacity` on top of what it holds
+    pub fn reserve(&mut self, capacity: BookCapacity) {
+        for tree in [&mut self.bid_tree, &mut self.ask_tree] {
+            tree.reserve(capacity.levels);
+            tree.set_level_capacity(capacity.orders_per_level);
+        }
+        self.order_id_map.reserve(capacity.orders);
+    }
+
+    // Switches how both sides find their levels by price. Only an empty
+    // book can switch.
+    pub fn set_price_level_index(&mut self, index: PriceLevelIndex) -> Result<(), OrderBookError> {
+        if !self.order_id_map.is_empty() {
+            return Err(OrderBookError::BookNotEmpty);
+        }
+        self.bid_tree.set_index(index)?;
+        self.ask_tree.set_index(index)?;
+        Ok(())
+    }
+
+    // Frees room held beyond the book's current size, e.g. after a busy
+    // session
+    pub fn shrink_to_fit(&mut self) {
+        self.bid_tree.shrink_to_fit();
+        self.ask_tree.shrink_to_fit();
+        self.order_id_map.shrink_to_fit();
+        self.owner_index.shrink_to_fit();
+    }
+
+    // Appends a check to the end of the pre-trade chain
+    pub fn add_risk_check(&mut self, check: Box<dyn RiskCheck>) {
+        self.risk_checks.push(check);
+    }
+
+    pub fn add_listener(&mut self, listener: Box<dyn BookListener>) {
+        self.listeners.push(listener);
+    }
+
+    // Tells every listener about work that failed with no request to answer,
+    // e.g. bookkeeping after the book committed to a trade
+    pub(crate) fn report_error(&mut self, err: anyhow::Error) {
+        for listener in &mut self.listeners {
+            listener.on_error(&err);
         }
     }
 
-    pub fn cancel_order(&mut self, order_id: Uuid) -> Result<()> {
-        if self.order_removed_set.contains(&order_id) {
-            return Err(anyhow!("Order is already removed from the book"));
+    // Reports the current state of a price level to every listener
+    fn notify_level_change(&mut self, side: OrderType, price: u32) {
+        // Any level can move where the auction uncrosses
+        if self.phase == TradingPhase::Auction {
+            self.refresh_indicative();
+        }
+        if self.listeners.is_empty() {
+            return;
+        }
+        let tree = match side {
+            OrderType::Ask => &self.ask_tree,
+            OrderType::Bid => &self.bid_tree,
+        };
+        let (total_quantity, num_orders) = tree
+            .level(price)
+            .map_or((0, 0), |level| (level.total_quantity(), level.num_orders()));
+        for listener in &mut self.listeners {
+            listener.on_level_change(side, price, total_quantity, num_orders);
+        }
+    }
+
+    fn rest_order(&mut self, order: Order, order_type: OrderType) -> Result<(), OrderBookError> {
+        let order_id = order.id();
+        let tree_to_add = match order_type {
+            OrderType::Ask => &mut self.ask_tree,
+            OrderType::Bid => &mut self.bid_tree,
+        };
+        let (price, expires_at) = (order.price(), order.expires_at());
+        let owner = order.owner().to_string();
+        let quantity = order.quantity();
+        let order_key = tree_to_add.insert_order(order)?;
+        self.open_exposure.add(&owner, order_type, price, quantity);
+        self.owner_index.entry(owner).or_default().insert(order_id);
+        if let Some(expires_at) = expires_at {
+            self.expiry_index.insert((expires_at, order_id));
         }
+        self.order_id_map.insert(order_id, (order_type, order_key));
+        self.notify_level_change(order_type, price);
+        Ok(())
+    }
+
+    // Sets the quantity left on a partially filled resting order
+    fn reduce_resting_order(
+        &mut self,
+        order_id: Uuid,
+        quantity: u64,
+    ) -> Result<(), OrderBookError> {
+        self.update_resting_quantity(order_id, quantity)?;
+        self.event_feed
+            .publish(BookEvent::OrderReduced { order_id, quantity });
+        Ok(())
+    }
+
+    fn update_resting_quantity(
+        &mut self,
+        order_id: Uuid,
+        quantity: u64,
+    ) -> Result<(), OrderBookError> {
+        let (order_type, order_key) = self
+            .order_id_map
+            .get(&order_id)
+            .ok_or(OrderBookError::UnknownOrder)?;
+        let order_type = *order_type;
+        let tree_to_update = match order_type {
+            OrderType::Ask => &mut self.ask_tree,
+            OrderType::Bid => &mut self.bid_tree,
+        };
+        let previous_quantity = tree_to_update.get_order(order_key).unwrap().quantity();
+        tree_to_update.update_order_quantity(order_key, quantity)?;
+        let order = tree_to_update.get_order(order_key).unwrap();
+        let price = order.price();
+        self.open_exposure
+            .remove(order.owner(), order_type, price, previous_quantity);
+        self.open_exposure
+            .add(order.owner(), order_type, price, quantity);
+        self.notify_level_change(order_type, price);
+        Ok(())
+    }
 
+    // Removes a resting order that was either filled or canceled
+    fn remove_resting_order(&mut self, order_id: Uuid, filled: bool) -> Result<(), OrderBookError> {
+        let status = self.detach_order(order_id, filled)?;
+        self.event_feed
+            .publish(BookEvent::OrderRemoved { order_id, status });
+        Ok(())
+    }
+
+    // Removes a resting order from its tree and every index referencing it
+    fn detach_order(
+        &mut self,
+        order_id: Uuid,
+        filled: bool,
+    ) -> Result<OrderStatus, OrderBookError> {
         if let Some((order_type, order_key)) = self.order_id_map.get(&order_id) {
+            let order_type = *order_type;
             let tree_to_remove = match order_type {
                 OrderType::Ask => &mut self.ask_tree,
                 OrderType::Bid => &mut self.bid_tree,
             };
 
-            tree_to_remove.remove_order(order_key).unwrap();
-            self.order_id_map.remove(&order_id);
-            self.order_removed_set.insert(order_id);
+            let order = tree_to_remove.remove_order(order_key).unwrap();
+            let status = self.unindex_order(&order, order_type, filled);
+            self.notify_level_change(order_type, order.price());
 
-            Ok(())
+            Ok(status)
         } else {
-            Err(anyhow!("Order cannot be found"))
+            Err(OrderBookError::UnknownOrder)
         }
     }
 
-    pub fn view_book_l2(&self) -> L2Book {
-        let mut bid_entries = Vec::new();
-
-        for (_, price_node) in self.bid_tree.iter() {
-            bid_entries.push(L2Entry {
-                price: price_node.price(),
-                total_quantity: price_node.total_quantity(),
-                num_orders: price_node.num_orders(),
-            })
+    // Drops an order already taken out of its tree from every index
+    // referencing it, and returns the status it closed with
+    fn unindex_order(&mut self, order: &Order, order_type: OrderType, filled: bool) -> OrderStatus {
+        let order_id = order.id();
+        // Going by the accounted fills where there are any, as self-match
+        // prevention may have reduced the order without filling it
+        let partially_filled = match self.accounting.account(order_id, 0) {
+            Some(account) => account.filled > 0,
+            None => order.is_partially_filled(),
+        };
+        let status = if filled {
+            OrderStatus::Filled
+        } else if partially_filled {
+            OrderStatus::PartiallyFilledThenCanceled
+        } else {
+            OrderStatus::Canceled
+        };
+        if let Some(expires_at) = order.expires_at() {
+            self.expiry_index.remove(&(expires_at, order_id));
         }
+        self.open_exposure
+            .remove(order.owner(), order_type, order.price(), order.quantity());
+        if let Some(order_ids) = self.owner_index.get_mut(order.owner()) {
+            order_ids.remove(&order_id);
+            if order_ids.is_empty() {
+                self.owner_index.remove(order.owner());
+            }
+        }
+        self.order_id_map.remove(&order_id);
+        self.closed_orders.insert(order_id, status);
+        if self.last_placed != Some(order_id) {
+            self.forget_order_details(order_id);
+        }
+        status
+    }
 
-        let mut ask_entries = Vec::new();
+    // Drops the execution report details kept for an open order
+    fn forget_order_details(&mut self, order_id: Uuid) {
+        self.matched_at.remove(&order_id);
+        self.self_matches.remove(&order_id);
+    }
 
-        for (_, price_node) in self.ask_tree.iter() {
-            ask_entries.push(L2Entry {
-                price: price_node.price(),
-                total_quantity: price_node.total_quantity(),
-                num_orders: price_node.num_orders(),
-            })
+    // Charges fees for a trade and records it in the session statistics,
+    // the trade tape and the clearing house ledger
+    fn record_trade(&mut self, mut trade: Trade) {
+        trade.fees = self.clearing_house.trade_fees(&trade);
+        self.session_stats.record_trade(trade.price, trade.quantity);
+        self.accounting
+            .record_fill(trade.maker_order_id, trade.quantity);
+        self.accounting
+            .record_fill(trade.taker_order_id, trade.quantity);
+        let trade = self.trade_tape.record(trade).clone();
+        if let Err(err) = self.trade_tape.persist() {
+            self.report_error(err.context("Failed to spill trades to disk"));
         }
-
-        L2Book {
-            bid: bid_entries,
-            ask: ask_entries,
+        self.candles.record_trade(&trade);
+        self.market_stats.record_trade(&trade);
+        if let Err(err) = self.clearing_house.clear(&trade) {
+            self.report_error(err.context(format!("Failed to clear trade {}", trade.seq)));
         }
+        for listener in &mut self.listeners {
+            listener.on_trade(&trade);
+        }
+        self.event_feed.publish(BookEvent::Traded(trade));
+    }
+
+    pub fn phase(&self) -> TradingPhase {
+        self.phase
+    }
+
+    // Switches the book into a call auction. Orders rest without matching
+    // until uncross() is called.
+    pub fn start_auction(&mut self) -> Result<(), OrderBookError> {
+        if self.phase == TradingPhase::Auction {
+            return Err(OrderBookError::AlreadyInAuction);
+        }
+        self.phase = TradingPhase::Auction;
+        self.event_feed
+            .publish(BookEvent::PhaseChanged(TradingPhase::Auction));
+        self.refresh_indicative();
+        Ok(())
+    }
+
+    // Price and volume the auction would uncross at now. None when the book
+    // doesn't cross or isn't in an auction.
+    pub fn indicative_uncross(&self) -> Option<AuctionUncross> {
+        self.indicative
+    }
+
+    // Recomputes the indicative uncross after a change to the book and
+    // tells the listeners if it moved
+    fn refresh_indicative(&mut self) {
+        let indicative = match self.phase {
+            TradingPhase::Auction => self.compute_uncross(),
+            TradingPhase::Continuous => None,
+        };
+        if indicative == self.indicative {
+            return;
+        }
+        self.indicative = indicative;
+        for listener in &mut self.listeners {
+            listener.on_indicative_uncross(indicative);
+        }
+    }
+
+    // Equilibrium price that maximizes executable volume, breaking ties by the
+    // smallest surplus, then closeness to the reference price, then the lower
+    // price. None when the book does not cross.
+    fn compute_uncross(&self) -> Option<AuctionUncross> {
+        // Both sides in ascending price order
+        let bids: Vec<(u32, u64)> = self
+            .bid_tree
+            .iter()
+            .map(|(_, node)| (node.price(), node.total_quantity()))
+            .collect();
+        let asks: Vec<(u32, u64)> = self
+            .ask_tree
+            .iter()
+            .map(|(_, node)| (node.price(), node.total_quantity()))
+            .collect();
+
+        // demand_from[i]: bid quantity at bids[i..], supply_to[i]: ask quantity at asks[..i]
+        let mut demand_from = vec![0u64; bids.len() + 1];
+        for i in (0..bids.len()).rev() {
+            demand_from[i] = demand_from[i + 1].saturating_add(bids[i].1);
+        }
+        let mut supply_to = vec![0u64; asks.len() + 1];
+        for i in 0..asks.len() {
+            supply_to[i + 1] = supply_to[i].saturating_add(asks[i].1);
+        }
+
+        let mut best: Option<(AuctionUncross, u64, u32)> = None;
+        for price in bids.iter().chain(asks.iter()).map(|&(price, _)| price) {
+            let demand = demand_from[bids.partition_point(|&(bid, _)| bid < price)];
+            let supply = supply_to[asks.partition_point(|&(ask, _)| ask <= price)];
+            let volume = demand.min(supply);
+            if volume == 0 {
+                continue;
+            }
+            let surplus = demand.abs_diff(supply);
+            let distance = self
+                .reference_price
+                .map_or(0, |reference_price| price.abs_diff(reference_price));
+
+            let is_better = match &best {
+                None => true,
+                Some((best_uncross, best_surplus, best_distance)) => {
+                    (volume, *best_surplus, *best_distance, best_uncross.price)
+                        > (best_uncross.volume, surplus, distance, price)
+                }
+            };
+            if is_better {
+                best = Some((AuctionUncross { price, volume }, surplus, distance));
+            }
+        }
+        best.map(|(uncross, _, _)| uncross)
+    }
+
+    // Uncrosses the auction a volatility interruption started once it has
+    // run its course. None until then, or when nothing crossed.
+    pub fn end_volatility_auction(&mut self, now: u64) -> Option<AuctionUncross> {
+        if self
+            .volatility_auction_ends_at
+            .is_none_or(|ends_at| ends_at > now)
+        {
+            return None;
+        }
+        self.uncross().ok().flatten()
+    }
+
+    // When the running volatility auction uncrosses, if one is running
+    pub fn volatility_auction_ends_at(&self) -> Option<u64> {
+        self.volatility_auction_ends_at
+    }
+
+    // Executes the auction at the single equilibrium price and returns the
+    // book to continuous trading
+    pub fn uncross(&mut self) -> Result<Option<AuctionUncross>, OrderBookError> {
+        if self.phase != TradingPhase::Auction {
+            return Err(OrderBookError::NotInAuction);
+        }
+        self.phase = TradingPhase::Continuous;
+        self.volatility_auction_ends_at = None;
+        self.event_feed
+            .publish(BookEvent::PhaseChanged(TradingPhase::Continuous));
+        self.refresh_indicative();
+
+        let uncross = match self.compute_uncross() {
+            Some(uncross) => uncross,
+            None => return Ok(None),
+        };
+        let mut bid_fills = self.auction_allocations(OrderType::Bid, &uncross);
+        let mut ask_fills = self.auction_allocations(OrderType::Ask, &uncross);
+
+        // Remove or reduce every allocated order
+        for fills in [&bid_fills, &ask_fills] {
+            for fill in fills {
+                if fill.fill_quantity == fill.order_quantity {
+                    self.remove_resting_order(fill.order_id, true).unwrap();
+                } else {
+                    self.reduce_resting_order(
+                        fill.order_id,
+                        fill.order_quantity - fill.fill_quantity,
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        // Pair both sides in priority order. Each side sums to the uncross volume.
+        let timestamp = self.now();
+        let (mut bid_idx, mut ask_idx) = (0, 0);
+        while bid_idx < bid_fills.len() && ask_idx < ask_fills.len() {
+            let quantity = bid_fills[bid_idx]
+                .fill_quantity
+                .min(ask_fills[ask_idx].fill_quantity);
+            let (bid, ask) = (&bid_fills[bid_idx], &ask_fills[ask_idx]);
+            self.record_trade(Trade {
+                seq: 0,
+                timestamp,
+                price: uncross.price,
+                quantity,
+                aggressor: None,
+                maker_order_id: ask.order_id,
+                maker_owner: ask.owner.clone(),
+                taker_order_id: bid.order_id,
+                taker_owner: bid.owner.clone(),
+                fees: TradeFees::default(),
+            });
+
+            bid_fills[bid_idx].fill_quantity -= quantity;
+            ask_fills[ask_idx].fill_quantity -= quantity;
+            if bid_fills[bid_idx].fill_quantity == 0 {
+                bid_idx += 1;
+            }
+            if ask_fills[ask_idx].fill_quantity == 0 {
+                ask_idx += 1;
+            }
+        }
+        self.reference_price = Some(uncross.price);
+        self.enforce_conservation();
+        self.enforce_invariants();
+        Ok(Some(uncross))
+    }
+
+    // Allocates the uncross volume to one side in price priority, split
+    // within each level by the matching engine
+    fn auction_allocations(
+        &self,
+        order_type: OrderType,
+        uncross: &AuctionUncross,
+    ) -> Vec<AuctionFill> {
+        let mut tree_iter = match order_type {
+            OrderType::Bid => self.bid_tree.iter(),
+            OrderType::Ask => self.ask_tree.iter(),
+        };
+        let mut tree_next = || match order_type {
+            OrderType::Bid => tree_iter.next_back(),
+            OrderType::Ask => tree_iter.next(),
+        };
+        let price_valid = |price: u32| match order_type {
+            OrderType::Bid => price >= uncross.price,
+            OrderType::Ask => price <= uncross.price,
+        };
+
+        let mut fills = Vec::new();
+        let mut remaining_volume = uncross.volume;
+        while let Some((_, price_node)) = tree_next() {
+            if remaining_volume == 0 || !price_valid(price_node.price()) {
+                break;
+            }
+            let allocations = self.matching_engine.allocate(
+                remaining_volume,
+                price_node.total_quantity(),
+                &mut price_node.iter().map(|(_, order)| order.quantity()),
+            );
+            for ((_, order), fill_quantity) in price_node.iter().zip(allocations) {
+                if fill_quantity == 0 {
+                    continue;
+                }
+                fills.push(AuctionFill {
+                    order_id: order.id(),
+                    owner: order.owner().to_string(),
+                    order_quantity: order.quantity(),
+                    fill_quantity,
+                });
+                remaining_volume -= fill_quantity;
+            }
+        }
+        fills
+    }
+
+    // Cancels every resting order that expired at or before `now` (unix
+    // millis) and returns their ids. Only expired entries are visited.
+    pub fn expire_orders(&mut self, now: u64) -> Vec<Uuid> {
+        let mut expired_ids = Vec::new();
+        while let Some(&(expires_at, order_id)) = self.expiry_index.first() {
+            if expires_at > now {
+                break;
+            }
+            // Canceling also removes the entry from the expiry index
+            self.cancel_resting_order(order_id, true).unwrap();
+            expired_ids.push(order_id);
+        }
+        self.enforce_conservation();
+        self.enforce_invariants();
+        expired_ids
+    }
+
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.expiry_index.first().map(|&(expires_at, _)| expires_at)
+    }
+
+    // Closes the session at `now`: trading halts, the settlement price is set
+    // from the session's trades, due and day orders expire and every
+    // participant's net position is reported to the clearing house
+    pub fn end_of_day(
+        &mut self,
+        now: u64,
+        options: &EndOfDayOptions,
+    ) -> anyhow::Result<SettlementReport> {
+        if self.phase == TradingPhase::Auction {
+            return Err(anyhow!("Auction has to be uncrossed before the close"));
+        }
+
+        let settlement = self.session_settlement_price(now, options.settlement_window_ms)?;
+        let settlement_price = settlement.map(|(price, _)| price);
+        let positions = self.clearing_house.net_positions(settlement_price)?;
+
+        if !self.halted {
+            self.halt();
+        }
+        let mut expired_orders = self.expire_orders(now);
+        if options.expire_day_orders {
+            let day_order_ids: Vec<Uuid> = self
+                .bid_tree
+                .iter()
+                .chain(self.ask_tree.iter())
+                .flat_map(|(_, price_node)| price_node.iter().map(|(_, order)| order))
+                .filter(|order| order.expires_at().is_none())
+                .map(|order| order.id())
+                .collect();
+            for order_id in day_order_ids {
+                self.cancel_resting_order(order_id, true)?;
+                expired_orders.push(order_id);
+            }
+            self.enforce_conservation();
+            self.enforce_invariants();
+        }
+
+        let report = SettlementReport {
+            settled_at: now,
+            settlement_price,
+            price_source: settlement.map(|(_, source)| source),
+            session: self.session_stats.clone(),
+            expired_orders,
+            positions,
+            rolled: options.roll,
+        };
+        if options.roll {
+            self.roll_session(settlement_price);
+            self.event_feed
+                .publish(BookEvent::SessionRolled { settlement_price });
+            self.resume();
+        }
+        self.clearing_house.record_settlement(report.clone());
+        Ok(report)
+    }
+
+    // Expires the instrument at `now`: trading halts for good, every resting
+    // order is canceled whatever its time in force, and every position is
+    // closed out in cash at a settlement price set as at the close
+    pub fn expire(
+        &mut self,
+        now: u64,
+        settlement_window_ms: u64,
+    ) -> anyhow::Result<SettlementReport> {
+        if self.phase == TradingPhase::Auction {
+            return Err(anyhow!("Auction has to be uncrossed before expiry"));
+        }
+
+        let settlement = self.session_settlement_price(now, settlement_window_ms)?;
+        let settlement_price = settlement.map(|(price, _)| price);
+        let positions = self.clearing_house.net_positions(settlement_price)?;
+        if settlement_price.is_none() && positions.iter().any(|line| line.position != 0) {
+            return Err(anyhow!(
+                "Positions cannot be settled without a settlement price"
+            ));
+        }
+
+        if !self.halted {
+            self.halt();
+        }
+        let expired_orders: Vec<Uuid> = self
+            .bid_tree
+            .iter()
+            .chain(self.ask_tree.iter())
+            .flat_map(|(_, price_node)| price_node.iter().map(|(_, order)| order.id()))
+            .collect();
+        for &order_id in &expired_orders {
+            self.cancel_resting_order(order_id, true)?;
+        }
+        self.enforce_conservation();
+        self.enforce_invariants();
+        if let Some(price) = settlement_price {
+            self.clearing_house.settle_expiry(price, now)?;
+        }
+
+        let report = SettlementReport {
+            settled_at: now,
+            settlement_price,
+            price_source: settlement.map(|(_, source)| source),
+            session: self.session_stats.clone(),
+            expired_orders,
+            positions,
+            rolled: false,
+        };
+        self.clearing_house.record_settlement(report.clone());
+        Ok(report)
+    }
+
+    // Settlement price of the session up to `now`, see settlement.rs
+    fn session_settlement_price(
+        &self,
+        now: u64,
+        settlement_window_ms: u64,
+    ) -> anyhow::Result<Option<(u32, SettlementPriceSource)>> {
+        let window_start = now.saturating_sub(settlement_window_ms);
+        // An in-memory tape may have dropped the session's early trades,
+        // which only matter if they could fall in the settlement window
+        let from_seq = self.session_start_seq.max(self.trade_tape.first_seq());
+        let session_trades = self
+            .trade_tape
+            .iter_from(from_seq)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if from_seq > self.session_start_seq
+            && session_trades
+                .first()
+                .is_none_or(|trade| trade.timestamp >= window_start)
+        {
+            return Err(anyhow!(
+                "Trades of the settlement window are no longer kept"
+            ));
+        }
+        let previous_price = self
+            .clearing_house
+            .settlements()
+            .last()
+            .and_then(|report| report.settlement_price);
+        Ok(settlement::settlement_price(
+            session_trades.iter().filter(|trade| trade.timestamp <= now),
+            window_start,
+            previous_price,
+        ))
+    }
+
+    fn roll_session(&mut self, settlement_price: Option<u32>) {
+        self.session_stats.reset();
+        self.client_order_ids.clear();
+        self.session_start_seq = self.trade_tape.next_seq();
+        // Until it trades, the new session's band is around the close: the
+        // settlement price, or the last trade without one
+        if settlement_price.is_some() {
+            self.reference_price = settlement_price;
+        }
+    }
+
+    pub fn view_book_l2(&self) -> L2Book {
+        let started = Instant::now();
+        let mut bid_entries = Vec::new();
+
+        for (_, price_node) in self.bid_tree.iter() {
+            bid_entries.push(L2Entry::from_level(price_node))
+        }
+
+        let mut ask_entries = Vec::new();
+
+        for (_, price_node) in self.ask_tree.iter() {
+            ask_entries.push(L2Entry::from_level(price_node))
+        }
+
+        let l2_book = L2Book {
+            as_of_seq: self.event_feed.last_seq(),
+            bid: bid_entries,
+            ask: ask_entries,
+            implied_bid: None,
+            implied_ask: None,
+        };
+        self.latency.record(Operation::L2Book, started.elapsed());
+        l2_book
+    }
+
+    // Like view_book_l2, limited to the best `depth` levels of each side
+    pub fn view_book_l2_depth(&self, depth: usize) -> L2Book {
+        let to_entry = |(_, price_node): (usize, &PriceNode)| L2Entry::from_level(price_node);
+
+        // Both sides are listed in ascending price order
+        let mut bid_entries: Vec<L2Entry> = self.bid_tree.top_n(depth).map(to_entry).collect();
+        bid_entries.reverse();
+
+        L2Book {
+            as_of_seq: self.event_feed.last_seq(),
+            bid: bid_entries,
+            ask: self.ask_tree.top_n(depth).map(to_entry).collect(),
+            implied_bid: None,
+            implied_ask: None,
+        }
+    }
+
+    // Total resting quantity on one side priced within the range
+    pub fn liquidity_within(&self, price_range: RangeInclusive<u32>, side: OrderType) -> u64 {
+        let tree = match side {
+            OrderType::Bid => &self.bid_tree,
+            OrderType::Ask => &self.ask_tree,
+        };
+        tree.range(price_range)
+            .fold(0u64, |total, (_, price_node)| {
+                total.saturating_add(price_node.total_quantity())
+            })
+    }
+
+    pub fn view_book_l1(&self) -> L1Book {
+        L1Book {
+            as_of_seq: self.event_feed.last_seq(),
+            bid: self.bid_tree.best().map(L2Entry::from_level),
+            ask: self.ask_tree.best().map(L2Entry::from_level),
+            stats: self.session_stats.clone(),
+            halted: self.halted,
+            phase: self.phase,
+            indicative_uncross: self.indicative,
+            reference_price: self.reference_price,
+            auction_ends_at: self.volatility_auction_ends_at,
+            mark_price: self.mark_price,
+            instrument_status: self.instrument_status,
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<&PriceNode> {
+        self.bid_tree.best()
+    }
+
+    pub fn best_ask(&self) -> Option<&PriceNode> {
+        self.ask_tree.best()
+    }
+
+    // The best `n` levels of one side, best first
+    pub fn top_levels(&self, side: OrderType, n: usize) -> TopLevels<'_, '_> {
+        match side {
+            OrderType::Bid => self.bid_tree.top_n(n),
+            OrderType::Ask => self.ask_tree.top_n(n),
+        }
+    }
+
+    // None for ids the book has never seen
+    pub fn order_status(&self, order_id: Uuid) -> Option<OrderStatus> {
+        if let Some((order_type, order_key)) = self.order_id_map.get(&order_id) {
+            let tree = match order_type {
+                OrderType::Ask => &self.ask_tree,
+                OrderType::Bid => &self.bid_tree,
+            };
+            let order = tree.get_order(order_key)?;
+            return Some(OrderStatus::Resting {
+                remaining_qty: order.quantity(),
+                price: order.price(),
+                side: *order_type,
+                timestamp: order.timestamp(),
+                arrival_seq: order.arrival_seq(),
+            });
+        }
+        self.closed_orders.get(&order_id).copied()
+    }
+
+    // None for ids the book has never seen
+    pub fn execution_report(&self, order_id: Uuid) -> Option<ExecutionReport> {
+        let status = self.order_status(order_id)?;
+        let filled_quantity = self
+            .order_account(order_id)
+            .map_or(0, |account| account.filled);
+        let timestamps = self
+            .matched_at
+            .get(&order_id)
+            .map(|&matched_nanos| OrderTimestamps {
+                received_nanos: None,
+                matched_nanos,
+                acked_nanos: None,
+            });
+        Some(ExecutionReport {
+            order_id,
+            filled_quantity,
+            status,
+            timestamps,
+            self_match_prevention: self.self_matches.get(&order_id).copied(),
+        })
+    }
+
+    // Owner of a resting order, None once it's closed
+    pub fn order_owner(&self, order_id: Uuid) -> Option<&str> {
+        let (order_type, order_key) = self.order_id_map.get(&order_id)?;
+        let tree = match order_type {
+            OrderType::Ask => &self.ask_tree,
+            OrderType::Bid => &self.bid_tree,
+        };
+        Some(tree.get_order(order_key)?.owner())
+    }
+
+    // Resting orders of `owner`, bids first, each side in price then time
+    // order. Only the owner's orders are visited.
+    pub fn open_orders(&self, owner: &str) -> Vec<OpenOrder> {
+        let Some(order_ids) = self.owner_index.get(owner) else {
+            return Vec::new();
+        };
+        let mut orders: Vec<(OrderType, &Order)> = order_ids
+            .iter()
+            .map(|order_id| {
+                let (order_type, order_key) = &self.order_id_map[order_id];
+                let tree = match order_type {
+                    OrderType::Ask => &self.ask_tree,
+                    OrderType::Bid => &self.bid_tree,
+                };
+                (*order_type, tree.get_order(order_key).unwrap())
+            })
+            .collect();
+        orders.sort_by_key(|(order_type, order)| {
+            (
+                *order_type == OrderType::Ask,
+                order.price(),
+                order.arrival_seq(),
+            )
+        });
+        orders
+            .into_iter()
+            .map(|(order_type, order)| OpenOrder::from_order(order, order_type))
+            .collect()
+    }
+
+    // Every resting order, bids then asks, each side best price first and
+    // in time priority within a price
+    pub fn l3_orders(&self) -> impl Iterator<Item = OpenOrder> + '_ {
+        [OrderType::Bid, OrderType::Ask]
+            .into_iter()
+            .flat_map(move |order_type| {
+                self.top_levels(order_type, usize::MAX)
+                    .flat_map(move |(_, level)| {
+                        level
+                            .iter()
+                            .map(move |(_, order)| OpenOrder::from_order(order, order_type))
+                    })
+            })
+    }
+
+    // Mirror of the resting orders of a book, ready to follow its events
+    // from `as_of_seq + 1` with apply_event. Nothing else about the book,
+    // such as its stats or phase, is carried over.
+    pub fn from_l3(l3_book: &L3Book) -> Result<OrderBook, OrderBookError> {
+        let mut book = OrderBook::new();
+        book.load_l3(l3_book)?;
+        Ok(book)
+    }
+
+    // Same as from_l3 but into this book, keeping its config and listeners.
+    // Only a book that has neither orders nor events can load a snapshot.
+    pub fn load_l3(&mut self, l3_book: &L3Book) -> Result<(), OrderBookError> {
+        if !self.order_id_map.is_empty() || self.event_feed.last_seq() != 0 {
+            return Err(OrderBookError::BookNotEmpty);
+        }
+        for open_order in &l3_book.orders {
+            let mut order = Order::with_id(
+                open_order.order_id,
+                open_order.owner.clone(),
+                open_order.price,
+                open_order.quantity,
+            );
+            order.set_expires_at(open_order.expires_at);
+            order.set_arrival(open_order.timestamp, open_order.arrival_seq);
+            self.next_arrival_seq = self.next_arrival_seq.max(open_order.arrival_seq + 1);
+            self.rest_order(order, open_order.order_type)?;
+        }
+        self.event_feed.continue_from(l3_book.as_of_seq);
+        Ok(())
+    }
+
+    pub fn view_book_l3(&self) -> L3Book {
+        L3Book {
+            as_of_seq: self.event_feed.last_seq(),
+            orders: self.l3_orders().collect(),
+        }
+    }
+
+    // Hashes every resting order in priority order, so the same orders
+    // queued differently make a different checksum. Walks the whole book, so
+    // it's computed on demand rather than kept up to date.
+    pub fn checksum(&self) -> BookChecksum {
+        let as_of_seq = self.event_feed.last_seq();
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&as_of_seq.to_le_bytes());
+        for order in self.l3_orders() {
+            hasher.update(order.order_id.as_bytes());
+            hasher.update(&(order.owner.len() as u64).to_le_bytes());
+            hasher.update(order.owner.as_bytes());
+            hasher.update(&[order.order_type as u8]);
+            hasher.update(&order.price.to_le_bytes());
+            hasher.update(&order.quantity.to_le_bytes());
+            hasher.update(&[order.expires_at.is_some() as u8]);
+            hasher.update(&order.expires_at.unwrap_or(0).to_le_bytes());
+            hasher.update(&order.timestamp.to_le_bytes());
+            hasher.update(&order.arrival_seq.to_le_bytes());
+        }
+        BookChecksum {
+            as_of_seq,
+            checksum: hasher.finalize(),
+        }
+    }
+
+    // Writes every resting order out without collecting them first, for
+    // dumping deep books
+    pub fn export(&self, format: ExportFormat, writer: impl Write) -> anyhow::Result<()> {
+        export::write_orders(self.l3_orders(), format, writer)
+    }
+
+    // Order an owner placed this session under the client order id
+    pub fn client_order(&self, owner: &str, client_order_id: &str) -> Option<Uuid> {
+        self.client_order_ids
+            .get(&(owner.to_string(), client_order_id.to_string()))
+            .copied()
+    }
+
+    pub fn record_client_order(&mut self, owner: &str, client_order_id: &str, order_id: Uuid) {
+        self.client_order_ids
+            .insert((owner.to_string(), client_order_id.to_string()), order_id);
+    }
+
+    // Cancels the order an owner placed this session under the client order
+    // id and returns the quantity that was still resting
+    pub fn cancel_client_order(
+        &mut self,
+        owner: &str,
+        client_order_id: &str,
+    ) -> Result<u64, OrderBookError> {
+        let order_id = self
+            .client_order(owner, client_order_id)
+            .ok_or(OrderBookError::UnknownOrder)?;
+        self.cancel_order(order_id)
+    }
+
+    pub fn session_stats(&self) -> &SessionStats {
+        &self.session_stats
+    }
+
+    // Last price and the high, low, volume and price change over the last 24
+    // hours as of the book's clock
+    pub fn market_stats(&mut self) -> MarketStats {
+        let now = self.now();
+        self.market_stats.stats(now)
+    }
+
+    // Time as the book sees it, in unix milliseconds
+    pub fn now(&self) -> u64 {
+        self.clock.now_millis()
+    }
+
+    // Replaces price-time priority for orders matched from now on
+    pub fn set_matching_engine(&mut self, matching_engine: Box<dyn MatchingEngine>) {
+        self.matching_engine = matching_engine;
+    }
+
+    // Latency of the book's operations, recorded as they run
+    pub fn latency(&self) -> &Arc<LatencyMetrics> {
+        &self.latency
+    }
+
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn set_trade_tape(&mut self, trade_tape: TradeTape) {
+        self.trade_tape = trade_tape;
+    }
+
+    // Sequence number the next trade will get
+    pub fn next_trade_seq(&self) -> u64 {
+        self.trade_tape.next_seq()
+    }
+
+    pub fn get_trades(&self, from_seq: u64, limit: usize) -> anyhow::Result<Vec<Trade>> {
+        self.trade_tape.get_trades(from_seq, limit)
+    }
+
+    pub fn set_candle_aggregator(&mut self, candles: CandleAggregator) {
+        self.candles = candles;
+    }
+
+    pub fn query_trades(&self, page: &PageRequest) -> anyhow::Result<Page<Trade>> {
+        self.trade_tape.query(page)
+    }
+
+    // Open orders of `owner` in arrival order, a page at a time
+    pub fn query_open_orders(
+        &self,
+        owner: &str,
+        page: &PageRequest,
+    ) -> anyhow::Result<Page<OpenOrder>> {
+        let mut open_orders = self.open_orders(owner);
+        open_orders.sort_by_key(|open_order| open_order.arrival_seq);
+        query::paginate(open_orders.into_iter().map(Ok), page)
+    }
+
+    pub fn query_candles(
+        &self,
+        interval_ms: u64,
+        page: &PageRequest,
+    ) -> anyhow::Result<Page<Candle>> {
+        self.candles.query(interval_ms, page)
+    }
+
+    pub fn clearing_house(&self) -> &ClearingHouse {
+        &self.clearing_house
+    }
+
+    pub fn clearing_house_mut(&mut self) -> &mut ClearingHouse {
+        &mut self.clearing_house
+    }
+
+    // Owner's cash, less what their resting bids reserve
+    pub fn balance(&self, owner: &str) -> Balance {
+        self.clearing_house
+            .balance(owner, self.open_exposure.reserved_funds(owner))
+    }
+
+    // Applies the action to the ledger at the book's time, refusing to take
+    // out cash that resting bids reserve
+    pub fn apply_account_action(
+        &mut self,
+        action: AccountAction,
+        memo: &str,
+    ) -> anyhow::Result<u64> {
+        let reserved = action
+            .debited()
+            .map_or(0, |owner| self.open_exposure.reserved_funds(owner));
+        let now = self.now();
+        self.clearing_house
+            .apply_account_action_with_reserved(action, memo, now, reserved)
+    }
+
+    pub fn reference_price(&self) -> Option<u32> {
+        self.reference_price
+    }
+
+    pub fn set_reference_price(&mut self, price: u32) {
+        self.reference_price = Some(price);
+        // Breaks ties between uncross prices
+        self.refresh_indicative();
+    }
+
+    pub fn mark_price(&self) -> Option<u32> {
+        self.mark_price
+    }
+
+    // Takes the latest mark price from the instrument's external price feed
+    pub fn set_mark_price(&mut self, price: u32) {
+        if self.mark_price != Some(price) {
+            self.mark_price = Some(price);
+            self.event_feed.publish(BookEvent::MarkPriceSet(price));
+        }
+    }
+
+    // Price the price band, volatility interruption and collar are around,
+    // as the risk config has it. Falls back to the reference price until a
+    // mark price arrives.
+    pub fn band_reference_price(&self) -> Option<u32> {
+        match self.risk_config.band_reference {
+            BandReference::LastTrade => self.reference_price,
+            BandReference::MarkPrice => self.mark_price.or(self.reference_price),
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn instrument_status(&self) -> Option<InstrumentStatus> {
+        self.instrument_status
+    }
+
+    // Only records the status, which the instrument brings the book in line
+    // with, see Instrument::transition
+    pub fn set_instrument_status(&mut self, status: InstrumentStatus) {
+        self.instrument_status = Some(status);
+    }
+
+    // Quantity of the owner's resting orders on each side
+    pub fn open_exposure(&self, owner: &str) -> OpenExposure {
+        self.open_exposure.exposure(owner)
+    }
+
+    // Owner's equity against the margin their position and open orders
+    // need, None when the book doesn't margin orders
+    pub fn margin_status(&self, owner: &str) -> Option<MarginStatus> {
+        let config = self.risk_config.margin?;
+        Some(risk::margin_status(
+            &config,
+            self,
+            owner,
+            self.open_exposure(owner),
+            None,
+        ))
+    }
+
+    pub fn risk_config(&self) -> &RiskConfig {
+        &self.risk_config
+    }
+
+    // Replaces the checks relaxed for a participant. An empty config removes
+    // the participant's bypasses.
+    pub fn set_participant_risk(&mut self, owner: &str, config: ParticipantRiskConfig) {
+        if config.bypass.is_empty() {
+            self.risk_config.participants.remove(owner);
+        } else {
+            self.risk_config
+                .participants
+                .insert(owner.to_string(), config);
+        }
+    }
+
+    pub fn halt(&mut self) {
+        self.halted = true;
+        self.event_feed.publish(BookEvent::Halted);
+    }
+
+    pub fn resume(&mut self) {
+        self.halted = false;
+        self.event_feed.publish(BookEvent::Resumed);
+    }
+
+    // Sequence number of the latest event applied to the book
+    pub fn last_event_seq(&self) -> u64 {
+        self.event_feed.last_seq()
+    }
+
+    pub fn events_since(&self, from_seq: u64, limit: usize) -> anyhow::Result<Vec<SequencedEvent>> {
+        self.event_feed.events_since(from_seq, limit)
+    }
+
+    // Applies an event from a primary's feed to a replica. Replicas do not
+    // match or clear; they only mirror the primary's book and trade tape.
+    pub fn apply_event(&mut self, event: SequencedEvent) -> anyhow::Result<()> {
+        if event.seq != self.event_feed.last_seq() + 1 {
+            return Err(anyhow!(
+                "Expected event {} but received {}",
+                self.event_feed.last_seq() + 1,
+                event.seq
+            ));
+        }
+        match &event.event {
+            BookEvent::OrderRested {
+                order_id,
+                owner,
+                order_type,
+                price,
+                quantity,
+                expires_at,
+                timestamp,
+                arrival_seq,
+            } => {
+                let mut order = Order::with_id(*order_id, owner.clone(), *price, *quantity);
+                order.set_expires_at(*expires_at);
+                order.set_arrival(*timestamp, *arrival_seq);
+                self.next_arrival_seq = self.next_arrival_seq.max(arrival_seq + 1);
+                self.rest_order(order, *order_type)?;
+            }
+            BookEvent::OrderReduced { order_id, quantity } => {
+                self.update_resting_quantity(*order_id, *quantity)?;
+            }
+            BookEvent::OrderRemoved { order_id, status } => {
+                self.detach_order(*order_id, *status == OrderStatus::Filled)?;
+                // Replicas only see the rested quantity, so take the status as published
+                self.closed_orders.insert(*order_id, *status);
+            }
+            BookEvent::Traded(trade) => {
+                self.session_stats.record_trade(trade.price, trade.quantity);
+                self.candles.record_trade(trade);
+                self.market_stats.record_trade(trade);
+                let trade = self.trade_tape.record(trade.clone()).clone();
+                if let Err(err) = self.trade_tape.persist() {
+                    self.report_error(err.context("Failed to spill trades to disk"));
+                }
+                for listener in &mut self.listeners {
+                    listener.on_trade(&trade);
+                }
+                self.reference_price = Some(trade.price);
+            }
+            BookEvent::Halted => self.halted = true,
+            BookEvent::Resumed => self.halted = false,
+            BookEvent::PhaseChanged(phase) => {
+                self.phase = *phase;
+                self.refresh_indicative();
+            }
+            BookEvent::SessionRolled { settlement_price } => self.roll_session(*settlement_price),
+            BookEvent::MarkPriceSet(price) => self.mark_price = Some(*price),
+        }
+        self.event_feed.append(event)
+    }
+}
+
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clearing::FeeAccrual;
+    use crate::clock::{unix_millis, ManualClock};
+    use crate::fees::FeeTier;
+    use crate::ledger::FEE_ACCOUNT;
+    use crate::listener::BookListener;
+    use crate::matching::MatchingAlgorithm;
+    use crate::query::MAX_PAGE_LIMIT;
+    use crate::settlement::SettlementPriceSource;
+    use std::sync::{Arc, Mutex};
+
+    // Records every callback as a line of text
+    struct RecordingListener(Arc<Mutex<Vec<String>>>);
+
+    impl BookListener for RecordingListener {
+        fn on_order_accepted(
+            &mut self,
+            _order_id: Uuid,
+            owner: &str,
+            order_type: OrderType,
+            price: u32,
+            quantity: u64,
+        ) {
+            let line = format!("accepted {owner} {order_type:?} {quantity}@{price}");
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn on_trade(&mut self, trade: &Trade) {
+            let line = format!("trade {}@{}", trade.quantity, trade.price);
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn on_order_canceled(&mut self, _order_id: Uuid) {
+            self.0.lock().unwrap().push("canceled".to_string());
+        }
+
+        fn on_level_change(
+            &mut self,
+            side: OrderType,
+            price: u32,
+            total_quantity: u64,
+            num_orders: usize,
+        ) {
+            let line = format!("level {side:?} {price} {total_quantity}/{num_orders}");
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn on_indicative_uncross(&mut self, uncross: Option<AuctionUncross>) {
+            let line = match uncross {
+                Some(uncross) => format!("indicative {}@{}", uncross.volume, uncross.price),
+                None => "indicative none".to_string(),
+            };
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn on_error(&mut self, err: &anyhow::Error) {
+            self.0.lock().unwrap().push(format!("error {err}"));
+        }
+    }
+    use crate::risk::{MarginConfig, PriceBand, SelfMatchPrevention, VolatilityInterruption};
+
+    #[test]
+    fn test_session_stats_track_fills() {
+        let mut book = OrderBook::new();
+        book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        book.place_order("alice", 105, 10, OrderType::Ask).unwrap();
+        book.place_order("alice", 105, 15, OrderType::Bid).unwrap();
+
+        let stats = book.session_stats();
+        assert_eq!(stats.open(), Some(100));
+        assert_eq!(stats.high(), Some(105));
+        assert_eq!(stats.low(), Some(100));
+        assert_eq!(stats.last(), Some(105));
+        assert_eq!(stats.volume(), 15);
+        assert_eq!(stats.trade_count(), 2);
+
+        // Rolling 24 hour stats see the same trades
+        let market_stats = book.market_stats();
+        assert_eq!(
+            (market_stats.open, market_stats.high),
+            (Some(100), Some(105))
+        );
+        assert_eq!(market_stats.volume, 15);
+        assert_eq!(market_stats.price_change, Some(5));
+    }
+
+    #[test]
+    fn test_reserved_book_trades_as_usual() {
+        let mut book = OrderBook::new();
+        book.reserve(BookCapacity {
+            levels: 64,
+            orders: 1_000,
+            orders_per_level: 8,
+        });
+        assert!(book.bid_tree.capacity() >= 64);
+        assert!(book.ask_tree.capacity() >= 64);
+        for price in 100..110 {
+            book.place_order("alice", price, 5, OrderType::Ask).unwrap();
+        }
+        book.place_order("bob", 104, 22, OrderType::Bid).unwrap();
+        let l2_book = book.view_book_l2();
+        assert_eq!(l2_book.ask[0].price, 104);
+        assert_eq!(l2_book.ask[0].total_quantity, 3);
+
+        book.cancel_all(&CancelFilter::All);
+        book.shrink_to_fit();
+        assert!(book.ask_tree.capacity() < 64);
+        book.place_order("alice", 100, 5, OrderType::Ask).unwrap();
+        assert_eq!(book.view_book_l1().ask.unwrap().total_quantity, 5);
+    }
+
+    #[test]
+    fn test_ladder_book_trades_as_usual() {
+        let mut book = OrderBook::new();
+        let ladder = PriceLevelIndex::Ladder {
+            min_price: 90,
+            max_price: 110,
+            tick_size: 1,
+        };
+        book.set_price_level_index(ladder).unwrap();
+        assert!(book.place_order("alice", 111, 5, OrderType::Ask).is_err());
+        for price in [100, 101, 103] {
+            book.place_order("alice", price, 5, OrderType::Ask).unwrap();
+        }
+        book.place_order("bob", 95, 5, OrderType::Bid).unwrap();
+        assert!(book.set_price_level_index(PriceLevelIndex::Tree).is_err());
+
+        book.place_order("bob", 102, 12, OrderType::Bid).unwrap();
+        let l2_book = book.view_book_l2();
+        assert_eq!(l2_book.ask[0].price, 103);
+        // Both sides in ascending price order
+        assert_eq!(l2_book.bid[0].price, 95);
+        assert_eq!(l2_book.bid[1].price, 102);
+        assert_eq!(l2_book.bid[1].total_quantity, 2);
+        assert_eq!(book.liquidity_within(90..=110, OrderType::Bid), 7);
+
+        book.cancel_all(&CancelFilter::All);
+        book.set_price_level_index(PriceLevelIndex::Tree).unwrap();
+        book.place_order("alice", 111, 5, OrderType::Ask).unwrap();
+    }
+
+    #[test]
+    fn test_view_book_l1() {
+        let mut book = OrderBook::new();
+        book.place_order("alice", 90, 4, OrderType::Bid).unwrap();
+        book.place_order("alice", 95, 6, OrderType::Bid).unwrap();
+        book.place_order("alice", 110, 3, OrderType::Ask).unwrap();
+
+        let l1_book = book.view_book_l1();
+        assert_eq!(l1_book.bid.unwrap().price, 95);
+        assert_eq!(l1_book.ask.unwrap().price, 110);
+        assert_eq!(l1_book.stats.trade_count(), 0);
+    }
+
+    #[test]
+    fn test_liquidity_within() {
+        let mut book = OrderBook::new();
+        book.place_order("alice", 90, 4, OrderType::Bid).unwrap();
+        book.place_order("alice", 95, 6, OrderType::Bid).unwrap();
+        book.place_order("alice", 95, 1, OrderType::Bid).unwrap();
+        book.place_order("alice", 110, 3, OrderType::Ask).unwrap();
+
+        assert_eq!(book.liquidity_within(90..=95, OrderType::Bid), 11);
+        assert_eq!(book.liquidity_within(91..=100, OrderType::Bid), 7);
+        assert_eq!(book.liquidity_within(90..=100, OrderType::Ask), 0);
+        assert_eq!(book.liquidity_within(100..=110, OrderType::Ask), 3);
+    }
+
+    #[test]
+    fn test_view_book_l2_depth() {
+        let mut book = OrderBook::new();
+        for price in [90, 95, 100] {
+            book.place_order("alice", price, 1, OrderType::Bid).unwrap();
+            book.place_order("alice", price + 20, 1, OrderType::Ask)
+                .unwrap();
+        }
+
+        let l2_book = book.view_book_l2_depth(2);
+        let bid_prices: Vec<u32> = l2_book.bid.iter().map(|entry| entry.price).collect();
+        let ask_prices: Vec<u32> = l2_book.ask.iter().map(|entry| entry.price).collect();
+        assert_eq!(bid_prices, [95, 100]);
+        assert_eq!(ask_prices, [110, 115]);
+    }
+
+    #[test]
+    fn test_replica_mirrors_primary() {
+        let mut primary = OrderBook::new();
+        primary
+            .place_order("alice", 100, 10, OrderType::Ask)
+            .unwrap();
+        let order_id = primary
+            .place_order("alice", 101, 5, OrderType::Ask)
+            .unwrap();
+        primary.place_order("bob", 100, 4, OrderType::Bid).unwrap();
+        primary.cancel_order(order_id).unwrap();
+        primary.halt();
+        primary.resume();
+        primary.start_auction().unwrap();
+        primary.place_order("bob", 102, 8, OrderType::Bid).unwrap();
+        primary.uncross().unwrap();
+
+        let mut replica = OrderBook::new();
+        for event in primary.events_since(1, usize::MAX).unwrap() {
+            replica.apply_event(event).unwrap();
+        }
+
+        assert_eq!(replica.last_event_seq(), primary.last_event_seq());
+        assert_eq!(
+            format!("{:?}", replica.view_book_l2()),
+            format!("{:?}", primary.view_book_l2())
+        );
+        assert_eq!(
+            format!("{:?}", replica.view_book_l1()),
+            format!("{:?}", primary.view_book_l1())
+        );
+        assert_eq!(
+            replica.get_trades(1, 10).unwrap(),
+            primary.get_trades(1, 10).unwrap()
+        );
+        assert_eq!(replica.session_stats(), primary.session_stats());
+    }
+
+    #[test]
+    fn test_checksum_catches_divergence() {
+        let mut primary = OrderBook::new();
+        primary
+            .place_order("alice", 100, 10, OrderType::Ask)
+            .unwrap();
+        primary
+            .place_order("carol", 100, 6, OrderType::Ask)
+            .unwrap();
+        primary.place_order("bob", 100, 4, OrderType::Bid).unwrap();
+        let mut replica = OrderBook::new();
+        for event in primary.events_since(1, usize::MAX).unwrap() {
+            replica.apply_event(event).unwrap();
+        }
+        assert_eq!(replica.checksum(), primary.checksum());
+        assert_eq!(primary.checksum().as_of_seq, primary.last_event_seq());
+
+        // Same orders as of the same event, but one quantity is off
+        let mut l3_book = primary.view_book_l3();
+        l3_book.orders[0].quantity -= 1;
+        let diverged = OrderBook::from_l3(&l3_book).unwrap();
+        assert_eq!(diverged.checksum().as_of_seq, primary.checksum().as_of_seq);
+        assert_ne!(diverged.checksum(), primary.checksum());
+
+        // Or the queue is in another order
+        let mut l3_book = primary.view_book_l3();
+        l3_book.orders.reverse();
+        let reordered = OrderBook::from_l3(&l3_book).unwrap();
+        assert_ne!(reordered.checksum(), primary.checksum());
+    }
+
+    #[test]
+    fn test_trades_build_candles() {
+        let mut book = OrderBook::new();
+        book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        book.place_order("alice", 101, 10, OrderType::Ask).unwrap();
+        book.place_order("bob", 101, 15, OrderType::Bid).unwrap();
+
+        // Both trades happen within the same minute
+        let candles = book
+            .query_candles(60_000, &PageRequest::default())
+            .unwrap()
+            .items;
+        assert_eq!(candles.len(), 1);
+        assert_eq!((candles[0].open, candles[0].close), (100, 101));
+        assert_eq!(candles[0].volume, 15);
+    }
+
+    #[test]
+    fn test_sweep_removes_whole_levels() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut book = OrderBook::new();
+        let mut swept = Vec::new();
+        for price in [100, 101] {
+            for owner in ["alice", "carol"] {
+                swept.push(book.place_order(owner, price, 3, OrderType::Ask).unwrap());
+            }
+        }
+        let rested = book.place_order("alice", 102, 4, OrderType::Ask).unwrap();
+        book.add_listener(Box::new(RecordingListener(events.clone())));
+
+        book.place_order("bob", 102, 14, OrderType::Bid).unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "accepted bob Bid 14@102",
+                "trade 3@100",
+                "trade 3@100",
+                "trade 3@101",
+                "trade 3@101",
+                "trade 2@102",
+                "level Ask 100 0/0",
+                "level Ask 101 0/0",
+                "level Ask 102 2/1",
+            ]
+        );
+        for order_id in swept {
+            assert_eq!(book.order_status(order_id), Some(OrderStatus::Filled));
+        }
+        assert!(book.open_orders("carol").is_empty());
+        assert_eq!(book.open_orders("alice")[0].order_id, rested);
+        assert_eq!(book.view_book_l1().ask.unwrap().price, 102);
+        assert_eq!(book.check_conservation().unwrap(), 6);
+    }
+
+    #[test]
+    fn test_pro_rata_matching() {
+        let mut book = OrderBook::new();
+        book.set_matching_engine(MatchingAlgorithm::ProRata.engine());
+        let small = book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        let large = book.place_order("carol", 100, 30, OrderType::Ask).unwrap();
+        let next_level = book.place_order("dave", 101, 5, OrderType::Ask).unwrap();
+
+        // Price-time priority would fill alice's order alone
+        book.place_order("bob", 101, 8, OrderType::Bid).unwrap();
+        assert_eq!(book.execution_report(small).unwrap().filled_quantity, 2);
+        assert_eq!(book.execution_report(large).unwrap().filled_quantity, 6);
+        assert_eq!(
+            book.execution_report(next_level).unwrap().filled_quantity,
+            0
+        );
+
+        // Sweeps the level and moves on to the next price
+        book.place_order("bob", 101, 35, OrderType::Bid).unwrap();
+        assert_eq!(book.order_status(small), Some(OrderStatus::Filled));
+        assert_eq!(book.order_status(large), Some(OrderStatus::Filled));
+        assert_eq!(
+            book.execution_report(next_level).unwrap().filled_quantity,
+            3
+        );
+        assert_eq!(book.check_conservation().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_listener_callbacks() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut book = OrderBook::new();
+        book.add_listener(Box::new(RecordingListener(events.clone())));
+
+        book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        let order_id = book.place_order("alice", 101, 5, OrderType::Ask).unwrap();
+        book.place_order("bob", 100, 4, OrderType::Bid).unwrap();
+        book.cancel_order(order_id).unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "accepted alice Ask 10@100",
+                "level Ask 100 10/1",
+                "accepted alice Ask 5@101",
+                "level Ask 101 5/1",
+                "accepted bob Bid 4@100",
+                "trade 4@100",
+                "level Ask 100 6/1",
+                "level Ask 101 0/0",
+                "canceled",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_spill_is_reported_to_listeners() {
+        let segment_dir =
+            std::env::temp_dir().join(format!("order_book_spill_error_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&segment_dir).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut book = OrderBook::new();
+        book.set_trade_tape(TradeTape::open(1, segment_dir.clone()).unwrap());
+        std::fs::remove_dir_all(&segment_dir).unwrap();
+        book.add_listener(Box::new(RecordingListener(events.clone())));
+
+        book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        book.place_order("bob", 100, 2, OrderType::Bid).unwrap();
+        book.place_order("bob", 100, 2, OrderType::Bid).unwrap();
+
+        // Both trades stand and stay in memory
+        let events = events.lock().unwrap();
+        assert!(events.contains(&"error Failed to spill trades to disk".to_string()));
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| *event == "trade 2@100")
+                .count(),
+            2
+        );
+        assert_eq!(book.get_trades(1, 10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_end_of_day_settles_and_rolls() {
+        let mut book = OrderBook::new();
+        let now = unix_millis();
+        book.place_order("alice", 100, 4, OrderType::Ask).unwrap();
+        book.place_order("bob", 100, 4, OrderType::Bid).unwrap();
+        book.place_order("carol", 104, 2, OrderType::Ask).unwrap();
+        book.place_order("bob", 104, 2, OrderType::Bid).unwrap();
+        let day_order_id = book.place_order("alice", 106, 6, OrderType::Ask).unwrap();
+        let gtd_order_id = book
+            .place_order_with_expiry("dave", 90, 1, OrderType::Bid, Some(now + 60_000))
+            .unwrap();
+
+        let report = book
+            .end_of_day(unix_millis(), &EndOfDayOptions::default())
+            .unwrap();
+        // (100 * 4 + 104 * 2) / 6 rounds to 101
+        assert_eq!(report.settlement_price, Some(101));
+        assert_eq!(report.price_source, Some(SettlementPriceSource::Vwap));
+        assert_eq!(report.session.trade_count(), 2);
+        assert_eq!(report.expired_orders, vec![day_order_id]);
+        let positions: Vec<_> = report
+            .positions
+            .iter()
+            .map(|line| {
+                (
+                    line.owner.as_str(),
+                    line.position,
+                    line.cash,
+                    line.mark_value,
+                )
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                ("alice", -4, 400, Some(-404)),
+                ("bob", 6, -608, Some(606)),
+                ("carol", -2, 208, Some(-202)),
+            ]
+        );
+
+        // The next session starts straight away from the settlement price
+        assert!(!book.is_halted());
+        assert_eq!(book.session_stats().trade_count(), 0);
+        assert_eq!(book.reference_price(), Some(101));
+        assert!(matches!(
+            book.order_status(gtd_order_id),
+            Some(OrderStatus::Resting { .. })
+        ));
+        assert_eq!(book.order_account(day_order_id).unwrap().expired, 6);
+
+        // A quiet session carries the settlement price over
+        let report = book
+            .end_of_day(
+                unix_millis(),
+                &EndOfDayOptions {
+                    roll: false,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(report.price_source, Some(SettlementPriceSource::Previous));
+        assert_eq!(report.settlement_price, Some(101));
+        assert!(book.is_halted());
+        assert_eq!(book.clearing_house().settlements().len(), 2);
+
+        let mut replica = OrderBook::new();
+        for event in book.events_since(1, usize::MAX).unwrap() {
+            replica.apply_event(event).unwrap();
+        }
+        assert_eq!(replica.session_stats(), book.session_stats());
+        assert_eq!(replica.reference_price(), Some(101));
+        assert!(replica.is_halted());
+    }
+
+    #[test]
+    fn test_replica_rejects_out_of_order_events() {
+        let mut primary = OrderBook::new();
+        primary
+            .place_order("alice", 100, 10, OrderType::Ask)
+            .unwrap();
+        primary
+            .place_order("alice", 101, 10, OrderType::Ask)
+            .unwrap();
+
+        let mut replica = OrderBook::new();
+        let events = primary.events_since(1, 10).unwrap();
+        assert!(replica.apply_event(events[1].clone()).is_err());
+        replica.apply_event(events[0].clone()).unwrap();
+        assert_eq!(replica.last_event_seq(), 1);
+    }
+
+    #[test]
+    fn test_price_band_rejects_order() {
+        let mut book = OrderBook::with_risk_config(RiskConfig {
+            price_band: Some(PriceBand::new(1_000, false)),
+            ..Default::default()
+        });
+        book.set_reference_price(100);
+        book.place_order("alice", 105, 10, OrderType::Ask).unwrap();
+        book.place_order("alice", 120, 10, OrderType::Ask).unwrap();
+
+        // Sweeping into the 120 level breaches the 10% band
+        assert!(book.place_order("alice", 120, 15, OrderType::Bid).is_err());
+        assert!(!book.is_halted());
+        assert_eq!(book.session_stats().trade_count(), 0);
+
+        // Trading within the band is allowed and moves the reference price
+        book.place_order("alice", 105, 10, OrderType::Bid).unwrap();
+        assert_eq!(book.reference_price(), Some(105));
+    }
+
+    #[test]
+    fn test_price_band_rejection_leaves_no_state() {
+        let mut book = OrderBook::with_risk_config(RiskConfig {
+            price_band: Some(PriceBand::new(1_000, false)),
+            duplicate_window_ms: Some(60_000),
+            ..Default::default()
+        });
+        book.set_reference_price(100);
+        let first = book.place_order("bob", 120, 10, OrderType::Ask).unwrap();
+        assert!(book.place_order("alice", 120, 5, OrderType::Bid).is_err());
+
+        // The breaching order wasn't seen by the duplicate check and took no
+        // arrival sequence number
+        book.set_reference_price(118);
+        book.place_order("alice", 120, 5, OrderType::Bid).unwrap();
+        let second = book.place_order("bob", 121, 10, OrderType::Ask).unwrap();
+        let arrival_seq = |order_id| match book.order_status(order_id) {
+            Some(OrderStatus::Resting { arrival_seq, .. }) => arrival_seq,
+            status => panic!("Unexpected status {status:?}"),
+        };
+        assert_eq!(arrival_seq(second), arrival_seq(first) + 2);
+    }
+
+    #[test]
+    fn test_max_notional_bypass_is_audited() {
+        let mut book = OrderBook::with_risk_config(RiskConfig {
+            max_notional: Some(1_000),
+            ..Default::default()
+        });
+        book.set_participant_risk(
+            "market_maker",
+            ParticipantRiskConfig {
+                bypass: HashSet::from([RiskCheckKind::MaxNotional]),
+                ..Default::default()
+            },
+        );
+
+        assert!(book.place_order("alice", 100, 11, OrderType::Bid).is_err());
+        // Orders within the cap are not recorded as bypasses
+        book.place_order("market_maker", 100, 10, OrderType::Bid)
+            .unwrap();
+        assert!(book.clearing_house().audit_log().is_empty());
+
+        let order_id = book
+            .place_order("market_maker", 100, 11, OrderType::Bid)
+            .unwrap();
+        let audit_log = book.clearing_house().audit_log();
+        assert_eq!(audit_log.len(), 1);
+        assert_eq!(
+            audit_log[0].event,
+            AuditEvent::RiskBypass {
+                owner: "market_maker".to_string(),
+                order_id,
+                check: RiskCheckKind::MaxNotional,
+            }
+        );
+
+        // Removing the bypass restores the check
+        book.set_participant_risk("market_maker", ParticipantRiskConfig::default());
+        assert!(book
+            .place_order("market_maker", 100, 11, OrderType::Bid)
+            .is_err());
+    }
+
+    #[test]
+    fn test_price_band_bypass_does_not_halt() {
+        let mut book = OrderBook::with_risk_config(RiskConfig {
+            price_band: Some(PriceBand::new(1_000, true)),
+            ..Default::default()
+        });
+        book.set_participant_risk(
+            "market_maker",
+            ParticipantRiskConfig {
+                bypass: HashSet::from([RiskCheckKind::PriceBand]),
+                ..Default::default()
+            },
+        );
+        book.set_reference_price(100);
+        book.place_order("alice", 150, 10, OrderType::Ask).unwrap();
+
+        book.place_order("market_maker", 150, 10, OrderType::Bid)
+            .unwrap();
+        assert!(!book.is_halted());
+        assert_eq!(book.session_stats().trade_count(), 1);
+        assert_eq!(book.clearing_house().audit_log().len(), 1);
+    }
+
+    #[test]
+    fn test_resting_bids_reserve_funds() {
+        let mut book = OrderBook::with_risk_config(RiskConfig {
+            require_funds: true,
+            ..Default::default()
+        });
+        let deposit = AccountAction::Deposit {
+            owner: "alice".to_string(),
+            amount: 1_000,
+        };
+        book.apply_account_action(deposit, "").unwrap();
+        let bid_id = book.place_order("alice", 100, 5, OrderType::Bid).unwrap();
+        assert_eq!(book.balance("alice").available, 500);
+        assert_eq!(
+            book.place_order("alice", 100, 6, OrderType::Bid),
+            Err(OrderBookError::RiskRejected(
+                RiskRejection::InsufficientFunds {
+                    required: 600,
+                    available: 500,
+                }
+            ))
+        );
+        let withdraw = |amount| AccountAction::Withdraw {
+            owner: "alice".to_string(),
+            amount,
+        };
+        assert!(book.apply_account_action(withdraw(600), "").is_err());
+
+        // Fill pays out of the reserved cash
+        book.place_order("bob", 100, 2, OrderType::Ask).unwrap();
+        let balance = book.balance("alice");
+        assert_eq!((balance.cash, balance.reserved), (800, 300));
+
+        book.cancel_order(bid_id).unwrap();
+        assert_eq!(book.balance("alice").available, 800);
+        book.apply_account_action(withdraw(800), "").unwrap();
+    }
+
+    #[test]
+    fn test_margin_checked_against_equity_at_mark() {
+        let mut book = OrderBook::with_risk_config(RiskConfig {
+            margin: Some(MarginConfig::new(1_000)),
+            ..Default::default()
+        });
+        assert!(OrderBook::new().margin_status("alice").is_none());
+        for (owner, amount) in [("alice", 1_000), ("bob", 10_000)] {
+            let deposit = AccountAction::Deposit {
+                owner: owner.to_string(),
+                amount,
+            };
+            book.apply_account_action(deposit, "").unwrap();
+        }
+
+        // 10% of 100 @ 100 uses up all of alice's equity
+        book.place_order("alice", 100, 100, OrderType::Bid).unwrap();
+        assert_eq!(
+            book.place_order("alice", 100, 1, OrderType::Bid),
+            Err(OrderBookError::RiskRejected(
+                RiskRejection::InsufficientMargin {
+                    required: 1_010,
+                    equity: 1_000,
+                }
+            ))
+        );
+        book.place_order("bob", 100, 100, OrderType::Ask).unwrap();
+
+        // Marked down, the position is worth less than the margin it needs
+        book.set_mark_price(95);
+        let status = book.margin_status("alice").unwrap();
+        assert_eq!(status.mark_price, Some(95));
+        assert_eq!((status.equity, status.required_margin), (500, 950));
+        assert_eq!(status.excess, -450);
+        assert!(book.place_order("alice", 95, 1, OrderType::Bid).is_err());
+        // Reducing the position still goes through
+        book.place_order("alice", 100, 50, OrderType::Ask).unwrap();
+    }
+    #[test]
+    fn test_price_band_halts_book() {
+        let mut book = OrderBook::with_risk_config(RiskConfig {
+            price_band: Some(PriceBand::new(1_000, true)),
+            ..Default::default()
+        });
+        book.set_reference_price(100);
+        book.place_order("alice", 150, 10, OrderType::Ask).unwrap();
+
+        assert!(book.place_order("alice", 150, 10, OrderType::Bid).is_err());
+        assert!(book.is_halted());
+        assert!(book.place_order("alice", 100, 10, OrderType::Bid).is_err());
+
+        book.resume();
+        book.place_order("alice", 100, 10, OrderType::Bid).unwrap();
+    }
+
+    #[test]
+    fn test_settlement_needs_the_window_trades_kept() {
+        let clock = ManualClock::new(1_000);
+        let mut book = OrderBook::new();
+        book.set_clock(Box::new(clock.clone()));
+        book.set_trade_tape(TradeTape::in_memory(4));
+        let trade = |book: &mut OrderBook, price| {
+            book.place_order("alice", price, 1, OrderType::Ask).unwrap();
+            book.place_order("bob", price, 1, OrderType::Bid).unwrap();
+        };
+        for _ in 0..6 {
+            trade(&mut book, 100);
+        }
+        let options = EndOfDayOptions {
+            settlement_window_ms: 1_000,
+            ..Default::default()
+        };
+        // The tape dropped trades from the window
+        assert!(book.end_of_day(1_500, &options).is_err());
+
+        // Only trades from before the window were dropped
+        clock.set(100_000);
+        trade(&mut book, 104);
+        let report = book.end_of_day(100_000, &options).unwrap();
+        assert_eq!(report.settlement_price, Some(104));
+    }
+
+    #[test]
+    fn test_volatility_interruption_switches_to_auction() {
+        let clock = ManualClock::new(1_000);
+        let mut book = OrderBook::with_risk_config(RiskConfig {
+            volatility_interruption: Some(VolatilityInterruption::new(500, 2_000)),
+            ..Default::default()
+        });
+        book.set_clock(Box::new(clock.clone()));
+        book.set_reference_price(100);
+        book.place_order("alice", 103, 10, OrderType::Ask).unwrap();
+        book.place_order("alice", 110, 10, OrderType::Ask).unwrap();
+
+        // Trades inside the band as usual
+        book.place_order("bob", 103, 4, OrderType::Bid).unwrap();
+        assert_eq!(book.session_stats().volume(), 4);
+
+        // Sweeping up to 110 would leave it, so the order rests for the auction
+        let bid_id = book.place_order("bob", 110, 10, OrderType::Bid).unwrap();
+        assert_eq!(book.session_stats().volume(), 4);
+        assert_eq!(book.phase(), TradingPhase::Auction);
+        assert_eq!(book.volatility_auction_ends_at(), Some(3_000));
+        assert_eq!(book.indicative_uncross().unwrap().volume, 10);
+
+        assert_eq!(book.end_volatility_auction(2_999), None);
+        let uncross = book.end_volatility_auction(3_000).unwrap();
+        assert_eq!(uncross.volume, 10);
+        assert_eq!(book.session_stats().volume(), 14);
+        assert_eq!(book.phase(), TradingPhase::Continuous);
+        assert_eq!(book.volatility_auction_ends_at(), None);
+        assert_eq!(book.order_status(bid_id), Some(OrderStatus::Filled));
+        assert_eq!(book.end_volatility_auction(10_000), None);
+    }
+
+    #[test]
+    fn test_trades_recorded_on_tape() {
+        let mut book = OrderBook::new();
+        let ask_id = book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        let bid_id = book.place_order("alice", 100, 4, OrderType::Bid).unwrap();
+
+        let trades = book.get_trades(1, 10).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].seq, 1);
+        assert_eq!(trades[0].price, 100);
+        assert_eq!(trades[0].quantity, 4);
+        assert_eq!(trades[0].maker_order_id, ask_id);
+        assert_eq!(trades[0].taker_order_id, bid_id);
+    }
+
+    #[test]
+    fn test_quantity_overflow_is_rejected() {
+        let mut book = OrderBook::new();
+        book.place_order("alice", 100, u64::MAX, OrderType::Bid)
+            .unwrap();
+        assert!(book.place_order("alice", 100, 1, OrderType::Bid).is_err());
+        assert_eq!(book.view_book_l2().bid[0].total_quantity, u64::MAX);
+    }
+
+    #[test]
+    fn test_trade_fees_follow_effective_schedule() {
+        let mut book = OrderBook::new();
+        let now = unix_millis();
+        book.clearing_house_mut()
+            .schedule_fees(-2, 10, now + 60_000, now)
+            .unwrap();
+
+        book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        book.place_order("alice", 100, 10, OrderType::Bid).unwrap();
+
+        // The new schedule is still pending, so the trade clears under v1
+        let trades = book.get_trades(1, 10).unwrap();
+        assert_eq!(trades[0].fees.schedule_version, 1);
+        assert_eq!(trades[0].fees.taker_fee, 0);
+        assert_eq!(book.clearing_house().fee_schedules().len(), 2);
+    }
+
+    #[test]
+    fn test_trades_are_cleared_to_ledger() {
+        let mut book = OrderBook::new();
+        book.clearing_house_mut()
+            .schedule_fees(-1, 5, 0, 0)
+            .unwrap();
+        book.place_order("alice", 100, 1_000, OrderType::Ask)
+            .unwrap();
+        book.place_order("bob", 100, 400, OrderType::Bid).unwrap();
+
+        let clearing_house = book.clearing_house();
+        let alice = clearing_house.account_statement("alice");
+        let bob = clearing_house.account_statement("bob");
+        // alice sold 400 @ 100 and earned a 4 rebate, bob paid a 20 fee
+        assert_eq!(alice.position, -400);
+        assert_eq!(alice.cash, 40_004);
+        assert_eq!(bob.position, 400);
+        assert_eq!(bob.cash, -40_020);
+        assert_eq!(clearing_house.account_statement(FEE_ACCOUNT).cash, 16);
+    }
+
+    #[test]
+    fn test_volume_tiers_and_rebates() {
+        let mut book = OrderBook::new();
+        let clearing_house = book.clearing_house_mut();
+        clearing_house.schedule_fees(0, 10, 0, 0).unwrap();
+        clearing_house
+            .set_fee_tiers(vec![FeeTier {
+                min_volume: 50_000,
+                maker_fee_bps: -2,
+                taker_fee_bps: 5,
+            }])
+            .unwrap();
+
+        // alice's first fill takes her into the tier for the next one
+        book.place_order("alice", 100, 500, OrderType::Ask).unwrap();
+        book.place_order("bob", 100, 500, OrderType::Bid).unwrap();
+        book.place_order("alice", 100, 100, OrderType::Ask).unwrap();
+        book.place_order("carol", 100, 100, OrderType::Bid).unwrap();
+
+        let trades = book.get_trades(1, 10).unwrap();
+        assert_eq!(
+            (trades[0].fees.maker_tier, trades[0].fees.maker_fee),
+            (0, 0)
+        );
+        assert_eq!(
+            (trades[1].fees.maker_tier, trades[1].fees.maker_fee),
+            (1, -2)
+        );
+        assert_eq!(
+            (trades[1].fees.taker_tier, trades[1].fees.taker_fee),
+            (0, 10)
+        );
+
+        let now = trades[1].timestamp;
+        let status = book.clearing_house().fee_tier_status("alice", now);
+        assert_eq!(status.volume, 60_000);
+        assert_eq!((status.tier, status.maker_fee_bps), (1, -2));
+        assert_eq!(status.accrued.rebates_received, 2);
+        assert_eq!(
+            book.clearing_house()
+                .fee_tier_status("bob", now)
+                .accrued
+                .fees_paid,
+            50
+        );
+
+        // Busting the first trade takes alice back out of the tier
+        book.clearing_house_mut().bust_trade(1, now).unwrap();
+        let status = book.clearing_house().fee_tier_status("alice", now);
+        assert_eq!((status.volume, status.tier), (10_000, 0));
+        assert_eq!(
+            book.clearing_house().fee_tier_status("bob", now).accrued,
+            FeeAccrual::default()
+        );
+    }
+
+    #[test]
+    fn test_bust_trade_reverses_ledger() {
+        let mut book = OrderBook::new();
+        book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        book.place_order("bob", 100, 10, OrderType::Bid).unwrap();
+
+        let clearing_house = book.clearing_house_mut();
+        clearing_house.bust_trade(1, 0).unwrap();
+        assert!(clearing_house.bust_trade(1, 0).is_err());
+
+        let bob = clearing_house.account_statement("bob");
+        assert_eq!(bob.position, 0);
+        assert_eq!(bob.cash, 0);
+        assert_eq!(bob.lines.len(), 4);
+    }
+
+    #[test]
+    fn test_expire_orders() {
+        let mut book = OrderBook::new();
+        let now = unix_millis();
+        let gtd_id = book
+            .place_order_with_expiry("alice", 100, 10, OrderType::Bid, Some(now + 1_000))
+            .unwrap();
+        let later_id = book
+            .place_order_with_expiry("alice", 99, 10, OrderType::Bid, Some(now + 5_000))
+            .unwrap();
+        book.place_order("alice", 98, 10, OrderType::Bid).unwrap();
+        assert_eq!(book.next_expiry(), Some(now + 1_000));
+
+        assert!(book.expire_orders(now).is_empty());
+        assert_eq!(book.expire_orders(now + 1_000), vec![gtd_id]);
+        assert!(book.cancel_order(gtd_id).is_err());
+        assert_eq!(book.view_book_l2().bid.len(), 2);
+
+        // Filled and canceled orders leave the expiry index
+        book.cancel_order(later_id).unwrap();
+        assert_eq!(book.next_expiry(), None);
+        assert!(book.expire_orders(now + 10_000).is_empty());
+    }
+
+    #[test]
+    fn test_filled_gtd_order_leaves_expiry_index() {
+        let mut book = OrderBook::new();
+        let now = unix_millis();
+        book.place_order_with_expiry("alice", 100, 10, OrderType::Ask, Some(now + 1_000))
+            .unwrap();
+        book.place_order("bob", 100, 10, OrderType::Bid).unwrap();
+
+        assert_eq!(book.next_expiry(), None);
+        assert!(book
+            .place_order_with_expiry("alice", 100, 10, OrderType::Ask, Some(now))
+            .is_err());
+    }
+
+    #[test]
+    fn test_auction_accumulates_without_matching() {
+        let mut book = OrderBook::new();
+        book.start_auction().unwrap();
+        book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        book.place_order("bob", 105, 10, OrderType::Bid).unwrap();
+
+        assert_eq!(book.session_stats().trade_count(), 0);
+        let l2_book = book.view_book_l2();
+        assert_eq!(l2_book.bid.len(), 1);
+        assert_eq!(l2_book.ask.len(), 1);
+    }
+
+    #[test]
+    fn test_indicative_uncross_price() {
+        let mut book = OrderBook::new();
+        book.start_auction().unwrap();
+        book.place_order("alice", 99, 5, OrderType::Ask).unwrap();
+        book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        book.place_order("alice", 102, 10, OrderType::Ask).unwrap();
+        book.place_order("bob", 103, 8, OrderType::Bid).unwrap();
+        book.place_order("bob", 101, 12, OrderType::Bid).unwrap();
+        book.place_order("bob", 98, 10, OrderType::Bid).unwrap();
+
+        // At 100 and 101: demand 20, supply 15. At 102: demand 8, supply 25.
+        // 100 and 101 tie on volume and surplus, so the lower price wins.
+        let uncross = book.indicative_uncross().unwrap();
+        assert_eq!(uncross.price, 100);
+        assert_eq!(uncross.volume, 15);
+
+        // A reference price breaks the tie towards the closer price
+        book.set_reference_price(101);
+        assert_eq!(book.indicative_uncross().unwrap().price, 101);
+        assert_eq!(book.view_book_l1().indicative_uncross.unwrap().volume, 15);
+    }
+
+    #[test]
+    fn test_indicative_uncross_published_as_it_moves() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut book = OrderBook::new();
+        book.start_auction().unwrap();
+        book.add_listener(Box::new(RecordingListener(events.clone())));
+        book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        book.place_order("bob", 101, 4, OrderType::Bid).unwrap();
+        let bid_id = book.place_order("bob", 100, 8, OrderType::Bid).unwrap();
+        // Doesn't cross, so the uncross stays put
+        book.place_order("bob", 90, 5, OrderType::Bid).unwrap();
+        book.cancel_order(bid_id).unwrap();
+        book.uncross().unwrap();
+
+        let indicative: Vec<String> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|line| line.starts_with("indicative"))
+            .cloned()
+            .collect();
+        assert_eq!(
+            indicative,
+            [
+                "indicative 4@100",
+                "indicative 10@100",
+                "indicative 4@100",
+                "indicative none",
+            ]
+        );
+        // Continuous trading bands around the uncross price
+        assert_eq!(book.indicative_uncross(), None);
+        assert_eq!(book.view_book_l1().reference_price, Some(100));
+    }
+
+    #[test]
+    fn test_uncross_executes_at_single_price() {
+        let mut book = OrderBook::new();
+        book.start_auction().unwrap();
+        book.place_order("alice", 99, 5, OrderType::Ask).unwrap();
+        book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        let bid_id = book.place_order("bob", 103, 8, OrderType::Bid).unwrap();
+        book.place_order("bob", 101, 12, OrderType::Bid).unwrap();
+
+        let uncross = book.uncross().unwrap().unwrap();
+        assert_eq!(uncross.volume, 15);
+        assert_eq!(book.phase(), TradingPhase::Continuous);
+
+        let trades = book.get_trades(1, 10).unwrap();
+        assert!(trades.iter().all(|trade| trade.price == uncross.price));
+        assert_eq!(trades.iter().map(|trade| trade.quantity).sum::<u64>(), 15);
+        assert_eq!(trades[0].taker_order_id, bid_id);
+        assert_eq!(trades[0].aggressor, None);
+
+        // The remaining 5 of the 101 bid rests and the ask side is empty
+        let l2_book = book.view_book_l2();
+        assert_eq!(l2_book.ask.len(), 0);
+        assert_eq!(l2_book.bid.len(), 1);
+        assert_eq!(l2_book.bid[0].total_quantity, 5);
+        assert_eq!(book.reference_price(), Some(uncross.price));
+        assert_eq!(book.clearing_house().account_statement("bob").position, 15);
+    }
+
+    #[test]
+    fn test_uncross_without_cross() {
+        let mut book = OrderBook::new();
+        assert!(book.uncross().is_err());
+        book.start_auction().unwrap();
+        book.place_order("alice", 105, 5, OrderType::Ask).unwrap();
+        book.place_order("bob", 100, 5, OrderType::Bid).unwrap();
+
+        assert_eq!(book.uncross().unwrap(), None);
+        assert_eq!(book.phase(), TradingPhase::Continuous);
+    }
+
+    #[test]
+    fn test_open_orders_by_owner() {
+        let mut book = OrderBook::new();
+        let ask_id = book.place_order("alice", 105, 5, OrderType::Ask).unwrap();
+        let bid_id = book.place_order("alice", 100, 5, OrderType::Bid).unwrap();
+        book.place_order("bob", 101, 7, OrderType::Bid).unwrap();
+        book.place_order("bob", 105, 2, OrderType::Bid).unwrap();
+
+        let open_orders = book.open_orders("alice");
+        assert_eq!(open_orders.len(), 2);
+        assert_eq!(open_orders[0].order_id, bid_id);
+        assert_eq!(open_orders[1].order_id, ask_id);
+        assert_eq!(open_orders[1].quantity, 3);
+        assert!(book.open_orders("carol").is_empty());
+
+        // Filled and canceled orders leave the owner index
+        book.place_order("carol", 100, 12, OrderType::Ask).unwrap();
+        book.cancel_order(ask_id).unwrap();
+        assert!(book.open_orders("alice").is_empty());
+        assert!(book.owner_index.is_empty());
+    }
+
+    #[test]
+    fn test_query_open_orders_pages_in_arrival_order() {
+        let mut book = OrderBook::new();
+        let order_ids: Vec<Uuid> = [103, 101, 102]
+            .into_iter()
+            .map(|price| book.place_order("alice", price, 5, OrderType::Ask).unwrap())
+            .collect();
+
+        let page = PageRequest {
+            limit: 2,
+            ..PageRequest::default()
+        };
+        let first = book.query_open_orders("alice", &page).unwrap();
+        assert_eq!(
+            first
+                .items
+                .iter()
+                .map(|order| order.order_id)
+                .collect::<Vec<_>>(),
+            order_ids[..2]
+        );
+        let second = book
+            .query_open_orders(
+                "alice",
+                &PageRequest {
+                    cursor: first.next_cursor,
+                    ..page
+                },
+            )
+            .unwrap();
+        assert_eq!(second.items[0].order_id, order_ids[2]);
+        assert_eq!(second.next_cursor, None);
+
+        let too_large = PageRequest {
+            limit: MAX_PAGE_LIMIT + 1,
+            ..PageRequest::default()
+        };
+        assert!(book.query_open_orders("alice", &too_large).is_err());
+    }
+
+    #[test]
+    fn test_cancel_client_order() {
+        let mut book = OrderBook::new();
+        let order_id = book.place_order("alice", 100, 5, OrderType::Ask).unwrap();
+        book.record_client_order("alice", "ask-1", order_id);
+
+        assert_eq!(
+            book.cancel_client_order("bob", "ask-1"),
+            Err(OrderBookError::UnknownOrder)
+        );
+        assert_eq!(book.cancel_client_order("alice", "ask-1"), Ok(5));
+        assert_eq!(book.order_status(order_id), Some(OrderStatus::Canceled));
+        assert_eq!(
+            book.cancel_client_order("alice", "ask-1"),
+            Err(OrderBookError::AlreadyCanceled)
+        );
+        assert_eq!(
+            book.cancel_client_order("alice", "ask-2"),
+            Err(OrderBookError::UnknownOrder)
+        );
+    }
+
+    #[test]
+    fn test_rejection_codes() {
+        let mut book = OrderBook::new();
+        assert_eq!(
+            book.place_order("alice", 0, 5, OrderType::Ask),
+            Err(OrderBookError::InvalidPrice)
+        );
+        assert_eq!(
+            book.place_order("alice", 100, 0, OrderType::Ask),
+            Err(OrderBookError::InvalidQuantity)
+        );
+        assert_eq!(
+            book.cancel_order(Uuid::new_v4()),
+            Err(OrderBookError::UnknownOrder)
+        );
+
+        let ask_id = book.place_order("alice", 100, 5, OrderType::Ask).unwrap();
+        book.place_order("bob", 100, 5, OrderType::Bid).unwrap();
+        assert_eq!(
+            book.cancel_order(ask_id),
+            Err(OrderBookError::AlreadyFilled)
+        );
+
+        book.halt();
+        assert_eq!(
+            book.place_order("alice", 100, 5, OrderType::Ask),
+            Err(OrderBookError::TradingHalted)
+        );
+    }
+
+    #[test]
+    fn test_order_status() {
+        let mut book = OrderBook::new();
+        book.set_clock(Box::new(ManualClock::new(1_000)));
+        let filled_id = book.place_order("alice", 100, 5, OrderType::Ask).unwrap();
+        let partial_id = book.place_order("alice", 101, 5, OrderType::Ask).unwrap();
+        let canceled_id = book.place_order("alice", 102, 5, OrderType::Ask).unwrap();
+        let taker_id = book.place_order("bob", 101, 7, OrderType::Bid).unwrap();
+
+        assert_eq!(book.order_status(filled_id), Some(OrderStatus::Filled));
+        assert_eq!(book.order_status(taker_id), Some(OrderStatus::Filled));
+        assert_eq!(
+            book.order_status(partial_id),
+            Some(OrderStatus::Resting {
+                remaining_qty: 3,
+                price: 101,
+                side: OrderType::Ask,
+                timestamp: 1_000,
+                arrival_seq: 2,
+            })
+        );
+
+        book.cancel_order(partial_id).unwrap();
+        book.cancel_order(canceled_id).unwrap();
+        assert_eq!(
+            book.order_status(partial_id),
+            Some(OrderStatus::PartiallyFilledThenCanceled)
+        );
+        assert_eq!(book.order_status(canceled_id), Some(OrderStatus::Canceled));
+        assert_eq!(book.order_status(Uuid::new_v4()), None);
+    }
+
+    #[test]
+    fn test_market_order_stops_at_protection() {
+        let mut book = OrderBook::new();
+        book.set_price_level_index(PriceLevelIndex::Ladder {
+            min_price: 50,
+            max_price: 150,
+            tick_size: 5,
+        })
+        .unwrap();
+        assert_eq!(
+            book.place_market_order("bob", 5, OrderType::Bid, MarketProtection::Ticks(1)),
+            Err(OrderBookError::NoLiquidity)
+        );
+        for price in [100, 105, 110, 150] {
+            book.place_order("alice", price, 5, OrderType::Ask).unwrap();
+        }
+
+        // One tick through the best ask reaches 105 and no further
+        let bid = book
+            .place_market_order("bob", 20, OrderType::Bid, MarketProtection::Ticks(1))
+            .unwrap();
+        assert_eq!(book.session_stats().volume(), 10);
+        assert_eq!(
+            book.order_status(bid),
+            Some(OrderStatus::PartiallyFilledThenCanceled)
+        );
+        assert!(book.open_orders("bob").is_empty());
+
+        let bid = book
+            .place_market_order("bob", 5, OrderType::Bid, MarketProtection::Price(110))
+            .unwrap();
+        assert_eq!(book.order_status(bid), Some(OrderStatus::Filled));
+        // Nothing within the limit, so nothing trades
+        let bid = book
+            .place_market_order("bob", 5, OrderType::Bid, MarketProtection::Price(120))
+            .unwrap();
+        assert_eq!(book.order_status(bid), Some(OrderStatus::Canceled));
+        assert_eq!(book.session_stats().volume(), 15);
+        book.check_conservation().unwrap();
+    }
+
+    #[test]
+    fn test_self_match_prevention_policies() {
+        // Bob's order trades first, then Alice's own is in the way
+        let book_with = |policy| {
+            let mut book = OrderBook::with_risk_config(RiskConfig {
+                self_match_prevention: Some(policy),
+                ..Default::default()
+            });
+            book.place_order("bob", 100, 2, OrderType::Ask).unwrap();
+            let own = book.place_order("alice", 100, 5, OrderType::Ask).unwrap();
+            book.place_order("bob", 101, 5, OrderType::Ask).unwrap();
+            (book, own)
+        };
+        let resting = |book: &OrderBook, order_id| match book.order_status(order_id) {
+            Some(OrderStatus::Resting { remaining_qty, .. }) => Some(remaining_qty),
+            _ => None,
+        };
+
+        let (mut book, own) = book_with(SelfMatchPrevention::CancelResting);
+        let bid = book.place_order("alice", 101, 4, OrderType::Bid).unwrap();
+        assert_eq!(book.order_status(own), Some(OrderStatus::Canceled));
+        assert_eq!(book.order_status(bid), Some(OrderStatus::Filled));
+        assert_eq!(book.session_stats().volume(), 4);
+        assert_eq!(
+            book.execution_report(bid).unwrap().self_match_prevention,
+            Some(SelfMatchPrevention::CancelResting)
+        );
+
+        let (mut book, own) = book_with(SelfMatchPrevention::CancelAggressing);
+        let bid = book.place_order("alice", 101, 4, OrderType::Bid).unwrap();
+        assert_eq!(resting(&book, own), Some(5));
+        assert_eq!(
+            book.order_status(bid),
+            Some(OrderStatus::PartiallyFilledThenCanceled)
+        );
+        assert_eq!(
+            book.execution_report(own).unwrap().self_match_prevention,
+            None
+        );
+
+        let (mut book, own) = book_with(SelfMatchPrevention::CancelBoth);
+        let bid = book.place_order("alice", 101, 4, OrderType::Bid).unwrap();
+        assert_eq!(book.order_status(own), Some(OrderStatus::Canceled));
+        assert_eq!(
+            book.order_status(bid),
+            Some(OrderStatus::PartiallyFilledThenCanceled)
+        );
+        assert_eq!(book.session_stats().volume(), 2);
+
+        // Smaller incoming order is used up by the decrement
+        let (mut book, own) = book_with(SelfMatchPrevention::DecrementAndCancel);
+        let bid = book.place_order("alice", 101, 4, OrderType::Bid).unwrap();
+        assert_eq!(resting(&book, own), Some(3));
+        assert_eq!(
+            book.order_status(bid),
+            Some(OrderStatus::PartiallyFilledThenCanceled)
+        );
+        assert_eq!(
+            book.execution_report(own).unwrap().self_match_prevention,
+            Some(SelfMatchPrevention::DecrementAndCancel)
+        );
+        // Larger one cancels the resting order and trades on
+        let bid = book.place_order("alice", 101, 10, OrderType::Bid).unwrap();
+        assert_eq!(book.order_status(own), Some(OrderStatus::Canceled));
+        assert_eq!(resting(&book, bid), Some(2));
+        assert_eq!(book.session_stats().volume(), 7);
+        book.check_conservation().unwrap();
+
+        // Without a policy an owner's orders trade with each other
+        let mut book = OrderBook::new();
+        let own = book.place_order("alice", 100, 5, OrderType::Ask).unwrap();
+        book.place_order("alice", 100, 5, OrderType::Bid).unwrap();
+        assert_eq!(book.order_status(own), Some(OrderStatus::Filled));
+    }
+
+    #[test]
+    fn test_execution_report() {
+        let mut book = OrderBook::new();
+        book.set_clock(Box::new(ManualClock::new(1_000)));
+        let maker_id = book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        let taker_id = book.place_order("bob", 100, 4, OrderType::Bid).unwrap();
+
+        let report = book.execution_report(taker_id).unwrap();
+        assert_eq!(report.filled_quantity, 4);
+        assert_eq!(report.status, OrderStatus::Filled);
+        let taker_matched = report.timestamps.unwrap().matched_nanos;
+        let report = book.execution_report(maker_id).unwrap();
+        assert!(report.timestamps.unwrap().matched_nanos <= taker_matched);
+        assert_eq!(report.filled_quantity, 4);
+        assert!(matches!(
+            report.status,
+            OrderStatus::Resting {
+                remaining_qty: 6,
+                ..
+            }
+        ));
+
+        assert_eq!(book.cancel_order(maker_id), Ok(6));
+        assert_eq!(
+            book.execution_report(maker_id).unwrap().status,
+            OrderStatus::PartiallyFilledThenCanceled
+        );
+        assert_eq!(book.execution_report(Uuid::new_v4()), None);
+    }
+
+    #[test]
+    fn test_execution_report_details_only_kept_for_open_orders() {
+        let mut book = OrderBook::with_risk_config(RiskConfig {
+            self_match_prevention: Some(SelfMatchPrevention::CancelAggressing),
+            ..Default::default()
+        });
+        let maker_id = book.place_order("alice", 100, 5, OrderType::Ask).unwrap();
+        let own_id = book.place_order("alice", 100, 5, OrderType::Bid).unwrap();
+        // The ack of an order closed on arrival still has its details
+        let report = book.execution_report(own_id).unwrap();
+        assert!(report.timestamps.is_some());
+        assert_eq!(
+            report.self_match_prevention,
+            Some(SelfMatchPrevention::CancelAggressing)
+        );
+
+        book.place_order("bob", 100, 5, OrderType::Bid).unwrap();
+        assert_eq!(book.order_status(maker_id), Some(OrderStatus::Filled));
+        assert_eq!(book.execution_report(own_id).unwrap().timestamps, None);
+        assert_eq!(book.matched_at.len(), 1);
+        assert!(book.self_matches.is_empty());
+    }
+
+    #[test]
+    fn test_cancel_all() {
+        let mut book = OrderBook::new();
+        let alice_bid = book.place_order("alice", 99, 5, OrderType::Bid).unwrap();
+        let bob_bid = book.place_order("bob", 98, 5, OrderType::Bid).unwrap();
+        let alice_ask = book.place_order("alice", 101, 5, OrderType::Ask).unwrap();
+        let bob_ask = book.place_order("bob", 103, 5, OrderType::Ask).unwrap();
+
+        let canceled = book.cancel_all(&CancelFilter::PriceRange {
+            side: OrderType::Ask,
+            min_price: 102,
+            max_price: 110,
+        });
+        assert_eq!(canceled, vec![bob_ask]);
+
+        assert_eq!(
+            book.cancel_all(&CancelFilter::Owner("alice".to_string())),
+            vec![alice_bid, alice_ask]
+        );
+        assert_eq!(book.cancel_all(&CancelFilter::All), vec![bob_bid]);
+        assert!(book.cancel_all(&CancelFilter::All).is_empty());
+        assert_eq!(book.order_status(bob_bid), Some(OrderStatus::Canceled));
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let mut book = OrderBook::new();
+        let expires_at = unix_millis() + 60_000;
+        let expiring = book
+            .place_order_with_expiry("alice", 101, 5, OrderType::Ask, Some(expires_at))
+            .unwrap();
+        book.place_order("alice", 102, 5, OrderType::Ask).unwrap();
+        let bid_id = book.place_order("bob", 99, 5, OrderType::Bid).unwrap();
+        book.place_order("bob", 101, 3, OrderType::Bid).unwrap();
+        assert_eq!(book.check_invariants().unwrap(), 3);
+
+        // Left out of the owner index
+        book.owner_index.get_mut("bob").unwrap().remove(&bid_id);
+        assert!(book.check_invariants().is_err());
+        book.owner_index.get_mut("bob").unwrap().insert(bid_id);
+
+        book.expiry_index.clear();
+        assert!(book.check_invariants().is_err());
+        // Indexed at the wrong time
+        book.expiry_index.insert((expires_at + 1, expiring));
+        assert!(book.check_invariants().is_err());
+    }
+
+    #[test]
+    fn test_quantity_conservation() {
+        let mut book = OrderBook::new();
+        let ask_id = book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        let bid_id = book.place_order("bob", 100, 4, OrderType::Bid).unwrap();
+        let expiring_id = book
+            .place_order_with_expiry("bob", 90, 5, OrderType::Bid, Some(unix_millis() + 60_000))
+            .unwrap();
+        book.expire_orders(u64::MAX);
+        book.cancel_order(ask_id).unwrap();
+
+        assert_eq!(book.check_conservation().unwrap(), 3);
+        let ask_account = book.order_account(ask_id).unwrap();
+        assert_eq!((ask_account.filled, ask_account.cancelled), (4, 6));
+        assert_eq!(book.order_account(bid_id).unwrap().filled, 4);
+        assert_eq!(book.order_account(expiring_id).unwrap().expired, 5);
+
+        // A fill the book never applied leaves the account unbalanced
+        book.accounting.record_fill(ask_id, 1);
+        assert!(book.check_conservation().is_err());
+    }
+
+    #[test]
+    fn test_l2_entry_level_stats() {
+        let clock = ManualClock::new(1_000);
+        let mut book = OrderBook::new();
+        book.set_clock(Box::new(clock.clone()));
+        book.place_order("alice", 100, 5, OrderType::Ask).unwrap();
+        clock.set(2_000);
+        book.place_order("alice", 100, 9, OrderType::Ask).unwrap();
+        book.place_order("alice", 100, 2, OrderType::Ask).unwrap();
+
+        let entry = book.view_book_l1().ask.unwrap();
+        assert_eq!(entry.num_orders, 3);
+        assert_eq!(entry.min_order_quantity, 2);
+        assert_eq!(entry.max_order_quantity, 9);
+        assert_eq!(entry.oldest_order_timestamp, 1_000);
+
+        // Fills the oldest order and part of the largest
+        book.place_order("bob", 100, 8, OrderType::Bid).unwrap();
+        let entry = &book.view_book_l2().ask[0];
+        assert_eq!(entry.num_orders, 2);
+        assert_eq!(entry.min_order_quantity, 2);
+        assert_eq!(entry.max_order_quantity, 6);
+        assert_eq!(entry.oldest_order_timestamp, 2_000);
+    }
+
+    #[test]
+    fn test_orders_carry_arrival_through_l3() {
+        let clock = ManualClock::new(1_000);
+        let mut book = OrderBook::new();
+        book.set_clock(Box::new(clock.clone()));
+        let first = book.place_order("alice", 100, 5, OrderType::Ask).unwrap();
+        clock.set(2_000);
+        // Fully filled, but still takes an arrival sequence number
+        book.place_order("bob", 100, 1, OrderType::Bid).unwrap();
+        let second = book.place_order("alice", 100, 3, OrderType::Ask).unwrap();
+
+        let l3_book = book.view_book_l3();
+        let arrivals: Vec<(Uuid, u64, u64)> = l3_book
+            .orders
+            .iter()
+            .map(|order| (order.order_id, order.timestamp, order.arrival_seq))
+            .collect();
+        assert_eq!(arrivals, vec![(first, 1_000, 1), (second, 2_000, 3)]);
+
+        // Survives the wire and a rebuilt mirror
+        let l3_book: L3Book =
+            serde_json::from_str(&serde_json::to_string(&l3_book).unwrap()).unwrap();
+        let mut mirror = OrderBook::from_l3(&l3_book).unwrap();
+        assert_eq!(mirror.order_status(second), book.order_status(second));
+        let next = mirror.place_order("carol", 101, 1, OrderType::Ask).unwrap();
+        assert!(matches!(
+            mirror.order_status(next),
+            Some(OrderStatus::Resting { arrival_seq: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_book_runs_on_injected_clock() {
+        let clock = ManualClock::new(1_000);
+        let mut book = OrderBook::new();
+        book.set_clock(Box::new(clock.clone()));
+
+        // Expiries are checked against the book's time, not the wall clock
+        assert!(book
+            .place_order_with_expiry("alice", 100, 5, OrderType::Ask, Some(1_000))
+            .is_err());
+        book.place_order_with_expiry("alice", 100, 5, OrderType::Ask, Some(2_000))
+            .unwrap();
+
+        clock.set(1_500);
+        book.place_order("bob", 100, 2, OrderType::Bid).unwrap();
+        assert_eq!(book.get_trades(1, 1).unwrap()[0].timestamp, 1_500);
+        assert_eq!(book.now(), 1_500);
     }
 }
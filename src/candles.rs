@@ -0,0 +1,236 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{
+    query::{paginate, Page, PageRequest, Paginated},
+    tape::Trade,
+};
+
+// 1s, 1m and 5m bars
+pub const DEFAULT_CANDLE_INTERVALS: [u64; 3] = [1_000, 60_000, 300_000];
+const DEFAULT_MAX_CANDLES: usize = 10_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Candle {
+    pub interval_ms: u64,
+    // Unix timestamp in milliseconds the bar starts at, aligned to the interval
+    pub open_time: u64,
+    pub open: u32,
+    pub high: u32,
+    pub low: u32,
+    pub close: u32,
+    pub volume: u64,
+    pub trade_count: u64,
+}
+
+impl Candle {
+    fn new(interval_ms: u64, open_time: u64, price: u32) -> Candle {
+        Candle {
+            interval_ms,
+            open_time,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: 0,
+            trade_count: 0,
+        }
+    }
+
+    fn record(&mut self, price: u32, quantity: u64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume = self.volume.saturating_add(quantity);
+        self.trade_count += 1;
+    }
+}
+
+// OHLCV bars built from the trade stream for every configured interval.
+// Intervals without trades have no bar.
+pub struct CandleAggregator {
+    // Interval -> bars ordered by open time, oldest first
+    series: BTreeMap<u64, VecDeque<Candle>>,
+    max_candles: usize,
+}
+
+impl CandleAggregator {
+    pub fn new(intervals_ms: &[u64], max_candles: usize) -> Result<CandleAggregator> {
+        if intervals_ms.contains(&0) {
+            return Err(anyhow!("Candle interval should be bigger than 0"));
+        }
+        Ok(CandleAggregator {
+            series: intervals_ms
+                .iter()
+                .map(|&interval_ms| (interval_ms, VecDeque::new()))
+                .collect(),
+            max_candles,
+        })
+    }
+
+    pub fn intervals(&self) -> impl Iterator<Item = u64> + '_ {
+        self.series.keys().copied()
+    }
+
+    pub fn record_trade(&mut self, trade: &Trade) {
+        for (&interval_ms, candles) in self.series.iter_mut() {
+            let open_time = trade.timestamp - trade.timestamp % interval_ms;
+            // Trades normally arrive in time order, so only the latest bar is updated
+            match candles.back_mut() {
+                Some(candle) if candle.open_time == open_time => {
+                    candle.record(trade.price, trade.quantity);
+                    continue;
+                }
+                Some(candle) if candle.open_time > open_time => {
+                    let idx = candles.partition_point(|candle| candle.open_time < open_time);
+                    if candles
+                        .get(idx)
+                        .is_none_or(|candle| candle.open_time != open_time)
+                    {
+                        candles.insert(idx, Candle::new(interval_ms, open_time, trade.price));
+                    }
+                    candles[idx].record(trade.price, trade.quantity);
+                }
+                _ => {
+                    let mut candle = Candle::new(interval_ms, open_time, trade.price);
+                    candle.record(trade.price, trade.quantity);
+                    candles.push_back(candle);
+                }
+            }
+            if candles.len() > self.max_candles {
+                candles.pop_front();
+            }
+        }
+    }
+
+    // Bars of one interval, paged by open time
+    pub fn query(&self, interval_ms: u64, page: &PageRequest) -> Result<Page<Candle>> {
+        let candles = self
+            .series
+            .get(&interval_ms)
+            .ok_or_else(|| anyhow!("No candles are kept for a {interval_ms}ms interval"))?;
+        let from = page.cursor.max(page.from_time).unwrap_or(0);
+        let start = candles.partition_point(|candle| candle.open_time < from);
+        paginate(candles.iter().skip(start).cloned().map(Ok), page)
+    }
+}
+
+impl Paginated for Candle {
+    fn cursor(&self) -> u64 {
+        self.open_time
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.open_time
+    }
+}
+
+impl Default for CandleAggregator {
+    fn default() -> Self {
+        Self::new(&DEFAULT_CANDLE_INTERVALS, DEFAULT_MAX_CANDLES).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{book::OrderType, clearing::TradeFees};
+
+    fn trade(timestamp: u64, price: u32, quantity: u64) -> Trade {
+        Trade {
+            seq: 0,
+            timestamp,
+            price,
+            quantity,
+            aggressor: Some(OrderType::Bid),
+            maker_order_id: uuid::Uuid::new_v4(),
+            maker_owner: "alice".to_string(),
+            taker_order_id: uuid::Uuid::new_v4(),
+            taker_owner: "bob".to_string(),
+            fees: TradeFees::default(),
+        }
+    }
+
+    #[test]
+    fn test_aggregate_bars() {
+        let mut aggregator = CandleAggregator::new(&[1_000, 60_000], 100).unwrap();
+        aggregator.record_trade(&trade(1_100, 100, 5));
+        aggregator.record_trade(&trade(1_900, 104, 1));
+        aggregator.record_trade(&trade(1_950, 98, 2));
+        aggregator.record_trade(&trade(3_000, 101, 4));
+
+        let seconds = aggregator
+            .query(1_000, &PageRequest::default())
+            .unwrap()
+            .items;
+        assert_eq!(seconds.len(), 2);
+        assert_eq!(seconds[0].open_time, 1_000);
+        assert_eq!(
+            (
+                seconds[0].open,
+                seconds[0].high,
+                seconds[0].low,
+                seconds[0].close
+            ),
+            (100, 104, 98, 98)
+        );
+        assert_eq!(seconds[0].volume, 8);
+        assert_eq!(seconds[1].open_time, 3_000);
+
+        let minutes = aggregator
+            .query(60_000, &PageRequest::default())
+            .unwrap()
+            .items;
+        assert_eq!(minutes.len(), 1);
+        assert_eq!(minutes[0].trade_count, 4);
+        assert_eq!(minutes[0].close, 101);
+    }
+
+    #[test]
+    fn test_late_trade_updates_earlier_bar() {
+        let mut aggregator = CandleAggregator::new(&[1_000], 100).unwrap();
+        aggregator.record_trade(&trade(1_000, 100, 1));
+        aggregator.record_trade(&trade(3_000, 100, 1));
+        aggregator.record_trade(&trade(2_500, 90, 1));
+        aggregator.record_trade(&trade(1_500, 110, 1));
+
+        let candles = aggregator
+            .query(1_000, &PageRequest::default())
+            .unwrap()
+            .items;
+        let open_times: Vec<u64> = candles.iter().map(|candle| candle.open_time).collect();
+        assert_eq!(open_times, [1_000, 2_000, 3_000]);
+        assert_eq!(candles[0].high, 110);
+        assert_eq!(candles[1].low, 90);
+    }
+
+    #[test]
+    fn test_query_range_and_limit() {
+        let mut aggregator = CandleAggregator::new(&[1_000], 3).unwrap();
+        for second in 0..5 {
+            aggregator.record_trade(&trade(second * 1_000, 100, 1));
+        }
+
+        // Only the latest three bars are kept
+        let candles = aggregator
+            .query(1_000, &PageRequest::default())
+            .unwrap()
+            .items;
+        assert_eq!(candles[0].open_time, 2_000);
+        let page = PageRequest {
+            from_time: Some(2_500),
+            to_time: Some(4_000),
+            ..Default::default()
+        };
+        assert_eq!(aggregator.query(1_000, &page).unwrap().items.len(), 2);
+        let page = PageRequest {
+            limit: 1,
+            ..Default::default()
+        };
+        let first_page = aggregator.query(1_000, &page).unwrap();
+        assert_eq!(first_page.items.len(), 1);
+        assert_eq!(first_page.next_cursor, Some(3_000));
+        assert!(aggregator.query(60_000, &page).is_err());
+    }
+}
@@ -0,0 +1,613 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::{
+    book::OrderType,
+    fees::{FeeSchedule, FeeScheduleHistory, FeeTier, FeeTiers, RollingVolume},
+    ledger::{Asset, EntryKind, Ledger, Posting, StatementLine, EXTERNAL_ACCOUNT, FEE_ACCOUNT},
+    netting::{self, NettingRun},
+    risk::RiskCheckKind,
+    settlement::{SettlementPosition, SettlementReport},
+    tape::Trade,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct TradeFees {
+    pub schedule_version: u32,
+    // Volume tier each side was charged at, 0 being the schedule itself
+    pub maker_tier: u32,
+    pub taker_tier: u32,
+    pub maker_fee: i64,
+    pub taker_fee: i64,
+}
+
+// Fees a participant has been charged and rebates they have been paid,
+// net of busted trades
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct FeeAccrual {
+    pub fees_paid: i64,
+    pub rebates_received: i64,
+}
+
+impl FeeAccrual {
+    fn apply(&mut self, fee: i64, sign: i64) {
+        if fee >= 0 {
+            self.fees_paid += sign * fee;
+        } else {
+            self.rebates_received -= sign * fee;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FeeTierStatus {
+    pub owner: String,
+    // Notional traded over the tier window
+    pub volume: u64,
+    pub tier: u32,
+    // Rates the next trade would be charged at
+    pub maker_fee_bps: i32,
+    pub taker_fee_bps: i32,
+    pub accrued: FeeAccrual,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AccountStatement {
+    pub owner: String,
+    pub cash: i64,
+    pub position: i64,
+    pub lines: Vec<StatementLine>,
+}
+
+// Cash of a participant and how much of it their resting bids hold back
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Balance {
+    pub cash: i64,
+    pub reserved: u64,
+    // Cash that can still be withdrawn or committed to new bids
+    pub available: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AccountAction {
+    Deposit {
+        owner: String,
+        amount: u64,
+    },
+    Withdraw {
+        owner: String,
+        amount: u64,
+    },
+    Transfer {
+        from: String,
+        to: String,
+        amount: u64,
+    },
+}
+
+impl AccountAction {
+    // Participant account the action takes cash out of, if any
+    pub fn debited(&self) -> Option<&str> {
+        match self {
+            AccountAction::Deposit { .. } => None,
+            AccountAction::Withdraw { owner, .. } => Some(owner),
+            AccountAction::Transfer { from, .. } => Some(from),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AuditEvent {
+    // Requested account action, recorded whether or not it was accepted
+    AccountAction {
+        action: AccountAction,
+        entry_id: Option<u64>,
+        rejection: Option<String>,
+    },
+    // Pre-trade check that was relaxed for a participant
+    RiskBypass {
+        owner: String,
+        order_id: Uuid,
+        check: RiskCheckKind,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    pub timestamp: u64,
+    pub event: AuditEvent,
+    pub memo: String,
+}
+
+// Kept so a bust can undo everything clearing the trade did
+struct ClearedTrade {
+    entry_id: u64,
+    maker_owner: String,
+    taker_owner: String,
+    fees: TradeFees,
+}
+
+#[derive(Default)]
+pub struct ClearingHouse {
+    fee_schedules: FeeScheduleHistory,
+    fee_tiers: FeeTiers,
+    ledger: Ledger,
+    // Trade sequence number -> how it was cleared
+    cleared_trades: HashMap<u64, ClearedTrade>,
+    volumes: HashMap<String, RollingVolume>,
+    accruals: HashMap<String, FeeAccrual>,
+    audit_log: Vec<AuditRecord>,
+    settlements: Vec<SettlementReport>,
+    netting_runs: Vec<NettingRun>,
+    // Last journal entry a netting run covered
+    netted_through: u64,
+}
+
+impl ClearingHouse {
+    pub fn new(fee_schedules: FeeScheduleHistory) -> ClearingHouse {
+        ClearingHouse {
+            fee_schedules,
+            fee_tiers: FeeTiers::default(),
+            ledger: Ledger::new(),
+            cleared_trades: HashMap::new(),
+            volumes: HashMap::new(),
+            accruals: HashMap::new(),
+            audit_log: Vec::new(),
+            settlements: Vec::new(),
+            netting_runs: Vec::new(),
+            netted_through: 0,
+        }
+    }
+
+    pub fn fee_schedules(&self) -> &[FeeSchedule] {
+        self.fee_schedules.schedules()
+    }
+
+    pub fn schedule_fees(
+        &mut self,
+        maker_fee_bps: i32,
+        taker_fee_bps: i32,
+        effective_from: u64,
+        now: u64,
+    ) -> Result<u32> {
+        self.fee_schedules
+            .schedule(maker_fee_bps, taker_fee_bps, effective_from, now)
+    }
+
+    pub fn fee_tiers(&self) -> &[FeeTier] {
+        self.fee_tiers.tiers()
+    }
+
+    // Replaces the volume tiers, taking effect from the next trade
+    pub fn set_fee_tiers(&mut self, tiers: Vec<FeeTier>) -> Result<()> {
+        self.fee_tiers = FeeTiers::new(tiers)?;
+        Ok(())
+    }
+
+    pub fn volume(&self, owner: &str, now: u64) -> u64 {
+        self.volumes
+            .get(owner)
+            .map_or(0, |volume| volume.volume(now))
+    }
+
+    // Charges a trade by the fee schedule that was active at its timestamp,
+    // or by the volume tier each side has reached before it. Auction trades
+    // have no aggressor, so both sides pay the maker fee.
+    pub fn trade_fees(&self, trade: &Trade) -> TradeFees {
+        let schedule = self.fee_schedules.active_at(trade.timestamp);
+        let (price, quantity) = (trade.price, trade.quantity);
+        let (maker_tier, maker_rates) = self
+            .fee_tiers
+            .tier_for(self.volume(&trade.maker_owner, trade.timestamp));
+        let (taker_tier, taker_rates) = self
+            .fee_tiers
+            .tier_for(self.volume(&trade.taker_owner, trade.timestamp));
+        let maker_fee = |tier: Option<&FeeTier>| {
+            tier.map_or(schedule.maker_fee(price, quantity), |tier| {
+                tier.maker_fee(price, quantity)
+            })
+        };
+        TradeFees {
+            schedule_version: schedule.version,
+            maker_tier,
+            taker_tier,
+            maker_fee: maker_fee(maker_rates),
+            taker_fee: if trade.aggressor.is_none() {
+                maker_fee(taker_rates)
+            } else {
+                taker_rates.map_or(schedule.taker_fee(price, quantity), |tier| {
+                    tier.taker_fee(price, quantity)
+                })
+            },
+        }
+    }
+
+    pub fn fee_tier_status(&self, owner: &str, now: u64) -> FeeTierStatus {
+        let volume = self.volume(owner, now);
+        let schedule = self.fee_schedules.active_at(now);
+        let (tier, rates) = self.fee_tiers.tier_for(volume);
+        FeeTierStatus {
+            owner: owner.to_string(),
+            volume,
+            tier,
+            maker_fee_bps: rates.map_or(schedule.maker_fee_bps, |tier| tier.maker_fee_bps),
+            taker_fee_bps: rates.map_or(schedule.taker_fee_bps, |tier| tier.taker_fee_bps),
+            accrued: self.accruals.get(owner).copied().unwrap_or_default(),
+        }
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    // Posts the cash, position and fee movements of a trade to the ledger
+    pub fn clear(&mut self, trade: &Trade) -> Result<u64> {
+        let quantity = i64::try_from(trade.quantity)?;
+        let notional = (trade.price as i64)
+            .checked_mul(quantity)
+            .ok_or_else(|| anyhow!("Trade notional overflows"))?;
+        let (buyer, seller) = match trade.aggressor {
+            Some(OrderType::Bid) | None => (&trade.taker_owner, &trade.maker_owner),
+            Some(OrderType::Ask) => (&trade.maker_owner, &trade.taker_owner),
+        };
+
+        let mut postings = vec![
+            posting(buyer, Asset::Position, quantity),
+            posting(seller, Asset::Position, -quantity),
+            posting(buyer, Asset::Cash, -notional),
+            posting(seller, Asset::Cash, notional),
+        ];
+        for (owner, fee) in [
+            (&trade.maker_owner, trade.fees.maker_fee),
+            (&trade.taker_owner, trade.fees.taker_fee),
+        ] {
+            if fee != 0 {
+                postings.push(posting(owner, Asset::Cash, -fee));
+                postings.push(posting(FEE_ACCOUNT, Asset::Cash, fee));
+            }
+        }
+
+        let entry_id = self.ledger.post(
+            trade.timestamp,
+            EntryKind::Trade {
+                trade_seq: trade.seq,
+            },
+            postings,
+        )?;
+        let cleared = ClearedTrade {
+            entry_id,
+            maker_owner: trade.maker_owner.clone(),
+            taker_owner: trade.taker_owner.clone(),
+            fees: trade.fees,
+        };
+        for (owner, fee) in cleared.charges() {
+            self.volumes.entry(owner.to_string()).or_default().record(
+                trade.timestamp,
+                trade.seq,
+                notional as u64,
+            );
+            self.accruals
+                .entry(owner.to_string())
+                .or_default()
+                .apply(fee, 1);
+        }
+        self.cleared_trades.insert(trade.seq, cleared);
+        Ok(entry_id)
+    }
+
+    // Reverses every ledger movement of a cleared trade, along with the
+    // volume and fees it added to each side
+    pub fn bust_trade(&mut self, trade_seq: u64, timestamp: u64) -> Result<u64> {
+        let cleared = self
+            .cleared_trades
+            .get(&trade_seq)
+            .ok_or_else(|| anyhow!("Trade has not been cleared"))?;
+        let reversal_id = self.ledger.reverse(cleared.entry_id, timestamp)?;
+        for (owner, fee) in cleared.charges() {
+            if let Some(volume) = self.volumes.get_mut(owner) {
+                volume.remove(trade_seq);
+            }
+            if let Some(accrual) = self.accruals.get_mut(owner) {
+                accrual.apply(fee, -1);
+            }
+        }
+        Ok(reversal_id)
+    }
+
+    // Credits, debits or moves cash between accounts. The outcome is recorded
+    // in the audit log whether or not the action is accepted.
+    pub fn apply_account_action(
+        &mut self,
+        action: AccountAction,
+        memo: &str,
+        timestamp: u64,
+    ) -> Result<u64> {
+        self.apply_account_action_with_reserved(action, memo, timestamp, 0)
+    }
+
+    // Same, but `reserved` of the debited account's cash is held back for
+    // its resting bids and can't be taken out
+    pub fn apply_account_action_with_reserved(
+        &mut self,
+        action: AccountAction,
+        memo: &str,
+        timestamp: u64,
+        reserved: u128,
+    ) -> Result<u64> {
+        let result = self.post_account_action(&action, timestamp, reserved);
+        self.audit(
+            timestamp,
+            AuditEvent::AccountAction {
+                action,
+                entry_id: result.as_ref().ok().copied(),
+                rejection: result.as_ref().err().map(|err| err.to_string()),
+            },
+            memo,
+        );
+        result
+    }
+
+    pub fn audit(&mut self, timestamp: u64, event: AuditEvent, memo: &str) {
+        self.audit_log.push(AuditRecord {
+            timestamp,
+            event,
+            memo: memo.to_string(),
+        });
+    }
+
+    fn post_account_action(
+        &mut self,
+        action: &AccountAction,
+        timestamp: u64,
+        reserved: u128,
+    ) -> Result<u64> {
+        let (kind, from, to, amount) = match action {
+            AccountAction::Deposit { owner, amount } => (
+                EntryKind::Deposit,
+                EXTERNAL_ACCOUNT,
+                owner.as_str(),
+                *amount,
+            ),
+            AccountAction::Withdraw { owner, amount } => (
+                EntryKind::Withdrawal,
+                owner.as_str(),
+                EXTERNAL_ACCOUNT,
+                *amount,
+            ),
+            AccountAction::Transfer { from, to, amount } => {
+                (EntryKind::Transfer, from.as_str(), to.as_str(), *amount)
+            }
+        };
+
+        if amount == 0 {
+            return Err(anyhow!("Amount should be bigger than 0"));
+        }
+        if from == to {
+            return Err(anyhow!("Cannot transfer funds to the same account"));
+        }
+        let amount = i64::try_from(amount)?;
+        // Funds can only leave a participant account if they are there and
+        // not reserved
+        if from != EXTERNAL_ACCOUNT
+            && (self.ledger.balance(from, Asset::Cash) as i128) - (reserved as i128)
+                < amount as i128
+        {
+            return Err(anyhow!("Insufficient cash balance in {from}"));
+        }
+
+        self.ledger.post(
+            timestamp,
+            kind,
+            vec![
+                posting(from, Asset::Cash, -amount),
+                posting(to, Asset::Cash, amount),
+            ],
+        )
+    }
+
+    pub fn audit_log(&self) -> &[AuditRecord] {
+        &self.audit_log
+    }
+
+    // Net position and cash of every participant, valued at the settlement
+    // price. Every unit bought was sold by someone, so the positions have to
+    // net to zero across participants.
+    pub fn net_positions(&self, settlement_price: Option<u32>) -> Result<Vec<SettlementPosition>> {
+        let positions = self.ledger.balances(Asset::Position);
+        let net: i128 = positions.values().map(|&position| position as i128).sum();
+        if net != 0 {
+            return Err(anyhow!("Positions net to {net} instead of zero"));
+        }
+
+        let mut cash = self.ledger.balances(Asset::Cash);
+        let mut owners: Vec<String> = positions.keys().chain(cash.keys()).cloned().collect();
+        owners.sort();
+        owners.dedup();
+        Ok(owners
+            .into_iter()
+            .filter(|owner| owner != FEE_ACCOUNT && owner != EXTERNAL_ACCOUNT)
+            .map(|owner| {
+                let position = positions.get(&owner).copied().unwrap_or(0);
+                SettlementPosition {
+                    cash: cash.remove(&owner).unwrap_or(0),
+                    mark_value: settlement_price.map(|price| position.saturating_mul(price as i64)),
+                    position,
+                    owner,
+                }
+            })
+            .filter(|line| line.position != 0 || line.cash != 0)
+            .collect())
+    }
+
+    // Closes out every position in cash at the final settlement price of an
+    // expiring instrument. Returns the journal entry, None when nobody held a
+    // position.
+    pub fn settle_expiry(&mut self, settlement_price: u32, timestamp: u64) -> Result<Option<u64>> {
+        let mut postings = Vec::new();
+        for (owner, position) in self.ledger.balances(Asset::Position) {
+            if position == 0 {
+                continue;
+            }
+            let value = position
+                .checked_mul(settlement_price as i64)
+                .ok_or_else(|| anyhow!("Settlement value of {owner}'s position overflows"))?;
+            postings.push(posting(&owner, Asset::Position, -position));
+            postings.push(posting(&owner, Asset::Cash, value));
+        }
+        if postings.is_empty() {
+            return Ok(None);
+        }
+        // Positions net to zero, so the cash does too
+        let entry_id = self.ledger.post(
+            timestamp,
+            EntryKind::FinalSettlement { settlement_price },
+            postings,
+        )?;
+        Ok(Some(entry_id))
+    }
+
+    pub fn record_settlement(&mut self, report: SettlementReport) {
+        self.settlements.push(report);
+    }
+
+    // Every end of day report, oldest first
+    pub fn settlements(&self) -> &[SettlementReport] {
+        &self.settlements
+    }
+
+    pub fn balance(&self, owner: &str, reserved: u128) -> Balance {
+        let cash = self.ledger.balance(owner, Asset::Cash);
+        let reserved = u64::try_from(reserved).unwrap_or(u64::MAX);
+        Balance {
+            cash,
+            reserved,
+            available: cash.saturating_sub_unsigned(reserved),
+        }
+    }
+
+    // Closes the netting period: every trade and bust cleared since the last
+    // run is collapsed into one net settlement per participant
+    pub fn net_trades(&mut self, timestamp: u64) -> NettingRun {
+        let run = netting::net_trades(&self.ledger, self.netted_through, timestamp);
+        if let Some(last_entry) = run.last_entry {
+            self.netted_through = last_entry;
+        }
+        self.netting_runs.push(run.clone());
+        run
+    }
+
+    // Every netting run, oldest first
+    pub fn netting_runs(&self) -> &[NettingRun] {
+        &self.netting_runs
+    }
+
+    pub fn account_statement(&self, owner: &str) -> AccountStatement {
+        AccountStatement {
+            owner: owner.to_string(),
+            cash: self.ledger.balance(owner, Asset::Cash),
+            position: self.ledger.balance(owner, Asset::Position),
+            lines: self.ledger.statement(owner),
+        }
+    }
+}
+
+impl ClearedTrade {
+    // Each side of the trade and the fee it was charged
+    fn charges(&self) -> [(&str, i64); 2] {
+        [
+            (&self.maker_owner, self.fees.maker_fee),
+            (&self.taker_owner, self.fees.taker_fee),
+        ]
+    }
+}
+
+fn posting(account: &str, asset: Asset, amount: i64) -> Posting {
+    Posting {
+        account: account.to_string(),
+        asset,
+        amount,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deposit(owner: &str, amount: u64) -> AccountAction {
+        AccountAction::Deposit {
+            owner: owner.to_string(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn test_deposit_withdraw_and_transfer() {
+        let mut clearing_house = ClearingHouse::default();
+        clearing_house
+            .apply_account_action(deposit("alice", 1_000), "initial funding", 1)
+            .unwrap();
+        clearing_house
+            .apply_account_action(
+                AccountAction::Transfer {
+                    from: "alice".to_string(),
+                    to: "bob".to_string(),
+                    amount: 300,
+                },
+                "",
+                2,
+            )
+            .unwrap();
+        clearing_house
+            .apply_account_action(
+                AccountAction::Withdraw {
+                    owner: "bob".to_string(),
+                    amount: 100,
+                },
+                "",
+                3,
+            )
+            .unwrap();
+
+        assert_eq!(clearing_house.account_statement("alice").cash, 700);
+        assert_eq!(clearing_house.account_statement("bob").cash, 200);
+        assert_eq!(
+            clearing_house.account_statement(EXTERNAL_ACCOUNT).cash,
+            -900
+        );
+        assert_eq!(clearing_house.audit_log().len(), 3);
+    }
+
+    #[test]
+    fn test_rejected_actions_are_audited() {
+        let mut clearing_house = ClearingHouse::default();
+        let overdraw = AccountAction::Withdraw {
+            owner: "alice".to_string(),
+            amount: 1,
+        };
+        assert!(clearing_house
+            .apply_account_action(overdraw, "overdraw", 1)
+            .is_err());
+        assert!(clearing_house
+            .apply_account_action(deposit("alice", 0), "empty", 2)
+            .is_err());
+
+        let audit_log = clearing_house.audit_log();
+        assert_eq!(audit_log.len(), 2);
+        for record in audit_log {
+            match &record.event {
+                AuditEvent::AccountAction {
+                    entry_id,
+                    rejection,
+                    ..
+                } => {
+                    assert!(entry_id.is_none());
+                    assert!(rejection.is_some());
+                }
+                event => panic!("Unexpected audit event {event:?}"),
+            }
+        }
+        assert!(clearing_house.ledger().journal().is_empty());
+    }
+}
@@ -0,0 +1,830 @@
+use anyhow::{anyhow, Result};
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{net::TcpStream, sync::mpsc, task::JoinHandle};
+use tokio_stream::Stream;
+use uuid::Uuid;
+
+use crate::{
+    auth::sign_from_env,
+    book::{
+        CancelFilter, ExecutionReport, L1Book, L2Book, L3Book, OpenOrder, OrderBook, OrderStatus,
+        OrderType,
+    },
+    error::OrderBookError,
+    feed::{BookEvent, SequencedEvent},
+    fills::Fill,
+    query::{Page, PageRequest, MAX_PAGE_LIMIT},
+    req::{
+        CancelAllArgs, CancelClientOrderArgs, CancelOrderArgs, GetEventsArgs, HandshakeArgs,
+        PlaceMarketOrderArgs, PlaceOrderArgs, QueryOrderArgs, Request, SubscribeArgs,
+        SubscribeFillsArgs, TaggedRequest, ViewOpenOrdersArgs,
+    },
+    resp::{MarketDataUpdate, Reject, Response},
+    tape::Trade,
+    wire::Framed,
+};
+
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const EVENTS_PER_POLL: usize = 1_000;
+// Items a subscriber can fall behind by before the feed waits for it
+const SUBSCRIPTION_BUFFER: usize = 1_024;
+// Most pipelined requests in flight at once, so neither side blocks writing
+// while the other isn't reading
+const PIPELINE_WINDOW: usize = 64;
+
+// Why a call on the client failed
+#[derive(Debug)]
+pub enum ClientError {
+    // Server turned the request down, e.g. an order that failed validation
+    // or a cancel of an order that already left the book
+    Rejected(Reject),
+    // Matching queue was full or the connection went over its rate limit.
+    // The request was not applied, so it's safe to retry.
+    Overloaded,
+    RateLimited,
+    // Server is a read-only replica or market data listener
+    ReadOnly,
+    // Request failed the signature, freshness or nonce check
+    Unauthorized,
+    // Server answered with a response that doesn't belong to the request
+    Unexpected(Box<Response>),
+    // Server couldn't be reached within the reconnect policy, or the
+    // connection dropped before the response arrived
+    Connection(anyhow::Error),
+}
+
+impl ClientError {
+    // Whether sending the request again may succeed without changing it
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ClientError::Overloaded | ClientError::RateLimited | ClientError::Connection(_)
+        )
+    }
+
+    pub(crate) fn from_response(response: Response) -> ClientError {
+        match response {
+            Response::PlaceErr(reject)
+            | Response::CancelErr(reject)
+            | Response::StartAuctionErr(reject)
+            | Response::UncrossErr(reject) => ClientError::Rejected(reject),
+            Response::Rejected(rejected) => ClientError::Rejected(rejected.reject),
+            Response::OrderStatusErr => ClientError::Rejected(OrderBookError::UnknownOrder.into()),
+            Response::Overloaded => ClientError::Overloaded,
+            Response::RateLimited => ClientError::RateLimited,
+            Response::ReadOnlyErr => ClientError::ReadOnly,
+            Response::AuthErr | Response::TenantErr => ClientError::Unauthorized,
+            response => ClientError::Unexpected(Box::new(response)),
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Rejected(reject) => write!(f, "Request was rejected: {}", reject.message),
+            ClientError::Overloaded => write!(f, "Server is overloaded"),
+            ClientError::RateLimited => write!(f, "Connection went over its rate limit"),
+            ClientError::ReadOnly => write!(f, "Server is read-only"),
+            ClientError::Unauthorized => write!(f, "Request failed authentication"),
+            ClientError::Unexpected(response) => write!(f, "Unexpected response {response:?}"),
+            ClientError::Connection(err) => write!(f, "Connection failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Connection(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for ClientError {
+    fn from(err: anyhow::Error) -> ClientError {
+        ClientError::Connection(err)
+    }
+}
+
+pub type ClientResult<T> = std::result::Result<T, ClientError>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    // Wait before the second attempt, doubling for every attempt after
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    // Attempts at a request, counting the first, before giving up
+    pub max_attempts: u32,
+    // Resend requests that are safe to repeat when the connection drops
+    // before their response arrives: queries, and orders that carry a
+    // client order id. Others fail, as they may have been applied.
+    pub resend_idempotent: bool,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            max_attempts: 10,
+            resend_idempotent: false,
+        }
+    }
+}
+
+// Connection to an order book server for applications. Requests are signed
+// with the credentials in the environment when set, see auth.rs. A dropped
+// connection is reopened by the next request.
+pub struct OrderBookClient {
+    connection: Connection,
+    poll_interval: Duration,
+}
+
+impl OrderBookClient {
+    // Client that opens its connection on the first request rather than
+    // straight away
+    pub fn new(addr: &str, policy: ReconnectPolicy) -> OrderBookClient {
+        OrderBookClient {
+            connection: Connection::new(addr, policy),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    pub async fn connect(addr: &str) -> ClientResult<OrderBookClient> {
+        OrderBookClient::connect_with_policy(addr, ReconnectPolicy::default()).await
+    }
+
+    pub async fn connect_with_policy(
+        addr: &str,
+        policy: ReconnectPolicy,
+    ) -> ClientResult<OrderBookClient> {
+        let mut client = OrderBookClient::new(addr, policy);
+        client.connection.open().await?;
+        Ok(client)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.socket.is_some()
+    }
+
+    // Closes the connection. The next request opens a new one.
+    pub fn disconnect(&mut self) {
+        self.connection.socket = None;
+    }
+
+    // Has the server compress large responses such as deep book snapshots,
+    // for this connection and the ones subscriptions open afterwards
+    pub async fn set_compression(&mut self, compression: bool) -> ClientResult<()> {
+        self.connection.handshake.compression = compression;
+        self.renegotiate().await
+    }
+
+    // Sends orders and cancels, and has their acks sent back, in the fixed
+    // layout of codec.rs rather than through serde. Signed requests keep
+    // going through serde.
+    pub async fn set_fixed_layout(&mut self, fixed_layout: bool) -> ClientResult<()> {
+        self.connection.handshake.fixed_layout = fixed_layout;
+        self.renegotiate().await
+    }
+
+    // Has the server answer pipelined requests as the book gets to them,
+    // rather than one at a time, with rejects as Response::Rejected
+    pub async fn set_async_acks(&mut self, async_acks: bool) -> ClientResult<()> {
+        self.connection.handshake.async_acks = async_acks;
+        self.renegotiate().await
+    }
+
+    // Reopens an open connection so the handshake takes effect, as it's
+    // only sent as a connection opens
+    async fn renegotiate(&mut self) -> ClientResult<()> {
+        if self.connection.socket.take().is_some() {
+            self.connection.open().await?;
+        }
+        Ok(())
+    }
+
+    // How often subscriptions started afterwards poll the server's feed
+    pub fn set_poll_interval(&mut self, poll_interval: Duration) {
+        self.poll_interval = poll_interval;
+    }
+
+    // Sends any request and returns the server's response as is, error
+    // responses included
+    pub async fn request(&mut self, request: Request) -> ClientResult<Response> {
+        let resend = self.connection.policy.resend_idempotent && can_resend(&request);
+        Ok(self.connection.send(&request, resend).await?)
+    }
+
+    // Sends the requests without waiting for each response in between and
+    // returns the responses in the order of the requests. Each request is
+    // tagged so its response can be told apart from the others and from
+    // fills or market data pushed in between, which are dropped. Nothing is
+    // resent: if the connection drops, the requests' outcome is unknown.
+    pub async fn pipeline(&mut self, requests: Vec<Request>) -> ClientResult<Vec<Response>> {
+        Ok(self.connection.pipeline(requests).await?)
+    }
+
+    pub async fn place(
+        &mut self,
+        place_order_args: PlaceOrderArgs,
+    ) -> ClientResult<ExecutionReport> {
+        match self.request(Request::PlaceOrder(place_order_args)).await? {
+            Response::PlaceOk(report) => Ok(report),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    // Good-till-canceled limit order without a client order id
+    pub async fn place_limit_order(
+        &mut self,
+        owner: &str,
+        order_type: OrderType,
+        price: u32,
+        quantity: u64,
+    ) -> ClientResult<ExecutionReport> {
+        self.place(PlaceOrderArgs {
+            order_type,
+            price,
+            quantity,
+            owner: owner.to_string(),
+            expires_at: None,
+            client_order_id: None,
+        })
+        .await
+    }
+
+    pub async fn place_order(&mut self, place_order_args: PlaceOrderArgs) -> ClientResult<Uuid> {
+        Ok(self.place(place_order_args).await?.order_id)
+    }
+
+    pub async fn place_market_order(
+        &mut self,
+        place_market_order_args: PlaceMarketOrderArgs,
+    ) -> ClientResult<ExecutionReport> {
+        match self
+            .request(Request::PlaceMarketOrder(place_market_order_args))
+            .await?
+        {
+            Response::PlaceOk(report) => Ok(report),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    // Cancels the order and returns the quantity that was still resting
+    pub async fn cancel(&mut self, order_id: Uuid) -> ClientResult<u64> {
+        self.cancel_request(Request::CancelOrder(CancelOrderArgs { order_id }))
+            .await
+    }
+
+    pub async fn cancel_client_order(
+        &mut self,
+        owner: &str,
+        client_order_id: &str,
+    ) -> ClientResult<u64> {
+        self.cancel_request(Request::CancelClientOrder(CancelClientOrderArgs {
+            owner: owner.to_string(),
+            client_order_id: client_order_id.to_string(),
+        }))
+        .await
+    }
+
+    async fn cancel_request(&mut self, request: Request) -> ClientResult<u64> {
+        match self.request(request).await? {
+            Response::CancelOk(canceled_quantity) => Ok(canceled_quantity),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    // Ids of the canceled orders
+    pub async fn cancel_all(&mut self, filter: CancelFilter) -> ClientResult<Vec<Uuid>> {
+        match self
+            .request(Request::CancelAll(CancelAllArgs { filter }))
+            .await?
+        {
+            Response::CancelAllOk(order_ids) => Ok(order_ids),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    pub async fn order_status(&mut self, order_id: Uuid) -> ClientResult<OrderStatus> {
+        match self
+            .request(Request::QueryOrder(QueryOrderArgs { order_id }))
+            .await?
+        {
+            Response::OrderStatusOk(status) => Ok(status),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    // Every open order of `owner`, following pages until the last one
+    pub async fn open_orders(&mut self, owner: &str) -> ClientResult<Vec<OpenOrder>> {
+        let mut open_orders = Vec::new();
+        let mut cursor = None;
+        loop {
+            let request = Request::ViewOpenOrders(ViewOpenOrdersArgs {
+                owner: owner.to_string(),
+                page: PageRequest {
+                    cursor,
+                    limit: MAX_PAGE_LIMIT,
+                    ..PageRequest::default()
+                },
+            });
+            match self.request(request).await? {
+                Response::OpenOrdersOk(page) => {
+                    open_orders.extend(page.items);
+                    cursor = page.next_cursor;
+                    if cursor.is_none() {
+                        return Ok(open_orders);
+                    }
+                }
+                response => return Err(ClientError::from_response(response)),
+            }
+        }
+    }
+
+    pub async fn l1_snapshot(&mut self) -> ClientResult<L1Book> {
+        match self.request(Request::ViewL1Book).await? {
+            Response::L1BookOk(l1_book) => Ok(l1_book),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    pub async fn l2_snapshot(&mut self) -> ClientResult<L2Book> {
+        match self.request(Request::ViewL2Book).await? {
+            Response::L2BookOk(l2_book) => Ok(l2_book),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    pub async fn l3_snapshot(&mut self) -> ClientResult<L3Book> {
+        match self.request(Request::ViewL3Book).await? {
+            Response::L3BookOk(l3_book) => Ok(l3_book),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    pub async fn trades(&mut self, page: PageRequest) -> ClientResult<Page<Trade>> {
+        match self.request(Request::GetTrades(page)).await? {
+            Response::TradesOk(trades) => Ok(trades),
+            response => Err(ClientError::from_response(response)),
+        }
+    }
+
+    // Full depth book, first as it stands and then again after every poll
+    // that changed it
+    pub async fn subscribe_l2(&self) -> ClientResult<Subscription<L2Book>> {
+        let mut connection = self.connection.reopen();
+        let mirror = snapshot(&mut connection).await?;
+        let (sender, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        let task = tokio::spawn(follow_l2(connection, mirror, self.poll_interval, sender));
+        Ok(Subscription { receiver, task })
+    }
+
+    // Trades from the time of subscribing on, in the order they happened
+    pub async fn subscribe_trades(&self) -> ClientResult<Subscription<Trade>> {
+        let mut connection = self.connection.reopen();
+        let from_seq = match connection.send(&Request::ViewL1Book, true).await? {
+            Response::L1BookOk(l1_book) => l1_book.as_of_seq + 1,
+            response => return Err(ClientError::from_response(response)),
+        };
+        let (sender, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        let task = tokio::spawn(follow_trades(
+            connection,
+            from_seq,
+            self.poll_interval,
+            sender,
+        ));
+        Ok(Subscription { receiver, task })
+    }
+
+    // Fills of the owner's orders as the server pushes them, including those
+    // the server queued while no connection was subscribed
+    pub async fn subscribe_fills(&self, owner: &str) -> ClientResult<Subscription<Fill>> {
+        let mut connection = self.connection.reopen();
+        request_fills(&mut connection, owner).await?;
+        let (sender, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        let task = tokio::spawn(follow_fills(connection, owner.to_string(), sender));
+        Ok(Subscription { receiver, task })
+    }
+
+    // Market data the server pushes for the subscriptions, each update the
+    // latest state of its channel, starting with the current one
+    pub async fn subscribe_market_data(
+        &self,
+        subscriptions: Vec<SubscribeArgs>,
+    ) -> ClientResult<Subscription<MarketDataUpdate>> {
+        let mut connection = self.connection.reopen();
+        request_market_data(&mut connection, &subscriptions).await?;
+        let (sender, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        let task = tokio::spawn(follow_market_data(connection, subscriptions, sender));
+        Ok(Subscription { receiver, task })
+    }
+}
+
+// Stream of market data fed by a task following the server's event feed on
+// its own connection, which it reopens after a drop and carries on from the
+// last event it saw. The stream ends after an error, e.g. when the server
+// can't be reached within the reconnect policy. Dropping it stops the task.
+pub struct Subscription<T> {
+    receiver: mpsc::Receiver<Result<T>>,
+    task: JoinHandle<()>,
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<T>>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+pub(crate) fn can_resend(request: &Request) -> bool {
+    match request {
+        Request::PlaceOrder(place_order_args) => place_order_args.client_order_id.is_some(),
+        Request::PlaceMarketOrder(place_market_order_args) => {
+            place_market_order_args.client_order_id.is_some()
+        }
+        Request::PlaceOrders(place_orders_args) => place_orders_args
+            .iter()
+            .all(|place_order_args| place_order_args.client_order_id.is_some()),
+        request => request.is_query(),
+    }
+}
+
+// Waits between attempts, doubling the wait up to the policy's maximum
+struct Backoff {
+    wait: Duration,
+    max_wait: Duration,
+    attempts_left: u32,
+}
+
+impl Backoff {
+    fn new(policy: &ReconnectPolicy) -> Backoff {
+        Backoff {
+            wait: policy.initial_backoff,
+            max_wait: policy.max_backoff,
+            attempts_left: policy.max_attempts.saturating_sub(1),
+        }
+    }
+
+    // False once every attempt was used
+    async fn wait(&mut self) -> bool {
+        if self.attempts_left == 0 {
+            return false;
+        }
+        self.attempts_left -= 1;
+        tokio::time::sleep(self.wait).await;
+        self.wait = (self.wait * 2).min(self.max_wait);
+        true
+    }
+}
+
+struct Connection {
+    addr: String,
+    policy: ReconnectPolicy,
+    // Negotiated for every socket opened
+    handshake: HandshakeArgs,
+    socket: Option<Framed>,
+    // Tag of the next pipelined request
+    next_request_id: u64,
+}
+
+impl Connection {
+    fn new(addr: &str, policy: ReconnectPolicy) -> Connection {
+        Connection {
+            addr: addr.to_string(),
+            policy,
+            handshake: HandshakeArgs {
+                compression: false,
+                fixed_layout: false,
+                async_acks: false,
+            },
+            socket: None,
+            next_request_id: 0,
+        }
+    }
+
+    // Unopened connection to the same server with the same settings
+    fn reopen(&self) -> Connection {
+        Connection {
+            handshake: self.handshake,
+            ..Connection::new(&self.addr, self.policy.clone())
+        }
+    }
+
+    async fn open(&mut self) -> Result<&mut Framed> {
+        let mut backoff = Backoff::new(&self.policy);
+        loop {
+            match connect(&self.addr, self.handshake).await {
+                Ok(socket) => return Ok(self.socket.insert(socket)),
+                Err(err) if !backoff.wait().await => return Err(err),
+                Err(_) => {}
+            }
+        }
+    }
+
+    // Sends the request, first reconnecting if the connection had dropped.
+    // With `resend`, a request whose response was lost is sent again.
+    async fn send(&mut self, request: &Request, resend: bool) -> Result<Response> {
+        let mut backoff = Backoff::new(&self.policy);
+        loop {
+            let socket = match &mut self.socket {
+                Some(socket) => socket,
+                None => self.open().await?,
+            };
+            match send(socket, request.clone()).await {
+                Ok(response) => return Ok(response),
+                Err(err) => {
+                    self.socket = None;
+                    if !resend || !backoff.wait().await {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+    }
+
+    // Sends the requests a window at a time, opening the connection first if
+    // it had dropped
+    async fn pipeline(&mut self, requests: Vec<Request>) -> Result<Vec<Response>> {
+        let mut responses = Vec::with_capacity(requests.len());
+        let mut requests = requests.into_iter().peekable();
+        while requests.peek().is_some() {
+            let window: Vec<Request> = requests.by_ref().take(PIPELINE_WINDOW).collect();
+            let first_id = self.next_request_id;
+            self.next_request_id += window.len() as u64;
+            let socket = match &mut self.socket {
+                Some(socket) => socket,
+                None => self.open().await?,
+            };
+            match send_tagged(socket, window, first_id).await {
+                Ok(window_responses) => responses.extend(window_responses),
+                Err(err) => {
+                    self.socket = None;
+                    return Err(err);
+                }
+            }
+        }
+        Ok(responses)
+    }
+}
+
+async fn connect(addr: &str, handshake: HandshakeArgs) -> Result<Framed> {
+    let mut socket = Framed::new(TcpStream::connect(addr).await?);
+    if handshake.compression || handshake.fixed_layout || handshake.async_acks {
+        match send(&mut socket, Request::Handshake(handshake)).await? {
+            Response::HandshakeOk(agreed) => {
+                socket.set_compression(agreed.compression);
+                socket.set_fixed_layout(agreed.fixed_layout);
+            }
+            response => return Err(anyhow!("Unexpected response {response:?}")),
+        }
+    }
+    Ok(socket)
+}
+
+async fn send(socket: &mut Framed, request: Request) -> Result<Response> {
+    // Signed for every attempt, since the server turns away repeated nonces
+    socket.write_msg(&sign_from_env(request)?).await?;
+    socket.read_msg().await
+}
+
+// Sends every request, tagged from `first_id` on, then reads until each has
+// its response
+async fn send_tagged(
+    socket: &mut Framed,
+    requests: Vec<Request>,
+    first_id: u64,
+) -> Result<Vec<Response>> {
+    let mut responses: Vec<Option<Response>> = requests.iter().map(|_| None).collect();
+    for (request_id, request) in (first_id..).zip(requests) {
+        let request = Request::Tagged(Box::new(TaggedRequest {
+            request_id,
+            request: sign_from_env(request)?,
+        }));
+        socket.write_msg(&request).await?;
+    }
+    let mut pending = responses.len();
+    while pending > 0 {
+        // Anything else untagged was pushed rather than sent in response
+        let (request_id, response) = match socket.read_msg().await? {
+            Response::Tagged(tagged) => (tagged.request_id, tagged.response),
+            Response::Rejected(rejected) => (rejected.request_id, Response::Rejected(rejected)),
+            _ => continue,
+        };
+        let slot = request_id
+            .checked_sub(first_id)
+            .and_then(|index| responses.get_mut(index as usize));
+        match slot {
+            Some(slot) if slot.is_none() => {
+                *slot = Some(response);
+                pending -= 1;
+            }
+            _ => return Err(anyhow!("Unexpected response to request {request_id}")),
+        }
+    }
+    Ok(responses.into_iter().flatten().collect())
+}
+
+async fn snapshot(connection: &mut Connection) -> Result<OrderBook> {
+    match connection.send(&Request::ViewL3Book, true).await? {
+        Response::L3BookOk(l3_book) => Ok(OrderBook::from_l3(&l3_book)?),
+        response => Err(anyhow!("Unexpected response {response:?}")),
+    }
+}
+
+// Every event from `from_seq` the server has published so far, or None if
+// it no longer retains event `from_seq`
+async fn poll_events(
+    connection: &mut Connection,
+    from_seq: u64,
+) -> Result<Option<Vec<SequencedEvent>>> {
+    let mut events = Vec::new();
+    loop {
+        let request = Request::GetEvents(GetEventsArgs {
+            from_seq: from_seq + events.len() as u64,
+            limit: EVENTS_PER_POLL,
+        });
+        let page = match connection.send(&request, true).await? {
+            Response::EventsOk(page) => page,
+            Response::EventsErr if events.is_empty() => return Ok(None),
+            response => return Err(anyhow!("Unexpected response {response:?}")),
+        };
+        let caught_up = page.len() < EVENTS_PER_POLL;
+        events.extend(page);
+        if caught_up {
+            return Ok(Some(events));
+        }
+    }
+}
+
+async fn follow_l2(
+    mut connection: Connection,
+    mut mirror: OrderBook,
+    poll_interval: Duration,
+    sender: mpsc::Sender<Result<L2Book>>,
+) {
+    let mut interval = tokio::time::interval(poll_interval);
+    let mut last_sent: Option<L2Book> = None;
+    loop {
+        interval.tick().await;
+        let result = async {
+            match poll_events(&mut connection, mirror.last_event_seq() + 1).await? {
+                Some(events) => {
+                    for event in events {
+                        mirror.apply_event(event)?;
+                    }
+                }
+                // Fell further behind than the server keeps events for, so
+                // the book is mirrored afresh
+                None => mirror = snapshot(&mut connection).await?,
+            }
+            Ok(mirror.view_book_l2())
+        }
+        .await;
+        let l2_book = match result {
+            Ok(l2_book) => l2_book,
+            Err(err) => {
+                let _ = sender.send(Err(err)).await;
+                return;
+            }
+        };
+        // Events that leave the levels as they were, such as halts, aren't
+        // passed on
+        let changed = last_sent
+            .as_ref()
+            .is_none_or(|last_sent| last_sent.bid != l2_book.bid || last_sent.ask != l2_book.ask);
+        if changed {
+            last_sent = Some(l2_book.clone());
+            if sender.send(Ok(l2_book)).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn request_fills(connection: &mut Connection, owner: &str) -> Result<()> {
+    let request = Request::SubscribeFills(SubscribeFillsArgs {
+        owner: owner.to_string(),
+    });
+    match connection.send(&request, true).await? {
+        Response::SubscribeFillsOk => Ok(()),
+        response => Err(anyhow!("Unexpected response {response:?}")),
+    }
+}
+
+async fn follow_fills(
+    mut connection: Connection,
+    owner: String,
+    sender: mpsc::Sender<Result<Fill>>,
+) {
+    loop {
+        if let Some(socket) = &mut connection.socket {
+            while let Ok(response) = socket.read_msg().await {
+                if let Response::Fill(fill) = response {
+                    if sender.send(Ok(fill)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+        // Subscribing again on a new connection picks up the fills the
+        // server queued in the meantime
+        connection.socket = None;
+        if let Err(err) = request_fills(&mut connection, &owner).await {
+            let _ = sender.send(Err(err)).await;
+            return;
+        }
+    }
+}
+
+async fn request_market_data(
+    connection: &mut Connection,
+    subscriptions: &[SubscribeArgs],
+) -> Result<()> {
+    for subscription in subscriptions {
+        let request = Request::Subscribe(subscription.clone());
+        let mut response = connection.send(&request, true).await?;
+        // Updates of the earlier subscriptions can come first. The last one
+        // has the server send the state of every channel again after it.
+        while let (Response::MarketData(_), Some(socket)) = (&response, &mut connection.socket) {
+            response = socket.read_msg().await?;
+        }
+        match response {
+            Response::SubscribeOk => {}
+            response => return Err(anyhow!("Unexpected response {response:?}")),
+        }
+    }
+    Ok(())
+}
+
+async fn follow_market_data(
+    mut connection: Connection,
+    subscriptions: Vec<SubscribeArgs>,
+    sender: mpsc::Sender<Result<MarketDataUpdate>>,
+) {
+    loop {
+        if let Some(socket) = &mut connection.socket {
+            while let Ok(response) = socket.read_msg().await {
+                if let Response::MarketData(update) = response {
+                    if sender.send(Ok(update)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+        // Subscriptions belong to the connection, so they're made again on
+        // a new one, which starts with the latest state
+        connection.socket = None;
+        if let Err(err) = request_market_data(&mut connection, &subscriptions).await {
+            let _ = sender.send(Err(err)).await;
+            return;
+        }
+    }
+}
+
+async fn follow_trades(
+    mut connection: Connection,
+    mut from_seq: u64,
+    poll_interval: Duration,
+    sender: mpsc::Sender<Result<Trade>>,
+) {
+    let mut interval = tokio::time::interval(poll_interval);
+    loop {
+        interval.tick().await;
+        let events = match poll_events(&mut connection, from_seq).await {
+            Ok(Some(events)) => events,
+            Ok(None) => {
+                let err =
+                    anyhow!("Server no longer retains event {from_seq}, so trades were missed");
+                let _ = sender.send(Err(err)).await;
+                return;
+            }
+            Err(err) => {
+                let _ = sender.send(Err(err)).await;
+                return;
+            }
+        };
+        for event in events {
+            from_seq = event.seq + 1;
+            if let BookEvent::Traded(trade) = event.event {
+                if sender.send(Ok(trade)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
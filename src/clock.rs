@@ -0,0 +1,128 @@
+use anyhow::{anyhow, Result};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, OnceLock,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DAY_MS: u64 = 24 * 60 * 60 * 1_000;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System clock is before the unix epoch")
+        .as_millis() as u64
+}
+
+// wasm32-unknown-unknown has no clock of its own, and std's panics there, so
+// the time is read from the JavaScript host
+#[cfg(target_arch = "wasm32")]
+pub fn unix_millis() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+// Stands in for std's Instant on wasm32, in the host's milliseconds
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy)]
+pub struct Instant(f64);
+
+#[cfg(target_arch = "wasm32")]
+impl Instant {
+    pub fn now() -> Instant {
+        Instant(js_sys::Date::now())
+    }
+
+    pub fn elapsed(&self) -> std::time::Duration {
+        let millis = (js_sys::Date::now() - self.0).max(0.0);
+        std::time::Duration::from_secs_f64(millis / 1_000.0)
+    }
+}
+
+// Nanoseconds on a monotonic clock since the process first read it. Steady
+// across wall clock adjustments, but only comparable within the process.
+pub fn monotonic_nanos() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+// Where the book reads the time from. The wall clock unless a backtest
+// replays history on a clock of its own.
+pub trait Clock: Send + Sync {
+    fn now_millis(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        unix_millis()
+    }
+}
+
+// Clock that only moves when set. Clones share the same time, so one can be
+// handed to the book and another kept to drive it.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    millis: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new(millis: u64) -> ManualClock {
+        ManualClock {
+            millis: Arc::new(AtomicU64::new(millis)),
+        }
+    }
+
+    pub fn set(&self, millis: u64) {
+        self.millis.store(millis, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.millis.load(Ordering::Relaxed)
+    }
+}
+
+// Milliseconds past midnight UTC of a "HH:MM" time
+pub fn parse_time_of_day(time: &str) -> Result<u64> {
+    let (hours, minutes) = time
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Expected HH:MM but got {time}"))?;
+    let (hours, minutes): (u64, u64) = (hours.parse()?, minutes.parse()?);
+    if hours >= 24 || minutes >= 60 {
+        return Err(anyhow!("{time} is not a time of day"));
+    }
+    Ok((hours * 60 + minutes) * 60 * 1_000)
+}
+
+// First time strictly after `now` that is `time_of_day` past midnight UTC
+pub fn next_time_of_day(time_of_day: u64, now: u64) -> u64 {
+    let today = now - now % DAY_MS + time_of_day;
+    if today > now {
+        today
+    } else {
+        today + DAY_MS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_of_day() {
+        let close = parse_time_of_day("16:30").unwrap();
+        assert_eq!(close, 59_400_000);
+        assert!(parse_time_of_day("24:00").is_err());
+        assert!(parse_time_of_day("1630").is_err());
+
+        let day_two = 2 * DAY_MS;
+        assert_eq!(next_time_of_day(close, day_two), day_two + close);
+        assert_eq!(next_time_of_day(close, day_two + close), 3 * DAY_MS + close);
+    }
+}
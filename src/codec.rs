@@ -0,0 +1,376 @@
+use anyhow::{anyhow, Result};
+use bytes::BufMut;
+use uuid::Uuid;
+
+use crate::{
+    book::{ExecutionReport, OrderStatus, OrderTimestamps, OrderType},
+    req::{CancelOrderArgs, PlaceOrderArgs, Request},
+    resp::Response,
+    risk::SelfMatchPrevention,
+};
+
+// Fixed layout of the order entry messages, written and read field by field
+// rather than through serde, for connections that agreed to it at handshake.
+// Every body starts with a byte naming the message. Integers are little
+// endian, strings a u16 length followed by their UTF-8 bytes and optional
+// fields a 0 or 1 byte before the value. Other messages have no fixed
+// layout and are framed with serde as before, see wire.rs.
+pub trait FixedCodec: Sized {
+    // Appends the message's fixed layout, or returns false without writing
+    // anything if it has none
+    fn encode_fixed(&self, _buf: &mut impl BufMut) -> bool {
+        false
+    }
+
+    fn decode_fixed(_body: &[u8]) -> Result<Self> {
+        Err(anyhow!("Message has no fixed layout"))
+    }
+}
+
+const PLACE_ORDER: u8 = 1;
+const CANCEL_ORDER: u8 = 2;
+const PLACE_OK: u8 = 1;
+const CANCEL_OK: u8 = 2;
+
+const RESTING: u8 = 0;
+const FILLED: u8 = 1;
+const PARTIALLY_FILLED_THEN_CANCELED: u8 = 2;
+const CANCELED: u8 = 3;
+
+impl FixedCodec for Request {
+    fn encode_fixed(&self, buf: &mut impl BufMut) -> bool {
+        match self {
+            Request::PlaceOrder(place_order_args) => {
+                // Longer strings than a u16 can count aren't worth a layout
+                let too_long = |text: &str| text.len() > u16::MAX as usize;
+                if too_long(&place_order_args.owner)
+                    || place_order_args
+                        .client_order_id
+                        .as_deref()
+                        .is_some_and(too_long)
+                {
+                    return false;
+                }
+                buf.put_u8(PLACE_ORDER);
+                put_side(buf, place_order_args.order_type);
+                buf.put_u32_le(place_order_args.price);
+                buf.put_u64_le(place_order_args.quantity);
+                put_option(buf, place_order_args.expires_at, |buf, expires_at| {
+                    buf.put_u64_le(expires_at)
+                });
+                put_str(buf, &place_order_args.owner);
+                put_option(buf, place_order_args.client_order_id.as_deref(), put_str);
+                true
+            }
+            Request::CancelOrder(cancel_order_args) => {
+                buf.put_u8(CANCEL_ORDER);
+                buf.put_slice(cancel_order_args.order_id.as_bytes());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn decode_fixed(body: &[u8]) -> Result<Request> {
+        let mut reader = Reader(body);
+        let request = match reader.u8()? {
+            PLACE_ORDER => Request::PlaceOrder(PlaceOrderArgs {
+                order_type: reader.side()?,
+                price: reader.u32()?,
+                quantity: reader.u64()?,
+                expires_at: reader.option(Reader::u64)?,
+                owner: reader.string()?,
+                client_order_id: reader.option(Reader::string)?,
+            }),
+            CANCEL_ORDER => Request::CancelOrder(CancelOrderArgs {
+                order_id: reader.uuid()?,
+            }),
+            kind => return Err(anyhow!("No request has fixed layout {kind}")),
+        };
+        reader.finish()?;
+        Ok(request)
+    }
+}
+
+impl FixedCodec for Response {
+    fn encode_fixed(&self, buf: &mut impl BufMut) -> bool {
+        match self {
+            Response::PlaceOk(report) => {
+                buf.put_u8(PLACE_OK);
+                buf.put_slice(report.order_id.as_bytes());
+                buf.put_u64_le(report.filled_quantity);
+                put_status(buf, report.status);
+                put_option(buf, report.timestamps, |buf, timestamps| {
+                    put_option(buf, timestamps.received_nanos, |buf, nanos| {
+                        buf.put_u64_le(nanos)
+                    });
+                    buf.put_u64_le(timestamps.matched_nanos);
+                    put_option(buf, timestamps.acked_nanos, |buf, nanos| {
+                        buf.put_u64_le(nanos)
+                    });
+                });
+                put_option(buf, report.self_match_prevention, put_self_match);
+                true
+            }
+            Response::CancelOk(canceled_quantity) => {
+                buf.put_u8(CANCEL_OK);
+                buf.put_u64_le(*canceled_quantity);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn decode_fixed(body: &[u8]) -> Result<Response> {
+        let mut reader = Reader(body);
+        let response = match reader.u8()? {
+            PLACE_OK => Response::PlaceOk(ExecutionReport {
+                order_id: reader.uuid()?,
+                filled_quantity: reader.u64()?,
+                status: reader.status()?,
+                timestamps: reader.option(|reader| {
+                    Ok(OrderTimestamps {
+                        received_nanos: reader.option(Reader::u64)?,
+                        matched_nanos: reader.u64()?,
+                        acked_nanos: reader.option(Reader::u64)?,
+                    })
+                })?,
+                self_match_prevention: reader.option(Reader::self_match)?,
+            }),
+            CANCEL_OK => Response::CancelOk(reader.u64()?),
+            kind => return Err(anyhow!("No response has fixed layout {kind}")),
+        };
+        reader.finish()?;
+        Ok(response)
+    }
+}
+
+// Framed as serde, e.g. by tests sending free form text
+impl FixedCodec for String {}
+
+fn put_side(buf: &mut impl BufMut, side: OrderType) {
+    buf.put_u8(match side {
+        OrderType::Bid => 0,
+        OrderType::Ask => 1,
+    });
+}
+
+fn put_self_match(buf: &mut impl BufMut, policy: SelfMatchPrevention) {
+    buf.put_u8(match policy {
+        SelfMatchPrevention::CancelResting => 0,
+        SelfMatchPrevention::CancelAggressing => 1,
+        SelfMatchPrevention::CancelBoth => 2,
+        SelfMatchPrevention::DecrementAndCancel => 3,
+    });
+}
+
+fn put_str(buf: &mut impl BufMut, text: &str) {
+    buf.put_u16_le(text.len() as u16);
+    buf.put_slice(text.as_bytes());
+}
+
+fn put_option<B: BufMut, T>(buf: &mut B, value: Option<T>, put: impl FnOnce(&mut B, T)) {
+    match value {
+        Some(value) => {
+            buf.put_u8(1);
+            put(buf, value);
+        }
+        None => buf.put_u8(0),
+    }
+}
+
+fn put_status(buf: &mut impl BufMut, status: OrderStatus) {
+    match status {
+        OrderStatus::Resting {
+            remaining_qty,
+            price,
+            side,
+            timestamp,
+            arrival_seq,
+        } => {
+            buf.put_u8(RESTING);
+            buf.put_u64_le(remaining_qty);
+            buf.put_u32_le(price);
+            put_side(buf, side);
+            buf.put_u64_le(timestamp);
+            buf.put_u64_le(arrival_seq);
+        }
+        OrderStatus::Filled => buf.put_u8(FILLED),
+        OrderStatus::PartiallyFilledThenCanceled => buf.put_u8(PARTIALLY_FILLED_THEN_CANCELED),
+        OrderStatus::Canceled => buf.put_u8(CANCELED),
+    }
+}
+
+// Reads fields off the front of a body, failing rather than panicking on
+// one cut short, since bodies come from the peer
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(anyhow!("Message ends part way through a field"));
+        }
+        let (field, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(field)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn uuid(&mut self) -> Result<Uuid> {
+        Ok(Uuid::from_slice(self.take(16)?)?)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u16()? as usize;
+        Ok(std::str::from_utf8(self.take(len)?)?.to_string())
+    }
+
+    fn side(&mut self) -> Result<OrderType> {
+        match self.u8()? {
+            0 => Ok(OrderType::Bid),
+            1 => Ok(OrderType::Ask),
+            side => Err(anyhow!("Unknown side {side}")),
+        }
+    }
+
+    fn self_match(&mut self) -> Result<SelfMatchPrevention> {
+        match self.u8()? {
+            0 => Ok(SelfMatchPrevention::CancelResting),
+            1 => Ok(SelfMatchPrevention::CancelAggressing),
+            2 => Ok(SelfMatchPrevention::CancelBoth),
+            3 => Ok(SelfMatchPrevention::DecrementAndCancel),
+            policy => Err(anyhow!("Unknown self-match prevention {policy}")),
+        }
+    }
+
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T>) -> Result<Option<T>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(read(self)?)),
+            flag => Err(anyhow!("Unknown option flag {flag}")),
+        }
+    }
+
+    fn status(&mut self) -> Result<OrderStatus> {
+        match self.u8()? {
+            RESTING => Ok(OrderStatus::Resting {
+                remaining_qty: self.u64()?,
+                price: self.u32()?,
+                side: self.side()?,
+                timestamp: self.u64()?,
+                arrival_seq: self.u64()?,
+            }),
+            FILLED => Ok(OrderStatus::Filled),
+            PARTIALLY_FILLED_THEN_CANCELED => Ok(OrderStatus::PartiallyFilledThenCanceled),
+            CANCELED => Ok(OrderStatus::Canceled),
+            status => Err(anyhow!("Unknown order status {status}")),
+        }
+    }
+
+    // Trailing bytes mean the peer wrote a different layout
+    fn finish(self) -> Result<()> {
+        if !self.0.is_empty() {
+            return Err(anyhow!(
+                "Message has {} bytes past its fields",
+                self.0.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: FixedCodec>(msg: &T) -> T {
+        let mut buf = Vec::new();
+        assert!(msg.encode_fixed(&mut buf));
+        T::decode_fixed(&buf).unwrap()
+    }
+
+    #[test]
+    fn test_requests_round_trip() {
+        let place_order = Request::PlaceOrder(PlaceOrderArgs {
+            order_type: OrderType::Ask,
+            price: 10_100,
+            quantity: 25,
+            owner: "alice".to_string(),
+            expires_at: Some(1_700_000_000_000),
+            client_order_id: Some("ord-1".to_string()),
+        });
+        let Request::PlaceOrder(decoded) = round_trip(&place_order) else {
+            panic!("Expected a place order request");
+        };
+        assert_eq!(decoded.order_type, OrderType::Ask);
+        assert_eq!((decoded.price, decoded.quantity), (10_100, 25));
+        assert_eq!(decoded.owner, "alice");
+        assert_eq!(decoded.expires_at, Some(1_700_000_000_000));
+        assert_eq!(decoded.client_order_id.as_deref(), Some("ord-1"));
+
+        let order_id = Uuid::new_v4();
+        let cancel = Request::CancelOrder(CancelOrderArgs { order_id });
+        assert!(matches!(
+            round_trip(&cancel),
+            Request::CancelOrder(args) if args.order_id == order_id
+        ));
+
+        // Anything else is left to serde
+        assert!(!Request::ViewL2Book.encode_fixed(&mut Vec::new()));
+    }
+
+    #[test]
+    fn test_responses_round_trip() {
+        let report = ExecutionReport {
+            order_id: Uuid::new_v4(),
+            filled_quantity: 4,
+            status: OrderStatus::Resting {
+                remaining_qty: 6,
+                price: 100,
+                side: OrderType::Bid,
+                timestamp: 1_000,
+                arrival_seq: 7,
+            },
+            timestamps: Some(OrderTimestamps {
+                received_nanos: Some(10),
+                matched_nanos: 20,
+                acked_nanos: None,
+            }),
+            self_match_prevention: Some(SelfMatchPrevention::DecrementAndCancel),
+        };
+        assert!(matches!(
+            round_trip(&Response::PlaceOk(report)),
+            Response::PlaceOk(decoded) if decoded == report
+        ));
+        assert!(matches!(
+            round_trip(&Response::CancelOk(6)),
+            Response::CancelOk(6)
+        ));
+    }
+
+    #[test]
+    fn test_malformed_bodies_are_rejected() {
+        let mut buf = Vec::new();
+        Response::CancelOk(6).encode_fixed(&mut buf);
+        assert!(Response::decode_fixed(&buf[..buf.len() - 1]).is_err());
+        buf.push(0);
+        assert!(Response::decode_fixed(&buf).is_err());
+        assert!(Request::decode_fixed(&[99]).is_err());
+        assert!(Request::decode_fixed(&[]).is_err());
+    }
+}
@@ -0,0 +1,628 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    book::{BookCapacity, OrderBook, PriceLevelIndex},
+    exchange::Exchange,
+    instrument::{Instrument, InstrumentCheck, InstrumentStatus, OptionSeries},
+    matching::MatchingAlgorithm,
+    price_feed::{JsonPriceFeed, DEFAULT_POLL_INTERVAL_MS},
+    rate_limit::RateLimit,
+    risk::RiskConfig,
+    router::{Router, RouterConfig},
+    tape::{TradeTape, DEFAULT_MEMORY_CAPACITY},
+};
+
+pub const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 8080;
+pub const DEFAULT_REPLICA_PORT: u16 = 8081;
+
+// Settings the server starts with, read from a TOML file. Anything left out
+// keeps its default, so an empty file is a valid config.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    // Port for order entry, which also answers queries
+    pub port: u16,
+    // Port answering only queries, so market data clients can be kept apart
+    // from order entry
+    pub market_data_port: Option<u16>,
+    // Port the replica serves market data on
+    pub replica_port: u16,
+    // Port serving latency metrics to Prometheus over HTTP
+    pub metrics_port: Option<u16>,
+    // Symbols traded, with the tick and lot size their orders are held to.
+    // A single book trades at most one, and an exchange lists each on a book
+    // of its own.
+    pub symbols: Vec<SymbolConfig>,
+    pub risk: RiskConfig,
+    // Trades the tape no longer holds in memory are kept here rather than
+    // dropped
+    pub data_dir: Option<PathBuf>,
+    // Limit on the requests of each connection
+    pub rate_limit: Option<RateLimit>,
+    // When connections that stalled or went quiet are closed
+    pub timeouts: ConnectionTimeouts,
+    // Largest message read from a client, see wire.rs
+    pub max_frame_len: Option<usize>,
+    // Read messages that aren't MessagePack as JSON, for hand written
+    // clients
+    pub json_fallback: bool,
+    // Most responses waiting to be written to a connection, fills and
+    // market data alike
+    pub outbox_limit: Option<usize>,
+    // What a connection falling behind its market data gets
+    pub backpressure: BackpressurePolicy,
+    // Size of book to allocate for at startup
+    pub capacity: Option<BookCapacity>,
+    // How the book finds its levels by price, e.g. a ladder for a known
+    // price band
+    pub price_levels: PriceLevelIndex,
+    // How incoming orders are split among the orders resting at a level
+    pub matching: MatchingAlgorithm,
+    // Venues hosted side by side, each with its own book built from the
+    // settings above. Without any the server runs a single book open to
+    // every client.
+    pub tenants: Vec<TenantConfig>,
+    // External feed marking the configured symbol
+    pub price_feed: Option<PriceFeedConfig>,
+    // Runs an exchange of the configured symbols, spread over matching
+    // shards, instead of a single book. Requests name the symbol they are
+    // for, see Request::OnSymbol.
+    pub exchange: Option<ExchangeConfig>,
+}
+
+// Limits after which the server closes a connection, ending its session.
+// Each is off when left out.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionTimeouts {
+    // Time a message has to arrive in full once it started arriving
+    pub read_timeout_ms: Option<u64>,
+    // Time without a message either way
+    pub idle_timeout_ms: Option<u64>,
+    // Time since the connection opened, however busy it is
+    pub max_lifetime_ms: Option<u64>,
+}
+
+// What a connection gets once more market data is waiting for it than its
+// outbox holds, i.e. the subscriber can't keep up
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackpressurePolicy {
+    // Updates replace the one of the same subscription still waiting, so
+    // the subscriber skips to the latest state and never queues more than
+    // one per subscription
+    #[default]
+    Conflate,
+    // Every update is queued, and those that don't fit are dropped. The next
+    // update of the subscription that fits is marked as following a gap.
+    DropAndMarkGap,
+    // Every update is queued, and the connection is closed once one doesn't
+    // fit
+    Disconnect,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TenantConfig {
+    pub id: String,
+    // Client ids whose signed requests go to the tenant's book. Each needs a
+    // secret among the server's client secrets.
+    pub clients: Vec<String>,
+}
+
+// How an exchange spreads its symbols over matching shards, see router.rs
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExchangeConfig {
+    pub shards: usize,
+    pub router: RouterStrategy,
+}
+
+impl Default for ExchangeConfig {
+    fn default() -> Self {
+        ExchangeConfig {
+            shards: 1,
+            router: RouterStrategy::Hash,
+        }
+    }
+}
+
+// Which shard owns a symbol, so hot symbols can be moved around without a
+// code change
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RouterStrategy {
+    // Spreads symbols evenly, but changing the shard count moves most of them
+    #[default]
+    Hash,
+    // Pins symbols to a shard, e.g. to isolate a hot one, and hashes the rest
+    Static {
+        routes: HashMap<String, usize>,
+    },
+    // Adding a shard only moves the symbols landing on its slice of the ring
+    ConsistentHash {
+        #[serde(default = "default_replicas")]
+        replicas: usize,
+    },
+}
+
+fn default_replicas() -> usize {
+    64
+}
+
+impl ExchangeConfig {
+    // Checked here, as the routers themselves panic on a bad shard count
+    pub fn router(&self) -> Result<Box<dyn Router>> {
+        if self.shards == 0 {
+            return Err(anyhow!("An exchange needs at least one shard"));
+        }
+        let hash = RouterConfig::Hash {
+            num_shards: self.shards,
+        };
+        let router = match &self.router {
+            RouterStrategy::Hash => hash,
+            RouterStrategy::Static { routes } => {
+                if let Some((symbol, shard)) =
+                    routes.iter().find(|(_, &shard)| shard >= self.shards)
+                {
+                    return Err(anyhow!(
+                        "{symbol} is routed to shard {shard} of only {}",
+                        self.shards
+                    ));
+                }
+                RouterConfig::Static {
+                    routes: routes.clone(),
+                    fallback: Box::new(hash),
+                }
+            }
+            RouterStrategy::ConsistentHash { replicas } => {
+                if *replicas == 0 {
+                    return Err(anyhow!(
+                        "A consistent hash router needs at least one replica"
+                    ));
+                }
+                RouterConfig::ConsistentHash {
+                    num_shards: self.shards,
+                    replicas: *replicas,
+                }
+            }
+        };
+        Ok(router.build())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SymbolConfig {
+    pub symbol: String,
+    pub tick_size: u32,
+    #[serde(default = "default_lot_size")]
+    pub lot_size: u64,
+    // Symbol the price feed quotes the instrument under
+    #[serde(default)]
+    pub mark_price_symbol: Option<String>,
+    // Terms of the option series the symbol trades, if it's one, so an
+    // exchange can quote it in its underlying's option chain
+    #[serde(default)]
+    pub option: Option<OptionSeries>,
+}
+
+fn default_lot_size() -> u64 {
+    1
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PriceFeedConfig {
+    // JSON file or http:// URL of mark prices by symbol, see price_feed.rs
+    pub source: String,
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+}
+
+fn default_poll_interval_ms() -> u64 {
+    DEFAULT_POLL_INTERVAL_MS
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: DEFAULT_BIND_ADDRESS.to_string(),
+            port: DEFAULT_PORT,
+            market_data_port: None,
+            replica_port: DEFAULT_REPLICA_PORT,
+            metrics_port: None,
+            symbols: Vec::new(),
+            risk: RiskConfig::default(),
+            data_dir: None,
+            rate_limit: None,
+            timeouts: ConnectionTimeouts::default(),
+            max_frame_len: None,
+            json_fallback: false,
+            outbox_limit: None,
+            backpressure: BackpressurePolicy::Conflate,
+            capacity: None,
+            price_levels: PriceLevelIndex::Tree,
+            matching: MatchingAlgorithm::PriceTime,
+            tenants: Vec::new(),
+            price_feed: None,
+            exchange: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn load(path: &Path) -> Result<ServerConfig> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        ServerConfig::parse(&text).with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<ServerConfig> {
+        let config: ServerConfig = toml::from_str(text)?;
+        match &config.exchange {
+            Some(exchange) => {
+                exchange.router()?;
+                config.instruments()?;
+                if !config.tenants.is_empty() || config.price_feed.is_some() {
+                    return Err(anyhow!(
+                        "Tenants and price feeds only cover servers without an exchange"
+                    ));
+                }
+            }
+            None => {
+                config.instrument()?;
+                config.mark_price_feed()?;
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.bind_address, self.port)
+    }
+
+    pub fn market_data_addr(&self) -> Option<String> {
+        self.market_data_port
+            .map(|port| format!("{}:{}", self.bind_address, port))
+    }
+
+    pub fn metrics_addr(&self) -> Option<String> {
+        self.metrics_port
+            .map(|port| format!("{}:{}", self.bind_address, port))
+    }
+
+    pub fn replica_addr(&self) -> String {
+        format!("{}:{}", self.bind_address, self.replica_port)
+    }
+
+    // Instruments of the configured symbols, open for trading
+    pub fn instruments(&self) -> Result<Vec<Instrument>> {
+        self.symbols
+            .iter()
+            .map(|symbol| {
+                let mut instrument = match &symbol.option {
+                    Some(series) => Instrument::option(
+                        &symbol.symbol,
+                        symbol.tick_size,
+                        symbol.lot_size,
+                        series.clone(),
+                    )?,
+                    None => Instrument::new(&symbol.symbol, symbol.tick_size, symbol.lot_size)?,
+                };
+                instrument.status = InstrumentStatus::Open;
+                instrument.mark_price_symbol = symbol.mark_price_symbol.clone();
+                Ok(instrument)
+            })
+            .collect()
+    }
+
+    // Instrument of the symbol a single book trades
+    pub fn instrument(&self) -> Result<Option<Instrument>> {
+        let mut instruments = self.instruments()?;
+        if instruments.len() > 1 {
+            return Err(anyhow!(
+                "A single book trades one symbol, so several need an exchange"
+            ));
+        }
+        Ok(instruments.pop())
+    }
+
+    // Price feed to poll, the symbol it marks the book under and how often
+    // it's polled
+    pub fn mark_price_feed(&self) -> Result<Option<(JsonPriceFeed, String, Duration)>> {
+        let Some(price_feed) = &self.price_feed else {
+            return Ok(None);
+        };
+        let mark_price_symbol = self
+            .instrument()?
+            .and_then(|instrument| instrument.mark_price_symbol)
+            .ok_or_else(|| anyhow!("The price feed needs a symbol with a mark_price_symbol"))?;
+        Ok(Some((
+            JsonPriceFeed::new(&price_feed.source)?,
+            mark_price_symbol,
+            Duration::from_millis(price_feed.poll_interval_ms),
+        )))
+    }
+
+    // Book set up with the configured risk limits, symbol, data directory,
+    // capacity, price level index and matching algorithm
+    pub fn build_book(&self) -> Result<OrderBook> {
+        let mut book = self.build_book_in(self.data_dir.as_deref())?;
+        if let Some(instrument) = self.instrument()? {
+            book.set_instrument_status(instrument.status);
+            book.add_risk_check(Box::new(InstrumentCheck { instrument }));
+        }
+        Ok(book)
+    }
+
+    // Same as build_book for one tenant, keeping its trades under a
+    // directory of its own
+    pub fn build_tenant_book(&self, tenant: &TenantConfig) -> Result<OrderBook> {
+        let data_dir = self
+            .data_dir
+            .as_ref()
+            .map(|data_dir| data_dir.join("tenants").join(&tenant.id));
+        let mut book = self.build_book_in(data_dir.as_deref())?;
+        if let Some(instrument) = self.instrument()? {
+            book.set_instrument_status(instrument.status);
+            book.add_risk_check(Box::new(InstrumentCheck { instrument }));
+        }
+        Ok(book)
+    }
+
+    // Exchange with the configured router, listing every configured symbol
+    // on a book of its own set up like build_book's, or None without one
+    pub fn build_exchange(&self) -> Result<Option<Exchange>> {
+        let Some(exchange_config) = &self.exchange else {
+            return Ok(None);
+        };
+        let mut exchange = Exchange::new(exchange_config.router()?);
+        for instrument in self.instruments()? {
+            let data_dir = self
+                .data_dir
+                .as_ref()
+                .map(|data_dir| data_dir.join("symbols").join(&instrument.symbol));
+            let book = self.build_book_in(data_dir.as_deref())?;
+            exchange.list_instrument_with_book(instrument, book)?;
+        }
+        Ok(Some(exchange))
+    }
+
+    fn build_book_in(&self, data_dir: Option<&Path>) -> Result<OrderBook> {
+        let mut book = OrderBook::with_risk_config(self.risk.clone());
+        book.set_price_level_index(self.price_levels)?;
+        book.set_matching_engine(self.matching.engine());
+        if let Some(capacity) = self.capacity {
+            book.reserve(capacity);
+        }
+        if let Some(data_dir) = data_dir {
+            book.set_trade_tape(TradeTape::open(
+                DEFAULT_MEMORY_CAPACITY,
+                data_dir.join("tape"),
+            )?);
+        }
+        Ok(book)
+    }
+}
+
+// Address in a server URL, which is host:port with an optional tcp://
+pub fn parse_server_url(url: &str) -> Result<String> {
+    let addr = match url.split_once("://") {
+        Some(("tcp", addr)) => addr,
+        Some((scheme, _)) => return Err(anyhow!("Unsupported scheme {scheme} in {url}")),
+        None => url,
+    };
+    match addr.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+            Ok(addr.to_string())
+        }
+        _ => Err(anyhow!("Expected host:port in {url}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        book::OrderType,
+        risk::{BandReference, RiskCheckKind},
+    };
+
+    #[test]
+    fn test_parse_config() {
+        assert_eq!(ServerConfig::parse("").unwrap(), ServerConfig::default());
+
+        let config = ServerConfig::parse(
+            r#"
+            bind_address = "0.0.0.0"
+            port = 9000
+            market_data_port = 9001
+            metrics_port = 9090
+            data_dir = "/var/lib/order_book"
+            matching = "pro_rata"
+            max_frame_len = 1048576
+            backpressure = "drop_and_mark_gap"
+
+            [[symbols]]
+            symbol = "ACME"
+            tick_size = 5
+            mark_price_symbol = "ACME.IDX"
+
+            [risk]
+            max_order_size = 100
+            band_reference = "mark_price"
+
+            [risk.participants.market_maker]
+            bypass = ["MaxOrderSize"]
+
+            [rate_limit]
+            requests_per_sec = 50
+            burst = 100
+
+            [timeouts]
+            idle_timeout_ms = 30000
+
+            [capacity]
+            levels = 1000
+            orders = 50000
+
+            [price_levels]
+            kind = "ladder"
+            min_price = 9000
+            max_price = 11000
+
+            [[tenants]]
+            id = "venue-a"
+            clients = ["desk-1", "desk-2"]
+
+            [price_feed]
+            source = "http://marks.local:8000/prices"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.addr(), "0.0.0.0:9000");
+        assert_eq!(config.market_data_addr().unwrap(), "0.0.0.0:9001");
+        assert_eq!(config.metrics_addr().unwrap(), "0.0.0.0:9090");
+        assert_eq!(config.replica_addr(), "0.0.0.0:8081");
+        assert_eq!(config.symbols[0].lot_size, 1);
+        assert_eq!(config.risk.max_order_size, Some(100));
+        assert!(config
+            .risk
+            .bypasses("market_maker", RiskCheckKind::MaxOrderSize));
+        assert_eq!(config.rate_limit.unwrap().burst, 100);
+        assert_eq!(config.timeouts.idle_timeout_ms, Some(30_000));
+        assert_eq!(config.timeouts.read_timeout_ms, None);
+        assert_eq!(config.max_frame_len, Some(1 << 20));
+        assert!(!config.json_fallback);
+        assert_eq!(config.backpressure, BackpressurePolicy::DropAndMarkGap);
+        assert_eq!(config.capacity.unwrap().orders_per_level, 0);
+        assert_eq!(config.matching, MatchingAlgorithm::ProRata);
+        assert_eq!(
+            config.price_levels,
+            PriceLevelIndex::Ladder {
+                min_price: 9000,
+                max_price: 11000,
+                tick_size: 1,
+            }
+        );
+        assert_eq!(config.tenants[0].id, "venue-a");
+        assert_eq!(config.tenants[0].clients, ["desk-1", "desk-2"]);
+        assert_eq!(config.risk.band_reference, BandReference::MarkPrice);
+        assert_eq!(config.price_feed.as_ref().unwrap().poll_interval_ms, 1_000);
+        let (_, mark_price_symbol, _) = config.mark_price_feed().unwrap().unwrap();
+        assert_eq!(mark_price_symbol, "ACME.IDX");
+
+        assert!(ServerConfig::parse("prot = 9000").is_err());
+        // A price feed has to mark the configured symbol
+        assert!(ServerConfig::parse(
+            r#"
+            [price_feed]
+            source = "marks.json"
+            "#
+        )
+        .is_err());
+        assert!(ServerConfig::parse(
+            r#"
+            [[symbols]]
+            symbol = "ACME"
+            tick_size = 0
+            "#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_exchange() {
+        let config = ServerConfig::parse(
+            r#"
+            [[symbols]]
+            symbol = "ACME"
+            tick_size = 5
+
+            [[symbols]]
+            symbol = "INITECH"
+            tick_size = 1
+
+            [exchange]
+            shards = 2
+            router = { kind = "static", routes = { ACME = 1 } }
+            "#,
+        )
+        .unwrap();
+        let exchange_config = config.exchange.as_ref().unwrap();
+        assert_eq!(exchange_config.shards, 2);
+        let mut exchange = config.build_exchange().unwrap().unwrap();
+        assert_eq!(exchange.shards().len(), 2);
+        assert_eq!(exchange.shard_for("ACME").unwrap(), 1);
+        let book = exchange.book_mut("ACME").unwrap();
+        assert!(book.place_order("alice", 101, 10, OrderType::Bid).is_err());
+        assert!(exchange.book("INITECH").is_some());
+        // A single book can't trade both
+        assert!(config.build_book().is_err());
+
+        let config = ServerConfig::parse("[exchange]").unwrap();
+        assert_eq!(config.exchange, Some(ExchangeConfig::default()));
+        let config = ServerConfig::parse(
+            r#"
+            [exchange]
+            router = { kind = "consistent_hash" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.exchange.unwrap().router,
+            RouterStrategy::ConsistentHash { replicas: 64 }
+        );
+
+        for invalid in [
+            "[exchange]\nshards = 0",
+            "[exchange]\nrouter = { kind = \"static\", routes = { ACME = 2 } }",
+            "[exchange]\nrouter = { kind = \"consistent_hash\", replicas = 0 }",
+            "[exchange]\nrouter = { kind = \"round_robin\" }",
+            "[exchange]\n[[tenants]]\nid = \"venue-a\"",
+        ] {
+            assert!(ServerConfig::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_parse_server_url() {
+        assert_eq!(
+            parse_server_url("tcp://example.com:9000").unwrap(),
+            "example.com:9000"
+        );
+        assert_eq!(
+            parse_server_url("127.0.0.1:8080").unwrap(),
+            "127.0.0.1:8080"
+        );
+        assert!(parse_server_url("http://example.com:9000").is_err());
+        assert!(parse_server_url("example.com").is_err());
+        assert!(parse_server_url(":9000").is_err());
+    }
+
+    #[test]
+    fn test_book_holds_orders_to_tick_size() {
+        let config = ServerConfig::parse(
+            r#"
+            [[symbols]]
+            symbol = "ACME"
+            tick_size = 5
+            lot_size = 10
+            "#,
+        )
+        .unwrap();
+        let mut book = config.build_book().unwrap();
+        assert!(book.place_order("alice", 100, 10, OrderType::Bid).is_ok());
+        assert!(book.place_order("alice", 101, 10, OrderType::Bid).is_err());
+        assert!(book.place_order("alice", 100, 15, OrderType::Bid).is_err());
+    }
+}
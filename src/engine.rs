@@ -0,0 +1,262 @@
+use anyhow::{anyhow, Result};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tokio::sync::{mpsc, oneshot, watch};
+
+use crate::{book::OrderBook, exchange::Exchange, fills::FillRouter};
+
+// Commands waiting for the matching task before new ones are turned away
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1_024;
+
+type Job<T = OrderBook> = Box<dyn FnOnce(&mut T) + Send>;
+
+// Handle to a matching task that owns an order book outright. Every read and
+// write is queued as a command and applied one at a time in arrival order,
+// so no lock is ever taken on the book. Cloning the handle is cheap.
+#[derive(Clone)]
+pub struct BookHandle {
+    commands: mpsc::Sender<Job>,
+    fills: FillRouter,
+    // Sequence number of the latest event the book published
+    published: watch::Receiver<u64>,
+    // Set while the book follows a primary, see replication.rs
+    standby: Arc<AtomicBool>,
+}
+
+// Queue was full, so the command was dropped without touching the book
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFull;
+
+impl std::fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Matching queue is full")
+    }
+}
+
+impl std::error::Error for QueueFull {}
+
+impl BookHandle {
+    // Moves the book onto its own task. The task ends once every handle is
+    // dropped and the queue has drained.
+    pub fn spawn(book: OrderBook, queue_capacity: usize) -> BookHandle {
+        let (commands, mut queue) = mpsc::channel::<Job>(queue_capacity);
+        let fills = FillRouter::default();
+        let mut book = book;
+        book.add_listener(fills.listener());
+        let (publish, published) = watch::channel(book.last_event_seq());
+        tokio::spawn(async move {
+            while let Some(job) = queue.recv().await {
+                job(&mut book);
+                publish.send_if_modified(|last_seq| {
+                    let changed = *last_seq != book.last_event_seq();
+                    *last_seq = book.last_event_seq();
+                    changed
+                });
+            }
+        });
+        BookHandle {
+            commands,
+            fills,
+            published,
+            standby: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Fills of the book's orders, for pushing to their owners
+    pub fn fills(&self) -> &FillRouter {
+        &self.fills
+    }
+
+    // Changes to the sequence number of the latest event, for streaming the
+    // event log as it grows
+    pub fn published(&self) -> watch::Receiver<u64> {
+        self.published.clone()
+    }
+
+    // A standby book only changes through the primary's events, so its
+    // connections answer queries alone until it's promoted
+    pub fn set_standby(&self, standby: bool) {
+        self.standby.store(standby, Ordering::SeqCst);
+    }
+
+    pub fn is_standby(&self) -> bool {
+        self.standby.load(Ordering::SeqCst)
+    }
+
+    // Runs `f` on the matching task, waiting for room in the queue if it is
+    // full. For housekeeping that must not be dropped.
+    pub async fn execute<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut OrderBook) -> R + Send + 'static,
+    {
+        let (job, reply) = job(f);
+        self.commands
+            .send(job)
+            .await
+            .map_err(|_| anyhow!("Matching task has stopped"))?;
+        reply
+            .await
+            .map_err(|_| anyhow!("Matching task dropped the command"))
+    }
+
+    // Runs `f` on the matching task unless the queue is full, in which case
+    // it fails straight away with QueueFull so the caller can shed load
+    pub async fn try_execute<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut OrderBook) -> R + Send + 'static,
+    {
+        self.try_submit(f)?
+            .await
+            .map_err(|_| anyhow!("Matching task dropped the command"))
+    }
+
+    // Queues `f` like try_execute without waiting for it to run. Its result
+    // comes through the receiver, after those of the commands queued before.
+    pub fn try_submit<R, F>(&self, f: F) -> Result<oneshot::Receiver<R>>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut OrderBook) -> R + Send + 'static,
+    {
+        let (job, reply) = job(f);
+        self.commands.try_send(job).map_err(|err| match err {
+            mpsc::error::TrySendError::Full(_) => anyhow!(QueueFull),
+            mpsc::error::TrySendError::Closed(_) => anyhow!("Matching task has stopped"),
+        })?;
+        Ok(reply)
+    }
+
+    // Commands that can still be queued before the queue is full
+    pub fn queue_room(&self) -> usize {
+        self.commands.capacity()
+    }
+}
+
+// Handle to a matching task that owns a whole exchange, every symbol's book
+// included, the way BookHandle owns a single book
+#[derive(Clone)]
+pub struct ExchangeHandle {
+    commands: mpsc::Sender<Job<Exchange>>,
+}
+
+impl ExchangeHandle {
+    pub fn spawn(exchange: Exchange, queue_capacity: usize) -> ExchangeHandle {
+        let (commands, mut queue) = mpsc::channel::<Job<Exchange>>(queue_capacity);
+        let mut exchange = exchange;
+        tokio::spawn(async move {
+            while let Some(job) = queue.recv().await {
+                job(&mut exchange);
+            }
+        });
+        ExchangeHandle { commands }
+    }
+
+    // Same as BookHandle::execute
+    pub async fn execute<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Exchange) -> R + Send + 'static,
+    {
+        let (job, reply) = job(f);
+        self.commands
+            .send(job)
+            .await
+            .map_err(|_| anyhow!("Matching task has stopped"))?;
+        reply
+            .await
+            .map_err(|_| anyhow!("Matching task dropped the command"))
+    }
+
+    // Same as BookHandle::try_submit
+    pub fn try_submit<R, F>(&self, f: F) -> Result<oneshot::Receiver<R>>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Exchange) -> R + Send + 'static,
+    {
+        let (job, reply) = job(f);
+        self.commands.try_send(job).map_err(|err| match err {
+            mpsc::error::TrySendError::Full(_) => anyhow!(QueueFull),
+            mpsc::error::TrySendError::Closed(_) => anyhow!("Matching task has stopped"),
+        })?;
+        Ok(reply)
+    }
+}
+
+fn job<T, R, F>(f: F) -> (Job<T>, oneshot::Receiver<R>)
+where
+    R: Send + 'static,
+    F: FnOnce(&mut T) -> R + Send + 'static,
+{
+    let (reply, receiver) = oneshot::channel();
+    let job: Job<T> = Box::new(move |target| {
+        // The caller may have given up waiting, which is fine
+        let _ = reply.send(f(target));
+    });
+    (job, receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::OrderType;
+
+    #[tokio::test]
+    async fn test_commands_apply_in_order() {
+        let handle = BookHandle::spawn(OrderBook::new(), 8);
+        handle
+            .execute(|book| book.place_order("alice", 100, 10, OrderType::Ask))
+            .await
+            .unwrap()
+            .unwrap();
+        handle
+            .try_execute(|book| book.place_order("bob", 100, 4, OrderType::Bid))
+            .await
+            .unwrap()
+            .unwrap();
+        let trade_count = handle
+            .execute(|book| book.session_stats().trade_count())
+            .await
+            .unwrap();
+        assert_eq!(trade_count, 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_full_queue_sheds_load() {
+        let handle = BookHandle::spawn(OrderBook::new(), 1);
+        // Hold the matching task so nothing leaves the queue
+        let (release, blocked) = std::sync::mpsc::channel::<()>();
+        let (started, has_started) = oneshot::channel();
+        let busy = tokio::spawn({
+            let handle = handle.clone();
+            async move {
+                handle
+                    .execute(move |_| {
+                        started.send(()).unwrap();
+                        blocked.recv().unwrap();
+                    })
+                    .await
+            }
+        });
+        has_started.await.unwrap();
+
+        let queued = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.execute(|book| book.is_halted()).await }
+        });
+        while handle.queue_room() > 0 {
+            tokio::task::yield_now().await;
+        }
+        let err = handle
+            .try_execute(|book| book.is_halted())
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<QueueFull>().is_some());
+
+        release.send(()).unwrap();
+        busy.await.unwrap().unwrap();
+        assert!(!queued.await.unwrap().unwrap());
+    }
+}
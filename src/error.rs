@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::{book::OrderStatus, risk::RiskRejection};
+
+// Why the book turned down an order or a cancel. Sent back to clients as is,
+// so they can tell rejections apart without parsing messages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OrderBookError {
+    // Price was zero
+    InvalidPrice,
+    // Quantity was zero
+    InvalidQuantity,
+    // Good-till-date order would have expired before it was placed
+    ExpiryInPast,
+    TradingHalted,
+    // Price is not a tick on the book's price ladder
+    PriceOffLadder(u32),
+    // Total quantity at the order's price level would overflow
+    QuantityOverflow,
+    RiskRejected(RiskRejection),
+    // Order or client order id the book holds no order for
+    UnknownOrder,
+    // Order left the book filled before it could be canceled
+    AlreadyFilled,
+    // Order was already canceled or expired
+    AlreadyCanceled,
+    AlreadyInAuction,
+    NotInAuction,
+    // Only an empty book can switch its price level index or load a snapshot
+    BookNotEmpty,
+    InvalidPriceLadder(String),
+    // Market order protected by ticks found no order on the other side to
+    // count them from
+    NoLiquidity,
+    // Request was turned away before reaching the book, as answered with
+    // Response::AuthErr, Response::RateLimited and Response::Overloaded.
+    // Only sent as the reason of a Response::Rejected.
+    Unauthorized,
+    RateLimited,
+    Overloaded,
+}
+
+impl OrderBookError {
+    // Error for an order that already left the book with `status`
+    pub fn closed(status: OrderStatus) -> OrderBookError {
+        match status {
+            OrderStatus::Filled => OrderBookError::AlreadyFilled,
+            _ => OrderBookError::AlreadyCanceled,
+        }
+    }
+}
+
+impl fmt::Display for OrderBookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderBookError::InvalidPrice => write!(f, "Price should be bigger than 0"),
+            OrderBookError::InvalidQuantity => write!(f, "Quantity should be bigger than 0"),
+            OrderBookError::ExpiryInPast => write!(f, "Order expiry should be in the future"),
+            OrderBookError::TradingHalted => write!(f, "Trading is halted"),
+            OrderBookError::PriceOffLadder(price) => {
+                write!(f, "Price {price} is outside the book's price ladder")
+            }
+            OrderBookError::QuantityOverflow => {
+                write!(f, "Total quantity at price level would overflow")
+            }
+            OrderBookError::RiskRejected(rejection) => write!(f, "{rejection}"),
+            OrderBookError::UnknownOrder => write!(f, "Order cannot be found"),
+            OrderBookError::AlreadyFilled => write!(f, "Order is already filled"),
+            OrderBookError::AlreadyCanceled => write!(f, "Order is already canceled"),
+            OrderBookError::AlreadyInAuction => write!(f, "Book is already in an auction"),
+            OrderBookError::NotInAuction => write!(f, "Book is not in an auction"),
+            OrderBookError::BookNotEmpty => write!(f, "Book has to be empty for this"),
+            OrderBookError::InvalidPriceLadder(reason) => write!(f, "{reason}"),
+            OrderBookError::NoLiquidity => write!(f, "No orders on the other side of the book"),
+            OrderBookError::Unauthorized => write!(f, "Request is not authorized"),
+            OrderBookError::RateLimited => write!(f, "Connection is over its rate limit"),
+            OrderBookError::Overloaded => write!(f, "Matching queue is full"),
+        }
+    }
+}
+
+impl std::error::Error for OrderBookError {}
+
+impl From<RiskRejection> for OrderBookError {
+    fn from(rejection: RiskRejection) -> OrderBookError {
+        OrderBookError::RiskRejected(rejection)
+    }
+}
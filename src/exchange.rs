@@ -0,0 +1,980 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+use crate::{
+    batching::{AdaptiveBatcher, BatchingConfig, BatchingMetrics},
+    book::{L1Book, L2Entry, OrderBook, OrderType},
+    instrument::{Instrument, InstrumentCheck, InstrumentStatus, OptionKind},
+    price_feed::PriceFeed,
+    risk::RiskConfig,
+    router::Router,
+    schedule::{self, TradingCalendar},
+    settlement::SettlementReport,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookCommand {
+    PlaceOrder {
+        owner: String,
+        price: u32,
+        quantity: u64,
+        order_type: OrderType,
+    },
+    CancelOrder {
+        order_id: Uuid,
+    },
+    // Admin request moving the instrument through its lifecycle
+    SetStatus {
+        status: InstrumentStatus,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandOutcome {
+    Placed(Uuid),
+    Cancelled,
+    // Status the instrument was in before
+    StatusChanged(InstrumentStatus),
+}
+
+// Top of book of a symbol along with its trading status
+#[derive(Debug)]
+pub struct MarketData {
+    pub instrument: Instrument,
+    pub l1: L1Book,
+}
+
+// Best bid and ask of one option series
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChainQuote {
+    pub symbol: String,
+    pub status: InstrumentStatus,
+    pub bid: Option<L2Entry>,
+    pub ask: Option<L2Entry>,
+}
+
+// Call and put listed at one strike and expiry
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChainRow {
+    pub expires_at: u64,
+    pub strike: u32,
+    pub call: Option<ChainQuote>,
+    pub put: Option<ChainQuote>,
+}
+
+#[derive(Debug)]
+pub struct CommandResult {
+    pub ticket: u64,
+    pub symbol: String,
+    pub outcome: Result<CommandOutcome>,
+}
+
+// Commands of a busy symbol waiting to be applied together
+struct PendingBatch {
+    started_at: u64,
+    commands: Vec<(u64, BookCommand)>,
+}
+
+// Books of the symbols owned by one matching shard
+#[derive(Default)]
+pub struct Shard {
+    books: HashMap<String, OrderBook>,
+}
+
+impl Shard {
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.books.keys().map(String::as_str)
+    }
+
+    pub fn num_books(&self) -> usize {
+        self.books.len()
+    }
+}
+
+// One order book per symbol, spread over matching shards by a router
+pub struct Exchange {
+    router: Box<dyn Router>,
+    shards: Vec<Shard>,
+    // Symbols migrated by an admin, which take precedence over the router
+    migrated: HashMap<String, usize>,
+    // Every listed symbol, wherever its book lives
+    instruments: HashMap<String, Instrument>,
+    batcher: Option<AdaptiveBatcher>,
+    pending: HashMap<String, PendingBatch>,
+    next_ticket: u64,
+    results: Vec<CommandResult>,
+}
+
+impl Exchange {
+    pub fn new(router: Box<dyn Router>) -> Exchange {
+        let shards = (0..router.num_shards()).map(|_| Shard::default()).collect();
+        Exchange {
+            router,
+            shards,
+            migrated: HashMap::new(),
+            instruments: HashMap::new(),
+            batcher: None,
+            pending: HashMap::new(),
+            next_ticket: 1,
+            results: Vec::new(),
+        }
+    }
+
+    // Busy symbols will have their commands applied in micro-batches
+    pub fn enable_adaptive_batching(&mut self, config: BatchingConfig) {
+        self.batcher = Some(AdaptiveBatcher::new(config));
+    }
+
+    pub fn batching_metrics(&self, symbol: &str) -> Option<&BatchingMetrics> {
+        self.batcher.as_ref()?.metrics(symbol)
+    }
+
+    // Accepts a command for a symbol and returns its ticket. The outcome is
+    // available from take_results, straight away unless the symbol is busy
+    // enough to be batched, in which case it arrives when the batch flushes.
+    pub fn submit(&mut self, symbol: &str, command: BookCommand, now: u64) -> Result<u64> {
+        let instrument = self
+            .instruments
+            .get(symbol)
+            .ok_or_else(|| anyhow!("Symbol {symbol} is not listed"))?;
+        if let BookCommand::PlaceOrder {
+            price, quantity, ..
+        } = &command
+        {
+            instrument.validate_order(*price, *quantity)?;
+        }
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+
+        let batched = match &mut self.batcher {
+            Some(batcher) => batcher.record(symbol, now),
+            None => false,
+        };
+        if batched {
+            self.pending
+                .entry(symbol.to_string())
+                .or_insert_with(|| PendingBatch {
+                    started_at: now,
+                    commands: Vec::new(),
+                })
+                .commands
+                .push((ticket, command));
+        } else {
+            // Earlier batched commands must be applied first to keep ordering
+            self.flush(symbol);
+            self.apply(symbol, ticket, command);
+        }
+        Ok(ticket)
+    }
+
+    // Applies every batch that has waited for the maximum batch delay
+    pub fn flush_due(&mut self, now: u64) {
+        let Some(batcher) = &self.batcher else {
+            return;
+        };
+        let max_batch_delay_ms = batcher.config().max_batch_delay_ms;
+        let due: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, batch)| now >= batch.started_at.saturating_add(max_batch_delay_ms))
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        for symbol in due {
+            self.flush(&symbol);
+        }
+    }
+
+    pub fn flush(&mut self, symbol: &str) {
+        let Some(batch) = self.pending.remove(symbol) else {
+            return;
+        };
+        for (ticket, command) in batch.commands {
+            self.apply(symbol, ticket, command);
+        }
+        if let Some(batcher) = &mut self.batcher {
+            batcher.record_flush(symbol);
+        }
+    }
+
+    fn apply(&mut self, symbol: &str, ticket: u64, command: BookCommand) {
+        let outcome = self.apply_command(symbol, command);
+        self.results.push(CommandResult {
+            ticket,
+            symbol: symbol.to_string(),
+            outcome,
+        });
+    }
+
+    fn apply_command(&mut self, symbol: &str, command: BookCommand) -> Result<CommandOutcome> {
+        let (book, instrument) = self.listing_mut(symbol)?;
+        match command {
+            BookCommand::PlaceOrder {
+                owner,
+                price,
+                quantity,
+                order_type,
+            } => {
+                // The status may have changed while the order was batched
+                instrument.validate_order(price, quantity)?;
+                let order_id = book.place_order(&owner, price, quantity, order_type)?;
+                Ok(CommandOutcome::Placed(order_id))
+            }
+            BookCommand::CancelOrder { order_id } => {
+                book.cancel_order(order_id)?;
+                Ok(CommandOutcome::Cancelled)
+            }
+            BookCommand::SetStatus { status } => instrument
+                .transition(book, status)
+                .map(CommandOutcome::StatusChanged),
+        }
+    }
+
+    fn listing_mut(&mut self, symbol: &str) -> Result<(&mut OrderBook, &mut Instrument)> {
+        let shard = self.shard_for(symbol)?;
+        match (
+            self.shards[shard].books.get_mut(symbol),
+            self.instruments.get_mut(symbol),
+        ) {
+            (Some(book), Some(instrument)) => Ok((book, instrument)),
+            _ => Err(anyhow!("Symbol {symbol} is not listed")),
+        }
+    }
+
+    pub fn take_results(&mut self) -> Vec<CommandResult> {
+        std::mem::take(&mut self.results)
+    }
+
+    fn owner(&self, symbol: &str) -> usize {
+        match self.migrated.get(symbol) {
+            Some(&shard) => shard,
+            None => self.router.route(symbol),
+        }
+    }
+
+    pub fn shards(&self) -> &[Shard] {
+        &self.shards
+    }
+
+    pub fn shard_for(&self, symbol: &str) -> Result<usize> {
+        let shard = self.owner(symbol);
+        if shard >= self.shards.len() {
+            return Err(anyhow!("Router assigned {symbol} to missing shard {shard}"));
+        }
+        Ok(shard)
+    }
+
+    // Lists a symbol open for trading with a tick and lot size of 1
+    pub fn list_symbol(&mut self, symbol: &str) -> Result<()> {
+        let mut instrument = Instrument::new(symbol, 1, 1)?;
+        instrument.status = InstrumentStatus::Open;
+        self.list_instrument(instrument)
+    }
+
+    // Lists an instrument with a new book set up for its status
+    pub fn list_instrument(&mut self, instrument: Instrument) -> Result<()> {
+        self.list_instrument_with_risk_config(instrument, RiskConfig::default())
+    }
+
+    // Lists an instrument whose book has its own risk limits and circuit
+    // breakers
+    pub fn list_instrument_with_risk_config(
+        &mut self,
+        instrument: Instrument,
+        risk_config: RiskConfig,
+    ) -> Result<()> {
+        self.list_instrument_with_book(instrument, OrderBook::with_risk_config(risk_config))
+    }
+
+    // Lists an instrument on a book the caller set up, e.g. with a trade tape
+    // of its own. The book is held to the instrument's tick and lot size and
+    // brought in line with its status.
+    pub fn list_instrument_with_book(
+        &mut self,
+        instrument: Instrument,
+        mut book: OrderBook,
+    ) -> Result<()> {
+        let symbol = instrument.symbol.clone();
+        let shard = self.shard_for(&symbol)?;
+        if self.instruments.contains_key(&symbol) {
+            return Err(anyhow!("Symbol {symbol} is already listed"));
+        }
+        if let Some(series) = &instrument.option {
+            let listed = self
+                .instruments
+                .values()
+                .find(|listed| !listed.expired && listed.option.as_ref() == Some(series));
+            if let Some(listed) = listed {
+                return Err(anyhow!("Series is already listed as {}", listed.symbol));
+            }
+        }
+        book.add_risk_check(Box::new(InstrumentCheck {
+            instrument: instrument.clone(),
+        }));
+        match instrument.status {
+            InstrumentStatus::PreOpen => book.start_auction()?,
+            InstrumentStatus::Open => {}
+            InstrumentStatus::Halted | InstrumentStatus::Closed => book.halt(),
+        }
+        book.set_instrument_status(instrument.status);
+        self.shards[shard].books.insert(symbol.clone(), book);
+        self.instruments.insert(symbol, instrument);
+        Ok(())
+    }
+
+    pub fn instrument(&self, symbol: &str) -> Option<&Instrument> {
+        self.instruments.get(symbol)
+    }
+
+    // Moves an instrument to a new status straight away, after any of its
+    // batched commands. Returns the status it was in.
+    pub fn set_instrument_status(
+        &mut self,
+        symbol: &str,
+        status: InstrumentStatus,
+    ) -> Result<InstrumentStatus> {
+        self.flush(symbol);
+        let (book, instrument) = self.listing_mut(symbol)?;
+        instrument.transition(book, status)
+    }
+
+    // Moves every scheduled instrument to the status its trading hours call
+    // for at `now`. Returns the instruments that changed status, or failed to.
+    pub fn apply_calendar(
+        &mut self,
+        calendar: &TradingCalendar,
+        now: u64,
+    ) -> Vec<(String, Result<InstrumentStatus>)> {
+        let mut changes = Vec::new();
+        for (symbol, hours) in calendar.iter() {
+            // Expired instruments don't trade again, whatever their hours
+            if self
+                .instruments
+                .get(symbol)
+                .is_none_or(|instrument| instrument.expired)
+            {
+                continue;
+            }
+            self.flush(symbol);
+            let outcome = self.listing_mut(symbol).and_then(|(book, instrument)| {
+                schedule::follow_hours(instrument, book, hours, now)
+            });
+            match outcome {
+                Ok(None) => {}
+                Ok(Some(status)) => changes.push((symbol.to_string(), Ok(status))),
+                Err(err) => changes.push((symbol.to_string(), Err(err))),
+            }
+        }
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        changes
+    }
+
+    // Expires every instrument due at `now`, after any of its batched
+    // commands, settling it at the VWAP of its trades within
+    // `settlement_window_ms` of `now`
+    pub fn expire_due(
+        &mut self,
+        now: u64,
+        settlement_window_ms: u64,
+    ) -> Vec<(String, Result<SettlementReport>)> {
+        let mut due: Vec<String> = self
+            .instruments
+            .values()
+            .filter(|instrument| instrument.expiry_due(now))
+            .map(|instrument| instrument.symbol.clone())
+            .collect();
+        due.sort();
+        due.into_iter()
+            .map(|symbol| {
+                self.flush(&symbol);
+                let outcome = self.listing_mut(&symbol).and_then(|(book, instrument)| {
+                    instrument.expire(book, now, settlement_window_ms)
+                });
+                (symbol, outcome)
+            })
+            .collect()
+    }
+
+    // Polls the feed and marks every instrument it quotes. Returns the
+    // symbols that were marked.
+    pub fn apply_mark_prices(&mut self, feed: &mut dyn PriceFeed) -> Result<Vec<String>> {
+        let prices = feed.poll()?;
+        let mut marked: Vec<(String, u32)> = self
+            .instruments
+            .values()
+            .filter_map(|instrument| {
+                let price = prices.get(instrument.mark_price_symbol.as_ref()?)?;
+                Some((instrument.symbol.clone(), *price))
+            })
+            .collect();
+        marked.sort();
+        for (symbol, price) in &marked {
+            self.flush(symbol);
+            self.listing_mut(symbol)?.0.set_mark_price(*price);
+        }
+        Ok(marked.into_iter().map(|(symbol, _)| symbol).collect())
+    }
+
+    pub fn market_data(&self, symbol: &str) -> Option<MarketData> {
+        Some(MarketData {
+            instrument: self.instrument(symbol)?.clone(),
+            l1: self.book(symbol)?.view_book_l1(),
+        })
+    }
+
+    // Option series listed on `underlying` and yet to expire, by expiry then
+    // strike. `expires_at` narrows the chain down to one expiry.
+    pub fn option_chain(&self, underlying: &str, expires_at: Option<u64>) -> Vec<ChainRow> {
+        let mut rows: BTreeMap<(u64, u32), ChainRow> = BTreeMap::new();
+        for instrument in self.instruments.values() {
+            let Some(series) = &instrument.option else {
+                continue;
+            };
+            if series.underlying != underlying
+                || instrument.expired
+                || expires_at.is_some_and(|expires_at| expires_at != series.expires_at)
+            {
+                continue;
+            }
+            let Some(book) = self.book(&instrument.symbol) else {
+                continue;
+            };
+            let l1 = book.view_book_l1();
+            let quote = ChainQuote {
+                symbol: instrument.symbol.clone(),
+                status: instrument.status,
+                bid: l1.bid,
+                ask: l1.ask,
+            };
+            let row = rows
+                .entry((series.expires_at, series.strike))
+                .or_insert_with(|| ChainRow {
+                    expires_at: series.expires_at,
+                    strike: series.strike,
+                    call: None,
+                    put: None,
+                });
+            match series.kind {
+                OptionKind::Call => row.call = Some(quote),
+                OptionKind::Put => row.put = Some(quote),
+            }
+        }
+        rows.into_values().collect()
+    }
+
+    pub fn book(&self, symbol: &str) -> Option<&OrderBook> {
+        let shard = self.shard_for(symbol).ok()?;
+        self.shards[shard].books.get(symbol)
+    }
+
+    pub fn book_mut(&mut self, symbol: &str) -> Option<&mut OrderBook> {
+        let shard = self.shard_for(symbol).ok()?;
+        self.shards[shard].books.get_mut(symbol)
+    }
+
+    // Book of every listed symbol, on whichever shard it lives
+    pub fn books_mut(&mut self) -> impl Iterator<Item = &mut OrderBook> {
+        self.shards
+            .iter_mut()
+            .flat_map(|shard| shard.books.values_mut())
+    }
+
+    // Moves a listed symbol's book to another shard. The book is halted while
+    // it is transferred so no order can reach it half-moved, and trading
+    // resumes on the new shard with every resting order intact.
+    pub fn migrate_symbol(&mut self, symbol: &str, target_shard: usize) -> Result<usize> {
+        if target_shard >= self.shards.len() {
+            return Err(anyhow!("Shard {target_shard} does not exist"));
+        }
+        let source_shard = self.shard_for(symbol)?;
+        if source_shard == target_shard {
+            return Err(anyhow!(
+                "Symbol {symbol} is already on shard {target_shard}"
+            ));
+        }
+        let mut book = self.shards[source_shard]
+            .books
+            .remove(symbol)
+            .ok_or_else(|| anyhow!("Symbol {symbol} is not listed"))?;
+
+        // Quiesce, transfer, resume
+        let was_halted = book.is_halted();
+        book.halt();
+        self.shards[target_shard]
+            .books
+            .insert(symbol.to_string(), book);
+        self.migrated.insert(symbol.to_string(), target_shard);
+        if !was_halted {
+            self.shards[target_shard]
+                .books
+                .get_mut(symbol)
+                .unwrap()
+                .resume();
+        }
+        Ok(source_shard)
+    }
+
+    // Swaps the routing strategy and moves every book whose owner changed.
+    // Manual migrations are dropped in favour of the new router. Returns the
+    // symbols that moved.
+    pub fn set_router(&mut self, router: Box<dyn Router>) -> Vec<String> {
+        let books: Vec<(String, OrderBook)> = self
+            .shards
+            .iter_mut()
+            .flat_map(|shard| shard.books.drain())
+            .collect();
+        let previous_owners: HashMap<String, usize> = books
+            .iter()
+            .map(|(symbol, _)| (symbol.clone(), self.owner(symbol)))
+            .collect();
+
+        self.router = router;
+        self.migrated.clear();
+        self.shards
+            .resize_with(self.router.num_shards(), Shard::default);
+
+        let mut moved = Vec::new();
+        for (symbol, book) in books {
+            let shard = self.router.route(&symbol);
+            if previous_owners[&symbol] != shard {
+                moved.push(symbol.clone());
+            }
+            self.shards[shard].books.insert(symbol, book);
+        }
+        moved.sort();
+        moved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        book::{OrderType, TradingPhase},
+        clock::unix_millis,
+        instrument::OptionSeries,
+        risk::{BandReference, PriceBand},
+        router::{HashRouter, StaticRouter},
+        schedule::TradingHours,
+        settlement::DEFAULT_SETTLEMENT_WINDOW_MS,
+    };
+
+    #[test]
+    fn test_list_and_trade_symbols() {
+        let mut exchange = Exchange::new(Box::new(HashRouter::new(4)));
+        exchange.list_symbol("AAPL").unwrap();
+        exchange.list_symbol("MSFT").unwrap();
+        assert!(exchange.list_symbol("AAPL").is_err());
+        assert!(exchange.book("TSLA").is_none());
+
+        exchange
+            .book_mut("AAPL")
+            .unwrap()
+            .place_order("alice", 100, 10, OrderType::Bid)
+            .unwrap();
+        let shard = exchange.shard_for("AAPL").unwrap();
+        assert!(exchange.shards()[shard].symbols().any(|s| s == "AAPL"));
+    }
+
+    #[test]
+    fn test_migrate_symbol_keeps_orders() {
+        let mut exchange = Exchange::new(Box::new(HashRouter::new(2)));
+        exchange.list_symbol("AAPL").unwrap();
+        let book = exchange.book_mut("AAPL").unwrap();
+        book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        let order_id = book.place_order("alice", 101, 5, OrderType::Ask).unwrap();
+
+        let source_shard = exchange.shard_for("AAPL").unwrap();
+        let target_shard = 1 - source_shard;
+        assert!(exchange.migrate_symbol("AAPL", source_shard).is_err());
+        assert!(exchange.migrate_symbol("AAPL", 2).is_err());
+        assert!(exchange.migrate_symbol("MSFT", target_shard).is_err());
+        assert_eq!(
+            exchange.migrate_symbol("AAPL", target_shard).unwrap(),
+            source_shard
+        );
+
+        assert_eq!(exchange.shard_for("AAPL").unwrap(), target_shard);
+        assert_eq!(exchange.shards()[source_shard].num_books(), 0);
+        let book = exchange.book_mut("AAPL").unwrap();
+        assert!(!book.is_halted());
+        book.cancel_order(order_id).unwrap();
+        book.place_order("bob", 100, 10, OrderType::Bid).unwrap();
+        assert_eq!(book.session_stats().trade_count(), 1);
+    }
+
+    #[test]
+    fn test_migrate_halted_symbol_stays_halted() {
+        let mut exchange = Exchange::new(Box::new(HashRouter::new(2)));
+        exchange.list_symbol("AAPL").unwrap();
+        exchange.book_mut("AAPL").unwrap().halt();
+
+        let target_shard = 1 - exchange.shard_for("AAPL").unwrap();
+        exchange.migrate_symbol("AAPL", target_shard).unwrap();
+        assert!(exchange.book("AAPL").unwrap().is_halted());
+    }
+
+    fn place(price: u32, order_type: OrderType) -> BookCommand {
+        BookCommand::PlaceOrder {
+            owner: "alice".to_string(),
+            price,
+            quantity: 1,
+            order_type,
+        }
+    }
+
+    #[test]
+    fn test_busy_symbol_is_batched() {
+        let mut exchange = Exchange::new(Box::new(HashRouter::new(1)));
+        exchange.list_symbol("AAPL").unwrap();
+        exchange.enable_adaptive_batching(BatchingConfig {
+            window_ms: 100,
+            busy_threshold: 3,
+            calm_threshold: 1,
+            max_batch_delay_ms: 10,
+        });
+
+        for _ in 0..2 {
+            exchange
+                .submit("AAPL", place(100, OrderType::Ask), 0)
+                .unwrap();
+        }
+        assert_eq!(exchange.take_results().len(), 2);
+
+        // The third command in the window trips the threshold
+        let ticket = exchange
+            .submit("AAPL", place(100, OrderType::Bid), 5)
+            .unwrap();
+        exchange
+            .submit("AAPL", place(100, OrderType::Bid), 6)
+            .unwrap();
+        assert!(exchange.take_results().is_empty());
+        exchange.flush_due(14);
+        assert!(exchange.take_results().is_empty());
+
+        exchange.flush_due(15);
+        let results = exchange.take_results();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].ticket, ticket);
+        assert!(results.iter().all(|result| result.outcome.is_ok()));
+        assert_eq!(
+            exchange.book("AAPL").unwrap().session_stats().trade_count(),
+            2
+        );
+
+        let metrics = exchange.batching_metrics("AAPL").unwrap();
+        assert!(metrics.batching);
+        assert_eq!(metrics.batched_commands, 2);
+        assert_eq!(metrics.batches_flushed, 1);
+        assert_eq!(metrics.last_enabled_at, Some(5));
+    }
+
+    #[test]
+    fn test_pending_batch_applies_before_unbatched_command() {
+        let mut exchange = Exchange::new(Box::new(HashRouter::new(1)));
+        exchange.list_symbol("AAPL").unwrap();
+        exchange.enable_adaptive_batching(BatchingConfig {
+            window_ms: 100,
+            busy_threshold: 2,
+            calm_threshold: 3,
+            max_batch_delay_ms: 1_000,
+        });
+
+        // The second command is batched
+        for _ in 0..2 {
+            exchange
+                .submit("AAPL", place(100, OrderType::Ask), 0)
+                .unwrap();
+        }
+        // A quiet window turns batching off, flushing the queued command first
+        exchange
+            .submit("AAPL", place(100, OrderType::Bid), 200)
+            .unwrap();
+        let tickets: Vec<u64> = exchange
+            .take_results()
+            .iter()
+            .map(|result| result.ticket)
+            .collect();
+        assert_eq!(tickets, [1, 2, 3]);
+        assert_eq!(
+            exchange.book("AAPL").unwrap().session_stats().trade_count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_instrument_lifecycle() {
+        let mut exchange = Exchange::new(Box::new(HashRouter::new(2)));
+        exchange
+            .list_instrument(Instrument::new("AAPL", 5, 10).unwrap())
+            .unwrap();
+        assert!(exchange
+            .list_instrument(Instrument::new("AAPL", 1, 1).unwrap())
+            .is_err());
+
+        // Orders rest in the opening auction and have to fit the tick and lot size
+        let place = |owner: &str, price, order_type| BookCommand::PlaceOrder {
+            owner: owner.to_string(),
+            price,
+            quantity: 10,
+            order_type,
+        };
+        assert!(exchange
+            .submit("AAPL", place("alice", 102, OrderType::Ask), 0)
+            .is_err());
+        exchange
+            .submit("AAPL", place("alice", 100, OrderType::Ask), 0)
+            .unwrap();
+        exchange
+            .submit("AAPL", place("bob", 105, OrderType::Bid), 0)
+            .unwrap();
+        assert_eq!(
+            exchange.book("AAPL").unwrap().session_stats().trade_count(),
+            0
+        );
+
+        // Opening uncrosses the auction
+        assert_eq!(
+            exchange
+                .set_instrument_status("AAPL", InstrumentStatus::Open)
+                .unwrap(),
+            InstrumentStatus::PreOpen
+        );
+        assert_eq!(
+            exchange.book("AAPL").unwrap().session_stats().trade_count(),
+            1
+        );
+
+        let ticket = exchange
+            .submit(
+                "AAPL",
+                BookCommand::SetStatus {
+                    status: InstrumentStatus::Halted,
+                },
+                0,
+            )
+            .unwrap();
+        let results = exchange.take_results();
+        let result = results
+            .iter()
+            .find(|result| result.ticket == ticket)
+            .unwrap();
+        assert_eq!(
+            *result.outcome.as_ref().unwrap(),
+            CommandOutcome::StatusChanged(InstrumentStatus::Open)
+        );
+        assert!(exchange
+            .submit("AAPL", place("alice", 100, OrderType::Ask), 0)
+            .is_err());
+
+        exchange
+            .set_instrument_status("AAPL", InstrumentStatus::Closed)
+            .unwrap();
+        assert!(exchange
+            .set_instrument_status("AAPL", InstrumentStatus::Open)
+            .is_err());
+        let market_data = exchange.market_data("AAPL").unwrap();
+        assert_eq!(market_data.instrument.status, InstrumentStatus::Closed);
+        assert_eq!(
+            market_data.l1.instrument_status,
+            Some(InstrumentStatus::Closed)
+        );
+        assert!(exchange.market_data("MSFT").is_none());
+    }
+
+    #[test]
+    fn test_calendar_moves_scheduled_instruments() {
+        const HOUR_MS: u64 = 60 * 60 * 1_000;
+        let mut exchange = Exchange::new(Box::new(HashRouter::new(2)));
+        exchange.list_symbol("AAPL").unwrap();
+        exchange.list_symbol("MSFT").unwrap();
+        let mut calendar = TradingCalendar::default();
+        calendar.set_hours("AAPL", TradingHours::parse("08:00-09:30-16:00").unwrap());
+        calendar.set_hours("TSLA", TradingHours::parse("08:00-09:30-16:00").unwrap());
+
+        let changes = exchange.apply_calendar(&calendar, 8 * HOUR_MS);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].0, "AAPL");
+        assert_eq!(*changes[0].1.as_ref().unwrap(), InstrumentStatus::PreOpen);
+        assert_eq!(
+            exchange.book("AAPL").unwrap().phase(),
+            TradingPhase::Auction
+        );
+        // Unscheduled instruments are left alone
+        assert_eq!(
+            exchange.instrument("MSFT").unwrap().status,
+            InstrumentStatus::Open
+        );
+        assert!(exchange
+            .apply_calendar(&calendar, 8 * HOUR_MS + 1)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_expiry_settles_positions_and_closes() {
+        let mut exchange = Exchange::new(Box::new(HashRouter::new(2)));
+        let expires_at = unix_millis() + 60_000;
+        let mut instrument = Instrument::new("ESZ6", 1, 1)
+            .unwrap()
+            .with_expiry(expires_at);
+        instrument.status = InstrumentStatus::Open;
+        exchange.list_instrument(instrument).unwrap();
+        let book = exchange.book_mut("ESZ6").unwrap();
+        book.place_order("alice", 100, 4, OrderType::Ask).unwrap();
+        book.place_order("carol", 106, 2, OrderType::Ask).unwrap();
+        book.place_order("bob", 106, 6, OrderType::Bid).unwrap();
+        book.place_order("alice", 110, 5, OrderType::Ask).unwrap();
+        book.place_order("bob", 90, 5, OrderType::Bid).unwrap();
+
+        assert!(exchange
+            .expire_due(expires_at - 1, DEFAULT_SETTLEMENT_WINDOW_MS)
+            .is_empty());
+        let expired = exchange.expire_due(expires_at, DEFAULT_SETTLEMENT_WINDOW_MS);
+        assert_eq!(expired.len(), 1);
+        let report = expired[0].1.as_ref().unwrap();
+        // (100 * 4 + 106 * 2) / 6 = 102
+        assert_eq!(report.settlement_price, Some(102));
+        assert_eq!(report.expired_orders.len(), 2);
+
+        let book = exchange.book("ESZ6").unwrap();
+        assert!(book.view_book_l1().bid.is_none());
+        let clearing_house = book.clearing_house();
+        for (owner, cash) in [("alice", -8), ("bob", 0), ("carol", 8)] {
+            let statement = clearing_house.account_statement(owner);
+            assert_eq!((statement.position, statement.cash), (0, cash));
+        }
+
+        // Closed for good
+        let instrument = exchange.instrument("ESZ6").unwrap();
+        assert_eq!(instrument.status, InstrumentStatus::Closed);
+        assert!(instrument.expired);
+        assert!(exchange
+            .set_instrument_status("ESZ6", InstrumentStatus::PreOpen)
+            .is_err());
+        assert!(exchange
+            .expire_due(expires_at + 1, DEFAULT_SETTLEMENT_WINDOW_MS)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_option_chain_by_expiry_and_strike() {
+        const DEC: u64 = 1_797_638_400_000;
+        const MAR: u64 = 1_805_414_400_000;
+        let mut exchange = Exchange::new(Box::new(HashRouter::new(2)));
+        let series = |strike, expires_at, kind| OptionSeries {
+            underlying: "AAPL".to_string(),
+            strike,
+            expires_at,
+            kind,
+        };
+        for (symbol, series) in [
+            ("AAPL-DEC-150-C", series(150, DEC, OptionKind::Call)),
+            ("AAPL-DEC-150-P", series(150, DEC, OptionKind::Put)),
+            ("AAPL-DEC-140-C", series(140, DEC, OptionKind::Call)),
+            ("AAPL-MAR-150-C", series(150, MAR, OptionKind::Call)),
+        ] {
+            let mut instrument = Instrument::option(symbol, 1, 1, series).unwrap();
+            instrument.status = InstrumentStatus::Open;
+            exchange.list_instrument(instrument).unwrap();
+        }
+        exchange.list_symbol("AAPL").unwrap();
+        assert!(exchange
+            .list_instrument(
+                Instrument::option("AAPL-DEC-150-C2", 1, 1, series(150, DEC, OptionKind::Call))
+                    .unwrap()
+            )
+            .is_err());
+        assert!(
+            Instrument::option("AAPL-DEC-0-C", 1, 1, series(0, DEC, OptionKind::Call)).is_err()
+        );
+
+        let book = exchange.book_mut("AAPL-DEC-150-C").unwrap();
+        book.place_order("alice", 12, 5, OrderType::Bid).unwrap();
+        book.place_order("bob", 14, 3, OrderType::Ask).unwrap();
+        exchange
+            .book_mut("AAPL-DEC-150-P")
+            .unwrap()
+            .place_order("carol", 9, 2, OrderType::Ask)
+            .unwrap();
+
+        let chain = exchange.option_chain("AAPL", Some(DEC));
+        let strikes: Vec<u32> = chain.iter().map(|row| row.strike).collect();
+        assert_eq!(strikes, [140, 150]);
+        assert!(chain[0].put.is_none());
+        let call = chain[1].call.as_ref().unwrap();
+        assert_eq!(call.symbol, "AAPL-DEC-150-C");
+        assert_eq!(call.bid.as_ref().unwrap().price, 12);
+        assert_eq!(call.ask.as_ref().unwrap().price, 14);
+        let put = chain[1].put.as_ref().unwrap();
+        assert_eq!(
+            (put.bid.as_ref(), put.ask.as_ref().unwrap().price),
+            (None, 9)
+        );
+
+        // Every expiry without one, nearest first
+        let chain = exchange.option_chain("AAPL", None);
+        assert_eq!(chain.len(), 3);
+        assert_eq!((chain[2].expires_at, chain[2].strike), (MAR, 150));
+        assert!(exchange.option_chain("MSFT", None).is_empty());
+    }
+
+    #[test]
+    fn test_mark_prices_move_the_price_band() {
+        let mut exchange = Exchange::new(Box::new(HashRouter::new(2)));
+        let mut instrument = Instrument::new("ESZ6", 1, 1).unwrap();
+        instrument.status = InstrumentStatus::Open;
+        instrument.mark_price_symbol = Some("ES.DEC".to_string());
+        let risk_config = RiskConfig {
+            price_band: Some(PriceBand::new(500, false)),
+            band_reference: BandReference::MarkPrice,
+            ..Default::default()
+        };
+        exchange
+            .list_instrument_with_risk_config(instrument, risk_config)
+            .unwrap();
+        exchange.list_symbol("AAPL").unwrap();
+
+        let book = exchange.book_mut("ESZ6").unwrap();
+        book.set_reference_price(100);
+        book.place_order("alice", 120, 10, OrderType::Ask).unwrap();
+        assert!(book.place_order("bob", 120, 1, OrderType::Bid).is_err());
+
+        let mut feed = HashMap::from([("ES.DEC".to_string(), 118), ("AAPL".to_string(), 150)]);
+        assert_eq!(exchange.apply_mark_prices(&mut feed).unwrap(), ["ESZ6"]);
+        assert_eq!(exchange.book("AAPL").unwrap().mark_price(), None);
+        // The band is around the mark now rather than the last trade
+        let book = exchange.book_mut("ESZ6").unwrap();
+        assert_eq!(book.view_book_l1().mark_price, Some(118));
+        book.place_order("bob", 120, 1, OrderType::Bid).unwrap();
+        assert_eq!(book.session_stats().trade_count(), 1);
+    }
+
+    #[test]
+    fn test_rebalance_keeps_books() {
+        let mut exchange = Exchange::new(Box::new(HashRouter::new(2)));
+        for symbol in ["AAPL", "MSFT", "TSLA"] {
+            exchange.list_symbol(symbol).unwrap();
+        }
+        exchange
+            .book_mut("TSLA")
+            .unwrap()
+            .place_order("alice", 100, 10, OrderType::Ask)
+            .unwrap();
+
+        // Isolate the hot symbol on a new third shard
+        let routes = HashMap::from([("TSLA".to_string(), 2)]);
+        let router = StaticRouter::new(routes, Box::new(HashRouter::new(3)));
+        let moved = exchange.set_router(Box::new(router));
+
+        assert_eq!(exchange.shards().len(), 3);
+        assert!(moved.contains(&"TSLA".to_string()));
+        assert_eq!(exchange.shard_for("TSLA").unwrap(), 2);
+        assert!(exchange.shards()[2].symbols().any(|s| s == "TSLA"));
+        let book = exchange.book_mut("TSLA").unwrap();
+        book.place_order("bob", 100, 10, OrderType::Bid).unwrap();
+        assert_eq!(book.session_stats().trade_count(), 1);
+        let total_books: usize = exchange.shards().iter().map(Shard::num_books).sum();
+        assert_eq!(total_books, 3);
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::Result;
+use std::io::Write;
+
+use crate::book::{OpenOrder, OrderType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    // A JSON array of OpenOrder
+    Json,
+    // order_id,owner,side,price,quantity,expires_at lines after a header
+    Csv,
+}
+
+// Writes the orders one at a time, so a deep book is never held as one
+// string. The writer should be buffered.
+pub fn write_orders(
+    orders: impl IntoIterator<Item = OpenOrder>,
+    format: ExportFormat,
+    mut writer: impl Write,
+) -> Result<()> {
+    match format {
+        ExportFormat::Json => {
+            writer.write_all(b"[")?;
+            for (idx, order) in orders.into_iter().enumerate() {
+                writer.write_all(if idx == 0 { b"\n" } else { b",\n" })?;
+                serde_json::to_writer(&mut writer, &order)?;
+            }
+            writer.write_all(b"\n]\n")?;
+        }
+        ExportFormat::Csv => {
+            writeln!(writer, "order_id,owner,side,price,quantity,expires_at")?;
+            for order in orders {
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{}",
+                    order.order_id,
+                    order.owner,
+                    match order.order_type {
+                        OrderType::Bid => "bid",
+                        OrderType::Ask => "ask",
+                    },
+                    order.price,
+                    order.quantity,
+                    order
+                        .expires_at
+                        .map_or(String::new(), |expires_at| expires_at.to_string()),
+                )?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::OrderBook;
+
+    fn book() -> OrderBook {
+        let mut book = OrderBook::new();
+        book.place_order("alice", 101, 5, OrderType::Ask).unwrap();
+        book.place_order("bob", 99, 10, OrderType::Bid).unwrap();
+        book.place_order("carol", 100, 3, OrderType::Bid).unwrap();
+        book.place_order("dave", 99, 1, OrderType::Bid).unwrap();
+        book
+    }
+
+    #[test]
+    fn test_export_json_round_trips() {
+        let book = book();
+        let mut buf = Vec::new();
+        book.export(ExportFormat::Json, &mut buf).unwrap();
+        let orders: Vec<OpenOrder> = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(orders, book.l3_orders().collect::<Vec<_>>());
+        // Bids best first in time priority, then asks
+        assert_eq!(
+            orders
+                .iter()
+                .map(|order| order.owner.as_str())
+                .collect::<Vec<_>>(),
+            vec!["carol", "bob", "dave", "alice"]
+        );
+
+        let mut buf = Vec::new();
+        OrderBook::new()
+            .export(ExportFormat::Json, &mut buf)
+            .unwrap();
+        assert!(serde_json::from_slice::<Vec<OpenOrder>>(&buf)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let book = book();
+        let mut buf = Vec::new();
+        book.export(ExportFormat::Csv, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "order_id,owner,side,price,quantity,expires_at");
+        assert!(lines[1].ends_with(",carol,bid,100,3,"));
+        assert!(lines[4].ends_with(",alice,ask,101,5,"));
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use uuid::Uuid;
+
+use crate::{
+    book::{OrderStatus, OrderType, TradingPhase},
+    tape::Trade,
+};
+
+const DEFAULT_FEED_CAPACITY: usize = 100_000;
+
+// Every change to the state of a book. Replaying the events in sequence
+// order rebuilds the book, including its trade tape and statistics.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum BookEvent {
+    OrderRested {
+        order_id: Uuid,
+        owner: String,
+        order_type: OrderType,
+        price: u32,
+        quantity: u64,
+        expires_at: Option<u64>,
+        // When the book accepted the order, in unix milliseconds, and its
+        // place in the book's arrival order
+        timestamp: u64,
+        arrival_seq: u64,
+    },
+    // Resting order partially filled down to `quantity`
+    OrderReduced {
+        order_id: Uuid,
+        quantity: u64,
+    },
+    OrderRemoved {
+        order_id: Uuid,
+        // Filled or canceled
+        status: OrderStatus,
+    },
+    Traded(Trade),
+    Halted,
+    Resumed,
+    PhaseChanged(TradingPhase),
+    // End of day started a new session
+    SessionRolled {
+        settlement_price: Option<u32>,
+    },
+    // External mark price of the instrument changed
+    MarkPriceSet(u32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SequencedEvent {
+    pub seq: u64,
+    pub event: BookEvent,
+}
+
+// Sequenced log of the latest book events. Older events are dropped once
+// the capacity is reached, so a replica has to keep up with the feed.
+pub struct EventFeed {
+    events: VecDeque<SequencedEvent>,
+    capacity: usize,
+    last_seq: u64,
+}
+
+impl EventFeed {
+    pub fn new(capacity: usize) -> EventFeed {
+        EventFeed {
+            events: VecDeque::new(),
+            capacity,
+            last_seq: 0,
+        }
+    }
+
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    // Numbers events on from another feed's, e.g. for a book rebuilt from a
+    // snapshot. Only meant for a feed nothing was published to yet.
+    pub fn continue_from(&mut self, last_seq: u64) {
+        debug_assert!(self.events.is_empty());
+        self.last_seq = last_seq;
+    }
+
+    pub fn publish(&mut self, event: BookEvent) -> u64 {
+        let seq = self.last_seq + 1;
+        self.push(SequencedEvent { seq, event });
+        seq
+    }
+
+    // Appends an event produced elsewhere, e.g. by the primary of a replica.
+    // Events must arrive without gaps.
+    pub fn append(&mut self, event: SequencedEvent) -> Result<()> {
+        if event.seq != self.last_seq + 1 {
+            return Err(anyhow!(
+                "Expected event {} but received {}",
+                self.last_seq + 1,
+                event.seq
+            ));
+        }
+        self.push(event);
+        Ok(())
+    }
+
+    fn push(&mut self, event: SequencedEvent) {
+        self.last_seq = event.seq;
+        self.events.push_back(event);
+        if self.events.len() > self.capacity {
+            self.events.pop_front();
+        }
+    }
+
+    // Events with a sequence number of at least `from_seq`
+    pub fn events_since(&self, from_seq: u64, limit: usize) -> Result<Vec<SequencedEvent>> {
+        let first_seq = self.last_seq + 1 - self.events.len() as u64;
+        if from_seq < first_seq {
+            return Err(anyhow!("Events before {first_seq} are no longer retained"));
+        }
+        let skip = (from_seq - first_seq) as usize;
+        Ok(self.events.iter().skip(skip).take(limit).cloned().collect())
+    }
+}
+
+impl Default for EventFeed {
+    fn default() -> Self {
+        Self::new(DEFAULT_FEED_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_and_read() {
+        let mut feed = EventFeed::new(10);
+        assert_eq!(feed.publish(BookEvent::Halted), 1);
+        assert_eq!(feed.publish(BookEvent::Resumed), 2);
+
+        let events = feed.events_since(2, 10).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, BookEvent::Resumed);
+        assert!(feed.events_since(3, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_capacity_drops_oldest() {
+        let mut feed = EventFeed::new(2);
+        for _ in 0..3 {
+            feed.publish(BookEvent::Halted);
+        }
+        assert!(feed.events_since(1, 10).is_err());
+        assert_eq!(feed.events_since(2, 10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_append_rejects_gaps() {
+        let mut feed = EventFeed::default();
+        let event = |seq| SequencedEvent {
+            seq,
+            event: BookEvent::Halted,
+        };
+        assert!(feed.append(event(2)).is_err());
+        feed.append(event(1)).unwrap();
+        assert!(feed.append(event(1)).is_err());
+        feed.append(event(2)).unwrap();
+        assert_eq!(feed.last_seq(), 2);
+    }
+}
@@ -0,0 +1,262 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// Trailing window over which a participant's volume sets their fee tier
+pub const TIER_WINDOW_MS: u64 = 30 * 24 * 60 * 60 * 1_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct FeeSchedule {
+    pub version: u32,
+    // Unix timestamp in milliseconds from which this schedule applies
+    pub effective_from: u64,
+    pub maker_fee_bps: i32,
+    pub taker_fee_bps: i32,
+}
+
+impl FeeSchedule {
+    pub fn maker_fee(&self, price: u32, quantity: u64) -> i64 {
+        fee_for(price, quantity, self.maker_fee_bps)
+    }
+
+    pub fn taker_fee(&self, price: u32, quantity: u64) -> i64 {
+        fee_for(price, quantity, self.taker_fee_bps)
+    }
+}
+
+impl FeeTier {
+    pub fn maker_fee(&self, price: u32, quantity: u64) -> i64 {
+        fee_for(price, quantity, self.maker_fee_bps)
+    }
+
+    pub fn taker_fee(&self, price: u32, quantity: u64) -> i64 {
+        fee_for(price, quantity, self.taker_fee_bps)
+    }
+}
+
+// Rates replacing the active schedule's for participants whose traded
+// notional over the tier window reaches `min_volume`. A negative maker fee
+// is a rebate.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct FeeTier {
+    pub min_volume: u64,
+    pub maker_fee_bps: i32,
+    pub taker_fee_bps: i32,
+}
+
+// Tiers ordered by increasing volume. Participants below the first tier are
+// charged by the fee schedule.
+#[derive(Debug, Clone, Default)]
+pub struct FeeTiers {
+    tiers: Vec<FeeTier>,
+}
+
+impl FeeTiers {
+    pub fn new(tiers: Vec<FeeTier>) -> Result<FeeTiers> {
+        if tiers.first().is_some_and(|tier| tier.min_volume == 0) {
+            return Err(anyhow!("Fee tiers must require some volume"));
+        }
+        if tiers
+            .windows(2)
+            .any(|pair| pair[0].min_volume >= pair[1].min_volume)
+        {
+            return Err(anyhow!("Fee tiers must be in increasing order of volume"));
+        }
+        Ok(FeeTiers { tiers })
+    }
+
+    pub fn tiers(&self) -> &[FeeTier] {
+        &self.tiers
+    }
+
+    // Tier number, counting from 1, and rates of the highest tier reached.
+    // Tier 0 is the fee schedule.
+    pub fn tier_for(&self, volume: u64) -> (u32, Option<&FeeTier>) {
+        let reached = self.tiers.partition_point(|tier| tier.min_volume <= volume);
+        (
+            reached as u32,
+            reached.checked_sub(1).map(|idx| &self.tiers[idx]),
+        )
+    }
+}
+
+// Notional one participant traded within the tier window
+#[derive(Debug, Default)]
+pub struct RollingVolume {
+    // (timestamp, trade sequence number, notional) in timestamp order
+    fills: VecDeque<(u64, u64, u64)>,
+}
+
+impl RollingVolume {
+    pub fn record(&mut self, timestamp: u64, trade_seq: u64, notional: u64) {
+        let window_start = timestamp.saturating_sub(TIER_WINDOW_MS);
+        while self
+            .fills
+            .front()
+            .is_some_and(|&(filled_at, _, _)| filled_at <= window_start)
+        {
+            self.fills.pop_front();
+        }
+        self.fills.push_back((timestamp, trade_seq, notional));
+    }
+
+    // Busted trades don't count towards the tier
+    pub fn remove(&mut self, trade_seq: u64) {
+        self.fills.retain(|&(_, seq, _)| seq != trade_seq);
+    }
+
+    // Volume within the window ending at `now`
+    pub fn volume(&self, now: u64) -> u64 {
+        let window_start = now.saturating_sub(TIER_WINDOW_MS);
+        self.fills
+            .iter()
+            .filter(|&&(filled_at, _, _)| filled_at > window_start && filled_at <= now)
+            .fold(0u64, |total, &(_, _, notional)| {
+                total.saturating_add(notional)
+            })
+    }
+}
+
+fn fee_for(price: u32, quantity: u64, fee_bps: i32) -> i64 {
+    let notional = price as i128 * quantity as i128;
+    (notional * fee_bps as i128 / 10_000).clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+// Every fee schedule version ever applied or pending, ordered by the time it
+// takes effect. Trades are charged by the schedule active at their timestamp.
+#[derive(Debug, Clone)]
+pub struct FeeScheduleHistory {
+    schedules: Vec<FeeSchedule>,
+}
+
+impl FeeScheduleHistory {
+    pub fn new(maker_fee_bps: i32, taker_fee_bps: i32) -> FeeScheduleHistory {
+        FeeScheduleHistory {
+            schedules: vec![FeeSchedule {
+                version: 1,
+                effective_from: 0,
+                maker_fee_bps,
+                taker_fee_bps,
+            }],
+        }
+    }
+
+    pub fn schedules(&self) -> &[FeeSchedule] {
+        &self.schedules
+    }
+
+    // Schedules a new version taking effect at `effective_from`. Pending
+    // versions that would take effect at or after it are superseded.
+    pub fn schedule(
+        &mut self,
+        maker_fee_bps: i32,
+        taker_fee_bps: i32,
+        effective_from: u64,
+        now: u64,
+    ) -> Result<u32> {
+        if effective_from < now {
+            return Err(anyhow!("Fee schedule cannot take effect in the past"));
+        }
+
+        let version = self.schedules.last().unwrap().version + 1;
+        self.schedules
+            .retain(|schedule| schedule.effective_from < effective_from);
+        self.schedules.push(FeeSchedule {
+            version,
+            effective_from,
+            maker_fee_bps,
+            taker_fee_bps,
+        });
+        Ok(version)
+    }
+
+    pub fn active_at(&self, timestamp: u64) -> &FeeSchedule {
+        let idx = self
+            .schedules
+            .partition_point(|schedule| schedule.effective_from <= timestamp);
+        // The first schedule is effective from 0, so idx is always at least 1
+        &self.schedules[idx - 1]
+    }
+}
+
+impl Default for FeeScheduleHistory {
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_amounts() {
+        let history = FeeScheduleHistory::new(-1, 5);
+        let schedule = history.active_at(0);
+        assert_eq!(schedule.maker_fee(100, 1_000), -10);
+        assert_eq!(schedule.taker_fee(100, 1_000), 50);
+    }
+
+    #[test]
+    fn test_active_schedule_by_timestamp() {
+        let mut history = FeeScheduleHistory::new(1, 2);
+        assert_eq!(history.schedule(3, 4, 1_000, 500).unwrap(), 2);
+        assert_eq!(history.schedule(5, 6, 2_000, 500).unwrap(), 3);
+
+        assert_eq!(history.active_at(999).version, 1);
+        assert_eq!(history.active_at(1_000).version, 2);
+        assert_eq!(history.active_at(1_999).version, 2);
+        assert_eq!(history.active_at(5_000).version, 3);
+    }
+
+    #[test]
+    fn test_schedule_supersedes_pending_versions() {
+        let mut history = FeeScheduleHistory::new(1, 2);
+        history.schedule(3, 4, 2_000, 500).unwrap();
+        history.schedule(5, 6, 1_000, 500).unwrap();
+
+        assert_eq!(history.schedules().len(), 2);
+        assert_eq!(history.active_at(5_000).version, 3);
+        assert_eq!(history.active_at(5_000).maker_fee_bps, 5);
+    }
+
+    #[test]
+    fn test_tier_for_volume() {
+        let tier = |min_volume| FeeTier {
+            min_volume,
+            maker_fee_bps: -1,
+            taker_fee_bps: 3,
+        };
+        assert!(FeeTiers::new(vec![tier(0)]).is_err());
+        assert!(FeeTiers::new(vec![tier(10), tier(10)]).is_err());
+
+        let tiers = FeeTiers::new(vec![tier(1_000), tier(5_000)]).unwrap();
+        assert_eq!(tiers.tier_for(999), (0, None));
+        assert_eq!(tiers.tier_for(1_000).0, 1);
+        assert_eq!(tiers.tier_for(10_000).1.unwrap().min_volume, 5_000);
+    }
+
+    #[test]
+    fn test_rolling_volume_window() {
+        let mut volume = RollingVolume::default();
+        volume.record(1_000, 1, 100);
+        volume.record(2_000, 2, 50);
+        volume.record(3_000, 3, 25);
+        assert_eq!(volume.volume(3_000), 175);
+        assert_eq!(volume.volume(TIER_WINDOW_MS + 1_000), 75);
+
+        volume.remove(2);
+        assert_eq!(volume.volume(3_000), 125);
+
+        // Old fills are dropped as new ones arrive
+        volume.record(TIER_WINDOW_MS + 2_500, 4, 10);
+        assert_eq!(volume.fills.len(), 2);
+    }
+
+    #[test]
+    fn test_schedule_in_past_is_rejected() {
+        let mut history = FeeScheduleHistory::new(1, 2);
+        assert!(history.schedule(3, 4, 100, 500).is_err());
+        assert_eq!(history.schedules().len(), 1);
+    }
+}
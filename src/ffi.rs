@@ -0,0 +1,233 @@
+// C interface to the book, for C and C++ systems embedding the engine.
+// include/order_book.h declares it and is generated from this file with
+// `cbindgen --config cbindgen.toml --output include/order_book.h`.
+//
+// A book is created with ob_book_new and freed with ob_book_free, and is
+// not thread safe. Pointers passed in must be valid for the call, or null
+// where a function checks for it. Functions report failure as one of the
+// negative OB_ERR_* codes.
+#![allow(clippy::missing_safety_doc)]
+
+use std::{ffi::CStr, os::raw::c_char, ptr};
+use uuid::Uuid;
+
+use crate::{
+    book::{OrderBook, OrderType},
+    error::OrderBookError,
+};
+
+pub const OB_OK: i32 = 0;
+// A pointer argument was null
+pub const OB_ERR_NULL: i32 = -1;
+// Side was neither OB_SIDE_BID nor OB_SIDE_ASK, or the owner not UTF-8
+pub const OB_ERR_ARGUMENT: i32 = -2;
+pub const OB_ERR_INVALID_PRICE: i32 = -3;
+pub const OB_ERR_INVALID_QUANTITY: i32 = -4;
+pub const OB_ERR_EXPIRY_IN_PAST: i32 = -5;
+pub const OB_ERR_TRADING_HALTED: i32 = -6;
+pub const OB_ERR_PRICE_OFF_LADDER: i32 = -7;
+pub const OB_ERR_QUANTITY_OVERFLOW: i32 = -8;
+pub const OB_ERR_RISK_REJECTED: i32 = -9;
+pub const OB_ERR_UNKNOWN_ORDER: i32 = -10;
+pub const OB_ERR_ALREADY_FILLED: i32 = -11;
+pub const OB_ERR_ALREADY_CANCELED: i32 = -12;
+// Any other rejection by the book
+pub const OB_ERR_REJECTED: i32 = -13;
+
+pub const OB_SIDE_BID: u8 = 0;
+pub const OB_SIDE_ASK: u8 = 1;
+
+// Order id as the 16 bytes of its UUID
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObOrderId {
+    pub bytes: [u8; 16],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObLevel {
+    pub price: u32,
+    pub total_quantity: u64,
+    pub num_orders: u64,
+}
+
+fn error_code(err: OrderBookError) -> i32 {
+    match err {
+        OrderBookError::InvalidPrice => OB_ERR_INVALID_PRICE,
+        OrderBookError::InvalidQuantity => OB_ERR_INVALID_QUANTITY,
+        OrderBookError::ExpiryInPast => OB_ERR_EXPIRY_IN_PAST,
+        OrderBookError::TradingHalted => OB_ERR_TRADING_HALTED,
+        OrderBookError::PriceOffLadder(_) => OB_ERR_PRICE_OFF_LADDER,
+        OrderBookError::QuantityOverflow => OB_ERR_QUANTITY_OVERFLOW,
+        OrderBookError::RiskRejected(_) => OB_ERR_RISK_REJECTED,
+        OrderBookError::UnknownOrder => OB_ERR_UNKNOWN_ORDER,
+        OrderBookError::AlreadyFilled => OB_ERR_ALREADY_FILLED,
+        OrderBookError::AlreadyCanceled => OB_ERR_ALREADY_CANCELED,
+        _ => OB_ERR_REJECTED,
+    }
+}
+
+fn parse_side(side: u8) -> Option<OrderType> {
+    match side {
+        OB_SIDE_BID => Some(OrderType::Bid),
+        OB_SIDE_ASK => Some(OrderType::Ask),
+        _ => None,
+    }
+}
+
+// Empty book, to be freed with ob_book_free
+#[no_mangle]
+pub extern "C" fn ob_book_new() -> *mut OrderBook {
+    Box::into_raw(Box::new(OrderBook::new()))
+}
+
+// Frees a book from ob_book_new. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn ob_book_free(book: *mut OrderBook) {
+    if !book.is_null() {
+        drop(Box::from_raw(book));
+    }
+}
+
+// Places a good-till-canceled limit order for `owner`, a NUL terminated
+// string, and writes its id to `order_id` unless that's null. The order
+// may have traded in full by the time this returns.
+#[no_mangle]
+pub unsafe extern "C" fn ob_place(
+    book: *mut OrderBook,
+    owner: *const c_char,
+    side: u8,
+    price: u32,
+    quantity: u64,
+    order_id: *mut ObOrderId,
+) -> i32 {
+    let (Some(book), false) = (book.as_mut(), owner.is_null()) else {
+        return OB_ERR_NULL;
+    };
+    let (Ok(owner), Some(order_type)) = (CStr::from_ptr(owner).to_str(), parse_side(side)) else {
+        return OB_ERR_ARGUMENT;
+    };
+    match book.place_order(owner, price, quantity, order_type) {
+        Ok(placed_id) => {
+            if !order_id.is_null() {
+                ptr::write(
+                    order_id,
+                    ObOrderId {
+                        bytes: placed_id.into_bytes(),
+                    },
+                );
+            }
+            OB_OK
+        }
+        Err(err) => error_code(err),
+    }
+}
+
+// Cancels the order and writes the quantity that was still resting to
+// `canceled_quantity` unless that's null
+#[no_mangle]
+pub unsafe extern "C" fn ob_cancel(
+    book: *mut OrderBook,
+    order_id: *const ObOrderId,
+    canceled_quantity: *mut u64,
+) -> i32 {
+    let (Some(book), Some(order_id)) = (book.as_mut(), order_id.as_ref()) else {
+        return OB_ERR_NULL;
+    };
+    match book.cancel_order(Uuid::from_bytes(order_id.bytes)) {
+        Ok(quantity) => {
+            if !canceled_quantity.is_null() {
+                ptr::write(canceled_quantity, quantity);
+            }
+            OB_OK
+        }
+        Err(err) => error_code(err),
+    }
+}
+
+// Copies up to `capacity` levels of one side into `levels`, best price
+// first, and returns how many it copied, or a negative OB_ERR_* code.
+// Fewer than `capacity` means the side has no more levels.
+#[no_mangle]
+pub unsafe extern "C" fn ob_snapshot(
+    book: *const OrderBook,
+    side: u8,
+    levels: *mut ObLevel,
+    capacity: usize,
+) -> i64 {
+    let Some(book) = book.as_ref() else {
+        return OB_ERR_NULL.into();
+    };
+    if levels.is_null() && capacity > 0 {
+        return OB_ERR_NULL.into();
+    }
+    let Some(order_type) = parse_side(side) else {
+        return OB_ERR_ARGUMENT.into();
+    };
+    let mut copied = 0;
+    for (_, price_node) in book.top_levels(order_type, capacity) {
+        ptr::write(
+            levels.add(copied),
+            ObLevel {
+                price: price_node.price(),
+                total_quantity: price_node.total_quantity(),
+                num_orders: price_node.num_orders() as u64,
+            },
+        );
+        copied += 1;
+    }
+    copied as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_place_snapshot_and_cancel() {
+        unsafe {
+            let book = ob_book_new();
+            let alice = CString::new("alice").unwrap();
+            let mut ask_id = ObOrderId { bytes: [0; 16] };
+            for price in [101, 102] {
+                let status = ob_place(book, alice.as_ptr(), OB_SIDE_ASK, price, 10, &mut ask_id);
+                assert_eq!(status, OB_OK);
+            }
+            assert_eq!(
+                ob_place(book, alice.as_ptr(), OB_SIDE_BID, 0, 1, ptr::null_mut()),
+                OB_ERR_INVALID_PRICE
+            );
+            assert_eq!(
+                ob_place(book, alice.as_ptr(), 7, 100, 1, ptr::null_mut()),
+                OB_ERR_ARGUMENT
+            );
+
+            let mut levels = [ObLevel::default(); 4];
+            assert_eq!(ob_snapshot(book, OB_SIDE_ASK, levels.as_mut_ptr(), 1), 1);
+            assert_eq!(levels[0].price, 101);
+            assert_eq!(ob_snapshot(book, OB_SIDE_ASK, levels.as_mut_ptr(), 4), 2);
+            assert_eq!(
+                (
+                    levels[1].price,
+                    levels[1].total_quantity,
+                    levels[1].num_orders
+                ),
+                (102, 10, 1)
+            );
+            assert_eq!(ob_snapshot(book, OB_SIDE_BID, levels.as_mut_ptr(), 4), 0);
+
+            let mut canceled_quantity = 0;
+            assert_eq!(ob_cancel(book, &ask_id, &mut canceled_quantity), OB_OK);
+            assert_eq!(canceled_quantity, 10);
+            assert_eq!(
+                ob_cancel(book, &ask_id, &mut canceled_quantity),
+                OB_ERR_ALREADY_CANCELED
+            );
+            assert_eq!(ob_snapshot(book, OB_SIDE_ASK, levels.as_mut_ptr(), 4), 1);
+            assert_eq!(ob_cancel(book, ptr::null(), ptr::null_mut()), OB_ERR_NULL);
+            ob_book_free(book);
+        }
+    }
+}
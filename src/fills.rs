@@ -0,0 +1,195 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc;
+
+// Defined with the trades it's taken from, so it's available without `net`
+pub use crate::tape::Fill;
+use crate::{listener::BookListener, tape::Trade};
+
+// Fills kept for an owner with no session to push them to, oldest dropped first
+pub const MAX_QUEUED_FILLS: usize = 10_000;
+
+// Routes the fills of a book to the sessions subscribed to their owners.
+// Owners that subscribed once but have no session at the moment, e.g. while
+// reconnecting, have their fills queued until they subscribe again. Owners
+// that never subscribed aren't tracked. Cloning the router is cheap.
+#[derive(Clone, Default)]
+pub struct FillRouter {
+    inner: Arc<Mutex<RouterState>>,
+}
+
+#[derive(Default)]
+struct RouterState {
+    owners: HashMap<String, OwnerFills>,
+    next_session_id: u64,
+}
+
+#[derive(Default)]
+struct OwnerFills {
+    sessions: Vec<(u64, mpsc::UnboundedSender<Fill>)>,
+    queued: VecDeque<Fill>,
+}
+
+impl OwnerFills {
+    fn queue(&mut self, fill: Fill) {
+        if self.queued.len() == MAX_QUEUED_FILLS {
+            self.queued.pop_front();
+        }
+        self.queued.push_back(fill);
+    }
+}
+
+impl FillRouter {
+    // Listener to register on the book whose fills are routed
+    pub fn listener(&self) -> Box<dyn BookListener> {
+        Box::new(FillListener(self.clone()))
+    }
+
+    // Session receiving the owner's fills from now on, starting with those
+    // queued since its last session ended
+    pub fn subscribe(&self, owner: &str) -> FillSubscription {
+        let mut state = self.inner.lock().unwrap();
+        let session_id = state.next_session_id;
+        state.next_session_id += 1;
+        let owner_fills = state.owners.entry(owner.to_string()).or_default();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        for fill in owner_fills.queued.drain(..) {
+            // The receiver is held below, so sending can't fail
+            let _ = sender.send(fill);
+        }
+        owner_fills.sessions.push((session_id, sender));
+        FillSubscription {
+            router: self.clone(),
+            owner: owner.to_string(),
+            session_id,
+            receiver,
+        }
+    }
+
+    // Fills waiting for the owner to subscribe again
+    pub fn queued(&self, owner: &str) -> usize {
+        let state = self.inner.lock().unwrap();
+        state
+            .owners
+            .get(owner)
+            .map_or(0, |owner_fills| owner_fills.queued.len())
+    }
+
+    fn publish(&self, fill: Fill) {
+        let mut state = self.inner.lock().unwrap();
+        let Some(owner_fills) = state.owners.get_mut(&fill.owner) else {
+            return;
+        };
+        owner_fills
+            .sessions
+            .retain(|(_, sender)| sender.send(fill.clone()).is_ok());
+        if owner_fills.sessions.is_empty() {
+            owner_fills.queue(fill);
+        }
+    }
+}
+
+struct FillListener(FillRouter);
+
+impl BookListener for FillListener {
+    fn on_trade(&mut self, trade: &Trade) {
+        for fill in Fill::from_trade(trade) {
+            self.0.publish(fill);
+        }
+    }
+}
+
+// Fills of one owner for one session. Dropping it ends the session, and
+// fills it didn't receive are queued for the owner's next session.
+pub struct FillSubscription {
+    router: FillRouter,
+    owner: String,
+    session_id: u64,
+    receiver: mpsc::UnboundedReceiver<Fill>,
+}
+
+impl FillSubscription {
+    // Never None while the session is open. Cancel safe.
+    pub async fn recv(&mut self) -> Option<Fill> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for FillSubscription {
+    fn drop(&mut self) {
+        let mut state = self.router.inner.lock().unwrap();
+        let Some(owner_fills) = state.owners.get_mut(&self.owner) else {
+            return;
+        };
+        owner_fills
+            .sessions
+            .retain(|(session_id, _)| *session_id != self.session_id);
+        // Other sessions were sent the same fills
+        if owner_fills.sessions.is_empty() {
+            while let Ok(fill) = self.receiver.try_recv() {
+                owner_fills.queue(fill);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::{OrderBook, OrderType};
+
+    fn book_with(router: &FillRouter) -> OrderBook {
+        let mut book = OrderBook::new();
+        book.add_listener(router.listener());
+        book
+    }
+
+    #[tokio::test]
+    async fn test_fills_reach_both_owners() {
+        let router = FillRouter::default();
+        let mut book = book_with(&router);
+        let mut alice = router.subscribe("alice");
+        let mut bob = router.subscribe("bob");
+
+        let ask_id = book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        let bid_id = book.place_order("bob", 100, 4, OrderType::Bid).unwrap();
+
+        let maker_fill = alice.recv().await.unwrap();
+        assert_eq!(maker_fill.order_id, ask_id);
+        assert_eq!(maker_fill.side, OrderType::Ask);
+        assert!(maker_fill.maker);
+        assert_eq!((maker_fill.price, maker_fill.quantity), (100, 4));
+        let taker_fill = bob.recv().await.unwrap();
+        assert_eq!(taker_fill.order_id, bid_id);
+        assert!(!taker_fill.maker);
+        assert_eq!(taker_fill.trade_seq, maker_fill.trade_seq);
+    }
+
+    #[tokio::test]
+    async fn test_fills_queue_between_sessions() {
+        let router = FillRouter::default();
+        let mut book = book_with(&router);
+        // Never subscribed, so nothing is kept for carol
+        book.place_order("carol", 100, 1, OrderType::Ask).unwrap();
+        book.place_order("bob", 100, 1, OrderType::Bid).unwrap();
+        assert_eq!(router.queued("carol"), 0);
+
+        drop(router.subscribe("alice"));
+        book.place_order("alice", 100, 10, OrderType::Ask).unwrap();
+        book.place_order("bob", 100, 3, OrderType::Bid).unwrap();
+        book.place_order("bob", 100, 2, OrderType::Bid).unwrap();
+        assert_eq!(router.queued("alice"), 2);
+
+        let mut alice = router.subscribe("alice");
+        assert_eq!(router.queued("alice"), 0);
+        assert_eq!(alice.recv().await.unwrap().quantity, 3);
+        assert_eq!(alice.recv().await.unwrap().quantity, 2);
+
+        // Undelivered fills go back to the queue when the session ends
+        book.place_order("bob", 100, 1, OrderType::Bid).unwrap();
+        drop(alice);
+        assert_eq!(router.queued("alice"), 1);
+    }
+}
@@ -0,0 +1,254 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    book::{OrderBook, TradingPhase},
+    risk::{RiskCheck, RiskCheckKind, RiskOrder, RiskRejection},
+    settlement::SettlementReport,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstrumentStatus {
+    // Orders are accepted into the opening auction but don't match
+    PreOpen,
+    Open,
+    // Only cancels are accepted
+    Halted,
+    // Done for the day. Only goes back to PreOpen.
+    Closed,
+}
+
+impl InstrumentStatus {
+    pub fn can_transition_to(self, status: InstrumentStatus) -> bool {
+        match (self, status) {
+            (from, to) if from == to => false,
+            (InstrumentStatus::Closed, to) => to == InstrumentStatus::PreOpen,
+            _ => true,
+        }
+    }
+
+    pub fn accepts_orders(self) -> bool {
+        matches!(self, InstrumentStatus::PreOpen | InstrumentStatus::Open)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionKind {
+    Call,
+    Put,
+}
+
+// Contract terms of an option series, each listed as an instrument with
+// its own book
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OptionSeries {
+    // Symbol of the instrument the option is on
+    pub underlying: String,
+    pub strike: u32,
+    // Unix timestamp in milliseconds
+    pub expires_at: u64,
+    pub kind: OptionKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Instrument {
+    pub symbol: String,
+    // Prices have to be a multiple of the tick size
+    pub tick_size: u32,
+    // Quantities have to be a multiple of the lot size
+    pub lot_size: u64,
+    pub status: InstrumentStatus,
+    // Unix timestamp in milliseconds a futures-style instrument expires at,
+    // None for one that never does
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    // Settled at expiry and closed for good
+    #[serde(default)]
+    pub expired: bool,
+    // Terms of the series when the instrument is an option
+    #[serde(default)]
+    pub option: Option<OptionSeries>,
+    // Symbol an external price feed marks the instrument under, None when
+    // it isn't marked
+    #[serde(default)]
+    pub mark_price_symbol: Option<String>,
+}
+
+impl Instrument {
+    pub fn new(symbol: &str, tick_size: u32, lot_size: u64) -> Result<Instrument> {
+        if tick_size == 0 || lot_size == 0 {
+            return Err(anyhow!("Tick and lot size should be bigger than 0"));
+        }
+        Ok(Instrument {
+            symbol: symbol.to_string(),
+            tick_size,
+            lot_size,
+            status: InstrumentStatus::PreOpen,
+            expires_at: None,
+            expired: false,
+            option: None,
+            mark_price_symbol: None,
+        })
+    }
+
+    // Option series expiring along with its terms
+    pub fn option(
+        symbol: &str,
+        tick_size: u32,
+        lot_size: u64,
+        series: OptionSeries,
+    ) -> Result<Instrument> {
+        if series.strike == 0 {
+            return Err(anyhow!("Strike should be bigger than 0"));
+        }
+        let mut instrument =
+            Instrument::new(symbol, tick_size, lot_size)?.with_expiry(series.expires_at);
+        instrument.option = Some(series);
+        Ok(instrument)
+    }
+
+    pub fn with_expiry(mut self, expires_at: u64) -> Instrument {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    // Whether the instrument is due to be expired at `now`
+    pub fn expiry_due(&self, now: u64) -> bool {
+        !self.expired && self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn validate_order(&self, price: u32, quantity: u64) -> Result<()> {
+        if !self.status.accepts_orders() {
+            return Err(anyhow!(
+                "{} is not accepting orders while {:?}",
+                self.symbol,
+                self.status
+            ));
+        }
+        self.validate_increments(price, quantity)
+    }
+
+    // Whether the price is on a tick and the quantity a whole number of lots
+    pub fn validate_increments(&self, price: u32, quantity: u64) -> Result<()> {
+        if !price.is_multiple_of(self.tick_size) {
+            return Err(anyhow!(
+                "Price {price} is not a multiple of the tick size {}",
+                self.tick_size
+            ));
+        }
+        if !quantity.is_multiple_of(self.lot_size) {
+            return Err(anyhow!(
+                "Quantity {quantity} is not a multiple of the lot size {}",
+                self.lot_size
+            ));
+        }
+        Ok(())
+    }
+
+    // Moves to a new status and brings the book in line with it. Going into
+    // PreOpen starts the opening auction, which uncrosses when trading opens.
+    // Returns the previous status.
+    pub fn transition(
+        &mut self,
+        book: &mut OrderBook,
+        status: InstrumentStatus,
+    ) -> Result<InstrumentStatus> {
+        let previous = self.status;
+        if self.expired {
+            return Err(anyhow!("{} has expired", self.symbol));
+        }
+        if !previous.can_transition_to(status) {
+            return Err(anyhow!(
+                "{} cannot go from {previous:?} to {status:?}",
+                self.symbol
+            ));
+        }
+        match status {
+            InstrumentStatus::PreOpen => {
+                book.resume();
+                if book.phase() == TradingPhase::Continuous {
+                    book.start_auction()?;
+                }
+            }
+            InstrumentStatus::Open => {
+                book.resume();
+                if book.phase() == TradingPhase::Auction {
+                    book.uncross()?;
+                }
+            }
+            InstrumentStatus::Halted | InstrumentStatus::Closed => book.halt(),
+        }
+        self.status = status;
+        book.set_instrument_status(status);
+        Ok(previous)
+    }
+
+    // Cancels every resting order, settles every position at the VWAP of
+    // the trades within `settlement_window_ms` before `now`, and closes the
+    // instrument for good
+    pub fn expire(
+        &mut self,
+        book: &mut OrderBook,
+        now: u64,
+        settlement_window_ms: u64,
+    ) -> Result<SettlementReport> {
+        if self.expired {
+            return Err(anyhow!("{} has already expired", self.symbol));
+        }
+        if !self.expiry_due(now) {
+            return Err(anyhow!("{} is not due to expire", self.symbol));
+        }
+        let report = book.expire(now, settlement_window_ms)?;
+        self.status = InstrumentStatus::Closed;
+        book.set_instrument_status(self.status);
+        self.expired = true;
+        Ok(report)
+    }
+}
+
+// Holds the orders of an instrument's book to its tick and lot size. Its
+// status is left to the book, which each transition halts or puts into an
+// auction.
+pub struct InstrumentCheck {
+    pub instrument: Instrument,
+}
+
+impl RiskCheck for InstrumentCheck {
+    fn kind(&self) -> RiskCheckKind {
+        RiskCheckKind::Custom
+    }
+
+    fn check(&mut self, order: &RiskOrder, _: &OrderBook) -> Result<(), RiskRejection> {
+        self.instrument
+            .validate_increments(order.price, order.quantity)
+            .map_err(|err| RiskRejection::Custom(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_order() {
+        assert!(Instrument::new("AAPL", 0, 1).is_err());
+        let mut instrument = Instrument::new("AAPL", 5, 10).unwrap();
+        instrument.validate_order(105, 20).unwrap();
+        assert!(instrument.validate_order(104, 20).is_err());
+        assert!(instrument.validate_order(105, 25).is_err());
+
+        instrument.status = InstrumentStatus::Halted;
+        assert!(instrument.validate_order(105, 20).is_err());
+    }
+
+    #[test]
+    fn test_status_transitions() {
+        use InstrumentStatus::*;
+        assert!(PreOpen.can_transition_to(Open));
+        assert!(Open.can_transition_to(Halted));
+        assert!(Halted.can_transition_to(PreOpen));
+        assert!(!Open.can_transition_to(Open));
+        assert!(!Closed.can_transition_to(Open));
+        assert!(Closed.can_transition_to(PreOpen));
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// Account credited with fees charged by the clearing house
+pub const FEE_ACCOUNT: &str = "clearing_house:fees";
+// Counterparty for funds entering or leaving the exchange
+pub const EXTERNAL_ACCOUNT: &str = "clearing_house:external";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Asset {
+    // Quote currency
+    Cash,
+    // Units of the traded instrument
+    Position,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum EntryKind {
+    Trade { trade_seq: u64 },
+    Reversal { entry_id: u64 },
+    Deposit,
+    Withdrawal,
+    Transfer,
+    // Positions closed out in cash when the instrument expires
+    FinalSettlement { settlement_price: u32 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Posting {
+    pub account: String,
+    pub asset: Asset,
+    pub amount: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub id: u64,
+    pub timestamp: u64,
+    pub kind: EntryKind,
+    pub postings: Vec<Posting>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatementLine {
+    pub entry_id: u64,
+    pub timestamp: u64,
+    pub kind: EntryKind,
+    pub asset: Asset,
+    pub amount: i64,
+}
+
+// Append-only double-entry journal. Corrections are made by posting reversals,
+// so any statement can be regenerated exactly from the postings. Balances are
+// running totals of the postings, which check compares against the journal.
+#[derive(Default)]
+pub struct Ledger {
+    journal: Vec<JournalEntry>,
+    reversed: HashSet<u64>,
+    balances: HashMap<Asset, BTreeMap<String, i64>>,
+}
+
+impl Ledger {
+    pub fn new() -> Ledger {
+        Ledger::default()
+    }
+
+    pub fn journal(&self) -> &[JournalEntry] {
+        &self.journal
+    }
+
+    pub fn post(&mut self, timestamp: u64, kind: EntryKind, postings: Vec<Posting>) -> Result<u64> {
+        // Every asset must balance to zero across the entry
+        let mut totals: HashMap<Asset, i128> = HashMap::new();
+        for posting in &postings {
+            *totals.entry(posting.asset).or_default() += posting.amount as i128;
+        }
+        if totals.values().any(|&total| total != 0) {
+            return Err(anyhow!("Journal entry postings do not balance"));
+        }
+
+        for posting in &postings {
+            *self
+                .balances
+                .entry(posting.asset)
+                .or_default()
+                .entry(posting.account.clone())
+                .or_default() += posting.amount;
+        }
+        let id = self.journal.len() as u64 + 1;
+        self.journal.push(JournalEntry {
+            id,
+            timestamp,
+            kind,
+            postings,
+        });
+        Ok(id)
+    }
+
+    // Posts the exact negation of an entry, e.g. to bust a trade
+    pub fn reverse(&mut self, entry_id: u64, timestamp: u64) -> Result<u64> {
+        if self.reversed.contains(&entry_id) {
+            return Err(anyhow!("Journal entry is already reversed"));
+        }
+        let entry = self
+            .entry(entry_id)
+            .ok_or_else(|| anyhow!("Journal entry cannot be found"))?;
+        if let EntryKind::Reversal { .. } = entry.kind {
+            return Err(anyhow!("Reversals cannot be reversed"));
+        }
+
+        let postings = entry
+            .postings
+            .iter()
+            .map(|posting| Posting {
+                account: posting.account.clone(),
+                asset: posting.asset,
+                amount: -posting.amount,
+            })
+            .collect();
+        let reversal_id = self.post(timestamp, EntryKind::Reversal { entry_id }, postings)?;
+        self.reversed.insert(entry_id);
+        Ok(reversal_id)
+    }
+
+    pub fn entry(&self, entry_id: u64) -> Option<&JournalEntry> {
+        // Entry ids are assigned sequentially from 1
+        self.journal.get(entry_id.checked_sub(1)? as usize)
+    }
+
+    pub fn balance(&self, account: &str, asset: Asset) -> i64 {
+        self.balances
+            .get(&asset)
+            .and_then(|balances| balances.get(account))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // Balance of every account that has ever held the asset
+    pub fn balances(&self, asset: Asset) -> BTreeMap<String, i64> {
+        self.balances.get(&asset).cloned().unwrap_or_default()
+    }
+
+    // Recomputes every balance from the journal and checks the running totals
+    // agree. Scans the whole journal, so it is only run by the conservation check
+    pub fn check(&self) -> Result<()> {
+        let mut balances: HashMap<Asset, BTreeMap<String, i64>> = HashMap::new();
+        for posting in self.journal.iter().flat_map(|entry| entry.postings.iter()) {
+            *balances
+                .entry(posting.asset)
+                .or_default()
+                .entry(posting.account.clone())
+                .or_default() += posting.amount;
+        }
+        if balances != self.balances {
+            return Err(anyhow!("Ledger balances do not match its journal"));
+        }
+        Ok(())
+    }
+
+    pub fn statement(&self, account: &str) -> Vec<StatementLine> {
+        let mut lines = Vec::new();
+        for entry in &self.journal {
+            for posting in entry.postings.iter().filter(|p| p.account == account) {
+                lines.push(StatementLine {
+                    entry_id: entry.id,
+                    timestamp: entry.timestamp,
+                    kind: entry.kind.clone(),
+                    asset: posting.asset,
+                    amount: posting.amount,
+                });
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn posting(account: &str, asset: Asset, amount: i64) -> Posting {
+        Posting {
+            account: account.to_string(),
+            asset,
+            amount,
+        }
+    }
+
+    fn trade_postings() -> Vec<Posting> {
+        vec![
+            posting("alice", Asset::Position, 10),
+            posting("bob", Asset::Position, -10),
+            posting("alice", Asset::Cash, -1_000),
+            posting("bob", Asset::Cash, 1_000),
+        ]
+    }
+
+    #[test]
+    fn test_post_and_balance() {
+        let mut ledger = Ledger::new();
+        ledger
+            .post(0, EntryKind::Trade { trade_seq: 1 }, trade_postings())
+            .unwrap();
+
+        assert_eq!(ledger.balance("alice", Asset::Position), 10);
+        assert_eq!(ledger.balance("alice", Asset::Cash), -1_000);
+        assert_eq!(ledger.balance("bob", Asset::Position), -10);
+        assert_eq!(ledger.balance("bob", Asset::Cash), 1_000);
+        assert_eq!(ledger.balance("carol", Asset::Cash), 0);
+    }
+
+    #[test]
+    fn test_unbalanced_entry_is_rejected() {
+        let mut ledger = Ledger::new();
+        let postings = vec![
+            posting("alice", Asset::Cash, 10),
+            posting("bob", Asset::Cash, -9),
+        ];
+        assert!(ledger
+            .post(0, EntryKind::Trade { trade_seq: 1 }, postings)
+            .is_err());
+        assert!(ledger.journal().is_empty());
+    }
+
+    #[test]
+    fn test_reversal_restores_balances() {
+        let mut ledger = Ledger::new();
+        let entry_id = ledger
+            .post(0, EntryKind::Trade { trade_seq: 1 }, trade_postings())
+            .unwrap();
+        let reversal_id = ledger.reverse(entry_id, 1).unwrap();
+
+        assert_eq!(ledger.balance("alice", Asset::Position), 0);
+        assert_eq!(ledger.balance("bob", Asset::Cash), 0);
+        assert!(ledger.reverse(entry_id, 2).is_err());
+        assert!(ledger.reverse(reversal_id, 2).is_err());
+
+        // The statement keeps both the original and the reversal
+        let statement = ledger.statement("alice");
+        assert_eq!(statement.len(), 4);
+        assert_eq!(statement[2].kind, EntryKind::Reversal { entry_id });
+
+        // The running balances agree with the journal
+        ledger.check().unwrap();
+        assert_eq!(
+            ledger.balances(Asset::Cash),
+            BTreeMap::from([("alice".to_string(), 0), ("bob".to_string(), 0)])
+        );
+    }
+}
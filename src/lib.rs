@@ -1,7 +1,111 @@
+//! Limit order book with price-time priority matching, clearing and market
+//! data, plus the wire protocol spoken by the bundled server, replica and
+//! client binaries.
+//!
+//! To embed the engine, own an [`OrderBook`] directly and drive it through
+//! its methods. Matching is synchronous and single threaded, so hand the book
+//! to an [`engine::BookHandle`] (as `bin/server.rs` does) when sharing it
+//! between tasks, and register a [`BookListener`] to react to trades and
+//! cancels as they happen.
+//! Use [`exchange::Exchange`] for several symbols spread across shards, each
+//! listed as an [`instrument::Instrument`] with its own trading status and
+//! served through an [`engine::ExchangeHandle`] when `[exchange]` is
+//! configured, and
+//! [`smart_router::route_order`] to split one order across several books of
+//! the same instrument by the liquidity they display. Spreads between two
+//! books trade through a [`spread::SpreadBook`] implied into both.
+//!
+//! The price level storage is an implementation detail. Build with the
+//! `internals` feature to reach `linked_list`, `price_tree` and
+//! `price_ladder` directly, e.g. from benchmarks.
+//!
+//! Everything built on tokio, i.e. the server, the clients, the wire
+//! framing and [`engine::BookHandle`], sits behind the default `net`
+//! feature. Without it the book builds for `wasm32-unknown-unknown`, e.g.
+//! to reconstruct a book from its feed in the browser.
+//!
+//! The `python` feature builds a Python extension module exposing the book,
+//! e.g. with `maturin develop` as set up in pyproject.toml, and the `ffi`
+//! feature a C interface to it declared in include/order_book.h.
+
+pub mod accounting;
+pub mod analytics;
+pub mod auth;
+pub mod backtest;
+pub mod batching;
+#[cfg(feature = "net")]
+pub mod blocking;
 pub mod book;
+pub mod candles;
+pub mod clearing;
+#[cfg(feature = "net")]
+pub mod client;
+pub mod clock;
+pub mod codec;
+pub mod config;
+#[cfg(feature = "net")]
+pub mod engine;
+pub mod error;
+pub mod exchange;
+pub mod export;
+pub mod feed;
+pub mod fees;
+#[cfg(feature = "ffi")]
+pub(crate) mod ffi;
+#[cfg(feature = "net")]
+pub mod fills;
+pub mod instrument;
+pub mod ledger;
+#[cfg(feature = "internals")]
 pub mod linked_list;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod linked_list;
+pub mod listener;
+pub mod matching;
+pub mod metrics;
+pub mod netting;
 pub mod order;
+pub mod order_cache;
+pub mod price_feed;
+#[cfg(feature = "internals")]
+pub mod price_ladder;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod price_ladder;
+#[cfg(feature = "internals")]
 pub mod price_tree;
-pub mod wire;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod price_tree;
+#[cfg(feature = "python")]
+pub(crate) mod python;
+pub mod query;
+pub mod quoting;
+pub mod rate_limit;
+#[cfg(feature = "net")]
+pub mod replication;
+pub mod req;
 pub mod resp;
-pub mod req;
\ No newline at end of file
+pub mod risk;
+pub mod router;
+pub mod scenario;
+pub mod schedule;
+pub mod seed;
+#[cfg(feature = "net")]
+pub mod server;
+pub mod settlement;
+pub mod simulation;
+pub mod smart_router;
+pub mod spread;
+pub mod stats;
+#[cfg(feature = "net")]
+pub(crate) mod subscription;
+pub mod tape;
+#[cfg(feature = "net")]
+pub mod tenant;
+#[cfg(feature = "net")]
+pub mod wire;
+
+pub use book::{L1Book, L2Book, OpenOrder, OrderBook, OrderStatus, OrderType, TradingPhase};
+pub use error::OrderBookError;
+pub use listener::BookListener;
+pub use order::Order;
+pub use tape::Trade;
@@ -1,9 +1,20 @@
 use slab::Slab;
 
+// Handle to a value in a list. The slab reuses the slot of a removed value,
+// so the id also carries the generation the value was inserted in, and an id
+// kept after its value was removed finds nothing rather than whatever took
+// the slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    generation: u64,
+}
+
 struct SlabNode<T> {
     pub value: T,
     pub prev_id: Option<usize>,
     pub next_id: Option<usize>,
+    pub generation: u64,
 }
 
 pub struct SlabLinkedList<T> {
@@ -11,6 +22,9 @@ pub struct SlabLinkedList<T> {
     back_id: Option<usize>,
     len: usize,
     slab: Slab<SlabNode<T>>,
+    // Generation the next inserted value gets. Never reused, so no two
+    // values in the list's lifetime share one.
+    next_generation: u64,
 }
 
 impl<T> SlabLinkedList<T> {
@@ -20,6 +34,58 @@ impl<T> SlabLinkedList<T> {
             back_id: None,
             len: 0,
             slab: Slab::new(),
+            next_generation: 0,
+        }
+    }
+
+    // Room for `capacity` values before the slab has to grow
+    pub fn with_capacity(capacity: usize) -> SlabLinkedList<T> {
+        SlabLinkedList {
+            slab: Slab::with_capacity(capacity),
+            ..SlabLinkedList::new()
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slab.capacity()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.slab.reserve(additional);
+    }
+
+    // Frees room beyond the highest node id in use. Node ids stay valid, so
+    // a list with a late node left after removals keeps most of its room.
+    pub fn shrink_to_fit(&mut self) {
+        self.slab.shrink_to_fit();
+    }
+
+    // Index of the node behind the id, if it's still in the list
+    fn index_of(&self, node_id: NodeId) -> Option<usize> {
+        let node = self.slab.get(node_id.index)?;
+        (node.generation == node_id.generation).then_some(node_id.index)
+    }
+
+    fn id_at(&self, index: usize) -> NodeId {
+        NodeId {
+            index,
+            generation: self.slab[index].generation,
+        }
+    }
+
+    fn new_node(
+        &mut self,
+        value: T,
+        prev_id: Option<usize>,
+        next_id: Option<usize>,
+    ) -> SlabNode<T> {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        SlabNode {
+            value,
+            prev_id,
+            next_id,
+            generation,
         }
     }
 
@@ -27,6 +93,31 @@ impl<T> SlabLinkedList<T> {
         self.len == 0
     }
 
+    // Whether the links, the length and the slab agree: walking from the
+    // front visits every node once, each linked back to the one before, and
+    // ends at the back
+    pub fn is_consistent(&self) -> bool {
+        if self.len != self.slab.len() {
+            return false;
+        }
+        let mut prev_id = None;
+        let mut next_id = self.front_id;
+        let mut visited = 0;
+        while let Some(index) = next_id {
+            // More nodes than the length means the links loop
+            let Some(node) = self.slab.get(index) else {
+                return false;
+            };
+            if node.prev_id != prev_id || visited == self.len {
+                return false;
+            }
+            visited += 1;
+            prev_id = Some(index);
+            next_id = node.next_id;
+        }
+        visited == self.len && prev_id == self.back_id
+    }
+
     pub fn len(&self) -> usize {
         self.len
     }
@@ -85,61 +176,50 @@ impl<T> SlabLinkedList<T> {
         }
     }
 
-    pub fn push_front(&mut self, value: T) -> usize {
+    pub fn push_front(&mut self, value: T) -> NodeId {
         self.len += 1;
 
         match self.front_id {
             Some(front_id) => {
-                let node_id = self.slab.insert(SlabNode {
-                    value,
-                    prev_id: None,
-                    next_id: Some(front_id),
-                });
+                let node = self.new_node(value, None, Some(front_id));
+                let node_id = self.slab.insert(node);
                 self.slab[front_id].prev_id = Some(node_id);
                 self.front_id = Some(node_id);
-                node_id
+                self.id_at(node_id)
             }
             None => {
-                let node_id = self.slab.insert(SlabNode {
-                    value,
-                    prev_id: None,
-                    next_id: None,
-                });
+                let node = self.new_node(value, None, None);
+                let node_id = self.slab.insert(node);
                 self.front_id = Some(node_id);
                 self.back_id = Some(node_id);
-                node_id
+                self.id_at(node_id)
             }
         }
     }
 
-    pub fn push_back(&mut self, value: T) -> usize {
+    pub fn push_back(&mut self, value: T) -> NodeId {
         self.len += 1;
 
         match self.back_id {
             Some(back_id) => {
-                let node_id = self.slab.insert(SlabNode {
-                    value,
-                    prev_id: Some(back_id),
-                    next_id: None,
-                });
+                let node = self.new_node(value, Some(back_id), None);
+                let node_id = self.slab.insert(node);
                 self.slab[back_id].next_id = Some(node_id);
                 self.back_id = Some(node_id);
-                node_id
+                self.id_at(node_id)
             }
             None => {
-                let node_id = self.slab.insert(SlabNode {
-                    value,
-                    prev_id: None,
-                    next_id: None,
-                });
+                let node = self.new_node(value, None, None);
+                let node_id = self.slab.insert(node);
                 self.front_id = Some(node_id);
                 self.back_id = Some(node_id);
-                node_id
+                self.id_at(node_id)
             }
         }
     }
 
-    pub fn remove(&mut self, node_id: usize) -> Option<T> {
+    pub fn remove(&mut self, node_id: NodeId) -> Option<T> {
+        let node_id = self.index_of(node_id)?;
         match (self.front_id, self.back_id) {
             (Some(front_id), Some(back_id)) => {
                 if front_id == node_id {
@@ -170,33 +250,334 @@ impl<T> SlabLinkedList<T> {
         }
     }
 
-    // TODO: Needs testing
-    pub fn get_mut(&mut self, node_id: usize) -> Option<&mut T> {
-        match self.slab.get_mut(node_id) {
-            Some(node) => {
-                Some(&mut node.value)
-            }
-            None => None,
-        }
+    pub fn get_mut(&mut self, node_id: NodeId) -> Option<&mut T> {
+        let index = self.index_of(node_id)?;
+        Some(&mut self.slab[index].value)
     }
 
-    // TODO: Needs testing
-    pub fn get(&mut self, node_id: usize) -> Option<&T> {
-        match self.slab.get(node_id) {
-            Some(node) => {
-                Some(&node.value)
-            }
-            None => None,
-        }
+    pub fn get(&self, node_id: NodeId) -> Option<&T> {
+        let index = self.index_of(node_id)?;
+        Some(&self.slab[index].value)
     }
 
-    pub fn iter(&self) -> SlabLinkedListIter<T> {
+    pub fn iter(&self) -> SlabLinkedListIter<'_, T> {
         SlabLinkedListIter {
             next_id: self.front_id,
             next_back_id: self.back_id,
             slab: &self.slab,
         }
     }
+
+    // Walks the list in order like `iter`. The nodes are indexed once up
+    // front, so each step is a plain index rather than a slab lookup.
+    pub fn iter_mut(&mut self) -> SlabLinkedListIterMut<'_, T> {
+        let mut nodes: Vec<Option<&mut SlabNode<T>>> = Vec::with_capacity(self.slab.len());
+        for (node_id, node) in self.slab.iter_mut() {
+            nodes.resize_with(node_id, || None);
+            nodes.push(Some(node));
+        }
+        SlabLinkedListIterMut {
+            nodes,
+            next_id: self.front_id,
+            next_back_id: self.back_id,
+        }
+    }
+
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            current_id: self.front_id,
+            list: self,
+        }
+    }
+
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            current_id: self.back_id,
+            list: self,
+        }
+    }
+
+    // Cursor at the node, or None if it isn't in the list
+    pub fn cursor_at_mut(&mut self, node_id: NodeId) -> Option<CursorMut<'_, T>> {
+        let index = self.index_of(node_id)?;
+        Some(CursorMut {
+            current_id: Some(index),
+            list: self,
+        })
+    }
+
+    // Moves all of `other`'s values to the back, in order, leaving `other`
+    // empty. The values get new ids in this list. Each value is moved from
+    // one slab to the other, except when this list is empty, where the two
+    // just swap.
+    pub fn append(&mut self, other: &mut SlabLinkedList<T>) {
+        if self.is_empty() {
+            // Neither list may hand out a generation the other has used
+            let next_generation = self.next_generation.max(other.next_generation);
+            std::mem::swap(self, other);
+            self.next_generation = next_generation;
+            other.next_generation = next_generation;
+            return;
+        }
+        self.splice_between(other, self.back_id, None);
+    }
+
+    // Moves all of `other`'s values in between two neighbours, in order
+    fn splice_between(
+        &mut self,
+        other: &mut SlabLinkedList<T>,
+        mut prev_id: Option<usize>,
+        next_id: Option<usize>,
+    ) {
+        while let Some(value) = other.pop_front() {
+            prev_id = Some(self.insert_between(value, prev_id, next_id).index);
+        }
+    }
+
+    // Links a new node between two neighbours, either of which is None at
+    // the ends of the list
+    fn insert_between(
+        &mut self,
+        value: T,
+        prev_id: Option<usize>,
+        next_id: Option<usize>,
+    ) -> NodeId {
+        let node = self.new_node(value, prev_id, next_id);
+        let node_id = self.slab.insert(node);
+        match prev_id {
+            Some(prev_id) => self.slab[prev_id].next_id = Some(node_id),
+            None => self.front_id = Some(node_id),
+        }
+        match next_id {
+            Some(next_id) => self.slab[next_id].prev_id = Some(node_id),
+            None => self.back_id = Some(node_id),
+        }
+        self.len += 1;
+        self.id_at(node_id)
+    }
+}
+
+// Position in a list that can edit around itself without looking nodes up
+// again, e.g. to fill and remove orders in one walk down a price level. Past
+// either end it sits on a "ghost" position between the back and the front,
+// so moving on from there wraps around.
+pub struct CursorMut<'a, T> {
+    list: &'a mut SlabLinkedList<T>,
+    // None on the ghost position
+    current_id: Option<usize>,
+}
+
+impl<'a, T> CursorMut<'a, T> {
+    pub fn node_id(&self) -> Option<NodeId> {
+        Some(self.list.id_at(self.current_id?))
+    }
+
+    pub fn current(&mut self) -> Option<&mut T> {
+        Some(&mut self.list.slab[self.current_id?].value)
+    }
+
+    pub fn peek_next(&mut self) -> Option<&mut T> {
+        let next_id = match self.current_id {
+            Some(current_id) => self.list.slab[current_id].next_id,
+            None => self.list.front_id,
+        };
+        Some(&mut self.list.slab[next_id?].value)
+    }
+
+    pub fn peek_prev(&mut self) -> Option<&mut T> {
+        let prev_id = match self.current_id {
+            Some(current_id) => self.list.slab[current_id].prev_id,
+            None => self.list.back_id,
+        };
+        Some(&mut self.list.slab[prev_id?].value)
+    }
+
+    pub fn move_next(&mut self) {
+        self.current_id = match self.current_id {
+            Some(current_id) => self.list.slab[current_id].next_id,
+            None => self.list.front_id,
+        };
+    }
+
+    pub fn move_prev(&mut self) {
+        self.current_id = match self.current_id {
+            Some(current_id) => self.list.slab[current_id].prev_id,
+            None => self.list.back_id,
+        };
+    }
+
+    // Moves to the node and returns true, or stays put if it isn't in the list
+    pub fn seek(&mut self, node_id: NodeId) -> bool {
+        match self.list.index_of(node_id) {
+            Some(index) => {
+                self.current_id = Some(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Inserts before the current node, or at the back from the ghost
+    // position. Returns the new node's id and leaves the cursor where it was.
+    pub fn insert_before(&mut self, value: T) -> NodeId {
+        let (prev_id, next_id) = match self.current_id {
+            Some(current_id) => (self.list.slab[current_id].prev_id, Some(current_id)),
+            None => (self.list.back_id, None),
+        };
+        self.list.insert_between(value, prev_id, next_id)
+    }
+
+    // Inserts after the current node, or at the front from the ghost
+    // position. Returns the new node's id and leaves the cursor where it was.
+    pub fn insert_after(&mut self, value: T) -> NodeId {
+        let (prev_id, next_id) = match self.current_id {
+            Some(current_id) => (Some(current_id), self.list.slab[current_id].next_id),
+            None => (None, self.list.front_id),
+        };
+        self.list.insert_between(value, prev_id, next_id)
+    }
+
+    // Moves all of `other`'s values in before the current node, or at the
+    // back from the ghost position, leaving `other` empty. The cursor stays
+    // where it was.
+    pub fn splice_before(&mut self, other: &mut SlabLinkedList<T>) {
+        let (prev_id, next_id) = match self.current_id {
+            Some(current_id) => (self.list.slab[current_id].prev_id, Some(current_id)),
+            None => (self.list.back_id, None),
+        };
+        self.list.splice_between(other, prev_id, next_id);
+    }
+
+    // Moves all of `other`'s values in after the current node, or at the
+    // front from the ghost position, leaving `other` empty. The cursor stays
+    // where it was.
+    pub fn splice_after(&mut self, other: &mut SlabLinkedList<T>) {
+        let (prev_id, next_id) = match self.current_id {
+            Some(current_id) => (Some(current_id), self.list.slab[current_id].next_id),
+            None => (None, self.list.front_id),
+        };
+        self.list.splice_between(other, prev_id, next_id);
+    }
+
+    // Removes the current node and moves on to the next one
+    pub fn remove_current(&mut self) -> Option<T> {
+        let current_id = self.current_id?;
+        let node = self.list.slab.remove(current_id);
+        match node.prev_id {
+            Some(prev_id) => self.list.slab[prev_id].next_id = node.next_id,
+            None => self.list.front_id = node.next_id,
+        }
+        match node.next_id {
+            Some(next_id) => self.list.slab[next_id].prev_id = node.prev_id,
+            None => self.list.back_id = node.prev_id,
+        }
+        self.list.len -= 1;
+        self.current_id = node.next_id;
+        Some(node.value)
+    }
+}
+
+impl<T> Default for SlabLinkedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> IntoIterator for SlabLinkedList<T> {
+    type Item = T;
+    type IntoIter = SlabLinkedListIntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        SlabLinkedListIntoIter { list: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SlabLinkedList<T> {
+    type Item = (NodeId, &'a T);
+    type IntoIter = SlabLinkedListIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut SlabLinkedList<T> {
+    type Item = (NodeId, &'a mut T);
+    type IntoIter = SlabLinkedListIterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+// Values in list order. Node ids are left out, as they mean nothing once
+// the list is gone.
+pub struct SlabLinkedListIntoIter<T> {
+    list: SlabLinkedList<T>,
+}
+
+impl<T> Iterator for SlabLinkedListIntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.list.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.len(), Some(self.list.len()))
+    }
+}
+
+impl<T> DoubleEndedIterator for SlabLinkedListIntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.list.pop_back()
+    }
+}
+
+impl<T> ExactSizeIterator for SlabLinkedListIntoIter<T> {}
+
+pub struct SlabLinkedListIterMut<'a, T> {
+    // Node with each id, taken as it's handed out
+    nodes: Vec<Option<&'a mut SlabNode<T>>>,
+    next_id: Option<usize>,
+    next_back_id: Option<usize>,
+}
+
+impl<'a, T> SlabLinkedListIterMut<'a, T> {
+    // Takes the node, moving the ends on from it, or ends the iteration once
+    // they've met
+    fn take(&mut self, node_id: usize, forward: bool) -> (NodeId, &'a mut T) {
+        let node = self.nodes[node_id].take().unwrap(); // Each node is handed out once
+        if self.next_id == self.next_back_id {
+            self.next_id = None;
+            self.next_back_id = None;
+        } else if forward {
+            self.next_id = node.next_id;
+        } else {
+            self.next_back_id = node.prev_id;
+        }
+        let node_id = NodeId {
+            index: node_id,
+            generation: node.generation,
+        };
+        (node_id, &mut node.value)
+    }
+}
+
+impl<'a, T> Iterator for SlabLinkedListIterMut<'a, T> {
+    type Item = (NodeId, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node_id = self.next_id?;
+        Some(self.take(node_id, true))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for SlabLinkedListIterMut<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node_id = self.next_back_id?;
+        Some(self.take(node_id, false))
+    }
 }
 
 // Forward iterator
@@ -207,7 +588,7 @@ pub struct SlabLinkedListIter<'a, T> {
 }
 
 impl<'a, T> Iterator for SlabLinkedListIter<'a, T> {
-    type Item = (usize, &'a T);
+    type Item = (NodeId, &'a T);
 
     fn next(&mut self) -> Option<Self::Item> {
         match (self.next_id, self.next_back_id) {
@@ -219,6 +600,10 @@ impl<'a, T> Iterator for SlabLinkedListIter<'a, T> {
                 } else {
                     self.next_id = node.next_id;
                 }
+                let node_id = NodeId {
+                    index: node_id,
+                    generation: node.generation,
+                };
                 Some((node_id, &node.value))
             }
             _ => None,
@@ -238,6 +623,10 @@ impl<'a, T> DoubleEndedIterator for SlabLinkedListIter<'a, T> {
                 } else {
                     self.next_back_id = node.prev_id;
                 }
+                let node_id = NodeId {
+                    index: node_id,
+                    generation: node.generation,
+                };
                 Some((node_id, &node.value))
             }
             _ => None,
@@ -246,6 +635,7 @@ impl<'a, T> DoubleEndedIterator for SlabLinkedListIter<'a, T> {
 }
 
 #[cfg(test)]
+#[allow(clippy::useless_vec, clippy::vec_init_then_push)]
 mod tests {
     use super::*;
 
@@ -257,6 +647,24 @@ mod tests {
         assert!(!list.is_empty());
     }
 
+    #[test]
+    fn test_is_consistent() {
+        let mut list = SlabLinkedList::new();
+        assert!(list.is_consistent());
+        let middle = list.push_back(1);
+        list.push_back(2);
+        list.push_back(3);
+        list.remove(middle);
+        assert!(list.is_consistent());
+
+        list.len += 1;
+        assert!(!list.is_consistent());
+        list.len -= 1;
+        // Back points at the front
+        list.back_id = list.front_id;
+        assert!(!list.is_consistent());
+    }
+
     #[test]
     fn test_front_and_back() {
         let mut list = SlabLinkedList::new();
@@ -388,8 +796,20 @@ mod tests {
         let id1 = list.push_front(1);
         let id2 = list.push_back(2);
         // Removing a non-existent node_id should return None
-        assert_eq!(list.remove(id1 + 1000), None); // Assuming the list's capacity is  1000
-        assert_eq!(list.remove(id2 + 1000), None);
+        assert_eq!(
+            list.remove(NodeId {
+                index: id1.index + 1000,
+                ..id1
+            }),
+            None
+        ); // Assuming the list's capacity is  1000
+        assert_eq!(
+            list.remove(NodeId {
+                index: id2.index + 1000,
+                ..id2
+            }),
+            None
+        );
     }
 
     #[test]
@@ -496,4 +916,282 @@ mod tests {
         assert_eq!(iter.next(), None, "Iter should be exhausted");
         assert_eq!(iter.next_back(), None, "Iter should be exhausted");
     }
+
+    fn values(list: &SlabLinkedList<i32>) -> Vec<i32> {
+        list.iter().map(|(_, &value)| value).collect()
+    }
+
+    #[test]
+    fn test_cursor_moves_and_wraps() {
+        let mut list = SlabLinkedList::new();
+        let ids = [list.push_back(1), list.push_back(2), list.push_back(3)];
+
+        let mut cursor = list.cursor_front_mut();
+        assert_eq!(cursor.current(), Some(&mut 1));
+        assert_eq!(cursor.peek_prev(), None);
+        cursor.move_next();
+        assert_eq!(cursor.node_id(), Some(ids[1]));
+        assert_eq!(cursor.peek_next(), Some(&mut 3));
+        cursor.move_next();
+        cursor.move_next();
+        // Past the back onto the ghost, then round to the front
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.peek_next(), Some(&mut 1));
+        assert_eq!(cursor.peek_prev(), Some(&mut 3));
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&mut 1));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&mut 3));
+
+        assert!(cursor.seek(ids[1]));
+        assert_eq!(cursor.current(), Some(&mut 2));
+        assert!(!cursor.seek(NodeId {
+            index: ids[2].index + 1000,
+            ..ids[2]
+        }));
+        assert_eq!(cursor.node_id(), Some(ids[1]));
+
+        let mut cursor = list.cursor_back_mut();
+        assert_eq!(cursor.current(), Some(&mut 3));
+        assert!(list
+            .cursor_at_mut(NodeId {
+                index: ids[2].index + 1000,
+                ..ids[2]
+            })
+            .is_none());
+    }
+
+    #[test]
+    fn test_cursor_inserts() {
+        let mut list = SlabLinkedList::new();
+        let two = list.push_back(2);
+
+        let mut cursor = list.cursor_at_mut(two).unwrap();
+        cursor.insert_before(1);
+        let three = cursor.insert_after(3);
+        assert_eq!(cursor.current(), Some(&mut 2));
+        assert!(cursor.seek(three));
+        cursor.insert_after(4);
+        // From the ghost position, before is the back and after the front
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        cursor.insert_before(5);
+        cursor.insert_after(0);
+        assert_eq!(values(&list), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(list.len(), 6);
+        assert_eq!(list.iter().rev().map(|(_, &value)| value).next(), Some(5));
+
+        // Into an empty list
+        let mut list = SlabLinkedList::new();
+        let id = list.cursor_front_mut().insert_after(7);
+        assert_eq!(list.front(), Some(&7));
+        assert_eq!(list.back(), Some(&7));
+        assert_eq!(list.remove(id), Some(7));
+        list.cursor_back_mut().insert_before(8);
+        assert_eq!(values(&list), vec![8]);
+    }
+
+    #[test]
+    fn test_cursor_mutates_and_removes_in_one_pass() {
+        let mut list = SlabLinkedList::new();
+        for value in 1..=6 {
+            list.push_back(value);
+        }
+
+        // Drops the even values and doubles the odd ones, as matching fills
+        // and reduces orders
+        let mut cursor = list.cursor_front_mut();
+        while let Some(value) = cursor.current() {
+            if *value % 2 == 0 {
+                cursor.remove_current();
+            } else {
+                *value *= 2;
+                cursor.move_next();
+            }
+        }
+        assert_eq!(values(&list), vec![2, 6, 10]);
+        assert_eq!(list.len(), 3);
+        assert_eq!(
+            list.iter()
+                .rev()
+                .map(|(_, &value)| value)
+                .collect::<Vec<_>>(),
+            vec![10, 6, 2]
+        );
+
+        // Removing the ends keeps the list linked
+        let mut cursor = list.cursor_back_mut();
+        assert_eq!(cursor.remove_current(), Some(10));
+        assert_eq!(cursor.current(), None);
+        let mut cursor = list.cursor_front_mut();
+        assert_eq!(cursor.remove_current(), Some(2));
+        assert_eq!(cursor.current(), Some(&mut 6));
+        assert_eq!(cursor.remove_current(), Some(6));
+        assert_eq!(cursor.remove_current(), None);
+        assert!(list.is_empty());
+        assert_eq!(list.front(), None);
+        assert_eq!(list.back(), None);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut list = SlabLinkedList::with_capacity(16);
+        assert!(list.capacity() >= 16);
+        assert!(list.is_empty());
+        for value in 0..16 {
+            list.push_back(value);
+        }
+        assert!(list.capacity() >= 16);
+
+        list.reserve(100);
+        assert!(list.capacity() >= 116);
+        while list.len() > 1 {
+            list.pop_back();
+        }
+        list.shrink_to_fit();
+        assert!(list.capacity() < 116);
+        assert_eq!(list.front(), Some(&0));
+        list.push_back(1);
+        assert_eq!(values(&list), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut list = SlabLinkedList::new();
+        let ids: Vec<NodeId> = (1..=5).map(|value| list.push_back(value)).collect();
+        // Leaves a gap in the node ids, and a node out of id order
+        list.remove(ids[1]);
+        let zero = list.push_front(0);
+
+        for (_, value) in list.iter_mut() {
+            *value *= 10;
+        }
+        assert_eq!(values(&list), vec![0, 10, 30, 40, 50]);
+        assert_eq!(
+            list.iter_mut()
+                .map(|(node_id, _)| node_id)
+                .collect::<Vec<_>>(),
+            vec![zero, ids[0], ids[2], ids[3], ids[4]]
+        );
+
+        // From both ends until they meet
+        let mut iter = list.iter_mut();
+        assert_eq!(iter.next(), Some((zero, &mut 0)));
+        assert_eq!(iter.next_back(), Some((ids[4], &mut 50)));
+        assert_eq!(iter.next_back(), Some((ids[3], &mut 40)));
+        *iter.next().unwrap().1 += 1;
+        assert_eq!(iter.next(), Some((ids[2], &mut 30)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(list.get(ids[0]), Some(&11));
+
+        for (_, value) in &mut list {
+            *value += 1;
+        }
+        assert_eq!((&list).into_iter().count(), 5);
+        assert!(SlabLinkedList::<i32>::new().iter_mut().next().is_none());
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let mut list = SlabLinkedList::new();
+        for value in 1..=4 {
+            list.push_back(value);
+        }
+        let mut iter = list.into_iter();
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.next(), Some(1));
+        assert_eq!(iter.next_back(), Some(4));
+        assert_eq!(iter.collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_stale_id_finds_nothing() {
+        let mut list = SlabLinkedList::new();
+        let stale = list.push_back(1);
+        assert_eq!(list.remove(stale), Some(1));
+        // Takes the slot the removed value had
+        let reused = list.push_back(2);
+        assert_eq!(reused.index, stale.index);
+        assert_ne!(reused, stale);
+
+        assert_eq!(list.get(stale), None);
+        assert_eq!(list.get_mut(stale), None);
+        assert_eq!(list.remove(stale), None);
+        assert!(list.cursor_at_mut(stale).is_none());
+        assert!(!list.cursor_front_mut().seek(stale));
+        assert_eq!(list.get(reused), Some(&2));
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn test_append() {
+        let mut list = SlabLinkedList::new();
+        let mut other = SlabLinkedList::new();
+        list.push_back(1);
+        for value in 2..=4 {
+            other.push_back(value);
+        }
+        list.append(&mut other);
+        assert_eq!(values(&list), vec![1, 2, 3, 4]);
+        assert!(other.is_empty());
+        assert_eq!(other.front(), None);
+        assert_eq!(list.back(), Some(&4));
+        assert_eq!(list.len(), 4);
+
+        // Into an empty list the two swap, and the ids of the moved values
+        // still find them. Those left behind in the emptied list don't.
+        let moved = list.iter().map(|(node_id, _)| node_id).collect::<Vec<_>>();
+        other.append(&mut list);
+        assert_eq!(values(&other), vec![1, 2, 3, 4]);
+        assert!(list.is_empty());
+        assert_eq!(other.get(moved[2]), Some(&3));
+        let pushed = list.push_back(5);
+        assert!(moved.iter().all(|&node_id| node_id != pushed));
+        assert_eq!(list.get(moved[0]), None);
+
+        // Appending an empty list changes nothing
+        let mut empty = SlabLinkedList::new();
+        other.append(&mut empty);
+        assert_eq!(values(&other), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_cursor_splice() {
+        let mut list = SlabLinkedList::new();
+        let ids: Vec<NodeId> = [1, 5]
+            .into_iter()
+            .map(|value| list.push_back(value))
+            .collect();
+        let mut other = SlabLinkedList::new();
+        for value in 2..=4 {
+            other.push_back(value);
+        }
+
+        let mut cursor = list.cursor_at_mut(ids[0]).unwrap();
+        cursor.splice_after(&mut other);
+        assert_eq!(cursor.current(), Some(&mut 1));
+        assert_eq!(values(&list), vec![1, 2, 3, 4, 5]);
+        assert!(other.is_empty());
+
+        other.push_back(0);
+        let mut cursor = list.cursor_front_mut();
+        cursor.splice_before(&mut other);
+        assert_eq!(cursor.peek_prev(), Some(&mut 0));
+        assert_eq!(list.front(), Some(&0));
+
+        // From the ghost position, before is the back and after the front
+        other.push_back(6);
+        other.push_back(7);
+        let mut cursor = list.cursor_back_mut();
+        cursor.move_next();
+        cursor.splice_before(&mut other);
+        other.push_back(-1);
+        cursor.splice_after(&mut other);
+        assert_eq!(values(&list), vec![-1, 0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(list.len(), 9);
+        assert_eq!(list.iter().rev().map(|(_, &value)| value).next(), Some(7));
+    }
 }
@@ -0,0 +1,82 @@
+use uuid::Uuid;
+
+use crate::{
+    book::{AuctionUncross, OrderType},
+    tape::Trade,
+};
+
+// Callbacks for embedders that react to book activity. Every method has an
+// empty default, so listeners only implement what they need.
+pub trait BookListener: Send + Sync {
+    fn on_order_accepted(
+        &mut self,
+        _order_id: Uuid,
+        _owner: &str,
+        _order_type: OrderType,
+        _price: u32,
+        _quantity: u64,
+    ) {
+    }
+
+    fn on_trade(&mut self, _trade: &Trade) {}
+
+    // Resting order canceled by its owner or expired
+    fn on_order_canceled(&mut self, _order_id: Uuid) {}
+
+    // Quantity resting at a price level changed. A level that emptied is
+    // reported with zero quantity and orders.
+    fn on_level_change(
+        &mut self,
+        _side: OrderType,
+        _price: u32,
+        _total_quantity: u64,
+        _num_orders: usize,
+    ) {
+    }
+
+    // Price and volume the auction would uncross at changed, as orders
+    // arrived or left during it. None once nothing crosses or the auction
+    // ended.
+    fn on_indicative_uncross(&mut self, _uncross: Option<AuctionUncross>) {}
+
+    // Work the book or its background tasks did unprompted failed, e.g.
+    // clearing a trade, spilling the tape to disk or a scheduled end of day.
+    // Whatever was already applied, such as the trade, stands.
+    fn on_error(&mut self, _err: &anyhow::Error) {}
+}
+
+// Prints every trade and cancel, imitating orders sent to a clearing house
+pub struct ClearingLogListener;
+
+impl BookListener for ClearingLogListener {
+    fn on_trade(&mut self, trade: &Trade) {
+        match trade.aggressor {
+            Some(order_type) => println!(
+                "Trade #{}: {order_type:?} -> ID: {} Qty: {}, Price: {}, Maker ID: {}, Fee Schedule: v{}",
+                trade.seq,
+                trade.taker_order_id,
+                trade.quantity,
+                trade.price,
+                trade.maker_order_id,
+                trade.fees.schedule_version
+            ),
+            None => println!(
+                "Trade #{}: Auction -> Bid ID: {} <-> Ask ID: {} Qty: {}, Price: {}, Fee Schedule: v{}",
+                trade.seq,
+                trade.taker_order_id,
+                trade.maker_order_id,
+                trade.quantity,
+                trade.price,
+                trade.fees.schedule_version
+            ),
+        }
+    }
+
+    fn on_order_canceled(&mut self, order_id: Uuid) {
+        println!("Canceled -> ID: {order_id}");
+    }
+
+    fn on_error(&mut self, err: &anyhow::Error) {
+        eprintln!("{err:#}");
+    }
+}
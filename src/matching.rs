@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+
+// How an incoming order's quantity is split among the orders resting at a
+// price level. Levels are always matched best price first; the engine only
+// decides who trades within one, so it can be tested on plain quantities.
+pub trait MatchingEngine: Send + Sync {
+    // Splits `quantity` among the orders of a level, given by their
+    // quantities in time priority and `level_quantity` in total. Returns
+    // what each order trades, in the same order, and may stop before the
+    // orders that trade nothing. Must allocate min(quantity, level_quantity)
+    // in total and no order more than it holds.
+    fn allocate(
+        &self,
+        quantity: u64,
+        level_quantity: u64,
+        resting: &mut dyn Iterator<Item = u64>,
+    ) -> Vec<u64>;
+}
+
+// Oldest order first, each filled in full before the next trades
+pub struct PriceTimeFifo;
+
+impl MatchingEngine for PriceTimeFifo {
+    fn allocate(
+        &self,
+        mut quantity: u64,
+        _level_quantity: u64,
+        resting: &mut dyn Iterator<Item = u64>,
+    ) -> Vec<u64> {
+        let mut allocations = Vec::new();
+        // Only the orders needed are visited, however deep the level
+        for order_quantity in resting {
+            if quantity == 0 {
+                break;
+            }
+            let allocated = order_quantity.min(quantity);
+            allocations.push(allocated);
+            quantity -= allocated;
+        }
+        allocations
+    }
+}
+
+// In proportion to the size of each order, rounded down. What rounding
+// leaves over goes to the oldest orders with room for it.
+pub struct ProRata;
+
+impl MatchingEngine for ProRata {
+    fn allocate(
+        &self,
+        quantity: u64,
+        level_quantity: u64,
+        resting: &mut dyn Iterator<Item = u64>,
+    ) -> Vec<u64> {
+        let order_quantities: Vec<u64> = resting.collect();
+        pro_rata(quantity, level_quantity, &order_quantities)
+    }
+}
+
+// The oldest order at the level, the one that set its price, is filled
+// first. The rest is split pro rata among the others.
+pub struct TopOrderProRata;
+
+impl MatchingEngine for TopOrderProRata {
+    fn allocate(
+        &self,
+        quantity: u64,
+        level_quantity: u64,
+        resting: &mut dyn Iterator<Item = u64>,
+    ) -> Vec<u64> {
+        let Some(top_quantity) = resting.next() else {
+            return Vec::new();
+        };
+        let top_allocated = top_quantity.min(quantity);
+        let others: Vec<u64> = resting.collect();
+        let mut allocations = vec![top_allocated];
+        allocations.extend(pro_rata(
+            quantity - top_allocated,
+            level_quantity - top_quantity,
+            &others,
+        ));
+        allocations
+    }
+}
+
+fn pro_rata(quantity: u64, level_quantity: u64, order_quantities: &[u64]) -> Vec<u64> {
+    if quantity >= level_quantity {
+        return order_quantities.to_vec();
+    }
+    let mut allocations: Vec<u64> = order_quantities
+        .iter()
+        .map(|&order_quantity| {
+            (order_quantity as u128 * quantity as u128 / level_quantity as u128) as u64
+        })
+        .collect();
+    let mut left_over = quantity - allocations.iter().sum::<u64>();
+    for (allocated, &order_quantity) in allocations.iter_mut().zip(order_quantities) {
+        if left_over == 0 {
+            break;
+        }
+        let extra = (order_quantity - *allocated).min(left_over);
+        *allocated += extra;
+        left_over -= extra;
+    }
+    allocations
+}
+
+// Matching engine a book is configured with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchingAlgorithm {
+    #[default]
+    PriceTime,
+    ProRata,
+    TopOrderProRata,
+}
+
+impl MatchingAlgorithm {
+    pub fn engine(self) -> Box<dyn MatchingEngine> {
+        match self {
+            MatchingAlgorithm::PriceTime => Box::new(PriceTimeFifo),
+            MatchingAlgorithm::ProRata => Box::new(ProRata),
+            MatchingAlgorithm::TopOrderProRata => Box::new(TopOrderProRata),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocate(engine: &dyn MatchingEngine, quantity: u64, resting: &[u64]) -> Vec<u64> {
+        let level_quantity = resting.iter().sum();
+        engine.allocate(quantity, level_quantity, &mut resting.iter().copied())
+    }
+
+    #[test]
+    fn test_price_time_fills_oldest_first() {
+        assert_eq!(allocate(&PriceTimeFifo, 7, &[5, 4, 3]), vec![5, 2]);
+        assert_eq!(allocate(&PriceTimeFifo, 20, &[5, 4, 3]), vec![5, 4, 3]);
+        assert!(allocate(&PriceTimeFifo, 0, &[5]).is_empty());
+    }
+
+    #[test]
+    fn test_pro_rata_splits_by_size() {
+        assert_eq!(allocate(&ProRata, 10, &[10, 30, 60]), vec![1, 3, 6]);
+        // 7 * 1/3 rounds down to 2 each, and the lot left over goes to the
+        // oldest order
+        assert_eq!(allocate(&ProRata, 7, &[5, 5, 5]), vec![3, 2, 2]);
+        assert_eq!(allocate(&ProRata, 20, &[5, 4]), vec![5, 4]);
+        // Too small to get a share, but still first in line for what's left
+        assert_eq!(allocate(&ProRata, 2, &[1, 100]), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_top_order_first_then_pro_rata() {
+        assert_eq!(allocate(&TopOrderProRata, 3, &[5, 10, 30]), vec![3, 0, 0]);
+        assert_eq!(allocate(&TopOrderProRata, 9, &[5, 10, 30]), vec![5, 1, 3]);
+        assert!(allocate(&TopOrderProRata, 9, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_allocations_sum_to_quantity() {
+        let resting = [7, 13, 1, 29, 50, 3];
+        let level_quantity: u64 = resting.iter().sum();
+        for algorithm in [
+            MatchingAlgorithm::PriceTime,
+            MatchingAlgorithm::ProRata,
+            MatchingAlgorithm::TopOrderProRata,
+        ] {
+            let engine = algorithm.engine();
+            for quantity in 0..=level_quantity + 5 {
+                let allocations = allocate(engine.as_ref(), quantity, &resting);
+                assert_eq!(
+                    allocations.iter().sum::<u64>(),
+                    quantity.min(level_quantity)
+                );
+                assert!(allocations
+                    .iter()
+                    .zip(&resting)
+                    .all(|(allocated, order_quantity)| allocated <= order_quantity));
+            }
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+// Sub-buckets per power of two, so a recorded value is off by at most 1/16
+const SUB_BUCKET_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+// Exact buckets for values below SUB_BUCKETS, then a row of sub-buckets for
+// every power of two from there up to u64::MAX
+const BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+// Quantiles reported for every operation
+const QUANTILES: [f64; 4] = [0.5, 0.9, 0.99, 0.999];
+
+// Part of the engine whose latency is measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operation {
+    // Placing an order, from checks through matching to resting it
+    Place,
+    Cancel,
+    // Finding the resting orders an incoming order trades against
+    Match,
+    // Building a full depth L2 snapshot
+    L2Book,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 4] = [
+        Operation::Place,
+        Operation::Cancel,
+        Operation::Match,
+        Operation::L2Book,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Operation::Place => "place",
+            Operation::Cancel => "cancel",
+            Operation::Match => "match",
+            Operation::L2Book => "l2_book",
+        }
+    }
+}
+
+// Log-linear histogram of nanosecond latencies in the manner of an HDR
+// histogram: fixed memory, constant time to record and a bounded relative
+// error. Records through a shared reference, so it can be read while the
+// matching task writes to it.
+pub struct LatencyHistogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0),
+            max_nanos: AtomicU64::new(0),
+        }
+    }
+}
+
+fn bucket_index(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    let magnitude = 63 - nanos.leading_zeros();
+    let sub_bucket = (nanos >> (magnitude - SUB_BUCKET_BITS)) as usize & (SUB_BUCKETS - 1);
+    (magnitude - SUB_BUCKET_BITS + 1) as usize * SUB_BUCKETS + sub_bucket
+}
+
+// Largest value that falls in the bucket
+fn bucket_upper_bound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let lower = ((SUB_BUCKETS + index % SUB_BUCKETS) as u64) << shift;
+    lower + ((1 << shift) - 1)
+}
+
+impl LatencyHistogram {
+    pub fn record(&self, nanos: u64) {
+        // Bucket before count, so a reader never sees a count its buckets
+        // don't add up to
+        self.buckets[bucket_index(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum_nanos(&self) -> u64 {
+        self.sum_nanos.load(Ordering::Relaxed)
+    }
+
+    pub fn max_nanos(&self) -> u64 {
+        self.max_nanos.load(Ordering::Relaxed)
+    }
+
+    pub fn mean_nanos(&self) -> u64 {
+        self.sum_nanos().checked_div(self.count()).unwrap_or(0)
+    }
+
+    // Smallest latency at least `quantile` of the recorded ones are below,
+    // rounded up to its bucket. Zero before anything is recorded.
+    pub fn quantile_nanos(&self, quantile: f64) -> u64 {
+        let count = self.count();
+        if count == 0 {
+            return 0;
+        }
+        let rank = ((quantile * count as f64).ceil() as u64).clamp(1, count);
+        let mut seen = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= rank {
+                return bucket_upper_bound(index).min(self.max_nanos());
+            }
+        }
+        self.max_nanos()
+    }
+}
+
+// Latency of one operation, in nanoseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub operation: Operation,
+    pub count: u64,
+    pub mean_nanos: u64,
+    pub p50_nanos: u64,
+    pub p90_nanos: u64,
+    pub p99_nanos: u64,
+    pub p999_nanos: u64,
+    pub max_nanos: u64,
+}
+
+// A latency histogram for every measured operation of a book
+#[derive(Default)]
+pub struct LatencyMetrics {
+    histograms: [LatencyHistogram; Operation::ALL.len()],
+}
+
+impl LatencyMetrics {
+    pub fn record(&self, operation: Operation, elapsed: Duration) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.histogram(operation).record(nanos);
+    }
+
+    pub fn histogram(&self, operation: Operation) -> &LatencyHistogram {
+        &self.histograms[operation as usize]
+    }
+
+    pub fn summary(&self) -> Vec<LatencySummary> {
+        Operation::ALL
+            .into_iter()
+            .map(|operation| {
+                let histogram = self.histogram(operation);
+                let [p50_nanos, p90_nanos, p99_nanos, p999_nanos] =
+                    QUANTILES.map(|quantile| histogram.quantile_nanos(quantile));
+                LatencySummary {
+                    operation,
+                    count: histogram.count(),
+                    mean_nanos: histogram.mean_nanos(),
+                    p50_nanos,
+                    p90_nanos,
+                    p99_nanos,
+                    p999_nanos,
+                    max_nanos: histogram.max_nanos(),
+                }
+            })
+            .collect()
+    }
+
+    // Every histogram as a summary in the Prometheus text format
+    pub fn prometheus(&self) -> String {
+        let mut text = String::new();
+        text.push_str("# HELP order_book_latency_seconds Time the book spent on an operation\n");
+        text.push_str("# TYPE order_book_latency_seconds summary\n");
+        for operation in Operation::ALL {
+            let histogram = self.histogram(operation);
+            let label = operation.label();
+            for quantile in QUANTILES {
+                let seconds = histogram.quantile_nanos(quantile) as f64 / 1e9;
+                writeln!(
+                    text,
+                    "order_book_latency_seconds{{operation=\"{label}\",quantile=\"{quantile}\"}} {seconds}"
+                )
+                .unwrap();
+            }
+            let sum_seconds = histogram.sum_nanos() as f64 / 1e9;
+            writeln!(
+                text,
+                "order_book_latency_seconds_sum{{operation=\"{label}\"}} {sum_seconds}"
+            )
+            .unwrap();
+            writeln!(
+                text,
+                "order_book_latency_seconds_count{{operation=\"{label}\"}} {}",
+                histogram.count()
+            )
+            .unwrap();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_bound_their_values() {
+        for nanos in (0..10_000).chain([u64::MAX / 3, u64::MAX - 1, u64::MAX]) {
+            let index = bucket_index(nanos);
+            assert!(index < BUCKETS);
+            let upper_bound = bucket_upper_bound(index);
+            assert!(upper_bound >= nanos);
+            // Within 1/16 of the value
+            assert!(upper_bound - nanos <= nanos / SUB_BUCKETS as u64);
+        }
+    }
+
+    #[test]
+    fn test_quantiles() {
+        let histogram = LatencyHistogram::default();
+        assert_eq!(histogram.quantile_nanos(0.5), 0);
+        for nanos in 1..=1_000 {
+            histogram.record(nanos);
+        }
+        assert_eq!(histogram.count(), 1_000);
+        assert_eq!(histogram.mean_nanos(), 500);
+        assert_eq!(histogram.max_nanos(), 1_000);
+        for (quantile, expected) in [(0.5, 500), (0.9, 900), (0.99, 990)] {
+            let nanos = histogram.quantile_nanos(quantile);
+            assert!(nanos >= expected && nanos <= expected + expected / 16);
+        }
+        assert_eq!(histogram.quantile_nanos(1.0), 1_000);
+    }
+
+    #[test]
+    fn test_prometheus_export() {
+        let metrics = LatencyMetrics::default();
+        metrics.record(Operation::Place, Duration::from_micros(3));
+        let text = metrics.prometheus();
+        assert!(text.contains("# TYPE order_book_latency_seconds summary"));
+        assert!(text.contains("order_book_latency_seconds_count{operation=\"place\"} 1"));
+        assert!(text.contains("order_book_latency_seconds_count{operation=\"cancel\"} 0"));
+        assert!(text.contains("order_book_latency_seconds_sum{operation=\"place\"} 0.000003"));
+    }
+}
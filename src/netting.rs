@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::ledger::{Asset, EntryKind, JournalEntry, Ledger, EXTERNAL_ACCOUNT, FEE_ACCOUNT};
+
+// What one participant delivers and pays, or receives, for every trade
+// netted in a run, rather than trade by trade
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NetSettlement {
+    pub owner: String,
+    // Trades and busts of trades the participant was part of
+    pub entries: u64,
+    // Units received, or delivered when negative
+    pub net_position: i64,
+    // Cash received, or paid when negative, fees included
+    pub net_cash: i64,
+}
+
+// Trades cleared between two netting runs, collapsed per participant
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NettingRun {
+    // Unix timestamp in milliseconds of the run
+    pub netted_at: u64,
+    // Journal entries the run covers, None when nothing was left to net
+    pub first_entry: Option<u64>,
+    pub last_entry: Option<u64>,
+    // Participants with a non-zero position or cash movement, by owner
+    pub settlements: Vec<NetSettlement>,
+}
+
+// Nets the trade entries of the journal after `after_entry`, busts
+// included, so a bust of a trade netted in an earlier run is settled in
+// this one
+pub fn net_trades(ledger: &Ledger, after_entry: u64, netted_at: u64) -> NettingRun {
+    let entries: Vec<&JournalEntry> = ledger
+        .journal()
+        .iter()
+        .filter(|entry| entry.id > after_entry && is_trade_entry(ledger, entry))
+        .collect();
+
+    let mut settlements: BTreeMap<&str, NetSettlement> = BTreeMap::new();
+    for entry in &entries {
+        let mut counted = Vec::new();
+        for posting in &entry.postings {
+            let owner = posting.account.as_str();
+            if owner == FEE_ACCOUNT || owner == EXTERNAL_ACCOUNT {
+                continue;
+            }
+            let settlement = settlements.entry(owner).or_insert_with(|| NetSettlement {
+                owner: owner.to_string(),
+                entries: 0,
+                net_position: 0,
+                net_cash: 0,
+            });
+            match posting.asset {
+                Asset::Position => settlement.net_position += posting.amount,
+                Asset::Cash => settlement.net_cash += posting.amount,
+            }
+            if !counted.contains(&owner) {
+                counted.push(owner);
+                settlement.entries += 1;
+            }
+        }
+    }
+
+    NettingRun {
+        netted_at,
+        first_entry: entries.first().map(|entry| entry.id),
+        last_entry: entries.last().map(|entry| entry.id),
+        settlements: settlements
+            .into_values()
+            .filter(|settlement| settlement.net_position != 0 || settlement.net_cash != 0)
+            .collect(),
+    }
+}
+
+fn is_trade_entry(ledger: &Ledger, entry: &JournalEntry) -> bool {
+    match entry.kind {
+        EntryKind::Trade { .. } => true,
+        EntryKind::Reversal { entry_id } => ledger
+            .entry(entry_id)
+            .is_some_and(|reversed| matches!(reversed.kind, EntryKind::Trade { .. })),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::Posting;
+
+    fn posting(account: &str, asset: Asset, amount: i64) -> Posting {
+        Posting {
+            account: account.to_string(),
+            asset,
+            amount,
+        }
+    }
+
+    fn trade(ledger: &mut Ledger, trade_seq: u64, buyer: &str, seller: &str, price: i64) -> u64 {
+        ledger
+            .post(
+                0,
+                EntryKind::Trade { trade_seq },
+                vec![
+                    posting(buyer, Asset::Position, 1),
+                    posting(seller, Asset::Position, -1),
+                    posting(buyer, Asset::Cash, -price),
+                    posting(seller, Asset::Cash, price),
+                ],
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_trades_net_per_participant() {
+        let mut ledger = Ledger::new();
+        ledger
+            .post(
+                0,
+                EntryKind::Deposit,
+                vec![
+                    posting(EXTERNAL_ACCOUNT, Asset::Cash, -1_000),
+                    posting("alice", Asset::Cash, 1_000),
+                ],
+            )
+            .unwrap();
+        trade(&mut ledger, 1, "alice", "bob", 100);
+        trade(&mut ledger, 2, "bob", "alice", 102);
+        let busted = trade(&mut ledger, 3, "alice", "carol", 101);
+
+        let run = net_trades(&ledger, 0, 10);
+        assert_eq!((run.first_entry, run.last_entry), (Some(2), Some(4)));
+        // Bob bought back what he sold, so only his cash moved
+        assert_eq!(
+            run.settlements,
+            vec![
+                NetSettlement {
+                    owner: "alice".to_string(),
+                    entries: 3,
+                    net_position: 1,
+                    net_cash: -99,
+                },
+                NetSettlement {
+                    owner: "bob".to_string(),
+                    entries: 2,
+                    net_position: 0,
+                    net_cash: -2,
+                },
+                NetSettlement {
+                    owner: "carol".to_string(),
+                    entries: 1,
+                    net_position: -1,
+                    net_cash: 101,
+                },
+            ]
+        );
+
+        // Bust is netted by the next run
+        ledger.reverse(busted, 20).unwrap();
+        let run = net_trades(&ledger, 4, 30);
+        assert_eq!(run.settlements.len(), 2);
+        assert_eq!(run.settlements[0].net_cash, 101);
+        assert!(net_trades(&ledger, 5, 40).settlements.is_empty());
+    }
+}
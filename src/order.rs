@@ -1,21 +1,45 @@
-use std::time::Instant;
 use uuid::Uuid;
 
+pub const ANONYMOUS_OWNER: &str = "anonymous";
+
 #[derive(Debug)]
 pub struct Order {
     id: Uuid,
-    quantity: u32,
+    owner: String,
+    quantity: u64,
+    // Quantity the order was placed with, before any fills
+    original_quantity: u64,
     price: u32,
-    created_at: Instant,
+    // Unix timestamp in milliseconds, by the book's clock, at which the book
+    // accepted the order, or 0 before it does
+    timestamp: u64,
+    // Place of the order in the book's arrival order, which unlike the
+    // timestamp never ties
+    arrival_seq: u64,
+    // Unix timestamp in milliseconds after which a resting order expires
+    expires_at: Option<u64>,
 }
 
 impl Order {
-    pub fn new(price: u32, quantity: u32) -> Order {
+    pub fn new(price: u32, quantity: u64) -> Order {
+        Order::with_owner(ANONYMOUS_OWNER.to_string(), price, quantity)
+    }
+
+    pub fn with_owner(owner: String, price: u32, quantity: u64) -> Order {
+        Order::with_id(Uuid::new_v4(), owner, price, quantity)
+    }
+
+    // Recreates an order known by id elsewhere, e.g. on a replica
+    pub fn with_id(id: Uuid, owner: String, price: u32, quantity: u64) -> Order {
         Order {
-            id: Uuid::new_v4(),
+            id,
+            owner,
             price,
             quantity,
-            created_at: Instant::now(),
+            original_quantity: quantity,
+            timestamp: 0,
+            arrival_seq: 0,
+            expires_at: None,
         }
     }
 
@@ -23,38 +47,73 @@ impl Order {
         self.id
     }
 
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
     pub fn price(&self) -> u32 {
         self.price
     }
 
-    pub fn quantity(&self) -> u32 {
+    pub fn quantity(&self) -> u64 {
         self.quantity
     }
 
-    pub fn update_quantity(&mut self, quantity: u32) {
+    pub fn update_quantity(&mut self, quantity: u64) {
         self.quantity = quantity
     }
 
-    pub fn created_at(&self) -> Instant {
-        self.created_at
+    pub fn original_quantity(&self) -> u64 {
+        self.original_quantity
+    }
+
+    pub fn is_partially_filled(&self) -> bool {
+        self.quantity < self.original_quantity
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn arrival_seq(&self) -> u64 {
+        self.arrival_seq
+    }
+
+    pub fn set_arrival(&mut self, timestamp: u64, arrival_seq: u64) {
+        self.timestamp = timestamp;
+        self.arrival_seq = arrival_seq;
+    }
+
+    pub fn expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+
+    pub fn set_expires_at(&mut self, expires_at: Option<u64>) {
+        self.expires_at = expires_at
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::time::Duration;
 
     #[test]
     fn test_new_order() {
         let order = Order::new(100, 5);
         assert_eq!(order.price(), 100);
         assert_eq!(order.quantity(), 5);
-        // Since the created_at field is set to Instant::now(), we can't predict its exact value.
-        // However, we can check that it's within a reasonable range of the current time.
-        let now = Instant::now();
-        assert!(order.created_at() >= now - Duration::from_secs(1));
-        assert!(order.created_at() <= now + Duration::from_secs(1));
+        // Stamped by the book once it accepts the order
+        assert_eq!(order.timestamp(), 0);
+        assert_eq!(order.arrival_seq(), 0);
+    }
+
+    #[test]
+    fn test_order_owner() {
+        assert_eq!(Order::new(100, 5).owner(), ANONYMOUS_OWNER);
+        assert_eq!(
+            Order::with_owner("alice".to_string(), 100, 5).owner(),
+            "alice"
+        );
     }
 
     #[test]
@@ -75,13 +134,23 @@ mod tests {
         assert_eq!(order.quantity(), 15);
         order.update_quantity(10);
         assert_eq!(order.quantity(), 10);
+        assert_eq!(order.original_quantity(), 15);
+        assert!(order.is_partially_filled());
+    }
+
+    #[test]
+    fn test_order_expires_at() {
+        let mut order = Order::new(400, 20);
+        assert_eq!(order.expires_at(), None);
+        order.set_expires_at(Some(1_000));
+        assert_eq!(order.expires_at(), Some(1_000));
     }
 
     #[test]
-    fn test_order_created_at() {
-        let order = Order::new(400, 20);
-        let now = Instant::now();
-        assert!(order.created_at() >= now - Duration::from_secs(1));
-        assert!(order.created_at() <= now + Duration::from_secs(1));
+    fn test_order_arrival() {
+        let mut order = Order::new(400, 20);
+        order.set_arrival(1_700_000_000_000, 42);
+        assert_eq!(order.timestamp(), 1_700_000_000_000);
+        assert_eq!(order.arrival_seq(), 42);
     }
 }
@@ -0,0 +1,164 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+use uuid::Uuid;
+
+use crate::book::OpenOrder;
+
+// Client's own view of its working orders, built from the acks it received.
+// The server stays authoritative, so the cache is only used to spot orders
+// that changed without the client noticing.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct OrderCache {
+    orders: BTreeMap<Uuid, OpenOrder>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum OrderMismatch {
+    // Cached as working but no longer resting, i.e. filled, expired or
+    // canceled without an ack
+    MissingOnServer(OpenOrder),
+    // Resting on the server but never acked to this client
+    UnknownLocally(OpenOrder),
+    QuantityDiffers {
+        order_id: Uuid,
+        cached: u64,
+        server: u64,
+    },
+}
+
+impl OrderCache {
+    // A missing file is an empty cache
+    pub fn load(path: &Path) -> Result<OrderCache> {
+        if !path.exists() {
+            return Ok(OrderCache::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn orders(&self, owner: &str) -> impl Iterator<Item = &OpenOrder> + '_ {
+        let owner = owner.to_string();
+        self.orders
+            .values()
+            .filter(move |order| order.owner == owner)
+    }
+
+    pub fn record_placed(&mut self, order: OpenOrder) {
+        self.orders.insert(order.order_id, order);
+    }
+
+    pub fn record_canceled(&mut self, order_id: Uuid) {
+        self.orders.remove(&order_id);
+    }
+
+    // Differences between the cached orders of `owner` and the orders the
+    // server reports as open for them
+    pub fn diff(&self, owner: &str, open_orders: &[OpenOrder]) -> Vec<OrderMismatch> {
+        let server: BTreeMap<Uuid, &OpenOrder> = open_orders
+            .iter()
+            .map(|order| (order.order_id, order))
+            .collect();
+        let mut mismatches = Vec::new();
+        for cached in self.orders(owner) {
+            match server.get(&cached.order_id) {
+                None => mismatches.push(OrderMismatch::MissingOnServer(cached.clone())),
+                Some(order) if order.quantity != cached.quantity => {
+                    mismatches.push(OrderMismatch::QuantityDiffers {
+                        order_id: cached.order_id,
+                        cached: cached.quantity,
+                        server: order.quantity,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        mismatches.extend(
+            open_orders
+                .iter()
+                .filter(|order| !self.orders.contains_key(&order.order_id))
+                .map(|order| OrderMismatch::UnknownLocally(order.clone())),
+        );
+        mismatches
+    }
+
+    // Replaces the cached orders of `owner` with the server's view
+    pub fn reconcile(&mut self, owner: &str, open_orders: &[OpenOrder]) -> Vec<OrderMismatch> {
+        let mismatches = self.diff(owner, open_orders);
+        self.orders.retain(|_, order| order.owner != owner);
+        for order in open_orders {
+            self.orders.insert(order.order_id, order.clone());
+        }
+        mismatches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::OrderType;
+
+    fn order(owner: &str, quantity: u64) -> OpenOrder {
+        OpenOrder {
+            order_id: Uuid::new_v4(),
+            owner: owner.to_string(),
+            order_type: OrderType::Bid,
+            price: 100,
+            quantity,
+            expires_at: None,
+            timestamp: 0,
+            arrival_seq: 0,
+        }
+    }
+
+    #[test]
+    fn test_reconcile_reports_mismatches() {
+        let mut cache = OrderCache::default();
+        let matching = order("alice", 5);
+        let filled = order("alice", 5);
+        let mut partially_filled = order("alice", 5);
+        let other_owner = order("bob", 5);
+        for order in [&matching, &filled, &partially_filled, &other_owner] {
+            cache.record_placed(order.clone());
+        }
+
+        partially_filled.quantity = 2;
+        let unknown = order("alice", 1);
+        let open_orders = vec![matching.clone(), partially_filled.clone(), unknown.clone()];
+        let mismatches = cache.reconcile("alice", &open_orders);
+
+        assert_eq!(mismatches.len(), 3);
+        assert!(mismatches.contains(&OrderMismatch::MissingOnServer(filled)));
+        assert!(mismatches.contains(&OrderMismatch::QuantityDiffers {
+            order_id: partially_filled.order_id,
+            cached: 5,
+            server: 2,
+        }));
+        assert!(mismatches.contains(&OrderMismatch::UnknownLocally(unknown)));
+
+        // The cache now follows the server, leaving other owners alone
+        assert!(cache.diff("alice", &open_orders).is_empty());
+        assert_eq!(cache.orders("bob").count(), 1);
+    }
+
+    #[test]
+    fn test_cache_round_trips_through_file() {
+        let path = std::env::temp_dir().join(format!("order_book_cache_{}.json", Uuid::new_v4()));
+        assert!(OrderCache::load(&path).unwrap().orders.is_empty());
+
+        let mut cache = OrderCache::default();
+        let placed = order("alice", 5);
+        cache.record_placed(placed.clone());
+        cache.record_placed(order("alice", 3));
+        cache.record_canceled(placed.order_id);
+        cache.save(&path).unwrap();
+
+        let loaded = OrderCache::load(&path).unwrap();
+        assert_eq!(loaded.orders("alice").count(), 1);
+        fs::remove_file(path).unwrap();
+    }
+}
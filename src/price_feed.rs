@@ -0,0 +1,171 @@
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    time::Duration,
+};
+
+// External mark prices, e.g. an index or the price on another venue, for
+// instruments whose own trades make a poor reference. Each instrument names
+// the symbol a feed quotes it under, and the book's risk config decides
+// whether its circuit breakers follow the mark, see BandReference. Margin
+// is always worked out at the mark once there is one, see MarginCheck.
+
+pub const DEFAULT_POLL_INTERVAL_MS: u64 = 1_000;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub trait PriceFeed: Send {
+    // Latest mark price of every symbol the source quotes
+    fn poll(&mut self) -> Result<HashMap<String, u32>>;
+}
+
+// Fixed prices, e.g. for tests and backtests
+impl PriceFeed for HashMap<String, u32> {
+    fn poll(&mut self) -> Result<HashMap<String, u32>> {
+        Ok(self.clone())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonSource {
+    File(PathBuf),
+    // Fetched with a plain HTTP/1.0 GET
+    Http { addr: String, path: String },
+}
+
+// Reads a JSON object of mark prices by symbol, e.g. {"ESZ6": 5012}, from
+// a file or over HTTP every time it's polled
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPriceFeed {
+    source: JsonSource,
+}
+
+impl JsonPriceFeed {
+    // From an http:// URL or otherwise a file path
+    pub fn new(source: &str) -> Result<JsonPriceFeed> {
+        let source = match source.strip_prefix("http://") {
+            Some(url) => {
+                let (addr, path) = match url.find('/') {
+                    Some(index) => url.split_at(index),
+                    None => (url, "/"),
+                };
+                if addr.is_empty() {
+                    return Err(anyhow!("Price feed URL {source} has no host"));
+                }
+                let addr = if addr.contains(':') {
+                    addr.to_string()
+                } else {
+                    format!("{addr}:80")
+                };
+                JsonSource::Http {
+                    addr,
+                    path: path.to_string(),
+                }
+            }
+            None if source.contains("://") => {
+                return Err(anyhow!("Price feeds are read from files or http:// URLs"))
+            }
+            None => JsonSource::File(PathBuf::from(source)),
+        };
+        Ok(JsonPriceFeed { source })
+    }
+
+    pub fn source(&self) -> &JsonSource {
+        &self.source
+    }
+}
+
+impl PriceFeed for JsonPriceFeed {
+    fn poll(&mut self) -> Result<HashMap<String, u32>> {
+        let body = match &self.source {
+            JsonSource::File(path) => fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+            JsonSource::Http { addr, path } => http_get(addr, path)?,
+        };
+        serde_json::from_str(&body).context("Price feed should be a JSON object of prices")
+    }
+}
+
+fn http_get(addr: &str, path: &str) -> Result<String> {
+    let socket_addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("{addr} doesn't resolve"))?;
+    let mut stream = TcpStream::connect_timeout(&socket_addr, HTTP_TIMEOUT)?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+    write!(
+        stream,
+        "GET {path} HTTP/1.0\r\nHost: {addr}\r\nAccept: application/json\r\n\r\n"
+    )?;
+    // HTTP/1.0 servers close the connection after the body
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow!("Malformed response from {addr}"))?;
+    let status_line = head.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(anyhow!("Price feed at {addr} answered {status_line}"));
+    }
+    Ok(body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_parse_source() {
+        assert_eq!(
+            JsonPriceFeed::new("http://prices.local/marks.json")
+                .unwrap()
+                .source(),
+            &JsonSource::Http {
+                addr: "prices.local:80".to_string(),
+                path: "/marks.json".to_string(),
+            }
+        );
+        assert_eq!(
+            JsonPriceFeed::new("marks.json").unwrap().source(),
+            &JsonSource::File(PathBuf::from("marks.json"))
+        );
+        assert!(JsonPriceFeed::new("https://prices.local").is_err());
+    }
+
+    #[test]
+    fn test_poll_file_and_http() {
+        let path = std::env::temp_dir().join(format!("order_book_marks_{}.json", Uuid::new_v4()));
+        fs::write(&path, r#"{"ESZ6": 5012, "ESH7": 5040}"#).unwrap();
+        let mut feed = JsonPriceFeed::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(feed.poll().unwrap()["ESZ6"], 5012);
+        fs::write(&path, "not json").unwrap();
+        assert!(feed.poll().is_err());
+        fs::remove_file(&path).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            let body = r#"{"ESZ6": 5013}"#;
+            write!(
+                stream,
+                "HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        });
+        let mut feed = JsonPriceFeed::new(&format!("http://{addr}/marks")).unwrap();
+        assert_eq!(
+            feed.poll().unwrap(),
+            HashMap::from([("ESZ6".to_string(), 5013)])
+        );
+    }
+}
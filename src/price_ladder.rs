@@ -0,0 +1,257 @@
+use anyhow::{anyhow, Result};
+use std::ops::RangeInclusive;
+
+use crate::{error::OrderBookError, price_tree::PriceIndex};
+
+// Most slots a ladder can have, so a wide band fails to build rather than
+// allocating gigabytes
+pub const MAX_LADDER_SLOTS: usize = 1 << 24;
+
+// Price index for a known price band, with a slot for every tick in it. A
+// level is found with an index into the slots rather than a walk down a
+// tree, at the cost of memory for every tick whether it has a level or not.
+pub struct PriceLadder {
+    min_price: u32,
+    tick_size: u32,
+    // Price node id of the level at each tick from min_price up
+    slots: Vec<Option<usize>>,
+    // Lowest and highest slots with a level, so the ends are found without
+    // a scan
+    low: Option<usize>,
+    high: Option<usize>,
+}
+
+impl PriceLadder {
+    // Ladder for every tick from `min_price` to `max_price`, both inclusive
+    pub fn new(min_price: u32, max_price: u32, tick_size: u32) -> Result<PriceLadder> {
+        if tick_size == 0 {
+            return Err(anyhow!("Tick size should be bigger than 0"));
+        }
+        if min_price > max_price {
+            return Err(anyhow!(
+                "Ladder min price {min_price} is above its max price {max_price}"
+            ));
+        }
+        let num_slots = ((max_price - min_price) / tick_size) as usize + 1;
+        if num_slots > MAX_LADDER_SLOTS {
+            return Err(anyhow!(
+                "Ladder would have {num_slots} slots, more than the {MAX_LADDER_SLOTS} allowed"
+            ));
+        }
+        Ok(PriceLadder {
+            min_price,
+            tick_size,
+            slots: vec![None; num_slots],
+            low: None,
+            high: None,
+        })
+    }
+
+    pub fn tick_size(&self) -> u32 {
+        self.tick_size
+    }
+
+    fn price_at(&self, slot: usize) -> u32 {
+        self.min_price + slot as u32 * self.tick_size
+    }
+
+    // Slot of the price, if it's a tick on the ladder
+    fn slot(&self, price: u32) -> Option<usize> {
+        let offset = price.checked_sub(self.min_price)?;
+        if offset % self.tick_size != 0 {
+            return None;
+        }
+        let slot = (offset / self.tick_size) as usize;
+        (slot < self.slots.len()).then_some(slot)
+    }
+
+    fn entry(&self, slot: usize) -> (u32, usize) {
+        (self.price_at(slot), self.slots[slot].unwrap()) // Ends always have a level
+    }
+}
+
+impl PriceIndex for PriceLadder {
+    type Range<'a> = LadderRange<'a>;
+
+    fn holds(&self, price: u32) -> bool {
+        self.slot(price).is_some()
+    }
+
+    fn get(&self, price: u32) -> Option<usize> {
+        self.slots[self.slot(price)?]
+    }
+
+    fn insert(&mut self, price: u32, price_node_id: usize) -> Result<(), OrderBookError> {
+        let slot = self
+            .slot(price)
+            .ok_or(OrderBookError::PriceOffLadder(price))?;
+        self.slots[slot] = Some(price_node_id);
+        self.low = Some(self.low.map_or(slot, |low| low.min(slot)));
+        self.high = Some(self.high.map_or(slot, |high| high.max(slot)));
+        Ok(())
+    }
+
+    fn remove(&mut self, price: u32) -> Option<usize> {
+        let slot = self.slot(price)?;
+        let price_node_id = self.slots[slot].take()?;
+        let (low, high) = (self.low.unwrap(), self.high.unwrap()); // Set while any slot is
+        if low == high {
+            self.low = None;
+            self.high = None;
+        } else if slot == low {
+            self.low = (slot + 1..=high).find(|&slot| self.slots[slot].is_some());
+        } else if slot == high {
+            self.high = (low..slot).rev().find(|&slot| self.slots[slot].is_some());
+        }
+        Some(price_node_id)
+    }
+
+    fn first(&self) -> Option<(u32, usize)> {
+        Some(self.entry(self.low?))
+    }
+
+    fn last(&self) -> Option<(u32, usize)> {
+        Some(self.entry(self.high?))
+    }
+
+    fn range(&self, prices: RangeInclusive<u32>) -> LadderRange<'_> {
+        let empty = LadderRange {
+            ladder: self,
+            front: 0,
+            back: 0,
+        };
+        let (Some(low), Some(high)) = (self.low, self.high) else {
+            return empty;
+        };
+        let (start, end) = prices.into_inner();
+        if end < self.min_price || start > end {
+            return empty;
+        }
+        // First tick at or above the start, and last at or below the end
+        let front = start
+            .saturating_sub(self.min_price)
+            .div_ceil(self.tick_size) as usize;
+        let back = ((end - self.min_price) / self.tick_size) as usize;
+        LadderRange {
+            ladder: self,
+            front: front.max(low),
+            back: back.min(high) + 1,
+        }
+    }
+}
+
+// Levels of a ladder in ascending price order, as (price, price node id)
+pub struct LadderRange<'a> {
+    ladder: &'a PriceLadder,
+    // Slots left to visit, from front up to but not including back
+    front: usize,
+    back: usize,
+}
+
+impl Iterator for LadderRange<'_> {
+    type Item = (u32, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.front < self.back {
+            let slot = self.front;
+            self.front += 1;
+            if let Some(price_node_id) = self.ladder.slots[slot] {
+                return Some((self.ladder.price_at(slot), price_node_id));
+            }
+        }
+        None
+    }
+}
+
+impl DoubleEndedIterator for LadderRange<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.front < self.back {
+            self.back -= 1;
+            if let Some(price_node_id) = self.ladder.slots[self.back] {
+                return Some((self.ladder.price_at(self.back), price_node_id));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ladder_holds_ticks_in_band() {
+        let ladder = PriceLadder::new(100, 200, 5).unwrap();
+        assert_eq!(ladder.slots.len(), 21);
+        assert!(ladder.holds(100));
+        assert!(ladder.holds(155));
+        assert!(ladder.holds(200));
+        assert!(!ladder.holds(95));
+        assert!(!ladder.holds(102));
+        assert!(!ladder.holds(205));
+
+        assert!(PriceLadder::new(100, 200, 0).is_err());
+        assert!(PriceLadder::new(200, 100, 1).is_err());
+        assert!(PriceLadder::new(1, u32::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn test_ladder_tracks_ends() {
+        let mut ladder = PriceLadder::new(100, 200, 5).unwrap();
+        assert_eq!(ladder.first(), None);
+        assert!(ladder.insert(103, 0).is_err());
+
+        for (price_node_id, price) in [150, 110, 190, 130].into_iter().enumerate() {
+            ladder.insert(price, price_node_id).unwrap();
+        }
+        assert_eq!(ladder.get(130), Some(3));
+        assert_eq!(ladder.get(135), None);
+        assert_eq!(ladder.first(), Some((110, 1)));
+        assert_eq!(ladder.last(), Some((190, 2)));
+
+        assert_eq!(ladder.remove(110), Some(1));
+        assert_eq!(ladder.remove(110), None);
+        assert_eq!(ladder.first(), Some((130, 3)));
+        assert_eq!(ladder.remove(190), Some(2));
+        assert_eq!(ladder.last(), Some((150, 0)));
+        ladder.remove(130);
+        ladder.remove(150);
+        assert_eq!(ladder.first(), None);
+        assert_eq!(ladder.last(), None);
+    }
+
+    #[test]
+    fn test_ladder_range() {
+        let mut ladder = PriceLadder::new(100, 200, 5).unwrap();
+        for (price_node_id, price) in [100, 120, 125, 200].into_iter().enumerate() {
+            ladder.insert(price, price_node_id).unwrap();
+        }
+        let prices = |range: RangeInclusive<u32>| {
+            ladder
+                .range(range)
+                .map(|(price, _)| price)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(prices(0..=u32::MAX), vec![100, 120, 125, 200]);
+        assert_eq!(prices(101..=125), vec![120, 125]);
+        assert_eq!(prices(121..=199), vec![125]);
+        assert_eq!(prices(0..=99), Vec::<u32>::new());
+        assert_eq!(prices(201..=300), Vec::<u32>::new());
+        assert_eq!(
+            ladder
+                .range(0..=u32::MAX)
+                .rev()
+                .map(|(price, _)| price)
+                .collect::<Vec<_>>(),
+            vec![200, 125, 120, 100]
+        );
+
+        let mut range = ladder.range(0..=u32::MAX);
+        assert_eq!(range.next(), Some((100, 0)));
+        assert_eq!(range.next_back(), Some((200, 3)));
+        assert_eq!(range.next_back(), Some((125, 2)));
+        assert_eq!(range.next(), Some((120, 1)));
+        assert_eq!(range.next(), None);
+        assert_eq!(range.next_back(), None);
+    }
+}
@@ -1,16 +1,165 @@
-use anyhow::{anyhow, Ok, Result};
+use anyhow::anyhow;
 use slab::Slab;
-use std::collections::BTreeMap;
+use std::collections::{btree_map, BTreeMap};
+use std::ops::RangeInclusive;
 
 use crate::{
-    linked_list::{SlabLinkedList, SlabLinkedListIter},
+    book::{OrderType, PriceLevelIndex},
+    error::OrderBookError,
+    linked_list::{NodeId, SlabLinkedList, SlabLinkedListIntoIter, SlabLinkedListIter},
     order::Order,
+    price_ladder::{LadderRange, PriceLadder},
 };
 
+// Finds the price node id of each level by its price
+pub trait PriceIndex {
+    // Levels in ascending price order, as (price, price node id)
+    type Range<'a>: DoubleEndedIterator<Item = (u32, usize)>
+    where
+        Self: 'a;
+
+    // Whether a level at the price can be indexed
+    fn holds(&self, price: u32) -> bool;
+    fn get(&self, price: u32) -> Option<usize>;
+    fn insert(&mut self, price: u32, price_node_id: usize) -> Result<(), OrderBookError>;
+    fn remove(&mut self, price: u32) -> Option<usize>;
+    // Level at the lowest price
+    fn first(&self) -> Option<(u32, usize)>;
+    // Level at the highest price
+    fn last(&self) -> Option<(u32, usize)>;
+    fn range(&self, prices: RangeInclusive<u32>) -> Self::Range<'_>;
+}
+
+type BTreeRange<'a> =
+    std::iter::Map<btree_map::Range<'a, u32, usize>, fn((&u32, &usize)) -> (u32, usize)>;
+
+impl PriceIndex for BTreeMap<u32, usize> {
+    type Range<'a> = BTreeRange<'a>;
+
+    fn holds(&self, _: u32) -> bool {
+        true
+    }
+
+    fn get(&self, price: u32) -> Option<usize> {
+        BTreeMap::get(self, &price).copied()
+    }
+
+    fn insert(&mut self, price: u32, price_node_id: usize) -> Result<(), OrderBookError> {
+        BTreeMap::insert(self, price, price_node_id);
+        Ok(())
+    }
+
+    fn remove(&mut self, price: u32) -> Option<usize> {
+        BTreeMap::remove(self, &price)
+    }
+
+    fn first(&self) -> Option<(u32, usize)> {
+        self.first_key_value()
+            .map(|(&price, &price_node_id)| (price, price_node_id))
+    }
+
+    fn last(&self) -> Option<(u32, usize)> {
+        self.last_key_value()
+            .map(|(&price, &price_node_id)| (price, price_node_id))
+    }
+
+    fn range(&self, prices: RangeInclusive<u32>) -> BTreeRange<'_> {
+        BTreeMap::range(self, prices).map(|(&price, &price_node_id)| (price, price_node_id))
+    }
+}
+
+// Index a tree was set up with
+enum PriceLevels {
+    Tree(BTreeMap<u32, usize>),
+    Ladder(PriceLadder),
+}
+
+enum PriceLevelsRange<'a> {
+    Tree(BTreeRange<'a>),
+    Ladder(LadderRange<'a>),
+}
+
+impl PriceIndex for PriceLevels {
+    type Range<'a> = PriceLevelsRange<'a>;
+
+    fn holds(&self, price: u32) -> bool {
+        match self {
+            PriceLevels::Tree(tree) => tree.holds(price),
+            PriceLevels::Ladder(ladder) => ladder.holds(price),
+        }
+    }
+
+    fn get(&self, price: u32) -> Option<usize> {
+        match self {
+            PriceLevels::Tree(tree) => PriceIndex::get(tree, price),
+            PriceLevels::Ladder(ladder) => ladder.get(price),
+        }
+    }
+
+    fn insert(&mut self, price: u32, price_node_id: usize) -> Result<(), OrderBookError> {
+        match self {
+            PriceLevels::Tree(tree) => PriceIndex::insert(tree, price, price_node_id),
+            PriceLevels::Ladder(ladder) => ladder.insert(price, price_node_id),
+        }
+    }
+
+    fn remove(&mut self, price: u32) -> Option<usize> {
+        match self {
+            PriceLevels::Tree(tree) => PriceIndex::remove(tree, price),
+            PriceLevels::Ladder(ladder) => ladder.remove(price),
+        }
+    }
+
+    fn first(&self) -> Option<(u32, usize)> {
+        match self {
+            PriceLevels::Tree(tree) => PriceIndex::first(tree),
+            PriceLevels::Ladder(ladder) => ladder.first(),
+        }
+    }
+
+    fn last(&self) -> Option<(u32, usize)> {
+        match self {
+            PriceLevels::Tree(tree) => PriceIndex::last(tree),
+            PriceLevels::Ladder(ladder) => ladder.last(),
+        }
+    }
+
+    fn range(&self, prices: RangeInclusive<u32>) -> PriceLevelsRange<'_> {
+        match self {
+            PriceLevels::Tree(tree) => PriceLevelsRange::Tree(PriceIndex::range(tree, prices)),
+            PriceLevels::Ladder(ladder) => PriceLevelsRange::Ladder(ladder.range(prices)),
+        }
+    }
+}
+
+impl Iterator for PriceLevelsRange<'_> {
+    type Item = (u32, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            PriceLevelsRange::Tree(range) => range.next(),
+            PriceLevelsRange::Ladder(range) => range.next(),
+        }
+    }
+}
+
+impl DoubleEndedIterator for PriceLevelsRange<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            PriceLevelsRange::Tree(range) => range.next_back(),
+            PriceLevelsRange::Ladder(range) => range.next_back(),
+        }
+    }
+}
+
 pub struct PriceNode {
     linked_list: SlabLinkedList<Order>,
     price: u32,
-    total_quantity: u32,
+    total_quantity: u64,
+    // Tells this level apart from earlier ones that had its slot
+    generation: u64,
+    // Number of orders of each quantity, for the smallest and largest
+    order_sizes: BTreeMap<u64, usize>,
 }
 
 impl PriceNode {
@@ -18,15 +167,50 @@ impl PriceNode {
         self.price
     }
 
-    pub fn total_quantity(&self) -> u32 {
+    pub fn total_quantity(&self) -> u64 {
         self.total_quantity
     }
 
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
     pub fn num_orders(&self) -> usize {
         self.linked_list.len()
     }
 
-    pub fn iter(&self) -> PriceNodeIterator {
+    pub fn min_order_quantity(&self) -> Option<u64> {
+        self.order_sizes
+            .first_key_value()
+            .map(|(&quantity, _)| quantity)
+    }
+
+    pub fn max_order_quantity(&self) -> Option<u64> {
+        self.order_sizes
+            .last_key_value()
+            .map(|(&quantity, _)| quantity)
+    }
+
+    // Timestamp of the order first in time priority. Orders join the back
+    // of a level, so it's the oldest.
+    pub fn oldest_order_timestamp(&self) -> Option<u64> {
+        self.linked_list.front().map(|order| order.timestamp())
+    }
+
+    fn add_order_size(&mut self, quantity: u64) {
+        *self.order_sizes.entry(quantity).or_default() += 1;
+    }
+
+    fn remove_order_size(&mut self, quantity: u64) {
+        if let btree_map::Entry::Occupied(mut entry) = self.order_sizes.entry(quantity) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+    }
+
+    pub fn iter(&self) -> PriceNodeIterator<'_> {
         PriceNodeIterator {
             linked_list_iter: self.linked_list.iter(),
         }
@@ -38,132 +222,381 @@ pub struct PriceNodeIterator<'a> {
 }
 
 impl<'a> Iterator for PriceNodeIterator<'a> {
-    type Item = (usize, &'a Order);
+    type Item = (NodeId, &'a Order);
 
     fn next(&mut self) -> Option<Self::Item> {
         self.linked_list_iter.next()
     }
 }
 
+// Finds an order in the tree. Like a list's NodeId, a key kept after its
+// order or level is gone finds nothing rather than whatever reused the slot.
 #[derive(Debug)]
 pub struct OrderKey {
     price_node_id: usize,
-    linked_list_node_id: usize,
+    price_node_generation: u64,
+    linked_list_node_id: NodeId,
 }
 
 impl OrderKey {
-    pub fn new(price_node_id: usize, linked_list_node_id: usize) -> OrderKey {
+    pub fn new(
+        price_node_id: usize,
+        price_node_generation: u64,
+        linked_list_node_id: NodeId,
+    ) -> OrderKey {
         OrderKey {
             price_node_id,
+            price_node_generation,
             linked_list_node_id,
         }
     }
 }
 
 pub struct PriceTree {
-    // tree: BTreeMap<u32, PriceNode>
-    tree: BTreeMap<u32, usize>,
+    // Price node id of the level at each price
+    tree: PriceLevels,
     slab: Slab<PriceNode>,
+    // Bids are best at the highest price, asks at the lowest
+    side: OrderType,
+    // Price node id of the best level, kept in sync on insert and remove
+    best: Option<usize>,
+    // Room for orders each new level starts with
+    level_capacity: usize,
+    // Generation the next new level gets
+    next_generation: u64,
 }
 
 impl PriceTree {
-    pub fn new() -> PriceTree {
+    pub fn new(side: OrderType) -> PriceTree {
+        PriceTree::with_capacity(side, 0, 0)
+    }
+
+    // Room for `levels` price levels, each starting with room for
+    // `level_capacity` orders, before anything has to grow
+    pub fn with_capacity(side: OrderType, levels: usize, level_capacity: usize) -> PriceTree {
         PriceTree {
-            tree: BTreeMap::new(),
-            slab: Slab::new(),
+            tree: PriceLevels::Tree(BTreeMap::new()),
+            slab: Slab::with_capacity(levels),
+            side,
+            best: None,
+            level_capacity,
+            next_generation: 0,
         }
     }
 
-    pub fn insert_order(&mut self, order: Order) -> OrderKey {
+    // Levels the tree can hold before growing
+    #[cfg(test)]
+    pub fn capacity(&self) -> usize {
+        self.slab.capacity()
+    }
+
+    pub fn reserve(&mut self, additional_levels: usize) {
+        self.slab.reserve(additional_levels);
+    }
+
+    pub fn set_level_capacity(&mut self, level_capacity: usize) {
+        self.level_capacity = level_capacity;
+    }
+
+    // Frees unused room in the tree and in every level
+    pub fn shrink_to_fit(&mut self) {
+        self.slab.shrink_to_fit();
+        for (_, price_node) in self.slab.iter_mut() {
+            price_node.linked_list.shrink_to_fit();
+        }
+    }
+
+    // Switches how levels are found by price. Only an empty tree can switch.
+    pub fn set_index(&mut self, index: PriceLevelIndex) -> Result<(), OrderBookError> {
+        if !self.slab.is_empty() {
+            return Err(OrderBookError::BookNotEmpty);
+        }
+        self.tree = match index {
+            PriceLevelIndex::Tree => PriceLevels::Tree(BTreeMap::new()),
+            PriceLevelIndex::Ladder {
+                min_price,
+                max_price,
+                tick_size,
+            } => PriceLevels::Ladder(
+                PriceLadder::new(min_price, max_price, tick_size)
+                    .map_err(|err| OrderBookError::InvalidPriceLadder(err.to_string()))?,
+            ),
+        };
+        Ok(())
+    }
+
+    // Whether an order at the price can rest in the tree
+    pub fn holds(&self, price: u32) -> bool {
+        self.tree.holds(price)
+    }
+
+    // Step between the prices the tree holds, any price without a ladder
+    pub fn tick_size(&self) -> u32 {
+        match &self.tree {
+            PriceLevels::Tree(_) => 1,
+            PriceLevels::Ladder(ladder) => ladder.tick_size(),
+        }
+    }
+
+    pub fn best(&self) -> Option<&PriceNode> {
+        self.best.map(|price_node_id| &self.slab[price_node_id])
+    }
+
+    fn is_better(&self, price: u32, other: u32) -> bool {
+        match self.side {
+            OrderType::Bid => price > other,
+            OrderType::Ask => price < other,
+        }
+    }
+
+    // Only needed when the best level itself is removed
+    fn refresh_best(&mut self) {
+        let best = match self.side {
+            OrderType::Bid => self.tree.last(),
+            OrderType::Ask => self.tree.first(),
+        };
+        self.best = best.map(|(_, price_node_id)| price_node_id);
+    }
+
+    pub fn insert_order(&mut self, order: Order) -> Result<OrderKey, OrderBookError> {
         let price = order.price();
-        match self.tree.get(&price) {
-            Some(&price_node_id) => {
+        match self.tree.get(price) {
+            Some(price_node_id) => {
                 // Get and insert order to price node's linked list
                 let price_node = &mut self.slab[price_node_id];
-                price_node.total_quantity += order.quantity();
+                price_node.total_quantity = price_node
+                    .total_quantity
+                    .checked_add(order.quantity())
+                    .ok_or(OrderBookError::QuantityOverflow)?;
+                price_node.add_order_size(order.quantity());
                 let linked_list_node_id = price_node.linked_list.push_back(order);
 
-                OrderKey {
+                Ok(OrderKey {
                     price_node_id,
+                    price_node_generation: price_node.generation,
                     linked_list_node_id,
-                }
+                })
             }
             None => {
+                // Index the level first, as a ladder may not hold its price
+                let price_node_id = self.slab.vacant_key();
+                self.tree.insert(price, price_node_id)?;
+
                 // Create a price node
                 let mut price_node = PriceNode {
-                    linked_list: SlabLinkedList::new(),
+                    linked_list: SlabLinkedList::with_capacity(self.level_capacity),
                     price,
                     total_quantity: order.quantity(),
+                    generation: self.next_generation,
+                    order_sizes: BTreeMap::new(),
                 };
+                price_node.add_order_size(order.quantity());
+                self.next_generation += 1;
+                let price_node_generation = price_node.generation;
                 let linked_list_node_id = price_node.linked_list.push_back(order);
-                let price_node_id = self.slab.insert(price_node);
-                self.tree.insert(price, price_node_id);
+                self.slab.insert(price_node);
+                if self
+                    .best()
+                    .is_none_or(|best| self.is_better(price, best.price))
+                {
+                    self.best = Some(price_node_id);
+                }
 
-                OrderKey {
+                Ok(OrderKey {
                     price_node_id,
+                    price_node_generation,
                     linked_list_node_id,
-                }
+                })
             }
         }
     }
 
-    pub fn remove_order(&mut self, key: &OrderKey) -> Result<()> {
-        match self.slab.get_mut(key.price_node_id) {
+    pub fn level(&self, price: u32) -> Option<&PriceNode> {
+        self.tree
+            .get(price)
+            .map(|price_node_id| &self.slab[price_node_id])
+    }
+
+    pub fn level_quantity(&self, price: u32) -> u64 {
+        self.tree
+            .get(price)
+            .map_or(0, |price_node_id| self.slab[price_node_id].total_quantity)
+    }
+
+    // Level the key's order was placed on, if it's still in the tree
+    fn key_level(&self, key: &OrderKey) -> Option<&PriceNode> {
+        self.slab
+            .get(key.price_node_id)
+            .filter(|price_node| price_node.generation == key.price_node_generation)
+    }
+
+    fn key_level_mut(&mut self, key: &OrderKey) -> Option<&mut PriceNode> {
+        self.slab
+            .get_mut(key.price_node_id)
+            .filter(|price_node| price_node.generation == key.price_node_generation)
+    }
+
+    pub fn remove_order(&mut self, key: &OrderKey) -> Result<Order, OrderBookError> {
+        match self.key_level_mut(key) {
             Some(price_node) => {
                 match price_node.linked_list.remove(key.linked_list_node_id) {
                     Some(order) => {
                         if price_node.linked_list.is_empty() {
                             // Remove price node from slab and tree
                             self.slab.remove(key.price_node_id);
-                            self.tree.remove(&order.price());
+                            self.tree.remove(order.price());
+                            if self.best == Some(key.price_node_id) {
+                                self.refresh_best();
+                            }
                         } else {
-                            price_node.total_quantity -= order.quantity()
+                            price_node.total_quantity -= order.quantity();
+                            price_node.remove_order_size(order.quantity());
                         }
-                        Ok(())
+                        Ok(order)
                     }
-                    None => Err(anyhow!("Order does not exist in linked list")),
+                    None => Err(OrderBookError::UnknownOrder),
                 }
             }
-            None => Err(anyhow!("Order does not exist in tree")),
+            None => Err(OrderBookError::UnknownOrder),
         }
     }
 
+    // Removes the level at the price in one step rather than order by order,
+    // returning its orders oldest first
+    pub fn remove_level(&mut self, price: u32) -> Option<SlabLinkedListIntoIter<Order>> {
+        let price_node_id = self.tree.remove(price)?;
+        let price_node = self.slab.remove(price_node_id);
+        if self.best == Some(price_node_id) {
+            self.refresh_best();
+        }
+        Some(price_node.linked_list.into_iter())
+    }
+
     // TODO: Needs testing
-    pub fn update_order_quantity(&mut self, key: &OrderKey, quantity: u32) -> Result<()> {
-        match self.slab.get_mut(key.price_node_id) {
+    pub fn update_order_quantity(
+        &mut self,
+        key: &OrderKey,
+        quantity: u64,
+    ) -> Result<(), OrderBookError> {
+        match self.key_level_mut(key) {
             Some(price_node) => match price_node.linked_list.get_mut(key.linked_list_node_id) {
                 Some(order) => {
-                    let original_quantity = order.quantity();
-                    let delta = original_quantity - quantity;
-                    price_node.total_quantity -= delta;
+                    let previous_quantity = order.quantity();
+                    let total_quantity = (price_node.total_quantity - previous_quantity)
+                        .checked_add(quantity)
+                        .ok_or(OrderBookError::QuantityOverflow)?;
+                    price_node.total_quantity = total_quantity;
                     order.update_quantity(quantity);
+                    price_node.remove_order_size(previous_quantity);
+                    price_node.add_order_size(quantity);
                     Ok(())
                 }
-                None => Err(anyhow!("Order does not exist in linked list")),
+                None => Err(OrderBookError::UnknownOrder),
             },
-            None => Err(anyhow!("Order does not exist in tree")),
+            None => Err(OrderBookError::UnknownOrder),
         }
     }
 
     // TODO: Needs testing
-    pub fn get_order(&mut self, key: &OrderKey) -> Option<&Order> {
-        match self.slab.get_mut(key.price_node_id) {
+    pub fn get_order(&self, key: &OrderKey) -> Option<&Order> {
+        match self.key_level(key) {
             Some(price_node) => price_node.linked_list.get(key.linked_list_node_id),
             None => None,
         }
     }
 
-    pub fn iter(&self) -> PriceTreeIterator {
+    pub fn iter(&self) -> PriceTreeIterator<'_, '_> {
         PriceTreeIterator {
             slab: &self.slab,
-            tree_iter: self.tree.iter(),
+            tree_iter: self.tree.range(0..=u32::MAX),
+        }
+    }
+
+    // Levels priced within the range, in ascending price order
+    pub fn range(&self, price_range: RangeInclusive<u32>) -> PriceTreeRange<'_, '_> {
+        PriceTreeRange {
+            slab: &self.slab,
+            tree_range: self.tree.range(price_range),
+        }
+    }
+
+    // Checks every level against its orders and the price index, and
+    // returns how many orders the tree holds. Walks the whole tree.
+    pub fn check(&self) -> anyhow::Result<usize> {
+        let mut orders = 0;
+        let mut levels = 0;
+        for (price, price_node_id) in self.tree.range(0..=u32::MAX) {
+            levels += 1;
+            let price_node = self
+                .slab
+                .get(price_node_id)
+                .ok_or_else(|| anyhow!("Level at {price} is indexed to an empty slot"))?;
+            if price_node.price != price {
+                return Err(anyhow!(
+                    "Level at {} is indexed at {price}",
+                    price_node.price
+                ));
+            }
+            if price_node.linked_list.is_empty() {
+                return Err(anyhow!("Empty level left at {price}"));
+            }
+            if !price_node.linked_list.is_consistent() {
+                return Err(anyhow!("Orders at {price} are not linked consistently"));
+            }
+            let mut total_quantity = 0;
+            let mut order_sizes = BTreeMap::new();
+            for (_, order) in price_node.linked_list.iter() {
+                if order.price() != price || order.quantity() == 0 {
+                    return Err(anyhow!(
+                        "Order {} of {}@{} rests at {price}",
+                        order.id(),
+                        order.quantity(),
+                        order.price()
+                    ));
+                }
+                total_quantity += order.quantity();
+                *order_sizes.entry(order.quantity()).or_default() += 1;
+            }
+            if total_quantity != price_node.total_quantity {
+                return Err(anyhow!(
+                    "Level at {price} totals {} but its orders add up to {total_quantity}",
+                    price_node.total_quantity
+                ));
+            }
+            if order_sizes != price_node.order_sizes {
+                return Err(anyhow!("Order sizes at {price} are out of date"));
+            }
+            orders += price_node.linked_list.len();
+        }
+        if levels != self.slab.len() {
+            return Err(anyhow!(
+                "{} levels are stored but {levels} are indexed",
+                self.slab.len()
+            ));
+        }
+        let best = match self.side {
+            OrderType::Bid => self.tree.last(),
+            OrderType::Ask => self.tree.first(),
+        };
+        if best.map(|(_, price_node_id)| price_node_id) != self.best {
+            return Err(anyhow!("Best level is out of date"));
+        }
+        Ok(orders)
+    }
+
+    // The best `n` levels, best first
+    pub fn top_n(&self, n: usize) -> TopLevels<'_, '_> {
+        TopLevels {
+            tree_iter: self.iter(),
+            side: self.side,
+            remaining: n,
         }
     }
 }
 
 pub struct PriceTreeIterator<'a, 'b> {
     slab: &'a Slab<PriceNode>,
-    tree_iter: std::collections::btree_map::Iter<'b, u32, usize>,
+    tree_iter: PriceLevelsRange<'b>,
 }
 
 impl<'a, 'b> Iterator for PriceTreeIterator<'a, 'b> {
@@ -171,7 +604,7 @@ impl<'a, 'b> Iterator for PriceTreeIterator<'a, 'b> {
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.tree_iter.next() {
-            Some((_, &node_id)) => Some((node_id, &self.slab[node_id])),
+            Some((_, node_id)) => Some((node_id, &self.slab[node_id])),
             None => None,
         }
     }
@@ -181,12 +614,54 @@ impl<'a, 'b> Iterator for PriceTreeIterator<'a, 'b> {
 impl<'a, 'b> DoubleEndedIterator for PriceTreeIterator<'a, 'b> {
     fn next_back(&mut self) -> Option<Self::Item> {
         match self.tree_iter.next_back() {
-            Some((_, &node_id)) => Some((node_id, &self.slab[node_id])),
+            Some((_, node_id)) => Some((node_id, &self.slab[node_id])),
             None => None,
         }
     }
 }
 
+pub struct PriceTreeRange<'a, 'b> {
+    slab: &'a Slab<PriceNode>,
+    tree_range: PriceLevelsRange<'b>,
+}
+
+impl<'a, 'b> Iterator for PriceTreeRange<'a, 'b> {
+    type Item = (usize, &'a PriceNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, node_id) = self.tree_range.next()?;
+        Some((node_id, &self.slab[node_id]))
+    }
+}
+
+impl<'a, 'b> DoubleEndedIterator for PriceTreeRange<'a, 'b> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (_, node_id) = self.tree_range.next_back()?;
+        Some((node_id, &self.slab[node_id]))
+    }
+}
+
+pub struct TopLevels<'a, 'b> {
+    tree_iter: PriceTreeIterator<'a, 'b>,
+    side: OrderType,
+    remaining: usize,
+}
+
+impl<'a, 'b> Iterator for TopLevels<'a, 'b> {
+    type Item = (usize, &'a PriceNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        match self.side {
+            OrderType::Bid => self.tree_iter.next_back(),
+            OrderType::Ask => self.tree_iter.next(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -196,14 +671,14 @@ mod tests {
 
     #[test]
     fn test_insert_order_simple() {
-        let mut price_tree = PriceTree::new();
+        let mut price_tree = PriceTree::new(OrderType::Ask);
         let order1 = Order::new(100, 10);
         let order2 = Order::new(150, 5);
         let order3 = Order::new(100, 4);
 
-        let key1 = price_tree.insert_order(order1);
-        let key2 = price_tree.insert_order(order2);
-        price_tree.insert_order(order3);
+        let key1 = price_tree.insert_order(order1).unwrap();
+        let key2 = price_tree.insert_order(order2).unwrap();
+        price_tree.insert_order(order3).unwrap();
 
         assert_eq!(price_tree.slab.len(), 2);
         assert_eq!(price_tree.slab[key1.price_node_id].total_quantity, 14);
@@ -221,15 +696,15 @@ mod tests {
     #[test]
     fn test_insert_order_advanced() {
         let mut rng = rand::thread_rng();
-        let mut price_tree = PriceTree::new();
+        let mut price_tree = PriceTree::new(OrderType::Ask);
 
-        let mut quantity_map: HashMap<u32, u32> = HashMap::new();
+        let mut quantity_map: HashMap<u32, u64> = HashMap::new();
 
         for _ in 0..10000 {
             let price: u32 = rng.gen_range(1..30);
-            let quantity: u32 = rng.gen_range(1..500);
+            let quantity: u64 = rng.gen_range(1..500);
             let order = Order::new(price, quantity);
-            price_tree.insert_order(order);
+            price_tree.insert_order(order).unwrap();
 
             match quantity_map.get_mut(&price) {
                 Some(total_quantity) => *total_quantity += quantity,
@@ -241,7 +716,7 @@ mod tests {
 
         for (price, &total_quantity) in quantity_map.iter() {
             assert_eq!(
-                price_tree.slab[price_tree.tree[price]].total_quantity,
+                price_tree.slab[price_tree.tree.get(*price).unwrap()].total_quantity,
                 total_quantity
             );
         }
@@ -249,24 +724,24 @@ mod tests {
 
     #[test]
     fn test_remove_order() {
-        let mut price_tree = PriceTree::new();
+        let mut price_tree = PriceTree::new(OrderType::Ask);
         let order = Order::new(200, 8);
-        let key = price_tree.insert_order(order);
+        let key = price_tree.insert_order(order).unwrap();
 
-        assert_eq!(price_tree.remove_order(&key).unwrap(), ());
+        assert_eq!(price_tree.remove_order(&key).unwrap().quantity(), 8);
         assert_eq!(price_tree.slab.len(), 0);
     }
 
     #[test]
     fn test_iteration() {
-        let mut price_tree = PriceTree::new();
+        let mut price_tree = PriceTree::new(OrderType::Ask);
         let order1 = Order::new(120, 3);
         let order2 = Order::new(140, 6);
         let order3 = Order::new(140, 2);
 
-        price_tree.insert_order(order1);
-        price_tree.insert_order(order2);
-        price_tree.insert_order(order3);
+        price_tree.insert_order(order1).unwrap();
+        price_tree.insert_order(order2).unwrap();
+        price_tree.insert_order(order3).unwrap();
 
         let mut iter = price_tree.iter();
 
@@ -276,18 +751,261 @@ mod tests {
 
     #[test]
     fn test_iteration_rev() {
-        let mut price_tree = PriceTree::new();
+        let mut price_tree = PriceTree::new(OrderType::Ask);
         let order1 = Order::new(120, 3);
         let order2 = Order::new(140, 6);
         let order3 = Order::new(140, 2);
 
-        price_tree.insert_order(order1);
-        price_tree.insert_order(order2);
-        price_tree.insert_order(order3);
+        price_tree.insert_order(order1).unwrap();
+        price_tree.insert_order(order2).unwrap();
+        price_tree.insert_order(order3).unwrap();
 
         let mut iter = price_tree.iter().rev();
 
         assert_eq!(iter.next().unwrap().1.total_quantity, 8);
         assert_eq!(iter.next().unwrap().1.total_quantity, 3);
     }
+
+    #[test]
+    fn test_capacity() {
+        let mut price_tree = PriceTree::with_capacity(OrderType::Bid, 8, 4);
+        assert!(price_tree.capacity() >= 8);
+        let key = price_tree.insert_order(Order::new(100, 1)).unwrap();
+        assert!(price_tree.slab[key.price_node_id].linked_list.capacity() >= 4);
+
+        price_tree.reserve(100);
+        assert!(price_tree.capacity() >= 101);
+        price_tree.shrink_to_fit();
+        assert!(price_tree.capacity() < 101);
+        assert!(price_tree.slab[key.price_node_id].linked_list.capacity() < 4);
+        assert_eq!(price_tree.best().unwrap().total_quantity(), 1);
+    }
+
+    #[test]
+    fn test_best_level() {
+        let mut bid_tree = PriceTree::new(OrderType::Bid);
+        let mut ask_tree = PriceTree::new(OrderType::Ask);
+        assert!(bid_tree.best().is_none());
+
+        let mut bid_keys = Vec::new();
+        for price in [100, 120, 110] {
+            bid_keys.push(bid_tree.insert_order(Order::new(price, 1)).unwrap());
+            ask_tree.insert_order(Order::new(price, 1)).unwrap();
+        }
+        assert_eq!(bid_tree.best().unwrap().price(), 120);
+        assert_eq!(ask_tree.best().unwrap().price(), 100);
+
+        // Removing a level behind the best keeps it
+        bid_tree.remove_order(&bid_keys[0]).unwrap();
+        assert_eq!(bid_tree.best().unwrap().price(), 120);
+
+        // Removing the best level falls back to the next one
+        bid_tree.remove_order(&bid_keys[1]).unwrap();
+        assert_eq!(bid_tree.best().unwrap().price(), 110);
+        bid_tree.remove_order(&bid_keys[2]).unwrap();
+        assert!(bid_tree.best().is_none());
+    }
+
+    #[test]
+    fn test_best_level_randomized() {
+        let mut rng = rand::thread_rng();
+        let mut price_tree = PriceTree::new(OrderType::Bid);
+        let mut keys = Vec::new();
+
+        for _ in 0..2000 {
+            if keys.is_empty() || rng.gen_bool(0.6) {
+                let price: u32 = rng.gen_range(1..50);
+                keys.push(price_tree.insert_order(Order::new(price, 1)).unwrap());
+            } else {
+                let key = keys.swap_remove(rng.gen_range(0..keys.len()));
+                price_tree.remove_order(&key).unwrap();
+            }
+
+            let expected = price_tree.iter().next_back().map(|(_, node)| node.price());
+            assert_eq!(price_tree.best().map(|node| node.price()), expected);
+        }
+    }
+
+    #[test]
+    fn test_range() {
+        let mut price_tree = PriceTree::new(OrderType::Ask);
+        for price in [90, 100, 105, 110, 120] {
+            price_tree.insert_order(Order::new(price, 1)).unwrap();
+        }
+
+        let prices: Vec<u32> = price_tree
+            .range(100..=110)
+            .map(|(_, node)| node.price())
+            .collect();
+        assert_eq!(prices, [100, 105, 110]);
+        assert_eq!(price_tree.range(111..=119).count(), 0);
+        assert_eq!(
+            price_tree
+                .range(0..=u32::MAX)
+                .next_back()
+                .unwrap()
+                .1
+                .price(),
+            120
+        );
+    }
+
+    #[test]
+    fn test_top_n() {
+        let mut bid_tree = PriceTree::new(OrderType::Bid);
+        let mut ask_tree = PriceTree::new(OrderType::Ask);
+        for price in [90, 100, 110] {
+            bid_tree.insert_order(Order::new(price, 1)).unwrap();
+            ask_tree.insert_order(Order::new(price, 1)).unwrap();
+        }
+
+        let top_bids: Vec<u32> = bid_tree.top_n(2).map(|(_, node)| node.price()).collect();
+        let top_asks: Vec<u32> = ask_tree.top_n(2).map(|(_, node)| node.price()).collect();
+        assert_eq!(top_bids, [110, 100]);
+        assert_eq!(top_asks, [90, 100]);
+        assert_eq!(ask_tree.top_n(10).count(), 3);
+        assert_eq!(ask_tree.top_n(0).count(), 0);
+    }
+
+    #[test]
+    fn test_insert_order_overflow() {
+        let mut price_tree = PriceTree::new(OrderType::Ask);
+        price_tree.insert_order(Order::new(100, u64::MAX)).unwrap();
+        assert!(price_tree.insert_order(Order::new(100, 1)).is_err());
+
+        let mut iter = price_tree.iter();
+        let (_, price_node) = iter.next().unwrap();
+        assert_eq!(price_node.total_quantity(), u64::MAX);
+        assert_eq!(price_node.num_orders(), 1);
+    }
+
+    #[test]
+    fn test_update_order_quantity() {
+        let mut price_tree = PriceTree::new(OrderType::Ask);
+        let key1 = price_tree.insert_order(Order::new(100, 10)).unwrap();
+        price_tree
+            .insert_order(Order::new(100, u64::MAX - 10))
+            .unwrap();
+
+        price_tree.update_order_quantity(&key1, 4).unwrap();
+        assert_eq!(price_tree.get_order(&key1).unwrap().quantity(), 4);
+        assert_eq!(
+            price_tree.slab[key1.price_node_id].total_quantity,
+            u64::MAX - 6
+        );
+
+        // Growing the order past the level capacity is rejected untouched
+        assert!(price_tree.update_order_quantity(&key1, 11).is_err());
+        assert_eq!(price_tree.get_order(&key1).unwrap().quantity(), 4);
+    }
+
+    #[test]
+    fn test_stale_key_finds_nothing() {
+        let mut price_tree = PriceTree::new(OrderType::Ask);
+        let stale = price_tree.insert_order(Order::new(100, 5)).unwrap();
+        price_tree.remove_order(&stale).unwrap();
+        // A new level takes the removed level's slot, and its first order
+        // the removed order's slot in the list
+        let key = price_tree.insert_order(Order::new(110, 7)).unwrap();
+        assert_eq!(key.price_node_id, stale.price_node_id);
+
+        assert!(price_tree.get_order(&stale).is_none());
+        assert!(price_tree.update_order_quantity(&stale, 1).is_err());
+        assert!(price_tree.remove_order(&stale).is_err());
+        assert_eq!(price_tree.get_order(&key).unwrap().quantity(), 7);
+        assert_eq!(price_tree.level_quantity(110), 7);
+    }
+
+    #[test]
+    fn test_remove_level() {
+        let mut price_tree = PriceTree::new(OrderType::Ask);
+        let key = price_tree.insert_order(Order::new(100, 5)).unwrap();
+        price_tree.insert_order(Order::new(100, 3)).unwrap();
+        price_tree.insert_order(Order::new(105, 2)).unwrap();
+
+        let orders = price_tree.remove_level(100).unwrap();
+        assert_eq!(
+            orders.map(|order| order.quantity()).collect::<Vec<_>>(),
+            vec![5, 3]
+        );
+        assert!(price_tree.level(100).is_none());
+        assert!(price_tree.get_order(&key).is_none());
+        assert_eq!(price_tree.best().unwrap().price(), 105);
+        assert!(price_tree.remove_level(100).is_none());
+
+        assert_eq!(price_tree.remove_level(105).unwrap().len(), 1);
+        assert!(price_tree.best().is_none());
+        assert_eq!(price_tree.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_ladder_index() {
+        let mut bid_tree = PriceTree::new(OrderType::Bid);
+        bid_tree
+            .set_index(PriceLevelIndex::Ladder {
+                min_price: 100,
+                max_price: 200,
+                tick_size: 10,
+            })
+            .unwrap();
+        assert!(bid_tree.holds(150));
+        assert!(!bid_tree.holds(155));
+        assert!(bid_tree.insert_order(Order::new(155, 1)).is_err());
+        assert!(bid_tree.insert_order(Order::new(210, 1)).is_err());
+        assert!(bid_tree.iter().next().is_none());
+
+        let keys: Vec<OrderKey> = [120, 180, 150, 180]
+            .into_iter()
+            .map(|price| bid_tree.insert_order(Order::new(price, 5)).unwrap())
+            .collect();
+        assert_eq!(bid_tree.best().unwrap().price(), 180);
+        assert_eq!(bid_tree.level_quantity(180), 10);
+        assert_eq!(
+            bid_tree
+                .top_n(2)
+                .map(|(_, level)| level.price())
+                .collect::<Vec<_>>(),
+            vec![180, 150]
+        );
+        assert_eq!(
+            bid_tree
+                .range(130..=200)
+                .map(|(_, level)| level.price())
+                .collect::<Vec<_>>(),
+            vec![150, 180]
+        );
+        assert!(bid_tree.set_index(PriceLevelIndex::Tree).is_err());
+
+        bid_tree.remove_order(&keys[1]).unwrap();
+        bid_tree.remove_order(&keys[3]).unwrap();
+        assert_eq!(bid_tree.best().unwrap().price(), 150);
+        assert_eq!(bid_tree.remove_level(150).unwrap().len(), 1);
+        assert_eq!(bid_tree.best().unwrap().price(), 120);
+    }
+
+    #[test]
+    fn test_level_order_sizes() {
+        let mut price_tree = PriceTree::new(OrderType::Ask);
+        let keys: Vec<OrderKey> = [4, 10, 4, 7]
+            .into_iter()
+            .map(|quantity| price_tree.insert_order(Order::new(100, quantity)).unwrap())
+            .collect();
+        let level = price_tree.level(100).unwrap();
+        assert_eq!(level.num_orders(), 4);
+        assert_eq!(level.min_order_quantity(), Some(4));
+        assert_eq!(level.max_order_quantity(), Some(10));
+
+        price_tree.update_order_quantity(&keys[1], 3).unwrap();
+        let level = price_tree.level(100).unwrap();
+        assert_eq!(level.min_order_quantity(), Some(3));
+        assert_eq!(level.max_order_quantity(), Some(7));
+
+        // One of the two orders of 4 leaves, so 4 is still the next smallest
+        price_tree.remove_order(&keys[1]).unwrap();
+        price_tree.remove_order(&keys[0]).unwrap();
+        let level = price_tree.level(100).unwrap();
+        assert_eq!(level.min_order_quantity(), Some(4));
+        assert_eq!(level.max_order_quantity(), Some(7));
+        assert_eq!(level.oldest_order_timestamp(), Some(0));
+    }
 }
@@ -0,0 +1,139 @@
+// The wrappers pyo3 0.22 generates for methods returning PyResult convert
+// the error into itself
+#![allow(clippy::useless_conversion)]
+
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+use uuid::Uuid;
+
+use crate::{
+    book::{L2Entry, OrderBook, OrderType},
+    error::OrderBookError,
+};
+
+// Python module wrapping an OrderBook, to drive the engine from notebooks
+// and backtests without the server. Sides are "bid" or "ask", order ids
+// their string form and book errors raise ValueError.
+#[pyclass(name = "OrderBook")]
+pub struct PyOrderBook {
+    book: OrderBook,
+}
+
+#[pymethods]
+impl PyOrderBook {
+    #[new]
+    fn new() -> PyOrderBook {
+        PyOrderBook {
+            book: OrderBook::new(),
+        }
+    }
+
+    // Id of the placed order, which may have traded in full already
+    #[pyo3(signature = (owner, side, price, quantity, expires_at = None))]
+    fn place(
+        &mut self,
+        owner: &str,
+        side: &str,
+        price: u32,
+        quantity: u64,
+        expires_at: Option<u64>,
+    ) -> PyResult<String> {
+        let order_id = self
+            .book
+            .place_order_with_expiry(owner, price, quantity, parse_side(side)?, expires_at)
+            .map_err(rejected)?;
+        Ok(order_id.to_string())
+    }
+
+    // Quantity that was still resting
+    fn cancel(&mut self, order_id: &str) -> PyResult<u64> {
+        let order_id = Uuid::parse_str(order_id)
+            .map_err(|err| PyValueError::new_err(format!("Invalid order id: {err}")))?;
+        self.book.cancel_order(order_id).map_err(rejected)
+    }
+
+    // Levels of either side as (price, total quantity, number of orders) in
+    // ascending price order, all of them or the best `depth`
+    #[pyo3(signature = (depth = None))]
+    fn l2<'py>(&self, py: Python<'py>, depth: Option<usize>) -> PyResult<Bound<'py, PyDict>> {
+        let l2_book = match depth {
+            Some(depth) => self.book.view_book_l2_depth(depth),
+            None => self.book.view_book_l2(),
+        };
+        let levels = |entries: &[L2Entry]| -> Vec<(u32, u64, usize)> {
+            entries
+                .iter()
+                .map(|entry| (entry.price, entry.total_quantity, entry.num_orders))
+                .collect()
+        };
+        let dict = PyDict::new_bound(py);
+        dict.set_item("as_of_seq", l2_book.as_of_seq)?;
+        dict.set_item("bid", levels(&l2_book.bid))?;
+        dict.set_item("ask", levels(&l2_book.ask))?;
+        Ok(dict)
+    }
+
+    fn best_bid(&self) -> Option<u32> {
+        self.book.best_bid().map(|price_node| price_node.price())
+    }
+
+    fn best_ask(&self) -> Option<u32> {
+        self.book.best_ask().map(|price_node| price_node.price())
+    }
+
+    // Trades from `from_seq` on, oldest first, as dicts
+    #[pyo3(signature = (from_seq = 1, limit = 1_000))]
+    fn trades<'py>(
+        &self,
+        py: Python<'py>,
+        from_seq: u64,
+        limit: usize,
+    ) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let trades = self
+            .book
+            .get_trades(from_seq, limit)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        trades
+            .into_iter()
+            .map(|trade| {
+                let dict = PyDict::new_bound(py);
+                dict.set_item("seq", trade.seq)?;
+                dict.set_item("timestamp", trade.timestamp)?;
+                dict.set_item("price", trade.price)?;
+                dict.set_item("quantity", trade.quantity)?;
+                dict.set_item("aggressor", trade.aggressor.map(side_name))?;
+                dict.set_item("maker_order_id", trade.maker_order_id.to_string())?;
+                dict.set_item("maker_owner", trade.maker_owner)?;
+                dict.set_item("taker_order_id", trade.taker_order_id.to_string())?;
+                dict.set_item("taker_owner", trade.taker_owner)?;
+                Ok(dict)
+            })
+            .collect()
+    }
+}
+
+fn parse_side(side: &str) -> PyResult<OrderType> {
+    match side {
+        "bid" => Ok(OrderType::Bid),
+        "ask" => Ok(OrderType::Ask),
+        _ => Err(PyValueError::new_err(format!(
+            "Expected bid or ask but got {side}"
+        ))),
+    }
+}
+
+fn side_name(side: OrderType) -> &'static str {
+    match side {
+        OrderType::Bid => "bid",
+        OrderType::Ask => "ask",
+    }
+}
+
+fn rejected(err: OrderBookError) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+#[pymodule]
+fn order_book(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyOrderBook>()?;
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+pub const MAX_PAGE_LIMIT: usize = 1_000;
+
+// Paging and time filter shared by every list query. Results are ordered by
+// cursor, oldest first.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PageRequest {
+    // Cursor of the first item to return, taken from a previous page's
+    // next_cursor. None starts from the oldest item.
+    pub cursor: Option<u64>,
+    pub limit: usize,
+    // Unix timestamps in milliseconds, both inclusive
+    pub from_time: Option<u64>,
+    pub to_time: Option<u64>,
+}
+
+impl PageRequest {
+    pub fn validate(&self) -> Result<()> {
+        if self.limit == 0 || self.limit > MAX_PAGE_LIMIT {
+            return Err(anyhow!(
+                "Page limit should be between 1 and {MAX_PAGE_LIMIT}"
+            ));
+        }
+        if let (Some(from_time), Some(to_time)) = (self.from_time, self.to_time) {
+            if from_time > to_time {
+                return Err(anyhow!("Time filter starts after it ends"));
+            }
+        }
+        Ok(())
+    }
+
+    fn in_time_range(&self, timestamp: u64) -> bool {
+        self.from_time
+            .is_none_or(|from_time| timestamp >= from_time)
+            && self.to_time.is_none_or(|to_time| timestamp <= to_time)
+    }
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        PageRequest {
+            cursor: None,
+            limit: DEFAULT_PAGE_LIMIT,
+            from_time: None,
+            to_time: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    // Cursor to request the following page with. None on the last page.
+    pub next_cursor: Option<u64>,
+}
+
+pub trait Paginated {
+    fn cursor(&self) -> u64;
+
+    fn timestamp(&self) -> u64;
+}
+
+// Builds a page from items ordered by cursor and timestamp, starting at the
+// requested cursor
+pub fn paginate<T: Paginated>(
+    items: impl IntoIterator<Item = Result<T>>,
+    page: &PageRequest,
+) -> Result<Page<T>> {
+    page.validate()?;
+    let mut page_items = Vec::new();
+    for item in items {
+        let item = item?;
+        if page.cursor.is_some_and(|cursor| item.cursor() < cursor)
+            || page
+                .from_time
+                .is_some_and(|from_time| item.timestamp() < from_time)
+        {
+            continue;
+        }
+        if !page.in_time_range(item.timestamp()) {
+            // Items are in time order, so nothing later can match
+            break;
+        }
+        if page_items.len() == page.limit {
+            return Ok(Page {
+                items: page_items,
+                next_cursor: Some(item.cursor()),
+            });
+        }
+        page_items.push(item);
+    }
+    Ok(Page {
+        items: page_items,
+        next_cursor: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Item(u64);
+
+    impl Paginated for Item {
+        fn cursor(&self) -> u64 {
+            self.0
+        }
+
+        // Ten milliseconds apart
+        fn timestamp(&self) -> u64 {
+            self.0 * 10
+        }
+    }
+
+    fn items() -> impl Iterator<Item = Result<Item>> {
+        (1..=10).map(|i| Ok(Item(i)))
+    }
+
+    #[test]
+    fn test_pages_follow_cursor() {
+        let mut page = PageRequest {
+            limit: 4,
+            ..Default::default()
+        };
+        let mut seen = Vec::new();
+        loop {
+            let result = paginate(items(), &page).unwrap();
+            seen.extend(result.items.into_iter().map(|item| item.0));
+            match result.next_cursor {
+                Some(cursor) => page.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(seen, (1..=10).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn test_time_filter() {
+        let page = PageRequest {
+            from_time: Some(25),
+            to_time: Some(60),
+            ..Default::default()
+        };
+        let result = paginate(items(), &page).unwrap();
+        assert_eq!(result.items, [Item(3), Item(4), Item(5), Item(6)]);
+        assert_eq!(result.next_cursor, None);
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        for page in [
+            PageRequest {
+                limit: 0,
+                ..Default::default()
+            },
+            PageRequest {
+                limit: MAX_PAGE_LIMIT + 1,
+                ..Default::default()
+            },
+            PageRequest {
+                from_time: Some(2),
+                to_time: Some(1),
+                ..Default::default()
+            },
+        ] {
+            assert!(paginate(items(), &page).is_err());
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+use crate::{
+    book::{OrderBook, OrderType},
+    feed::BookEvent,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuoteConfig {
+    // Every quote is placed under this owner
+    pub owner: String,
+    // Ticks between the mid and each quote
+    pub half_spread: u32,
+    pub size: u64,
+    // Ticks the mid has to move before quotes that haven't traded are replaced
+    pub requote_threshold: u32,
+    // Mid used until anyone else quotes or trades
+    pub initial_mid: u32,
+    // Largest position either way. A side that could go past it isn't quoted.
+    pub max_position: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quote {
+    pub order_type: OrderType,
+    pub price: u32,
+    pub quantity: u64,
+}
+
+// Two-sided quoting around the mid of a mirrored book. The quoter follows
+// its own orders through the book's events, so it learns of fills from the
+// feed rather than by polling order status.
+pub struct Quoter {
+    config: QuoteConfig,
+    // Side of every quote ever placed. Fills can arrive after a quote was
+    // dropped from `live`, e.g. when it traded just before being canceled.
+    sides: HashMap<Uuid, OrderType>,
+    live: HashSet<Uuid>,
+    // Mid the live quotes were placed around
+    quoted_mid: Option<u32>,
+    // One of the quotes traded or was canceled, so they need replacing
+    stale: bool,
+    position: i64,
+}
+
+impl Quoter {
+    pub fn new(config: QuoteConfig) -> Quoter {
+        Quoter {
+            config,
+            sides: HashMap::new(),
+            live: HashSet::new(),
+            quoted_mid: None,
+            stale: false,
+            position: 0,
+        }
+    }
+
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    pub fn live_orders(&self) -> Vec<Uuid> {
+        self.live.iter().copied().collect()
+    }
+
+    pub fn on_event(&mut self, event: &BookEvent) {
+        match event {
+            BookEvent::Traded(trade) => {
+                for order_id in [trade.maker_order_id, trade.taker_order_id] {
+                    match self.sides.get(&order_id) {
+                        Some(OrderType::Bid) => self.position += trade.quantity as i64,
+                        Some(OrderType::Ask) => self.position -= trade.quantity as i64,
+                        None => continue,
+                    }
+                    if self.live.contains(&order_id) {
+                        self.stale = true;
+                    }
+                }
+            }
+            BookEvent::OrderRemoved { order_id, .. } if self.live.remove(order_id) => {
+                self.stale = true;
+            }
+            _ => {}
+        }
+    }
+
+    // Mid of everyone else's best prices, so the quoter doesn't chase its
+    // own quotes. Falls back to the last trade and then the initial mid.
+    pub fn fair_mid(&self, book: &OrderBook) -> u32 {
+        let best = |side| {
+            book.top_levels(side, usize::MAX)
+                .find(|(_, level)| {
+                    level
+                        .iter()
+                        .any(|(_, order)| order.owner() != self.config.owner)
+                })
+                .map(|(_, level)| level.price())
+        };
+        match (best(OrderType::Bid), best(OrderType::Ask)) {
+            (Some(bid), Some(ask)) => ((bid as u64 + ask as u64) / 2) as u32,
+            _ => book
+                .session_stats()
+                .last()
+                .unwrap_or(self.config.initial_mid),
+        }
+    }
+
+    // Quotes to replace the live ones with, if they are due a refresh. The
+    // caller cancels the live orders, places these and reports their ids
+    // back through `record_placed`.
+    pub fn requote(&mut self, book: &OrderBook) -> Option<Vec<Quote>> {
+        let mid = self.fair_mid(book);
+        let moved = self
+            .quoted_mid
+            .is_none_or(|quoted_mid| quoted_mid.abs_diff(mid) >= self.config.requote_threshold);
+        if !self.stale && !moved {
+            return None;
+        }
+        self.stale = false;
+        self.quoted_mid = Some(mid);
+
+        let size = self.config.size as i64;
+        let mut quotes = Vec::new();
+        if self.position + size <= self.config.max_position {
+            quotes.push(Quote {
+                order_type: OrderType::Bid,
+                price: mid.saturating_sub(self.config.half_spread).max(1),
+                quantity: self.config.size,
+            });
+        }
+        if self.position - size >= -self.config.max_position {
+            quotes.push(Quote {
+                order_type: OrderType::Ask,
+                price: mid.saturating_add(self.config.half_spread),
+                quantity: self.config.size,
+            });
+        }
+        Some(quotes)
+    }
+
+    pub fn record_placed(&mut self, order_id: Uuid, order_type: OrderType) {
+        self.sides.insert(order_id, order_type);
+        self.live.insert(order_id);
+    }
+
+    // The live quotes were canceled, or had gone already
+    pub fn clear_live(&mut self) {
+        self.live.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> QuoteConfig {
+        QuoteConfig {
+            owner: "mm".to_string(),
+            half_spread: 2,
+            size: 10,
+            requote_threshold: 3,
+            initial_mid: 100,
+            max_position: 15,
+        }
+    }
+
+    // Places the quotes on the book the way the bot would
+    fn place_quotes(quoter: &mut Quoter, book: &mut OrderBook, quotes: &[Quote]) {
+        for order_id in quoter.live_orders() {
+            let _ = book.cancel_order(order_id);
+        }
+        quoter.clear_live();
+        for quote in quotes {
+            let order_id = book
+                .place_order("mm", quote.price, quote.quantity, quote.order_type)
+                .unwrap();
+            quoter.record_placed(order_id, quote.order_type);
+        }
+    }
+
+    fn feed(quoter: &mut Quoter, book: &OrderBook, from_seq: &mut u64) {
+        for event in book.events_since(*from_seq, usize::MAX).unwrap() {
+            *from_seq = event.seq + 1;
+            quoter.on_event(&event.event);
+        }
+    }
+
+    #[test]
+    fn test_quotes_follow_mid_and_fills() {
+        let mut book = OrderBook::new();
+        let mut quoter = Quoter::new(config());
+        let mut from_seq = 1;
+
+        // Nothing else on the book, so the initial mid is used
+        let quotes = quoter.requote(&book).unwrap();
+        assert_eq!(
+            quotes.iter().map(|quote| quote.price).collect::<Vec<_>>(),
+            vec![98, 102]
+        );
+        place_quotes(&mut quoter, &mut book, &quotes);
+        feed(&mut quoter, &book, &mut from_seq);
+        assert!(quoter.requote(&book).is_none());
+
+        // Others quoting 101-105 move the mid by 3, over the threshold
+        book.place_order("alice", 101, 5, OrderType::Bid).unwrap();
+        book.place_order("bob", 105, 5, OrderType::Ask).unwrap();
+        feed(&mut quoter, &book, &mut from_seq);
+        let quotes = quoter.requote(&book).unwrap();
+        assert_eq!(
+            quotes.iter().map(|quote| quote.price).collect::<Vec<_>>(),
+            vec![101, 105]
+        );
+        place_quotes(&mut quoter, &mut book, &quotes);
+        feed(&mut quoter, &book, &mut from_seq);
+
+        // Filling the ask behind bob's requotes and leaves the quoter short.
+        // Another ask would go past the position limit, so only the bid is
+        // quoted.
+        book.place_order("carol", 105, 15, OrderType::Bid).unwrap();
+        feed(&mut quoter, &book, &mut from_seq);
+        assert_eq!(quoter.position(), -10);
+        let quotes = quoter.requote(&book).unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].order_type, OrderType::Bid);
+    }
+}
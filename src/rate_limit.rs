@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct RateLimit {
+    // Sustained requests per second allowed on one connection
+    pub requests_per_sec: u32,
+    // Requests a connection can send at once after being idle
+    pub burst: u32,
+}
+
+// Token bucket holding up to `burst` requests, refilled continuously at
+// `requests_per_sec`
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit, now: Instant) -> TokenBucket {
+        TokenBucket {
+            limit,
+            tokens: limit.burst as f64,
+            refilled_at: now,
+        }
+    }
+
+    // Takes a token for one request, or returns false if none are left
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.limit.requests_per_sec as f64)
+            .min(self.limit.burst as f64);
+        self.refilled_at = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_token_bucket_refills_up_to_burst() {
+        let start = Instant::now();
+        let limit = RateLimit {
+            requests_per_sec: 10,
+            burst: 3,
+        };
+        let mut bucket = TokenBucket::new(limit, start);
+        assert!((0..3).all(|_| bucket.try_acquire(start)));
+        assert!(!bucket.try_acquire(start));
+
+        // One token every 100ms
+        assert!(!bucket.try_acquire(start + Duration::from_millis(50)));
+        assert!(bucket.try_acquire(start + Duration::from_millis(100)));
+        assert!(!bucket.try_acquire(start + Duration::from_millis(100)));
+
+        // Idle for long enough refills no more than the burst
+        let later = start + Duration::from_secs(60);
+        assert!((0..3).all(|_| bucket.try_acquire(later)));
+        assert!(!bucket.try_acquire(later));
+    }
+}
@@ -0,0 +1,278 @@
+use anyhow::{anyhow, Result};
+use std::time::{Duration, Instant};
+use tokio::{net::TcpStream, sync::watch, time};
+
+use crate::{
+    engine::BookHandle,
+    req::{ReplicateArgs, Request},
+    resp::Response,
+    wire::Framed,
+};
+
+// Primary-backup replication. A standby server connects to the primary with
+// Request::Replicate and is pushed the primary's event log as it grows,
+// which it applies to its own book. A standby that starts from scratch or
+// reconnects asks for the events after the last one it applied; when the
+// primary no longer retains those it sends a snapshot of its resting orders
+// first, and the events after it. Every so often, once the standby is
+// caught up, the primary also sends the checksum of its book so the standby
+// can tell it diverged rather than carry on from a wrong book.
+//
+// While following, the standby's connections answer queries only. Once the
+// primary goes quiet for longer than the failover timeout and can't be
+// reached again, the standby is promoted and takes order entry itself.
+
+// Primary pushes an empty batch this often when it has no new events, so the
+// standby can tell an idle primary from a lost one
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(500);
+// Standby counts the primary as lost after hearing nothing for this long
+pub const DEFAULT_FAILOVER_TIMEOUT: Duration = Duration::from_secs(3);
+// How often a caught up standby is sent the primary's checksum, and a
+// replica checks its own against the primary's
+pub const CHECKSUM_INTERVAL: Duration = Duration::from_secs(5);
+// Most events pushed in one message
+const REPLICATION_BATCH: usize = 1_000;
+
+// Primary's side of one standby's connection
+pub(crate) struct ReplicationStream {
+    book: BookHandle,
+    // First event the standby doesn't have yet
+    next_seq: u64,
+    published: watch::Receiver<u64>,
+    checksum_due: Instant,
+}
+
+impl ReplicationStream {
+    pub(crate) fn new(book: &BookHandle, from_seq: u64) -> ReplicationStream {
+        ReplicationStream {
+            book: book.clone(),
+            next_seq: from_seq,
+            published: book.published(),
+            checksum_due: Instant::now(),
+        }
+    }
+
+    // Next message for the standby, waiting for new events or until a
+    // heartbeat is due. Safe to drop part way, nothing is lost.
+    pub(crate) async fn next(&mut self) -> Result<Response> {
+        if *self.published.borrow_and_update() < self.next_seq {
+            // Caught up, so the checksum is as of the standby's last event
+            // unless another was published in the meantime
+            if Instant::now() >= self.checksum_due {
+                self.checksum_due = Instant::now() + CHECKSUM_INTERVAL;
+                let checksum = self.book.execute(|book| book.checksum()).await?;
+                if checksum.as_of_seq + 1 == self.next_seq {
+                    return Ok(Response::ReplicationChecksum(checksum));
+                }
+            }
+            match time::timeout(HEARTBEAT_INTERVAL, self.published.changed()).await {
+                Ok(changed) => changed.map_err(|_| anyhow!("Matching task has stopped"))?,
+                Err(_) => return Ok(Response::ReplicatedEvents(Vec::new())),
+            }
+        }
+        let from_seq = self.next_seq;
+        let response = self
+            .book
+            .execute(
+                move |book| match book.events_since(from_seq, REPLICATION_BATCH) {
+                    Ok(events) => Response::ReplicatedEvents(events),
+                    Err(_) => Response::ReplicationSnapshot(book.view_book_l3()),
+                },
+            )
+            .await?;
+        match &response {
+            Response::ReplicatedEvents(events) => {
+                if let Some(event) = events.last() {
+                    self.next_seq = event.seq + 1;
+                }
+            }
+            Response::ReplicationSnapshot(l3_book) => self.next_seq = l3_book.as_of_seq + 1,
+            _ => {}
+        }
+        Ok(response)
+    }
+}
+
+// Marks the book as a standby and applies the primary's event log to it
+// until the primary is lost, then promotes it. Fails, leaving the book a
+// standby, if the log can't be applied, e.g. a snapshot arrives for a book
+// that already has events, or the book diverged from the primary's. Either
+// way the standby must be rebuilt.
+pub async fn follow_primary(
+    primary_addr: &str,
+    book: &BookHandle,
+    failover_timeout: Duration,
+) -> Result<()> {
+    book.set_standby(true);
+    // Reconnects as long as the last connection heard from the primary
+    while stream_from_primary(primary_addr, book, failover_timeout).await? {}
+    book.set_standby(false);
+    Ok(())
+}
+
+// Follows the primary over one connection until it's dropped or goes quiet,
+// and returns whether anything was heard on it
+async fn stream_from_primary(
+    primary_addr: &str,
+    book: &BookHandle,
+    failover_timeout: Duration,
+) -> Result<bool> {
+    let Ok(Ok(socket)) = time::timeout(failover_timeout, TcpStream::connect(primary_addr)).await
+    else {
+        return Ok(false);
+    };
+    let mut socket = Framed::new(socket);
+    let from_seq = book.execute(|book| book.last_event_seq() + 1).await?;
+    if socket
+        .write_msg(&Request::Replicate(ReplicateArgs { from_seq }))
+        .await
+        .is_err()
+    {
+        return Ok(false);
+    }
+    let mut heard = false;
+    loop {
+        let response = match time::timeout(failover_timeout, socket.read_msg()).await {
+            Ok(Ok(response)) => response,
+            _ => return Ok(heard),
+        };
+        heard = true;
+        match response {
+            Response::ReplicateOk => {}
+            Response::ReplicatedEvents(events) => {
+                if events.is_empty() {
+                    continue;
+                }
+                book.execute(move |book| {
+                    events
+                        .into_iter()
+                        .try_for_each(|event| book.apply_event(event))
+                })
+                .await??;
+            }
+            Response::ReplicationSnapshot(l3_book) => {
+                book.execute(move |book| book.load_l3(&l3_book)).await??;
+            }
+            Response::ReplicationChecksum(checksum) => {
+                let own = book.execute(|book| book.checksum()).await?;
+                if own != checksum {
+                    return Err(anyhow!(
+                        "Diverged from the primary: {own:?} against its {checksum:?}"
+                    ));
+                }
+            }
+            response => return Err(anyhow!("Unexpected response {response:?}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::Authenticator,
+        book::{OrderBook, OrderType},
+        server::serve,
+    };
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    async fn spawn_primary(book: OrderBook) -> (String, BookHandle) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let book = BookHandle::spawn(book, 64);
+        let auth = Arc::new(Mutex::new(Authenticator::new(Default::default(), 0)));
+        tokio::spawn(serve(listener, book.clone(), auth));
+        (addr, book)
+    }
+
+    async fn wait_for_seq(book: &BookHandle, seq: u64) {
+        let mut published = book.published();
+        while *published.borrow_and_update() < seq {
+            published.changed().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_standby_catches_up_from_snapshot() {
+        // Primary rebuilt from a snapshot retains none of the events before
+        // it, so a standby starting from scratch needs the snapshot too
+        let mut source = OrderBook::new();
+        for price in [101, 102, 103] {
+            source
+                .place_order("alice", price, 5, OrderType::Ask)
+                .unwrap();
+        }
+        let book = OrderBook::from_l3(&source.view_book_l3()).unwrap();
+        let (primary_addr, primary) = spawn_primary(book).await;
+
+        let standby = BookHandle::spawn(OrderBook::new(), 64);
+        let follower = tokio::spawn({
+            let standby = standby.clone();
+            async move { follow_primary(&primary_addr, &standby, Duration::from_secs(1)).await }
+        });
+        wait_for_seq(&standby, 3).await;
+        assert!(standby.is_standby());
+
+        // Then follows the events published after it
+        primary
+            .execute(|book| book.place_order("bob", 100, 2, OrderType::Bid))
+            .await
+            .unwrap()
+            .unwrap();
+        wait_for_seq(&standby, 4).await;
+        let l3_book = |book: &mut OrderBook| serde_json::to_value(book.view_book_l3()).unwrap();
+        assert_eq!(
+            standby.execute(l3_book).await.unwrap(),
+            primary.execute(l3_book).await.unwrap()
+        );
+        follower.abort();
+    }
+
+    #[tokio::test]
+    async fn test_standby_promoted_once_primary_is_lost() {
+        // Primary that answers the standby once and then goes away
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let primary_addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = Framed::new(socket);
+            let _: Request = socket.read_msg().await.unwrap();
+            socket.write_msg(&Response::ReplicateOk).await.unwrap();
+        });
+
+        let standby = BookHandle::spawn(OrderBook::new(), 64);
+        follow_primary(&primary_addr, &standby, Duration::from_millis(200))
+            .await
+            .unwrap();
+        assert!(!standby.is_standby());
+    }
+
+    #[tokio::test]
+    async fn test_standby_stops_on_divergence() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let primary_addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = Framed::new(socket);
+            let _: Request = socket.read_msg().await.unwrap();
+            socket.write_msg(&Response::ReplicateOk).await.unwrap();
+            let mut checksum = OrderBook::new().checksum();
+            checksum.checksum ^= 1;
+            socket
+                .write_msg(&Response::ReplicationChecksum(checksum))
+                .await
+                .unwrap();
+            // Holds the connection so only the checksum ends it
+            std::future::pending::<()>().await;
+        });
+
+        let standby = BookHandle::spawn(OrderBook::new(), 64);
+        assert!(
+            follow_primary(&primary_addr, &standby, Duration::from_secs(5))
+                .await
+                .is_err()
+        );
+        assert!(standby.is_standby());
+    }
+}
@@ -1,23 +1,356 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::book::OrderType;
+use crate::{
+    auth::SignedRequest,
+    book::{CancelFilter, MarketProtection, OrderType},
+    clearing::AccountAction,
+    fees::FeeTier,
+    instrument::InstrumentStatus,
+    query::PageRequest,
+    risk::ParticipantRiskConfig,
+    seed::RestingOrder,
+    settlement::EndOfDayOptions,
+};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PlaceOrderArgs {
     pub order_type: OrderType,
     pub price: u32,
-    pub quantity: u32,
+    pub quantity: u64,
+    pub owner: String,
+    // Unix timestamp in milliseconds for good-till-date orders
+    pub expires_at: Option<u64>,
+    // Id the client picked for the order. Sending an order with an id the
+    // owner already used this session places nothing and answers with the
+    // first order's id, so a request lost to a dropped connection can be
+    // resent safely.
+    #[serde(default)]
+    pub client_order_id: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Trades at once at the best prices available, as far as the protection
+// allows, and is canceled rather than left resting for what it can't fill
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlaceMarketOrderArgs {
+    pub order_type: OrderType,
+    pub quantity: u64,
+    pub owner: String,
+    pub protection: MarketProtection,
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CancelOrderArgs {
     pub order_id: Uuid,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Cancels the order the owner placed this session under the client order id
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CancelClientOrderArgs {
+    pub owner: String,
+    pub client_order_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CancelAllArgs {
+    pub filter: CancelFilter,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ViewStatsArgs {
+    // Levels per side included in the imbalance
+    pub depth: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetEventsArgs {
+    pub from_seq: u64,
+    pub limit: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueryCandlesArgs {
+    pub interval_ms: u64,
+    pub page: PageRequest,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduleFeesArgs {
+    pub maker_fee_bps: i32,
+    pub taker_fee_bps: i32,
+    // Unix timestamp in milliseconds
+    pub effective_from: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetFeeTiersArgs {
+    pub tiers: Vec<FeeTier>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EndOfDayArgs {
+    pub options: EndOfDayOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ViewAccountArgs {
+    pub owner: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BustTradeArgs {
+    pub trade_seq: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountActionArgs {
+    pub action: AccountAction,
+    pub memo: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FundsArgs {
+    pub owner: String,
+    pub amount: u64,
+    pub memo: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetParticipantRiskArgs {
+    pub owner: String,
+    pub config: ParticipantRiskConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueryOrderArgs {
+    pub order_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ViewOpenOrdersArgs {
+    pub owner: String,
+    // Orders come in arrival order, the cursor being an order's arrival_seq
+    #[serde(default)]
+    pub page: PageRequest,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandshakeArgs {
+    // Client can read compressed messages, see wire.rs
+    pub compression: bool,
+    // Order entry messages go both ways in their fixed layout rather than
+    // through serde, see codec.rs
+    #[serde(default)]
+    pub fixed_layout: bool,
+    // Tagged requests for the book are answered once applied, without
+    // holding up the requests read after them. Acks come back tagged and
+    // rejects as Response::Rejected, in the order the requests were sent.
+    #[serde(default)]
+    pub async_acks: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SubscribeFillsArgs {
+    pub owner: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Channel {
+    L1,
+    L2,
+    Trades,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SubscribeArgs {
+    // Symbol the client knows the book by, echoed in its updates
+    pub symbol: Option<String>,
+    pub channel: Channel,
+    // Levels of each side an L2 update carries, all of them when None
+    pub depth: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnsubscribeArgs {
+    pub symbol: Option<String>,
+    pub channel: Channel,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaggedRequest {
+    // Chosen by the client, echoed in Response::Tagged
+    pub request_id: u64,
+    pub request: Request,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SymbolRequest {
+    pub symbol: String,
+    pub request: Request,
+}
+
+// Moves a symbol's book to another of the exchange's shards
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MigrateSymbolArgs {
+    pub symbol: String,
+    pub shard: usize,
+}
+
+// Option series listed on an exchange on one underlying, see
+// Exchange::option_chain
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OptionChainArgs {
+    pub underlying: String,
+    // Only the series expiring at this Unix timestamp in milliseconds
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+// Opens, halts or closes trading in a symbol, see Instrument::transition
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetInstrumentStatusArgs {
+    pub symbol: String,
+    pub status: InstrumentStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct ReplicateArgs {
+    // First event the standby doesn't have yet
+    pub from_seq: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Request {
     PlaceOrder(PlaceOrderArgs),
     CancelOrder(CancelOrderArgs),
+    CancelClientOrder(CancelClientOrderArgs),
     ViewL2Book,
-}
\ No newline at end of file
+    ViewL1Book,
+    // Every resting order in one snapshot
+    ViewL3Book,
+    ViewStats(ViewStatsArgs),
+    ResumeTrading,
+    GetTrades(PageRequest),
+    ScheduleFees(ScheduleFeesArgs),
+    ViewFeeSchedules,
+    ViewAccount(ViewAccountArgs),
+    BustTrade(BustTradeArgs),
+    AccountAction(AccountActionArgs),
+    // Cash into and out of the owner's account. A withdrawal can't touch the
+    // cash their resting bids reserve.
+    Deposit(FundsArgs),
+    Withdraw(FundsArgs),
+    // Owner's cash and how much of it is reserved
+    ViewBalance(ViewAccountArgs),
+    ViewAuditLog,
+    StartAuction,
+    Uncross,
+    SetParticipantRisk(SetParticipantRiskArgs),
+    GetEvents(GetEventsArgs),
+    QueryCandles(QueryCandlesArgs),
+    ViewOpenOrders(ViewOpenOrdersArgs),
+    QueryOrder(QueryOrderArgs),
+    CancelAll(CancelAllArgs),
+    QueryOrderAccount(QueryOrderArgs),
+    CheckConservation,
+    SetFeeTiers(SetFeeTiersArgs),
+    ViewFeeTier(ViewAccountArgs),
+    EndOfDay(EndOfDayArgs),
+    ViewSettlements,
+    // Nets the trades cleared since the last run per participant
+    NetTrades,
+    ViewNettingRuns,
+    // Latency of the book's operations since it started
+    ViewLatency,
+    // Checksum of the resting orders, to compare with a replica's
+    ViewChecksum,
+    // Last price and the high, low, volume and price change over the last
+    // 24 hours, see analytics.rs
+    ViewMarketStats,
+    PlaceMarketOrder(PlaceMarketOrderArgs),
+    // Owner's equity against the margin their position and open orders
+    // need, see risk.rs
+    ViewMargin(ViewAccountArgs),
+    // Applied in order as one command, so nothing else reaches the book
+    // part way through a batch
+    PlaceOrders(Vec<PlaceOrderArgs>),
+    CancelOrders(Vec<CancelOrderArgs>),
+    // Seeds the book with resting orders, all or none of them, see seed.rs
+    LoadOrders(Vec<RestingOrder>),
+    // Settles how the rest of the connection is framed. Answered by the
+    // connection rather than the book.
+    Handshake(HandshakeArgs),
+    // Has the connection pushed a Response::Fill for every fill of the
+    // owner's orders from now on, starting with those queued while the
+    // owner had no connection subscribed. Answered by the connection.
+    SubscribeFills(SubscribeFillsArgs),
+    // Has the connection pushed a Response::MarketData with the latest state
+    // of the channel whenever the book changes, replacing any subscription
+    // to the same symbol and channel, see subscription.rs. Answered by the
+    // connection, as are the two below.
+    Subscribe(SubscribeArgs),
+    Unsubscribe(UnsubscribeArgs),
+    ViewSubscriptions,
+    // Streams the book's event log to a standby from `from_seq` on, see
+    // replication.rs. Answered by the connection.
+    Replicate(ReplicateArgs),
+    // Wraps another request with the client's signature, see auth.rs
+    Signed(Box<SignedRequest>),
+    // Wraps another request, signed or not, with an id its response is
+    // tagged with. Lets a client have several requests in flight on one
+    // connection and tell their responses apart, from each other and from
+    // the fills and market data pushed in between. Only the outermost
+    // request can be tagged.
+    Tagged(Box<TaggedRequest>),
+    // Sends a request for a book to the book of one symbol on an exchange
+    // server, see exchange.rs. Every request for a book has to be sent this
+    // way there, signed outside the symbol if at all.
+    OnSymbol(Box<SymbolRequest>),
+    // Answered by an exchange server rather than one of its books, see
+    // Exchange::migrate_symbol, as are the two below
+    MigrateSymbol(MigrateSymbolArgs),
+    SetInstrumentStatus(SetInstrumentStatusArgs),
+    ViewOptionChain(OptionChainArgs),
+}
+
+impl Request {
+    // Only reads the book, so it's safe to repeat and to serve from a
+    // market data listener or replica
+    pub fn is_query(&self) -> bool {
+        if let Request::OnSymbol(symbol_request) = self {
+            return symbol_request.request.is_query();
+        }
+        matches!(
+            self,
+            Request::ViewL2Book
+                | Request::ViewL1Book
+                | Request::ViewL3Book
+                | Request::ViewStats(_)
+                | Request::GetTrades(_)
+                | Request::ViewFeeSchedules
+                | Request::ViewAccount(_)
+                | Request::ViewBalance(_)
+                | Request::ViewAuditLog
+                | Request::GetEvents(_)
+                | Request::QueryCandles(_)
+                | Request::ViewOpenOrders(_)
+                | Request::QueryOrder(_)
+                | Request::QueryOrderAccount(_)
+                | Request::CheckConservation
+                | Request::ViewFeeTier(_)
+                | Request::ViewSettlements
+                | Request::ViewNettingRuns
+                | Request::ViewLatency
+                | Request::ViewMarketStats
+                | Request::ViewMargin(_)
+                | Request::ViewChecksum
+                | Request::Subscribe(_)
+                | Request::Unsubscribe(_)
+                | Request::ViewSubscriptions
+                | Request::Replicate(_)
+                | Request::ViewOptionChain(_)
+        )
+    }
+}
@@ -1,13 +1,225 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::book::L2Book;
+use crate::{
+    accounting::OrderAccount,
+    analytics::{BookStats, MarketStats},
+    book::{
+        AuctionUncross, BookChecksum, ExecutionReport, L1Book, L2Book, L3Book, OpenOrder,
+        OrderStatus,
+    },
+    candles::Candle,
+    clearing::{AccountStatement, AuditRecord, Balance, FeeTierStatus},
+    error::OrderBookError,
+    exchange::ChainRow,
+    feed::SequencedEvent,
+    fees::FeeSchedule,
+    instrument::InstrumentStatus,
+    metrics::LatencySummary,
+    netting::NettingRun,
+    query::Page,
+    req::{Channel, HandshakeArgs, SubscribeArgs},
+    risk::MarginStatus,
+    settlement::SettlementReport,
+    tape::{Fill, Trade},
+};
+
+// Why a request was turned down, as a code to act on and a message to show
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reject {
+    pub code: OrderBookError,
+    pub message: String,
+}
+
+impl From<OrderBookError> for Reject {
+    fn from(code: OrderBookError) -> Reject {
+        Reject {
+            message: code.to_string(),
+            code,
+        }
+    }
+}
+
+// Reject of a tagged request on a connection with async acks
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AsyncReject {
+    pub request_id: u64,
+    pub reject: Reject,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum MarketData {
+    L1(L1Book),
+    L2(L2Book),
+    // Oldest first. A gap from the previous update's last sequence number
+    // means trades were skipped.
+    Trades(Vec<Trade>),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MarketDataUpdate {
+    pub symbol: Option<String>,
+    pub data: MarketData,
+    // Updates of the subscription were dropped before this one, as the
+    // connection fell behind, see BackpressurePolicy
+    #[serde(default)]
+    pub gap: bool,
+}
+
+impl MarketDataUpdate {
+    pub fn channel(&self) -> Channel {
+        match self.data {
+            MarketData::L1(_) => Channel::L1,
+            MarketData::L2(_) => Channel::L2,
+            MarketData::Trades(_) => Channel::Trades,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TaggedResponse {
+    pub request_id: u64,
+    pub response: Response,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Response {
     L2BookOk(L2Book),
-    CancelOk,
-    CancelErr,
-    PlaceOk(Uuid),
-    PlacErr,
-}
\ No newline at end of file
+    L1BookOk(L1Book),
+    L3BookOk(L3Book),
+    StatsOk(BookStats),
+    // Quantity that was still resting
+    CancelOk(u64),
+    CancelErr(Reject),
+    PlaceOk(ExecutionReport),
+    // Including orders that failed a pre-trade risk check
+    #[serde(alias = "PlacErr")]
+    PlaceErr(Reject),
+    ResumeOk,
+    TradesOk(Page<Trade>),
+    TradesErr,
+    ScheduleFeesOk(u32),
+    ScheduleFeesErr,
+    FeeSchedulesOk(Vec<FeeSchedule>),
+    AccountOk(AccountStatement),
+    BustOk,
+    BustErr,
+    AccountActionOk(u64),
+    AccountActionErr,
+    // Owner's balance, after the deposit or withdrawal if it was one
+    BalanceOk(Balance),
+    AuditLogOk(Vec<AuditRecord>),
+    StartAuctionOk,
+    StartAuctionErr(Reject),
+    UncrossOk(Option<AuctionUncross>),
+    UncrossErr(Reject),
+    SetParticipantRiskOk,
+    EventsOk(Vec<SequencedEvent>),
+    EventsErr,
+    // Request changes the book but was sent to a read-only replica or market
+    // data listener
+    ReadOnlyErr,
+    CandlesOk(Page<Candle>),
+    CandlesErr,
+    OpenOrdersOk(Page<OpenOrder>),
+    OpenOrdersErr,
+    OrderStatusOk(OrderStatus),
+    // Order id was never seen by the book
+    OrderStatusErr,
+    // Ids of the canceled orders
+    CancelAllOk(Vec<Uuid>),
+    OrderAccountOk(OrderAccount),
+    OrderAccountErr,
+    // Number of orders checked
+    ConservationOk(usize),
+    ConservationErr(String),
+    SetFeeTiersOk,
+    SetFeeTiersErr,
+    FeeTierOk(FeeTierStatus),
+    EndOfDayOk(SettlementReport),
+    EndOfDayErr,
+    SettlementsOk(Vec<SettlementReport>),
+    NettingOk(NettingRun),
+    NettingRunsOk(Vec<NettingRun>),
+    LatencyOk(Vec<LatencySummary>),
+    ChecksumOk(BookChecksum),
+    MarketStatsOk(MarketStats),
+    MarginOk(MarginStatus),
+    // Book doesn't margin orders
+    MarginErr,
+    // Request failed the signature, freshness or nonce check, or acts on
+    // orders of someone other than the client that signed it
+    AuthErr,
+    // Server hosts several tenants and the request wasn't signed by a client
+    // of any of them, so it has no book to go to
+    TenantErr,
+    // Request for a book named a symbol the exchange doesn't list, or an
+    // exchange server got it without a symbol, or a single book with one
+    SymbolErr,
+    // Shard the book moved from
+    MigrateSymbolOk(usize),
+    // Symbol isn't listed, the shard doesn't exist or already has it, or the
+    // server isn't an exchange
+    MigrateSymbolErr(String),
+    // Status the instrument was in
+    InstrumentStatusOk(InstrumentStatus),
+    // Symbol isn't listed, can't go to the status from the one it's in, or
+    // the server isn't an exchange
+    InstrumentStatusErr(String),
+    // By expiry then strike
+    OptionChainOk(Vec<ChainRow>),
+    // Server isn't an exchange
+    OptionChainErr,
+    // Response to each order of a batch, in the order they were sent
+    PlaceOrdersOk(Vec<Response>),
+    CancelOrdersOk(Vec<Response>),
+    // Batch had more than MAX_BATCH_ORDERS orders, so none were applied
+    BatchErr,
+    // Ids of the loaded orders, in the order they were sent
+    LoadOrdersOk(Vec<Uuid>),
+    LoadOrdersErr(String),
+    // What the server agreed to, in effect from the next message
+    HandshakeOk(HandshakeArgs),
+    SubscribeFillsOk,
+    // Fills can only be pushed over a server connection
+    SubscribeFillsErr,
+    // Pushed to a connection subscribed to the owner's fills, between the
+    // responses to its requests
+    Fill(Fill),
+    SubscribeOk,
+    // Connection is at its limit of subscriptions, or isn't a server
+    // connection
+    SubscribeErr,
+    // Whether the connection had the subscription
+    UnsubscribeOk(bool),
+    SubscriptionsOk(Vec<SubscribeArgs>),
+    // Pushed to a connection subscribed to the channel, between the
+    // responses to its requests
+    MarketData(MarketDataUpdate),
+    // Replication stream is on, with what the standby is missing to follow
+    ReplicateOk,
+    // Events can only be streamed over a server connection
+    ReplicateErr,
+    // Pushed to a standby as the primary publishes events. Sent empty as a
+    // heartbeat when the primary has been idle.
+    ReplicatedEvents(Vec<SequencedEvent>),
+    // Pushed instead of events the primary no longer retains. The standby
+    // rebuilds from it and follows the events after `as_of_seq`.
+    ReplicationSnapshot(L3Book),
+    // Pushed now and then once the standby is caught up, for it to check
+    // its book against as of the same event
+    ReplicationChecksum(BookChecksum),
+    // Response to a Request::Tagged, with its id
+    Tagged(Box<TaggedResponse>),
+    // Tagged request was tagged again inside, or inside its signature
+    TagErr,
+    // Pushed to a connection with async acks in place of the response to a
+    // tagged request the book turned down, between the acks of the requests
+    // sent before and after it
+    Rejected(AsyncReject),
+    // Matching queue was full, so the request was not applied. Safe to retry.
+    Overloaded,
+    // Connection went over its rate limit, so the request was not applied.
+    // Safe to retry once it slows down.
+    RateLimited,
+}
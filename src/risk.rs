@@ -0,0 +1,882 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    book::{OrderBook, OrderType},
+    ledger::Asset,
+};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct PriceBand {
+    // Maximum distance from the reference price, in basis points
+    pub max_deviation_bps: u32,
+    // Halt the book instead of only rejecting the offending order
+    pub halt_on_breach: bool,
+}
+
+impl PriceBand {
+    pub fn new(max_deviation_bps: u32, halt_on_breach: bool) -> PriceBand {
+        PriceBand {
+            max_deviation_bps,
+            halt_on_breach,
+        }
+    }
+
+    pub fn contains(&self, reference_price: u32, price: u32) -> bool {
+        within_bps(reference_price, price, self.max_deviation_bps)
+    }
+}
+
+// Instead of trading too far from the reference price, the book switches to
+// a short auction and uncrosses once it has run, see
+// OrderBook::end_volatility_auction
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct VolatilityInterruption {
+    // Distance from the reference price a trade may reach, in basis points
+    pub max_deviation_bps: u32,
+    // How long the auction collects orders before it uncrosses
+    pub auction_ms: u64,
+}
+
+impl VolatilityInterruption {
+    pub fn new(max_deviation_bps: u32, auction_ms: u64) -> VolatilityInterruption {
+        VolatilityInterruption {
+            max_deviation_bps,
+            auction_ms,
+        }
+    }
+
+    pub fn contains(&self, reference_price: u32, price: u32) -> bool {
+        within_bps(reference_price, price, self.max_deviation_bps)
+    }
+}
+
+fn within_bps(reference_price: u32, price: u32, max_deviation_bps: u32) -> bool {
+    let deviation = (price as u64).abs_diff(reference_price as u64);
+    deviation * 10_000 <= reference_price as u64 * max_deviation_bps as u64
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum RiskCheckKind {
+    PriceBand,
+    MaxNotional,
+    MaxOrderSize,
+    PriceCollar,
+    PositionLimit,
+    DuplicateOrder,
+    Funds,
+    Margin,
+    // Every check added outside this module
+    Custom,
+}
+
+// Why an order failed a pre-trade check
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum RiskRejection {
+    MaxOrderSize {
+        quantity: u64,
+        max_quantity: u64,
+    },
+    MaxNotional {
+        price: u32,
+        quantity: u64,
+        max_notional: u64,
+    },
+    // Limit price too far from the reference price
+    PriceCollar {
+        price: u32,
+        reference_price: u32,
+        max_deviation_bps: u32,
+    },
+    // Order would trade too far from the reference price
+    PriceBand {
+        price: u32,
+        reference_price: u32,
+    },
+    PositionLimit {
+        position: i64,
+        limit: u64,
+    },
+    DuplicateOrder,
+    // Bid costs more at its limit than the owner's cash not yet reserved
+    InsufficientFunds {
+        required: u64,
+        available: i64,
+    },
+    // Positions and open orders, this one included, would need more margin
+    // than the owner's equity at the mark price
+    InsufficientMargin {
+        required: u64,
+        equity: i64,
+    },
+    Custom(String),
+}
+
+impl fmt::Display for RiskRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RiskRejection::MaxOrderSize {
+                quantity,
+                max_quantity,
+            } => write!(
+                f,
+                "Quantity {quantity} exceeds the maximum of {max_quantity}"
+            ),
+            RiskRejection::MaxNotional {
+                price,
+                quantity,
+                max_notional,
+            } => write!(
+                f,
+                "Notional {} exceeds the maximum of {max_notional}",
+                *price as u128 * *quantity as u128
+            ),
+            RiskRejection::PriceCollar {
+                price,
+                reference_price,
+                max_deviation_bps,
+            } => write!(
+                f,
+                "Price {price} is more than {max_deviation_bps}bps away from {reference_price}"
+            ),
+            RiskRejection::PriceBand {
+                price,
+                reference_price,
+            } => write!(
+                f,
+                "Trades at {price}, outside the price band around {reference_price}"
+            ),
+            RiskRejection::PositionLimit { position, limit } => {
+                write!(
+                    f,
+                    "Position would reach {position}, beyond the limit of {limit}"
+                )
+            }
+            RiskRejection::DuplicateOrder => write!(f, "Order repeats the previous order"),
+            RiskRejection::InsufficientFunds {
+                required,
+                available,
+            } => write!(
+                f,
+                "Order needs {required} in cash but only {available} is available"
+            ),
+            RiskRejection::InsufficientMargin { required, equity } => write!(
+                f,
+                "Order needs {required} in margin but equity is only {equity}"
+            ),
+            RiskRejection::Custom(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl std::error::Error for RiskRejection {}
+
+// Order as seen by the pre-trade checks
+pub struct RiskOrder<'a> {
+    pub owner: &'a str,
+    pub order_type: OrderType,
+    pub price: u32,
+    pub quantity: u64,
+    // Unix timestamp in milliseconds
+    pub timestamp: u64,
+}
+
+// Pre-trade check run on every order before it is matched. Checks see the
+// book as it was before the order, and may keep state across orders.
+pub trait RiskCheck: Send + Sync {
+    fn kind(&self) -> RiskCheckKind;
+
+    fn check(&mut self, order: &RiskOrder, book: &OrderBook) -> Result<(), RiskRejection>;
+
+    // Called once every check has let the order through, so state is only
+    // kept for orders that reach the book
+    fn on_accepted(&mut self, _order: &RiskOrder) {}
+}
+
+pub struct MaxOrderSizeCheck {
+    pub max_quantity: u64,
+}
+
+impl RiskCheck for MaxOrderSizeCheck {
+    fn kind(&self) -> RiskCheckKind {
+        RiskCheckKind::MaxOrderSize
+    }
+
+    fn check(&mut self, order: &RiskOrder, _: &OrderBook) -> Result<(), RiskRejection> {
+        if order.quantity > self.max_quantity {
+            return Err(RiskRejection::MaxOrderSize {
+                quantity: order.quantity,
+                max_quantity: self.max_quantity,
+            });
+        }
+        Ok(())
+    }
+}
+
+pub struct MaxNotionalCheck {
+    pub max_notional: u64,
+}
+
+impl RiskCheck for MaxNotionalCheck {
+    fn kind(&self) -> RiskCheckKind {
+        RiskCheckKind::MaxNotional
+    }
+
+    fn check(&mut self, order: &RiskOrder, _: &OrderBook) -> Result<(), RiskRejection> {
+        if order.price as u128 * order.quantity as u128 > self.max_notional as u128 {
+            return Err(RiskRejection::MaxNotional {
+                price: order.price,
+                quantity: order.quantity,
+                max_notional: self.max_notional,
+            });
+        }
+        Ok(())
+    }
+}
+
+// Rejects limit prices too far from the reference price. Unlike the price
+// band this looks at the order's own price, whether or not it would trade.
+pub struct PriceCollarCheck {
+    pub max_deviation_bps: u32,
+}
+
+impl RiskCheck for PriceCollarCheck {
+    fn kind(&self) -> RiskCheckKind {
+        RiskCheckKind::PriceCollar
+    }
+
+    fn check(&mut self, order: &RiskOrder, book: &OrderBook) -> Result<(), RiskRejection> {
+        match book.band_reference_price() {
+            Some(reference_price)
+                if !within_bps(reference_price, order.price, self.max_deviation_bps) =>
+            {
+                Err(RiskRejection::PriceCollar {
+                    price: order.price,
+                    reference_price,
+                    max_deviation_bps: self.max_deviation_bps,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Quantity a participant has resting on each side of the book
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OpenExposure {
+    pub bid_quantity: u64,
+    pub ask_quantity: u64,
+}
+
+// Open exposure per participant, kept up to date as orders rest, fill and
+// leave the book
+#[derive(Default)]
+pub struct ExposureTracker {
+    exposures: HashMap<String, OpenExposure>,
+    // Price * quantity of each participant's resting bids, i.e. the cash
+    // they'd pay if every bid filled at its limit
+    reserved_funds: HashMap<String, u128>,
+}
+
+impl ExposureTracker {
+    pub fn add(&mut self, owner: &str, side: OrderType, price: u32, quantity: u64) {
+        let exposure = self.exposures.entry(owner.to_string()).or_default();
+        match side {
+            OrderType::Bid => {
+                exposure.bid_quantity += quantity;
+                *self.reserved_funds.entry(owner.to_string()).or_default() +=
+                    price as u128 * quantity as u128;
+            }
+            OrderType::Ask => exposure.ask_quantity += quantity,
+        }
+    }
+
+    pub fn remove(&mut self, owner: &str, side: OrderType, price: u32, quantity: u64) {
+        let Some(exposure) = self.exposures.get_mut(owner) else {
+            return;
+        };
+        match side {
+            OrderType::Bid => {
+                exposure.bid_quantity -= quantity;
+                if let Some(reserved) = self.reserved_funds.get_mut(owner) {
+                    *reserved -= price as u128 * quantity as u128;
+                    if *reserved == 0 {
+                        self.reserved_funds.remove(owner);
+                    }
+                }
+            }
+            OrderType::Ask => exposure.ask_quantity -= quantity,
+        }
+        if *exposure == OpenExposure::default() {
+            self.exposures.remove(owner);
+        }
+    }
+
+    pub fn exposure(&self, owner: &str) -> OpenExposure {
+        self.exposures.get(owner).copied().unwrap_or_default()
+    }
+
+    pub fn reserved_funds(&self, owner: &str) -> u128 {
+        self.reserved_funds.get(owner).copied().unwrap_or(0)
+    }
+}
+
+// Rejects orders that would take a participant's potential position, i.e.
+// the current position with every open order on that side filled, beyond the
+// limit in either direction
+pub struct PositionLimitCheck {
+    pub limit: u64,
+}
+
+impl RiskCheck for PositionLimitCheck {
+    fn kind(&self) -> RiskCheckKind {
+        RiskCheckKind::PositionLimit
+    }
+
+    fn check(&mut self, order: &RiskOrder, book: &OrderBook) -> Result<(), RiskRejection> {
+        let position = book
+            .clearing_house()
+            .ledger()
+            .balance(order.owner, Asset::Position) as i128;
+        let exposure = book.open_exposure(order.owner);
+        let position = match order.order_type {
+            OrderType::Bid => position + exposure.bid_quantity as i128 + order.quantity as i128,
+            OrderType::Ask => position - exposure.ask_quantity as i128 - order.quantity as i128,
+        };
+        if position.unsigned_abs() > self.limit as u128 {
+            return Err(RiskRejection::PositionLimit {
+                position: position.clamp(i64::MIN as i128, i64::MAX as i128) as i64,
+                limit: self.limit,
+            });
+        }
+        Ok(())
+    }
+}
+
+// Rejects an order identical to the owner's previous order within the
+// window, e.g. a client resending after a timeout
+pub struct DuplicateOrderCheck {
+    pub window_ms: u64,
+    // Owner -> (side, price, quantity, timestamp) of their last order
+    last_orders: HashMap<String, (OrderType, u32, u64, u64)>,
+}
+
+impl DuplicateOrderCheck {
+    pub fn new(window_ms: u64) -> DuplicateOrderCheck {
+        DuplicateOrderCheck {
+            window_ms,
+            last_orders: HashMap::new(),
+        }
+    }
+}
+
+impl RiskCheck for DuplicateOrderCheck {
+    fn kind(&self) -> RiskCheckKind {
+        RiskCheckKind::DuplicateOrder
+    }
+
+    fn check(&mut self, order: &RiskOrder, _: &OrderBook) -> Result<(), RiskRejection> {
+        if let Some(&(order_type, price, quantity, timestamp)) = self.last_orders.get(order.owner) {
+            if (order_type, price, quantity) == (order.order_type, order.price, order.quantity)
+                && order.timestamp.saturating_sub(timestamp) < self.window_ms
+            {
+                return Err(RiskRejection::DuplicateOrder);
+            }
+        }
+        Ok(())
+    }
+
+    fn on_accepted(&mut self, order: &RiskOrder) {
+        self.last_orders.insert(
+            order.owner.to_string(),
+            (
+                order.order_type,
+                order.price,
+                order.quantity,
+                order.timestamp,
+            ),
+        );
+    }
+}
+
+// Rejects bids the owner's cash can't pay for at their limit, counting the
+// cash their resting bids already reserve. Asks are left to the position
+// limit.
+pub struct FundsCheck;
+
+impl RiskCheck for FundsCheck {
+    fn kind(&self) -> RiskCheckKind {
+        RiskCheckKind::Funds
+    }
+
+    fn check(&mut self, order: &RiskOrder, book: &OrderBook) -> Result<(), RiskRejection> {
+        if order.order_type == OrderType::Ask {
+            return Ok(());
+        }
+        let required = order.price as u128 * order.quantity as u128;
+        let available = book.balance(order.owner).available;
+        if required as i128 > available as i128 {
+            return Err(RiskRejection::InsufficientFunds {
+                required: u64::try_from(required).unwrap_or(u64::MAX),
+                available,
+            });
+        }
+        Ok(())
+    }
+}
+
+// Initial margin participants have to hold against what they could end up
+// holding, i.e. their position with every open order on one side filled,
+// valued at the mark price
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct MarginConfig {
+    // Share of the marked value of the larger of the potential long and
+    // short positions, in basis points
+    pub initial_margin_bps: u32,
+}
+
+impl MarginConfig {
+    pub fn new(initial_margin_bps: u32) -> MarginConfig {
+        MarginConfig { initial_margin_bps }
+    }
+
+    pub fn required_margin(&self, position: i64, exposure: OpenExposure, mark_price: u32) -> u128 {
+        let long = position as i128 + exposure.bid_quantity as i128;
+        let short = exposure.ask_quantity as i128 - position as i128;
+        let potential = long.max(short).max(0) as u128;
+        potential * mark_price as u128 * self.initial_margin_bps as u128 / 10_000
+    }
+}
+
+// Equity of a participant against the margin their position and open
+// orders need
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct MarginStatus {
+    // Price the position is valued at: the external mark, the reference
+    // price without one, and None before the book has either
+    pub mark_price: Option<u32>,
+    // Cash plus the position at the mark price
+    pub equity: i64,
+    pub required_margin: u64,
+    // Equity left over the required margin, negative when short of it
+    pub excess: i64,
+}
+
+// Margin status of the owner were their open orders as in `exposure`,
+// valuing positions at `fallback_price` while the book has no mark
+pub fn margin_status(
+    config: &MarginConfig,
+    book: &OrderBook,
+    owner: &str,
+    exposure: OpenExposure,
+    fallback_price: Option<u32>,
+) -> MarginStatus {
+    let ledger = book.clearing_house().ledger();
+    let position = ledger.balance(owner, Asset::Position);
+    let cash = ledger.balance(owner, Asset::Cash);
+    let mark_price = book
+        .mark_price()
+        .or(book.reference_price())
+        .or(fallback_price);
+    let price = mark_price.unwrap_or(0);
+    let equity = cash as i128 + position as i128 * price as i128;
+    let required = config.required_margin(position, exposure, price);
+    let clamp = |amount: i128| amount.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+    MarginStatus {
+        mark_price,
+        equity: clamp(equity),
+        required_margin: u64::try_from(required).unwrap_or(u64::MAX),
+        excess: clamp(equity - required.min(i128::MAX as u128) as i128),
+    }
+}
+
+// Rejects orders that would leave the owner's equity short of the margin
+// their position and open orders need. Orders are margined at the mark
+// price, or at the order's own limit while the book has no price at all.
+pub struct MarginCheck {
+    pub config: MarginConfig,
+}
+
+impl RiskCheck for MarginCheck {
+    fn kind(&self) -> RiskCheckKind {
+        RiskCheckKind::Margin
+    }
+
+    fn check(&mut self, order: &RiskOrder, book: &OrderBook) -> Result<(), RiskRejection> {
+        let exposure = book.open_exposure(order.owner);
+        let before = margin_status(&self.config, book, order.owner, exposure, Some(order.price));
+        let mut exposure = exposure;
+        match order.order_type {
+            OrderType::Bid => exposure.bid_quantity += order.quantity,
+            OrderType::Ask => exposure.ask_quantity += order.quantity,
+        }
+        let status = margin_status(&self.config, book, order.owner, exposure, Some(order.price));
+        // Orders that don't add to the margin needed, e.g. reducing a
+        // position, are let through even when the owner is already short
+        if status.excess < 0 && status.required_margin > before.required_margin {
+            return Err(RiskRejection::InsufficientMargin {
+                required: status.required_margin,
+                equity: status.equity,
+            });
+        }
+        Ok(())
+    }
+}
+
+// Checks configured by `config`, in the order they run
+pub fn build_checks(config: &RiskConfig) -> Vec<Box<dyn RiskCheck>> {
+    let mut checks: Vec<Box<dyn RiskCheck>> = Vec::new();
+    if let Some(max_quantity) = config.max_order_size {
+        checks.push(Box::new(MaxOrderSizeCheck { max_quantity }));
+    }
+    if let Some(max_notional) = config.max_notional {
+        checks.push(Box::new(MaxNotionalCheck { max_notional }));
+    }
+    if let Some(max_deviation_bps) = config.price_collar_bps {
+        checks.push(Box::new(PriceCollarCheck { max_deviation_bps }));
+    }
+    if let Some(limit) = config.position_limit {
+        checks.push(Box::new(PositionLimitCheck { limit }));
+    }
+    if let Some(window_ms) = config.duplicate_window_ms {
+        checks.push(Box::new(DuplicateOrderCheck::new(window_ms)));
+    }
+    if config.require_funds {
+        checks.push(Box::new(FundsCheck));
+    }
+    if let Some(config) = config.margin {
+        checks.push(Box::new(MarginCheck { config }));
+    }
+    checks
+}
+
+// Runs the checks in order, stopping at the first rejection. Failures the
+// owner may bypass are returned with their reason instead.
+pub fn run_checks(
+    checks: &mut [Box<dyn RiskCheck>],
+    order: &RiskOrder,
+    book: &OrderBook,
+) -> Result<Vec<(RiskCheckKind, RiskRejection)>, RiskRejection> {
+    let mut bypassed = Vec::new();
+    for check in checks.iter_mut() {
+        if let Err(rejection) = check.check(order, book) {
+            if !book.risk_config().bypasses(order.owner, check.kind()) {
+                return Err(rejection);
+            }
+            bypassed.push((check.kind(), rejection));
+        }
+    }
+    for check in checks {
+        check.on_accepted(order);
+    }
+    Ok(bypassed)
+}
+
+// What happens when an order would trade against a resting order of the
+// same owner. Trades with other owners before it still execute.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfMatchPrevention {
+    // Resting order is canceled and the incoming one carries on matching
+    CancelResting,
+    // Rest of the incoming order is canceled
+    CancelAggressing,
+    CancelBoth,
+    // Both are reduced by the smaller of the two quantities, canceling the
+    // smaller one; what is left of the incoming order carries on matching
+    DecrementAndCancel,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BandReference {
+    // Last trade, or the previous close until the session trades
+    #[default]
+    LastTrade,
+    // External mark price from the instrument's price feed, the last trade
+    // until one arrives
+    MarkPrice,
+}
+
+// Relaxations granted to a single participant, e.g. a designated market maker
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ParticipantRiskConfig {
+    pub bypass: HashSet<RiskCheckKind>,
+    // Overrides the book's self-match prevention for this participant
+    pub self_match_prevention: Option<SelfMatchPrevention>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RiskConfig {
+    pub price_band: Option<PriceBand>,
+    // Price the band, volatility interruption and collar are around
+    pub band_reference: BandReference,
+    // Band inside the price band where trading pauses for an auction
+    // rather than being rejected
+    pub volatility_interruption: Option<VolatilityInterruption>,
+    // Maximum price * quantity of a single order
+    pub max_notional: Option<u64>,
+    pub max_order_size: Option<u64>,
+    // Maximum distance of a limit price from the reference price
+    pub price_collar_bps: Option<u32>,
+    // Maximum absolute position a participant may reach on this book's
+    // symbol, counting their open orders as filled
+    pub position_limit: Option<u64>,
+    // Identical orders from one owner within this window are rejected
+    pub duplicate_window_ms: Option<u64>,
+    // Bids have to be paid for out of the owner's deposited cash, which
+    // they reserve while resting
+    pub require_funds: bool,
+    // Orders have to be covered by the owner's equity, see MarginCheck
+    pub margin: Option<MarginConfig>,
+    // Self-match prevention of participants without their own. Orders of
+    // one owner trade with each other when neither is set.
+    pub self_match_prevention: Option<SelfMatchPrevention>,
+    pub participants: HashMap<String, ParticipantRiskConfig>,
+}
+
+impl RiskConfig {
+    pub fn self_match_prevention(&self, owner: &str) -> Option<SelfMatchPrevention> {
+        self.participants
+            .get(owner)
+            .and_then(|participant| participant.self_match_prevention)
+            .or(self.self_match_prevention)
+    }
+
+    pub fn bypasses(&self, owner: &str, check: RiskCheckKind) -> bool {
+        self.participants
+            .get(owner)
+            .is_some_and(|participant| participant.bypass.contains(&check))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clearing::AccountAction;
+    use crate::error::OrderBookError;
+
+    #[test]
+    fn test_price_band_contains() {
+        // 5% band around 100
+        let band = PriceBand::new(500, false);
+        assert!(band.contains(100, 100));
+        assert!(band.contains(100, 105));
+        assert!(band.contains(100, 95));
+        assert!(!band.contains(100, 106));
+        assert!(!band.contains(100, 94));
+    }
+
+    #[test]
+    fn test_participant_bypass() {
+        let mut risk_config = RiskConfig::default();
+        risk_config.participants.insert(
+            "market_maker".to_string(),
+            ParticipantRiskConfig {
+                bypass: HashSet::from([RiskCheckKind::MaxNotional]),
+                ..Default::default()
+            },
+        );
+
+        assert!(risk_config.bypasses("market_maker", RiskCheckKind::MaxNotional));
+        assert!(!risk_config.bypasses("market_maker", RiskCheckKind::PriceBand));
+        assert!(!risk_config.bypasses("alice", RiskCheckKind::MaxNotional));
+    }
+
+    fn book_with(config: RiskConfig) -> OrderBook {
+        OrderBook::with_risk_config(config)
+    }
+
+    fn rejection(result: Result<uuid::Uuid, OrderBookError>) -> RiskRejection {
+        match result {
+            Err(OrderBookError::RiskRejected(rejection)) => rejection,
+            result => panic!("Expected a risk rejection, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_max_order_size_and_collar() {
+        let mut book = book_with(RiskConfig {
+            max_order_size: Some(100),
+            price_collar_bps: Some(1_000),
+            ..Default::default()
+        });
+        assert_eq!(
+            rejection(book.place_order("alice", 100, 101, OrderType::Bid)),
+            RiskRejection::MaxOrderSize {
+                quantity: 101,
+                max_quantity: 100,
+            }
+        );
+
+        // No reference price yet, so any price passes the collar
+        book.place_order("alice", 200, 10, OrderType::Ask).unwrap();
+        book.set_reference_price(100);
+        assert_eq!(
+            rejection(book.place_order("alice", 111, 10, OrderType::Ask)),
+            RiskRejection::PriceCollar {
+                price: 111,
+                reference_price: 100,
+                max_deviation_bps: 1_000,
+            }
+        );
+        book.place_order("alice", 110, 10, OrderType::Ask).unwrap();
+    }
+
+    #[test]
+    fn test_position_limit() {
+        let mut book = book_with(RiskConfig {
+            position_limit: Some(10),
+            ..Default::default()
+        });
+        book.place_order("alice", 100, 8, OrderType::Ask).unwrap();
+        book.place_order("bob", 100, 8, OrderType::Bid).unwrap();
+
+        // Bob is long 8, so only 2 more can be bought
+        assert_eq!(
+            rejection(book.place_order("bob", 100, 3, OrderType::Bid)),
+            RiskRejection::PositionLimit {
+                position: 11,
+                limit: 10,
+            }
+        );
+        book.place_order("bob", 100, 18, OrderType::Ask).unwrap();
+    }
+
+    #[test]
+    fn test_position_limit_counts_open_orders() {
+        let mut book = book_with(RiskConfig {
+            position_limit: Some(10),
+            ..Default::default()
+        });
+        let first_id = book.place_order("bob", 99, 6, OrderType::Bid).unwrap();
+        assert!(book.place_order("bob", 98, 5, OrderType::Bid).is_err());
+        // Asks don't add to the long exposure
+        book.place_order("bob", 105, 10, OrderType::Ask).unwrap();
+
+        // A partial fill moves exposure into the position
+        book.place_order("alice", 99, 4, OrderType::Ask).unwrap();
+        assert_eq!(
+            book.open_exposure("bob"),
+            OpenExposure {
+                bid_quantity: 2,
+                ask_quantity: 10,
+            }
+        );
+        assert!(book.place_order("bob", 98, 5, OrderType::Bid).is_err());
+
+        book.cancel_order(first_id).unwrap();
+        book.place_order("bob", 98, 6, OrderType::Bid).unwrap();
+    }
+
+    #[test]
+    fn test_exposure_tracker() {
+        let mut tracker = ExposureTracker::default();
+        tracker.add("alice", OrderType::Bid, 100, 5);
+        tracker.add("alice", OrderType::Bid, 99, 2);
+        tracker.add("alice", OrderType::Ask, 101, 3);
+        assert_eq!(tracker.reserved_funds("alice"), 698);
+        tracker.remove("alice", OrderType::Bid, 100, 5);
+        assert_eq!(tracker.reserved_funds("alice"), 198);
+        tracker.remove("alice", OrderType::Bid, 99, 2);
+        assert_eq!(tracker.exposure("alice").ask_quantity, 3);
+        tracker.remove("alice", OrderType::Ask, 101, 3);
+        assert!(tracker.exposures.is_empty());
+        assert!(tracker.reserved_funds.is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_orders() {
+        let mut check = DuplicateOrderCheck::new(1_000);
+        let book = OrderBook::new();
+        let order = |price, timestamp| RiskOrder {
+            owner: "alice",
+            order_type: OrderType::Bid,
+            price,
+            quantity: 5,
+            timestamp,
+        };
+        check.check(&order(100, 0), &book).unwrap();
+        check.on_accepted(&order(100, 0));
+        assert_eq!(
+            check.check(&order(100, 500), &book),
+            Err(RiskRejection::DuplicateOrder)
+        );
+        check.check(&order(101, 600), &book).unwrap();
+        check.on_accepted(&order(101, 600));
+        check.check(&order(101, 1_600), &book).unwrap();
+    }
+
+    #[test]
+    fn test_rejected_orders_are_not_duplicates() {
+        let mut book = book_with(RiskConfig {
+            duplicate_window_ms: Some(60_000),
+            require_funds: true,
+            ..Default::default()
+        });
+
+        // The funds check turns the order away after the duplicate check
+        // passed it, so resending it after a deposit isn't a duplicate
+        assert!(matches!(
+            rejection(book.place_order("alice", 100, 5, OrderType::Bid)),
+            RiskRejection::InsufficientFunds { .. }
+        ));
+        let deposit = AccountAction::Deposit {
+            owner: "alice".to_string(),
+            amount: 1_000,
+        };
+        book.apply_account_action(deposit, "").unwrap();
+        book.place_order("alice", 100, 5, OrderType::Bid).unwrap();
+        assert_eq!(
+            rejection(book.place_order("alice", 100, 5, OrderType::Bid)),
+            RiskRejection::DuplicateOrder
+        );
+    }
+
+    #[test]
+    fn test_custom_check_can_be_bypassed() {
+        struct NoAsks;
+
+        impl RiskCheck for NoAsks {
+            fn kind(&self) -> RiskCheckKind {
+                RiskCheckKind::Custom
+            }
+
+            fn check(&mut self, order: &RiskOrder, _: &OrderBook) -> Result<(), RiskRejection> {
+                match order.order_type {
+                    OrderType::Ask => Err(RiskRejection::Custom("No asks".to_string())),
+                    OrderType::Bid => Ok(()),
+                }
+            }
+        }
+
+        let mut book = OrderBook::new();
+        book.add_risk_check(Box::new(NoAsks));
+        assert_eq!(
+            rejection(book.place_order("alice", 100, 1, OrderType::Ask)),
+            RiskRejection::Custom("No asks".to_string())
+        );
+        book.set_participant_risk(
+            "market_maker",
+            ParticipantRiskConfig {
+                bypass: HashSet::from([RiskCheckKind::Custom]),
+                ..Default::default()
+            },
+        );
+        book.place_order("market_maker", 100, 1, OrderType::Ask)
+            .unwrap();
+        assert_eq!(book.clearing_house().audit_log()[0].memo, "No asks");
+    }
+
+    #[test]
+    fn test_price_band_large_prices() {
+        let band = PriceBand::new(10_000, true);
+        assert!(band.contains(u32::MAX / 2, u32::MAX - 1));
+        assert!(!band.contains(1, u32::MAX));
+    }
+}
@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+// Decides which matching shard owns a symbol. Implementations must be
+// deterministic so every process agrees on the owner of a symbol.
+pub trait Router: Send + Sync {
+    fn num_shards(&self) -> usize;
+
+    fn route(&self, symbol: &str) -> usize;
+}
+
+// FNV-1a, used instead of the std hasher whose output is not guaranteed to be
+// stable across Rust releases
+fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Spreads symbols evenly, but changing the shard count moves most of them
+pub struct HashRouter {
+    num_shards: usize,
+}
+
+impl HashRouter {
+    pub fn new(num_shards: usize) -> HashRouter {
+        assert!(num_shards > 0, "Router needs at least one shard");
+        HashRouter { num_shards }
+    }
+}
+
+impl Router for HashRouter {
+    fn num_shards(&self) -> usize {
+        self.num_shards
+    }
+
+    fn route(&self, symbol: &str) -> usize {
+        (stable_hash(symbol.as_bytes()) % self.num_shards as u64) as usize
+    }
+}
+
+// Pins listed symbols to a shard, e.g. to isolate a hot symbol, and routes
+// every other symbol with the fallback router
+pub struct StaticRouter {
+    routes: HashMap<String, usize>,
+    fallback: Box<dyn Router>,
+}
+
+impl StaticRouter {
+    pub fn new(routes: HashMap<String, usize>, fallback: Box<dyn Router>) -> StaticRouter {
+        let num_shards = fallback.num_shards();
+        assert!(
+            routes.values().all(|&shard| shard < num_shards),
+            "Static route points to a missing shard"
+        );
+        StaticRouter { routes, fallback }
+    }
+}
+
+impl Router for StaticRouter {
+    fn num_shards(&self) -> usize {
+        self.fallback.num_shards()
+    }
+
+    fn route(&self, symbol: &str) -> usize {
+        match self.routes.get(symbol) {
+            Some(&shard) => shard,
+            None => self.fallback.route(symbol),
+        }
+    }
+}
+
+// Hash ring with virtual nodes. Adding a shard only moves the symbols that
+// land on its slice of the ring.
+pub struct ConsistentHashRouter {
+    num_shards: usize,
+    ring: BTreeMap<u64, usize>,
+}
+
+impl ConsistentHashRouter {
+    pub fn new(num_shards: usize, replicas: usize) -> ConsistentHashRouter {
+        assert!(num_shards > 0, "Router needs at least one shard");
+        assert!(replicas > 0, "Router needs at least one replica per shard");
+        let mut ring = BTreeMap::new();
+        for shard in 0..num_shards {
+            for replica in 0..replicas {
+                ring.insert(stable_hash(format!("{shard}-{replica}").as_bytes()), shard);
+            }
+        }
+        ConsistentHashRouter { num_shards, ring }
+    }
+}
+
+impl Router for ConsistentHashRouter {
+    fn num_shards(&self) -> usize {
+        self.num_shards
+    }
+
+    fn route(&self, symbol: &str) -> usize {
+        let hash = stable_hash(symbol.as_bytes());
+        // Walk clockwise to the first virtual node, wrapping around the ring
+        let (_, &shard) = self
+            .ring
+            .range(hash..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .unwrap();
+        shard
+    }
+}
+
+// Serializable description of a router, so the routing strategy can be
+// changed by deployment config instead of code
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RouterConfig {
+    Hash {
+        num_shards: usize,
+    },
+    Static {
+        routes: HashMap<String, usize>,
+        fallback: Box<RouterConfig>,
+    },
+    ConsistentHash {
+        num_shards: usize,
+        replicas: usize,
+    },
+}
+
+impl RouterConfig {
+    pub fn build(&self) -> Box<dyn Router> {
+        match self {
+            RouterConfig::Hash { num_shards } => Box::new(HashRouter::new(*num_shards)),
+            RouterConfig::Static { routes, fallback } => {
+                Box::new(StaticRouter::new(routes.clone(), fallback.build()))
+            }
+            RouterConfig::ConsistentHash {
+                num_shards,
+                replicas,
+            } => Box::new(ConsistentHashRouter::new(*num_shards, *replicas)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbols() -> Vec<String> {
+        (0..1_000).map(|i| format!("SYM{i}")).collect()
+    }
+
+    #[test]
+    fn test_hash_router_is_deterministic_and_in_range() {
+        let router = HashRouter::new(4);
+        for symbol in symbols() {
+            let shard = router.route(&symbol);
+            assert!(shard < 4);
+            assert_eq!(shard, router.route(&symbol));
+        }
+    }
+
+    #[test]
+    fn test_static_router_overrides_fallback() {
+        let routes = HashMap::from([("HOT".to_string(), 3)]);
+        let router = StaticRouter::new(routes, Box::new(HashRouter::new(4)));
+        assert_eq!(router.route("HOT"), 3);
+        assert_eq!(router.route("COLD"), HashRouter::new(4).route("COLD"));
+    }
+
+    #[test]
+    fn test_consistent_hash_moves_few_symbols() {
+        let before = ConsistentHashRouter::new(4, 64);
+        let after = ConsistentHashRouter::new(5, 64);
+
+        let moved = symbols()
+            .iter()
+            .filter(|symbol| before.route(symbol) != after.route(symbol))
+            .count();
+        // Roughly a fifth of the symbols move to the new shard
+        assert!(moved < 400, "{moved} symbols moved");
+        for symbol in symbols() {
+            let shard = after.route(&symbol);
+            assert!(shard == before.route(&symbol) || shard == 4);
+        }
+    }
+
+    #[test]
+    fn test_router_config_build() {
+        let config: RouterConfig = serde_json::from_str(
+            r#"{"Static": {"routes": {"HOT": 1}, "fallback": {"Hash": {"num_shards": 2}}}}"#,
+        )
+        .unwrap();
+        let router = config.build();
+        assert_eq!(router.num_shards(), 2);
+        assert_eq!(router.route("HOT"), 1);
+    }
+}
@@ -0,0 +1,358 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::book::{OrderBook, OrderStatus, OrderType};
+
+// Plain text description of a matching scenario, one step per line:
+//
+//   # comment
+//   place <name> <owner> bid|ask <quantity>@<price>
+//   place-rejected <owner> bid|ask <quantity>@<price>
+//   cancel <name>
+//   start-auction
+//   uncross
+//   expect-fill <maker> <taker> <quantity>@<price>
+//   expect-no-fill
+//   expect-book bid|ask <price> <quantity>
+//   expect-status <name> resting <quantity>|filled|partially-canceled|canceled
+//
+// Orders are referred to by the names given when placing them. Fills are
+// checked in trade order, each expect-fill consuming the next trade.
+pub struct Scenario {
+    // (line number, step)
+    steps: Vec<(usize, Step)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Place {
+        name: String,
+        owner: String,
+        side: OrderType,
+        price: u32,
+        quantity: u64,
+    },
+    PlaceRejected {
+        owner: String,
+        side: OrderType,
+        price: u32,
+        quantity: u64,
+    },
+    Cancel {
+        name: String,
+    },
+    StartAuction,
+    Uncross,
+    ExpectFill {
+        maker: String,
+        taker: String,
+        price: u32,
+        quantity: u64,
+    },
+    ExpectNoFill,
+    ExpectBook {
+        side: OrderType,
+        price: u32,
+        quantity: u64,
+    },
+    ExpectStatus {
+        name: String,
+        status: ExpectedStatus,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ExpectedStatus {
+    Resting(u64),
+    Filled,
+    PartiallyCanceled,
+    Canceled,
+}
+
+impl Scenario {
+    pub fn parse(text: &str) -> Result<Scenario> {
+        let mut steps = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let step = parse_step(line).with_context(|| format!("line {}: {line}", idx + 1))?;
+            steps.push((idx + 1, step));
+        }
+        Ok(Scenario { steps })
+    }
+
+    // Runs the scenario against a new book
+    pub fn run(&self) -> Result<OrderBook> {
+        let mut book = OrderBook::new();
+        self.run_on(&mut book)?;
+        Ok(book)
+    }
+
+    pub fn run_on(&self, book: &mut OrderBook) -> Result<()> {
+        let mut runner = Runner {
+            book,
+            orders: HashMap::new(),
+            next_trade_seq: 1,
+        };
+        for (line, step) in &self.steps {
+            runner
+                .step(step)
+                .with_context(|| format!("line {line}: {step:?}"))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_step(line: &str) -> Result<Step> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let step = match words.as_slice() {
+        ["place", name, owner, side, order] => {
+            let (quantity, price) = parse_order(order)?;
+            Step::Place {
+                name: name.to_string(),
+                owner: owner.to_string(),
+                side: parse_side(side)?,
+                price,
+                quantity,
+            }
+        }
+        ["place-rejected", owner, side, order] => {
+            let (quantity, price) = parse_order(order)?;
+            Step::PlaceRejected {
+                owner: owner.to_string(),
+                side: parse_side(side)?,
+                price,
+                quantity,
+            }
+        }
+        ["cancel", name] => Step::Cancel {
+            name: name.to_string(),
+        },
+        ["start-auction"] => Step::StartAuction,
+        ["uncross"] => Step::Uncross,
+        ["expect-fill", maker, taker, fill] => {
+            let (quantity, price) = parse_order(fill)?;
+            Step::ExpectFill {
+                maker: maker.to_string(),
+                taker: taker.to_string(),
+                price,
+                quantity,
+            }
+        }
+        ["expect-no-fill"] => Step::ExpectNoFill,
+        ["expect-book", side, price, quantity] => Step::ExpectBook {
+            side: parse_side(side)?,
+            price: price.parse()?,
+            quantity: quantity.parse()?,
+        },
+        ["expect-status", name, status @ ..] => Step::ExpectStatus {
+            name: name.to_string(),
+            status: match status {
+                ["resting", quantity] => ExpectedStatus::Resting(quantity.parse()?),
+                ["filled"] => ExpectedStatus::Filled,
+                ["partially-canceled"] => ExpectedStatus::PartiallyCanceled,
+                ["canceled"] => ExpectedStatus::Canceled,
+                _ => return Err(anyhow!("Unknown order status {status:?}")),
+            },
+        },
+        _ => return Err(anyhow!("Unknown step")),
+    };
+    Ok(step)
+}
+
+fn parse_side(side: &str) -> Result<OrderType> {
+    match side {
+        "bid" => Ok(OrderType::Bid),
+        "ask" => Ok(OrderType::Ask),
+        _ => Err(anyhow!("Expected bid or ask but got {side}")),
+    }
+}
+
+// <quantity>@<price>
+fn parse_order(order: &str) -> Result<(u64, u32)> {
+    let (quantity, price) = order
+        .split_once('@')
+        .ok_or_else(|| anyhow!("Expected <quantity>@<price> but got {order}"))?;
+    Ok((quantity.parse()?, price.parse()?))
+}
+
+struct Runner<'a> {
+    book: &'a mut OrderBook,
+    orders: HashMap<String, Uuid>,
+    next_trade_seq: u64,
+}
+
+impl Runner<'_> {
+    fn step(&mut self, step: &Step) -> Result<()> {
+        match step {
+            Step::Place {
+                name,
+                owner,
+                side,
+                price,
+                quantity,
+            } => {
+                if self.orders.contains_key(name) {
+                    return Err(anyhow!("Order {name} is already placed"));
+                }
+                let order_id = self.book.place_order(owner, *price, *quantity, *side)?;
+                self.orders.insert(name.clone(), order_id);
+            }
+            Step::PlaceRejected {
+                owner,
+                side,
+                price,
+                quantity,
+            } => {
+                if let Ok(order_id) = self.book.place_order(owner, *price, *quantity, *side) {
+                    return Err(anyhow!("Order was accepted as {order_id}"));
+                }
+            }
+            Step::Cancel { name } => {
+                self.book.cancel_order(self.order_id(name)?)?;
+            }
+            Step::StartAuction => self.book.start_auction()?,
+            Step::Uncross => {
+                self.book.uncross()?;
+            }
+            Step::ExpectFill {
+                maker,
+                taker,
+                price,
+                quantity,
+            } => {
+                let trade = self
+                    .book
+                    .get_trades(self.next_trade_seq, 1)?
+                    .pop()
+                    .ok_or_else(|| anyhow!("No more fills"))?;
+                self.next_trade_seq = trade.seq + 1;
+                let expected = (
+                    self.order_id(maker)?,
+                    self.order_id(taker)?,
+                    *price,
+                    *quantity,
+                );
+                let actual = (
+                    trade.maker_order_id,
+                    trade.taker_order_id,
+                    trade.price,
+                    trade.quantity,
+                );
+                if actual != expected {
+                    return Err(anyhow!(
+                        "Got fill of {}@{} between maker {} and taker {}",
+                        trade.quantity,
+                        trade.price,
+                        self.order_name(trade.maker_order_id),
+                        self.order_name(trade.taker_order_id)
+                    ));
+                }
+            }
+            Step::ExpectNoFill => {
+                if let Some(trade) = self.book.get_trades(self.next_trade_seq, 1)?.pop() {
+                    return Err(anyhow!("Got fill of {}@{}", trade.quantity, trade.price));
+                }
+            }
+            Step::ExpectBook {
+                side,
+                price,
+                quantity,
+            } => {
+                let actual = self.book.liquidity_within(*price..=*price, *side);
+                if actual != *quantity {
+                    return Err(anyhow!("Level holds {actual}"));
+                }
+            }
+            Step::ExpectStatus { name, status } => {
+                let actual = self
+                    .book
+                    .order_status(self.order_id(name)?)
+                    .ok_or_else(|| anyhow!("Order {name} is unknown to the book"))?;
+                let matches = match (status, actual) {
+                    (
+                        ExpectedStatus::Resting(quantity),
+                        OrderStatus::Resting { remaining_qty, .. },
+                    ) => *quantity == remaining_qty,
+                    (ExpectedStatus::Filled, OrderStatus::Filled)
+                    | (
+                        ExpectedStatus::PartiallyCanceled,
+                        OrderStatus::PartiallyFilledThenCanceled,
+                    )
+                    | (ExpectedStatus::Canceled, OrderStatus::Canceled) => true,
+                    _ => false,
+                };
+                if !matches {
+                    return Err(anyhow!("Order {name} is {actual:?}"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn order_id(&self, name: &str) -> Result<Uuid> {
+        self.orders
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("No order is named {name}"))
+    }
+
+    fn order_name(&self, order_id: Uuid) -> String {
+        self.orders
+            .iter()
+            .find(|(_, &id)| id == order_id)
+            .map_or_else(|| order_id.to_string(), |(name, _)| name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_steps() {
+        let scenario = Scenario::parse(
+            "# resting ask\n\
+             place a1 alice ask 5@100\n\
+             \n\
+             expect-status a1 resting 5 # trailing comment\n",
+        )
+        .unwrap();
+        assert_eq!(scenario.steps.len(), 2);
+        assert_eq!(scenario.steps[1].0, 4);
+        assert_eq!(
+            scenario.steps[0].1,
+            Step::Place {
+                name: "a1".to_string(),
+                owner: "alice".to_string(),
+                side: OrderType::Ask,
+                price: 100,
+                quantity: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        for text in ["place a1 alice ask 5", "place a1 alice buy 5@100", "fill"] {
+            let err = Scenario::parse(&format!("\n{text}")).err().unwrap();
+            assert!(err.to_string().starts_with("line 2"));
+        }
+    }
+
+    #[test]
+    fn test_failed_expectation() {
+        let scenario = Scenario::parse(
+            "place a1 alice ask 5@100\n\
+             place b1 bob bid 3@100\n\
+             expect-fill a1 b1 5@100\n",
+        )
+        .unwrap();
+        let err = scenario.run().err().unwrap();
+        assert!(err.to_string().starts_with("line 3"));
+        assert!(format!("{err:#}").contains("Got fill of 3@100 between maker a1 and taker b1"));
+    }
+}
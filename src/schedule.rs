@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+use crate::{
+    book::OrderBook,
+    clock::{next_time_of_day, parse_time_of_day, DAY_MS},
+    instrument::{Instrument, InstrumentStatus},
+};
+
+// Daily session of one instrument, as milliseconds past midnight UTC. The
+// opening auction runs from `pre_open` until `open`, continuous trading
+// until `close`, and the instrument is closed the rest of the day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TradingHours {
+    pub pre_open: u64,
+    pub open: u64,
+    pub close: u64,
+}
+
+impl TradingHours {
+    pub fn new(pre_open: u64, open: u64, close: u64) -> Result<TradingHours> {
+        if pre_open > open || open >= close || close > DAY_MS {
+            return Err(anyhow!(
+                "Trading hours must go pre-open, open, close within one day"
+            ));
+        }
+        Ok(TradingHours {
+            pre_open,
+            open,
+            close,
+        })
+    }
+
+    // "HH:MM-HH:MM-HH:MM" giving the pre-open, open and close
+    pub fn parse(hours: &str) -> Result<TradingHours> {
+        match hours.split('-').collect::<Vec<_>>().as_slice() {
+            [pre_open, open, close] => TradingHours::new(
+                parse_time_of_day(pre_open)?,
+                parse_time_of_day(open)?,
+                parse_time_of_day(close)?,
+            ),
+            _ => Err(anyhow!(
+                "Expected <pre-open>-<open>-<close> but got {hours}"
+            )),
+        }
+    }
+
+    pub fn status_at(&self, now: u64) -> InstrumentStatus {
+        let time_of_day = now % DAY_MS;
+        if time_of_day < self.pre_open || time_of_day >= self.close {
+            InstrumentStatus::Closed
+        } else if time_of_day < self.open {
+            InstrumentStatus::PreOpen
+        } else {
+            InstrumentStatus::Open
+        }
+    }
+
+    // Next time after `now` the status changes
+    pub fn next_boundary(&self, now: u64) -> u64 {
+        [self.pre_open, self.open, self.close]
+            .into_iter()
+            .map(|time_of_day| next_time_of_day(time_of_day, now))
+            .min()
+            .unwrap()
+    }
+}
+
+// Trading hours of every scheduled instrument. Instruments without hours are
+// only moved between states by admin requests.
+#[derive(Debug, Clone, Default)]
+pub struct TradingCalendar {
+    hours: HashMap<String, TradingHours>,
+}
+
+impl TradingCalendar {
+    pub fn set_hours(&mut self, symbol: &str, hours: TradingHours) {
+        self.hours.insert(symbol.to_string(), hours);
+    }
+
+    pub fn hours(&self, symbol: &str) -> Option<&TradingHours> {
+        self.hours.get(symbol)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &TradingHours)> {
+        self.hours
+            .iter()
+            .map(|(symbol, hours)| (symbol.as_str(), hours))
+    }
+
+    // Next time after `now` any instrument changes status
+    pub fn next_boundary(&self, now: u64) -> Option<u64> {
+        self.hours
+            .values()
+            .map(|hours| hours.next_boundary(now))
+            .min()
+    }
+}
+
+// Steps taken to get from one status to another the way the calendar would,
+// i.e. reopening always goes through the opening auction
+pub fn transition_path(from: InstrumentStatus, to: InstrumentStatus) -> Vec<InstrumentStatus> {
+    match (from, to) {
+        (from, to) if from == to => Vec::new(),
+        (InstrumentStatus::Closed, InstrumentStatus::Open) => {
+            vec![InstrumentStatus::PreOpen, InstrumentStatus::Open]
+        }
+        (_, to) => vec![to],
+    }
+}
+
+// Moves the instrument to the status its hours call for at `now`, returning
+// the new status if it changed. An instrument halted by an admin stays halted
+// until the close.
+pub fn follow_hours(
+    instrument: &mut Instrument,
+    book: &mut OrderBook,
+    hours: &TradingHours,
+    now: u64,
+) -> Result<Option<InstrumentStatus>> {
+    let target = hours.status_at(now);
+    if instrument.status == InstrumentStatus::Halted && target != InstrumentStatus::Closed {
+        return Ok(None);
+    }
+    let path = transition_path(instrument.status, target);
+    for &status in &path {
+        instrument.transition(book, status)?;
+    }
+    Ok(path.last().copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR_MS: u64 = 60 * 60 * 1_000;
+
+    #[test]
+    fn test_status_through_the_day() {
+        let hours = TradingHours::parse("08:00-09:30-16:00").unwrap();
+        assert!(TradingHours::parse("09:30-08:00-16:00").is_err());
+        assert!(TradingHours::parse("08:00-16:00").is_err());
+
+        let day = 3 * DAY_MS;
+        assert_eq!(hours.status_at(day + 7 * HOUR_MS), InstrumentStatus::Closed);
+        assert_eq!(
+            hours.status_at(day + 8 * HOUR_MS),
+            InstrumentStatus::PreOpen
+        );
+        assert_eq!(hours.status_at(day + 10 * HOUR_MS), InstrumentStatus::Open);
+        assert_eq!(
+            hours.status_at(day + 16 * HOUR_MS),
+            InstrumentStatus::Closed
+        );
+
+        assert_eq!(hours.next_boundary(day), day + 8 * HOUR_MS);
+        assert_eq!(
+            hours.next_boundary(day + 8 * HOUR_MS),
+            day + 9 * HOUR_MS + HOUR_MS / 2
+        );
+        assert_eq!(
+            hours.next_boundary(day + 17 * HOUR_MS),
+            day + DAY_MS + 8 * HOUR_MS
+        );
+    }
+
+    #[test]
+    fn test_calendar_next_boundary() {
+        let mut calendar = TradingCalendar::default();
+        assert_eq!(calendar.next_boundary(0), None);
+        calendar.set_hours("AAPL", TradingHours::parse("08:00-09:30-16:00").unwrap());
+        calendar.set_hours("MSFT", TradingHours::parse("07:00-07:00-12:00").unwrap());
+        assert_eq!(calendar.next_boundary(0), Some(7 * HOUR_MS));
+        assert_eq!(calendar.next_boundary(7 * HOUR_MS), Some(8 * HOUR_MS));
+    }
+
+    #[test]
+    fn test_follow_hours_runs_opening_auction() {
+        let hours = TradingHours::parse("08:00-09:30-16:00").unwrap();
+        let mut instrument = Instrument::new("AAPL", 1, 1).unwrap();
+        instrument.status = InstrumentStatus::Closed;
+        let mut book = OrderBook::new();
+        book.halt();
+
+        let pre_open = 8 * HOUR_MS;
+        assert_eq!(
+            follow_hours(&mut instrument, &mut book, &hours, pre_open).unwrap(),
+            Some(InstrumentStatus::PreOpen)
+        );
+        assert_eq!(
+            follow_hours(&mut instrument, &mut book, &hours, pre_open + 1).unwrap(),
+            None
+        );
+        book.place_order("alice", 100, 5, crate::OrderType::Ask)
+            .unwrap();
+        book.place_order("bob", 101, 5, crate::OrderType::Bid)
+            .unwrap();
+        assert_eq!(book.session_stats().trade_count(), 0);
+
+        follow_hours(&mut instrument, &mut book, &hours, 10 * HOUR_MS).unwrap();
+        assert_eq!(book.session_stats().trade_count(), 1);
+
+        // An admin halt holds until the close
+        instrument
+            .transition(&mut book, InstrumentStatus::Halted)
+            .unwrap();
+        follow_hours(&mut instrument, &mut book, &hours, 11 * HOUR_MS).unwrap();
+        assert_eq!(instrument.status, InstrumentStatus::Halted);
+        follow_hours(&mut instrument, &mut book, &hours, 16 * HOUR_MS).unwrap();
+        assert_eq!(instrument.status, InstrumentStatus::Closed);
+        assert!(book.is_halted());
+
+        // Starting up mid-session goes through the auction to open
+        follow_hours(&mut instrument, &mut book, &hours, DAY_MS + 10 * HOUR_MS).unwrap();
+        assert_eq!(instrument.status, InstrumentStatus::Open);
+        assert!(!book.is_halted());
+    }
+
+    #[test]
+    fn test_reopening_goes_through_auction() {
+        use InstrumentStatus::*;
+        assert_eq!(transition_path(Closed, Open), vec![PreOpen, Open]);
+        assert_eq!(transition_path(Open, Closed), vec![Closed]);
+        assert!(transition_path(Open, Open).is_empty());
+    }
+}
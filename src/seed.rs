@@ -0,0 +1,181 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::book::{OrderBook, OrderType};
+
+// Order to put on the book as it stands, without matching
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RestingOrder {
+    pub price: u32,
+    pub quantity: u64,
+    pub side: OrderType,
+    pub owner: String,
+}
+
+// Parses CSV with a header line and one order per line:
+//
+//   price,quantity,side,owner
+//   101,5,ask,alice
+//   99,10,bid,bob
+pub fn parse_csv(text: &str) -> Result<Vec<RestingOrder>> {
+    let mut orders = Vec::new();
+    for (idx, line) in text.lines().enumerate().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let order = parse_csv_line(line).with_context(|| format!("Line {}", idx + 1))?;
+        orders.push(order);
+    }
+    Ok(orders)
+}
+
+fn parse_csv_line(line: &str) -> Result<RestingOrder> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let [price, quantity, side, owner] = fields[..] else {
+        return Err(anyhow!("Expected 4 fields but got {}", fields.len()));
+    };
+    Ok(RestingOrder {
+        price: price.parse()?,
+        quantity: quantity.parse()?,
+        side: match side {
+            "bid" => OrderType::Bid,
+            "ask" => OrderType::Ask,
+            _ => return Err(anyhow!("Expected bid or ask but got {side:?}")),
+        },
+        owner: owner.to_string(),
+    })
+}
+
+// Parses a JSON array of orders, e.g.
+// [{"price": 101, "quantity": 5, "side": "Ask", "owner": "alice"}]
+pub fn parse_json(text: &str) -> Result<Vec<RestingOrder>> {
+    Ok(serde_json::from_str(text)?)
+}
+
+// Reads JSON from .json files and CSV from anything else
+pub fn read_file(path: &Path) -> Result<Vec<RestingOrder>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        parse_json(&text)
+    } else {
+        parse_csv(&text)
+    }
+}
+
+// Places the orders in the order given, so earlier ones at a price keep time
+// priority. Seeding never trades: if the orders would cross each other or the
+// book, none are placed. An order the book rejects part way through takes the
+// ones placed before it back off.
+pub fn load(book: &mut OrderBook, orders: &[RestingOrder]) -> Result<Vec<Uuid>> {
+    let best = |side| {
+        let loaded = orders
+            .iter()
+            .filter(|order| order.side == side)
+            .map(|order| order.price);
+        let resting = book.top_levels(side, 1).map(|(_, level)| level.price());
+        match side {
+            OrderType::Bid => loaded.chain(resting).max(),
+            OrderType::Ask => loaded.chain(resting).min(),
+        }
+    };
+    if let (Some(bid), Some(ask)) = (best(OrderType::Bid), best(OrderType::Ask)) {
+        if bid >= ask {
+            return Err(anyhow!(
+                "Orders would cross with a best bid of {bid} and best ask of {ask}"
+            ));
+        }
+    }
+
+    let mut placed = Vec::with_capacity(orders.len());
+    for (idx, order) in orders.iter().enumerate() {
+        match book.place_order(&order.owner, order.price, order.quantity, order.side) {
+            Ok(order_id) => placed.push(order_id),
+            Err(err) => {
+                for order_id in placed {
+                    book.cancel_order(order_id)?;
+                }
+                return Err(err).context(format!("Order {} was rejected", idx + 1));
+            }
+        }
+    }
+    Ok(placed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_and_json() {
+        let from_csv =
+            parse_csv("price,quantity,side,owner\n101,5,ask,alice\n\n99,10,bid,bob\n").unwrap();
+        let from_json = parse_json(
+            r#"[
+                {"price": 101, "quantity": 5, "side": "Ask", "owner": "alice"},
+                {"price": 99, "quantity": 10, "side": "Bid", "owner": "bob"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(from_csv, from_json);
+        assert_eq!(from_csv[1].side, OrderType::Bid);
+
+        let err = parse_csv("price,quantity,side,owner\n101,5,sell,alice").unwrap_err();
+        assert!(format!("{err:#}").contains("Line 2"));
+    }
+
+    #[test]
+    fn test_load_rests_without_trading() {
+        let mut book = OrderBook::new();
+        book.place_order("carol", 98, 1, OrderType::Bid).unwrap();
+        let orders =
+            parse_csv("price,quantity,side,owner\n101,5,ask,alice\n99,10,bid,bob\n").unwrap();
+        let order_ids = load(&mut book, &orders).unwrap();
+        assert_eq!(order_ids.len(), 2);
+        assert_eq!(book.best_bid().unwrap().price(), 99);
+        assert_eq!(book.best_ask().unwrap().price(), 101);
+        assert!(book.get_trades(1, usize::MAX).unwrap().is_empty());
+
+        // An ask at the best bid would trade, so nothing is loaded
+        let crossing = vec![
+            RestingOrder {
+                price: 105,
+                quantity: 1,
+                side: OrderType::Ask,
+                owner: "dave".to_string(),
+            },
+            RestingOrder {
+                price: 99,
+                quantity: 1,
+                side: OrderType::Ask,
+                owner: "dave".to_string(),
+            },
+        ];
+        assert!(load(&mut book, &crossing).is_err());
+        assert_eq!(book.open_exposure("dave").ask_quantity, 0);
+
+        // A rejected order takes the ones before it back off
+        let rejected = vec![
+            RestingOrder {
+                price: 105,
+                quantity: 1,
+                side: OrderType::Ask,
+                owner: "dave".to_string(),
+            },
+            RestingOrder {
+                price: 106,
+                quantity: 0,
+                side: OrderType::Ask,
+                owner: "dave".to_string(),
+            },
+        ];
+        let err = load(&mut book, &rejected).unwrap_err();
+        assert!(format!("{err:#}").contains("Order 2"));
+        assert_eq!(book.best_ask().unwrap().price(), 101);
+    }
+}
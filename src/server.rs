@@ -0,0 +1,1050 @@
+use anyhow::{anyhow, Result};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::oneshot,
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::{self, Authenticator},
+    book::OrderBook,
+    clearing::AccountAction,
+    clock::{monotonic_nanos, next_time_of_day, unix_millis},
+    config::{BackpressurePolicy, ConnectionTimeouts},
+    engine::{BookHandle, ExchangeHandle, QueueFull},
+    error::OrderBookError,
+    exchange::Exchange,
+    fills::{Fill, FillSubscription},
+    instrument::{Instrument, InstrumentStatus},
+    metrics::LatencyMetrics,
+    price_feed::PriceFeed,
+    rate_limit::{RateLimit, TokenBucket},
+    replication::ReplicationStream,
+    req::{CancelOrderArgs, HandshakeArgs, PlaceOrderArgs, Request, SymbolRequest},
+    resp::{AsyncReject, MarketDataUpdate, Response, TaggedResponse},
+    schedule::{self, TradingHours},
+    seed,
+    settlement::EndOfDayOptions,
+    subscription::{MarketDataStream, Outbox, DEFAULT_OUTBOX_LIMIT},
+    tenant::Tenants,
+    wire::Framed,
+};
+
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_millis(100);
+// Largest batch applied in one go, so a batch can't hold up the matching task
+pub const MAX_BATCH_ORDERS: usize = 1_000;
+// Most requests a connection with async acks has waiting on the book before
+// it stops reading more
+const MAX_PENDING_ACKS: usize = 1_024;
+
+// Accepts connections until the listener fails, serving each on its own task
+pub async fn serve(
+    listener: TcpListener,
+    book: BookHandle,
+    auth: Arc<Mutex<Authenticator>>,
+) -> Result<()> {
+    serve_with_options(listener, book, auth, ServeOptions::default()).await
+}
+
+// Serves latency metrics to Prometheus over HTTP. Every request gets the
+// current metrics whatever its path, and the connection is closed after.
+pub async fn serve_metrics(listener: TcpListener, latency: Arc<LatencyMetrics>) -> Result<()> {
+    loop {
+        let (mut socket, _) = listener.accept().await?;
+        let latency = latency.clone();
+        tokio::spawn(async move {
+            // Only read so the scraper isn't reset mid request
+            let mut request = [0; 1024];
+            if matches!(socket.read(&mut request).await, Ok(0) | Err(_)) {
+                return;
+            }
+            let body = latency.prometheus();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+// How a listener treats the connections it accepts
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ServeOptions {
+    // Requests beyond it on a connection are turned away
+    pub rate_limit: Option<RateLimit>,
+    // Answer queries only, turning away order entry and admin requests
+    pub market_data_only: bool,
+    // When connections that stalled or went quiet are closed
+    pub timeouts: ConnectionTimeouts,
+    // Largest message read, DEFAULT_MAX_FRAME_LEN if not set
+    pub max_frame_len: Option<usize>,
+    // Read messages that aren't MessagePack as JSON
+    pub json_fallback: bool,
+    // Most responses waiting to be written, DEFAULT_OUTBOX_LIMIT if not set
+    pub outbox_limit: Option<usize>,
+    // What a connection falling behind its market data gets
+    pub backpressure: BackpressurePolicy,
+}
+
+pub async fn serve_with_options(
+    listener: TcpListener,
+    book: BookHandle,
+    auth: Arc<Mutex<Authenticator>>,
+    options: ServeOptions,
+) -> Result<()> {
+    serve_venue(listener, Venue::Single(book), auth, options).await
+}
+
+// Serves several tenants from one listener, each request going to the book
+// of the tenant whose client signed it, see tenant.rs
+pub async fn serve_tenants(
+    listener: TcpListener,
+    tenants: Arc<Tenants>,
+    auth: Arc<Mutex<Authenticator>>,
+    options: ServeOptions,
+) -> Result<()> {
+    serve_venue(listener, Venue::Tenants(tenants), auth, options).await
+}
+
+// Serves an exchange from one listener, each request going to the book of
+// the symbol it names, see Request::OnSymbol. Fills, market data and the
+// event log are only pushed by servers of a single book or tenants.
+pub async fn serve_exchange(
+    listener: TcpListener,
+    exchange: ExchangeHandle,
+    auth: Arc<Mutex<Authenticator>>,
+    options: ServeOptions,
+) -> Result<()> {
+    serve_venue(listener, Venue::Exchange(exchange), auth, options).await
+}
+
+// Books the requests of a listener's connections can reach
+#[derive(Clone)]
+enum Venue {
+    Single(BookHandle),
+    Tenants(Arc<Tenants>),
+    Exchange(ExchangeHandle),
+}
+
+impl Venue {
+    // Book for a request signed by `client_id`, or unsigned. Only a client
+    // of one of the tenants reaches a book on a multi-tenant server, and
+    // an exchange's books are reached through the exchange.
+    fn book_for(&self, client_id: Option<&str>) -> Option<&BookHandle> {
+        match self {
+            Venue::Single(book) => Some(book),
+            Venue::Tenants(tenants) => {
+                client_id.and_then(|client_id| tenants.book_for_client(client_id))
+            }
+            Venue::Exchange(_) => None,
+        }
+    }
+
+    fn exchange(&self) -> Option<&ExchangeHandle> {
+        match self {
+            Venue::Exchange(exchange) => Some(exchange),
+            _ => None,
+        }
+    }
+}
+
+async fn serve_venue(
+    listener: TcpListener,
+    venue: Venue,
+    auth: Arc<Mutex<Authenticator>>,
+    options: ServeOptions,
+) -> Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let venue = venue.clone();
+        let auth = auth.clone();
+
+        tokio::spawn(async move {
+            process_connection(socket, venue, auth, options).await;
+        });
+    }
+}
+
+pub async fn process_socket(
+    socket: TcpStream,
+    book: BookHandle,
+    auth: Arc<Mutex<Authenticator>>,
+    options: ServeOptions,
+) {
+    process_connection(socket, Venue::Single(book), auth, options).await
+}
+
+async fn process_connection(
+    socket: TcpStream,
+    venue: Venue,
+    auth: Arc<Mutex<Authenticator>>,
+    options: ServeOptions,
+) {
+    let mut bucket = options
+        .rate_limit
+        .map(|rate_limit| TokenBucket::new(rate_limit, Instant::now()));
+    // Set once the connection subscribes to an owner's fills
+    let mut fills: Option<FillSubscription> = None;
+    // Set once a standby asks for the event log
+    let mut replication: Option<ReplicationStream> = None;
+    // Set once the connection subscribes to market data
+    let mut market_data: Option<MarketDataStream> = None;
+    // Set once the connection agrees to async acks, see HandshakeArgs
+    let mut async_acks = false;
+    // Responses owed in async ack mode, oldest first
+    let mut acks: VecDeque<PendingAck> = VecDeque::new();
+    // Fills and market data waiting to be written
+    let mut outbox = Outbox::new(
+        options.outbox_limit.unwrap_or(DEFAULT_OUTBOX_LIMIT),
+        options.backpressure,
+    );
+    // Deserialize incoming requests until the connection closes or sends
+    // something that doesn't parse or fails its checksum. Closing it has the
+    // client reconnect at a frame boundary. Fills, market data and
+    // replicated events are pushed in between.
+    //
+    // A connection past its idle timeout or lifetime, or stalled part way
+    // through a message, is closed too. Closing it ends its fill session, so
+    // the owner's fills queue for its next one.
+    let mut socket = Framed::new(socket);
+    if let Some(max_frame_len) = options.max_frame_len {
+        socket.set_max_frame_len(max_frame_len);
+    }
+    socket.set_json_fallback(options.json_fallback);
+    socket.set_read_timeout(options.timeouts.read_timeout_ms.map(Duration::from_millis));
+    let opened_at = Instant::now();
+    let mut active_at = opened_at;
+    loop {
+        let msg = tokio::select! {
+            msg = socket.read_msg(), if acks.len() < MAX_PENDING_ACKS => match msg {
+                Ok(msg) => msg,
+                Err(_) => return,
+            },
+            _ = reap(&options.timeouts, opened_at, active_at) => return,
+            // One at a time, so requests are still read in between
+            _ = std::future::ready(()), if !outbox.is_empty() => {
+                let response = outbox.pop().unwrap();
+                if socket.write_msg(&response).await.is_err() {
+                    return;
+                }
+                active_at = Instant::now();
+                continue;
+            }
+            // Left with the fill router while the outbox is full
+            Some(fill) = next_fill(&mut fills), if !outbox.is_full() => {
+                outbox.push(Response::Fill(fill));
+                continue;
+            }
+            updates = next_market_data(&mut market_data) => match updates {
+                Ok(updates) => {
+                    for update in updates {
+                        if !outbox.push_market_data(update) {
+                            return;
+                        }
+                    }
+                    continue;
+                }
+                Err(_) => return,
+            },
+            ack = next_ack(&mut acks) => match ack {
+                Ok(response) => {
+                    if socket.write_msg(&response).await.is_err() {
+                        return;
+                    }
+                    active_at = Instant::now();
+                    continue;
+                }
+                Err(_) => return,
+            },
+            replicated = next_replicated(&mut replication) => match replicated {
+                Ok(response) => {
+                    if socket.write_msg(&response).await.is_err() {
+                        return;
+                    }
+                    active_at = Instant::now();
+                    continue;
+                }
+                Err(_) => return,
+            },
+        };
+        active_at = Instant::now();
+        let received_nanos = monotonic_nanos();
+        // Every response to the request carries its tag, if it has one
+        let (request_id, msg) = match msg {
+            Request::Tagged(tagged_request) => {
+                (Some(tagged_request.request_id), tagged_request.request)
+            }
+            msg => (None, msg),
+        };
+        // Checked first so a flood costs neither signature checks nor a
+        // place on the matching queue
+        if let Some(bucket) = &mut bucket {
+            if !bucket.try_acquire(Instant::now()) {
+                let rate_limited = Response::RateLimited;
+                if respond(
+                    &mut socket,
+                    &mut acks,
+                    async_acks,
+                    request_id,
+                    received_nanos,
+                    rate_limited,
+                )
+                .await
+                .is_err()
+                {
+                    return;
+                }
+                continue;
+            }
+        }
+        // Shared across connections so a frame can't be replayed on another socket
+        let opened = {
+            let mut auth = auth.lock().unwrap();
+            auth.authenticate(msg, unix_millis())
+                .map(|(client_id, request)| {
+                    // Client a signed request may only act for, unless it's an admin
+                    let signer = client_id
+                        .clone()
+                        .filter(|client_id| !auth.is_admin(client_id));
+                    (venue.book_for(client_id.as_deref()), signer, request)
+                })
+        };
+        let response = match opened {
+            Ok((_, _, Request::Handshake(handshake_args))) => {
+                let handshake_ok = tag(request_id, Response::HandshakeOk(handshake_args));
+                if socket.write_msg(&handshake_ok).await.is_err() {
+                    return;
+                }
+                socket.set_compression(handshake_args.compression);
+                socket.set_fixed_layout(handshake_args.fixed_layout);
+                async_acks = handshake_args.async_acks;
+                continue;
+            }
+            Ok((None, signer, request)) => match venue.exchange() {
+                Some(_) if options.market_data_only && !request.is_query() => Response::ReadOnlyErr,
+                Some(exchange) => {
+                    let reply = exchange.try_submit(move |exchange| {
+                        handle_exchange_request(exchange, signer, request)
+                    });
+                    match await_reply(reply, &mut acks, async_acks, request_id, received_nanos)
+                        .await
+                    {
+                        Ok(Some(response)) => response,
+                        Ok(None) => continue,
+                        Err(_) => return,
+                    }
+                }
+                None => Response::TenantErr,
+            },
+            Ok((Some(book), _, request))
+                if (options.market_data_only || book.is_standby()) && !request.is_query() =>
+            {
+                Response::ReadOnlyErr
+            }
+            // Replaces any earlier subscription of the connection
+            // Fills never reach the book, so there are no orders to look up
+            Ok((Some(_), Some(signer), request @ Request::SubscribeFills(_)))
+                if !auth::acts_for(&signer, &request, |_| false) =>
+            {
+                Response::AuthErr
+            }
+            Ok((Some(book), _, Request::SubscribeFills(subscribe_fills_args))) => {
+                fills = Some(book.fills().subscribe(&subscribe_fills_args.owner));
+                Response::SubscribeFillsOk
+            }
+            Ok((Some(book), _, Request::Replicate(replicate_args))) => {
+                replication = Some(ReplicationStream::new(book, replicate_args.from_seq));
+                Response::ReplicateOk
+            }
+            // Every subscription follows the book of the first one
+            Ok((Some(book), _, Request::Subscribe(subscribe_args))) => {
+                let mut stream = match market_data.take() {
+                    Some(stream) => stream,
+                    None => match MarketDataStream::new(book).await {
+                        Ok(stream) => stream,
+                        Err(_) => return,
+                    },
+                };
+                let response = match stream.subscribe(subscribe_args) {
+                    Ok(()) => Response::SubscribeOk,
+                    Err(_) => Response::SubscribeErr,
+                };
+                market_data = Some(stream);
+                response
+            }
+            Ok((Some(_), _, Request::Unsubscribe(unsubscribe_args))) => {
+                Response::UnsubscribeOk(market_data.as_mut().is_some_and(|stream| {
+                    stream.unsubscribe(unsubscribe_args.symbol, unsubscribe_args.channel)
+                }))
+            }
+            Ok((Some(_), _, Request::ViewSubscriptions)) => Response::SubscriptionsOk(
+                market_data
+                    .as_ref()
+                    .map(MarketDataStream::subscriptions)
+                    .unwrap_or_default(),
+            ),
+            Ok((Some(book), signer, request)) => {
+                let reply =
+                    book.try_submit(move |book| handle_signed_request(book, signer, request));
+                match await_reply(reply, &mut acks, async_acks, request_id, received_nanos).await {
+                    Ok(Some(response)) => response,
+                    Ok(None) => continue,
+                    Err(_) => return,
+                }
+            }
+            Err(_) => Response::AuthErr,
+        };
+        if respond(
+            &mut socket,
+            &mut acks,
+            async_acks,
+            request_id,
+            received_nanos,
+            response,
+        )
+        .await
+        .is_err()
+        {
+            return;
+        }
+    }
+}
+
+// Waits for the response to a request queued on a matching task. Requests
+// beyond the queue's capacity are turned away rather than left to pile up.
+// With async acks, a tagged request's response is left owed, to be written
+// once the book gets to it, and None returned. Fails once the matching task
+// is gone.
+async fn await_reply(
+    reply: Result<oneshot::Receiver<Response>>,
+    acks: &mut VecDeque<PendingAck>,
+    async_acks: bool,
+    request_id: Option<u64>,
+    received_nanos: u64,
+) -> Result<Option<Response>> {
+    match reply {
+        Ok(reply) if async_acks && request_id.is_some() => {
+            acks.push_back(PendingAck {
+                request_id,
+                received_nanos,
+                reply,
+            });
+            Ok(None)
+        }
+        Ok(reply) => Ok(Some(reply.await?)),
+        Err(err) if err.is::<QueueFull>() => Ok(Some(Response::Overloaded)),
+        Err(err) => Err(err),
+    }
+}
+
+// Response owed to a request on a connection with async acks
+struct PendingAck {
+    request_id: Option<u64>,
+    received_nanos: u64,
+    reply: oneshot::Receiver<Response>,
+}
+
+// Writes the response to a request, or while acks are still owed, queues it
+// behind them so responses go out in the order of their requests
+async fn respond(
+    socket: &mut Framed,
+    acks: &mut VecDeque<PendingAck>,
+    async_acks: bool,
+    request_id: Option<u64>,
+    received_nanos: u64,
+    mut response: Response,
+) -> Result<()> {
+    if !acks.is_empty() {
+        let (ready, reply) = oneshot::channel();
+        let _ = ready.send(response);
+        acks.push_back(PendingAck {
+            request_id,
+            received_nanos,
+            reply,
+        });
+        return Ok(());
+    }
+    stamp_reports(&mut response, received_nanos);
+    let response = match async_acks {
+        true => async_ack(request_id, response),
+        false => tag(request_id, response),
+    };
+    socket.write_msg(&response).await
+}
+
+// Waits forever on a connection without acks owed, otherwise for the
+// oldest to be ready. The ones behind it wait their turn even if the book
+// is done with them, as they are applied in order anyway.
+async fn next_ack(acks: &mut VecDeque<PendingAck>) -> Result<Response> {
+    let Some(ack) = acks.front_mut() else {
+        return std::future::pending().await;
+    };
+    let mut response = (&mut ack.reply)
+        .await
+        .map_err(|_| anyhow!("Matching task dropped the command"))?;
+    let ack = acks.pop_front().unwrap();
+    stamp_reports(&mut response, ack.received_nanos);
+    Ok(async_ack(ack.request_id, response))
+}
+
+// Response as pushed with async acks, where a tagged request the book turned
+// down, or that was turned away before reaching it, gets a Response::Rejected
+// rather than its tagged error
+fn async_ack(request_id: Option<u64>, response: Response) -> Response {
+    let Some(request_id) = request_id else {
+        return response;
+    };
+    let reject = match response {
+        Response::PlaceErr(reject)
+        | Response::CancelErr(reject)
+        | Response::StartAuctionErr(reject)
+        | Response::UncrossErr(reject) => reject,
+        Response::AuthErr => OrderBookError::Unauthorized.into(),
+        Response::RateLimited => OrderBookError::RateLimited.into(),
+        Response::Overloaded => OrderBookError::Overloaded.into(),
+        response => return tag(Some(request_id), response),
+    };
+    Response::Rejected(AsyncReject { request_id, reject })
+}
+
+// Response as sent back for a request tagged with `request_id`, if it was
+fn tag(request_id: Option<u64>, response: Response) -> Response {
+    match request_id {
+        Some(request_id) => Response::Tagged(Box::new(TaggedResponse {
+            request_id,
+            response,
+        })),
+        None => response,
+    }
+}
+
+// Completes the timestamps of the execution reports in a response with when
+// the request was received and, as it's about to be sent, acknowledged
+fn stamp_reports(response: &mut Response, received_nanos: u64) {
+    match response {
+        Response::PlaceOk(report) => {
+            if let Some(timestamps) = &mut report.timestamps {
+                timestamps.received_nanos = Some(received_nanos);
+                timestamps.acked_nanos = Some(monotonic_nanos());
+            }
+        }
+        Response::PlaceOrdersOk(responses) => {
+            for response in responses {
+                stamp_reports(response, received_nanos);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Returns once the connection is past its idle timeout or lifetime, and
+// waits forever when it has neither
+async fn reap(timeouts: &ConnectionTimeouts, opened_at: Instant, active_at: Instant) {
+    let idle_until = timeouts
+        .idle_timeout_ms
+        .map(|idle_timeout_ms| active_at + Duration::from_millis(idle_timeout_ms));
+    let lifetime_until = timeouts
+        .max_lifetime_ms
+        .map(|max_lifetime_ms| opened_at + Duration::from_millis(max_lifetime_ms));
+    match idle_until.into_iter().chain(lifetime_until).min() {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+// Waits forever on a connection that isn't subscribed to fills
+async fn next_fill(fills: &mut Option<FillSubscription>) -> Option<Fill> {
+    match fills {
+        Some(fills) => fills.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// Waits forever on a connection without market data subscriptions,
+// otherwise for the book to change
+async fn next_market_data(
+    market_data: &mut Option<MarketDataStream>,
+) -> Result<Vec<MarketDataUpdate>> {
+    match market_data {
+        Some(market_data) => market_data.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+// Waits forever on a connection that isn't replicating, otherwise for the
+// next events to push or a heartbeat once it's been idle
+async fn next_replicated(replication: &mut Option<ReplicationStream>) -> Result<Response> {
+    match replication {
+        Some(replication) => replication.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+// Applies a request signed by `signer` only if it acts on the signer's own
+// orders. Checked on the matching task, which knows who owns each order.
+fn handle_signed_request(
+    book: &mut OrderBook,
+    signer: Option<String>,
+    request: Request,
+) -> Response {
+    if let Some(signer) = signer {
+        let owns_order = |order_id| {
+            book.order_owner(order_id)
+                .is_none_or(|owner| owner == signer)
+        };
+        if !auth::acts_for(&signer, &request, owns_order) {
+            return Response::AuthErr;
+        }
+    }
+    handle_request(book, request)
+}
+
+// Applies a request to the book of the symbol it names, checking it acts for
+// `signer` as handle_signed_request does. Runs on the exchange's matching
+// task, which owns every book.
+pub fn handle_exchange_request(
+    exchange: &mut Exchange,
+    signer: Option<String>,
+    request: Request,
+) -> Response {
+    match request {
+        Request::OnSymbol(symbol_request) => {
+            let SymbolRequest { symbol, request } = *symbol_request;
+            // Commands batched for the symbol come first
+            exchange.flush(&symbol);
+            match exchange.book_mut(&symbol) {
+                Some(book) => handle_signed_request(book, signer, request),
+                None => Response::SymbolErr,
+            }
+        }
+        Request::MigrateSymbol(args) => match exchange.migrate_symbol(&args.symbol, args.shard) {
+            Ok(shard) => Response::MigrateSymbolOk(shard),
+            Err(err) => Response::MigrateSymbolErr(err.to_string()),
+        },
+        Request::SetInstrumentStatus(args) => {
+            match exchange.set_instrument_status(&args.symbol, args.status) {
+                Ok(previous) => Response::InstrumentStatusOk(previous),
+                Err(err) => Response::InstrumentStatusErr(err.to_string()),
+            }
+        }
+        Request::ViewOptionChain(args) => {
+            Response::OptionChainOk(exchange.option_chain(&args.underlying, args.expires_at))
+        }
+        _ => Response::SymbolErr,
+    }
+}
+
+// Applies one request to the book. Runs on the matching task, which owns the
+// book, so requests never interleave.
+pub fn handle_request(book: &mut OrderBook, request: Request) -> Response {
+    match request {
+        Request::ViewL2Book => Response::L2BookOk(book.view_book_l2()),
+        Request::ViewL1Book => Response::L1BookOk(book.view_book_l1()),
+        Request::ViewL3Book => Response::L3BookOk(book.view_book_l3()),
+        Request::ViewStats(view_stats_args) => {
+            Response::StatsOk(book.book_stats(view_stats_args.depth))
+        }
+        Request::ResumeTrading => {
+            book.resume();
+            Response::ResumeOk
+        }
+        Request::GetTrades(page) => match book.query_trades(&page) {
+            Ok(trades) => Response::TradesOk(trades),
+            Err(_) => Response::TradesErr,
+        },
+        Request::ScheduleFees(schedule_fees_args) => {
+            match book.clearing_house_mut().schedule_fees(
+                schedule_fees_args.maker_fee_bps,
+                schedule_fees_args.taker_fee_bps,
+                schedule_fees_args.effective_from,
+                unix_millis(),
+            ) {
+                Ok(version) => Response::ScheduleFeesOk(version),
+                Err(_) => Response::ScheduleFeesErr,
+            }
+        }
+        Request::ViewFeeSchedules => {
+            Response::FeeSchedulesOk(book.clearing_house().fee_schedules().to_vec())
+        }
+        Request::SetFeeTiers(set_fee_tiers_args) => match book
+            .clearing_house_mut()
+            .set_fee_tiers(set_fee_tiers_args.tiers)
+        {
+            Ok(()) => Response::SetFeeTiersOk,
+            Err(_) => Response::SetFeeTiersErr,
+        },
+        Request::ViewFeeTier(view_account_args) => Response::FeeTierOk(
+            book.clearing_house()
+                .fee_tier_status(&view_account_args.owner, unix_millis()),
+        ),
+        Request::EndOfDay(end_of_day_args) => {
+            match book.end_of_day(unix_millis(), &end_of_day_args.options) {
+                Ok(report) => Response::EndOfDayOk(report),
+                Err(_) => Response::EndOfDayErr,
+            }
+        }
+        Request::ViewSettlements => {
+            Response::SettlementsOk(book.clearing_house().settlements().to_vec())
+        }
+        Request::NetTrades => {
+            Response::NettingOk(book.clearing_house_mut().net_trades(unix_millis()))
+        }
+        Request::ViewNettingRuns => {
+            Response::NettingRunsOk(book.clearing_house().netting_runs().to_vec())
+        }
+        Request::ViewLatency => Response::LatencyOk(book.latency().summary()),
+        Request::ViewChecksum => Response::ChecksumOk(book.checksum()),
+        Request::ViewMarketStats => Response::MarketStatsOk(book.market_stats()),
+        Request::ViewMargin(view_account_args) => {
+            match book.margin_status(&view_account_args.owner) {
+                Some(status) => Response::MarginOk(status),
+                None => Response::MarginErr,
+            }
+        }
+        Request::ViewAccount(view_account_args) => Response::AccountOk(
+            book.clearing_house()
+                .account_statement(&view_account_args.owner),
+        ),
+        Request::BustTrade(bust_trade_args) => match book
+            .clearing_house_mut()
+            .bust_trade(bust_trade_args.trade_seq, unix_millis())
+        {
+            Ok(_) => Response::BustOk,
+            Err(_) => Response::BustErr,
+        },
+        Request::AccountAction(account_action_args) => {
+            match book.apply_account_action(account_action_args.action, &account_action_args.memo) {
+                Ok(entry_id) => Response::AccountActionOk(entry_id),
+                Err(_) => Response::AccountActionErr,
+            }
+        }
+        Request::Deposit(funds_args) => {
+            let action = AccountAction::Deposit {
+                owner: funds_args.owner.clone(),
+                amount: funds_args.amount,
+            };
+            match book.apply_account_action(action, &funds_args.memo) {
+                Ok(_) => Response::BalanceOk(book.balance(&funds_args.owner)),
+                Err(_) => Response::AccountActionErr,
+            }
+        }
+        Request::Withdraw(funds_args) => {
+            let action = AccountAction::Withdraw {
+                owner: funds_args.owner.clone(),
+                amount: funds_args.amount,
+            };
+            match book.apply_account_action(action, &funds_args.memo) {
+                Ok(_) => Response::BalanceOk(book.balance(&funds_args.owner)),
+                Err(_) => Response::AccountActionErr,
+            }
+        }
+        Request::ViewBalance(view_account_args) => {
+            Response::BalanceOk(book.balance(&view_account_args.owner))
+        }
+        Request::SetParticipantRisk(set_participant_risk_args) => {
+            book.set_participant_risk(
+                &set_participant_risk_args.owner,
+                set_participant_risk_args.config,
+            );
+            Response::SetParticipantRiskOk
+        }
+        Request::QueryCandles(query_candles_args) => {
+            match book.query_candles(query_candles_args.interval_ms, &query_candles_args.page) {
+                Ok(candles) => Response::CandlesOk(candles),
+                Err(_) => Response::CandlesErr,
+            }
+        }
+        Request::GetEvents(get_events_args) => {
+            match book.events_since(get_events_args.from_seq, get_events_args.limit) {
+                Ok(events) => Response::EventsOk(events),
+                Err(_) => Response::EventsErr,
+            }
+        }
+        Request::ViewOpenOrders(view_open_orders_args) => match book
+            .query_open_orders(&view_open_orders_args.owner, &view_open_orders_args.page)
+        {
+            Ok(open_orders) => Response::OpenOrdersOk(open_orders),
+            Err(_) => Response::OpenOrdersErr,
+        },
+        Request::QueryOrder(query_order_args) => {
+            match book.order_status(query_order_args.order_id) {
+                Some(status) => Response::OrderStatusOk(status),
+                None => Response::OrderStatusErr,
+            }
+        }
+        Request::QueryOrderAccount(query_order_args) => {
+            match book.order_account(query_order_args.order_id) {
+                Some(account) => Response::OrderAccountOk(account),
+                None => Response::OrderAccountErr,
+            }
+        }
+        Request::CheckConservation => match book.check_conservation() {
+            Ok(num_orders) => Response::ConservationOk(num_orders),
+            Err(err) => Response::ConservationErr(err.to_string()),
+        },
+        Request::ViewAuditLog => Response::AuditLogOk(book.clearing_house().audit_log().to_vec()),
+        Request::StartAuction => match book.start_auction() {
+            Ok(()) => Response::StartAuctionOk,
+            Err(err) => Response::StartAuctionErr(err.into()),
+        },
+        Request::Uncross => match book.uncross() {
+            Ok(uncross) => Response::UncrossOk(uncross),
+            Err(err) => Response::UncrossErr(err.into()),
+        },
+        Request::CancelOrder(cancel_order_args) => cancel_order(book, cancel_order_args),
+        Request::CancelClientOrder(cancel_client_order_args) => match book.cancel_client_order(
+            &cancel_client_order_args.owner,
+            &cancel_client_order_args.client_order_id,
+        ) {
+            Ok(canceled_quantity) => Response::CancelOk(canceled_quantity),
+            Err(err) => Response::CancelErr(err.into()),
+        },
+        Request::CancelOrders(cancel_orders_args) => {
+            if cancel_orders_args.len() > MAX_BATCH_ORDERS {
+                return Response::BatchErr;
+            }
+            Response::CancelOrdersOk(
+                cancel_orders_args
+                    .into_iter()
+                    .map(|cancel_order_args| cancel_order(book, cancel_order_args))
+                    .collect(),
+            )
+        }
+        Request::CancelAll(cancel_all_args) => {
+            Response::CancelAllOk(book.cancel_all(&cancel_all_args.filter))
+        }
+        Request::PlaceOrder(place_order_args) => place_order(book, place_order_args),
+        Request::PlaceMarketOrder(place_market_order_args) => place_once(
+            book,
+            &place_market_order_args.owner,
+            place_market_order_args.client_order_id.as_deref(),
+            |book| {
+                book.place_market_order(
+                    &place_market_order_args.owner,
+                    place_market_order_args.quantity,
+                    place_market_order_args.order_type,
+                    place_market_order_args.protection,
+                )
+            },
+        ),
+        Request::PlaceOrders(place_orders_args) => {
+            if place_orders_args.len() > MAX_BATCH_ORDERS {
+                return Response::BatchErr;
+            }
+            Response::PlaceOrdersOk(
+                place_orders_args
+                    .into_iter()
+                    .map(|place_order_args| place_order(book, place_order_args))
+                    .collect(),
+            )
+        }
+        // Not capped like batches, since loads are meant to seed a whole
+        // book before trading starts
+        Request::LoadOrders(orders) => match seed::load(book, &orders) {
+            Ok(order_ids) => Response::LoadOrdersOk(order_ids),
+            Err(err) => Response::LoadOrdersErr(format!("{err:#}")),
+        },
+        // Only a connection can agree to compression or be pushed fills, see
+        // process_socket
+        Request::Handshake(_) => Response::HandshakeOk(HandshakeArgs {
+            compression: false,
+            fixed_layout: false,
+            async_acks: false,
+        }),
+        Request::SubscribeFills(_) => Response::SubscribeFillsErr,
+        Request::Subscribe(_) => Response::SubscribeErr,
+        Request::Unsubscribe(_) => Response::UnsubscribeOk(false),
+        Request::ViewSubscriptions => Response::SubscriptionsOk(Vec::new()),
+        Request::Replicate(_) => Response::ReplicateErr,
+        // Nested signed requests are rejected when opened
+        Request::Signed(_) => Response::AuthErr,
+        // Connection takes the outermost tag off, so this one was nested
+        Request::Tagged(_) => Response::TagErr,
+        // Only an exchange has symbols, see handle_exchange_request
+        Request::OnSymbol(_) => Response::SymbolErr,
+        Request::MigrateSymbol(_) => {
+            Response::MigrateSymbolErr("Only an exchange has shards".to_string())
+        }
+        Request::SetInstrumentStatus(_) => {
+            Response::InstrumentStatusErr("Only an exchange lists instruments".to_string())
+        }
+        Request::ViewOptionChain(_) => Response::OptionChainErr,
+    }
+}
+
+fn place_order(book: &mut OrderBook, place_order_args: PlaceOrderArgs) -> Response {
+    place_once(
+        book,
+        &place_order_args.owner,
+        place_order_args.client_order_id.as_deref(),
+        |book| {
+            book.place_order_with_expiry(
+                &place_order_args.owner,
+                place_order_args.price,
+                place_order_args.quantity,
+                place_order_args.order_type,
+                place_order_args.expires_at,
+            )
+        },
+    )
+}
+
+// Places an order with `place`, unless the owner already placed one under
+// the client order id. A resent order gets the first one's report, as it
+// stands now, rather than being placed twice.
+fn place_once(
+    book: &mut OrderBook,
+    owner: &str,
+    client_order_id: Option<&str>,
+    place: impl FnOnce(&mut OrderBook) -> Result<Uuid, OrderBookError>,
+) -> Response {
+    let order_id = match client_order_id
+        .and_then(|client_order_id| book.client_order(owner, client_order_id))
+    {
+        Some(order_id) => order_id,
+        None => match place(book) {
+            Ok(order_id) => {
+                if let Some(client_order_id) = client_order_id {
+                    book.record_client_order(owner, client_order_id, order_id);
+                }
+                order_id
+            }
+            Err(err) => return Response::PlaceErr(err.into()),
+        },
+    };
+    // Every order the book handed out an id for has a status
+    Response::PlaceOk(book.execution_report(order_id).unwrap())
+}
+
+fn cancel_order(book: &mut OrderBook, cancel_order_args: CancelOrderArgs) -> Response {
+    match book.cancel_order(cancel_order_args.order_id) {
+        Ok(canceled_quantity) => Response::CancelOk(canceled_quantity),
+        Err(err) => Response::CancelErr(err.into()),
+    }
+}
+
+// Periodically removes good-till-date orders that have expired and uncrosses
+// volatility auctions that have run their course
+pub async fn sweep_expired_orders(book: BookHandle) {
+    let mut interval = tokio::time::interval(EXPIRY_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let now = unix_millis();
+        // Listeners hear of each expired order and uncross trade
+        let swept = book
+            .execute(move |book| {
+                book.expire_orders(now);
+                book.end_volatility_auction(now);
+            })
+            .await;
+        if swept.is_err() {
+            return;
+        }
+    }
+}
+
+// Same as sweep_expired_orders for every book of an exchange
+pub async fn sweep_exchange(exchange: ExchangeHandle) {
+    let mut interval = tokio::time::interval(EXPIRY_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let now = unix_millis();
+        let swept = exchange
+            .execute(move |exchange| {
+                for book in exchange.books_mut() {
+                    book.expire_orders(now);
+                    book.end_volatility_auction(now);
+                }
+            })
+            .await;
+        if swept.is_err() {
+            return;
+        }
+    }
+}
+
+// Runs end of day every day at `time_of_day` milliseconds past midnight UTC
+pub async fn run_end_of_day(book: BookHandle, time_of_day: u64, options: EndOfDayOptions) {
+    loop {
+        let now = unix_millis();
+        let close = next_time_of_day(time_of_day, now);
+        tokio::time::sleep(Duration::from_millis(close - now)).await;
+        let settled = book
+            .execute(move |book| {
+                if let Err(err) = book.end_of_day(unix_millis(), &options) {
+                    book.report_error(err.context("End of day failed"));
+                }
+            })
+            .await;
+        if settled.is_err() {
+            return;
+        }
+    }
+}
+
+// Marks the book from an external price feed every `interval`. A failed
+// poll leaves the last mark in place.
+pub async fn run_price_feed(
+    book: BookHandle,
+    mut feed: Box<dyn PriceFeed>,
+    symbol: String,
+    interval: Duration,
+) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        // Polling blocks on the file or socket
+        let Ok((returned, prices)) = tokio::task::spawn_blocking(move || {
+            let prices = feed.poll();
+            (feed, prices)
+        })
+        .await
+        else {
+            return;
+        };
+        feed = returned;
+        let price = prices.map(|prices| prices.get(&symbol).copied());
+        let marked = book
+            .execute(move |book| match price {
+                Ok(Some(price)) => book.set_mark_price(price),
+                Ok(None) => {}
+                Err(err) => book.report_error(err.context("Polling the price feed failed")),
+            })
+            .await;
+        if marked.is_err() {
+            return;
+        }
+    }
+}
+
+// Keeps the book on its daily trading hours: the opening auction starts at
+// the pre-open and uncrosses at the open, and trading halts at the close
+pub async fn run_trading_hours(book: BookHandle, hours: TradingHours) {
+    // The server's book starts out trading continuously
+    let mut instrument = Instrument::new("book", 1, 1).unwrap();
+    instrument.status = InstrumentStatus::Open;
+    loop {
+        let now = unix_millis();
+        let Ok(followed) = book
+            .execute(move |book| {
+                if let Err(err) = schedule::follow_hours(&mut instrument, book, &hours, now) {
+                    book.report_error(err.context("Failed to follow trading hours"));
+                }
+                instrument
+            })
+            .await
+        else {
+            return;
+        };
+        instrument = followed;
+        let next_boundary = hours.next_boundary(now);
+        tokio::time::sleep(Duration::from_millis(next_boundary - now)).await;
+    }
+}
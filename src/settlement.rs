@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{stats::SessionStats, tape::Trade};
+
+pub const DEFAULT_SETTLEMENT_WINDOW_MS: u64 = 5 * 60 * 1_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct EndOfDayOptions {
+    // Trades within this long before the close make up the settlement VWAP
+    pub settlement_window_ms: u64,
+    // Orders without a good-till-date expiry only live for the day. Off
+    // leaves them resting into the next session.
+    pub expire_day_orders: bool,
+    // Starts the next session straight away: session statistics are reset,
+    // the settlement price becomes the reference price and trading resumes.
+    // Otherwise the book stays halted until trading is resumed.
+    pub roll: bool,
+}
+
+impl Default for EndOfDayOptions {
+    fn default() -> Self {
+        EndOfDayOptions {
+            settlement_window_ms: DEFAULT_SETTLEMENT_WINDOW_MS,
+            expire_day_orders: true,
+            roll: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettlementPriceSource {
+    // Volume weighted average of the trades in the settlement window
+    Vwap,
+    // Nothing traded in the window
+    LastTrade,
+    // Nothing traded all session, so the previous settlement price carries over
+    Previous,
+}
+
+// Net holdings of one participant at the settlement price
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SettlementPosition {
+    pub owner: String,
+    pub position: i64,
+    pub cash: i64,
+    // Position valued at the settlement price, None without one
+    pub mark_value: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SettlementReport {
+    // Unix timestamp in milliseconds of the close
+    pub settled_at: u64,
+    pub settlement_price: Option<u32>,
+    pub price_source: Option<SettlementPriceSource>,
+    // Statistics of the session that closed
+    pub session: SessionStats,
+    pub expired_orders: Vec<Uuid>,
+    // Participants with a non-zero position or cash balance
+    pub positions: Vec<SettlementPosition>,
+    pub rolled: bool,
+}
+
+// Settlement price from the session's trades, falling back to the last
+// trade and then to the previous settlement price
+pub fn settlement_price<'a>(
+    session_trades: impl Iterator<Item = &'a Trade>,
+    window_start: u64,
+    previous: Option<u32>,
+) -> Option<(u32, SettlementPriceSource)> {
+    let mut last_price = None;
+    let mut notional = 0u128;
+    let mut volume = 0u128;
+    for trade in session_trades {
+        last_price = Some(trade.price);
+        if trade.timestamp >= window_start {
+            notional += trade.price as u128 * trade.quantity as u128;
+            volume += trade.quantity as u128;
+        }
+    }
+    // Rounded to the nearest tick
+    if let Some(vwap) = (notional + volume / 2).checked_div(volume) {
+        return Some((vwap as u32, SettlementPriceSource::Vwap));
+    }
+    last_price
+        .map(|price| (price, SettlementPriceSource::LastTrade))
+        .or(previous.map(|price| (price, SettlementPriceSource::Previous)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(timestamp: u64, price: u32, quantity: u64) -> Trade {
+        Trade {
+            seq: 0,
+            timestamp,
+            price,
+            quantity,
+            aggressor: None,
+            maker_order_id: Uuid::new_v4(),
+            maker_owner: "alice".to_string(),
+            taker_order_id: Uuid::new_v4(),
+            taker_owner: "bob".to_string(),
+            fees: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_settlement_price_sources() {
+        let trades = [trade(100, 90, 10), trade(200, 100, 1), trade(300, 103, 2)];
+        // (100 * 1 + 103 * 2) / 3 = 102
+        assert_eq!(
+            settlement_price(trades.iter(), 200, Some(50)),
+            Some((102, SettlementPriceSource::Vwap))
+        );
+        assert_eq!(
+            settlement_price(trades.iter(), 400, Some(50)),
+            Some((103, SettlementPriceSource::LastTrade))
+        );
+        assert_eq!(
+            settlement_price([].iter(), 400, Some(50)),
+            Some((50, SettlementPriceSource::Previous))
+        );
+        assert_eq!(settlement_price([].iter(), 400, None), None);
+    }
+}
@@ -0,0 +1,248 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, time::Duration};
+use uuid::Uuid;
+
+use crate::{
+    book::OrderType,
+    req::{CancelOrderArgs, PlaceOrderArgs, Request},
+    resp::Response,
+    tape::Trade,
+};
+
+// Shape of the synthetic order flow
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FlowConfig {
+    // Orders are spread over this many owners, sim-0 to sim-<n - 1>
+    pub traders: usize,
+    pub mid_price: u32,
+    // Mean distance from the mid of passive orders, in ticks. Distances are
+    // exponentially distributed so most orders sit near the top of the book.
+    pub mean_offset: f64,
+    // Share of orders priced through the mid so they trade on arrival
+    pub aggressive_ratio: f64,
+    // Share of actions that cancel an earlier order instead of placing one
+    pub cancel_ratio: f64,
+    // Quantities are uniform between 1 and this
+    pub max_quantity: u64,
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        FlowConfig {
+            traders: 10,
+            mid_price: 10_000,
+            mean_offset: 5.0,
+            aggressive_ratio: 0.2,
+            cancel_ratio: 0.3,
+            max_quantity: 100,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FlowAction {
+    Place(PlaceOrderArgs),
+    Cancel(CancelOrderArgs),
+}
+
+impl FlowAction {
+    pub fn request(&self) -> Request {
+        match self {
+            FlowAction::Place(args) => Request::PlaceOrder(PlaceOrderArgs {
+                order_type: args.order_type,
+                price: args.price,
+                quantity: args.quantity,
+                owner: args.owner.clone(),
+                expires_at: args.expires_at,
+                client_order_id: None,
+            }),
+            FlowAction::Cancel(args) => Request::CancelOrder(CancelOrderArgs {
+                order_id: args.order_id,
+            }),
+        }
+    }
+}
+
+// Generates the actions of the simulated traders. Orders it is told were
+// placed become candidates for cancels, whether or not they have filled
+// since, just as a trader acting on stale state would.
+pub struct OrderFlow {
+    config: FlowConfig,
+    rng: StdRng,
+    placed: Vec<Uuid>,
+}
+
+impl OrderFlow {
+    // The same seed generates the same flow
+    pub fn new(config: FlowConfig, seed: u64) -> OrderFlow {
+        OrderFlow {
+            config,
+            rng: StdRng::seed_from_u64(seed),
+            placed: Vec::new(),
+        }
+    }
+
+    pub fn next_action(&mut self) -> FlowAction {
+        if !self.placed.is_empty() && self.rng.gen_bool(self.config.cancel_ratio) {
+            let idx = self.rng.gen_range(0..self.placed.len());
+            return FlowAction::Cancel(CancelOrderArgs {
+                order_id: self.placed.swap_remove(idx),
+            });
+        }
+
+        let order_type = if self.rng.gen_bool(0.5) {
+            OrderType::Bid
+        } else {
+            OrderType::Ask
+        };
+        let offset = self.exponential(self.config.mean_offset).round() as u32;
+        // Passive orders sit on their own side of the mid, aggressive ones
+        // on the far side
+        let passive = !self.rng.gen_bool(self.config.aggressive_ratio);
+        let price = match (order_type, passive) {
+            (OrderType::Bid, true) | (OrderType::Ask, false) => {
+                self.config.mid_price.saturating_sub(offset + 1)
+            }
+            (OrderType::Ask, true) | (OrderType::Bid, false) => {
+                self.config.mid_price.saturating_add(offset + 1)
+            }
+        };
+        let trader = self.rng.gen_range(0..self.config.traders.max(1));
+        FlowAction::Place(PlaceOrderArgs {
+            order_type,
+            price: price.max(1),
+            quantity: self.rng.gen_range(1..=self.config.max_quantity.max(1)),
+            owner: format!("sim-{trader}"),
+            expires_at: None,
+            client_order_id: None,
+        })
+    }
+
+    pub fn record_placed(&mut self, order_id: Uuid) {
+        self.placed.push(order_id);
+    }
+
+    // Time until the next arrival of a Poisson process with the given rate
+    pub fn next_delay(&mut self, orders_per_sec: f64) -> Duration {
+        Duration::from_secs_f64(self.exponential(1.0 / orders_per_sec))
+    }
+
+    fn exponential(&mut self, mean: f64) -> f64 {
+        // 1 - U is in (0, 1], so the log is finite
+        -(1.0 - self.rng.gen::<f64>()).ln() * mean
+    }
+}
+
+// What happened to the simulated flow
+#[derive(Debug, Default)]
+pub struct SimulationReport {
+    pub orders_accepted: u64,
+    pub orders_rejected: u64,
+    pub quantity_placed: u64,
+    pub cancels_accepted: u64,
+    // Includes cancels of orders that had already filled
+    pub cancels_rejected: u64,
+    // Requests turned away because the matching queue was full
+    pub overloaded: u64,
+    // Both sides of a trade between two simulated orders count
+    pub quantity_filled: u64,
+    pub trades: u64,
+    placed: HashSet<Uuid>,
+}
+
+impl SimulationReport {
+    pub fn record(&mut self, action: &FlowAction, response: &Response) {
+        match (action, response) {
+            (_, Response::Overloaded) => self.overloaded += 1,
+            (FlowAction::Place(place_order_args), Response::PlaceOk(report)) => {
+                self.orders_accepted += 1;
+                self.quantity_placed += place_order_args.quantity;
+                self.placed.insert(report.order_id);
+            }
+            (FlowAction::Place(_), _) => self.orders_rejected += 1,
+            (FlowAction::Cancel(_), Response::CancelOk(_)) => self.cancels_accepted += 1,
+            (FlowAction::Cancel(_), _) => self.cancels_rejected += 1,
+        }
+    }
+
+    // Only trades involving an order of this simulation are counted
+    pub fn record_trade(&mut self, trade: &Trade) {
+        let sides = [trade.maker_order_id, trade.taker_order_id]
+            .iter()
+            .filter(|order_id| self.placed.contains(order_id))
+            .count() as u64;
+        if sides > 0 {
+            self.trades += 1;
+            self.quantity_filled += sides * trade.quantity;
+        }
+    }
+
+    // Share of the quantity placed that traded
+    pub fn fill_rate(&self) -> f64 {
+        if self.quantity_placed == 0 {
+            return 0.0;
+        }
+        self.quantity_filled as f64 / self.quantity_placed as f64
+    }
+}
+
+// Drives the book through the server's request handling, so needs `net`
+#[cfg(all(test, feature = "net"))]
+mod tests {
+    use super::*;
+    use crate::{book::OrderBook, server::handle_request};
+
+    #[test]
+    fn test_flow_against_book() {
+        let config = FlowConfig {
+            cancel_ratio: 0.25,
+            ..Default::default()
+        };
+        let mut flow = OrderFlow::new(config.clone(), 7);
+        let mut book = OrderBook::new();
+        let mut report = SimulationReport::default();
+        for _ in 0..2_000 {
+            let action = flow.next_action();
+            if let FlowAction::Place(args) = &action {
+                assert!(args.quantity >= 1 && args.quantity <= config.max_quantity);
+                assert_ne!(args.price, config.mid_price);
+            }
+            let response = handle_request(&mut book, action.request());
+            if let Response::PlaceOk(report) = response {
+                flow.record_placed(report.order_id);
+            }
+            report.record(&action, &response);
+        }
+        for trade in book.get_trades(1, usize::MAX).unwrap() {
+            report.record_trade(&trade);
+        }
+
+        assert_eq!(report.orders_rejected, 0);
+        assert!(report.cancels_accepted > 0);
+        assert!(report.trades > 0);
+        assert_eq!(report.trades, book.session_stats().trade_count());
+        assert!(report.fill_rate() > 0.0 && report.fill_rate() <= 1.0);
+        // The book never ends up crossed
+        let (bid, ask) = (book.best_bid().unwrap(), book.best_ask().unwrap());
+        assert!(bid.price() < ask.price());
+    }
+
+    #[test]
+    fn test_same_seed_same_flow() {
+        let mut first = OrderFlow::new(FlowConfig::default(), 1);
+        let mut second = OrderFlow::new(FlowConfig::default(), 1);
+        for _ in 0..100 {
+            let (FlowAction::Place(a), FlowAction::Place(b)) =
+                (first.next_action(), second.next_action())
+            else {
+                panic!("Nothing was placed, so nothing can be canceled");
+            };
+            assert_eq!(
+                (a.price, a.quantity, a.owner),
+                (b.price, b.quantity, b.owner)
+            );
+        }
+        assert!(first.next_delay(1_000.0) < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    book::{ExecutionReport, L2Book, MarketProtection, OrderBook, OrderType},
+    error::OrderBookError,
+};
+
+// Smart order routing: one order split across books quoting the same
+// instrument, e.g. on different venues or instances, by the liquidity each
+// displays. Levels are taken best price first across every book, the larger
+// one first at equal prices, and each book is sent an order for what was
+// taken from it that trades no further than the worst price taken there.
+// Those orders never rest, so liquidity that moved between the snapshot and
+// the order leaves part of the parent order unfilled rather than resting on
+// a book it wasn't meant for.
+
+// Order to split, trading no further than its limit price
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParentOrder {
+    pub owner: String,
+    pub order_type: OrderType,
+    pub limit_price: u32,
+    pub quantity: u64,
+}
+
+// Part of the parent order sent to one book
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChildOrder {
+    // Index of the book among those routed across
+    pub venue: usize,
+    pub quantity: u64,
+    // Worst price taken from the book
+    pub price: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChildExecution {
+    pub child: ChildOrder,
+    pub outcome: Result<ExecutionReport, OrderBookError>,
+}
+
+// Execution reports of every child order, added up
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoutedExecution {
+    pub children: Vec<ChildExecution>,
+    pub filled_quantity: u64,
+    // Parent quantity no book filled, whether or not it was routed
+    pub unfilled_quantity: u64,
+}
+
+// Splits the order across the books' displayed liquidity, one child order
+// per book it takes from, in the books' order
+pub fn plan_route(order: &ParentOrder, books: &[L2Book]) -> Vec<ChildOrder> {
+    // (price, displayed quantity, book) of every level the order may take
+    let mut levels: Vec<(u32, u64, usize)> = Vec::new();
+    for (venue, l2_book) in books.iter().enumerate() {
+        let opposite = match order.order_type {
+            OrderType::Bid => &l2_book.ask,
+            OrderType::Ask => &l2_book.bid,
+        };
+        levels.extend(
+            opposite
+                .iter()
+                .filter(|entry| match order.order_type {
+                    OrderType::Bid => entry.price <= order.limit_price,
+                    OrderType::Ask => entry.price >= order.limit_price,
+                })
+                .map(|entry| (entry.price, entry.total_quantity, venue)),
+        );
+    }
+    levels.sort_by(|a, b| {
+        let by_price = match order.order_type {
+            OrderType::Bid => a.0.cmp(&b.0),
+            OrderType::Ask => b.0.cmp(&a.0),
+        };
+        by_price.then(b.1.cmp(&a.1)).then(a.2.cmp(&b.2))
+    });
+
+    let mut children: Vec<ChildOrder> = Vec::new();
+    let mut remaining = order.quantity;
+    for (price, quantity, venue) in levels {
+        if remaining == 0 {
+            break;
+        }
+        let taken = quantity.min(remaining);
+        remaining -= taken;
+        // Levels come best first, so a later one is the worst taken so far
+        match children.iter_mut().find(|child| child.venue == venue) {
+            Some(child) => {
+                child.quantity += taken;
+                child.price = price;
+            }
+            None => children.push(ChildOrder {
+                venue,
+                quantity: taken,
+                price,
+            }),
+        }
+    }
+    children.sort_by_key(|child| child.venue);
+    children
+}
+
+// Routes the order across the books as planned from their current depth
+pub fn route_order(order: &ParentOrder, books: &mut [&mut OrderBook]) -> RoutedExecution {
+    let depth: Vec<L2Book> = books.iter().map(|book| book.view_book_l2()).collect();
+    let mut filled_quantity = 0;
+    let children: Vec<ChildExecution> = plan_route(order, &depth)
+        .into_iter()
+        .map(|child| {
+            let book = &mut books[child.venue];
+            let outcome = book
+                .place_market_order(
+                    &order.owner,
+                    child.quantity,
+                    order.order_type,
+                    MarketProtection::Price(child.price),
+                )
+                // Every order the book handed out an id for has a status
+                .map(|order_id| book.execution_report(order_id).unwrap());
+            if let Ok(report) = &outcome {
+                filled_quantity += report.filled_quantity;
+            }
+            ChildExecution { child, outcome }
+        })
+        .collect();
+    RoutedExecution {
+        children,
+        filled_quantity,
+        unfilled_quantity: order.quantity - filled_quantity,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::OrderStatus;
+
+    fn book_with_asks(asks: &[(u32, u64)]) -> OrderBook {
+        let mut book = OrderBook::new();
+        for &(price, quantity) in asks {
+            book.place_order("maker", price, quantity, OrderType::Ask)
+                .unwrap();
+        }
+        book
+    }
+
+    #[test]
+    fn test_order_split_by_price_then_displayed_size() {
+        let mut venue_a = book_with_asks(&[(100, 5), (102, 10)]);
+        let mut venue_b = book_with_asks(&[(101, 4), (102, 20)]);
+        let mut venue_c = book_with_asks(&[(104, 50)]);
+        let order = ParentOrder {
+            owner: "alice".to_string(),
+            order_type: OrderType::Bid,
+            limit_price: 103,
+            quantity: 30,
+        };
+
+        // 100 on A, 101 on B, then the larger 102 level on B before A's
+        let depth = [&venue_a, &venue_b, &venue_c].map(|book| book.view_book_l2());
+        assert_eq!(
+            plan_route(&order, &depth),
+            vec![
+                ChildOrder {
+                    venue: 0,
+                    quantity: 6,
+                    price: 102,
+                },
+                ChildOrder {
+                    venue: 1,
+                    quantity: 24,
+                    price: 102,
+                },
+            ]
+        );
+
+        let routed = route_order(&order, &mut [&mut venue_a, &mut venue_b, &mut venue_c]);
+        assert_eq!(routed.filled_quantity, 30);
+        assert_eq!(routed.unfilled_quantity, 0);
+        assert!(routed.children.iter().all(|child| matches!(
+            child.outcome,
+            Ok(ExecutionReport {
+                status: OrderStatus::Filled,
+                ..
+            })
+        )));
+        assert_eq!(venue_a.view_book_l1().ask.unwrap().total_quantity, 9);
+        assert_eq!(venue_c.view_book_l1().ask.unwrap().total_quantity, 50);
+
+        // Only what sits within the limit is routed
+        let routed = route_order(&order, &mut [&mut venue_a, &mut venue_b, &mut venue_c]);
+        assert_eq!(routed.children.len(), 1);
+        assert_eq!(routed.filled_quantity, 9);
+        assert_eq!(routed.unfilled_quantity, 21);
+    }
+}
@@ -0,0 +1,419 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use uuid::Uuid;
+
+use crate::{
+    book::{L2Book, L2Entry, MarketProtection, OrderBook, OrderType},
+    error::OrderBookError,
+};
+
+// Synthetic instruments trading a linear combination of two books, e.g. a
+// calendar spread buying the front month and selling the back month. One
+// unit of the spread is `ratio` of the first leg bought and `ratio` of the
+// second sold, priced at the first leg's price times its ratio less the
+// second's, which may be negative. Selling the spread does the opposite.
+//
+// Spread orders rest in their own book and trade by legging into both books
+// at once, as soon as the legs' best prices make the spread's price or
+// better. Until then each one is implied out into either leg against the
+// other leg's best price, and shown in the leg's L2 as its implied bid or
+// ask. An outright order that trades at the implied price executes the
+// spread the next time the legs are matched, see SpreadBook::match_legs.
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpreadLeg {
+    pub symbol: String,
+    // Units of the leg in one unit of the spread
+    pub ratio: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpreadDefinition {
+    pub symbol: String,
+    // Bought, then sold, by a spread bid
+    pub legs: [SpreadLeg; 2],
+}
+
+#[derive(Debug, Clone)]
+struct SpreadOrder {
+    order_id: Uuid,
+    owner: String,
+    // Units of the spread still resting
+    quantity: u64,
+    timestamp: u64,
+}
+
+// Units of a spread order traded across both legs at once
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpreadFill {
+    pub order_id: Uuid,
+    pub owner: String,
+    pub side: OrderType,
+    pub units: u64,
+    pub price: i64,
+    pub leg_prices: [u32; 2],
+    // Quantity each leg filled. Short of units times the ratio on the
+    // second leg when it couldn't be completed, leaving the owner legged.
+    pub leg_quantities: [u64; 2],
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpreadLevel {
+    pub price: i64,
+    pub quantity: u64,
+    pub num_orders: usize,
+}
+
+// Resting spread orders, both sides in ascending price order like L2Book
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpreadDepth {
+    pub bid: Vec<SpreadLevel>,
+    pub ask: Vec<SpreadLevel>,
+}
+
+pub struct SpreadBook {
+    definition: SpreadDefinition,
+    bids: BTreeMap<i64, VecDeque<SpreadOrder>>,
+    asks: BTreeMap<i64, VecDeque<SpreadOrder>>,
+    fills: Vec<SpreadFill>,
+}
+
+impl SpreadBook {
+    pub fn new(definition: SpreadDefinition) -> Result<SpreadBook> {
+        if definition.legs.iter().any(|leg| leg.ratio == 0) {
+            return Err(anyhow!(
+                "Legs of spread {} need a ratio above zero",
+                definition.symbol
+            ));
+        }
+        Ok(SpreadBook {
+            definition,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            fills: Vec::new(),
+        })
+    }
+
+    pub fn definition(&self) -> &SpreadDefinition {
+        &self.definition
+    }
+
+    // Rests the order and trades what the legs allow of it straight away.
+    // `legs` are the books of the definition's legs, in its order.
+    pub fn place_order(
+        &mut self,
+        legs: [&mut OrderBook; 2],
+        owner: &str,
+        side: OrderType,
+        price: i64,
+        quantity: u64,
+    ) -> Result<Uuid, OrderBookError> {
+        if quantity == 0 {
+            return Err(OrderBookError::InvalidQuantity);
+        }
+        let order_id = Uuid::new_v4();
+        self.side_mut(side)
+            .entry(price)
+            .or_default()
+            .push_back(SpreadOrder {
+                order_id,
+                owner: owner.to_string(),
+                quantity,
+                timestamp: legs[0].now(),
+            });
+        self.match_legs(legs);
+        Ok(order_id)
+    }
+
+    // Returns the units that were still resting
+    pub fn cancel_order(&mut self, order_id: Uuid) -> Result<u64, OrderBookError> {
+        for side in [&mut self.bids, &mut self.asks] {
+            let found = side.iter_mut().find_map(|(&price, level)| {
+                let position = level.iter().position(|order| order.order_id == order_id)?;
+                Some((price, level.remove(position).unwrap()))
+            });
+            if let Some((price, order)) = found {
+                if side[&price].is_empty() {
+                    side.remove(&price);
+                }
+                return Ok(order.quantity);
+            }
+        }
+        Err(OrderBookError::UnknownOrder)
+    }
+
+    // Units of the order still resting, None once it's filled or canceled
+    pub fn resting_quantity(&self, order_id: Uuid) -> Option<u64> {
+        self.bids
+            .values()
+            .chain(self.asks.values())
+            .flatten()
+            .find(|order| order.order_id == order_id)
+            .map(|order| order.quantity)
+    }
+
+    // Trades every spread order the legs' best prices now allow, best
+    // spread price first. Call after the legs' books change.
+    pub fn match_legs(&mut self, mut legs: [&mut OrderBook; 2]) -> Vec<SpreadFill> {
+        let mut fills = Vec::new();
+        while let Some(fill) = self
+            .execute_best(OrderType::Bid, &mut legs)
+            .or_else(|| self.execute_best(OrderType::Ask, &mut legs))
+        {
+            fills.push(fill);
+        }
+        self.fills.extend(fills.iter().cloned());
+        fills
+    }
+
+    // Every fill of the book's spread orders, oldest first
+    pub fn fills(&self) -> &[SpreadFill] {
+        &self.fills
+    }
+
+    pub fn view_depth(&self) -> SpreadDepth {
+        let levels = |side: &BTreeMap<i64, VecDeque<SpreadOrder>>| {
+            side.iter()
+                .map(|(&price, level)| SpreadLevel {
+                    price,
+                    quantity: level.iter().map(|order| order.quantity).sum(),
+                    num_orders: level.len(),
+                })
+                .collect()
+        };
+        SpreadDepth {
+            bid: levels(&self.bids),
+            ask: levels(&self.asks),
+        }
+    }
+
+    // L2 of one leg along with the best bid and ask the spread orders imply
+    // into it against the other leg
+    pub fn view_leg_l2(&self, leg: usize, legs: [&OrderBook; 2]) -> L2Book {
+        let mut l2_book = legs[leg].view_book_l2();
+        l2_book.implied_bid = self.implied(leg, OrderType::Bid, legs);
+        l2_book.implied_ask = self.implied(leg, OrderType::Ask, legs);
+        l2_book
+    }
+
+    // Best spread order on `side` the legs could fill, implied into `leg`
+    // as an order on `leg_side` against the other leg's best price
+    fn implied(&self, leg: usize, leg_side: OrderType, legs: [&OrderBook; 2]) -> Option<L2Entry> {
+        let [ratio_0, ratio_1] = self.ratios();
+        let ratio = [ratio_0, ratio_1][leg];
+        let other_ratio = [ratio_0, ratio_1][1 - leg];
+        // A spread bid buys the first leg and sells the second
+        let side = match (leg, leg_side) {
+            (0, side) => side,
+            (_, OrderType::Bid) => OrderType::Ask,
+            (_, OrderType::Ask) => OrderType::Bid,
+        };
+        let (&spread_price, level) = self.best(side)?;
+        // The other leg always trades on the opposite side to this one, so
+        // against the other book's orders on this side
+        let other = best_level(legs[1 - leg], leg_side)?;
+
+        // Solves spread = ratio_0 * price_0 - ratio_1 * price_1 for this leg's
+        // price, rounded so the spread's price is kept
+        let numerator = if leg == 0 {
+            spread_price as i128 + other_ratio as i128 * other.0 as i128
+        } else {
+            other_ratio as i128 * other.0 as i128 - spread_price as i128
+        };
+        let price = match leg_side {
+            OrderType::Bid => numerator.div_euclid(ratio as i128),
+            OrderType::Ask => -(-numerator).div_euclid(ratio as i128),
+        };
+        let price = u32::try_from(price).ok().filter(|&price| price > 0)?;
+
+        let units: u64 = level.iter().map(|order| order.quantity).sum();
+        let units = units.min(other.1 / other_ratio);
+        if units == 0 {
+            return None;
+        }
+        let order_quantities = level.iter().map(|order| order.quantity.min(units) * ratio);
+        Some(L2Entry {
+            price,
+            total_quantity: units * ratio,
+            num_orders: level.len(),
+            min_order_quantity: order_quantities.clone().min().unwrap_or(0),
+            max_order_quantity: order_quantities.max().unwrap_or(0),
+            oldest_order_timestamp: level.front().map_or(0, |order| order.timestamp),
+        })
+    }
+
+    // Legs into both books for the first order at the best spread price on
+    // `side`, if their best prices make its price
+    fn execute_best(
+        &mut self,
+        side: OrderType,
+        legs: &mut [&mut OrderBook; 2],
+    ) -> Option<SpreadFill> {
+        let [ratio_0, ratio_1] = self.ratios();
+        let (spread_price, order) = self
+            .best(side)
+            .map(|(&price, level)| (price, level.front().unwrap().clone()))?;
+        // A spread bid buys the first leg off its asks and sells the second
+        // into its bids
+        let leg_sides = match side {
+            OrderType::Bid => [OrderType::Bid, OrderType::Ask],
+            OrderType::Ask => [OrderType::Ask, OrderType::Bid],
+        };
+        let (price_0, quantity_0) = best_level(legs[0], opposite(leg_sides[0]))?;
+        let (price_1, quantity_1) = best_level(legs[1], opposite(leg_sides[1]))?;
+        let legs_price = ratio_0 as i64 * price_0 as i64 - ratio_1 as i64 * price_1 as i64;
+        let crosses = match side {
+            OrderType::Bid => legs_price <= spread_price,
+            OrderType::Ask => legs_price >= spread_price,
+        };
+        let units = order
+            .quantity
+            .min(quantity_0 / ratio_0)
+            .min(quantity_1 / ratio_1);
+        if !crosses || units == 0 {
+            return None;
+        }
+
+        // The first leg is traded first and the second sized by what it
+        // filled, so a first leg that falls short leaves nothing unhedged
+        let leg_fill = |book: &mut OrderBook, quantity, leg_side, price| {
+            book.place_market_order(
+                &order.owner,
+                quantity,
+                leg_side,
+                MarketProtection::Price(price),
+            )
+            .ok()
+            .and_then(|order_id| book.execution_report(order_id))
+            .map_or(0, |report| report.filled_quantity)
+        };
+        let filled_0 = leg_fill(legs[0], units * ratio_0, leg_sides[0], price_0);
+        let units = filled_0 / ratio_0;
+        if units == 0 {
+            return None;
+        }
+        let filled_1 = leg_fill(legs[1], units * ratio_1, leg_sides[1], price_1);
+
+        let level = self.side_mut(side).get_mut(&spread_price).unwrap();
+        let front = level.front_mut().unwrap();
+        front.quantity -= units;
+        if front.quantity == 0 {
+            level.pop_front();
+            if level.is_empty() {
+                self.side_mut(side).remove(&spread_price);
+            }
+        }
+        Some(SpreadFill {
+            order_id: order.order_id,
+            owner: order.owner,
+            side,
+            units,
+            price: legs_price,
+            leg_prices: [price_0, price_1],
+            leg_quantities: [filled_0, filled_1],
+        })
+    }
+
+    fn ratios(&self) -> [u64; 2] {
+        [self.definition.legs[0].ratio, self.definition.legs[1].ratio]
+    }
+
+    fn best(&self, side: OrderType) -> Option<(&i64, &VecDeque<SpreadOrder>)> {
+        match side {
+            OrderType::Bid => self.bids.last_key_value(),
+            OrderType::Ask => self.asks.first_key_value(),
+        }
+    }
+
+    fn side_mut(&mut self, side: OrderType) -> &mut BTreeMap<i64, VecDeque<SpreadOrder>> {
+        match side {
+            OrderType::Bid => &mut self.bids,
+            OrderType::Ask => &mut self.asks,
+        }
+    }
+}
+
+fn opposite(side: OrderType) -> OrderType {
+    match side {
+        OrderType::Bid => OrderType::Ask,
+        OrderType::Ask => OrderType::Bid,
+    }
+}
+
+// Price and quantity of the best level on one side of a book
+fn best_level(book: &OrderBook, side: OrderType) -> Option<(u32, u64)> {
+    let level = match side {
+        OrderType::Bid => book.best_bid(),
+        OrderType::Ask => book.best_ask(),
+    }?;
+    Some((level.price(), level.total_quantity()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calendar_spread() -> SpreadBook {
+        let leg = |symbol: &str| SpreadLeg {
+            symbol: symbol.to_string(),
+            ratio: 1,
+        };
+        SpreadBook::new(SpreadDefinition {
+            symbol: "FRONT-BACK".to_string(),
+            legs: [leg("FRONT"), leg("BACK")],
+        })
+        .unwrap()
+    }
+
+    fn quoted(bid: u32, ask: u32) -> OrderBook {
+        let mut book = OrderBook::new();
+        book.place_order("maker", bid, 10, OrderType::Bid).unwrap();
+        book.place_order("maker", ask, 10, OrderType::Ask).unwrap();
+        book
+    }
+
+    #[test]
+    fn test_spread_orders_implied_into_legs_and_legged() {
+        let mut spread = calendar_spread();
+        let mut front = quoted(97, 101);
+        let mut back = quoted(94, 96);
+
+        // Buying the spread costs 101 - 94 = 7 now, so a bid at 5 rests
+        let bid = spread
+            .place_order([&mut front, &mut back], "alice", OrderType::Bid, 5, 4)
+            .unwrap();
+        assert_eq!(spread.resting_quantity(bid), Some(4));
+        let front_l2 = spread.view_leg_l2(0, [&front, &back]);
+        let implied_bid = front_l2.implied_bid.unwrap();
+        assert_eq!((implied_bid.price, implied_bid.total_quantity), (99, 4));
+        let back_l2 = spread.view_leg_l2(1, [&front, &back]);
+        assert_eq!(back_l2.implied_ask.unwrap().price, 96);
+        assert_eq!(back_l2.implied_bid, None);
+
+        // Outright seller at the implied bid executes the spread
+        front.place_order("bob", 99, 3, OrderType::Ask).unwrap();
+        let fills = spread.match_legs([&mut front, &mut back]);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].units, 3);
+        assert_eq!(fills[0].price, 5);
+        assert_eq!(fills[0].leg_prices, [99, 94]);
+        assert_eq!(fills[0].leg_quantities, [3, 3]);
+        assert_eq!(spread.resting_quantity(bid), Some(1));
+        assert_eq!(back.best_bid().unwrap().total_quantity(), 7);
+
+        // Selling the spread makes 97 - 96 = 1, so an ask at a discount
+        // trades at once
+        let ask = spread
+            .place_order([&mut front, &mut back], "carol", OrderType::Ask, -2, 2)
+            .unwrap();
+        assert_eq!(spread.resting_quantity(ask), None);
+        assert_eq!(spread.fills().len(), 2);
+        assert_eq!(spread.fills()[1].price, 1);
+
+        assert_eq!(spread.cancel_order(bid), Ok(1));
+        assert_eq!(spread.cancel_order(bid), Err(OrderBookError::UnknownOrder));
+        assert!(spread.view_depth().bid.is_empty());
+        assert_eq!(spread.view_leg_l2(0, [&front, &back]).implied_bid, None);
+    }
+}
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SessionStats {
+    open: Option<u32>,
+    high: Option<u32>,
+    low: Option<u32>,
+    last: Option<u32>,
+    volume: u64,
+    trade_count: u64,
+}
+
+impl SessionStats {
+    pub fn new() -> SessionStats {
+        SessionStats::default()
+    }
+
+    pub fn open(&self) -> Option<u32> {
+        self.open
+    }
+
+    pub fn high(&self) -> Option<u32> {
+        self.high
+    }
+
+    pub fn low(&self) -> Option<u32> {
+        self.low
+    }
+
+    pub fn last(&self) -> Option<u32> {
+        self.last
+    }
+
+    pub fn volume(&self) -> u64 {
+        self.volume
+    }
+
+    pub fn trade_count(&self) -> u64 {
+        self.trade_count
+    }
+
+    pub fn record_trade(&mut self, price: u32, quantity: u64) {
+        // First trade of the session sets the opening price
+        if self.open.is_none() {
+            self.open = Some(price);
+        }
+        self.high = Some(self.high.map_or(price, |high| high.max(price)));
+        self.low = Some(self.low.map_or(price, |low| low.min(price)));
+        self.last = Some(price);
+        self.volume = self.volume.saturating_add(quantity);
+        self.trade_count += 1;
+    }
+
+    pub fn reset(&mut self) {
+        *self = SessionStats::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_session() {
+        let stats = SessionStats::new();
+        assert_eq!(stats.open(), None);
+        assert_eq!(stats.high(), None);
+        assert_eq!(stats.low(), None);
+        assert_eq!(stats.last(), None);
+        assert_eq!(stats.volume(), 0);
+        assert_eq!(stats.trade_count(), 0);
+    }
+
+    #[test]
+    fn test_record_trades() {
+        let mut stats = SessionStats::new();
+        stats.record_trade(100, 5);
+        stats.record_trade(120, 3);
+        stats.record_trade(90, 2);
+        stats.record_trade(110, 10);
+
+        assert_eq!(stats.open(), Some(100));
+        assert_eq!(stats.high(), Some(120));
+        assert_eq!(stats.low(), Some(90));
+        assert_eq!(stats.last(), Some(110));
+        assert_eq!(stats.volume(), 20);
+        assert_eq!(stats.trade_count(), 4);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut stats = SessionStats::new();
+        stats.record_trade(100, 5);
+        stats.reset();
+        assert_eq!(stats, SessionStats::new());
+    }
+}
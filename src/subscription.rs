@@ -0,0 +1,374 @@
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use tokio::sync::watch;
+
+use crate::{
+    book::OrderBook,
+    config::BackpressurePolicy,
+    engine::BookHandle,
+    req::{Channel, SubscribeArgs},
+    resp::{MarketData, MarketDataUpdate, Response},
+};
+
+// Market data subscriptions of one connection. Rather than a delta per
+// event, a subscriber is pushed the latest state of each channel once the
+// book has changed: the top of book, the levels to its depth, or the trades
+// since its last update. Changes published while the connection is busy
+// writing are coalesced into one update, and by default so are updates still
+// waiting to be written, so a slow consumer falls behind by at most one
+// update per subscription instead of queueing every delta. See Outbox for
+// the other policies.
+
+// Most subscriptions one connection can hold
+pub const MAX_SUBSCRIPTIONS: usize = 64;
+// Most trades one update carries. A subscriber further behind is sent the
+// latest ones and can fetch the gap with Request::GetTrades.
+pub const MAX_TRADES_PER_UPDATE: usize = 1_000;
+// Most fills and other pushed responses queued for a connection
+pub const DEFAULT_OUTBOX_LIMIT: usize = 1_024;
+
+// Connection's side of its subscriptions to one book
+pub(crate) struct MarketDataStream {
+    book: BookHandle,
+    published: watch::Receiver<u64>,
+    subscriptions: BTreeMap<(Option<String>, Channel), SubscribeArgs>,
+    // First trade the trade subscriptions haven't been sent yet
+    next_trade_seq: u64,
+    // A subscription was added, so its first update is due straight away
+    pending: bool,
+}
+
+impl MarketDataStream {
+    pub(crate) async fn new(book: &BookHandle) -> Result<MarketDataStream> {
+        let next_trade_seq = book.execute(|book| book.next_trade_seq()).await?;
+        Ok(MarketDataStream {
+            book: book.clone(),
+            published: book.published(),
+            subscriptions: BTreeMap::new(),
+            next_trade_seq,
+            pending: false,
+        })
+    }
+
+    // Replaces any subscription to the same symbol and channel
+    pub(crate) fn subscribe(&mut self, subscription: SubscribeArgs) -> Result<()> {
+        let key = (subscription.symbol.clone(), subscription.channel);
+        if !self.subscriptions.contains_key(&key) && self.subscriptions.len() == MAX_SUBSCRIPTIONS {
+            return Err(anyhow!(
+                "At most {MAX_SUBSCRIPTIONS} subscriptions per connection"
+            ));
+        }
+        self.subscriptions.insert(key, subscription);
+        self.pending = true;
+        Ok(())
+    }
+
+    // Whether there was such a subscription
+    pub(crate) fn unsubscribe(&mut self, symbol: Option<String>, channel: Channel) -> bool {
+        self.subscriptions.remove(&(symbol, channel)).is_some()
+    }
+
+    pub(crate) fn subscriptions(&self) -> Vec<SubscribeArgs> {
+        self.subscriptions.values().cloned().collect()
+    }
+
+    // Waits for the book to change, then returns the latest state of every
+    // subscription. Safe to drop part way, nothing is lost.
+    pub(crate) async fn next(&mut self) -> Result<Vec<MarketDataUpdate>> {
+        if self.subscriptions.is_empty() {
+            std::future::pending::<()>().await;
+        }
+        if !self.pending {
+            self.published
+                .changed()
+                .await
+                .map_err(|_| anyhow!("Matching task has stopped"))?;
+            // Kept until the updates are taken, in case this is dropped
+            self.pending = true;
+        }
+        self.published.borrow_and_update();
+        let subscriptions = self.subscriptions();
+        let from_seq = self.next_trade_seq;
+        let (updates, next_trade_seq) = self
+            .book
+            .execute(move |book| market_data(book, &subscriptions, from_seq))
+            .await?;
+        self.pending = false;
+        self.next_trade_seq = next_trade_seq;
+        Ok(updates)
+    }
+}
+
+// Updates for the subscriptions, and the first trade they don't include
+fn market_data(
+    book: &OrderBook,
+    subscriptions: &[SubscribeArgs],
+    from_seq: u64,
+) -> (Vec<MarketDataUpdate>, u64) {
+    let next_trade_seq = book.next_trade_seq();
+    let from_seq = from_seq.max(next_trade_seq.saturating_sub(MAX_TRADES_PER_UPDATE as u64));
+    let trades = if from_seq < next_trade_seq {
+        book.get_trades(from_seq, MAX_TRADES_PER_UPDATE)
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    let updates = subscriptions
+        .iter()
+        .filter_map(|subscription| {
+            let data = match subscription.channel {
+                Channel::L1 => MarketData::L1(book.view_book_l1()),
+                Channel::L2 => MarketData::L2(match subscription.depth {
+                    Some(depth) => book.view_book_l2_depth(depth),
+                    None => book.view_book_l2(),
+                }),
+                // Nothing traded since the last update
+                Channel::Trades if trades.is_empty() => return None,
+                Channel::Trades => MarketData::Trades(trades.clone()),
+            };
+            Some(MarketDataUpdate {
+                symbol: subscription.symbol.clone(),
+                data,
+                gap: false,
+            })
+        })
+        .collect();
+    (updates, next_trade_seq)
+}
+
+// Responses pushed to a connection waiting to be written. Fills and other
+// pushed responses queue up to the limit, after which the connection stops
+// taking them until it catches up. Market data is bounded by the policy:
+// conflated, an update replaces the one of the same subscription still
+// waiting, trades being added to it instead, so there's at most one per
+// subscription; otherwise updates queue up to the limit too, and one that
+// doesn't fit is dropped or closes the connection.
+pub(crate) struct Outbox {
+    queue: VecDeque<Response>,
+    limit: usize,
+    policy: BackpressurePolicy,
+    // Subscriptions whose updates were dropped since their last queued one
+    gaps: BTreeSet<(Option<String>, Channel)>,
+}
+
+impl Outbox {
+    pub(crate) fn new(limit: usize, policy: BackpressurePolicy) -> Outbox {
+        Outbox {
+            queue: VecDeque::new(),
+            limit,
+            policy,
+            gaps: BTreeSet::new(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    // Takes no more pushed responses other than market data
+    pub(crate) fn is_full(&self) -> bool {
+        self.queue.len() >= self.limit
+    }
+
+    pub(crate) fn push(&mut self, response: Response) {
+        self.queue.push_back(response);
+    }
+
+    // Whether the connection kept up, false once it's to be closed
+    pub(crate) fn push_market_data(&mut self, mut update: MarketDataUpdate) -> bool {
+        match self.policy {
+            BackpressurePolicy::Conflate => self.conflate(update),
+            BackpressurePolicy::DropAndMarkGap => {
+                let key = (update.symbol.clone(), update.channel());
+                if self.is_full() {
+                    self.gaps.insert(key);
+                } else {
+                    update.gap = self.gaps.remove(&key);
+                    self.queue.push_back(Response::MarketData(update));
+                }
+            }
+            BackpressurePolicy::Disconnect => {
+                if self.is_full() {
+                    return false;
+                }
+                self.queue.push_back(Response::MarketData(update));
+            }
+        }
+        true
+    }
+
+    fn conflate(&mut self, update: MarketDataUpdate) {
+        let waiting = self.queue.iter_mut().find_map(|response| match response {
+            Response::MarketData(waiting)
+                if waiting.symbol == update.symbol && waiting.channel() == update.channel() =>
+            {
+                Some(waiting)
+            }
+            _ => None,
+        });
+        let Some(waiting) = waiting else {
+            self.queue.push_back(Response::MarketData(update));
+            return;
+        };
+        match (&mut waiting.data, update.data) {
+            (MarketData::Trades(trades), MarketData::Trades(newer)) => {
+                trades.extend(newer);
+                let excess = trades.len().saturating_sub(MAX_TRADES_PER_UPDATE);
+                trades.drain(..excess);
+            }
+            (data, newer) => *data = newer,
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Response> {
+        self.queue.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{book::OrderType, tape::Trade};
+
+    fn trade(seq: u64) -> Trade {
+        Trade {
+            seq,
+            timestamp: 0,
+            price: 100,
+            quantity: 1,
+            aggressor: None,
+            maker_order_id: Default::default(),
+            maker_owner: "alice".to_string(),
+            taker_order_id: Default::default(),
+            taker_owner: "bob".to_string(),
+            fees: Default::default(),
+        }
+    }
+
+    fn l1(book: &OrderBook) -> MarketDataUpdate {
+        MarketDataUpdate {
+            symbol: None,
+            data: MarketData::L1(book.view_book_l1()),
+            gap: false,
+        }
+    }
+
+    fn trades(seq: u64) -> MarketDataUpdate {
+        MarketDataUpdate {
+            symbol: None,
+            data: MarketData::Trades(vec![trade(seq)]),
+            gap: false,
+        }
+    }
+
+    #[test]
+    fn test_outbox_conflates_market_data() {
+        let mut book = OrderBook::new();
+        let mut outbox = Outbox::new(1, BackpressurePolicy::Conflate);
+        assert!(outbox.push_market_data(l1(&book)));
+        book.place_order("alice", 101, 5, OrderType::Ask).unwrap();
+        assert!(outbox.push_market_data(l1(&book)));
+        for seq in [1, 2] {
+            assert!(outbox.push_market_data(trades(seq)));
+        }
+        assert!(outbox.is_full());
+
+        // Only the latest top of book is left
+        match outbox.pop() {
+            Some(Response::MarketData(MarketDataUpdate {
+                data: MarketData::L1(l1_book),
+                ..
+            })) => assert_eq!(l1_book.ask.unwrap().price, 101),
+            response => panic!("Unexpected {response:?}"),
+        }
+        match outbox.pop() {
+            Some(Response::MarketData(MarketDataUpdate {
+                data: MarketData::Trades(trades),
+                ..
+            })) => assert_eq!(trades.len(), 2),
+            response => panic!("Unexpected {response:?}"),
+        }
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn test_outbox_drops_or_disconnects_when_full() {
+        let book = OrderBook::new();
+        let mut outbox = Outbox::new(2, BackpressurePolicy::DropAndMarkGap);
+        for seq in 1..=3 {
+            assert!(outbox.push_market_data(trades(seq)));
+        }
+        // Queued rather than merged, and the third trade dropped
+        for seq in [1, 2] {
+            match outbox.pop() {
+                Some(Response::MarketData(MarketDataUpdate {
+                    data: MarketData::Trades(trades),
+                    gap: false,
+                    ..
+                })) => assert_eq!(trades[0].seq, seq),
+                response => panic!("Unexpected {response:?}"),
+            }
+        }
+        // Only the subscription that lost an update is marked
+        assert!(outbox.push_market_data(l1(&book)));
+        assert!(outbox.push_market_data(trades(4)));
+        assert!(matches!(
+            outbox.pop(),
+            Some(Response::MarketData(MarketDataUpdate { gap: false, .. }))
+        ));
+        match outbox.pop() {
+            Some(Response::MarketData(MarketDataUpdate {
+                data: MarketData::Trades(trades),
+                gap: true,
+                ..
+            })) => assert_eq!(trades[0].seq, 4),
+            response => panic!("Unexpected {response:?}"),
+        }
+
+        let mut outbox = Outbox::new(1, BackpressurePolicy::Disconnect);
+        assert!(outbox.push_market_data(l1(&book)));
+        assert!(!outbox.push_market_data(l1(&book)));
+    }
+
+    #[tokio::test]
+    async fn test_stream_coalesces_changes() {
+        let book = BookHandle::spawn(OrderBook::new(), 8);
+        let mut stream = MarketDataStream::new(&book).await.unwrap();
+        stream
+            .subscribe(SubscribeArgs {
+                symbol: Some("ACME".to_string()),
+                channel: Channel::L2,
+                depth: Some(1),
+            })
+            .unwrap();
+        stream
+            .subscribe(SubscribeArgs {
+                symbol: Some("ACME".to_string()),
+                channel: Channel::Trades,
+                depth: None,
+            })
+            .unwrap();
+        // First update straight away, with no trades yet
+        assert_eq!(stream.next().await.unwrap().len(), 1);
+
+        book.execute(|book| {
+            book.place_order("alice", 101, 5, OrderType::Ask).unwrap();
+            book.place_order("alice", 102, 5, OrderType::Ask).unwrap();
+            book.place_order("bob", 101, 2, OrderType::Bid).unwrap();
+        })
+        .await
+        .unwrap();
+        let updates = stream.next().await.unwrap();
+        assert_eq!(updates.len(), 2);
+        match &updates[0].data {
+            MarketData::L2(l2_book) => assert_eq!(l2_book.ask.len(), 1),
+            data => panic!("Unexpected {data:?}"),
+        }
+        match &updates[1].data {
+            MarketData::Trades(trades) => assert_eq!(trades[0].quantity, 2),
+            data => panic!("Unexpected {data:?}"),
+        }
+        assert!(stream.unsubscribe(Some("ACME".to_string()), Channel::Trades));
+        assert_eq!(stream.subscriptions().len(), 1);
+    }
+}
@@ -0,0 +1,479 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+use crate::{
+    book::OrderType,
+    clearing::TradeFees,
+    query::{paginate, Page, PageRequest, Paginated},
+};
+
+pub const DEFAULT_MEMORY_CAPACITY: usize = 10_000;
+
+// Trades not yet spilled to a segment, appended as they are recorded
+const TAIL_NAME: &str = "trades-tail.log";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Trade {
+    pub seq: u64,
+    // Unix timestamp in milliseconds
+    pub timestamp: u64,
+    pub price: u32,
+    pub quantity: u64,
+    // None for auction trades, which record the ask as maker and the bid as taker
+    pub aggressor: Option<OrderType>,
+    pub maker_order_id: Uuid,
+    pub maker_owner: String,
+    pub taker_order_id: Uuid,
+    pub taker_owner: String,
+    pub fees: TradeFees,
+}
+
+// One side of a trade, as reported to the owner of the order
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub order_id: Uuid,
+    pub owner: String,
+    pub side: OrderType,
+    pub price: u32,
+    pub quantity: u64,
+    // Whether the order was resting on the book, rather than taking liquidity
+    pub maker: bool,
+    // Sequence number of the trade on the tape
+    pub trade_seq: u64,
+    pub timestamp: u64,
+}
+
+impl Fill {
+    // Maker side first. Auction trades pair resting bids, as takers, with
+    // resting asks, so both count as makers there.
+    #[cfg(feature = "net")]
+    pub(crate) fn from_trade(trade: &Trade) -> [Fill; 2] {
+        let taker_side = trade.aggressor.unwrap_or(OrderType::Bid);
+        let maker_side = match taker_side {
+            OrderType::Bid => OrderType::Ask,
+            OrderType::Ask => OrderType::Bid,
+        };
+        let fill = |order_id, owner: &str, side, maker| Fill {
+            order_id,
+            owner: owner.to_string(),
+            side,
+            price: trade.price,
+            quantity: trade.quantity,
+            maker,
+            trade_seq: trade.seq,
+            timestamp: trade.timestamp,
+        };
+        [
+            fill(trade.maker_order_id, &trade.maker_owner, maker_side, true),
+            fill(
+                trade.taker_order_id,
+                &trade.taker_owner,
+                taker_side,
+                trade.aggressor.is_none(),
+            ),
+        ]
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    first_seq: u64,
+    last_seq: u64,
+    path: PathBuf,
+}
+
+// Trade history that keeps the most recent trades in memory and spills
+// older ones to immutable segment files on disk. Without a segment
+// directory, spilled trades are dropped so memory stays bounded either way,
+// and asking for them is an error. With one, the in-memory trades are also
+// appended to a tail file so a restart picks up where the tape left off.
+pub struct TradeTape {
+    memory: VecDeque<Trade>,
+    memory_capacity: usize,
+    segment_dir: Option<PathBuf>,
+    segments: Vec<Segment>,
+    next_seq: u64,
+    tail: Option<File>,
+    // How many of the in-memory trades the tail file has
+    tail_len: usize,
+}
+
+impl TradeTape {
+    pub fn in_memory(memory_capacity: usize) -> TradeTape {
+        TradeTape {
+            memory: VecDeque::new(),
+            memory_capacity: memory_capacity.max(1),
+            segment_dir: None,
+            segments: Vec::new(),
+            next_seq: 1,
+            tail: None,
+            tail_len: 0,
+        }
+    }
+
+    // Opens a tape backed by `segment_dir`, indexing any segments written by
+    // a previous run so their history stays queryable and reloading the
+    // trades it hadn't spilled yet.
+    pub fn open(memory_capacity: usize, segment_dir: PathBuf) -> Result<TradeTape> {
+        fs::create_dir_all(&segment_dir)?;
+
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&segment_dir)? {
+            let path = entry?.path();
+            if let Some((first_seq, last_seq)) = parse_segment_name(&path) {
+                segments.push(Segment {
+                    first_seq,
+                    last_seq,
+                    path,
+                });
+            }
+        }
+        segments.sort_by_key(|segment| segment.first_seq);
+        let spilled_seq = segments.last().map_or(1, |segment| segment.last_seq + 1);
+
+        // The tail may still hold trades that made it into a segment before
+        // it was rewritten, or end in a trade cut off part way through
+        let tail_path = segment_dir.join(TAIL_NAME);
+        let mut memory = VecDeque::new();
+        if tail_path.exists() {
+            let bytes = fs::read(&tail_path)?;
+            let mut reader = bytes.as_slice();
+            while !reader.is_empty() {
+                match rmp_serde::from_read::<_, Trade>(&mut reader) {
+                    Ok(trade) if trade.seq >= spilled_seq => memory.push_back(trade),
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+        }
+        let next_seq = memory.back().map_or(spilled_seq, |trade| trade.seq + 1);
+        let tail = write_tail(&tail_path, &memory)?;
+
+        Ok(TradeTape {
+            tail_len: memory.len(),
+            memory,
+            memory_capacity: memory_capacity.max(1),
+            segment_dir: Some(segment_dir),
+            segments,
+            next_seq,
+            tail: Some(tail),
+        })
+    }
+
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    pub fn memory_len(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn num_segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    // Sequence number of the oldest trade the tape still has, or of the
+    // next trade if it has none
+    pub fn first_seq(&self) -> u64 {
+        match (self.segments.first(), self.memory.front()) {
+            (Some(segment), _) => segment.first_seq,
+            (None, Some(trade)) => trade.seq,
+            (None, None) => self.next_seq,
+        }
+    }
+
+    // Appends a trade to the tape, assigning it the next sequence number.
+    // Call persist afterwards to write it out and keep memory use bounded.
+    pub fn record(&mut self, mut trade: Trade) -> &Trade {
+        trade.seq = self.next_seq;
+        self.next_seq += 1;
+        self.memory.push_back(trade);
+        self.memory.back().unwrap()
+    }
+
+    // Appends the trades recorded since the last call to the tail file, and
+    // spills the older trades once memory holds more than its capacity. On
+    // error the trades stay in memory and the next call retries.
+    pub fn persist(&mut self) -> Result<()> {
+        if let Some(tail) = &mut self.tail {
+            let mut bytes = Vec::new();
+            for trade in self.memory.iter().skip(self.tail_len) {
+                rmp_serde::encode::write_named(&mut bytes, trade)?;
+            }
+            tail.write_all(&bytes)?;
+            self.tail_len = self.memory.len();
+        }
+        if self.memory.len() > self.memory_capacity {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    // Moves the older half of the in-memory trades into a new segment, then
+    // rewrites the tail without them
+    fn spill(&mut self) -> Result<()> {
+        let spill_len = self.memory.len() - self.memory_capacity / 2;
+
+        match &self.segment_dir {
+            Some(segment_dir) => {
+                let spilled: Vec<&Trade> = self.memory.iter().take(spill_len).collect();
+                let first_seq = spilled.first().unwrap().seq;
+                let last_seq = spilled.last().unwrap().seq;
+                let path = segment_dir.join(segment_name(first_seq, last_seq));
+                write_atomically(&path, &rmp_serde::to_vec_named(&spilled)?)?;
+
+                self.segments.push(Segment {
+                    first_seq,
+                    last_seq,
+                    path,
+                });
+                self.memory.drain(..spill_len);
+                // Until it's rewritten the tail still has every trade, and
+                // the spilled ones are skipped when it's read back
+                self.tail_len = self.memory.len();
+                self.tail = Some(write_tail(&segment_dir.join(TAIL_NAME), &self.memory)?);
+            }
+            None => {
+                self.memory.drain(..spill_len);
+            }
+        }
+        Ok(())
+    }
+
+    // Returns up to `limit` trades with a sequence number of at least
+    // `from_seq`, oldest first. Only segments overlapping the page are read.
+    pub fn get_trades(&self, from_seq: u64, limit: usize) -> Result<Vec<Trade>> {
+        self.iter_from(from_seq).take(limit).collect()
+    }
+
+    // Pages from the oldest trade the tape has when no cursor is given
+    pub fn query(&self, page: &PageRequest) -> Result<Page<Trade>> {
+        paginate(
+            self.iter_from(page.cursor.unwrap_or(self.first_seq())),
+            page,
+        )
+    }
+
+    // Trades from `from_seq` onwards, decoding each segment once as it's
+    // reached. Fails straight away if some of them were dropped.
+    pub fn iter_from(&self, from_seq: u64) -> impl Iterator<Item = Result<Trade>> + '_ {
+        let first_seq = self.first_seq();
+        let dropped = (from_seq.max(1) < first_seq)
+            .then(|| Err(anyhow!("Trades before {first_seq} are no longer kept")));
+        // Segments and memory each hold a run of consecutive sequence
+        // numbers, so a trade's segment and its offset in it are found
+        // without reading anything
+        let from_seq = from_seq.max(first_seq);
+        let start = self
+            .segments
+            .partition_point(|segment| segment.last_seq < from_seq);
+        let spilled = self.segments[start..].iter().flat_map(move |segment| {
+            let offset = from_seq.saturating_sub(segment.first_seq) as usize;
+            let (trades, err) = match read_segment(segment) {
+                Ok(trades) => (trades, None),
+                Err(err) => (Vec::new(), Some(Err(err))),
+            };
+            err.into_iter()
+                .chain(trades.into_iter().skip(offset).map(Ok))
+        });
+        let memory_offset = self
+            .memory
+            .front()
+            .map_or(0, |trade| from_seq.saturating_sub(trade.seq) as usize);
+        let memory = self.memory.iter().skip(memory_offset).cloned().map(Ok);
+        dropped.into_iter().chain(spilled).chain(memory)
+    }
+}
+
+impl Paginated for Trade {
+    fn cursor(&self) -> u64 {
+        self.seq
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+impl Default for TradeTape {
+    fn default() -> Self {
+        Self::in_memory(DEFAULT_MEMORY_CAPACITY)
+    }
+}
+
+fn read_segment(segment: &Segment) -> Result<Vec<Trade>> {
+    rmp_serde::from_slice(&fs::read(&segment.path)?)
+        .map_err(|err| anyhow!("Corrupted segment {:?}: {err}", segment.path))
+}
+
+// Segments are written under a temporary name and renamed into place, so a
+// crash never leaves a partly written one behind
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, bytes)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// Replaces the tail file with `trades` and opens it for appending
+fn write_tail(path: &Path, trades: &VecDeque<Trade>) -> Result<File> {
+    let mut bytes = Vec::new();
+    for trade in trades {
+        rmp_serde::encode::write_named(&mut bytes, trade)?;
+    }
+    write_atomically(path, &bytes)?;
+    Ok(fs::OpenOptions::new().append(true).open(path)?)
+}
+
+fn segment_name(first_seq: u64, last_seq: u64) -> String {
+    format!("trades-{first_seq:020}-{last_seq:020}.seg")
+}
+
+fn parse_segment_name(path: &Path) -> Option<(u64, u64)> {
+    let name = path.file_name()?.to_str()?;
+    let range = name.strip_prefix("trades-")?.strip_suffix(".seg")?;
+    let (first_seq, last_seq) = range.split_once('-')?;
+    Some((first_seq.parse().ok()?, last_seq.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_n(tape: &mut TradeTape, n: u32) {
+        for i in 1..=n {
+            tape.record(Trade {
+                seq: 0,
+                timestamp: 0,
+                price: i,
+                quantity: 1,
+                aggressor: Some(OrderType::Bid),
+                maker_order_id: Uuid::new_v4(),
+                maker_owner: "alice".to_string(),
+                taker_order_id: Uuid::new_v4(),
+                taker_owner: "bob".to_string(),
+                fees: TradeFees::default(),
+            });
+            tape.persist().unwrap();
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("order_book_{name}_{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_in_memory_tape_is_bounded() {
+        let mut tape = TradeTape::in_memory(10);
+        record_n(&mut tape, 100);
+
+        assert!(tape.memory_len() <= 10);
+        assert_eq!(tape.next_seq(), 101);
+
+        // Asking for dropped trades fails rather than skipping them
+        assert!(tape.get_trades(1, 100).is_err());
+        let trades = tape.get_trades(tape.first_seq(), 100).unwrap();
+        assert_eq!(trades.len(), tape.memory_len());
+        assert_eq!(trades.last().unwrap().seq, 100);
+
+        // Pages start from the oldest trade kept without a cursor
+        let page = tape.query(&PageRequest::default()).unwrap();
+        assert_eq!(page.items, trades);
+    }
+
+    #[test]
+    fn test_spilled_trades_are_paged_from_disk() {
+        let dir = temp_dir("tape_spill");
+        let mut tape = TradeTape::open(10, dir.clone()).unwrap();
+        record_n(&mut tape, 100);
+
+        assert!(tape.memory_len() <= 10);
+        assert!(tape.num_segments() > 0);
+
+        let all_trades = tape.get_trades(1, 1000).unwrap();
+        let seqs: Vec<u64> = all_trades.iter().map(|trade| trade.seq).collect();
+        assert_eq!(seqs, (1..=100).collect::<Vec<u64>>());
+
+        let page = tape.get_trades(42, 5).unwrap();
+        let seqs: Vec<u64> = page.iter().map(|trade| trade.seq).collect();
+        assert_eq!(seqs, vec![42, 43, 44, 45, 46]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_query_pages_through_segments() {
+        let dir = temp_dir("tape_query");
+        let mut tape = TradeTape::open(50, dir.clone()).unwrap();
+        record_n(&mut tape, 600);
+
+        let mut page = PageRequest {
+            limit: 250,
+            ..Default::default()
+        };
+        let mut seqs = Vec::new();
+        loop {
+            let result = tape.query(&page).unwrap();
+            seqs.extend(result.items.iter().map(|trade| trade.seq));
+            match result.next_cursor {
+                Some(cursor) => page.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(seqs, (1..=600).collect::<Vec<u64>>());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reopen_indexes_existing_segments() {
+        let dir = temp_dir("tape_reopen");
+        let mut tape = TradeTape::open(4, dir.clone()).unwrap();
+        record_n(&mut tape, 20);
+
+        let reopened = TradeTape::open(4, dir.clone()).unwrap();
+        assert_eq!(reopened.num_segments(), tape.num_segments());
+        assert_eq!(reopened.get_trades(1, 3).unwrap().len(), 3);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reopen_keeps_unspilled_trades() {
+        let dir = temp_dir("tape_tail");
+        let mut tape = TradeTape::open(4, dir.clone()).unwrap();
+        record_n(&mut tape, 21);
+        assert!(tape.memory_len() > 0);
+        drop(tape);
+
+        // A trade cut off part way through writing is dropped
+        let mut tail = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join(TAIL_NAME))
+            .unwrap();
+        tail.write_all(&[0x8b, 0xa3]).unwrap();
+
+        let mut reopened = TradeTape::open(4, dir.clone()).unwrap();
+        assert_eq!(reopened.next_seq(), 22);
+        record_n(&mut reopened, 3);
+        let reopened = TradeTape::open(4, dir.clone()).unwrap();
+        let seqs: Vec<u64> = reopened
+            .get_trades(1, 100)
+            .unwrap()
+            .iter()
+            .map(|trade| trade.seq)
+            .collect();
+        assert_eq!(seqs, (1..=24).collect::<Vec<u64>>());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
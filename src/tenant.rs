@@ -0,0 +1,103 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+use crate::engine::BookHandle;
+
+// Venues hosted side by side in one server. Each tenant has its own book, so
+// its own participants, accounts and fee schedules, and each client belongs
+// to exactly one tenant. A request goes to the book of the tenant whose
+// client signed it, so nothing a client sends can reach another tenant's
+// book; an order id of another tenant is simply unknown.
+#[derive(Default)]
+pub struct Tenants {
+    books: HashMap<String, BookHandle>,
+    // Tenant of each client id
+    tenant_of: HashMap<String, String>,
+}
+
+impl Tenants {
+    pub fn new() -> Tenants {
+        Tenants::default()
+    }
+
+    pub fn add_tenant(&mut self, tenant_id: &str, book: BookHandle) -> Result<()> {
+        if self.books.contains_key(tenant_id) {
+            return Err(anyhow!("Tenant {tenant_id} is already hosted"));
+        }
+        self.books.insert(tenant_id.to_string(), book);
+        Ok(())
+    }
+
+    pub fn add_client(&mut self, client_id: &str, tenant_id: &str) -> Result<()> {
+        if !self.books.contains_key(tenant_id) {
+            return Err(anyhow!("Unknown tenant {tenant_id}"));
+        }
+        if let Some(tenant) = self.tenant_of.get(client_id) {
+            return Err(anyhow!("Client {client_id} already belongs to {tenant}"));
+        }
+        self.tenant_of
+            .insert(client_id.to_string(), tenant_id.to_string());
+        Ok(())
+    }
+
+    pub fn book(&self, tenant_id: &str) -> Option<&BookHandle> {
+        self.books.get(tenant_id)
+    }
+
+    pub fn tenant_of(&self, client_id: &str) -> Option<&str> {
+        self.tenant_of.get(client_id).map(String::as_str)
+    }
+
+    // Book the requests signed by `client_id` go to
+    pub fn book_for_client(&self, client_id: &str) -> Option<&BookHandle> {
+        self.books.get(self.tenant_of.get(client_id)?)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &BookHandle)> {
+        self.books
+            .iter()
+            .map(|(tenant_id, book)| (tenant_id.as_str(), book))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::{OrderBook, OrderType};
+
+    #[tokio::test]
+    async fn test_clients_reach_their_own_tenant() {
+        let mut tenants = Tenants::new();
+        tenants
+            .add_tenant("venue-a", BookHandle::spawn(OrderBook::new(), 8))
+            .unwrap();
+        tenants
+            .add_tenant("venue-b", BookHandle::spawn(OrderBook::new(), 8))
+            .unwrap();
+        assert!(tenants
+            .add_tenant("venue-a", BookHandle::spawn(OrderBook::new(), 8))
+            .is_err());
+        tenants.add_client("alice", "venue-a").unwrap();
+        tenants.add_client("bob", "venue-b").unwrap();
+        assert!(tenants.add_client("alice", "venue-b").is_err());
+        assert!(tenants.add_client("carol", "venue-c").is_err());
+
+        assert_eq!(tenants.tenant_of("alice"), Some("venue-a"));
+        assert!(tenants.book_for_client("carol").is_none());
+        let order_id = tenants
+            .book_for_client("alice")
+            .unwrap()
+            .execute(|book| book.place_order("alice", 100, 5, OrderType::Ask))
+            .await
+            .unwrap()
+            .unwrap();
+        // Unknown to the other tenant's book
+        let in_b = tenants
+            .book_for_client("bob")
+            .unwrap()
+            .execute(move |book| book.order_status(order_id))
+            .await
+            .unwrap();
+        assert_eq!(in_b, None);
+    }
+}
@@ -1,38 +1,627 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, BytesMut};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::io::{Read, Write};
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio::time::{self, Instant};
 
-pub async fn read_msg<T: DeserializeOwned>(conn: &mut TcpStream) -> Result<T> {
-    let buf = read_buf(conn).await?;
-    if let Ok(msg) = rmp_serde::from_slice(&buf) {
-        return Ok(msg);
+use crate::codec::FixedCodec;
+
+// Every message is a big endian u32 length and big endian CRC32 of the body,
+// followed by the body of that many bytes. The length's top bit flags a body
+// compressed as lz4 behind its little endian u32 uncompressed length, and
+// the next bit a body in its fixed layout rather than serialized by serde,
+// see codec.rs. Only a connection that negotiated them sends either.
+//
+// Lengths come from the peer, so a frame over the reader's limit is refused
+// before anything is allocated for it, and the buffer for a frame grows
+// with the bytes that actually arrive rather than to the length up front.
+const LEN_PREFIX: usize = 4;
+const HEADER_LEN: usize = 8;
+const COMPRESSED_FLAG: u32 = 1 << 31;
+const FIXED_FLAG: u32 = 1 << 30;
+const MAX_MSG_LEN: usize = (FIXED_FLAG - 1) as usize;
+// Smaller messages aren't worth the time to compress
+pub const COMPRESSION_THRESHOLD: usize = 16 * 1024;
+const INITIAL_BUF_CAPACITY: usize = 4 * 1024;
+// Largest body read, before and after decompression, unless the connection
+// sets its own
+pub const DEFAULT_MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+// Most the read buffer grows by ahead of the bytes that arrived
+const MAX_READ_AHEAD: usize = 64 * 1024;
+
+// Why a connection's input couldn't be read. Nothing more can be read from
+// it after any of them but Undecodable, which leaves the stream at the next
+// frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolError {
+    // Frame's length, or the uncompressed length of its body, is over the
+    // reader's limit
+    FrameTooLarge { len: usize, max_frame_len: usize },
+    // Body didn't match its frame's checksum, so the stream was corrupted or
+    // lost its place between frames
+    ChecksumMismatch,
+    // Compressed body is missing its length or doesn't decompress to it
+    BadCompression,
+    // Body is neither a message nor, where the connection accepts it, JSON
+    Undecodable,
+    // Message started arriving but didn't arrive in full in time
+    ReadTimeout,
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::FrameTooLarge { len, max_frame_len } => {
+                write!(
+                    f,
+                    "Frame of {len} bytes is over the {max_frame_len} byte limit"
+                )
+            }
+            ProtocolError::ChecksumMismatch => write!(f, "Frame failed its checksum"),
+            ProtocolError::BadCompression => write!(f, "Frame body failed to decompress"),
+            ProtocolError::Undecodable => write!(f, "Frame body isn't a message"),
+            ProtocolError::ReadTimeout => {
+                write!(f, "Message didn't arrive within the read timeout")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+// Connection that keeps its read and write buffers between messages, so a
+// long lived connection stops allocating once they've grown to fit its
+// largest message. Reads may buffer the start of the next message, so the
+// stream shouldn't be read from other than through this.
+pub struct Framed {
+    stream: TcpStream,
+    read_buf: BytesMut,
+    write_buf: BytesMut,
+    // Holds messages on their way in or out of compression
+    scratch: Vec<u8>,
+    compression: bool,
+    fixed_layout: bool,
+    json_fallback: bool,
+    max_frame_len: usize,
+    read_timeout: Option<Duration>,
+    // When the first bytes of the message being read arrived
+    partial_since: Option<Instant>,
+}
+
+impl Framed {
+    pub fn new(stream: TcpStream) -> Framed {
+        Framed {
+            stream,
+            read_buf: BytesMut::with_capacity(INITIAL_BUF_CAPACITY),
+            write_buf: BytesMut::with_capacity(INITIAL_BUF_CAPACITY),
+            scratch: Vec::new(),
+            compression: false,
+            fixed_layout: false,
+            json_fallback: false,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            read_timeout: None,
+            partial_since: None,
+        }
+    }
+
+    // Largest body read from now on, before and after decompression. Written
+    // messages are only held to what the framing can carry, so the peer's
+    // limit is for the peer to check.
+    pub fn set_max_frame_len(&mut self, max_frame_len: usize) {
+        self.max_frame_len = max_frame_len.min(MAX_MSG_LEN);
+    }
+
+    // Whether a body that isn't a message is read as one in JSON instead,
+    // for hand written clients. Off unless the connection is meant to take
+    // JSON, so a corrupt message isn't also parsed as text.
+    pub fn set_json_fallback(&mut self, json_fallback: bool) {
+        self.json_fallback = json_fallback;
+    }
+
+    // Fails a read once a message started arriving but hasn't arrived in
+    // full within `read_timeout`, e.g. from a peer that stalled mid message.
+    // Waiting for a message to start is up to the caller.
+    pub fn set_read_timeout(&mut self, read_timeout: Option<Duration>) {
+        self.read_timeout = read_timeout;
+    }
+
+    // Whether messages written from now on over COMPRESSION_THRESHOLD are
+    // compressed. Only to be set once the peer agreed to it, see
+    // Request::Handshake. Compressed messages are always read.
+    pub fn set_compression(&mut self, compression: bool) {
+        self.compression = compression;
+    }
+
+    // Whether messages written from now on that have a fixed layout are
+    // sent in it, see codec.rs. Like compression, only to be set once the
+    // peer agreed to it, and always read.
+    pub fn set_fixed_layout(&mut self, fixed_layout: bool) {
+        self.fixed_layout = fixed_layout;
+    }
+
+    pub async fn read_msg<T: DeserializeOwned + FixedCodec>(&mut self) -> Result<T> {
+        let prefix = self.fill_frame().await?;
+        let (len, compressed) = split_len(prefix);
+        let body = &self.read_buf[HEADER_LEN..HEADER_LEN + len];
+        let msg = if let Err(err) = verify(&self.read_buf[..HEADER_LEN], body) {
+            Err(err)
+        } else if prefix & FIXED_FLAG != 0 {
+            T::decode_fixed(body)
+        } else if compressed {
+            decompress_into(body, &mut self.scratch, self.max_frame_len)
+                .and_then(|()| decode(&self.scratch, self.json_fallback))
+        } else {
+            decode(body, self.json_fallback)
+        };
+        self.read_buf.advance(HEADER_LEN + len);
+        // Whatever is left is the start of the next message
+        self.partial_since = (!self.read_buf.is_empty()).then(Instant::now);
+        msg
+    }
+
+    pub async fn write_msg<T: Serialize + FixedCodec>(&mut self, msg: &T) -> Result<()> {
+        self.write_buf.clear();
+        // Fixed layouts are small enough that compressing wouldn't pay
+        let fixed = self.fixed_layout && encode_fixed(&mut self.write_buf, msg);
+        if !fixed {
+            encode(&mut self.write_buf, msg)?;
+            if self.compression && self.write_buf.len() - HEADER_LEN > COMPRESSION_THRESHOLD {
+                self.compress_write_buf()?;
+            }
+        }
+        seal(&mut self.write_buf);
+        self.stream.write_all(&self.write_buf).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    // Swaps the encoded message in the write buffer for its compressed frame,
+    // unless compressing didn't make it smaller
+    fn compress_write_buf(&mut self) -> Result<()> {
+        let body = &self.write_buf[HEADER_LEN..];
+        self.scratch.clear();
+        self.scratch
+            .extend_from_slice(&u32::try_from(body.len())?.to_le_bytes());
+        self.scratch.resize(
+            LEN_PREFIX + lz4_flex::block::get_maximum_output_size(body.len()),
+            0,
+        );
+        let compressed_len = lz4_flex::block::compress_into(body, &mut self.scratch[LEN_PREFIX..])?;
+        self.scratch.truncate(LEN_PREFIX + compressed_len);
+        if self.scratch.len() >= body.len() {
+            return Ok(());
+        }
+        self.write_buf.clear();
+        self.write_buf
+            .put_u32(u32::try_from(self.scratch.len())? | COMPRESSED_FLAG);
+        self.write_buf.put_u32(0);
+        self.write_buf.extend_from_slice(&self.scratch);
+        Ok(())
+    }
+
+    // Reads until a whole message is buffered and returns its length prefix
+    async fn fill_frame(&mut self) -> Result<u32> {
+        let mut needed = HEADER_LEN;
+        loop {
+            if self.read_buf.len() >= HEADER_LEN {
+                let prefix = u32::from_be_bytes(self.read_buf[..LEN_PREFIX].try_into()?);
+                let (len, _) = split_len(prefix);
+                check_len(len, self.max_frame_len)?;
+                needed = HEADER_LEN + len;
+                if self.read_buf.len() >= needed {
+                    return Ok(prefix);
+                }
+            }
+            self.read_buf
+                .reserve((needed - self.read_buf.len()).min(MAX_READ_AHEAD));
+            let read = self.stream.read_buf(&mut self.read_buf);
+            let read = match (self.read_timeout, self.partial_since) {
+                (Some(read_timeout), Some(partial_since)) => {
+                    time::timeout_at(partial_since + read_timeout, read)
+                        .await
+                        .map_err(|_| ProtocolError::ReadTimeout)?
+                }
+                _ => read.await,
+            };
+            if read? == 0 {
+                return Err(anyhow!("Connection closed"));
+            }
+            self.partial_since.get_or_insert_with(Instant::now);
+        }
+    }
+}
+
+fn check_len(len: usize, max_frame_len: usize) -> Result<(), ProtocolError> {
+    if len > max_frame_len {
+        return Err(ProtocolError::FrameTooLarge { len, max_frame_len });
+    }
+    Ok(())
+}
+
+fn split_len(prefix: u32) -> (usize, bool) {
+    (
+        (prefix & !(COMPRESSED_FLAG | FIXED_FLAG)) as usize,
+        prefix & COMPRESSED_FLAG != 0,
+    )
+}
+
+// Fills in the checksum of a frame whose body is complete
+fn seal(frame: &mut [u8]) {
+    let crc = crc32fast::hash(&frame[HEADER_LEN..]);
+    frame[LEN_PREFIX..HEADER_LEN].copy_from_slice(&crc.to_be_bytes());
+}
+
+fn verify(header: &[u8], body: &[u8]) -> Result<()> {
+    let crc = u32::from_be_bytes(header[LEN_PREFIX..HEADER_LEN].try_into()?);
+    if crc32fast::hash(body) != crc {
+        return Err(ProtocolError::ChecksumMismatch.into());
+    }
+    Ok(())
+}
+
+fn decompress_into(body: &[u8], out: &mut Vec<u8>, max_frame_len: usize) -> Result<()> {
+    if body.len() < LEN_PREFIX {
+        return Err(ProtocolError::BadCompression.into());
+    }
+    let len = u32::from_le_bytes(body[..LEN_PREFIX].try_into()?) as usize;
+    // Checked before allocating, as the length comes from the peer
+    check_len(len, max_frame_len)?;
+    out.clear();
+    out.resize(len, 0);
+    match lz4_flex::block::decompress_into(&body[LEN_PREFIX..], out) {
+        Ok(decompressed) if decompressed == len => Ok(()),
+        _ => Err(ProtocolError::BadCompression.into()),
+    }
+}
+
+fn decode<T: DeserializeOwned>(buf: &[u8], json_fallback: bool) -> Result<T> {
+    // Whole body must be the one value, or a JSON message could pass for
+    // whatever its first byte happens to encode
+    let mut de = rmp_serde::Deserializer::new(buf);
+    if let Ok(msg) = T::deserialize(&mut de) {
+        if de.into_inner().is_empty() {
+            return Ok(msg);
+        }
+    }
+    if json_fallback {
+        if let Ok(msg) = serde_json::from_slice(buf) {
+            return Ok(msg);
+        }
+    }
+    Err(ProtocolError::Undecodable.into())
+}
+
+// Appends the message after a header with its length filled in once it's
+// serialized, so the frame is built in place rather than copied behind its
+// header. The checksum is left to `seal`.
+fn encode<T: Serialize>(buf: &mut BytesMut, msg: &T) -> Result<()> {
+    let start = buf.len();
+    buf.put_u32(0);
+    buf.put_u32(0);
+    rmp_serde::encode::write_named(&mut buf.writer(), msg)?;
+    let len = buf.len() - start - HEADER_LEN;
+    if len > MAX_MSG_LEN {
+        return Err(anyhow!("Message is {len} bytes, over the limit"));
     }
-    let msg = serde_json::from_str(std::str::from_utf8(&buf)?)?;
-    Ok(msg)
+    let len = len as u32;
+    buf[start..start + LEN_PREFIX].copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+// Header and fixed layout of the message, or false with nothing appended if
+// it has no fixed layout
+fn encode_fixed<T: FixedCodec>(buf: &mut BytesMut, msg: &T) -> bool {
+    let start = buf.len();
+    buf.put_u32(0);
+    buf.put_u32(0);
+    if !msg.encode_fixed(buf) {
+        buf.truncate(start);
+        return false;
+    }
+    // Fixed layouts stay far below MAX_MSG_LEN, as their strings are short
+    let len = (buf.len() - start - HEADER_LEN) as u32;
+    buf[start..start + LEN_PREFIX].copy_from_slice(&(len | FIXED_FLAG).to_be_bytes());
+    true
+}
+
+pub async fn read_msg<T: DeserializeOwned>(conn: &mut TcpStream) -> Result<T> {
+    decode(&read_buf(conn).await?, false)
 }
 pub async fn read_string(conn: &mut TcpStream) -> Result<String> {
     Ok(String::from_utf8(read_buf(conn).await?)?)
 }
+// Body of the next message, decompressed if it was sent compressed
 pub async fn read_buf(conn: &mut TcpStream) -> Result<Vec<u8>> {
-    let len = conn.read_u32().await?;
-    let mut buf = vec![0; len as usize];
-    conn.read_exact(&mut buf).await?;
+    let mut header = [0; HEADER_LEN];
+    conn.read_exact(&mut header).await?;
+    let (len, compressed) = split_len(u32::from_be_bytes(header[..LEN_PREFIX].try_into()?));
+    check_len(len, DEFAULT_MAX_FRAME_LEN)?;
+    let mut buf = Vec::with_capacity(len.min(MAX_READ_AHEAD));
+    if conn.take(len as u64).read_to_end(&mut buf).await? < len {
+        return Err(anyhow!("Connection closed"));
+    }
+    verify(&header, &buf)?;
+    if compressed {
+        let mut msg = Vec::new();
+        decompress_into(&buf, &mut msg, DEFAULT_MAX_FRAME_LEN)?;
+        return Ok(msg);
+    }
     Ok(buf)
 }
 
 pub async fn write_msg<T: Serialize>(conn: &mut TcpStream, msg: &T) -> Result<()> {
-    write_buf(conn, &rmp_serde::to_vec_named(msg)?).await
+    let mut buf = BytesMut::new();
+    encode(&mut buf, msg)?;
+    seal(&mut buf);
+    conn.write_all(&buf).await?;
+    conn.flush().await?;
+    Ok(())
 }
 pub async fn write_string(conn: &mut TcpStream, s: &str) -> Result<()> {
     write_buf(conn, s.as_bytes()).await
 }
 pub async fn write_buf(conn: &mut TcpStream, buf: &[u8]) -> Result<()> {
-    let len = buf.len() as u32;
-    let mut b: Vec<u8> = len.to_be_bytes().to_vec();
-    b.extend(buf);
-    conn.write_all(&b).await?;
+    if buf.len() > MAX_MSG_LEN {
+        return Err(anyhow!("Message is {} bytes, over the limit", buf.len()));
+    }
+    let mut header = [0; HEADER_LEN];
+    header[..LEN_PREFIX].copy_from_slice(&(buf.len() as u32).to_be_bytes());
+    header[LEN_PREFIX..].copy_from_slice(&crc32fast::hash(buf).to_be_bytes());
+    // Header and message go out in one vectored write without being joined
+    let mut frame = Buf::chain(&header[..], buf);
+    conn.write_all_buf(&mut frame).await?;
     conn.flush().await?;
     Ok(())
 }
+
+// Counterparts of read_msg and write_msg over blocking std IO, for callers
+// without a runtime such as the blocking client
+pub fn read_msg_blocking<T: DeserializeOwned>(conn: &mut impl Read) -> Result<T> {
+    let mut header = [0; HEADER_LEN];
+    conn.read_exact(&mut header)?;
+    let (len, compressed) = split_len(u32::from_be_bytes(header[..LEN_PREFIX].try_into()?));
+    check_len(len, DEFAULT_MAX_FRAME_LEN)?;
+    let mut buf = Vec::with_capacity(len.min(MAX_READ_AHEAD));
+    if conn.take(len as u64).read_to_end(&mut buf)? < len {
+        return Err(anyhow!("Connection closed"));
+    }
+    verify(&header, &buf)?;
+    if compressed {
+        let mut msg = Vec::new();
+        decompress_into(&buf, &mut msg, DEFAULT_MAX_FRAME_LEN)?;
+        return decode(&msg, false);
+    }
+    decode(&buf, false)
+}
+
+pub fn write_msg_blocking<T: Serialize>(conn: &mut impl Write, msg: &T) -> Result<()> {
+    let mut buf = BytesMut::new();
+    encode(&mut buf, msg)?;
+    seal(&mut buf);
+    conn.write_all(&buf)?;
+    conn.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::req::{CancelOrderArgs, Request, ViewStatsArgs};
+    use tokio::net::TcpListener;
+
+    async fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn test_framed_reads_back_to_back_messages() {
+        let (mut client, server) = pair().await;
+        let mut server = Framed::new(server);
+
+        // Several frames land in one read, and a later one is larger than
+        // the buffer started out
+        let memo = "x".repeat(3 * INITIAL_BUF_CAPACITY);
+        write_msg(&mut client, &Request::ViewL1Book).await.unwrap();
+        write_msg(&mut client, &Request::ViewStats(ViewStatsArgs { depth: 3 }))
+            .await
+            .unwrap();
+        write_msg(&mut client, &memo).await.unwrap();
+        for _ in 0..2 {
+            write_buf(&mut client, br#""ViewL2Book""#).await.unwrap();
+        }
+
+        assert!(matches!(
+            server.read_msg().await.unwrap(),
+            Request::ViewL1Book
+        ));
+        assert!(matches!(
+            server.read_msg().await.unwrap(),
+            Request::ViewStats(ViewStatsArgs { depth: 3 })
+        ));
+        assert_eq!(server.read_msg::<String>().await.unwrap(), memo);
+        // JSON only once the connection takes it, the refused one skipped
+        let err = server.read_msg::<Request>().await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::Undecodable)
+        );
+        server.set_json_fallback(true);
+        assert!(matches!(
+            server.read_msg().await.unwrap(),
+            Request::ViewL2Book
+        ));
+
+        server.write_msg(&memo).await.unwrap();
+        assert_eq!(read_msg::<String>(&mut client).await.unwrap(), memo);
+        drop(client);
+        assert!(server.read_msg::<Request>().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_large_messages_compressed_once_negotiated() {
+        let (mut client, server) = pair().await;
+        let mut server = Framed::new(server);
+        let small = "small".to_string();
+        let large = "order ".repeat(COMPRESSION_THRESHOLD);
+
+        server.write_msg(&large).await.unwrap();
+        let (len, compressed) = split_len(client.read_u32().await.unwrap());
+        assert!(!compressed);
+        let mut body = vec![0; len + HEADER_LEN - LEN_PREFIX];
+        client.read_exact(&mut body).await.unwrap();
+
+        server.set_compression(true);
+        server.write_msg(&large).await.unwrap();
+        server.write_msg(&small).await.unwrap();
+        let mut client = Framed::new(client);
+        assert_eq!(client.read_msg::<String>().await.unwrap(), large);
+        assert_eq!(client.read_msg::<String>().await.unwrap(), small);
+
+        // Only the large one went out compressed, to a fraction of its size
+        server.write_msg(&large).await.unwrap();
+        server.write_msg(&small).await.unwrap();
+        let mut client = client.stream;
+        let (len, compressed) = split_len(client.read_u32().await.unwrap());
+        assert!(compressed);
+        assert!(len < large.len() / 10);
+        client.read_u32().await.unwrap();
+        let mut body = vec![0; len];
+        client.read_exact(&mut body).await.unwrap();
+        let mut msg = Vec::new();
+        decompress_into(&body, &mut msg, DEFAULT_MAX_FRAME_LEN).unwrap();
+        assert_eq!(rmp_serde::from_slice::<String>(&msg).unwrap(), large);
+        assert_eq!(read_msg::<String>(&mut client).await.unwrap(), small);
+    }
+
+    #[tokio::test]
+    async fn test_fixed_layout_once_negotiated() {
+        let (client, server) = pair().await;
+        let (mut client, mut server) = (Framed::new(client), Framed::new(server));
+        let cancel = Request::CancelOrder(CancelOrderArgs {
+            order_id: uuid::Uuid::new_v4(),
+        });
+
+        client.set_fixed_layout(true);
+        client.write_msg(&cancel).await.unwrap();
+        // No fixed layout, so framed with serde
+        client.write_msg(&Request::ViewL1Book).await.unwrap();
+        let mut stream = server.stream;
+        let prefix = stream.read_u32().await.unwrap();
+        assert_ne!(prefix & FIXED_FLAG, 0);
+        // Tag, then the 16 byte order id
+        assert_eq!(split_len(prefix), (17, false));
+        stream.read_u32().await.unwrap();
+        let mut body = vec![0; 17];
+        stream.read_exact(&mut body).await.unwrap();
+        assert!(matches!(
+            Request::decode_fixed(&body).unwrap(),
+            Request::CancelOrder(_)
+        ));
+        assert!(matches!(
+            read_msg::<Request>(&mut stream).await.unwrap(),
+            Request::ViewL1Book
+        ));
+
+        server = Framed::new(stream);
+        client.write_msg(&cancel).await.unwrap();
+        assert!(matches!(
+            server.read_msg().await.unwrap(),
+            Request::CancelOrder(_)
+        ));
+    }
+
+    #[test]
+    fn test_blocking_round_trip() {
+        let mut buf = Vec::new();
+        write_msg_blocking(&mut buf, &Request::ViewStats(ViewStatsArgs { depth: 3 })).unwrap();
+        write_msg_blocking(&mut buf, &Request::ViewL1Book).unwrap();
+        let mut reader = &buf[..];
+        assert!(matches!(
+            read_msg_blocking(&mut reader).unwrap(),
+            Request::ViewStats(ViewStatsArgs { depth: 3 })
+        ));
+        assert!(matches!(
+            read_msg_blocking(&mut reader).unwrap(),
+            Request::ViewL1Book
+        ));
+        assert!(read_msg_blocking::<Request>(&mut reader).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_frame_fails_checksum() {
+        let (mut client, server) = pair().await;
+        let mut server = Framed::new(server);
+
+        let mut frame = BytesMut::new();
+        encode(&mut frame, &Request::ViewStats(ViewStatsArgs { depth: 3 })).unwrap();
+        seal(&mut frame);
+        let last = frame.len() - 1;
+        frame[last] ^= 1;
+        client.write_all(&frame).await.unwrap();
+        let err = server.read_msg::<Request>().await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::ChecksumMismatch)
+        );
+
+        // Read the same way without a Framed
+        let (mut client, mut server) = (server.stream, client);
+        client.write_all(&frame).await.unwrap();
+        let err = read_msg::<Request>(&mut server).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::ChecksumMismatch)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frames_refused_before_allocating() {
+        let (mut client, server) = pair().await;
+        let mut server = Framed::new(server);
+        server.set_max_frame_len(1024);
+
+        // Claims the largest body the framing allows, then sends nothing
+        client.write_u32(MAX_MSG_LEN as u32).await.unwrap();
+        client.write_u32(0).await.unwrap();
+        let err = server.read_msg::<Request>().await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::FrameTooLarge {
+                len: MAX_MSG_LEN,
+                max_frame_len: 1024,
+            })
+        );
+        assert!(server.read_buf.capacity() < 2 * INITIAL_BUF_CAPACITY);
+
+        // Compressed body that would decompress past the limit
+        let mut body = (2048u32).to_le_bytes().to_vec();
+        body.extend_from_slice(&[0; 8]);
+        let err = decompress_into(&body, &mut Vec::new(), 1024).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(ProtocolError::FrameTooLarge { len: 2048, .. })
+        ));
+        let err = decompress_into(&body, &mut Vec::new(), 4096).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::BadCompression)
+        );
+
+        // Without a Framed, the default limit
+        let mut reader = &[0xff; HEADER_LEN][..];
+        let err = read_msg_blocking::<Request>(&mut reader).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(ProtocolError::FrameTooLarge { .. })
+        ));
+    }
+}
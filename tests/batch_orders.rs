@@ -0,0 +1,105 @@
+mod common;
+
+use common::TestServer;
+use order_book::{
+    book::OrderType,
+    error::OrderBookError,
+    req::{CancelOrderArgs, PlaceOrderArgs, Request},
+    resp::{Reject, Response},
+    server::MAX_BATCH_ORDERS,
+};
+use uuid::Uuid;
+
+fn order(order_type: OrderType, price: u32, quantity: u64) -> PlaceOrderArgs {
+    PlaceOrderArgs {
+        order_type,
+        price,
+        quantity,
+        owner: "maker".to_string(),
+        expires_at: None,
+        client_order_id: None,
+    }
+}
+
+#[tokio::test]
+async fn test_requote_in_one_batch() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let Response::PlaceOrdersOk(placed) = client
+        .request(Request::PlaceOrders(vec![
+            order(OrderType::Bid, 99, 10),
+            order(OrderType::Ask, 101, 10),
+            // Zero quantity is rejected without failing the rest
+            order(OrderType::Bid, 98, 0),
+        ]))
+        .await
+    else {
+        panic!("Expected batch place results");
+    };
+    let order_ids: Vec<Uuid> = placed[..2]
+        .iter()
+        .map(|result| match result {
+            Response::PlaceOk(report) => report.order_id,
+            other => panic!("Expected PlaceOk but got {other:?}"),
+        })
+        .collect();
+    assert!(matches!(
+        placed[2],
+        Response::PlaceErr(Reject {
+            code: OrderBookError::InvalidQuantity,
+            ..
+        })
+    ));
+
+    let Response::CancelOrdersOk(canceled) = client
+        .request(Request::CancelOrders(
+            order_ids
+                .iter()
+                .chain(&[Uuid::new_v4()])
+                .map(|&order_id| CancelOrderArgs { order_id })
+                .collect(),
+        ))
+        .await
+    else {
+        panic!("Expected batch cancel results");
+    };
+    assert!(matches!(
+        canceled.as_slice(),
+        [
+            Response::CancelOk(10),
+            Response::CancelOk(10),
+            Response::CancelErr(Reject {
+                code: OrderBookError::UnknownOrder,
+                ..
+            })
+        ]
+    ));
+
+    let open_orders = server
+        .book
+        .execute(|book| book.open_orders("maker").len())
+        .await
+        .unwrap();
+    assert_eq!(open_orders, 0);
+}
+
+#[tokio::test]
+async fn test_oversized_batch_is_rejected_whole() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let orders = (0..=MAX_BATCH_ORDERS)
+        .map(|_| order(OrderType::Bid, 99, 1))
+        .collect();
+    assert!(matches!(
+        client.request(Request::PlaceOrders(orders)).await,
+        Response::BatchErr
+    ));
+    let open_orders = server
+        .book
+        .execute(|book| book.open_orders("maker").len())
+        .await
+        .unwrap();
+    assert_eq!(open_orders, 0);
+}
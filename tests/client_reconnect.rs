@@ -0,0 +1,215 @@
+mod common;
+
+use common::TestServer;
+use order_book::{
+    blocking::BlockingClient,
+    book::{OrderStatus, OrderType},
+    client::{ClientError, OrderBookClient, ReconnectPolicy},
+    error::OrderBookError,
+    query::PageRequest,
+    req::{PlaceOrderArgs, Request, ViewOpenOrdersArgs},
+    resp::Response,
+    wire::{read_buf, write_buf},
+};
+use std::{net::SocketAddr, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+
+// Proxy whose first connection passes one request on to the server and then
+// drops without answering, as if the connection failed mid request. Later
+// connections are passed through untouched.
+async fn flaky_proxy(server: SocketAddr) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut client, _) = listener.accept().await.unwrap();
+        let mut upstream = TcpStream::connect(server).await.unwrap();
+        let request = read_buf(&mut client).await.unwrap();
+        write_buf(&mut upstream, &request).await.unwrap();
+        read_buf(&mut upstream).await.unwrap();
+        drop(client);
+
+        loop {
+            let (mut client, _) = listener.accept().await.unwrap();
+            let mut upstream = TcpStream::connect(server).await.unwrap();
+            tokio::spawn(async move {
+                let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+            });
+        }
+    });
+    addr
+}
+
+fn policy(resend_idempotent: bool) -> ReconnectPolicy {
+    ReconnectPolicy {
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(10),
+        max_attempts: 3,
+        resend_idempotent,
+    }
+}
+
+fn order(client_order_id: Option<&str>) -> PlaceOrderArgs {
+    PlaceOrderArgs {
+        order_type: OrderType::Bid,
+        price: 99,
+        quantity: 10,
+        owner: "alice".to_string(),
+        expires_at: None,
+        client_order_id: client_order_id.map(str::to_string),
+    }
+}
+
+async fn open_orders(client: &mut OrderBookClient) -> usize {
+    let request = Request::ViewOpenOrders(ViewOpenOrdersArgs {
+        owner: "alice".to_string(),
+        page: PageRequest::default(),
+    });
+    match client.request(request).await.unwrap() {
+        Response::OpenOrdersOk(orders) => orders.items.len(),
+        response => panic!("Unexpected response {response:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_resent_order_is_placed_once() {
+    let server = TestServer::start().await;
+    let addr = flaky_proxy(server.addr).await;
+    let mut client = OrderBookClient::connect_with_policy(&addr.to_string(), policy(true))
+        .await
+        .unwrap();
+
+    // The first attempt reaches the book, the resend gets its id back
+    let order_id = client.place_order(order(Some("bid-1"))).await.unwrap();
+    assert_eq!(open_orders(&mut client).await, 1);
+    assert_eq!(
+        client.place_order(order(Some("bid-1"))).await.unwrap(),
+        order_id
+    );
+    assert_eq!(open_orders(&mut client).await, 1);
+
+    // A new client order id is a new order
+    assert_ne!(
+        client.place_order(order(Some("bid-2"))).await.unwrap(),
+        order_id
+    );
+    assert_eq!(open_orders(&mut client).await, 2);
+}
+
+#[tokio::test]
+async fn test_order_without_client_id_is_not_resent() {
+    let server = TestServer::start().await;
+    let addr = flaky_proxy(server.addr).await;
+    let mut client = OrderBookClient::connect_with_policy(&addr.to_string(), policy(true))
+        .await
+        .unwrap();
+
+    // It may have been placed, so the drop is left to the caller
+    assert!(client.place_order(order(None)).await.is_err());
+    // The next request reconnects
+    assert_eq!(open_orders(&mut client).await, 1);
+}
+
+#[tokio::test]
+async fn test_cancel_by_client_order_id() {
+    let server = TestServer::start().await;
+    let mut client = OrderBookClient::connect(&server.addr.to_string())
+        .await
+        .unwrap();
+    client.place_order(order(Some("bid-1"))).await.unwrap();
+    client.place_order(order(Some("bid-2"))).await.unwrap();
+
+    client.cancel_client_order("alice", "bid-1").await.unwrap();
+    assert_eq!(open_orders(&mut client).await, 1);
+    // Already canceled, unknown, or another owner's id
+    assert!(client.cancel_client_order("alice", "bid-1").await.is_err());
+    assert!(client.cancel_client_order("alice", "bid-3").await.is_err());
+    assert!(client.cancel_client_order("bob", "bid-2").await.is_err());
+    assert_eq!(open_orders(&mut client).await, 1);
+}
+
+#[tokio::test]
+async fn test_typed_results_and_errors() {
+    let server = TestServer::start().await;
+    let mut client = OrderBookClient::new(&server.addr.to_string(), policy(false));
+    assert!(!client.is_connected());
+
+    let ask = client
+        .place_limit_order("alice", OrderType::Ask, 101, 10)
+        .await
+        .unwrap();
+    assert!(client.is_connected());
+    let bid = client
+        .place_limit_order("bob", OrderType::Bid, 101, 4)
+        .await
+        .unwrap();
+    assert_eq!(bid.filled_quantity, 4);
+    let l2_book = client.l2_snapshot().await.unwrap();
+    assert_eq!(l2_book.ask[0].total_quantity, 6);
+    assert_eq!(client.open_orders("alice").await.unwrap().len(), 1);
+
+    assert_eq!(client.cancel(ask.order_id).await.unwrap(), 6);
+    match client.cancel(ask.order_id).await {
+        Err(ClientError::Rejected(reject)) => {
+            assert_eq!(reject.code, OrderBookError::AlreadyCanceled)
+        }
+        result => panic!("Unexpected result {result:?}"),
+    }
+    match client
+        .place_limit_order("alice", OrderType::Ask, 0, 1)
+        .await
+    {
+        Err(ClientError::Rejected(reject)) => assert_eq!(reject.code, OrderBookError::InvalidPrice),
+        result => panic!("Unexpected result {result:?}"),
+    }
+
+    // Reopened by the next request after a disconnect
+    client.disconnect();
+    assert!(client.l1_snapshot().await.is_ok());
+
+    // Nothing listening, so every attempt the policy allows fails
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    let mut client = OrderBookClient::new(&addr.to_string(), policy(false));
+    let err = client.l1_snapshot().await.unwrap_err();
+    assert!(matches!(err, ClientError::Connection(_)) && err.is_retryable());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_blocking_client() {
+    let server = TestServer::start().await;
+    let addr = server.addr.to_string();
+    // Off the runtime's threads, as a script without one would run it
+    tokio::task::spawn_blocking(move || {
+        let mut client = BlockingClient::connect_with_policy(&addr, policy(false)).unwrap();
+        client.set_timeout(Some(Duration::from_secs(5))).unwrap();
+        let ask = client
+            .place_limit_order("alice", OrderType::Ask, 101, 10)
+            .unwrap();
+        let bid = client
+            .place_order(PlaceOrderArgs {
+                order_type: OrderType::Bid,
+                price: 101,
+                quantity: 4,
+                owner: "bob".to_string(),
+                ..order(Some("bid-1"))
+            })
+            .unwrap();
+        assert!(matches!(
+            client.order_status(bid).unwrap(),
+            OrderStatus::Filled
+        ));
+        assert_eq!(client.l2_snapshot().unwrap().ask[0].total_quantity, 6);
+
+        // Reopened by the next request after a disconnect
+        client.disconnect();
+        assert_eq!(client.cancel(ask.order_id).unwrap(), 6);
+        assert!(matches!(
+            client.cancel(ask.order_id),
+            Err(ClientError::Rejected(_))
+        ));
+        assert!(client.open_orders("alice").unwrap().is_empty());
+    })
+    .await
+    .unwrap();
+}
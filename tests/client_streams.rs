@@ -0,0 +1,322 @@
+mod common;
+
+use common::TestServer;
+use order_book::{
+    book::OrderType,
+    client::OrderBookClient,
+    req::{
+        Channel, HandshakeArgs, PlaceOrderArgs, Request, SubscribeArgs, SubscribeFillsArgs,
+        TaggedRequest,
+    },
+    resp::{MarketData, Response},
+    OrderBookError,
+};
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+fn order(owner: &str, order_type: OrderType, price: u32, quantity: u64) -> PlaceOrderArgs {
+    PlaceOrderArgs {
+        order_type,
+        price,
+        quantity,
+        owner: owner.to_string(),
+        expires_at: None,
+        client_order_id: None,
+    }
+}
+
+#[tokio::test]
+async fn test_l2_and_trade_subscriptions() {
+    let server = TestServer::start().await;
+    let mut client = OrderBookClient::connect(&server.addr.to_string())
+        .await
+        .unwrap();
+    client.set_poll_interval(Duration::from_millis(5));
+
+    // Resting before subscribing, so it comes from the snapshot
+    client
+        .place_order(order("alice", OrderType::Ask, 101, 10))
+        .await
+        .unwrap();
+    let mut l2 = client.subscribe_l2().await.unwrap();
+    let mut trades = client.subscribe_trades().await.unwrap();
+
+    let first = l2.next().await.unwrap().unwrap();
+    assert!(first.bid.is_empty());
+    assert_eq!(first.ask[0].price, 101);
+    assert_eq!(first.ask[0].total_quantity, 10);
+
+    client
+        .place_order(order("bob", OrderType::Bid, 101, 4))
+        .await
+        .unwrap();
+    let trade = trades.next().await.unwrap().unwrap();
+    assert_eq!((trade.price, trade.quantity), (101, 4));
+    assert_eq!(trade.taker_owner, "bob");
+
+    let after = l2.next().await.unwrap().unwrap();
+    assert!(after.bid.is_empty());
+    assert_eq!(after.ask[0].total_quantity, 6);
+    assert!(after.as_of_seq > first.as_of_seq);
+
+    let canceled = client
+        .place_order(order("carol", OrderType::Bid, 99, 2))
+        .await
+        .unwrap();
+    let with_bid = l2.next().await.unwrap().unwrap();
+    assert_eq!(with_bid.bid[0].price, 99);
+    client.cancel(canceled).await.unwrap();
+    let without_bid = l2.next().await.unwrap().unwrap();
+    assert!(without_bid.bid.is_empty());
+    assert_eq!(without_bid.ask, after.ask);
+}
+
+#[tokio::test]
+async fn test_compressed_snapshot_of_deep_book() {
+    let server = TestServer::start().await;
+    let mut client = OrderBookClient::connect(&server.addr.to_string())
+        .await
+        .unwrap();
+    client.set_compression(true).await.unwrap();
+
+    // Deep enough that its snapshot goes out compressed
+    let orders: Vec<PlaceOrderArgs> = (0..500)
+        .map(|idx| order("alice", OrderType::Bid, 1 + idx % 100, 1))
+        .collect();
+    client
+        .request(Request::PlaceOrders(orders.clone()))
+        .await
+        .unwrap();
+    client.request(Request::PlaceOrders(orders)).await.unwrap();
+    match client.request(Request::ViewL3Book).await.unwrap() {
+        Response::L3BookOk(l3_book) => assert_eq!(l3_book.orders.len(), 1_000),
+        response => panic!("Unexpected response {response:?}"),
+    }
+
+    let mut l2 = client.subscribe_l2().await.unwrap();
+    let l2_book = l2.next().await.unwrap().unwrap();
+    assert_eq!(l2_book.bid.len(), 100);
+    assert!(l2_book.bid.iter().all(|level| level.total_quantity == 10));
+}
+
+#[tokio::test]
+async fn test_fixed_layout_order_entry() {
+    let server = TestServer::start().await;
+    let mut client = OrderBookClient::connect(&server.addr.to_string())
+        .await
+        .unwrap();
+    client.set_fixed_layout(true).await.unwrap();
+
+    let ask_id = client
+        .place_order(order("alice", OrderType::Ask, 101, 10))
+        .await
+        .unwrap();
+    match client
+        .request(Request::PlaceOrder(PlaceOrderArgs {
+            client_order_id: Some("bob-1".to_string()),
+            ..order("bob", OrderType::Bid, 101, 4)
+        }))
+        .await
+        .unwrap()
+    {
+        Response::PlaceOk(report) => {
+            assert_eq!(report.filled_quantity, 4);
+            assert!(report.timestamps.unwrap().acked_nanos.is_some());
+        }
+        response => panic!("Unexpected response {response:?}"),
+    }
+    // Messages without a fixed layout still go through serde
+    match client.request(Request::ViewL3Book).await.unwrap() {
+        Response::L3BookOk(l3_book) => assert_eq!(l3_book.orders.len(), 1),
+        response => panic!("Unexpected response {response:?}"),
+    }
+    assert_eq!(client.cancel(ask_id).await.unwrap(), 6);
+}
+
+#[tokio::test]
+async fn test_fill_subscription() {
+    let server = TestServer::start().await;
+    let mut client = OrderBookClient::connect(&server.addr.to_string())
+        .await
+        .unwrap();
+    let mut fills = client.subscribe_fills("alice").await.unwrap();
+
+    let ask_id = client
+        .place_order(order("alice", OrderType::Ask, 101, 10))
+        .await
+        .unwrap();
+    // Bob's fill isn't pushed to a subscription for alice
+    client
+        .place_order(order("bob", OrderType::Bid, 101, 4))
+        .await
+        .unwrap();
+    let fill = fills.next().await.unwrap().unwrap();
+    assert_eq!(fill.order_id, ask_id);
+    assert_eq!(fill.owner, "alice");
+    assert!(fill.maker);
+    assert_eq!((fill.price, fill.quantity), (101, 4));
+
+    // Queued while alice has no subscription, then pushed on subscribing
+    drop(fills);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    client
+        .place_order(order("bob", OrderType::Bid, 101, 6))
+        .await
+        .unwrap();
+    let mut fills = client.subscribe_fills("alice").await.unwrap();
+    let fill = fills.next().await.unwrap().unwrap();
+    assert_eq!(fill.quantity, 6);
+}
+
+#[tokio::test]
+async fn test_market_data_subscription() {
+    let server = TestServer::start().await;
+    let mut client = OrderBookClient::connect(&server.addr.to_string())
+        .await
+        .unwrap();
+    let subscription = |channel| SubscribeArgs {
+        symbol: Some("ACME".to_string()),
+        channel,
+        depth: Some(1),
+    };
+    let mut updates = client
+        .subscribe_market_data(vec![
+            subscription(Channel::L2),
+            subscription(Channel::Trades),
+        ])
+        .await
+        .unwrap();
+    // Current state first, an empty book
+    let update = updates.next().await.unwrap().unwrap();
+    assert_eq!(update.symbol.as_deref(), Some("ACME"));
+    assert!(matches!(update.data, MarketData::L2(l2_book) if l2_book.ask.is_empty()));
+
+    for price in [101, 102] {
+        client
+            .place_order(order("alice", OrderType::Ask, price, 10))
+            .await
+            .unwrap();
+    }
+    client
+        .place_order(order("bob", OrderType::Bid, 101, 10))
+        .await
+        .unwrap();
+    // Levels conflate to the latest state, so wait for the trade and the
+    // book as it stood after it
+    let mut traded = 0;
+    let mut best_ask = None;
+    while traded < 10 || best_ask != Some(102) {
+        match updates.next().await.unwrap().unwrap().data {
+            MarketData::Trades(trades) => {
+                traded += trades.iter().map(|trade| trade.quantity).sum::<u64>()
+            }
+            MarketData::L2(l2_book) => best_ask = l2_book.ask.first().map(|entry| entry.price),
+            data => panic!("Unexpected {data:?}"),
+        }
+    }
+    assert_eq!(traded, 10);
+
+    // Subscriptions belong to the connection that made them
+    let mut other = OrderBookClient::connect(&server.addr.to_string())
+        .await
+        .unwrap();
+    assert!(matches!(
+        other.request(Request::ViewSubscriptions).await.unwrap(),
+        Response::SubscriptionsOk(subscriptions) if subscriptions.is_empty()
+    ));
+}
+
+#[tokio::test]
+async fn test_pipelined_requests_skip_pushed_fills() {
+    let server = TestServer::start().await;
+    let mut client = OrderBookClient::connect(&server.addr.to_string())
+        .await
+        .unwrap();
+    // Alice's fill is pushed on the same connection between the responses
+    let responses = client
+        .pipeline(vec![
+            Request::SubscribeFills(SubscribeFillsArgs {
+                owner: "alice".to_string(),
+            }),
+            Request::PlaceOrder(order("alice", OrderType::Ask, 101, 10)),
+            Request::PlaceOrder(order("bob", OrderType::Bid, 101, 4)),
+            Request::ViewL1Book,
+        ])
+        .await
+        .unwrap();
+    assert_eq!(responses.len(), 4);
+    assert!(matches!(responses[0], Response::SubscribeFillsOk));
+    assert!(matches!(&responses[2], Response::PlaceOk(report) if report.filled_quantity == 4));
+    match &responses[3] {
+        Response::L1BookOk(l1_book) => assert_eq!(l1_book.ask.as_ref().unwrap().total_quantity, 6),
+        response => panic!("Unexpected {response:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_async_acks_keep_rejects_in_order() {
+    let server = TestServer::start().await;
+    let mut conn = server.connect().await;
+    let handshake = HandshakeArgs {
+        compression: false,
+        fixed_layout: false,
+        async_acks: true,
+    };
+    assert!(matches!(
+        conn.request(Request::Handshake(handshake)).await,
+        Response::HandshakeOk(agreed) if agreed.async_acks
+    ));
+
+    let requests = [
+        Request::PlaceOrder(order("alice", OrderType::Ask, 101, 10)),
+        Request::PlaceOrder(order("bob", OrderType::Bid, 101, 0)),
+        Request::ViewL1Book,
+    ];
+    for (request_id, request) in (1..).zip(requests) {
+        conn.send(Request::Tagged(Box::new(TaggedRequest {
+            request_id,
+            request,
+        })))
+        .await;
+    }
+    assert!(matches!(
+        conn.recv().await,
+        Response::Tagged(tagged)
+            if tagged.request_id == 1 && matches!(tagged.response, Response::PlaceOk(_))
+    ));
+    assert!(matches!(
+        conn.recv().await,
+        Response::Rejected(rejected)
+            if rejected.request_id == 2 && rejected.reject.code == OrderBookError::InvalidQuantity
+    ));
+    assert!(matches!(
+        conn.recv().await,
+        Response::Tagged(tagged)
+            if tagged.request_id == 3 && matches!(tagged.response, Response::L1BookOk(_))
+    ));
+
+    // Untagged requests are still answered directly
+    assert!(matches!(
+        conn.request(Request::PlaceOrder(order("bob", OrderType::Bid, 101, 0)))
+            .await,
+        Response::PlaceErr(_)
+    ));
+
+    // The client hands the rejects back in place of the responses
+    let mut client = OrderBookClient::connect(&server.addr.to_string())
+        .await
+        .unwrap();
+    client.set_async_acks(true).await.unwrap();
+    let responses = client
+        .pipeline(vec![
+            Request::PlaceOrder(order("bob", OrderType::Bid, 0, 5)),
+            Request::PlaceOrder(order("bob", OrderType::Bid, 101, 4)),
+        ])
+        .await
+        .unwrap();
+    assert!(matches!(
+        &responses[0],
+        Response::Rejected(rejected) if rejected.reject.code == OrderBookError::InvalidPrice
+    ));
+    assert!(matches!(&responses[1], Response::PlaceOk(report) if report.filled_quantity == 4));
+}
@@ -0,0 +1,127 @@
+// In-process server and socket clients shared by the integration tests.
+// Not every test binary uses every helper.
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::net::{TcpListener, TcpStream};
+
+use order_book::{
+    auth::{Authenticator, DEFAULT_FRESHNESS_WINDOW_MS},
+    config::ServerConfig,
+    engine::{BookHandle, DEFAULT_QUEUE_CAPACITY},
+    feed::SequencedEvent,
+    query::{PageRequest, MAX_PAGE_LIMIT},
+    req::{GetEventsArgs, Request},
+    resp::Response,
+    server::{serve_with_options, ServeOptions},
+    tape::Trade,
+    wire::Framed,
+};
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    // Same book the server uses, for checks that bypass the wire
+    pub book: BookHandle,
+}
+
+impl TestServer {
+    // Serves a fresh book on an ephemeral port for the rest of the test
+    pub async fn start() -> TestServer {
+        TestServer::start_with_config(&ServerConfig::default()).await
+    }
+
+    // Serves a book built from `config`. Its address is ignored in favour
+    // of an ephemeral port.
+    pub async fn start_with_config(config: &ServerConfig) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let book = BookHandle::spawn(config.build_book().unwrap(), DEFAULT_QUEUE_CAPACITY);
+        let auth = Arc::new(Mutex::new(Authenticator::new(
+            HashMap::new(),
+            DEFAULT_FRESHNESS_WINDOW_MS,
+        )));
+        let options = ServeOptions {
+            rate_limit: config.rate_limit,
+            timeouts: config.timeouts,
+            max_frame_len: config.max_frame_len,
+            json_fallback: config.json_fallback,
+            outbox_limit: config.outbox_limit,
+            backpressure: config.backpressure,
+            ..Default::default()
+        };
+        tokio::spawn(serve_with_options(listener, book.clone(), auth, options));
+        TestServer { addr, book }
+    }
+
+    pub async fn connect(&self) -> TestClient {
+        TestClient::connect(self.addr).await
+    }
+}
+
+pub struct TestClient {
+    socket: Framed,
+}
+
+impl TestClient {
+    pub async fn connect(addr: SocketAddr) -> TestClient {
+        TestClient {
+            socket: Framed::new(TcpStream::connect(addr).await.unwrap()),
+        }
+    }
+
+    pub async fn request(&mut self, request: Request) -> Response {
+        self.send(request).await;
+        self.recv().await
+    }
+
+    // Sends without waiting for the response, e.g. to pipeline requests
+    pub async fn send(&mut self, request: Request) {
+        self.socket.write_msg(&request).await.unwrap();
+    }
+
+    pub async fn recv(&mut self) -> Response {
+        self.socket.read_msg().await.unwrap()
+    }
+
+    // Every trade on the tape, following the page cursors
+    pub async fn all_trades(&mut self) -> Vec<Trade> {
+        let mut page = PageRequest {
+            limit: MAX_PAGE_LIMIT,
+            ..Default::default()
+        };
+        let mut trades = Vec::new();
+        loop {
+            let Response::TradesOk(result) = self.request(Request::GetTrades(page)).await else {
+                panic!("Failed to get trades");
+            };
+            trades.extend(result.items);
+            match result.next_cursor {
+                Some(cursor) => page.cursor = Some(cursor),
+                None => return trades,
+            }
+        }
+    }
+
+    // Every event published so far, oldest first
+    pub async fn all_events(&mut self) -> Vec<SequencedEvent> {
+        let mut events: Vec<SequencedEvent> = Vec::new();
+        loop {
+            let from_seq = events.last().map_or(1, |event| event.seq + 1);
+            let request = Request::GetEvents(GetEventsArgs {
+                from_seq,
+                limit: MAX_PAGE_LIMIT,
+            });
+            let Response::EventsOk(page) = self.request(request).await else {
+                panic!("Failed to get events");
+            };
+            if page.is_empty() {
+                return events;
+            }
+            events.extend(page);
+        }
+    }
+}
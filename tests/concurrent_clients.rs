@@ -0,0 +1,159 @@
+mod common;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{collections::HashMap, net::SocketAddr};
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+use common::{TestClient, TestServer};
+use order_book::{
+    book::{OrderBook, OrderStatus, OrderType},
+    query::{PageRequest, MAX_PAGE_LIMIT},
+    req::{CancelOrderArgs, PlaceOrderArgs, QueryOrderArgs, Request, ViewOpenOrdersArgs},
+    resp::Response,
+};
+
+const NUM_CLIENTS: usize = 16;
+const ACTIONS_PER_CLIENT: usize = 200;
+
+// Places and cancels random orders around 100, returning each accepted
+// order with the quantity it was placed with
+async fn simulate_client(addr: SocketAddr, client_idx: usize) -> Vec<(Uuid, u64)> {
+    let mut client = TestClient::connect(addr).await;
+    let mut rng = StdRng::seed_from_u64(client_idx as u64);
+    let owner = format!("client{client_idx}");
+    let mut placed = Vec::new();
+
+    for _ in 0..ACTIONS_PER_CLIENT {
+        if !placed.is_empty() && rng.gen_bool(0.2) {
+            let (order_id, _) = placed[rng.gen_range(0..placed.len())];
+            // Fails once the order is filled or already canceled
+            client
+                .request(Request::CancelOrder(CancelOrderArgs { order_id }))
+                .await;
+            continue;
+        }
+        let quantity = rng.gen_range(1..=10);
+        let request = Request::PlaceOrder(PlaceOrderArgs {
+            order_type: if rng.gen_bool(0.5) {
+                OrderType::Bid
+            } else {
+                OrderType::Ask
+            },
+            price: rng.gen_range(95..=105),
+            quantity,
+            owner: owner.clone(),
+            expires_at: None,
+            client_order_id: None,
+        });
+        match client.request(request).await {
+            Response::PlaceOk(report) => placed.push((report.order_id, quantity)),
+            response => panic!("Order was rejected: {response:?}"),
+        }
+    }
+    placed
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_clients_conserve_quantity() {
+    let server = TestServer::start().await;
+    let mut simulations = JoinSet::new();
+    for client_idx in 0..NUM_CLIENTS {
+        simulations.spawn(simulate_client(server.addr, client_idx));
+    }
+    let mut placed = Vec::new();
+    while let Some(client_placed) = simulations.join_next().await {
+        placed.extend(client_placed.unwrap());
+    }
+
+    let mut client = server.connect().await;
+    let trades = client.all_trades().await;
+    assert!(!trades.is_empty());
+    let mut filled: HashMap<Uuid, u64> = HashMap::new();
+    for trade in &trades {
+        *filled.entry(trade.maker_order_id).or_default() += trade.quantity;
+        *filled.entry(trade.taker_order_id).or_default() += trade.quantity;
+    }
+
+    // placed = filled + resting, and canceled orders only lost their remainder
+    let mut resting_total = 0;
+    for &(order_id, quantity) in &placed {
+        let filled = filled.get(&order_id).copied().unwrap_or(0);
+        let Response::OrderStatusOk(status) = client
+            .request(Request::QueryOrder(QueryOrderArgs { order_id }))
+            .await
+        else {
+            panic!("Order {order_id} is unknown");
+        };
+        match status {
+            OrderStatus::Resting { remaining_qty, .. } => {
+                assert_eq!(filled + remaining_qty, quantity);
+                resting_total += remaining_qty;
+            }
+            OrderStatus::Filled => assert_eq!(filled, quantity),
+            OrderStatus::PartiallyFilledThenCanceled => assert!(filled > 0 && filled < quantity),
+            OrderStatus::Canceled => assert_eq!(filled, 0),
+        }
+    }
+
+    let Response::ConservationOk(num_orders) = client.request(Request::CheckConservation).await
+    else {
+        panic!("Quantity is not conserved");
+    };
+    assert_eq!(num_orders, placed.len());
+
+    // Open orders reported per owner add up to the book's liquidity
+    let mut open_total = 0;
+    for client_idx in 0..NUM_CLIENTS {
+        let request = Request::ViewOpenOrders(ViewOpenOrdersArgs {
+            owner: format!("client{client_idx}"),
+            page: PageRequest {
+                limit: MAX_PAGE_LIMIT,
+                ..PageRequest::default()
+            },
+        });
+        let Response::OpenOrdersOk(open_orders) = client.request(request).await else {
+            panic!("Failed to view open orders");
+        };
+        assert_eq!(open_orders.next_cursor, None);
+        open_total += open_orders
+            .items
+            .iter()
+            .map(|order| order.quantity)
+            .sum::<u64>();
+    }
+    let (liquidity, best_prices) = server
+        .book
+        .execute(|book| {
+            let liquidity = book.liquidity_within(0..=u32::MAX, OrderType::Bid)
+                + book.liquidity_within(0..=u32::MAX, OrderType::Ask);
+            let best_prices = book
+                .best_bid()
+                .zip(book.best_ask())
+                .map(|(best_bid, best_ask)| (best_bid.price(), best_ask.price()));
+            (liquidity, best_prices)
+        })
+        .await
+        .unwrap();
+    assert_eq!(open_total, resting_total);
+    assert_eq!(liquidity, resting_total);
+    if let Some((best_bid, best_ask)) = best_prices {
+        assert!(best_bid < best_ask);
+    }
+
+    // Replaying the feed rebuilds the same book and tape
+    let mut replica = OrderBook::new();
+    for event in client.all_events().await {
+        replica.apply_event(event).unwrap();
+    }
+    let l2_book = server
+        .book
+        .execute(|book| serde_json::to_value(book.view_book_l2()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(
+        serde_json::to_value(replica.view_book_l2()).unwrap(),
+        l2_book
+    );
+    assert_eq!(replica.get_trades(1, trades.len() + 1).unwrap(), trades);
+}
@@ -0,0 +1,184 @@
+// Runs random command sequences through OrderBook and a naive reference book
+// and checks both make the same trades and end up with the same levels
+use proptest::{prelude::*, sample::Index};
+use uuid::Uuid;
+
+use order_book::{OrderBook, OrderType};
+
+#[derive(Debug, Clone)]
+enum Command {
+    Place {
+        order_type: OrderType,
+        price: u32,
+        quantity: u64,
+    },
+    // Picks one of the orders placed so far, which may already be closed
+    Cancel(Index),
+}
+
+fn command() -> impl Strategy<Value = Command> {
+    let order_type = prop_oneof![Just(OrderType::Bid), Just(OrderType::Ask)];
+    prop_oneof![
+        3 => (order_type, 95u32..=105, 1u64..=50).prop_map(|(order_type, price, quantity)| {
+            Command::Place {
+                order_type,
+                price,
+                quantity,
+            }
+        }),
+        1 => any::<Index>().prop_map(Command::Cancel),
+    ]
+}
+
+struct ReferenceOrder {
+    id: Uuid,
+    price: u32,
+    quantity: u64,
+}
+
+// Resting orders of each side kept in arrival order, with the best order
+// found by scanning. Slow, but easy to check by eye.
+#[derive(Default)]
+struct ReferenceBook {
+    bids: Vec<ReferenceOrder>,
+    asks: Vec<ReferenceOrder>,
+}
+
+// (maker order id, price, quantity) of one fill
+type Fill = (Uuid, u32, u64);
+
+impl ReferenceBook {
+    fn place(&mut self, id: Uuid, order_type: OrderType, price: u32, quantity: u64) -> Vec<Fill> {
+        let (resting, opposite) = match order_type {
+            OrderType::Bid => (&mut self.bids, &mut self.asks),
+            OrderType::Ask => (&mut self.asks, &mut self.bids),
+        };
+        let crosses = |maker_price: u32| match order_type {
+            OrderType::Bid => maker_price <= price,
+            OrderType::Ask => maker_price >= price,
+        };
+        let mut remaining = quantity;
+        let mut fills = Vec::new();
+        while remaining > 0 {
+            // First order at the best price, since ties keep arrival order
+            let mut best: Option<usize> = None;
+            for (idx, order) in opposite.iter().enumerate() {
+                if !crosses(order.price) {
+                    continue;
+                }
+                let better = best.is_none_or(|best| match order_type {
+                    OrderType::Bid => order.price < opposite[best].price,
+                    OrderType::Ask => order.price > opposite[best].price,
+                });
+                if better {
+                    best = Some(idx);
+                }
+            }
+            let Some(best) = best else {
+                break;
+            };
+            let maker = &mut opposite[best];
+            let fill_quantity = remaining.min(maker.quantity);
+            fills.push((maker.id, maker.price, fill_quantity));
+            maker.quantity -= fill_quantity;
+            remaining -= fill_quantity;
+            if maker.quantity == 0 {
+                opposite.remove(best);
+            }
+        }
+        if remaining > 0 {
+            resting.push(ReferenceOrder {
+                id,
+                price,
+                quantity: remaining,
+            });
+        }
+        fills
+    }
+
+    fn cancel(&mut self, id: Uuid) -> bool {
+        for side in [&mut self.bids, &mut self.asks] {
+            if let Some(idx) = side.iter().position(|order| order.id == id) {
+                side.remove(idx);
+                return true;
+            }
+        }
+        false
+    }
+
+    // (price, total quantity, number of orders) of each level, best first
+    fn levels(&self, order_type: OrderType) -> Vec<(u32, u64, usize)> {
+        let side = match order_type {
+            OrderType::Bid => &self.bids,
+            OrderType::Ask => &self.asks,
+        };
+        let mut levels: Vec<(u32, u64, usize)> = Vec::new();
+        for order in side {
+            match levels
+                .iter_mut()
+                .find(|(price, _, _)| *price == order.price)
+            {
+                Some(level) => {
+                    level.1 += order.quantity;
+                    level.2 += 1;
+                }
+                None => levels.push((order.price, order.quantity, 1)),
+            }
+        }
+        levels.sort_by_key(|(price, _, _)| *price);
+        if order_type == OrderType::Bid {
+            levels.reverse();
+        }
+        levels
+    }
+}
+
+fn book_levels(book: &OrderBook, order_type: OrderType) -> Vec<(u32, u64, usize)> {
+    book.top_levels(order_type, usize::MAX)
+        .map(|(_, level)| (level.price(), level.total_quantity(), level.num_orders()))
+        .collect()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn test_matches_reference_book(commands in prop::collection::vec(command(), 1..200)) {
+        let mut book = OrderBook::new();
+        let mut reference = ReferenceBook::default();
+        let mut placed = Vec::new();
+        let mut next_trade_seq = 1;
+
+        for command in commands {
+            match command {
+                Command::Place { order_type, price, quantity } => {
+                    let order_id = book.place_order("trader", price, quantity, order_type).unwrap();
+                    placed.push(order_id);
+                    let expected = reference.place(order_id, order_type, price, quantity);
+                    let trades = book.get_trades(next_trade_seq, usize::MAX).unwrap();
+                    if let Some(last) = trades.last() {
+                        next_trade_seq = last.seq + 1;
+                    }
+                    for trade in &trades {
+                        prop_assert_eq!(trade.taker_order_id, order_id);
+                    }
+                    let fills: Vec<Fill> = trades
+                        .iter()
+                        .map(|trade| (trade.maker_order_id, trade.price, trade.quantity))
+                        .collect();
+                    prop_assert_eq!(fills, expected);
+                }
+                Command::Cancel(idx) => {
+                    if placed.is_empty() {
+                        continue;
+                    }
+                    let order_id = *idx.get(&placed);
+                    prop_assert_eq!(book.cancel_order(order_id).is_ok(), reference.cancel(order_id));
+                }
+            }
+            for order_type in [OrderType::Bid, OrderType::Ask] {
+                prop_assert_eq!(book_levels(&book, order_type), reference.levels(order_type));
+            }
+        }
+    }
+}
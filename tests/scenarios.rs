@@ -0,0 +1,23 @@
+use std::fs;
+
+use order_book::scenario::Scenario;
+
+// Runs every scenario under tests/scenarios
+#[test]
+fn test_scenarios() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/scenarios");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "scn"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    for path in paths {
+        let text = fs::read_to_string(&path).unwrap();
+        if let Err(err) = Scenario::parse(&text).and_then(|scenario| scenario.run().map(|_| ())) {
+            panic!("{}: {err:#}", path.display());
+        }
+    }
+}
@@ -0,0 +1,711 @@
+mod common;
+
+use common::{TestClient, TestServer};
+use order_book::{
+    auth::{Authenticator, SignedRequest, DEFAULT_FRESHNESS_WINDOW_MS},
+    book::{CancelFilter, OrderBook, OrderType},
+    clock::unix_millis,
+    config::ServerConfig,
+    engine::{BookHandle, ExchangeHandle, DEFAULT_QUEUE_CAPACITY},
+    error::OrderBookError,
+    instrument::InstrumentStatus,
+    req::{
+        CancelAllArgs, CancelOrderArgs, HandshakeArgs, MigrateSymbolArgs, OptionChainArgs,
+        PlaceOrderArgs, Request, SetInstrumentStatusArgs, SymbolRequest, TaggedRequest,
+    },
+    resp::{Reject, Response},
+    risk::RiskRejection,
+    server::{
+        serve, serve_exchange, serve_metrics, serve_tenants, serve_with_options, ServeOptions,
+    },
+    tenant::Tenants,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time,
+};
+
+fn order(price: u32, quantity: u64) -> PlaceOrderArgs {
+    PlaceOrderArgs {
+        order_type: OrderType::Bid,
+        price,
+        quantity,
+        owner: "alice".to_string(),
+        expires_at: None,
+        client_order_id: None,
+    }
+}
+
+#[tokio::test]
+async fn test_configured_symbol_and_risk_limits() {
+    let config = ServerConfig::parse(
+        r#"
+        [[symbols]]
+        symbol = "ACME"
+        tick_size = 5
+
+        [risk]
+        max_order_size = 100
+        "#,
+    )
+    .unwrap();
+    let server = TestServer::start_with_config(&config).await;
+    let mut client = server.connect().await;
+
+    assert!(matches!(
+        client.request(Request::PlaceOrder(order(100, 10))).await,
+        Response::PlaceOk(_)
+    ));
+    assert!(matches!(
+        client.request(Request::PlaceOrder(order(101, 10))).await,
+        Response::PlaceErr(Reject {
+            code: OrderBookError::RiskRejected(RiskRejection::Custom(_)),
+            ..
+        })
+    ));
+    assert!(matches!(
+        client.request(Request::PlaceOrder(order(100, 200))).await,
+        Response::PlaceErr(Reject {
+            code: OrderBookError::RiskRejected(RiskRejection::MaxOrderSize { .. }),
+            ..
+        })
+    ));
+    let Response::L1BookOk(l1_book) = client.request(Request::ViewL1Book).await else {
+        panic!("Expected the L1 book");
+    };
+    assert_eq!(l1_book.instrument_status, Some(InstrumentStatus::Open));
+    // Only an exchange lists option series
+    let chain = Request::ViewOptionChain(OptionChainArgs {
+        underlying: "ACME".to_string(),
+        expires_at: None,
+    });
+    assert!(matches!(
+        client.request(chain).await,
+        Response::OptionChainErr
+    ));
+}
+
+#[tokio::test]
+async fn test_rate_limit_per_connection() {
+    let config = ServerConfig::parse(
+        r#"
+        [rate_limit]
+        requests_per_sec = 1
+        burst = 3
+        "#,
+    )
+    .unwrap();
+    let server = TestServer::start_with_config(&config).await;
+    let mut client = server.connect().await;
+
+    for _ in 0..3 {
+        assert!(matches!(
+            client.request(Request::ViewL1Book).await,
+            Response::L1BookOk(_)
+        ));
+    }
+    assert!(matches!(
+        client.request(Request::PlaceOrder(order(100, 10))).await,
+        Response::RateLimited
+    ));
+    // Another connection has its own allowance
+    let mut other = server.connect().await;
+    assert!(matches!(
+        other.request(Request::ViewL1Book).await,
+        Response::L1BookOk(_)
+    ));
+    assert!(server
+        .book
+        .execute(|book| book.view_book_l1().bid.is_none())
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn test_async_acks_reject_requests_turned_away_before_the_book() {
+    let config = ServerConfig::parse(
+        r#"
+        [rate_limit]
+        requests_per_sec = 1
+        burst = 3
+        "#,
+    )
+    .unwrap();
+    let server = TestServer::start_with_config(&config).await;
+    let mut client = server.connect().await;
+    let handshake = HandshakeArgs {
+        compression: false,
+        fixed_layout: false,
+        async_acks: true,
+    };
+    assert!(matches!(
+        client.request(Request::Handshake(handshake)).await,
+        Response::HandshakeOk(_)
+    ));
+
+    let requests = [
+        Request::PlaceOrder(order(100, 10)),
+        // Signed by a client the server has no secret for
+        signed("mallory", 1, Request::PlaceOrder(order(100, 10))),
+        Request::PlaceOrder(order(100, 10)),
+    ];
+    for (request_id, request) in (1..).zip(requests) {
+        client
+            .send(Request::Tagged(Box::new(TaggedRequest {
+                request_id,
+                request,
+            })))
+            .await;
+    }
+    assert!(matches!(
+        client.recv().await,
+        Response::Tagged(tagged)
+            if tagged.request_id == 1 && matches!(tagged.response, Response::PlaceOk(_))
+    ));
+    assert!(matches!(
+        client.recv().await,
+        Response::Rejected(rejected)
+            if rejected.request_id == 2 && rejected.reject.code == OrderBookError::Unauthorized
+    ));
+    assert!(matches!(
+        client.recv().await,
+        Response::Rejected(rejected)
+            if rejected.request_id == 3 && rejected.reject.code == OrderBookError::RateLimited
+    ));
+}
+
+#[tokio::test]
+async fn test_stalled_and_idle_connections_are_closed() {
+    let config = ServerConfig::parse(
+        r#"
+        [timeouts]
+        read_timeout_ms = 100
+        idle_timeout_ms = 300
+        "#,
+    )
+    .unwrap();
+    let server = TestServer::start_with_config(&config).await;
+
+    // Stalls part way through the header of a message, and is closed well
+    // before it would count as idle
+    let mut stalled = TcpStream::connect(server.addr).await.unwrap();
+    stalled.write_all(&[0, 0]).await.unwrap();
+    let mut buf = [0; 16];
+    let closed = time::timeout(Duration::from_millis(250), stalled.read(&mut buf)).await;
+    assert!(matches!(closed, Ok(Ok(0)) | Ok(Err(_))));
+
+    // Kept open while busy, then closed once it goes quiet
+    let mut client = server.connect().await;
+    for _ in 0..4 {
+        time::sleep(Duration::from_millis(150)).await;
+        assert!(matches!(
+            client.request(Request::ViewL1Book).await,
+            Response::L1BookOk(_)
+        ));
+    }
+    let mut idle = TcpStream::connect(server.addr).await.unwrap();
+    let closed = time::timeout(Duration::from_secs(1), idle.read(&mut buf)).await;
+    assert!(matches!(closed, Ok(Ok(0)) | Ok(Err(_))));
+}
+
+#[tokio::test]
+async fn test_market_data_listener_only_answers_queries() {
+    let book = BookHandle::spawn(OrderBook::new(), DEFAULT_QUEUE_CAPACITY);
+    let auth = Arc::new(Mutex::new(Authenticator::new(
+        HashMap::new(),
+        DEFAULT_FRESHNESS_WINDOW_MS,
+    )));
+    let order_entry = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let market_data = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut trader = TestClient::connect(order_entry.local_addr().unwrap()).await;
+    let mut watcher = TestClient::connect(market_data.local_addr().unwrap()).await;
+    tokio::spawn(serve(order_entry, book.clone(), auth.clone()));
+    let options = ServeOptions {
+        market_data_only: true,
+        ..Default::default()
+    };
+    tokio::spawn(serve_with_options(market_data, book, auth, options));
+
+    assert!(matches!(
+        watcher.request(Request::PlaceOrder(order(100, 10))).await,
+        Response::ReadOnlyErr
+    ));
+    assert!(matches!(
+        trader.request(Request::PlaceOrder(order(100, 10))).await,
+        Response::PlaceOk(_)
+    ));
+    // Both see the same book
+    let Response::L1BookOk(l1_book) = watcher.request(Request::ViewL1Book).await else {
+        panic!("Expected the L1 book");
+    };
+    assert_eq!(l1_book.bid.unwrap().price, 100);
+}
+
+fn signed(client_id: &str, nonce: u64, request: Request) -> Request {
+    let signed = SignedRequest::sign(client_id, b"secret", nonce, unix_millis(), &request);
+    Request::Signed(Box::new(signed.unwrap()))
+}
+
+#[tokio::test]
+async fn test_tenants_only_reach_their_own_book() {
+    let mut tenants = Tenants::new();
+    let mut secrets = HashMap::new();
+    for (tenant_id, client_id) in [("venue-a", "desk-a"), ("venue-b", "desk-b")] {
+        let book = BookHandle::spawn(OrderBook::new(), DEFAULT_QUEUE_CAPACITY);
+        tenants.add_tenant(tenant_id, book).unwrap();
+        tenants.add_client(client_id, tenant_id).unwrap();
+        secrets.insert(client_id.to_string(), b"secret".to_vec());
+    }
+    let tenants = Arc::new(tenants);
+    let auth = Arc::new(Mutex::new(Authenticator::new(
+        secrets,
+        DEFAULT_FRESHNESS_WINDOW_MS,
+    )));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut client = TestClient::connect(listener.local_addr().unwrap()).await;
+    tokio::spawn(serve_tenants(
+        listener,
+        tenants.clone(),
+        auth,
+        ServeOptions::default(),
+    ));
+
+    let place = Request::PlaceOrder(PlaceOrderArgs {
+        owner: "desk-a".to_string(),
+        ..order(100, 10)
+    });
+    let response = client.request(signed("desk-a", 1, place)).await;
+    let Response::PlaceOk(report) = response else {
+        panic!("Expected the order to be placed, got {response:?}");
+    };
+    // The other tenant's book has neither the order nor its level
+    let cancel = Request::CancelOrder(CancelOrderArgs {
+        order_id: report.order_id,
+    });
+    assert!(matches!(
+        client.request(signed("desk-b", 2, cancel)).await,
+        Response::CancelErr(Reject {
+            code: OrderBookError::UnknownOrder,
+            ..
+        })
+    ));
+    let Response::L1BookOk(l1_book) = client
+        .request(signed("desk-b", 3, Request::ViewL1Book))
+        .await
+    else {
+        panic!("Expected the L1 book");
+    };
+    assert!(l1_book.bid.is_none());
+    let bid = tenants
+        .book("venue-a")
+        .unwrap()
+        .execute(|book| book.view_book_l1().bid.map(|bid| bid.price))
+        .await
+        .unwrap();
+    assert_eq!(bid, Some(100));
+
+    // Unsigned requests belong to no tenant
+    assert!(matches!(
+        client.request(Request::ViewL1Book).await,
+        Response::TenantErr
+    ));
+}
+
+fn on_symbol(symbol: &str, request: Request) -> Request {
+    Request::OnSymbol(Box::new(SymbolRequest {
+        symbol: symbol.to_string(),
+        request,
+    }))
+}
+
+// Client of an exchange serving the configured symbols
+async fn connect_to_exchange(config: &str, auth: Authenticator) -> TestClient {
+    let config = ServerConfig::parse(config).unwrap();
+    let exchange = config.build_exchange().unwrap().unwrap();
+    let exchange = ExchangeHandle::spawn(exchange, DEFAULT_QUEUE_CAPACITY);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let client = TestClient::connect(listener.local_addr().unwrap()).await;
+    tokio::spawn(serve_exchange(
+        listener,
+        exchange,
+        Arc::new(Mutex::new(auth)),
+        ServeOptions::default(),
+    ));
+    client
+}
+
+const EXCHANGE_CONFIG: &str = r#"
+    [[symbols]]
+    symbol = "ACME"
+    tick_size = 5
+
+    [[symbols]]
+    symbol = "INITECH"
+    tick_size = 1
+
+    [exchange]
+    shards = 2
+    router = { kind = "static", routes = { ACME = 0, INITECH = 1 } }
+"#;
+
+#[tokio::test]
+async fn test_exchange_routes_requests_to_each_symbols_book() {
+    let auth = Authenticator::new(HashMap::new(), DEFAULT_FRESHNESS_WINDOW_MS);
+    let mut client = connect_to_exchange(EXCHANGE_CONFIG, auth).await;
+
+    assert!(matches!(
+        client
+            .request(on_symbol("ACME", Request::PlaceOrder(order(100, 10))))
+            .await,
+        Response::PlaceOk(_)
+    ));
+    assert!(matches!(
+        client
+            .request(on_symbol("INITECH", Request::PlaceOrder(order(101, 10))))
+            .await,
+        Response::PlaceOk(_)
+    ));
+    // Each symbol keeps its own tick size
+    assert!(matches!(
+        client
+            .request(on_symbol("ACME", Request::PlaceOrder(order(101, 10))))
+            .await,
+        Response::PlaceErr(Reject {
+            code: OrderBookError::RiskRejected(RiskRejection::Custom(_)),
+            ..
+        })
+    ));
+    for (symbol, price) in [("ACME", 100), ("INITECH", 101)] {
+        let Response::L1BookOk(l1_book) =
+            client.request(on_symbol(symbol, Request::ViewL1Book)).await
+        else {
+            panic!("Expected the L1 book of {symbol}");
+        };
+        assert_eq!(l1_book.bid.unwrap().price, price);
+    }
+
+    assert!(matches!(
+        client
+            .request(on_symbol("GLOBEX", Request::ViewL1Book))
+            .await,
+        Response::SymbolErr
+    ));
+    assert!(matches!(
+        client.request(Request::ViewL1Book).await,
+        Response::SymbolErr
+    ));
+}
+
+#[tokio::test]
+async fn test_admin_migrates_a_symbol_between_shards() {
+    let secrets = HashMap::from([
+        ("alice".to_string(), b"secret".to_vec()),
+        ("ops".to_string(), b"secret".to_vec()),
+    ]);
+    let mut auth = Authenticator::new(secrets, DEFAULT_FRESHNESS_WINDOW_MS);
+    auth.set_admins(HashSet::from(["ops".to_string()])).unwrap();
+    let mut client = connect_to_exchange(EXCHANGE_CONFIG, auth).await;
+    let place = on_symbol("ACME", Request::PlaceOrder(order(100, 10)));
+    assert!(matches!(
+        client.request(signed("alice", 1, place)).await,
+        Response::PlaceOk(_)
+    ));
+
+    let migrate = |shard| {
+        Request::MigrateSymbol(MigrateSymbolArgs {
+            symbol: "ACME".to_string(),
+            shard,
+        })
+    };
+    assert!(matches!(
+        client.request(signed("alice", 2, migrate(1))).await,
+        Response::AuthErr
+    ));
+    assert!(matches!(
+        client.request(signed("ops", 3, migrate(1))).await,
+        Response::MigrateSymbolOk(0)
+    ));
+    // Resting orders move with the book, which trades on
+    let Response::L1BookOk(l1_book) = client.request(on_symbol("ACME", Request::ViewL1Book)).await
+    else {
+        panic!("Expected the L1 book");
+    };
+    assert_eq!(l1_book.bid.unwrap().price, 100);
+    assert!(!l1_book.halted);
+    for (nonce, shard) in [(4, 1), (5, 2)] {
+        assert!(matches!(
+            client.request(signed("ops", nonce, migrate(shard))).await,
+            Response::MigrateSymbolErr(_)
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_admin_sets_instrument_status() {
+    let secrets = HashMap::from([
+        ("alice".to_string(), b"secret".to_vec()),
+        ("ops".to_string(), b"secret".to_vec()),
+    ]);
+    let mut auth = Authenticator::new(secrets, DEFAULT_FRESHNESS_WINDOW_MS);
+    auth.set_admins(HashSet::from(["ops".to_string()])).unwrap();
+    let mut client = connect_to_exchange(EXCHANGE_CONFIG, auth).await;
+
+    let set_status = |symbol: &str, status| {
+        Request::SetInstrumentStatus(SetInstrumentStatusArgs {
+            symbol: symbol.to_string(),
+            status,
+        })
+    };
+    assert!(matches!(
+        client
+            .request(signed(
+                "alice",
+                1,
+                set_status("ACME", InstrumentStatus::Halted)
+            ))
+            .await,
+        Response::AuthErr
+    ));
+    assert!(matches!(
+        client
+            .request(signed(
+                "ops",
+                2,
+                set_status("ACME", InstrumentStatus::Halted)
+            ))
+            .await,
+        Response::InstrumentStatusOk(InstrumentStatus::Open)
+    ));
+    let Response::L1BookOk(l1_book) = client.request(on_symbol("ACME", Request::ViewL1Book)).await
+    else {
+        panic!("Expected the L1 book");
+    };
+    assert_eq!(l1_book.instrument_status, Some(InstrumentStatus::Halted));
+    assert!(l1_book.halted);
+    let place = on_symbol("ACME", Request::PlaceOrder(order(100, 10)));
+    assert!(matches!(
+        client.request(signed("alice", 3, place)).await,
+        Response::PlaceErr(_)
+    ));
+    // The other symbol trades on
+    let Response::L1BookOk(l1_book) = client
+        .request(on_symbol("INITECH", Request::ViewL1Book))
+        .await
+    else {
+        panic!("Expected the L1 book");
+    };
+    assert_eq!(l1_book.instrument_status, Some(InstrumentStatus::Open));
+
+    // Closed only reopens through the opening auction
+    assert!(matches!(
+        client
+            .request(signed(
+                "ops",
+                4,
+                set_status("ACME", InstrumentStatus::Closed)
+            ))
+            .await,
+        Response::InstrumentStatusOk(InstrumentStatus::Halted)
+    ));
+    for (nonce, request) in [
+        (5, set_status("ACME", InstrumentStatus::Open)),
+        (6, set_status("GLOBEX", InstrumentStatus::Open)),
+    ] {
+        assert!(matches!(
+            client.request(signed("ops", nonce, request)).await,
+            Response::InstrumentStatusErr(_)
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_exchange_quotes_option_chain() {
+    let config = r#"
+        [[symbols]]
+        symbol = "AAPL"
+        tick_size = 1
+
+        [[symbols]]
+        symbol = "AAPL-DEC-150-C"
+        tick_size = 1
+        option = { underlying = "AAPL", strike = 150, expires_at = 1797638400000, kind = "Call" }
+
+        [[symbols]]
+        symbol = "AAPL-DEC-150-P"
+        tick_size = 1
+        option = { underlying = "AAPL", strike = 150, expires_at = 1797638400000, kind = "Put" }
+
+        [[symbols]]
+        symbol = "AAPL-MAR-140-C"
+        tick_size = 1
+        option = { underlying = "AAPL", strike = 140, expires_at = 1805414400000, kind = "Call" }
+
+        [exchange]
+        shards = 2
+    "#;
+    let auth = Authenticator::new(HashMap::new(), DEFAULT_FRESHNESS_WINDOW_MS);
+    let mut client = connect_to_exchange(config, auth).await;
+    let place = on_symbol("AAPL-DEC-150-C", Request::PlaceOrder(order(12, 5)));
+    assert!(matches!(client.request(place).await, Response::PlaceOk(_)));
+
+    let chain = |expires_at| {
+        Request::ViewOptionChain(OptionChainArgs {
+            underlying: "AAPL".to_string(),
+            expires_at,
+        })
+    };
+    let Response::OptionChainOk(rows) = client.request(chain(None)).await else {
+        panic!("Expected the option chain");
+    };
+    assert_eq!(rows.len(), 2);
+    let call = rows[0].call.as_ref().unwrap();
+    assert_eq!(call.symbol, "AAPL-DEC-150-C");
+    assert_eq!(call.status, InstrumentStatus::Open);
+    assert_eq!(call.bid.as_ref().unwrap().price, 12);
+    assert_eq!(rows[0].put.as_ref().unwrap().symbol, "AAPL-DEC-150-P");
+    assert_eq!(rows[1].strike, 140);
+    assert!(rows[1].put.is_none());
+
+    let Response::OptionChainOk(rows) = client.request(chain(Some(1_805_414_400_000))).await else {
+        panic!("Expected the option chain");
+    };
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].call.as_ref().unwrap().symbol, "AAPL-MAR-140-C");
+}
+
+#[tokio::test]
+async fn test_signed_requests_only_act_for_their_client() {
+    let book = BookHandle::spawn(OrderBook::new(), DEFAULT_QUEUE_CAPACITY);
+    let mut auth = Authenticator::new(
+        HashMap::from([
+            ("alice".to_string(), b"secret".to_vec()),
+            ("bob".to_string(), b"secret".to_vec()),
+            ("ops".to_string(), b"secret".to_vec()),
+        ]),
+        DEFAULT_FRESHNESS_WINDOW_MS,
+    );
+    auth.set_admins(HashSet::from(["ops".to_string()])).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut client = TestClient::connect(listener.local_addr().unwrap()).await;
+    tokio::spawn(serve(listener, book, Arc::new(Mutex::new(auth))));
+
+    let bob_order = PlaceOrderArgs {
+        owner: "bob".to_string(),
+        ..order(100, 10)
+    };
+    // Alice can't place an order in bob's name
+    assert!(matches!(
+        client
+            .request(signed("alice", 1, Request::PlaceOrder(bob_order.clone())))
+            .await,
+        Response::AuthErr
+    ));
+    let Response::PlaceOk(report) = client
+        .request(signed("bob", 2, Request::PlaceOrder(bob_order)))
+        .await
+    else {
+        panic!("Expected bob's order to be placed");
+    };
+
+    // Nor cancel bob's order with her signature
+    let cancel = Request::CancelOrder(CancelOrderArgs {
+        order_id: report.order_id,
+    });
+    assert!(matches!(
+        client.request(signed("alice", 3, cancel.clone())).await,
+        Response::AuthErr
+    ));
+    let Response::L1BookOk(l1_book) = client.request(Request::ViewL1Book).await else {
+        panic!("Expected the L1 book");
+    };
+    assert_eq!(l1_book.bid.unwrap().total_quantity, 10);
+
+    // Sweeping the book is left to admins
+    let cancel_all = Request::CancelAll(CancelAllArgs {
+        filter: CancelFilter::All,
+    });
+    assert!(matches!(
+        client.request(signed("bob", 4, cancel_all.clone())).await,
+        Response::AuthErr
+    ));
+    assert!(matches!(
+        client.request(signed("ops", 5, cancel_all)).await,
+        Response::CancelAllOk(order_ids) if order_ids == vec![report.order_id]
+    ));
+}
+
+#[tokio::test]
+async fn test_latency_metrics() {
+    let book = OrderBook::new();
+    let latency = book.latency().clone();
+    let book = BookHandle::spawn(book, DEFAULT_QUEUE_CAPACITY);
+    let auth = Arc::new(Mutex::new(Authenticator::new(
+        HashMap::new(),
+        DEFAULT_FRESHNESS_WINDOW_MS,
+    )));
+    let order_entry = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let metrics = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut trader = TestClient::connect(order_entry.local_addr().unwrap()).await;
+    let metrics_addr = metrics.local_addr().unwrap();
+    tokio::spawn(serve(order_entry, book, auth));
+    tokio::spawn(serve_metrics(metrics, latency));
+
+    trader.request(Request::PlaceOrder(order(100, 10))).await;
+    trader.request(Request::ViewL2Book).await;
+    let Response::LatencyOk(summary) = trader.request(Request::ViewLatency).await else {
+        panic!("Expected latency metrics");
+    };
+    let counts: Vec<u64> = summary.iter().map(|operation| operation.count).collect();
+    // One each of place, match and L2 book, and no cancels
+    assert_eq!(counts, [1, 0, 1, 1]);
+    assert!(summary[0].max_nanos > 0);
+
+    let mut scraper = TcpStream::connect(metrics_addr).await.unwrap();
+    scraper
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    scraper.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("order_book_latency_seconds_count{operation=\"place\"} 1"));
+}
+
+#[tokio::test]
+async fn test_execution_reports_carry_lifecycle_timestamps() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let Response::PlaceOk(report) = client.request(Request::PlaceOrder(order(100, 10))).await
+    else {
+        panic!("Expected the order to be placed");
+    };
+    let timestamps = report.timestamps.unwrap();
+    let received_nanos = timestamps.received_nanos.unwrap();
+    let acked_nanos = timestamps.acked_nanos.unwrap();
+    assert!(received_nanos <= timestamps.matched_nanos);
+    assert!(timestamps.matched_nanos <= acked_nanos);
+
+    // Orders of a batch share when the batch was received
+    let Response::PlaceOrdersOk(responses) = client
+        .request(Request::PlaceOrders(vec![order(99, 1), order(98, 1)]))
+        .await
+    else {
+        panic!("Expected the batch to be placed");
+    };
+    let received: Vec<Option<u64>> = responses
+        .iter()
+        .map(|response| match response {
+            Response::PlaceOk(report) => report.timestamps.unwrap().received_nanos,
+            response => panic!("Unexpected response {response:?}"),
+        })
+        .collect();
+    assert!(received[0].unwrap() > acked_nanos);
+    assert_eq!(received[0], received[1]);
+}